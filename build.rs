@@ -0,0 +1,82 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+// Generates `version_info.rs` in OUT_DIR with the git hash (if built from a checkout, or given
+// through IA_SANDBOX_GIT_HASH) and the enabled cargo features, see src/version_info.rs
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-env-changed=IA_SANDBOX_GIT_HASH");
+
+    let git_hash = env::var("IA_SANDBOX_GIT_HASH").ok().or_else(git_hash);
+    let git_hash = match git_hash.as_ref().map(|hash| hash.trim()) {
+        Some(hash) if !hash.is_empty() => format!("Some(Cow::Borrowed({:?}))", hash),
+        _ => "None".to_string(),
+    };
+
+    let mut features: Vec<String> = env::vars()
+        .filter(|(key, _)| key.starts_with("CARGO_FEATURE_") && key != "CARGO_FEATURE_DEFAULT")
+        .map(|(key, _)| {
+            key["CARGO_FEATURE_".len()..]
+                .to_lowercase()
+                .replace('_', "-")
+        })
+        .collect();
+    features.sort();
+    let features = features
+        .iter()
+        .map(|feature| format!("Cow::Borrowed({:?})", feature))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let out_dir = env::var("OUT_DIR").unwrap();
+    fs::write(
+        Path::new(&out_dir).join("version_info.rs"),
+        format!(
+            "const GIT_HASH: Option<Cow<'static, str>> = {};\n\
+             const FEATURES: &[Cow<'static, str>] = &[{}];\n",
+            git_hash, features
+        ),
+    )
+    .unwrap();
+}
+
+fn git(args: &[&str]) -> Option<String> {
+    Command::new("git")
+        .args(args)
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|output| output.trim().to_string())
+}
+
+// The hash of HEAD, `None` outside of a git checkout of this package (built from a crate
+// tarball, or vendored into the checkout of something else, say). HEAD and the branch it is on
+// are watched, the files are only there in a checkout and cargo would rerun the script on every
+// build for a missing one. A branch without a file of its own is in packed-refs.
+fn git_hash() -> Option<String> {
+    let toplevel = git(&["rev-parse", "--show-toplevel"])?;
+    let manifest_dir = env::var("CARGO_MANIFEST_DIR").ok()?;
+    if fs::canonicalize(toplevel).ok()? != fs::canonicalize(manifest_dir).ok()? {
+        return None;
+    }
+
+    let git_dir = git(&["rev-parse", "--git-dir"])?;
+    let mut watched = vec![Path::new(&git_dir).join("HEAD")];
+    if let Some(branch) = git(&["symbolic-ref", "-q", "HEAD"]) {
+        // Shared by the worktrees, unlike HEAD
+        let common_dir = git(&["rev-parse", "--git-common-dir"])?;
+        let branch_path = Path::new(&common_dir).join(branch);
+        watched.push(if branch_path.exists() {
+            branch_path
+        } else {
+            Path::new(&common_dir).join("packed-refs")
+        });
+    }
+    for path in watched.iter().filter(|path| path.exists()) {
+        println!("cargo:rerun-if-changed={}", path.display());
+    }
+    git(&["rev-parse", "--short=12", "HEAD"])
+}
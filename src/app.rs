@@ -47,6 +47,84 @@ pub(crate) fn app() -> App<'static, 'static> {
                      to this folder prior to running the command.",
                 ),
         )
+        .arg(
+            Arg::with_name("working-directory")
+                .long("working-directory")
+                .takes_value(true)
+                .help("The directory to run the command in")
+                .long_help(
+                    "The directory to run the command in, inside the new root. By default it\n\
+                     is / of the new root, or the current directory without one.",
+                ),
+        )
+        .arg(
+            Arg::with_name("copy-in")
+                .long("copy-in")
+                .multiple(true)
+                .number_of_values(1)
+                .help("A file of the host to copy into the sandbox, as source:destination")
+                .long_help(
+                    "A file of the host to copy into the sandbox before the command starts,\n\
+                     given as source:destination. The destination is inside the new root and\n\
+                     has to be writable there.",
+                ),
+        )
+        .arg(
+            Arg::with_name("extra-fd")
+                .long("extra-fd")
+                .multiple(true)
+                .number_of_values(1)
+                .help("A file descriptor for the command besides the standard streams")
+                .long_help(
+                    "A file descriptor for the command besides the standard streams, given as\n\
+                     fd:read:path or fd:write:path for a file of the host (a written one is\n\
+                     made or truncated), or fd:fd:source for a file descriptor of the caller.",
+                ),
+        )
+        .arg(
+            Arg::with_name("copy-out")
+                .long("copy-out")
+                .multiple(true)
+                .number_of_values(1)
+                .help("A file to copy out of the sandbox, as source:destination")
+                .long_help(
+                    "A file to copy out of the sandbox once the command exited, given as\n\
+                     source:destination. The source is inside the new root (mounts such as a\n\
+                     --tmpfs included), a source that does not exist is left out.",
+                ),
+        )
+        .arg(
+            Arg::with_name("copy-out-limit")
+                .long("copy-out-limit")
+                .takes_value(true)
+                .help("The largest file --copy-out copies (e.g. 10mb)"),
+        )
+        .arg(
+            Arg::with_name("new-root-overlay")
+                .long("new-root-overlay")
+                .takes_value(true)
+                .requires("new-root")
+                .help("mount an overlay at the new root, as lower[:lower...]:upper:work")
+                .long_help(
+                    "mount an overlay at the new root, as lower[:lower...]:upper:work. The\n\
+                     lower directories (the first one on top) stay untouched, the changes go\n\
+                     to upper. work is an empty directory on the same filesystem as upper.",
+                ),
+        )
+        .arg(
+            Arg::with_name("root-image")
+                .long("root-image")
+                .takes_value(true)
+                .requires("new-root")
+                .conflicts_with("new-root-overlay")
+                .help("mount an image at the new root, as squashfs:path or tarball:path")
+                .long_help(
+                    "mount an image at the new root, as squashfs:path or tarball:path. A\n\
+                     squashfs is mounted read-only through a loop device, which needs running\n\
+                     as root in the root isolation mode. An uncompressed tarball is unpacked\n\
+                     into a tmpfs.",
+                ),
+        )
         .arg(
             Arg::with_name("share-net")
                 .long("share-net")
@@ -57,6 +135,160 @@ pub(crate) fn app() -> App<'static, 'static> {
                      successive runs (Linux Kernel Bug).",
                 ),
         )
+        .arg(
+            Arg::with_name("share-ipc")
+                .long("share-ipc")
+                .help("Whether to share the IPC namespace or not")
+                .long_help(
+                    "Whether to share the IPC namespace or not. Not sharing keeps\n\
+                     System V shared memory, semaphores and message queues from\n\
+                     leaking from one run into the next.",
+                ),
+        )
+        .arg(
+            Arg::with_name("share-pid")
+                .long("share-pid")
+                .help("Whether to share the pid namespace of the supervisor or not")
+                .long_help(
+                    "Whether to share the pid namespace of the supervisor or not.\n\
+                     Sharing makes it easier to trace the program from outside, but it\n\
+                     can see and signal the supervisor then.",
+                ),
+        )
+        .arg(
+            Arg::with_name("veth")
+                .long("veth")
+                .takes_value(true)
+                .help("A veth pair connecting the sandbox to the host")
+                .long_help(
+                    "A veth pair connecting the sandbox to the host, given as\n\
+                     HOST_NAME:HOST_ADDRESS:PEER_NAME:PEER_ADDRESS/PREFIX_LEN (such as\n\
+                     ia-host:10.10.0.1:ia-peer:10.10.0.2/24). The peer end goes into the\n\
+                     network namespace of the sandbox, so it can not be shared. Creating\n\
+                     the host end needs CAP_NET_ADMIN, run as root without --clone-user.",
+                ),
+        )
+        .arg(
+            Arg::with_name("join-namespace")
+                .long("join-namespace")
+                .multiple(true)
+                .number_of_values(1)
+                .help("An existing namespace to join, as kind:path")
+                .long_help(
+                    "An existing namespace to join instead of a new one, given as kind:path\n\
+                     where kind is net, ipc or uts and path a /proc/<pid>/ns file or a bind\n\
+                     mount of one (such as /var/run/netns/name). Joining namespaces of the\n\
+                     host needs running as root without --clone-user.",
+                ),
+        )
+        .arg(
+            Arg::with_name("keep-capability")
+                .long("keep-capability")
+                .multiple(true)
+                .number_of_values(1)
+                .help("A capability the command keeps, such as net_raw")
+                .long_help(
+                    "A capability the command keeps, such as net_raw or CAP_NET_RAW. All the\n\
+                     others are dropped before the command runs, which also can not gain new\n\
+                     ones through setuid binaries or file capabilities.",
+                ),
+        )
+        .arg(
+            Arg::with_name("landlock")
+                .long("landlock")
+                .multiple(true)
+                .number_of_values(1)
+                .help("A path the program may access, as path:access[,access...]")
+                .long_help(
+                    "A path the program may access with Landlock, as path:access[,access...].\n\
+                     Given any, every other filesystem access fails with EACCES. The path is\n\
+                     resolved inside the sandbox, and the accesses apply beneath it:\n\
+                     - read-file\n\
+                     - read-dir\n\
+                     - write-file, in a directory creating and removing files as well\n\
+                     - execute, which reads as well\n\
+                     The command and its dynamic loader need execute, its libraries read-file.",
+                ),
+        )
+        .arg(
+            Arg::with_name("landlock-warn")
+                .long("landlock-warn")
+                .requires("landlock")
+                .help("whether to run without --landlock where the kernel has no Landlock")
+                .long_help(
+                    "whether to run without the --landlock restriction, with a warning, where\n\
+                     the kernel has no Landlock. By default the run fails there.",
+                ),
+        )
+        .arg(
+            Arg::with_name("seccomp-allow")
+                .long("seccomp-allow")
+                .multiple(true)
+                .number_of_values(1)
+                .help("A syscall the command may call, such as read")
+                .long_help(
+                    "A syscall the command may call, such as read or exit_group. Given any, the\n\
+                     command runs under a seccomp filter and every other syscall gets the\n\
+                     --seccomp-default action.",
+                ),
+        )
+        .arg(
+            Arg::with_name("seccomp-profile")
+                .long("seccomp-profile")
+                .takes_value(true)
+                .possible_values(&["cpp", "python3", "jvm"])
+                .help("A ready-made syscall allowlist for a language runtime")
+                .long_help(
+                    "A ready-made syscall allowlist for a language runtime, which\n\
+                     --seccomp-allow adds to.\n\
+                     cpp - native programs linked against glibc\n\
+                     python3 - the CPython 3 interpreter\n\
+                     jvm - the OpenJDK java launcher",
+                ),
+        )
+        .arg(
+            Arg::with_name("seccomp-default")
+                .long("seccomp-default")
+                .takes_value(true)
+                .help("What happens on the syscalls not allowed: kill (default), log or errno:N")
+                .long_help(
+                    "What happens on the syscalls not allowed with --seccomp-allow or\n\
+                     --seccomp-profile: kill (the default) ends the run as an illegal syscall,\n\
+                     log lets it run but logs it to the audit log and errno:N fails it with\n\
+                     errno N.",
+                ),
+        )
+        .arg(
+            Arg::with_name("single-exec")
+                .long("single-exec")
+                .help("whether to kill the command on any exec after the one starting it")
+                .long_help(
+                    "whether to kill the command on any exec after the one starting it, so it\n\
+                     can not run other programs. The run ends as an illegal execve syscall.",
+                ),
+        )
+        .arg(
+            Arg::with_name("allow-nested-namespaces")
+                .long("allow-nested-namespaces")
+                .help("whether to let the command create namespaces of its own")
+                .long_help(
+                    "whether to let the command create namespaces of its own, a user namespace\n\
+                     giving it every capability in it. By default unshare and clone fail with\n\
+                     EPERM when asked for any.",
+                ),
+        )
+        .arg(
+            Arg::with_name("trace-syscalls")
+                .long("trace-syscalls")
+                .takes_value(true)
+                .help("Where to log the syscalls of the program, to see what it does")
+                .long_help(
+                    "Where to log the syscalls of the program with their return values and\n\
+                     when they were made, to see what it does. The path must be outside the\n\
+                     jail. The program is traced with ptrace for it, which slows it down a lot\n\
+                     so the time limits are not to be relied on.",
+                ),
+        )
         .arg(
             Arg::with_name("stdin")
                 .long("stdin")
@@ -78,6 +310,74 @@ pub(crate) fn app() -> App<'static, 'static> {
                 .help("Where to redirect stderr")
                 .long_help("Where to redirect stderr. The path must be outside the jail"),
         )
+        .arg(
+            Arg::with_name("stderr-to-stdout")
+                .long("stderr-to-stdout")
+                .conflicts_with("stderr")
+                .help("whether to send stderr wherever stdout goes (like 2>&1)"),
+        )
+        .arg(
+            Arg::with_name("stderr-tail")
+                .long("stderr-tail")
+                .takes_value(true)
+                .help("How much of the end of stderr a failed run reports (4kb by default)")
+                .long_help(
+                    "How much of the end of stderr a failed run reports (4kb by default), 0\n\
+                     for none. Only when --stderr is a file outside the jail.",
+                ),
+        )
+        .arg(
+            Arg::with_name("output-mode")
+                .long("output-mode")
+                .takes_value(true)
+                .possible_values(&["truncate", "append", "create-new"])
+                .default_value("truncate")
+                .help("how the files of --stdout and --stderr are opened")
+                .long_help(
+                    "how the files of --stdout and --stderr are opened.\n\
+                     truncate - made if missing, emptied otherwise\n\
+                     append - made if missing, written at the end otherwise\n\
+                     create-new - made, failing if anything (a symlink too) is there already",
+                ),
+        )
+        .arg(
+            Arg::with_name("output-permissions")
+                .long("output-permissions")
+                .takes_value(true)
+                .help("the permissions, in octal, of the files --stdout and --stderr make"),
+        )
+        .arg(
+            Arg::with_name("redirects-in-sandbox")
+                .long("redirects-in-sandbox")
+                .help("whether the paths of --stdin, --stdout and --stderr are inside the jail")
+                .long_help(
+                    "whether the paths of --stdin, --stdout and --stderr are inside the jail,\n\
+                     opened once its root and mounts are in place (relative ones from the new\n\
+                     root). A FIFO made outside can then only be used if it is mounted in.",
+                ),
+        )
+        .arg(
+            Arg::with_name("no-close-fds")
+                .long("no-close-fds")
+                .help("whether to pass the file descriptors ia-sandbox inherited on to the program")
+                .long_help(
+                    "whether to pass the file descriptors ia-sandbox inherited on to the\n\
+                     program. By default it gets only stdin, stdout, stderr and the --extra-fd\n\
+                     ones.",
+                ),
+        )
+        .arg(
+            Arg::with_name("fifo-open-timeout")
+                .long("fifo-open-timeout")
+                .takes_value(true)
+                .default_value("5s")
+                .help("How long opening a FIFO to redirect to waits for its other end")
+                .long_help(
+                    "How long opening a FIFO to redirect to waits for its other end, the run\n\
+                     fails past it. Given as an unsigned number followed by one of the\n\
+                     following suffixes: ns(nanoseconds), ms(milliseconds) or s(seconds)",
+                ),
+        )
         .arg(
             Arg::with_name("wall-time")
                 .long("wall-time")
@@ -105,6 +405,44 @@ pub(crate) fn app() -> App<'static, 'static> {
                      suffixes: ns(nanoseconds), ms(milliseconds) or s(seconds)",
                 ),
         )
+        .arg(
+            Arg::with_name("wall-time-factor")
+                .long("wall-time-factor")
+                .takes_value(true)
+                .requires("time")
+                .conflicts_with("wall-time")
+                .help("Derive the wall time limit from the user time limit")
+                .long_help(
+                    "Derive the wall time limit from the user time limit, as the user time\n\
+                     limit times this factor plus the wall time slack. Useful when running\n\
+                     more sandboxes than there are cpus, so that the wall time only guards\n\
+                     against deadlocks.",
+                ),
+        )
+        .arg(
+            Arg::with_name("wall-time-slack")
+                .long("wall-time-slack")
+                .takes_value(true)
+                .requires("wall-time-factor")
+                .help("Added to the derived wall time limit")
+                .long_help(
+                    "Added to the derived wall time limit. Given as an unsigned number\n\
+                     followed by one of the following suffixes: ns(nanoseconds),\n\
+                     ms(milliseconds) or s(seconds)",
+                ),
+        )
+        .arg(
+            Arg::with_name("kill-grace-period")
+                .long("kill-grace-period")
+                .takes_value(true)
+                .help("Time between SIGTERM and SIGKILL when a limit is exceeded")
+                .long_help(
+                    "Time between SIGTERM and SIGKILL when a limit is exceeded, for the\n\
+                     program to flush its output. The verdict stays the exceeded limit.\n\
+                     Given as an unsigned number followed by one of the following\n\
+                     suffixes: ns(nanoseconds), ms(milliseconds) or s(seconds)",
+                ),
+        )
         .arg(
             Arg::with_name("memory")
                 .long("memory")
@@ -155,6 +493,97 @@ pub(crate) fn app() -> App<'static, 'static> {
                      Defaults 50 to protect against fork bombs.",
                 ),
         )
+        .arg(
+            Arg::with_name("cpu-quota")
+                .long("cpu-quota")
+                .takes_value(true)
+                .help("CPU bandwidth limit")
+                .long_help(
+                    "CPU bandwidth limit. The fraction of one CPU the program is allowed to\n\
+                     use, for example 0.5 for half of a core or 2 for two cores. Unlike the\n\
+                     time limit this does not kill the program, it only slows it down.",
+                ),
+        )
+        .arg(
+            Arg::with_name("cpus")
+                .long("cpus")
+                .takes_value(true)
+                .help("CPUs the program may run on")
+                .long_help(
+                    "CPUs the program may run on. Given as a list of cpu numbers and\n\
+                     ranges, for example 0-3,6.",
+                ),
+        )
+        .arg(
+            Arg::with_name("mems")
+                .long("mems")
+                .takes_value(true)
+                .help("Memory nodes the program may allocate from")
+                .long_help(
+                    "Memory nodes the program may allocate from. Given as a list of node\n\
+                     numbers and ranges, for example 0-1. Only useful on NUMA machines.",
+                ),
+        )
+        .arg(
+            Arg::with_name("read-bps")
+                .long("read-bps")
+                .takes_value(true)
+                .help("Disk read bandwidth limit")
+                .long_help(
+                    "Disk read bandwidth limit. How much the program is allowed to read\n\
+                     per second from the device backing the new root. Given as an unsigned\n\
+                     number followed by one of the usual suffixes b, kb, mb, gb, kib, mib, gib.",
+                ),
+        )
+        .arg(
+            Arg::with_name("write-bps")
+                .long("write-bps")
+                .takes_value(true)
+                .help("Disk write bandwidth limit")
+                .long_help(
+                    "Disk write bandwidth limit. How much the program is allowed to write\n\
+                     per second to the device backing the new root. Given as an unsigned\n\
+                     number followed by one of the usual suffixes b, kb, mb, gb, kib, mib, gib.",
+                ),
+        )
+        .arg(
+            Arg::with_name("net-bytes")
+                .long("net-bytes")
+                .takes_value(true)
+                .conflicts_with("share-net")
+                .help("Network traffic limit")
+                .long_help(
+                    "Network traffic limit. How much the program is allowed to receive and\n\
+                     send over its own network namespace in total, so the network can not\n\
+                     be shared. The traffic is sampled, so the limit is approximate. Given\n\
+                     as an unsigned number followed by one of the usual suffixes b, kb, mb,\n\
+                     gb, kib, mib, gib.",
+                ),
+        )
+        .arg(
+            Arg::with_name("parent-memory")
+                .long("parent-memory")
+                .takes_value(true)
+                .requires("instance-name")
+                .help("Memory limit shared by all instances under the same parent")
+                .long_help(
+                    "Memory limit shared by all instances under the same parent. For an\n\
+                     instance named batch42/run3 it is applied to batch42 by the run that\n\
+                     creates it, later runs leave it as it is. Given as an unsigned number\n\
+                     followed by one of the usual suffixes b, kb, mb, gb, kib, mib, gib.",
+                ),
+        )
+        .arg(
+            Arg::with_name("parent-pids")
+                .long("parent-pids")
+                .takes_value(true)
+                .requires("instance-name")
+                .help("Number of pids limit shared by all instances under the same parent")
+                .long_help(
+                    "Number of pids limit shared by all instances under the same parent.\n\
+                     Applied like --parent-memory.",
+                ),
+        )
         .arg(
             Arg::with_name("instance-name")
                 .long("instance-name")
@@ -165,7 +594,8 @@ pub(crate) fn app() -> App<'static, 'static> {
                     "Instance name for cgroups. If you plan on running multiple\n\
                      sandboxes at the same time, it is mandatory they be given\n\
                      different instance name, otherwise their user times will\n\
-                     add up.",
+                     add up. Names may be nested (batch42/run3), the cgroups in\n\
+                     between are created as needed.",
                 ),
         )
         .arg(
@@ -198,6 +628,36 @@ pub(crate) fn app() -> App<'static, 'static> {
                      user running the sandbox.",
                 ),
         )
+        .arg(
+            Arg::with_name("cpu-controller")
+                .long("cpu-controller")
+                .takes_value(true)
+                .help("cpu controller path")
+                .long_help(
+                    "cpu controller path. Must have write permissions with the\n\
+                     user running the sandbox.",
+                ),
+        )
+        .arg(
+            Arg::with_name("cpuset-controller")
+                .long("cpuset-controller")
+                .takes_value(true)
+                .help("cpuset controller path")
+                .long_help(
+                    "cpuset controller path. Must have write permissions with the\n\
+                     user running the sandbox.",
+                ),
+        )
+        .arg(
+            Arg::with_name("io-controller")
+                .long("io-controller")
+                .takes_value(true)
+                .help("blkio/io controller path")
+                .long_help(
+                    "blkio (cgroups v1) or io (cgroups v2) controller path. Must have write\n\
+                     permissions with the user running the sandbox.",
+                ),
+        )
         .arg(
             Arg::with_name("output")
                 .short("o")
@@ -230,7 +690,150 @@ pub(crate) fn app() -> App<'static, 'static> {
                      Mount options are given as a comma separated list of the following:\n\
                      - rw, default is to mount read-only\n\
                      - exec, default is to mount with no exec permissions\n\
-                     - dev, default is to mount with no access to devices\n",
+                     - force-exec, like exec but skips the check for noexec inherited\n\
+                       from the source filesystem, the mount clears it where allowed\n\
+                     - dev, default is to mount with no access to devices\n\
+                     - private, slave or shared, the mount propagation of this mount,\n\
+                       default is the one given by --propagation\n\
+                     - no-create, default is to create a missing destination\n\
+                     - no-recursive, default is to bind the mounts under the source\n\
+                       as well (with the same options)\n\
+                     - suid, default is to mount with no set-user-ID permissions\n\
+                     - noatime, default is to update access times (relatime)\n\
+                     The defaults can be spelled out as well (ro, nodev, noexec, nosuid,\n\
+                     atime, create, recursive or defaults), a later option wins.\n",
+                ),
+        )
+        .arg(
+            Arg::with_name("tmpfs")
+                .long("tmpfs")
+                .multiple(true)
+                .number_of_values(1)
+                .requires("new-root")
+                .help("where to mount an empty tmpfs inside the new root, as destination[:size]")
+                .long_help(
+                    "where to mount an empty tmpfs inside the new root, as destination[:size]\n\
+                     with the size given like --memory (half of the memory by default). It is\n\
+                     writable, with no exec permissions and no access to devices.",
+                ),
+        )
+        .arg(
+            Arg::with_name("overlay")
+                .long("overlay")
+                .multiple(true)
+                .number_of_values(1)
+                .requires("new-root")
+                .help("where to mount an overlay inside the new root, as lower[:lower...]:upper:work:destination")
+                .long_help(
+                    "where to mount an overlay inside the new root, as\n\
+                     lower[:lower...]:upper:work:destination, like --new-root-overlay. It is\n\
+                     writable, with no exec permissions and no access to devices.",
+                ),
+        )
+        .arg(
+            Arg::with_name("propagation")
+                .long("propagation")
+                .takes_value(true)
+                .possible_values(&["private", "slave", "shared"])
+                .default_value("private")
+                .help("how mounts propagate between the host and the sandbox")
+                .long_help(
+                    "how mounts propagate between the host and the sandbox.\n\
+                     private - nothing propagates either way\n\
+                     slave - mounts made on the host after the start show up in the sandbox\n\
+                     shared - mounts propagate both ways, can not be used with --new-root\n",
+                ),
+        )
+        .arg(
+            Arg::with_name("mount-binfmt-interpreter")
+                .long("mount-binfmt-interpreter")
+                .requires("new-root")
+                .help("whether to mount the binfmt_misc interpreter of a foreign binary")
+                .long_help(
+                    "whether to mount the binfmt_misc interpreter of a foreign binary.\n\
+                     Binaries for another architecture (run through qemu-user for example)\n\
+                     need their interpreter inside the new root, unless it was registered\n\
+                     with the F flag. With this option it is bind mounted read-only into the\n\
+                     new root when missing.",
+                ),
+        )
+        .arg(
+            Arg::with_name("no-minimal-dev")
+                .long("no-minimal-dev")
+                .requires("new-root")
+                .help("whether to leave /dev of the new root as it is")
+                .long_help(
+                    "whether to leave /dev of the new root as it is. By default a tmpfs is\n\
+                     mounted there with the null, zero, full, random, urandom and tty devices\n\
+                     of the host, and the fd, stdin, stdout and stderr links.",
+                ),
+        )
+        .arg(
+            Arg::with_name("no-private-tmp")
+                .long("no-private-tmp")
+                .requires("new-root")
+                .help("whether to leave /tmp, /var/tmp and /dev/shm of the new root as they are")
+                .long_help(
+                    "whether to leave /tmp, /var/tmp and /dev/shm of the new root as they are.\n\
+                     By default a tmpfs of --private-tmp-size is mounted at each of them, unless\n\
+                     a mount goes at or under it.",
+                ),
+        )
+        .arg(
+            Arg::with_name("private-tmp-size")
+                .long("private-tmp-size")
+                .takes_value(true)
+                .conflicts_with("no-private-tmp")
+                .help("The size of each private tmpfs (64mb by default)"),
+        )
+        .arg(
+            Arg::with_name("read-only-root")
+                .long("read-only-root")
+                .requires("new-root")
+                .help("whether to make the new root itself read-only")
+                .long_help(
+                    "whether to make the new root itself read-only, even for root in the\n\
+                     sandbox. The mounts in it keep their own options, a --tmpfs at /tmp\n\
+                     for one is still writable.",
+                ),
+        )
+        .arg(
+            Arg::with_name("proc-options")
+                .long("proc-options")
+                .takes_value(true)
+                .help("how /proc is mounted in a new pid namespace")
+                .long_help(
+                    "how /proc is mounted in a new pid namespace, as a comma separated list of\n\
+                     the following:\n\
+                     - hidepid=off, hidepid=noaccess or hidepid=invisible, whether processes\n\
+                       of other users are seen, default is invisible\n\
+                     - subset=all, default is subset=pid, which only leaves the process\n\
+                       directories (Linux 5.8 or later)\n\
+                     - no-mask, default is to hide kcore, keys, timer_list and the other files\n\
+                       revealing the host under /dev/null\n",
+                ),
+        )
+        .arg(
+            Arg::with_name("mount-cgroups")
+                .long("mount-cgroups")
+                .requires("new-root")
+                .help("whether to mount the instance cgroups inside the new root")
+                .long_help(
+                    "whether to mount the instance cgroups inside the new root.\n\
+                     They are bind mounted read-only under /sys/fs/cgroup so the program\n\
+                     can read its own limits (for example for JVM container awareness).",
+                ),
+        )
+        .arg(
+            Arg::with_name("allow-same-file-io")
+                .long("allow-same-file-io")
+                .requires("stdin")
+                .help("whether to allow stdin to be the same file as stdout or stderr")
+                .long_help(
+                    "whether to allow stdin to be the same file as stdout or stderr.\n\
+                     Opening the output truncates the file, so depending on the order the\n\
+                     redirects are opened in (see --swap-redirects) the input can be read\n\
+                     empty. Without this option such configurations are rejected.",
                 ),
         )
         .arg(
@@ -256,6 +859,16 @@ pub(crate) fn app() -> App<'static, 'static> {
                     "whether to pass CLONE_NEWUSER to clone().",
                 ),
         )
+        .arg(
+            Arg::with_name("sandbox-user")
+                .long("sandbox-user")
+                .takes_value(true)
+                .help("The uid and gid to run the command as")
+                .long_help(
+                    "The uid and gid to run the command as inside the sandbox, given as\n\
+                     uid:gid. Root by default. The mounts are still done as root.",
+                ),
+        )
         .arg(
             Arg::with_name("no-clear-usage")
                 .long("no-clear-usage")
@@ -263,6 +876,11 @@ pub(crate) fn app() -> App<'static, 'static> {
                 .conflicts_with("time")
                 .conflicts_with("memory")
                 .conflicts_with("pids")
+                .conflicts_with("cpu-quota")
+                .conflicts_with("cpus")
+                .conflicts_with("mems")
+                .conflicts_with("read-bps")
+                .conflicts_with("write-bps")
                 .long_help(
                     "whether to not clear usage (time/memory/pids) from cgroups.\n\
                      For multi-run tasks cpu usage might be added for all run of the task.\n\
@@ -270,6 +888,15 @@ pub(crate) fn app() -> App<'static, 'static> {
                      so this option conflicts with time/memory/pids limits.",
                 ),
         )
+        .arg(
+            Arg::with_name("pty")
+                .long("pty")
+                .help("whether to run the program on a pseudo terminal of its own")
+                .long_help(
+                    "whether to run the program on a pseudo terminal of its own, with its\n\
+                     input and output going through this one. Redirections still apply.",
+                ),
+        )
         .arg(
             Arg::with_name("interactive")
                 .long("interactive")
@@ -289,6 +916,40 @@ pub(crate) fn app() -> App<'static, 'static> {
                     "whether to not create isolated cgroups within the instance.",
                 ),
         )
+        .arg(
+            Arg::with_name("exclude-stopped-time")
+                .long("exclude-stopped-time")
+                .help("whether to leave the time spent stopped out of the wall time")
+                .long_help(
+                    "whether to leave the time spent stopped (SIGSTOP/SIGTSTP) out of the\n\
+                     wall time. The stopped time is reported separately either way.",
+                ),
+        )
+        .arg(
+            Arg::with_name("memory-accounting")
+                .long("memory-accounting")
+                .takes_value(true)
+                .possible_values(&["full", "rss-only"])
+                .default_value("full")
+                .help("what counts toward the memory limit")
+                .long_help(
+                    "what counts toward the memory limit.\n\
+                     full - the peak usage of the memory cgroup, minus the page cache when\n\
+                     sampled\n\
+                     rss-only - only anonymous and mapped memory, reading large files does\n\
+                     not count toward the limit\n",
+                ),
+        )
+        .arg(
+            Arg::with_name("allow-swap")
+                .long("allow-swap")
+                .help("whether to let the kernel swap out the memory of the program")
+                .long_help(
+                    "whether to let the kernel swap out the memory of the program.\n\
+                     By default memory.swappiness is set to 0 when there is a memory limit,\n\
+                     since swapping underreports the memory usage and slows down the run.",
+                ),
+        )
         .arg(
             Arg::with_name("env")
                 .long("env")
@@ -1,14 +1,22 @@
 use std::ffi::{OsStr, OsString};
+use std::net::Ipv4Addr;
 use std::ops;
+use std::os::unix::io::RawFd;
 use std::path::PathBuf;
 use std::result;
 use std::time::Duration;
 
 use ia_sandbox::config::{
-    ClearUsage, CloneUser, Config, ControllerPath, Environment, Interactive,
-    IsolatedCgroup, Limits, Mount, MountOptions, ShareNet, SpaceUsage,
-    SwapRedirects,
+    AllocatePty, AllowSameFileIo, Capability, ClearUsage, CloneUser, CloseFds, Config,
+    ControllerPath, DenyNestedNamespaces, DisableSwap, Environment, ExcludeStoppedTime, FdSource,
+    HidePid, Interactive, IsolatedCgroup, LandlockAccess, LandlockPolicy, LandlockUnsupported,
+    Limits, MemoryAccounting, MinimalDev, Mount, MountBinfmtInterpreter, MountCgroups,
+    MountOptions, NamespaceKind, OutputMode, Overlay, PrivateTmp, ProcOptions, Propagation,
+    ReadOnlyRoot, Redirect, RedirectOptions, RootImage, SeccompAction, SeccompPolicy, ShareIpc,
+    ShareNet, SharePid, SingleExec, SpaceUsage, SwapRedirects, VethConfig,
 };
+use ia_sandbox::errors::Error;
+use ia_sandbox::seccomp_profiles;
 
 use app;
 use clap;
@@ -76,48 +84,94 @@ fn parse_space_usage(string: &str) -> Result<SpaceUsage> {
     }
 }
 
-fn parse_mount_options(string: &str) -> Result<MountOptions> {
-    let mut mount_options = MountOptions::default();
+fn parse_proc_options(string: &str) -> Result<ProcOptions> {
+    let mut proc_options = ProcOptions::default();
 
     for option in string.split(',') {
         match option {
-            "rw" => mount_options.set_read_only(false),
-            "dev" => mount_options.set_dev(true),
-            "exec" => mount_options.set_exec(true),
+            "hidepid=off" => proc_options.set_hide_pid(HidePid::Off),
+            "hidepid=noaccess" => proc_options.set_hide_pid(HidePid::NoAccess),
+            "hidepid=invisible" => proc_options.set_hide_pid(HidePid::Invisible),
+            "subset=all" => proc_options.set_subset_pid(false),
+            "subset=pid" => proc_options.set_subset_pid(true),
+            "no-mask" => proc_options.set_mask_paths(false),
             _ => {
                 return Err(format_err!(
-                    "Could not parse mount option, unrecognized `{}`",
+                    "Could not parse proc option, unrecognized `{}`",
                     option
                 ));
             }
         }
     }
-    Ok(mount_options)
+    Ok(proc_options)
 }
 
 fn parse_mount(string: &str) -> Result<Mount> {
     let parts: Vec<&str> = string.split(':').collect();
 
     match *parts.as_slice() {
-        [source] => Ok(Mount::new(
+        [source] => Ok(Mount::read_only(
             PathBuf::from(source),
             PathBuf::from(source),
-            MountOptions::default(),
         )),
-        [source, destination] => Ok(Mount::new(
+        [source, destination] => Ok(Mount::read_only(
             PathBuf::from(source),
             PathBuf::from(destination),
-            MountOptions::default(),
         )),
         [source, destination, options] => Ok(Mount::new(
             PathBuf::from(source),
             PathBuf::from(destination),
-            parse_mount_options(options)?,
+            options.parse()?,
         )),
         _ => Err(format_err!("Could not parse mount")),
     }
 }
 
+// The last parts are upper and work, any number of lower directories come before them
+fn parse_overlay_parts(parts: &[&str]) -> Result<Overlay> {
+    match *parts {
+        [ref lower @ .., upper, work] if !lower.is_empty() => Ok(Overlay::new(
+            lower.iter().map(PathBuf::from).collect(),
+            PathBuf::from(upper),
+            PathBuf::from(work),
+        )),
+        _ => Err(format_err!("Could not parse overlay")),
+    }
+}
+
+fn parse_overlay(string: &str) -> Result<Overlay> {
+    parse_overlay_parts(&string.split(':').collect::<Vec<_>>())
+}
+
+fn parse_overlay_mount(string: &str) -> Result<Mount> {
+    let mount_options = MountOptions::builder().writable().build();
+    match *string.split(':').collect::<Vec<_>>().as_slice() {
+        [ref overlay @ .., destination] => Ok(Mount::overlay(
+            PathBuf::from(destination),
+            parse_overlay_parts(overlay)?,
+            mount_options,
+        )),
+        _ => Err(format_err!("Could not parse overlay")),
+    }
+}
+
+fn parse_tmpfs(string: &str) -> Result<Mount> {
+    let mount_options = MountOptions::builder().writable().build();
+    match *string.split(':').collect::<Vec<_>>().as_slice() {
+        [destination] => Ok(Mount::tmpfs(
+            PathBuf::from(destination),
+            None,
+            mount_options,
+        )),
+        [destination, size] => Ok(Mount::tmpfs(
+            PathBuf::from(destination),
+            Some(parse_space_usage(size).context("Could not parse tmpfs size")?),
+            mount_options,
+        )),
+        _ => Err(format_err!("Could not parse tmpfs")),
+    }
+}
+
 fn parse_environment(string: &str) -> Result<(String, String)> {
     let parts: Vec<&str> = string.splitn(2, '=').collect();
 
@@ -127,6 +181,165 @@ fn parse_environment(string: &str) -> Result<(String, String)> {
     }
 }
 
+fn parse_sandbox_user(string: &str) -> Result<(u32, u32)> {
+    let parts: Vec<&str> = string.split(':').collect();
+
+    match *parts.as_slice() {
+        [uid, gid] => Ok((
+            uid.parse::<u32>()
+                .context(format_err!("Could not parse uid {}", uid))?,
+            gid.parse::<u32>()
+                .context(format_err!("Could not parse gid {}", gid))?,
+        )),
+        _ => Err(format_err!("Could not parse sandbox user uid:gid")),
+    }
+}
+
+fn parse_veth(string: &str) -> Result<VethConfig> {
+    let parts: Vec<&str> = string.split(':').collect();
+
+    match *parts.as_slice() {
+        [host_name, host_address, peer_name, peer_address] => {
+            let mut peer_parts = peer_address.splitn(2, '/');
+            let peer_address = peer_parts.next().unwrap_or_default();
+            let prefix_len = peer_parts
+                .next()
+                .ok_or_else(|| format_err!("Missing prefix length in {}", string))?;
+            Ok(VethConfig::new(
+                host_name.to_owned(),
+                host_address
+                    .parse::<Ipv4Addr>()
+                    .context(format_err!("Could not parse address {}", host_address))?,
+                peer_name.to_owned(),
+                peer_address
+                    .parse::<Ipv4Addr>()
+                    .context(format_err!("Could not parse address {}", peer_address))?,
+                prefix_len
+                    .parse::<u8>()
+                    .context(format_err!("Could not parse prefix length {}", prefix_len))?,
+            ))
+        }
+        _ => Err(format_err!(
+            "Could not parse veth HOST_NAME:HOST_ADDRESS:PEER_NAME:PEER_ADDRESS/PREFIX_LEN"
+        )),
+    }
+}
+
+fn parse_copy(string: &str) -> Result<(PathBuf, PathBuf)> {
+    match *string.splitn(2, ':').collect::<Vec<_>>().as_slice() {
+        [source, destination] => Ok((PathBuf::from(source), PathBuf::from(destination))),
+        _ => Err(format_err!(
+            "Could not parse copy, expected source:destination"
+        )),
+    }
+}
+
+fn parse_extra_fd(string: &str) -> Result<(RawFd, FdSource)> {
+    let parse_fd = |fd: &str| {
+        fd.parse::<RawFd>()
+            .context(format_err!("Could not parse file descriptor {}", fd))
+    };
+    let (fd, source) = match *string.splitn(3, ':').collect::<Vec<_>>().as_slice() {
+        [fd, "read", path] => (
+            fd,
+            FdSource::Path {
+                path: PathBuf::from(path),
+                flags: libc::O_RDONLY,
+            },
+        ),
+        [fd, "write", path] => (
+            fd,
+            FdSource::Path {
+                path: PathBuf::from(path),
+                flags: libc::O_WRONLY | libc::O_CREAT | libc::O_TRUNC,
+            },
+        ),
+        [fd, "fd", source_fd] => (fd, FdSource::Fd(parse_fd(source_fd)?)),
+        _ => {
+            return Err(format_err!(
+                "Could not parse extra fd, expected fd:read:path, fd:write:path or fd:fd:fd"
+            ))
+        }
+    };
+    Ok((parse_fd(fd)?, source))
+}
+
+fn parse_root_image(string: &str) -> Result<RootImage> {
+    match *string.splitn(2, ':').collect::<Vec<_>>().as_slice() {
+        ["squashfs", path] => Ok(RootImage::Squashfs(PathBuf::from(path))),
+        ["tarball", path] => Ok(RootImage::Tarball(PathBuf::from(path))),
+        _ => Err(format_err!(
+            "Could not parse root image, expected squashfs:path or tarball:path"
+        )),
+    }
+}
+
+fn parse_join_namespace(string: &str) -> Result<(NamespaceKind, PathBuf)> {
+    let parts: Vec<&str> = string.splitn(2, ':').collect();
+
+    match *parts.as_slice() {
+        [kind, path] => {
+            let kind = match kind {
+                "cgroup" => NamespaceKind::Cgroup,
+                "ipc" => NamespaceKind::Ipc,
+                "mnt" => NamespaceKind::Mount,
+                "net" => NamespaceKind::Net,
+                "pid" => NamespaceKind::Pid,
+                "user" => NamespaceKind::User,
+                "uts" => NamespaceKind::Uts,
+                kind => return Err(format_err!("Unrecognized namespace kind: {}", kind)),
+            };
+            Ok((kind, PathBuf::from(path)))
+        }
+        _ => Err(format_err!("Could not parse namespace kind:path")),
+    }
+}
+
+fn parse_capability(string: &str) -> Result<Capability> {
+    let name = string.to_uppercase();
+    let name = if name.starts_with("CAP_") {
+        name
+    } else {
+        format!("CAP_{}", name)
+    };
+    Capability::ALL
+        .iter()
+        .cloned()
+        .find(|capability| capability.to_string() == name)
+        .ok_or_else(|| format_err!("Unrecognized capability: {}", string))
+}
+
+fn parse_seccomp_action(string: &str) -> Result<SeccompAction> {
+    match string {
+        "kill" => Ok(SeccompAction::Kill),
+        "log" => Ok(SeccompAction::Log),
+        _ if string.starts_with("errno:") => Ok(SeccompAction::Errno(
+            string["errno:".len()..]
+                .parse::<u16>()
+                .context(format_err!("Could not parse errno {}", string))?,
+        )),
+        _ => Err(format_err!("Unrecognized seccomp action: {}", string)),
+    }
+}
+
+fn parse_landlock_rule(string: &str) -> Result<(PathBuf, Vec<LandlockAccess>)> {
+    let (path, accesses) = match string.rfind(':') {
+        Some(index) => (&string[..index], &string[index + 1..]),
+        None => return Err(format_err!("Could not parse landlock rule {}", string)),
+    };
+    let accesses = accesses
+        .split(',')
+        .map(|access| match access {
+            "read-file" => Ok(LandlockAccess::ReadFile),
+            "read-dir" => Ok(LandlockAccess::ReadDir),
+            "write-file" => Ok(LandlockAccess::WriteFile),
+            "execute" => Ok(LandlockAccess::Execute),
+            _ => Err(format_err!("Unrecognized landlock access: {}", access)),
+        })
+        .collect::<Result<_>>()?;
+    Ok((PathBuf::from(path), accesses))
+}
+
 fn flip_option_result<T>(arg: Option<Result<T>>) -> Result<Option<T>> {
     match arg {
         None => Ok(None),
@@ -137,7 +350,7 @@ fn flip_option_result<T>(arg: Option<Result<T>>) -> Result<Option<T>> {
 
 impl<'a> ArgMatches<'a> {
     fn to_config_and_output(&self) -> Result<(Config, OutputType)> {
-        let limits = Limits::new(
+        let mut limits = Limits::new(
             self.wall_time()?,
             self.user_time()?,
             self.memory()?,
@@ -145,31 +358,124 @@ impl<'a> ArgMatches<'a> {
             self.cache()?,
             self.pids()?,
         );
-        let controller_path = ControllerPath::new(
+        if let Some(wall_time_factor) = self.wall_time_factor()? {
+            limits.derive_wall_from_user(
+                wall_time_factor,
+                self.wall_time_slack()?
+                    .unwrap_or_else(|| Duration::from_secs(0)),
+            );
+        }
+        limits.set_cpu_quota(self.cpu_quota()?);
+        limits.set_cpus(self.cpus());
+        limits.set_mems(self.mems());
+        limits.set_read_bps(self.read_bps()?);
+        limits.set_write_bps(self.write_bps()?);
+        limits.set_net_bytes(self.net_bytes()?);
+        let mut controller_path = ControllerPath::new(
             self.cpuacct_controller_path(),
             self.memory_controller_path(),
             self.pids_controller_path(),
         );
+        controller_path.set_cpu(self.cpu_controller_path());
+        controller_path.set_cpuset(self.cpuset_controller_path());
+        controller_path.set_io(self.io_controller_path());
 
-        let config = Config::new(
-            self.command()?,
-            self.args(),
-            self.new_root(),
-            self.share_net(),
-            self.redirect_stdin(),
-            self.redirect_stdout(),
-            self.redirect_stderr(),
-            limits,
-            self.instance_name(),
-            controller_path,
-            self.mounts()?,
-            self.swap_redirects(),
-            self.clear_usage(),
-            self.interactive(),
-	        self.clone_user(),
-            self.isolated_cgroup(),
-            self.environment()?,
-        );
+        let mut builder = Config::builder(self.command()?)
+            .args(self.args())
+            .share_net(self.share_net())
+            .redirect_stdin(self.redirect_stdin())
+            .redirect_stdout(self.redirect_stdout()?)
+            .redirect_stderr(self.redirect_stderr()?)
+            .limits(limits)
+            .controller_path(controller_path)
+            .swap_redirects(self.swap_redirects())
+            .clear_usage(self.clear_usage())
+            .interactive(self.interactive())
+            .clone_user(self.clone_user())
+            .isolated_cgroup(self.isolated_cgroup())
+            .environment(self.environment()?)
+            .exclude_stopped_time(self.exclude_stopped_time())
+            .mount_binfmt_interpreter(self.mount_binfmt_interpreter())
+            .mount_cgroups(self.mount_cgroups())
+            .minimal_dev(self.minimal_dev())
+            .private_tmp(self.private_tmp())
+            .read_only_root(self.read_only_root())
+            .proc_options(self.proc_options()?)
+            .disable_swap(self.disable_swap())
+            .allow_same_file_io(self.allow_same_file_io())
+            .fifo_open_timeout(self.fifo_open_timeout()?)
+            .close_fds(self.close_fds())
+            .memory_accounting(self.memory_accounting())
+            .share_ipc(self.share_ipc())
+            .share_pid(self.share_pid())
+            .propagation(self.propagation())
+            .single_exec(self.single_exec())
+            .deny_nested_namespaces(self.deny_nested_namespaces())
+            .allocate_pty(self.allocate_pty());
+        for mount in self.mounts()? {
+            builder = builder.mount(mount);
+        }
+        for (source, destination) in self.copy_in()? {
+            builder = builder.copy_in(source, destination);
+        }
+        for (source, destination) in self.copy_out()? {
+            builder = builder.copy_out(source, destination);
+        }
+        for (fd, source) in self.extra_fds()? {
+            builder = builder.extra_fd(fd, source);
+        }
+        for (kind, path) in self.join_namespaces()? {
+            builder = builder.join_namespace(kind, path);
+        }
+        for capability in self.keep_capabilities()? {
+            builder = builder.keep_capability(capability);
+        }
+        if let Some(new_root) = self.new_root() {
+            builder = builder.new_root(new_root);
+        }
+        if let Some(instance_name) = self.instance_name() {
+            builder = builder.instance_name(instance_name);
+        }
+        if let Some(new_root_overlay) = self.new_root_overlay()? {
+            builder = builder.new_root_overlay(new_root_overlay);
+        }
+        if let Some(root_image) = self.root_image()? {
+            builder = builder.root_image(root_image);
+        }
+        if let Some(working_directory) = self.working_directory() {
+            builder = builder.working_directory(working_directory);
+        }
+        if let Some(copy_out_limit) = self.copy_out_limit()? {
+            builder = builder.copy_out_limit(copy_out_limit);
+        }
+        if let Some(private_tmp_size) = self.private_tmp_size()? {
+            builder = builder.private_tmp_size(private_tmp_size);
+        }
+        if let Some(stderr_tail) = self.stderr_tail()? {
+            builder = builder.stderr_tail(stderr_tail);
+        }
+        if let Some(parent_limits) = self.parent_limits()? {
+            builder = builder.parent_limits(parent_limits);
+        }
+        if let Some(kill_grace_period) = self.kill_grace_period()? {
+            builder = builder.kill_grace_period(kill_grace_period);
+        }
+        if let Some((uid, gid)) = self.sandbox_user()? {
+            builder = builder.sandbox_user(uid, gid);
+        }
+        if let Some(veth) = self.veth()? {
+            builder = builder.veth(veth);
+        }
+        if let Some(seccomp) = self.seccomp()? {
+            builder = builder.seccomp(seccomp);
+        }
+        if let Some(trace_syscalls) = self.trace_syscalls() {
+            builder = builder.trace_syscalls(trace_syscalls);
+        }
+        if let Some(landlock) = self.landlock()? {
+            builder = builder.landlock(landlock);
+        }
+        let config = builder.build().map_err(Error::InvalidConfig)?;
 
         Ok((config, self.output_type()))
     }
@@ -191,6 +497,62 @@ impl<'a> ArgMatches<'a> {
         self.value_of_os("new-root").map(PathBuf::from)
     }
 
+    fn working_directory(&self) -> Option<PathBuf> {
+        self.value_of_os("working-directory").map(PathBuf::from)
+    }
+
+    fn copy_in(&self) -> Result<Vec<(PathBuf, PathBuf)>> {
+        match self.values_of("copy-in") {
+            None => Ok(vec![]),
+            Some(args) => args.map(parse_copy).collect(),
+        }
+    }
+
+    fn extra_fds(&self) -> Result<Vec<(RawFd, FdSource)>> {
+        match self.values_of("extra-fd") {
+            None => Ok(vec![]),
+            Some(args) => args.map(parse_extra_fd).collect(),
+        }
+    }
+
+    fn close_fds(&self) -> CloseFds {
+        if self.is_present("no-close-fds") {
+            CloseFds::No
+        } else {
+            CloseFds::Yes
+        }
+    }
+
+    fn fifo_open_timeout(&self) -> Result<Option<Duration>> {
+        Ok(flip_option_result(
+            self.value_of("fifo-open-timeout")
+                .map(|x| parse_duration(x)),
+        )
+        .context("Could not parse fifo open timeout")?)
+    }
+
+    fn copy_out(&self) -> Result<Vec<(PathBuf, PathBuf)>> {
+        match self.values_of("copy-out") {
+            None => Ok(vec![]),
+            Some(args) => args.map(parse_copy).collect(),
+        }
+    }
+
+    fn copy_out_limit(&self) -> Result<Option<SpaceUsage>> {
+        Ok(
+            flip_option_result(self.value_of("copy-out-limit").map(parse_space_usage))
+                .context("Could not parse copy out limit")?,
+        )
+    }
+
+    fn new_root_overlay(&self) -> Result<Option<Overlay>> {
+        flip_option_result(self.value_of("new-root-overlay").map(parse_overlay))
+    }
+
+    fn root_image(&self) -> Result<Option<RootImage>> {
+        flip_option_result(self.value_of("root-image").map(parse_root_image))
+    }
+
     fn share_net(&self) -> ShareNet {
         if self.is_present("share-net") {
             ShareNet::Share
@@ -199,16 +561,60 @@ impl<'a> ArgMatches<'a> {
         }
     }
 
-    fn redirect_stdin(&self) -> Option<PathBuf> {
-        self.value_of_os("stdin").map(PathBuf::from)
+    fn share_ipc(&self) -> ShareIpc {
+        if self.is_present("share-ipc") {
+            ShareIpc::Share
+        } else {
+            ShareIpc::Unshare
+        }
+    }
+
+    fn share_pid(&self) -> SharePid {
+        if self.is_present("share-pid") {
+            SharePid::Share
+        } else {
+            SharePid::Unshare
+        }
     }
 
-    fn redirect_stdout(&self) -> Option<PathBuf> {
-        self.value_of_os("stdout").map(PathBuf::from)
+    fn redirect_stdin(&self) -> Redirect {
+        let mut options = RedirectOptions::default();
+        options.set_in_sandbox(self.is_present("redirects-in-sandbox"));
+        self.value_of_os("stdin").map_or(Redirect::Inherit, |path| {
+            Redirect::File(PathBuf::from(path), options)
+        })
     }
 
-    fn redirect_stderr(&self) -> Option<PathBuf> {
-        self.value_of_os("stderr").map(PathBuf::from)
+    fn redirect_stdout(&self) -> Result<Redirect> {
+        self.output_redirect("stdout")
+    }
+
+    fn redirect_stderr(&self) -> Result<Redirect> {
+        if self.is_present("stderr-to-stdout") {
+            return Ok(Redirect::ToStdout);
+        }
+        self.output_redirect("stderr")
+    }
+
+    fn output_redirect(&self, name: &str) -> Result<Redirect> {
+        let path = match self.value_of_os(name) {
+            Some(path) => PathBuf::from(path),
+            None => return Ok(Redirect::Inherit),
+        };
+        let mode = match self.value_of("output-mode").expect("output-mode value") {
+            "truncate" => OutputMode::Truncate,
+            "append" => OutputMode::Append,
+            "create-new" => OutputMode::CreateNew,
+            _ => unreachable!(),
+        };
+        let permissions = flip_option_result(
+            self.value_of("output-permissions")
+                .map(|permissions| Ok(u32::from_str_radix(permissions, 8)?)),
+        )
+        .context("Could not parse output permissions")?;
+        let mut options = RedirectOptions::new(mode, permissions);
+        options.set_in_sandbox(self.is_present("redirects-in-sandbox"));
+        Ok(Redirect::File(path, options))
     }
 
     fn wall_time(&self) -> Result<Option<Duration>> {
@@ -225,6 +631,49 @@ impl<'a> ArgMatches<'a> {
         )
     }
 
+    fn wall_time_factor(&self) -> Result<Option<f64>> {
+        flip_option_result(self.value_of("wall-time-factor").map(|x| {
+            let factor = x
+                .parse::<f64>()
+                .context("Could not parse wall time factor")?;
+            // NaN fails the comparison as well
+            if factor > 0.0 {
+                Ok(factor)
+            } else {
+                Err(format_err!("The wall time factor must be positive: {}", x))
+            }
+        }))
+    }
+
+    fn wall_time_slack(&self) -> Result<Option<Duration>> {
+        Ok(
+            flip_option_result(self.value_of("wall-time-slack").map(|x| parse_duration(x)))
+                .context("Could not parse wall time slack")?,
+        )
+    }
+
+    fn kill_grace_period(&self) -> Result<Option<Duration>> {
+        Ok(flip_option_result(
+            self.value_of("kill-grace-period")
+                .map(|x| parse_duration(x)),
+        )
+        .context("Could not parse kill grace period")?)
+    }
+
+    fn sandbox_user(&self) -> Result<Option<(u32, u32)>> {
+        Ok(
+            flip_option_result(self.value_of("sandbox-user").map(|x| parse_sandbox_user(x)))
+                .context("Could not parse sandbox user")?,
+        )
+    }
+
+    fn veth(&self) -> Result<Option<VethConfig>> {
+        Ok(
+            flip_option_result(self.value_of("veth").map(|x| parse_veth(x)))
+                .context("Could not parse veth")?,
+        )
+    }
+
     fn memory(&self) -> Result<Option<SpaceUsage>> {
         Ok(
             flip_option_result(self.value_of("memory").map(|x| parse_space_usage(x)))
@@ -232,6 +681,20 @@ impl<'a> ArgMatches<'a> {
         )
     }
 
+    fn parent_limits(&self) -> Result<Option<Limits>> {
+        let memory =
+            flip_option_result(self.value_of("parent-memory").map(|x| parse_space_usage(x)))
+                .context("Could not parse parent memory")?;
+        let pids = flip_option_result(
+            self.value_of("parent-pids")
+                .map(|x| Ok(x.parse::<usize>().context("Could not parse parent pids")?)),
+        )?;
+        if memory.is_none() && pids.is_none() {
+            return Ok(None);
+        }
+        Ok(Some(Limits::new(None, None, memory, None, None, pids)))
+    }
+
     fn stack(&self) -> Result<Option<SpaceUsage>> {
         Ok(
             flip_option_result(self.value_of("stack").map(|x| parse_space_usage(x)))
@@ -253,20 +716,68 @@ impl<'a> ArgMatches<'a> {
         )
     }
 
+    fn cpu_quota(&self) -> Result<Option<f64>> {
+        flip_option_result(
+            self.value_of("cpu-quota")
+                .map(|x| Ok(x.parse::<f64>().context("Could not parse cpu quota")?)),
+        )
+    }
+
+    fn cpus(&self) -> Option<String> {
+        self.value_of("cpus").map(str::to_owned)
+    }
+
+    fn mems(&self) -> Option<String> {
+        self.value_of("mems").map(str::to_owned)
+    }
+
+    fn read_bps(&self) -> Result<Option<SpaceUsage>> {
+        Ok(
+            flip_option_result(self.value_of("read-bps").map(|x| parse_space_usage(x)))
+                .context("Could not parse read bps")?,
+        )
+    }
+
+    fn write_bps(&self) -> Result<Option<SpaceUsage>> {
+        Ok(
+            flip_option_result(self.value_of("write-bps").map(|x| parse_space_usage(x)))
+                .context("Could not parse write bps")?,
+        )
+    }
+
+    fn net_bytes(&self) -> Result<Option<SpaceUsage>> {
+        Ok(
+            flip_option_result(self.value_of("net-bytes").map(|x| parse_space_usage(x)))
+                .context("Could not parse net bytes")?,
+        )
+    }
+
     fn instance_name(&self) -> Option<OsString> {
         self.value_of_os("instance-name").map(OsStr::to_os_string)
     }
 
     fn cpuacct_controller_path(&self) -> Option<PathBuf> {
-        self.value_of_os("cpuacct-path").map(PathBuf::from)
+        self.value_of_os("cpuacct-controller").map(PathBuf::from)
     }
 
     fn memory_controller_path(&self) -> Option<PathBuf> {
-        self.value_of_os("memory-path").map(PathBuf::from)
+        self.value_of_os("memory-controller").map(PathBuf::from)
     }
 
     fn pids_controller_path(&self) -> Option<PathBuf> {
-        self.value_of_os("pids-path").map(PathBuf::from)
+        self.value_of_os("pids-controller").map(PathBuf::from)
+    }
+
+    fn cpu_controller_path(&self) -> Option<PathBuf> {
+        self.value_of_os("cpu-controller").map(PathBuf::from)
+    }
+
+    fn cpuset_controller_path(&self) -> Option<PathBuf> {
+        self.value_of_os("cpuset-controller").map(PathBuf::from)
+    }
+
+    fn io_controller_path(&self) -> Option<PathBuf> {
+        self.value_of_os("io-controller").map(PathBuf::from)
     }
 
     fn output_type(&self) -> OutputType {
@@ -279,12 +790,107 @@ impl<'a> ArgMatches<'a> {
     }
 
     fn mounts(&self) -> Result<Vec<Mount>> {
-        match self.values_of("mount") {
+        let mounts = self
+            .values_of("mount")
+            .into_iter()
+            .flatten()
+            .map(parse_mount);
+        let tmpfs = self
+            .values_of("tmpfs")
+            .into_iter()
+            .flatten()
+            .map(parse_tmpfs);
+        let overlays = self
+            .values_of("overlay")
+            .into_iter()
+            .flatten()
+            .map(parse_overlay_mount);
+        mounts.chain(tmpfs).chain(overlays).collect()
+    }
+
+    fn join_namespaces(&self) -> Result<Vec<(NamespaceKind, PathBuf)>> {
+        match self.values_of("join-namespace") {
+            None => Ok(vec![]),
+            Some(args) => args.map(parse_join_namespace).collect(),
+        }
+    }
+
+    fn keep_capabilities(&self) -> Result<Vec<Capability>> {
+        match self.values_of("keep-capability") {
             None => Ok(vec![]),
-            Some(args) => args.map(parse_mount).collect(),
+            Some(args) => args.map(parse_capability).collect(),
+        }
+    }
+
+    fn landlock(&self) -> Result<Option<LandlockPolicy>> {
+        let rules = match self.values_of("landlock") {
+            None => return Ok(None),
+            Some(args) => args.map(parse_landlock_rule).collect::<Result<_>>()?,
+        };
+        let unsupported = if self.is_present("landlock-warn") {
+            LandlockUnsupported::Warn
+        } else {
+            LandlockUnsupported::Fail
+        };
+        Ok(Some(LandlockPolicy::new(rules, unsupported)))
+    }
+
+    fn seccomp(&self) -> Result<Option<SeccompPolicy>> {
+        let default_action =
+            flip_option_result(self.value_of("seccomp-default").map(parse_seccomp_action))
+                .context("Could not parse seccomp default action")?;
+        let profile = self
+            .value_of("seccomp-profile")
+            .map(|profile| match profile {
+                "cpp" => seccomp_profiles::cpp(),
+                "python3" => seccomp_profiles::python3(),
+                "jvm" => seccomp_profiles::jvm(),
+                _ => unreachable!(),
+            });
+        let syscalls = self.values_of("seccomp-allow");
+        if profile.is_none() && syscalls.is_none() && default_action.is_none() {
+            return Ok(None);
+        }
+
+        let mut policy =
+            profile.unwrap_or_else(|| SeccompPolicy::new(Vec::new(), SeccompAction::Kill));
+        for syscall in syscalls.into_iter().flatten() {
+            policy = policy.allow(syscall);
+        }
+        Ok(Some(match default_action {
+            Some(default_action) => SeccompPolicy::new(policy.syscalls().to_vec(), default_action),
+            None => policy,
+        }))
+    }
+
+    fn single_exec(&self) -> SingleExec {
+        if self.is_present("single-exec") {
+            SingleExec::Yes
+        } else {
+            SingleExec::No
+        }
+    }
+
+    fn deny_nested_namespaces(&self) -> DenyNestedNamespaces {
+        if self.is_present("allow-nested-namespaces") {
+            DenyNestedNamespaces::No
+        } else {
+            DenyNestedNamespaces::Yes
+        }
+    }
+
+    fn allocate_pty(&self) -> AllocatePty {
+        if self.is_present("pty") {
+            AllocatePty::Yes
+        } else {
+            AllocatePty::No
         }
     }
 
+    fn trace_syscalls(&self) -> Option<PathBuf> {
+        self.value_of_os("trace-syscalls").map(PathBuf::from)
+    }
+
     fn swap_redirects(&self) -> SwapRedirects {
         if self.is_present("swap-redirects") {
             SwapRedirects::Yes
@@ -293,6 +899,14 @@ impl<'a> ArgMatches<'a> {
         }
     }
 
+    fn allow_same_file_io(&self) -> AllowSameFileIo {
+        if self.is_present("allow-same-file-io") {
+            AllowSameFileIo::Yes
+        } else {
+            AllowSameFileIo::No
+        }
+    }
+
     fn clear_usage(&self) -> ClearUsage {
         if self.is_present("no-clear-usage") {
             ClearUsage::No
@@ -325,6 +939,101 @@ impl<'a> ArgMatches<'a> {
         }
     }
 
+    fn exclude_stopped_time(&self) -> ExcludeStoppedTime {
+        if self.is_present("exclude-stopped-time") {
+            ExcludeStoppedTime::Yes
+        } else {
+            ExcludeStoppedTime::No
+        }
+    }
+
+    fn mount_binfmt_interpreter(&self) -> MountBinfmtInterpreter {
+        if self.is_present("mount-binfmt-interpreter") {
+            MountBinfmtInterpreter::Yes
+        } else {
+            MountBinfmtInterpreter::No
+        }
+    }
+
+    fn minimal_dev(&self) -> MinimalDev {
+        if self.is_present("no-minimal-dev") {
+            MinimalDev::No
+        } else {
+            MinimalDev::Yes
+        }
+    }
+
+    fn private_tmp(&self) -> PrivateTmp {
+        if self.is_present("no-private-tmp") {
+            PrivateTmp::No
+        } else {
+            PrivateTmp::Yes
+        }
+    }
+
+    fn private_tmp_size(&self) -> Result<Option<SpaceUsage>> {
+        Ok(
+            flip_option_result(self.value_of("private-tmp-size").map(parse_space_usage))
+                .context("Could not parse private tmp size")?,
+        )
+    }
+
+    fn stderr_tail(&self) -> Result<Option<SpaceUsage>> {
+        Ok(
+            flip_option_result(self.value_of("stderr-tail").map(parse_space_usage))
+                .context("Could not parse stderr tail")?,
+        )
+    }
+
+    fn read_only_root(&self) -> ReadOnlyRoot {
+        if self.is_present("read-only-root") {
+            ReadOnlyRoot::Yes
+        } else {
+            ReadOnlyRoot::No
+        }
+    }
+
+    fn proc_options(&self) -> Result<ProcOptions> {
+        self.value_of("proc-options")
+            .map_or(Ok(ProcOptions::default()), parse_proc_options)
+    }
+
+    fn mount_cgroups(&self) -> MountCgroups {
+        if self.is_present("mount-cgroups") {
+            MountCgroups::Yes
+        } else {
+            MountCgroups::No
+        }
+    }
+
+    fn disable_swap(&self) -> DisableSwap {
+        if self.is_present("allow-swap") {
+            DisableSwap::No
+        } else {
+            DisableSwap::Yes
+        }
+    }
+
+    fn memory_accounting(&self) -> MemoryAccounting {
+        match self
+            .value_of("memory-accounting")
+            .expect("memory-accounting value")
+        {
+            "full" => MemoryAccounting::Full,
+            "rss-only" => MemoryAccounting::RssOnly,
+            _ => unreachable!(),
+        }
+    }
+
+    fn propagation(&self) -> Propagation {
+        match self.value_of("propagation").expect("propagation value") {
+            "private" => Propagation::Private,
+            "slave" => Propagation::Slave,
+            "shared" => Propagation::Shared,
+            _ => unreachable!(),
+        }
+    }
+
     fn environment(&self) -> Result<Environment> {
         if self.is_present("forward-env") {
             return Ok(Environment::Forward);
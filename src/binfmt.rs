@@ -0,0 +1,283 @@
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use errors::FFIError;
+
+const BINFMT_MISC_PATH: &str = "/proc/sys/fs/binfmt_misc";
+// Large enough for the ELF header and any sane binfmt_misc magic
+const HEADER_SIZE: usize = 128;
+
+/// The ELF machine (`e_machine`) of a binary, given its first bytes
+pub fn elf_machine(header: &[u8]) -> Option<u16> {
+    if header.len() < 20 || &header[..4] != b"\x7fELF" {
+        return None;
+    }
+
+    let bytes = [header[18], header[19]];
+    match header[5] {
+        1 => Some(u16::from_le_bytes(bytes)),
+        2 => Some(u16::from_be_bytes(bytes)),
+        _ => None,
+    }
+}
+
+/// The ELF machine of the running executable
+pub fn host_machine() -> Option<u16> {
+    read_header("/proc/self/exe").and_then(|header| elf_machine(&header))
+}
+
+/// Human readable name of an ELF machine
+pub fn machine_name(machine: u16) -> String {
+    match machine {
+        3 => "i386".into(),
+        8 => "mips".into(),
+        20 => "ppc".into(),
+        21 => "ppc64".into(),
+        22 => "s390x".into(),
+        40 => "arm".into(),
+        62 => "x86_64".into(),
+        183 => "aarch64".into(),
+        243 => "riscv".into(),
+        machine => format!("machine {}", machine),
+    }
+}
+
+fn read_header<T: AsRef<Path>>(path: T) -> Option<Vec<u8>> {
+    let mut header = Vec::with_capacity(HEADER_SIZE);
+    File::open(path)
+        .and_then(|file| file.take(HEADER_SIZE as u64).read_to_end(&mut header))
+        .ok()
+        .map(|_| header)
+}
+
+fn parse_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(hex.get(index..index + 2)?, 16).ok())
+        .collect()
+}
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+enum BinfmtMatcher {
+    Magic {
+        offset: usize,
+        magic: Vec<u8>,
+        mask: Vec<u8>,
+    },
+    Extension(String),
+}
+
+/// One registration under `/proc/sys/fs/binfmt_misc`
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct BinfmtEntry {
+    name: String,
+    enabled: bool,
+    interpreter: PathBuf,
+    flags: String,
+    matcher: BinfmtMatcher,
+}
+
+impl BinfmtEntry {
+    /// Parses the content of `/proc/sys/fs/binfmt_misc/<name>`, for example:
+    ///
+    /// ```text
+    /// enabled
+    /// interpreter /usr/bin/qemu-aarch64-static
+    /// flags: F
+    /// offset 0
+    /// magic 7f454c460201010000000000000000000200b700
+    /// mask ffffffffffffff00fffffffffffffffffeffffff
+    /// ```
+    pub fn parse(name: &str, content: &str) -> Option<Self> {
+        let mut enabled = None;
+        let mut interpreter = None;
+        let mut flags = String::new();
+        let mut offset = 0;
+        let mut magic = None;
+        let mut mask = None;
+        let mut extension = None;
+
+        for line in content.lines() {
+            let mut parts = line.splitn(2, ' ');
+            match (parts.next()?, parts.next().unwrap_or("").trim()) {
+                ("enabled", _) => enabled = Some(true),
+                ("disabled", _) => enabled = Some(false),
+                ("interpreter", value) => interpreter = Some(PathBuf::from(value)),
+                ("flags:", value) => flags = value.to_owned(),
+                ("offset", value) => offset = value.parse().ok()?,
+                ("magic", value) => magic = Some(parse_hex(value)?),
+                ("mask", value) => mask = Some(parse_hex(value)?),
+                ("extension", value) => extension = Some(value.trim_start_matches('.').to_owned()),
+                _ => (),
+            }
+        }
+
+        let matcher = match (magic, extension) {
+            (Some(magic), _) => BinfmtMatcher::Magic {
+                offset,
+                mask: mask.unwrap_or_else(|| vec![0xff; magic.len()]),
+                magic,
+            },
+            (None, Some(extension)) => BinfmtMatcher::Extension(extension),
+            (None, None) => return None,
+        };
+
+        Some(Self {
+            name: name.to_owned(),
+            enabled: enabled?,
+            interpreter: interpreter?,
+            flags,
+            matcher,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn interpreter(&self) -> &Path {
+        &self.interpreter
+    }
+
+    /// With the `F` flag the kernel opens the interpreter when registering it, so it does
+    /// not have to exist inside the new root
+    pub fn fix_binary(&self) -> bool {
+        self.flags.contains('F')
+    }
+
+    /// Whether this entry handles the binary at `path` starting with `header`
+    pub fn matches<T: AsRef<Path>>(&self, path: T, header: &[u8]) -> bool {
+        match self.matcher {
+            BinfmtMatcher::Magic {
+                offset,
+                ref magic,
+                ref mask,
+            } => header
+                .get(offset..offset + magic.len())
+                .map_or(false, |bytes| {
+                    bytes
+                        .iter()
+                        .zip(magic.iter().zip(mask))
+                        .all(|(byte, (magic, mask))| byte & mask == magic & mask)
+                }),
+            BinfmtMatcher::Extension(ref extension) => path
+                .as_ref()
+                .extension()
+                .map_or(false, |path_extension| path_extension == extension.as_str()),
+        }
+    }
+}
+
+/// An interpreter a foreign binary needs but that does not exist inside the new root
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct MissingInterpreter {
+    interpreter: PathBuf,
+    arch: String,
+}
+
+impl MissingInterpreter {
+    pub fn interpreter(&self) -> &Path {
+        &self.interpreter
+    }
+
+    pub fn arch(&self) -> &str {
+        &self.arch
+    }
+}
+
+/// The `binfmt_misc` registrations of the system
+#[derive(Debug, Eq, PartialEq, Clone, Default)]
+pub struct Binfmt(Vec<BinfmtEntry>);
+
+impl Binfmt {
+    pub fn new(entries: Vec<BinfmtEntry>) -> Self {
+        Self(entries)
+    }
+
+    /// Reads all registrations, none if `binfmt_misc` is not mounted
+    pub fn read() -> Result<Self, FFIError> {
+        let read_error = |err: io::Error| FFIError::ReadBinfmtError(err.to_string());
+        let dir = match fs::read_dir(BINFMT_MISC_PATH) {
+            Ok(dir) => dir,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => return Err(read_error(err)),
+        };
+
+        let mut entries = Vec::new();
+        for dir_entry in dir {
+            let dir_entry = dir_entry.map_err(read_error)?;
+            let name = dir_entry.file_name().to_string_lossy().into_owned();
+            if name == "register" || name == "status" {
+                continue;
+            }
+
+            let content = fs::read_to_string(dir_entry.path()).map_err(read_error)?;
+            entries.extend(BinfmtEntry::parse(&name, &content));
+        }
+        Ok(Self(entries))
+    }
+
+    pub fn entries(&self) -> &[BinfmtEntry] {
+        &self.0
+    }
+
+    /// The enabled entry the kernel would run the binary at `path` with
+    pub fn find<T: AsRef<Path>>(&self, path: T, header: &[u8]) -> Option<&BinfmtEntry> {
+        self.0
+            .iter()
+            .find(|entry| entry.enabled() && entry.matches(path.as_ref(), header))
+    }
+
+    /// If `command` (a path inside `new_root`) is a binary for another architecture that
+    /// runs through `binfmt_misc`, the interpreter it needs if that is missing from `new_root`
+    pub fn missing_interpreter(
+        &self,
+        new_root: &Path,
+        command: &Path,
+    ) -> Option<MissingInterpreter> {
+        let path = new_root.join(command.strip_prefix("/").unwrap_or(command));
+        let header = read_header(&path)?;
+        let machine = elf_machine(&header)?;
+        if Some(machine) == host_machine() {
+            return None;
+        }
+
+        let entry = self.find(&path, &header)?;
+        let inner_interpreter = new_root.join(
+            entry
+                .interpreter()
+                .strip_prefix("/")
+                .unwrap_or_else(|_| entry.interpreter()),
+        );
+        if entry.fix_binary() || inner_interpreter.exists() {
+            return None;
+        }
+
+        Some(MissingInterpreter {
+            interpreter: entry.interpreter().to_path_buf(),
+            arch: machine_name(machine),
+        })
+    }
+}
+
+/// Same as `Binfmt::missing_interpreter`, but only reads `binfmt_misc` for foreign binaries
+pub(crate) fn find_missing_interpreter(
+    new_root: &Path,
+    command: &Path,
+) -> Result<Option<MissingInterpreter>, FFIError> {
+    let path = new_root.join(command.strip_prefix("/").unwrap_or(command));
+    let machine = read_header(&path).and_then(|header| elf_machine(&header));
+    if machine.is_none() || machine == host_machine() {
+        return Ok(None);
+    }
+
+    Ok(Binfmt::read()?.missing_interpreter(new_root, command))
+}
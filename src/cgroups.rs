@@ -1,17 +1,25 @@
+use std::cell::Cell;
 use std::cmp;
+use std::collections::HashSet;
 use std::error::Error;
 use std::ffi::{OsStr, OsString};
-use std::fs::{self, OpenOptions};
-use std::io::{Read, Write};
+use std::fmt::{self, Display, Formatter};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::unix::io::{AsRawFd, RawFd};
 use std::path::{Path, PathBuf};
-use std::process::Command;
 use std::result;
 use std::str::FromStr;
-use std::time::Duration;
+use std::thread;
+use std::time::{Duration, Instant};
 
-use config::{ClearUsage, ControllerPath, IsolatedCgroup, Limits, SpaceUsage};
-use errors::CGroupError;
+use config::{
+    ClearUsage, ControllerPath, DisableSwap, IsolatedCgroup, Limits, MemoryAccounting, Mount,
+    MountOptions, Pausable, SpaceUsage,
+};
+use errors::{self, CGroupError};
 use ffi;
+use mount_info::{BlockDevice, MountInfo};
 use run_info::RunUsage;
 
 type Result<T> = result::Result<T, CGroupError>;
@@ -39,10 +47,7 @@ fn cgroup_write<T1: AsRef<Path>, T2: AsRef<str>>(
         })
 }
 
-fn cgroup_read<T1: AsRef<Path>, T2: FromStr>(controller_path: &Path, file: T1) -> Result<T2>
-where
-    <T2 as FromStr>::Err: Error,
-{
+fn cgroup_read_to_string<T: AsRef<Path>>(controller_path: &Path, file: T) -> Result<String> {
     let path = controller_path.join(file.as_ref());
     let mut cgroup_file = OpenOptions::new().read(true).open(&path).map_err(|err| {
         CGroupError::OpenCGroupFileError {
@@ -60,74 +65,208 @@ where
             error: err.to_string().into(),
         }
     })?;
+    Ok(buffer)
+}
 
+fn cgroup_parse<T1: AsRef<Path>, T2: FromStr>(
+    controller_path: &Path,
+    file: T1,
+    buffer: &str,
+) -> Result<T2>
+where
+    <T2 as FromStr>::Err: Error,
+{
     buffer
         .trim()
         .parse::<T2>()
         .map_err(|err| CGroupError::ParseCGroupFileError {
             controller_path: controller_path.to_path_buf(),
             file: file.as_ref().to_path_buf(),
-            buffer,
+            buffer: buffer.to_owned(),
             error: err.to_string().into(),
         })
 }
 
-// Assumes the file contains "key value" pairs separated by one space.
+fn cgroup_read<T1: AsRef<Path>, T2: FromStr>(controller_path: &Path, file: T1) -> Result<T2>
+where
+    <T2 as FromStr>::Err: Error,
+{
+    let buffer = cgroup_read_to_string(controller_path, file.as_ref())?;
+    cgroup_parse(controller_path, file, &buffer)
+}
+
+// Assumes the file contains "key value" pairs, one per line.
 // Returns the value for the given key.
-fn cgroup_read_value(controller_path: &Path, file: &str, _key: &str)
-                     -> libc::rlim_t {
+fn cgroup_read_value<T1: AsRef<Path>, T2: FromStr>(
+    controller_path: &Path,
+    file: T1,
+    key: &str,
+) -> Result<T2>
+where
+    <T2 as FromStr>::Err: Error,
+{
+    let buffer = cgroup_read_to_string(controller_path, file.as_ref())?;
+    let value = buffer
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.split_whitespace();
+            match (parts.next(), parts.next()) {
+                (Some(name), Some(value)) if name == key => Some(value),
+                _ => None,
+            }
+        })
+        .next()
+        .ok_or_else(|| CGroupError::ParseCGroupFileError {
+            controller_path: controller_path.to_path_buf(),
+            file: file.as_ref().to_path_buf(),
+            buffer: buffer.clone(),
+            error: format!("missing key `{}`", key),
+        })?;
+    cgroup_parse(controller_path, file, value)
+}
 
-    // grep for ^cache
-    let path = controller_path.join(file);
-    let output = Command::new("grep")
-        .arg("^cache ")
-        .arg(&path)
-        .output()
-        .expect("failed to execute process");
-    let stdout = String::from_utf8(output.stdout).unwrap();
+// Writes 0 to the counters that exist, which depends on the kernel (and its configuration).
+// Counters such as pids.events (or memory.peak on cgroups v2) can not be reset at all.
+fn cgroup_reset(controller_path: &Path, files: &[&str]) -> Result<()> {
+    for file in files {
+        if controller_path.join(file).exists() {
+            cgroup_write(controller_path, file, "0\n")?;
+        }
+    }
+    Ok(())
+}
 
-    // split the line and return the second chunk
-    let parts:Vec<&str> = stdout.trim().split(" ").collect();
-    parts[1].parse::<libc::rlim_t>().unwrap()
+/// Which cgroups version a controller is mounted with. On hybrid hosts (systemd) some
+/// controllers are on cgroups v1 hierarchies and the rest on the unified v2 one, so it is
+/// detected for each controller on its own.
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum CgroupVersion {
+    V1,
+    V2,
+}
 
+impl CgroupVersion {
+    /// Every cgroups v2 directory has a `cgroup.controllers` file, no v1 directory has one
+    pub fn detect(cgroup_path: &Path) -> Self {
+        if cgroup_path.join("cgroup.controllers").exists() {
+            Self::V2
+        } else {
+            Self::V1
+        }
+    }
+}
+
+// cgroups v2 only has the files of a controller in the cgroups whose parent enables it for its
+// children, so enable it from the controller path down to the parent of `cgroup_path`. The
+// controller path itself has to be set up with it already.
+fn enable_controller(controller: &str, controller_path: &Path, cgroup_path: &Path) -> Result<()> {
+    let mut path = controller_path.to_path_buf();
+    for component in cgroup_path
+        .strip_prefix(controller_path)
+        .unwrap_or(Path::new(""))
+    {
+        cgroup_write(
+            &path,
+            "cgroup.subtree_control",
+            format!("+{}\n", controller),
+        )?;
+        path.push(component);
+    }
+    Ok(())
+}
+
+// The cgroup the sandboxed process ends up in
+fn process_cgroup_path(instance_path: &Path, isolated_cgroup: IsolatedCgroup) -> PathBuf {
+    match isolated_cgroup {
+        IsolatedCgroup::Yes => instance_path.join(ISOLATED_CGROUP_NAME),
+        IsolatedCgroup::No => instance_path.to_path_buf(),
+    }
 }
 
 const ISOLATED_CGROUP_NAME: &str = "isolated";
+fn create_isolated_cgroup(controller_path: &Path) -> Result<PathBuf> {
+    let isolated_cgroup = controller_path.join(ISOLATED_CGROUP_NAME);
+
+    if !isolated_cgroup.exists() {
+        fs::create_dir(&isolated_cgroup).map_err(|err| {
+            CGroupError::InstanceControllerCreateError {
+                controller_path: controller_path.to_path_buf(),
+                instance_name: OsString::from(ISOLATED_CGROUP_NAME),
+                error: err.to_string().into(),
+            }
+        })?;
+    }
+    Ok(isolated_cgroup)
+}
+
 pub(crate) fn enter_cgroup(
     controller_path: &Path,
     isolated_cgroup: IsolatedCgroup,
+    version: CgroupVersion,
 ) -> Result<()> {
+    // cgroups v2 has no `tasks`, whole processes move through `cgroup.procs`
+    let file = match version {
+        CgroupVersion::V1 => "tasks",
+        CgroupVersion::V2 => "cgroup.procs",
+    };
     if isolated_cgroup == IsolatedCgroup::Yes {
-        let isolated_cgroup = controller_path.join(ISOLATED_CGROUP_NAME);
-
-        if !isolated_cgroup.exists() {
-            fs::create_dir(&isolated_cgroup).map_err(|err| {
-                CGroupError::InstanceControllerCreateError {
-                    controller_path: controller_path.to_path_buf(),
-                    instance_name: OsString::from(ISOLATED_CGROUP_NAME),
-                    error: err.to_string().into(),
-                }
-            })?;
-        }
-
-        cgroup_write(&isolated_cgroup, "tasks", format!("{}\n", ffi::getpid()))
+        let isolated_cgroup = create_isolated_cgroup(controller_path)?;
+        cgroup_write(&isolated_cgroup, file, format!("{}\n", ffi::getpid()))
     } else {
-        cgroup_write(&controller_path, "tasks", format!("{}\n", ffi::getpid()))
+        cgroup_write(&controller_path, file, format!("{}\n", ffi::getpid()))
     }
 }
 
 const DEFAULT_INSTANCE_NAME: &str = "default";
-fn get_instance_path(controller_path: &Path, instance_name: Option<&OsStr>) -> Result<PathBuf> {
-    let instance = instance_name.unwrap_or_else(|| OsStr::new(DEFAULT_INSTANCE_NAME));
+
+fn check_controller(controller: &str, controller_path: &Path) -> Result<()> {
     if !controller_path.exists() {
-        return Err(CGroupError::ControllerMissing(
-            controller_path.to_path_buf(),
-        ));
+        return Err(CGroupError::ControllerMissing {
+            controller: controller.to_owned(),
+            path: controller_path.to_path_buf(),
+            uid: ffi::caller_uid(),
+        });
+    }
+    Ok(())
+}
+
+/// Whether the controller exists and the caller may create cgroups in it
+pub(crate) fn check_controller_access(controller: &str, controller_path: &Path) -> Result<()> {
+    check_controller(controller, controller_path)?;
+    if !ffi::is_writable(controller_path) {
+        return Err(not_writable(controller, controller_path));
     }
+    Ok(())
+}
+
+fn not_writable(controller: &str, controller_path: &Path) -> CGroupError {
+    CGroupError::NotWritable {
+        controller: controller.to_owned(),
+        path: controller_path.to_path_buf(),
+        uid: ffi::caller_uid(),
+    }
+}
+
+fn is_permission_error(err: &io::Error) -> bool {
+    err.kind() == io::ErrorKind::PermissionDenied || err.raw_os_error() == Some(libc::EROFS)
+}
+
+// Instance names may contain path separators (`batch42/run3`), the cgroups in between are
+// created as needed. fs::create_dir_all tolerates concurrent runs creating them as well.
+fn get_instance_path(
+    controller: &str,
+    controller_path: &Path,
+    instance_name: Option<&OsStr>,
+) -> Result<PathBuf> {
+    let instance = instance_name.unwrap_or_else(|| OsStr::new(DEFAULT_INSTANCE_NAME));
+    check_controller(controller, controller_path)?;
 
     let instance_path = controller_path.join(instance);
     if !instance_path.exists() {
-        fs::create_dir(&instance_path).map_err(|err| {
+        fs::create_dir_all(&instance_path).map_err(|err| {
+            if is_permission_error(&err) {
+                return not_writable(controller, controller_path);
+            }
             CGroupError::InstanceControllerCreateError {
                 controller_path: controller_path.to_path_buf(),
                 instance_name: instance.to_os_string(),
@@ -135,9 +274,62 @@ fn get_instance_path(controller_path: &Path, instance_name: Option<&OsStr>) -> R
             }
         })?;
     }
+    // An instance left over from a run of another user
+    if !ffi::is_writable(&instance_path) {
+        return Err(not_writable(controller, controller_path));
+    }
     Ok(instance_path)
 }
 
+// Creates the parent of a nested instance (`batch42` for `batch42/run3`) and writes its
+// limits. It is created under its final name, cgroups v2 refuses to rename cgroups, so of
+// concurrent creators only the first gets it and the others get EEXIST and use the parent
+// as the first one sets it up.
+fn create_parent_cgroup<F>(
+    controller: &str,
+    controller_path: &Path,
+    instance_name: Option<&OsStr>,
+    write_limits: F,
+) -> Result<()>
+where
+    F: FnOnce(&Path) -> Result<()>,
+{
+    let parent = match instance_name.and_then(|instance| Path::new(instance).parent()) {
+        Some(parent) if parent != Path::new("") => parent,
+        _ => return Ok(()),
+    };
+    check_controller(controller, controller_path)?;
+    let parent_path = controller_path.join(parent);
+    if parent_path.exists() {
+        return Ok(());
+    }
+
+    let create_error = |err: io::Error| {
+        if is_permission_error(&err) {
+            return not_writable(controller, controller_path);
+        }
+        CGroupError::InstanceControllerCreateError {
+            controller_path: controller_path.to_path_buf(),
+            instance_name: parent.as_os_str().to_os_string(),
+            error: err.to_string(),
+        }
+    };
+    let grandparent_path = parent_path.parent().unwrap_or(controller_path);
+    fs::create_dir_all(grandparent_path).map_err(create_error)?;
+
+    match fs::create_dir(&parent_path) {
+        Ok(()) => {}
+        Err(ref err) if err.kind() == io::ErrorKind::AlreadyExists => return Ok(()),
+        Err(err) => return Err(create_error(err)),
+    }
+    // Removed again so that the next run retries instead of using a parent without limits
+    let result = write_limits(&parent_path);
+    if result.is_err() {
+        fs::remove_dir(&parent_path).unwrap_or(());
+    }
+    result
+}
+
 const CPUACCT_DEFAULT_CONTROLLER_PATH: &str = "/sys/fs/cgroup/cpuacct/ia-sandbox";
 pub(crate) fn enter_cpuacct_cgroup(
     controller_path: Option<&Path>,
@@ -145,36 +337,276 @@ pub(crate) fn enter_cpuacct_cgroup(
     clear_usage: ClearUsage,
     isolated_cgroup: IsolatedCgroup,
 ) -> Result<()> {
-    let instance_path = get_instance_path(
-        controller_path.unwrap_or_else(|| Path::new(CPUACCT_DEFAULT_CONTROLLER_PATH)),
-        instance_name,
-    )?;
+    let controller_path =
+        controller_path.unwrap_or_else(|| Path::new(CPUACCT_DEFAULT_CONTROLLER_PATH));
+    let version = CgroupVersion::detect(controller_path);
+    let instance_path = get_instance_path("cpuacct", controller_path, instance_name)?;
 
-    if clear_usage == ClearUsage::Yes {
+    // The cpu time in cpu.stat of cgroups v2 can not be reset, see `get_user_time_offset`
+    if clear_usage == ClearUsage::Yes && version == CgroupVersion::V1 {
         cgroup_write(&instance_path, "cpuacct.usage", "0\n")?;
     }
 
-    enter_cgroup(&instance_path, isolated_cgroup)
+    enter_cgroup(&instance_path, isolated_cgroup, version)
+}
+
+/// The cpu time the instance already used before the run when its usage is cleared. It is
+/// only ever not zero on cgroups v2, which has no cpuacct controller and no way to reset the
+/// `usage_usec` of cpu.stat.
+pub(crate) fn get_user_time_offset(
+    controller_path: &ControllerPath,
+    instance_name: Option<&OsStr>,
+    clear_usage: ClearUsage,
+) -> Result<Duration> {
+    let controller_path = controller_path
+        .cpuacct()
+        .unwrap_or_else(|| Path::new(CPUACCT_DEFAULT_CONTROLLER_PATH));
+    if clear_usage == ClearUsage::No || CgroupVersion::detect(controller_path) == CgroupVersion::V1
+    {
+        return Ok(Duration::from_secs(0));
+    }
+    let instance_path = get_instance_path("cpuacct", controller_path, instance_name)?;
+    cgroup_read_value(&instance_path, "cpu.stat", "usage_usec").map(Duration::from_micros)
+}
+
+const CPU_DEFAULT_CONTROLLER_PATH: &str = "/sys/fs/cgroup/cpu/ia-sandbox";
+const CPU_CFS_PERIOD_US: u64 = 100_000;
+// The kernel refuses quotas under 1ms
+const CPU_CFS_MIN_QUOTA_US: u64 = 1_000;
+pub(crate) fn enter_cpu_cgroup(
+    controller_path: Option<&Path>,
+    instance_name: Option<&OsStr>,
+    cpu_quota: f64,
+    clear_usage: ClearUsage,
+    isolated_cgroup: IsolatedCgroup,
+) -> Result<()> {
+    let controller_path = controller_path.unwrap_or_else(|| Path::new(CPU_DEFAULT_CONTROLLER_PATH));
+    let version = CgroupVersion::detect(controller_path);
+    let instance_path = get_instance_path("cpu", controller_path, instance_name)?;
+
+    if clear_usage == ClearUsage::Yes {
+        let quota = cmp::max(
+            (cpu_quota * CPU_CFS_PERIOD_US as f64).round() as u64,
+            CPU_CFS_MIN_QUOTA_US,
+        );
+        match version {
+            CgroupVersion::V1 => {
+                cgroup_write(
+                    &instance_path,
+                    "cpu.cfs_period_us",
+                    format!("{}\n", CPU_CFS_PERIOD_US),
+                )?;
+                cgroup_write(&instance_path, "cpu.cfs_quota_us", format!("{}\n", quota))?;
+            }
+            CgroupVersion::V2 => {
+                enable_controller(
+                    "cpu",
+                    controller_path,
+                    &process_cgroup_path(&instance_path, isolated_cgroup),
+                )?;
+                cgroup_write(
+                    &instance_path,
+                    "cpu.max",
+                    format!("{} {}\n", quota, CPU_CFS_PERIOD_US),
+                )?;
+            }
+        }
+    }
+
+    enter_cgroup(&instance_path, isolated_cgroup, version)
+}
+
+/// Total time the instance was throttled by its cpu quota and the number of periods it was
+/// throttled in (`None` if it never had a cpu quota)
+pub(crate) fn get_throttling(
+    controller_path: &ControllerPath,
+    instance_name: Option<&OsStr>,
+) -> Result<Option<(Duration, u64)>> {
+    let controller_path = controller_path
+        .cpu()
+        .unwrap_or_else(|| Path::new(CPU_DEFAULT_CONTROLLER_PATH));
+    let instance_path =
+        controller_path.join(instance_name.unwrap_or_else(|| OsStr::new(DEFAULT_INSTANCE_NAME)));
+    if !instance_path.join("cpu.stat").exists() {
+        return Ok(None);
+    }
+
+    // cgroups v1 reports nanoseconds, v2 microseconds (and has cpu.stat even without the cpu
+    // controller enabled, only without the throttling counters)
+    let throttled_time = match CgroupVersion::detect(controller_path) {
+        CgroupVersion::V1 => cgroup_read_value(&instance_path, "cpu.stat", "throttled_time")
+            .map(Duration::from_nanos),
+        CgroupVersion::V2 => {
+            match cgroup_read_value(&instance_path, "cpu.stat", "throttled_usec") {
+                Ok(throttled_usec) => Ok(Duration::from_micros(throttled_usec)),
+                Err(_) => return Ok(None),
+            }
+        }
+    }?;
+    let nr_throttled = cgroup_read_value(&instance_path, "cpu.stat", "nr_throttled")?;
+    Ok(Some((throttled_time, nr_throttled)))
+}
+
+const CPUSET_DEFAULT_CONTROLLER_PATH: &str = "/sys/fs/cgroup/cpuset/ia-sandbox";
+const CPUSET_FILES: [&str; 2] = ["cpuset.cpus", "cpuset.mems"];
+
+// cgroups v1 creates cpusets without any cpus or mems and refuses to attach tasks to them,
+// so start them off with the ones of their parent
+fn cpuset_inherit(cgroup_path: &Path) -> Result<()> {
+    let parent_path = match cgroup_path.parent() {
+        Some(parent_path) => parent_path,
+        None => return Ok(()),
+    };
+
+    for file in &CPUSET_FILES {
+        if cgroup_read_to_string(cgroup_path, file)?.trim().is_empty() {
+            let parent_value = cgroup_read_to_string(parent_path, file)?;
+            cgroup_write(cgroup_path, file, parent_value)?;
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn enter_cpuset_cgroup(
+    controller_path: Option<&Path>,
+    instance_name: Option<&OsStr>,
+    cpus: Option<&str>,
+    mems: Option<&str>,
+    clear_usage: ClearUsage,
+    isolated_cgroup: IsolatedCgroup,
+) -> Result<()> {
+    let controller_path =
+        controller_path.unwrap_or_else(|| Path::new(CPUSET_DEFAULT_CONTROLLER_PATH));
+    let version = CgroupVersion::detect(controller_path);
+    let instance_path = get_instance_path("cpuset", controller_path, instance_name)?;
+
+    // cgroups v2 cpusets start off empty as well, but then use the cpus and mems of their
+    // parent, and they do not have to stay subsets of it
+    if version == CgroupVersion::V2 {
+        enable_controller(
+            "cpuset",
+            controller_path,
+            &process_cgroup_path(&instance_path, isolated_cgroup),
+        )?;
+        if clear_usage == ClearUsage::Yes {
+            for (file, value) in CPUSET_FILES.iter().zip(&[cpus, mems]) {
+                cgroup_write(&instance_path, file, format!("{}\n", value.unwrap_or("")))?;
+            }
+        }
+        return enter_cgroup(&instance_path, isolated_cgroup, version);
+    }
+
+    // Top down, through the cgroups in between for nested instance names
+    let mut cgroup_path = controller_path.to_path_buf();
+    cpuset_inherit(&cgroup_path)?;
+    for component in instance_path
+        .strip_prefix(controller_path)
+        .unwrap_or(Path::new(""))
+    {
+        cgroup_path.push(component);
+        cpuset_inherit(&cgroup_path)?;
+    }
+
+    let isolated_path = if isolated_cgroup == IsolatedCgroup::Yes {
+        let isolated_path = create_isolated_cgroup(&instance_path)?;
+        cpuset_inherit(&isolated_path)?;
+        Some(isolated_path)
+    } else {
+        Some(instance_path.join(ISOLATED_CGROUP_NAME)).filter(|path| path.exists())
+    };
+
+    if clear_usage == ClearUsage::Yes {
+        for (file, value) in CPUSET_FILES.iter().zip(&[cpus, mems]) {
+            // Under cgroups v1 a cpuset must stay a subset of its parent, so widen the
+            // instance first and narrow it only after its isolated child
+            let parent_value = cgroup_read_to_string(controller_path, file)?;
+            cgroup_write(&instance_path, file, &parent_value)?;
+
+            let value = value.map_or(parent_value, |value| format!("{}\n", value));
+            if let Some(ref isolated_path) = isolated_path {
+                cgroup_write(isolated_path, file, &value)?;
+            }
+            cgroup_write(&instance_path, file, &value)?;
+        }
+    }
+
+    enter_cgroup(&instance_path, isolated_cgroup, version)
 }
 
 const MEMORY_DEFAULT_CONTROLLER_PATH: &str = "/sys/fs/cgroup/memory/ia-sandbox";
 const EXTRA_MEMORY_GIVEN: libc::rlim_t = 16 * 1_024;
+const MEMORY_RESET_FILES: [&str; 6] = [
+    "memory.max_usage_in_bytes",
+    "memory.memsw.max_usage_in_bytes",
+    "memory.kmem.max_usage_in_bytes",
+    "memory.failcnt",
+    "memory.memsw.failcnt",
+    "memory.kmem.failcnt",
+];
+// Expects the current limits to be at least `limit`, memsw.limit_in_bytes can never be below
+// limit_in_bytes. cgroups v2 limits swap separately, through memory.swap.max.
+fn write_memory_limit(
+    cgroup_path: &Path,
+    limit: libc::rlim_t,
+    version: CgroupVersion,
+) -> Result<()> {
+    if version == CgroupVersion::V2 {
+        return cgroup_write(cgroup_path, "memory.max", format!("{}\n", limit));
+    }
+    cgroup_write(cgroup_path, "memory.limit_in_bytes", format!("{}\n", limit))?;
+    cgroup_write(
+        cgroup_path,
+        "memory.memsw.limit_in_bytes",
+        format!("{}\n", limit),
+    )
+    .unwrap_or(());
+    Ok(())
+}
+
 pub(crate) fn enter_memory_cgroup(
     controller_path: Option<&Path>,
     instance_name: Option<&OsStr>,
     memory_limit: Option<SpaceUsage>,
     cache_limit: Option<SpaceUsage>,
+    disable_swap: DisableSwap,
     clear_usage: ClearUsage,
     isolated_cgroup: IsolatedCgroup,
 ) -> Result<()> {
-    let instance_path = get_instance_path(
-        controller_path.unwrap_or_else(|| Path::new(MEMORY_DEFAULT_CONTROLLER_PATH)),
-        instance_name,
-    )?;
+    let controller_path =
+        controller_path.unwrap_or_else(|| Path::new(MEMORY_DEFAULT_CONTROLLER_PATH));
+    let version = CgroupVersion::detect(controller_path);
+    let instance_path = get_instance_path("memory", controller_path, instance_name)?;
+
+    if version == CgroupVersion::V2 {
+        enable_controller(
+            "memory",
+            controller_path,
+            &process_cgroup_path(&instance_path, isolated_cgroup),
+        )?;
+        if clear_usage == ClearUsage::Yes {
+            // memory.peak can not be reset, memory.swap.max takes the place of swappiness
+            match memory_limit {
+                Some(memory_limit) => write_memory_limit(
+                    &instance_path,
+                    memory_limit
+                        .as_bytes()
+                        .saturating_add(cache_limit.map_or(0, SpaceUsage::as_bytes))
+                        .saturating_add(EXTRA_MEMORY_GIVEN),
+                    version,
+                )?,
+                None => cgroup_write(&instance_path, "memory.max", "max\n")?,
+            }
+            let swap_max = match (memory_limit, disable_swap) {
+                (Some(_), DisableSwap::Yes) => "0\n",
+                _ => "max\n",
+            };
+            cgroup_write(&instance_path, "memory.swap.max", swap_max).unwrap_or(());
+        }
+        return enter_cgroup(&instance_path, isolated_cgroup, version);
+    }
 
     if clear_usage == ClearUsage::Yes {
-        cgroup_write(&instance_path, "memory.max_usage_in_bytes", "0\n")?;
-        cgroup_write(&instance_path, "memory.memsw.max_usage_in_bytes", "0\n").unwrap_or(());
+        cgroup_reset(&instance_path, &MEMORY_RESET_FILES)?;
 
         // Reset limits to infinite in case there is no memory limit but also because we need at all
         // times for limit_in_bytes < memsw.limit_in_bytes
@@ -182,26 +614,29 @@ pub(crate) fn enter_memory_cgroup(
         cgroup_write(&instance_path, "memory.limit_in_bytes", "-1\n")?;
 
         if let Some(memory_limit) = memory_limit {
-            let actual_limit = memory_limit.as_bytes() +
-                cache_limit.unwrap_or(SpaceUsage::from_bytes(0)).as_bytes() +
-                EXTRA_MEMORY_GIVEN;
+            let actual_limit = memory_limit
+                .as_bytes()
+                .saturating_add(cache_limit.unwrap_or(SpaceUsage::from_bytes(0)).as_bytes())
+                .saturating_add(EXTRA_MEMORY_GIVEN);
             // Assign some extra memory so that we can tell when a killed by signal 9 is actually a
             // memory limit exceeded
-            cgroup_write(
-                &instance_path,
-                "memory.limit_in_bytes",
-                format!("{}\n", actual_limit),
-            )?;
-            cgroup_write(
-                &instance_path,
-                "memory.memsw.limit_in_bytes",
-                format!("{}\n", actual_limit),
-            )
-                .unwrap_or(());
+            write_memory_limit(&instance_path, actual_limit, version)?;
+        }
+
+        // Some kernels have no per cgroup swappiness
+        if instance_path.join("memory.swappiness").exists() {
+            let swappiness = match (memory_limit, disable_swap) {
+                (Some(_), DisableSwap::Yes) => "0\n".to_owned(),
+                _ => cgroup_read_to_string(
+                    instance_path.parent().unwrap_or(&instance_path),
+                    "memory.swappiness",
+                )?,
+            };
+            cgroup_write(&instance_path, "memory.swappiness", swappiness)?;
         }
     }
 
-    enter_cgroup(&instance_path, isolated_cgroup)
+    enter_cgroup(&instance_path, isolated_cgroup, version)
 }
 
 const PIDS_DEFAULT_CONTROLLER_PATH: &str = "/sys/fs/cgroup/pids/ia-sandbox";
@@ -212,11 +647,19 @@ pub(crate) fn enter_pids_cgroup(
     clear_usage: ClearUsage,
     isolated_cgroup: IsolatedCgroup,
 ) -> Result<()> {
-    let instance_path = get_instance_path(
-        controller_path.unwrap_or_else(|| Path::new(PIDS_DEFAULT_CONTROLLER_PATH)),
-        instance_name,
-    )?;
+    let controller_path =
+        controller_path.unwrap_or_else(|| Path::new(PIDS_DEFAULT_CONTROLLER_PATH));
+    let version = CgroupVersion::detect(controller_path);
+    let instance_path = get_instance_path("pids", controller_path, instance_name)?;
+    if version == CgroupVersion::V2 {
+        enable_controller(
+            "pids",
+            controller_path,
+            &process_cgroup_path(&instance_path, isolated_cgroup),
+        )?;
+    }
 
+    // pids.max is the same on both versions
     if clear_usage == ClearUsage::Yes {
         if let Some(pids_limit) = pids_limit {
             cgroup_write(&instance_path, "pids.max", format!("{}\n", pids_limit))?;
@@ -225,15 +668,294 @@ pub(crate) fn enter_pids_cgroup(
         }
     }
 
-    enter_cgroup(&instance_path, isolated_cgroup)
+    enter_cgroup(&instance_path, isolated_cgroup, version)
 }
 
+/// Kills the processes left in the pids cgroup of the instance, round after round until no
+/// new one shows up (they may still fork in the meantime). Processes stuck exiting are
+/// only signaled once.
+pub(crate) fn kill_instance_processes(
+    controller_path: &ControllerPath,
+    instance_name: Option<&OsStr>,
+) -> Result<()> {
+    let controller_path = controller_path
+        .pids()
+        .unwrap_or_else(|| Path::new(PIDS_DEFAULT_CONTROLLER_PATH));
+    let instance_path =
+        controller_path.join(instance_name.unwrap_or_else(|| OsStr::new(DEFAULT_INSTANCE_NAME)));
+    let cgroup_paths = [instance_path.join(ISOLATED_CGROUP_NAME), instance_path];
+
+    let mut killed = HashSet::new();
+    loop {
+        let mut any_new = false;
+        for cgroup_path in cgroup_paths
+            .iter()
+            .filter(|cgroup_path| cgroup_path.join("cgroup.procs").exists())
+        {
+            let procs = cgroup_read_to_string(cgroup_path, "cgroup.procs")?;
+            for pid in procs.lines() {
+                let pid: libc::pid_t = cgroup_parse(cgroup_path, "cgroup.procs", pid)?;
+                if killed.insert(pid) {
+                    ffi::send_signal(pid, libc::SIGKILL);
+                    any_new = true;
+                }
+            }
+        }
+        if !any_new {
+            return Ok(());
+        }
+    }
+}
+
+const IO_DEFAULT_CONTROLLER_PATH: &str = "/sys/fs/cgroup/blkio/ia-sandbox";
+pub(crate) fn enter_io_cgroup(
+    controller_path: Option<&Path>,
+    instance_name: Option<&OsStr>,
+    device: BlockDevice,
+    read_bps: Option<SpaceUsage>,
+    write_bps: Option<SpaceUsage>,
+    clear_usage: ClearUsage,
+    isolated_cgroup: IsolatedCgroup,
+) -> Result<()> {
+    let controller_path = controller_path.unwrap_or_else(|| Path::new(IO_DEFAULT_CONTROLLER_PATH));
+    let version = CgroupVersion::detect(controller_path);
+    let instance_path = get_instance_path("blkio", controller_path, instance_name)?;
+    if version == CgroupVersion::V2 {
+        enable_controller(
+            "io",
+            controller_path,
+            &process_cgroup_path(&instance_path, isolated_cgroup),
+        )?;
+    }
+
+    // Unset limits are written as well, to clear the ones of previous runs
+    if clear_usage == ClearUsage::Yes {
+        if version == CgroupVersion::V2 {
+            let bps = |bps: Option<SpaceUsage>| {
+                bps.map_or_else(|| "max".to_owned(), |bps| bps.as_bytes().to_string())
+            };
+            cgroup_write(
+                &instance_path,
+                "io.max",
+                format!(
+                    "{} rbps={} wbps={}\n",
+                    device,
+                    bps(read_bps),
+                    bps(write_bps)
+                ),
+            )?;
+        } else {
+            // cgroups v1 removes the limit of a device when it is set to 0
+            let bps = |bps: Option<SpaceUsage>| bps.map_or(0, |bps| bps.as_bytes());
+            cgroup_write(
+                &instance_path,
+                "blkio.throttle.read_bps_device",
+                format!("{} {}\n", device, bps(read_bps)),
+            )?;
+            cgroup_write(
+                &instance_path,
+                "blkio.throttle.write_bps_device",
+                format!("{} {}\n", device, bps(write_bps)),
+            )?;
+        }
+    }
+
+    enter_cgroup(&instance_path, isolated_cgroup, version)
+}
+
+/// Bytes read and written by the instance, summed over all devices (zero if it never had
+/// io limits)
+pub(crate) fn get_io_usage(
+    controller_path: &ControllerPath,
+    instance_name: Option<&OsStr>,
+) -> Result<(SpaceUsage, SpaceUsage)> {
+    let controller_path = controller_path
+        .io()
+        .unwrap_or_else(|| Path::new(IO_DEFAULT_CONTROLLER_PATH));
+    let instance_path =
+        controller_path.join(instance_name.unwrap_or_else(|| OsStr::new(DEFAULT_INSTANCE_NAME)));
+    let file = match CgroupVersion::detect(controller_path) {
+        CgroupVersion::V1 => "blkio.throttle.io_service_bytes",
+        CgroupVersion::V2 => "io.stat",
+    };
+    if !instance_path.join(file).exists() {
+        return Ok((SpaceUsage::from_bytes(0), SpaceUsage::from_bytes(0)));
+    }
+
+    let buffer = cgroup_read_to_string(&instance_path, file)?;
+    let mut read: libc::rlim_t = 0;
+    let mut written: libc::rlim_t = 0;
+    for line in buffer.lines() {
+        // cgroups v1 has a `MAJOR:MINOR Read 4096` line per counter, v2 all counters of a
+        // device on a single `MAJOR:MINOR rbytes=4096 wbytes=0 ...` line
+        let fields: Vec<&str> = line.split_whitespace().skip(1).collect();
+        let counters: Vec<(&str, &str)> = match *fields.as_slice() {
+            [key, value] if !key.contains('=') => vec![(key, value)],
+            _ => fields
+                .iter()
+                .filter_map(|field| {
+                    let mut parts = field.splitn(2, '=');
+                    Some((parts.next()?, parts.next()?))
+                })
+                .collect(),
+        };
+
+        for (key, value) in counters {
+            let total = match key {
+                "Read" | "rbytes" => &mut read,
+                "Write" | "wbytes" => &mut written,
+                _ => continue,
+            };
+            *total = total.saturating_add(cgroup_parse(&instance_path, file, value)?);
+        }
+    }
+    Ok((
+        SpaceUsage::from_bytes(read),
+        SpaceUsage::from_bytes(written),
+    ))
+}
+
+const FREEZER_DEFAULT_CONTROLLER_PATH: &str = "/sys/fs/cgroup/freezer/ia-sandbox";
+// How long `freeze_instance` waits for the processes to freeze (or thaw) before giving up
+const FREEZE_TIMEOUT: Duration = Duration::from_secs(1);
+pub(crate) fn enter_freezer_cgroup(
+    controller_path: Option<&Path>,
+    instance_name: Option<&OsStr>,
+    isolated_cgroup: IsolatedCgroup,
+) -> Result<()> {
+    let controller_path =
+        controller_path.unwrap_or_else(|| Path::new(FREEZER_DEFAULT_CONTROLLER_PATH));
+    let version = CgroupVersion::detect(controller_path);
+    let instance_path = get_instance_path("freezer", controller_path, instance_name)?;
+
+    // A run that ended while paused leaves its instance frozen
+    match version {
+        CgroupVersion::V1 => cgroup_write(&instance_path, "freezer.state", "THAWED\n")?,
+        CgroupVersion::V2 => cgroup_write(&instance_path, "cgroup.freeze", "0\n")?,
+    }
+
+    enter_cgroup(&instance_path, isolated_cgroup, version)
+}
+
+/// Freezes (or thaws) every process of the instance, and waits for them to get there. Frozen
+/// processes do not run and do not notice, unlike stopped ones. Fails past `FREEZE_TIMEOUT`,
+/// an instance that did not freeze in time is thawed again.
+pub(crate) fn freeze_instance(
+    controller_path: &ControllerPath,
+    instance_name: Option<&OsStr>,
+    frozen: bool,
+) -> Result<()> {
+    let controller_path = controller_path
+        .freezer()
+        .unwrap_or_else(|| Path::new(FREEZER_DEFAULT_CONTROLLER_PATH));
+    let instance_path =
+        controller_path.join(instance_name.unwrap_or_else(|| OsStr::new(DEFAULT_INSTANCE_NAME)));
+    let version = CgroupVersion::detect(controller_path);
+    let state = if frozen { "FROZEN" } else { "THAWED" };
+    freeze_instance_state(&instance_path, version, frozen)?;
+
+    let timer = Instant::now();
+    loop {
+        // cgroups v1 says FREEZING until every process is frozen
+        let done = match version {
+            CgroupVersion::V1 => {
+                cgroup_read_to_string(&instance_path, "freezer.state")?.trim() == state
+            }
+            CgroupVersion::V2 => {
+                cgroup_read_value::<_, u8>(&instance_path, "cgroup.events", "frozen")?
+                    == frozen as u8
+            }
+        };
+        if done {
+            return Ok(());
+        }
+        if timer.elapsed() >= FREEZE_TIMEOUT {
+            if frozen {
+                let _ = freeze_instance_state(&instance_path, version, false);
+            }
+            return Err(CGroupError::FreezeTimeout {
+                instance_path,
+                state: state.to_owned(),
+            });
+        }
+        thread::sleep(Duration::from_millis(1));
+    }
+}
+
+fn freeze_instance_state(instance_path: &Path, version: CgroupVersion, frozen: bool) -> Result<()> {
+    match version {
+        CgroupVersion::V1 => cgroup_write(
+            instance_path,
+            "freezer.state",
+            if frozen { "FROZEN\n" } else { "THAWED\n" },
+        ),
+        CgroupVersion::V2 => cgroup_write(
+            instance_path,
+            "cgroup.freeze",
+            format!("{}\n", frozen as u8),
+        ),
+    }
+}
+
+/// Creates the parents of nested instance cgroups with `parent_limits`, unless they exist
+/// already. Done before anything else creates the instance cgroups, which would create the
+/// parents without limits on the way.
+pub(crate) fn create_parent_cgroups(
+    controller_path: &ControllerPath,
+    instance_name: Option<&OsStr>,
+    parent_limits: &Limits,
+) -> Result<()> {
+    let memory_controller_path = controller_path
+        .memory()
+        .unwrap_or_else(|| Path::new(MEMORY_DEFAULT_CONTROLLER_PATH));
+    let memory_version = CgroupVersion::detect(memory_controller_path);
+    create_parent_cgroup(
+        "memory",
+        memory_controller_path,
+        instance_name,
+        |parent_path| {
+            if memory_version == CgroupVersion::V2 {
+                enable_controller("memory", memory_controller_path, parent_path)?;
+            }
+            match parent_limits.memory() {
+                Some(memory_limit) => write_memory_limit(
+                    parent_path,
+                    memory_limit
+                        .as_bytes()
+                        .saturating_add(parent_limits.cache().map_or(0, SpaceUsage::as_bytes)),
+                    memory_version,
+                ),
+                None => Ok(()),
+            }
+        },
+    )?;
+    let pids_controller_path = controller_path
+        .pids()
+        .unwrap_or_else(|| Path::new(PIDS_DEFAULT_CONTROLLER_PATH));
+    let pids_version = CgroupVersion::detect(pids_controller_path);
+    create_parent_cgroup("pids", pids_controller_path, instance_name, |parent_path| {
+        if pids_version == CgroupVersion::V2 {
+            enable_controller("pids", pids_controller_path, parent_path)?;
+        }
+        match parent_limits.pids() {
+            Some(pids_limit) => cgroup_write(parent_path, "pids.max", format!("{}\n", pids_limit)),
+            None => Ok(()),
+        }
+    })?;
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
 pub(crate) fn enter_all_cgroups(
     controller_path: &ControllerPath,
     instance_name: Option<&OsStr>,
-    limits: Limits,
+    limits: &Limits,
+    io_device: Option<BlockDevice>,
+    disable_swap: DisableSwap,
     clear_usage: ClearUsage,
     isolated_cgroup: IsolatedCgroup,
+    memory_accounting: MemoryAccounting,
+    pausable: Pausable,
 ) -> Result<()> {
     enter_cpuacct_cgroup(
         controller_path.cpuacct(),
@@ -241,11 +963,18 @@ pub(crate) fn enter_all_cgroups(
         clear_usage,
         isolated_cgroup,
     )?;
+    // Page cache does not count under RssOnly, leave it room so the kernel does not have to
+    // reclaim it on every allocation close to the limit
+    let cache_limit = match memory_accounting {
+        MemoryAccounting::Full => limits.cache(),
+        MemoryAccounting::RssOnly => limits.cache().or_else(|| limits.memory()),
+    };
     enter_memory_cgroup(
         controller_path.memory(),
         instance_name,
         limits.memory(),
-        limits.cache(),
+        cache_limit,
+        disable_swap,
         clear_usage,
         isolated_cgroup,
     )?;
@@ -255,13 +984,116 @@ pub(crate) fn enter_all_cgroups(
         limits.pids(),
         clear_usage,
         isolated_cgroup,
-    )
+    )?;
+    if let Some(cpu_quota) = limits.cpu_quota() {
+        enter_cpu_cgroup(
+            controller_path.cpu(),
+            instance_name,
+            cpu_quota,
+            clear_usage,
+            isolated_cgroup,
+        )?;
+    }
+    if limits.cpus().is_some() || limits.mems().is_some() {
+        enter_cpuset_cgroup(
+            controller_path.cpuset(),
+            instance_name,
+            limits.cpus(),
+            limits.mems(),
+            clear_usage,
+            isolated_cgroup,
+        )?;
+    }
+    if let Some(io_device) = io_device {
+        enter_io_cgroup(
+            controller_path.io(),
+            instance_name,
+            io_device,
+            limits.read_bps(),
+            limits.write_bps(),
+            clear_usage,
+            isolated_cgroup,
+        )?;
+    }
+    if pausable == Pausable::Yes {
+        enter_freezer_cgroup(controller_path.freezer(), instance_name, isolated_cgroup)?;
+    }
+    Ok(())
+}
+
+/// Read-only mounts of the instance cgroups that were entered, at the paths they usually have
+/// under `/sys/fs/cgroup`
+pub(crate) fn instance_mounts(
+    controller_path: &ControllerPath,
+    instance_name: Option<&OsStr>,
+    limits: &Limits,
+    io_device: Option<BlockDevice>,
+) -> Vec<Mount> {
+    let instance = instance_name.unwrap_or_else(|| OsStr::new(DEFAULT_INSTANCE_NAME));
+    let controllers = [
+        (
+            "cpuacct",
+            controller_path.cpuacct(),
+            CPUACCT_DEFAULT_CONTROLLER_PATH,
+            true,
+        ),
+        (
+            "memory",
+            controller_path.memory(),
+            MEMORY_DEFAULT_CONTROLLER_PATH,
+            true,
+        ),
+        (
+            "pids",
+            controller_path.pids(),
+            PIDS_DEFAULT_CONTROLLER_PATH,
+            true,
+        ),
+        (
+            "cpu",
+            controller_path.cpu(),
+            CPU_DEFAULT_CONTROLLER_PATH,
+            limits.cpu_quota().is_some(),
+        ),
+        (
+            "cpuset",
+            controller_path.cpuset(),
+            CPUSET_DEFAULT_CONTROLLER_PATH,
+            limits.cpus().is_some() || limits.mems().is_some(),
+        ),
+        (
+            "blkio",
+            controller_path.io(),
+            IO_DEFAULT_CONTROLLER_PATH,
+            io_device.is_some(),
+        ),
+    ];
+
+    controllers
+        .iter()
+        .filter(|&&(_, _, _, entered)| entered)
+        .map(|&(name, controller_path, default_controller_path, _)| {
+            Mount::new(
+                controller_path
+                    .unwrap_or_else(|| Path::new(default_controller_path))
+                    .join(instance),
+                Path::new("/sys/fs/cgroup").join(name),
+                MountOptions::default(),
+            )
+        })
+        .collect()
 }
 
+/// `user_time_offset` is subtracted from the cpu time, see `get_user_time_offset`.
+/// `rss_peak` keeps the highest RSS sampled so far under `MemoryAccounting::RssOnly`, the
+/// kernel does not track its peak.
 pub(crate) fn get_usage(
     controller_path: &ControllerPath,
     instance_name: Option<&OsStr>,
     wall_time: Duration,
+    user_time_offset: Duration,
+    memory_accounting: MemoryAccounting,
+    rss_peak: &Cell<libc::rlim_t>,
 ) -> Result<RunUsage> {
     let cpuacct_controller_path = controller_path
         .cpuacct()
@@ -270,17 +1102,437 @@ pub(crate) fn get_usage(
         .memory()
         .unwrap_or_else(|| Path::new(MEMORY_DEFAULT_CONTROLLER_PATH));
     let instance = instance_name.unwrap_or_else(|| OsStr::new(DEFAULT_INSTANCE_NAME));
+    check_controller("cpuacct", cpuacct_controller_path)?;
+    check_controller("memory", memory_controller_path)?;
 
     let cpuacct_instance_path = cpuacct_controller_path.join(instance);
-    let user_time = Duration::from_nanos(cgroup_read(&cpuacct_instance_path, "cpuacct.usage")?);
+    // cgroups v2 has no cpuacct controller, the cpu time is always in cpu.stat (without any per
+    // CPU accounting)
+    let (user_time, cpu_usage_percpu) = match CgroupVersion::detect(cpuacct_controller_path) {
+        CgroupVersion::V1 => {
+            let user_time =
+                Duration::from_nanos(cgroup_read(&cpuacct_instance_path, "cpuacct.usage")?);
+            // Reset along with cpuacct.usage
+            let cpu_usage_percpu = if cpuacct_instance_path.join("cpuacct.usage_percpu").exists() {
+                let buffer = cgroup_read_to_string(&cpuacct_instance_path, "cpuacct.usage_percpu")?;
+                Some(
+                    buffer
+                        .split_whitespace()
+                        .map(|usage| {
+                            cgroup_parse(&cpuacct_instance_path, "cpuacct.usage_percpu", usage)
+                                .map(Duration::from_nanos)
+                        })
+                        .collect::<Result<Vec<_>>>()?,
+                )
+            } else {
+                None
+            };
+            (user_time, cpu_usage_percpu)
+        }
+        CgroupVersion::V2 => (
+            cgroup_read_value(&cpuacct_instance_path, "cpu.stat", "usage_usec")
+                .map(Duration::from_micros)?,
+            None,
+        ),
+    };
+    let user_time = user_time.checked_sub(user_time_offset).unwrap_or_default();
 
     let memory_instance_path = memory_controller_path.join(instance);
-    let cache = cgroup_read_value(&memory_instance_path, "memory.stat", "cache");
+    let memory_version = CgroupVersion::detect(memory_controller_path);
+    let stat =
+        |key| cgroup_read_value::<_, libc::rlim_t>(&memory_instance_path, "memory.stat", key);
+    if memory_accounting == MemoryAccounting::RssOnly {
+        let rss = match memory_version {
+            // The hierarchical totals, the program runs in a child cgroup when isolated
+            CgroupVersion::V1 => stat("total_rss")? + stat("total_mapped_file")?,
+            CgroupVersion::V2 => stat("anon")?,
+        };
+        rss_peak.set(cmp::max(rss_peak.get(), rss));
+
+        let mut usage = RunUsage::new(
+            user_time,
+            wall_time,
+            SpaceUsage::from_bytes(rss_peak.get()),
+            SpaceUsage::from_bytes(rss),
+        );
+        usage.set_cpu_usage_percpu(cpu_usage_percpu);
+        return Ok(usage);
+    }
+
+    let (cache, memory, current_memory): (libc::rlim_t, libc::rlim_t, libc::rlim_t) =
+        match memory_version {
+            CgroupVersion::V1 => (
+                stat("cache")?,
+                cmp::max(
+                    cgroup_read(&memory_instance_path, "memory.max_usage_in_bytes")?,
+                    cgroup_read(&memory_instance_path, "memory.memsw.max_usage_in_bytes")
+                        .unwrap_or(0),
+                ),
+                cgroup_read(&memory_instance_path, "memory.usage_in_bytes")?,
+            ),
+            // The page cache is named `file` and memory.peak can not be reset
+            CgroupVersion::V2 => (
+                stat("file")?,
+                cgroup_read(&memory_instance_path, "memory.peak")?,
+                cgroup_read(&memory_instance_path, "memory.current")?,
+            ),
+        };
 
-    let memory = cmp::max(
-        cgroup_read(&memory_instance_path, "memory.max_usage_in_bytes")?,
-        cgroup_read(&memory_instance_path, "memory.memsw.max_usage_in_bytes").unwrap_or(0),
+    // The peak and the cache are sampled separately, cache can momentarily be larger
+    let mut usage = RunUsage::new(
+        user_time,
+        wall_time,
+        SpaceUsage::from_bytes(memory.saturating_sub(cache)),
+        SpaceUsage::from_bytes(current_memory.saturating_sub(cache)),
     );
-    let actual_memory = SpaceUsage::from_bytes(memory - cache);
-    Ok(RunUsage::new(user_time, wall_time, actual_memory))
+    usage.set_cpu_usage_percpu(cpu_usage_percpu);
+    Ok(usage)
+}
+
+/// Watches the memory cgroup of an instance for running out of memory, its own limit or the
+/// limit of a cgroup above it. The OOM killer of the whole machine does not count.
+///
+/// On cgroups v1 an eventfd is registered on `memory.oom_control` through
+/// `cgroup.event_control` (which is notified for the limits above as well), on v2 the `oom`
+/// counters of `memory.events` of the instance and the cgroups above it, up to the controller
+/// path, are compared with their values at registration (`oom_kill` counts the kills of the
+/// machine as well).
+pub(crate) enum OomNotifier {
+    EventControl {
+        instance_path: PathBuf,
+        eventfd: ffi::EventFd,
+    },
+    EventsCounters {
+        // Each cgroup with the counter at registration
+        initial: Vec<(PathBuf, u64)>,
+    },
+}
+
+impl OomNotifier {
+    pub(crate) fn new(
+        controller_path: &ControllerPath,
+        instance_name: Option<&OsStr>,
+    ) -> Result<Self> {
+        let controller_path = controller_path
+            .memory()
+            .unwrap_or_else(|| Path::new(MEMORY_DEFAULT_CONTROLLER_PATH));
+        let instance_path = get_instance_path("memory", controller_path, instance_name)?;
+
+        if CgroupVersion::detect(controller_path) == CgroupVersion::V2 {
+            // Before the child enters, memory.events is only there once the controller is on
+            enable_controller("memory", controller_path, &instance_path)?;
+            let mut initial = Vec::new();
+            for path in instance_path.ancestors() {
+                // The root cgroup has no limit, nor the file
+                if !path.join("memory.events").exists() {
+                    break;
+                }
+                initial.push((
+                    path.to_path_buf(),
+                    cgroup_read_value(path, "memory.events", "oom")?,
+                ));
+                if path == controller_path {
+                    break;
+                }
+            }
+            return Ok(Self::EventsCounters { initial });
+        }
+
+        let eventfd = ffi::EventFd::new().map_err(|err| CGroupError::OomNotifierError {
+            instance_path: instance_path.clone(),
+            error: err.to_string(),
+        })?;
+        let oom_control = File::open(instance_path.join("memory.oom_control")).map_err(|err| {
+            CGroupError::OpenCGroupFileError {
+                controller_path: instance_path.clone(),
+                file: PathBuf::from("memory.oom_control"),
+                error: err.to_string(),
+            }
+        })?;
+        cgroup_write(
+            &instance_path,
+            "cgroup.event_control",
+            format!("{} {}\n", eventfd.as_raw_fd(), oom_control.as_raw_fd()),
+        )?;
+        Ok(Self::EventControl {
+            instance_path,
+            eventfd,
+        })
+    }
+
+    /// Whether the instance ran out of memory since registration
+    pub(crate) fn triggered(&self) -> Result<bool> {
+        match *self {
+            Self::EventControl {
+                ref instance_path,
+                ref eventfd,
+            } => eventfd
+                .read_events()
+                .map(|events| events > 0)
+                .map_err(|err| CGroupError::OomNotifierError {
+                    instance_path: instance_path.clone(),
+                    error: err.to_string(),
+                }),
+            Self::EventsCounters { ref initial } => {
+                for &(ref path, initial) in initial {
+                    let oom: u64 = cgroup_read_value(path, "memory.events", "oom")?;
+                    if oom > initial {
+                        return Ok(true);
+                    }
+                }
+                Ok(false)
+            }
+        }
+    }
+}
+
+/// Wakes the supervisor up as soon as the memory cgroup of an instance runs out of memory,
+/// instead of on the next alarm tick.
+///
+/// On cgroups v1 an eventfd is registered for the `critical` level of `memory.pressure_level`
+/// through `cgroup.event_control`, on v2 `memory.events` is watched with inotify (the kernel
+/// notifies it whenever the `max` counter goes up).
+pub(crate) enum MemoryPressureNotifier {
+    EventControl(ffi::EventFd),
+    EventsFile(ffi::Inotify),
+}
+
+impl MemoryPressureNotifier {
+    pub(crate) fn new(
+        controller_path: &ControllerPath,
+        instance_name: Option<&OsStr>,
+    ) -> Result<Self> {
+        let controller_path = controller_path
+            .memory()
+            .unwrap_or_else(|| Path::new(MEMORY_DEFAULT_CONTROLLER_PATH));
+        let instance_path = get_instance_path("memory", controller_path, instance_name)?;
+        let notifier_error = |err: errors::FFIError| CGroupError::MemoryPressureNotifierError {
+            instance_path: instance_path.clone(),
+            error: err.to_string(),
+        };
+
+        if CgroupVersion::detect(controller_path) == CgroupVersion::V2 {
+            enable_controller("memory", controller_path, &instance_path)?;
+            let inotify = ffi::Inotify::new().map_err(&notifier_error)?;
+            inotify
+                .add_watch(&instance_path.join("memory.events"), libc::IN_MODIFY)
+                .map_err(&notifier_error)?;
+            return Ok(Self::EventsFile(inotify));
+        }
+
+        let eventfd = ffi::EventFd::new().map_err(&notifier_error)?;
+        let pressure_level =
+            File::open(instance_path.join("memory.pressure_level")).map_err(|err| {
+                CGroupError::OpenCGroupFileError {
+                    controller_path: instance_path.clone(),
+                    file: PathBuf::from("memory.pressure_level"),
+                    error: err.to_string(),
+                }
+            })?;
+        cgroup_write(
+            &instance_path,
+            "cgroup.event_control",
+            format!(
+                "{} {} critical\n",
+                eventfd.as_raw_fd(),
+                pressure_level.as_raw_fd()
+            ),
+        )?;
+        Ok(Self::EventControl(eventfd))
+    }
+}
+
+impl AsRawFd for MemoryPressureNotifier {
+    fn as_raw_fd(&self) -> RawFd {
+        match *self {
+            Self::EventControl(ref eventfd) => eventfd.as_raw_fd(),
+            Self::EventsFile(ref inotify) => inotify.as_raw_fd(),
+        }
+    }
+}
+
+/// Where a cgroup controller is mounted and which of its cgroups the caller is in
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct ControllerProbe {
+    controller: &'static str,
+    default_controller_path: &'static str,
+    mount_point: Option<PathBuf>,
+    mount_root: Option<PathBuf>,
+    cgroup: Option<PathBuf>,
+}
+
+impl ControllerProbe {
+    pub fn controller(&self) -> &str {
+        self.controller
+    }
+
+    pub fn mount_point(&self) -> Option<&Path> {
+        self.mount_point.as_ref().map(PathBuf::as_path)
+    }
+
+    /// The cgroup of the hierarchy mounted at the mount point, only `/` on the host. In a
+    /// container it is the cgroup of the container.
+    pub fn mount_root(&self) -> Option<&Path> {
+        self.mount_root.as_ref().map(PathBuf::as_path)
+    }
+
+    /// The cgroup the caller is in, as listed in `/proc/self/cgroup` (`/` in a cgroup
+    /// namespace)
+    pub fn cgroup(&self) -> Option<&Path> {
+        self.cgroup.as_ref().map(PathBuf::as_path)
+    }
+
+    /// The directory of the cgroup of the caller, which is where it may create cgroups.
+    /// `None` if the controller is not mounted or its cgroup is outside the mounted part of
+    /// the hierarchy.
+    pub fn subtree(&self) -> Option<PathBuf> {
+        let cgroup = self.cgroup.as_ref()?;
+        let relative = cgroup.strip_prefix(self.mount_root.as_ref()?).ok()?;
+        Some(self.mount_point.as_ref()?.join(relative))
+    }
+
+    /// The default path of the controller if it exists, otherwise the subtree of the caller
+    pub fn controller_path(&self) -> PathBuf {
+        let default_controller_path = Path::new(self.default_controller_path);
+        if default_controller_path.exists() {
+            return default_controller_path.to_path_buf();
+        }
+        self.subtree()
+            .unwrap_or_else(|| default_controller_path.to_path_buf())
+    }
+}
+
+impl Display for ControllerProbe {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match (self.mount_point(), self.mount_root()) {
+            (Some(mount_point), Some(mount_root)) => write!(
+                f,
+                "{}: mounted at {:?} (root {:?})",
+                self.controller, mount_point, mount_root
+            )?,
+            _ => write!(f, "{}: not mounted", self.controller)?,
+        }
+        if let Some(cgroup) = self.cgroup() {
+            write!(f, ", in cgroup {:?}", cgroup)?;
+        }
+        write!(f, ", using {:?}", self.controller_path())
+    }
+}
+
+/// What `probe` detected about the cgroup controllers the sandbox uses
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct CgroupProbe(Vec<ControllerProbe>);
+
+impl CgroupProbe {
+    /// Matches the cgroup mounts with the content of `/proc/self/cgroup`. cgroups v1
+    /// hierarchies are found by the controllers in their super options, anything else is
+    /// looked for on the cgroups v2 hierarchy.
+    pub fn new(mount_info: &MountInfo, proc_cgroup: &str) -> Self {
+        let cgroups: Vec<(&str, &str)> = proc_cgroup
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.splitn(3, ':').skip(1);
+                Some((fields.next()?, fields.next()?))
+            })
+            .collect();
+
+        let controllers = [
+            ("cpuacct", CPUACCT_DEFAULT_CONTROLLER_PATH),
+            ("memory", MEMORY_DEFAULT_CONTROLLER_PATH),
+            ("pids", PIDS_DEFAULT_CONTROLLER_PATH),
+            ("cpu", CPU_DEFAULT_CONTROLLER_PATH),
+            ("cpuset", CPUSET_DEFAULT_CONTROLLER_PATH),
+            ("blkio", IO_DEFAULT_CONTROLLER_PATH),
+            ("freezer", FREEZER_DEFAULT_CONTROLLER_PATH),
+        ];
+        Self(
+            controllers
+                .iter()
+                .map(|&(controller, default_controller_path)| {
+                    // Later mounts are stacked on top of earlier ones
+                    let v1_mount = mount_info.entries().iter().rev().find(|entry| {
+                        entry.filesystem_type() == "cgroup"
+                            && entry
+                                .super_options()
+                                .iter()
+                                .any(|option| option == controller)
+                    });
+                    let (mount, cgroup) = match v1_mount {
+                        Some(mount) => (
+                            Some(mount),
+                            cgroups.iter().find(|&&(controllers, _)| {
+                                controllers.split(',').any(|name| name == controller)
+                            }),
+                        ),
+                        None => (
+                            mount_info
+                                .entries()
+                                .iter()
+                                .rev()
+                                .find(|entry| entry.filesystem_type() == "cgroup2"),
+                            cgroups
+                                .iter()
+                                .find(|&&(controllers, _)| controllers.is_empty()),
+                        ),
+                    };
+
+                    ControllerProbe {
+                        controller,
+                        default_controller_path,
+                        mount_point: mount.map(|mount| mount.mount_point().to_path_buf()),
+                        mount_root: mount.map(|mount| mount.root().to_path_buf()),
+                        cgroup: cgroup.map(|&(_, cgroup)| PathBuf::from(cgroup)),
+                    }
+                })
+                .collect(),
+        )
+    }
+
+    pub fn controllers(&self) -> &[ControllerProbe] {
+        &self.0
+    }
+
+    pub fn controller(&self, controller: &str) -> Option<&ControllerProbe> {
+        self.0.iter().find(|probe| probe.controller == controller)
+    }
+
+    /// `controller_path` with the paths it leaves to their defaults filled in
+    pub fn fill_controller_path(&self, controller_path: &ControllerPath) -> ControllerPath {
+        let probed = |controller: &str, given: Option<&Path>| {
+            given.map(Path::to_path_buf).or_else(|| {
+                self.controller(controller)
+                    .map(ControllerProbe::controller_path)
+            })
+        };
+
+        let mut filled = ControllerPath::new(
+            probed("cpuacct", controller_path.cpuacct()),
+            probed("memory", controller_path.memory()),
+            probed("pids", controller_path.pids()),
+        );
+        filled.set_cpu(probed("cpu", controller_path.cpu()));
+        filled.set_cpuset(probed("cpuset", controller_path.cpuset()));
+        filled.set_io(probed("blkio", controller_path.io()));
+        filled.set_freezer(probed("freezer", controller_path.freezer()));
+        filled
+    }
+}
+
+impl Display for CgroupProbe {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for probe in &self.0 {
+            writeln!(f, "{}", probe)?;
+        }
+        Ok(())
+    }
+}
+
+/// Detects where the cgroup controllers are mounted and which part of them the caller may
+/// use. Inside a container the mounted hierarchies are usually rooted at the cgroup of the
+/// container, or the caller only has write access to its own cgroup of the full hierarchy.
+pub fn probe() -> errors::Result<CgroupProbe> {
+    let mount_info = MountInfo::read()?;
+    let proc_cgroup = fs::read_to_string("/proc/self/cgroup")
+        .map_err(|err| CGroupError::ReadProcCgroupError(err.to_string()))?;
+    Ok(CgroupProbe::new(&mount_info, &proc_cgroup))
 }
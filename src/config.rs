@@ -1,8 +1,21 @@
 use std::ffi::{OsStr, OsString};
-use std::fmt::{self, Display, Formatter};
-use std::path::{Path, PathBuf};
+use std::fmt::{self, Debug, Display, Formatter};
+use std::fs;
+use std::net::Ipv4Addr;
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::io::RawFd;
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
 use std::time::Duration;
 
+use errors::{ConfigError, ConfigErrors};
+use mount_info::MountInfo;
+use run_info::RunUsage;
+use seccomp::SeccompNotifyHandler;
+use syscalls;
+use utils;
+
 #[derive(Debug, Eq, PartialEq, Copy, Clone)]
 pub enum ShareNet {
     Share,
@@ -16,434 +29,3383 @@ impl Default for ShareNet {
 }
 
 #[derive(Debug, Eq, PartialEq, Copy, Clone)]
-pub enum SwapRedirects {
-    Yes,
-    No,
+pub enum ShareIpc {
+    Share,
+    Unshare,
 }
 
-impl Default for SwapRedirects {
+impl Default for ShareIpc {
     fn default() -> Self {
-        Self::No
+        Self::Unshare
     }
 }
 
 #[derive(Debug, Eq, PartialEq, Copy, Clone)]
-pub enum ClearUsage {
-    Yes,
-    No,
+pub enum SharePid {
+    Share,
+    Unshare,
 }
 
-impl Default for ClearUsage {
+impl Default for SharePid {
     fn default() -> Self {
-        Self::Yes
+        Self::Unshare
     }
 }
 
+/// How mount and unmount events propagate between the host and the mount namespace of the
+/// sandbox, see `mount_namespaces(7)`
 #[derive(Debug, Eq, PartialEq, Copy, Clone)]
-pub enum Interactive {
-    Yes,
-    No,
+pub enum Propagation {
+    /// Nothing propagates either way
+    Private,
+    /// Events on the host propagate into the sandbox but not the other way around
+    Slave,
+    /// Events propagate both ways
+    Shared,
 }
 
-impl Default for Interactive {
+impl Default for Propagation {
     fn default() -> Self {
-        Self::No
+        Self::Private
     }
 }
 
+/// The kinds of namespaces, named as in `/proc/<pid>/ns`
 #[derive(Debug, Eq, PartialEq, Copy, Clone)]
-pub enum CloneUser {
-    Yes,
-    No,
+pub enum NamespaceKind {
+    Cgroup,
+    Ipc,
+    Mount,
+    Net,
+    Pid,
+    User,
+    Uts,
 }
 
-impl Default for CloneUser {
-    fn default() -> Self {
-        CloneUser::Yes
+impl NamespaceKind {
+    // Why the sandbox can not join an existing namespace of this kind, if it can not
+    fn join_conflict(self) -> Option<&'static str> {
+        match self {
+            Self::Cgroup => {
+                Some("the sandbox unshares the cgroup namespace once inside its instance cgroups")
+            }
+            Self::Mount => Some("the sandbox sets up its mounts in a mount namespace of its own"),
+            Self::Pid => Some(
+                "joining a pid namespace only moves the children of a process into it, share \
+                 the one of the supervisor instead",
+            ),
+            Self::User => Some(
+                "the sandbox needs the capabilities of its own user namespace (or the host \
+                 one) for the mounts",
+            ),
+            Self::Ipc | Self::Net | Self::Uts => None,
+        }
+    }
+}
+
+impl Display for NamespaceKind {
+    fn fmt(&self, fmt: &mut Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Cgroup => "cgroup",
+            Self::Ipc => "ipc",
+            Self::Mount => "mnt",
+            Self::Net => "net",
+            Self::Pid => "pid",
+            Self::User => "user",
+            Self::Uts => "uts",
+        };
+        write!(fmt, "{}", name)
     }
 }
 
+/// The capabilities of `capabilities(7)`, numbered as in `linux/capability.h`. The sandbox drops
+/// all of them (and any newer ones the kernel has) before the exec, but the ones kept through
+/// `Config::set_keep_capabilities`
 #[derive(Debug, Eq, PartialEq, Copy, Clone)]
-pub enum IsolatedCgroup {
-    Yes,
-    No,
+pub enum Capability {
+    Chown = 0,
+    DacOverride = 1,
+    DacReadSearch = 2,
+    Fowner = 3,
+    Fsetid = 4,
+    Kill = 5,
+    Setgid = 6,
+    Setuid = 7,
+    Setpcap = 8,
+    LinuxImmutable = 9,
+    NetBindService = 10,
+    NetBroadcast = 11,
+    NetAdmin = 12,
+    NetRaw = 13,
+    IpcLock = 14,
+    IpcOwner = 15,
+    SysModule = 16,
+    SysRawio = 17,
+    SysChroot = 18,
+    SysPtrace = 19,
+    SysPacct = 20,
+    SysAdmin = 21,
+    SysBoot = 22,
+    SysNice = 23,
+    SysResource = 24,
+    SysTime = 25,
+    SysTtyConfig = 26,
+    Mknod = 27,
+    Lease = 28,
+    AuditWrite = 29,
+    AuditControl = 30,
+    Setfcap = 31,
+    MacOverride = 32,
+    MacAdmin = 33,
+    Syslog = 34,
+    WakeAlarm = 35,
+    BlockSuspend = 36,
+    AuditRead = 37,
+    Perfmon = 38,
+    Bpf = 39,
+    CheckpointRestore = 40,
 }
 
-impl Default for IsolatedCgroup {
-    fn default() -> Self {
-        Self::No
+impl Capability {
+    /// Every capability, in the order of their numbers
+    pub const ALL: [Self; 41] = [
+        Self::Chown,
+        Self::DacOverride,
+        Self::DacReadSearch,
+        Self::Fowner,
+        Self::Fsetid,
+        Self::Kill,
+        Self::Setgid,
+        Self::Setuid,
+        Self::Setpcap,
+        Self::LinuxImmutable,
+        Self::NetBindService,
+        Self::NetBroadcast,
+        Self::NetAdmin,
+        Self::NetRaw,
+        Self::IpcLock,
+        Self::IpcOwner,
+        Self::SysModule,
+        Self::SysRawio,
+        Self::SysChroot,
+        Self::SysPtrace,
+        Self::SysPacct,
+        Self::SysAdmin,
+        Self::SysBoot,
+        Self::SysNice,
+        Self::SysResource,
+        Self::SysTime,
+        Self::SysTtyConfig,
+        Self::Mknod,
+        Self::Lease,
+        Self::AuditWrite,
+        Self::AuditControl,
+        Self::Setfcap,
+        Self::MacOverride,
+        Self::MacAdmin,
+        Self::Syslog,
+        Self::WakeAlarm,
+        Self::BlockSuspend,
+        Self::AuditRead,
+        Self::Perfmon,
+        Self::Bpf,
+        Self::CheckpointRestore,
+    ];
+
+    pub fn number(self) -> u32 {
+        self as u32
     }
 }
 
-#[derive(Debug, Eq, PartialEq, Copy, Clone, PartialOrd, Ord, Serialize, Deserialize)]
-pub struct SpaceUsage(libc::rlim_t);
-
-impl SpaceUsage {
-    pub fn from_bytes(bytes: libc::rlim_t) -> Self {
-        Self(bytes)
+impl Display for Capability {
+    fn fmt(&self, fmt: &mut Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Chown => "CHOWN",
+            Self::DacOverride => "DAC_OVERRIDE",
+            Self::DacReadSearch => "DAC_READ_SEARCH",
+            Self::Fowner => "FOWNER",
+            Self::Fsetid => "FSETID",
+            Self::Kill => "KILL",
+            Self::Setgid => "SETGID",
+            Self::Setuid => "SETUID",
+            Self::Setpcap => "SETPCAP",
+            Self::LinuxImmutable => "LINUX_IMMUTABLE",
+            Self::NetBindService => "NET_BIND_SERVICE",
+            Self::NetBroadcast => "NET_BROADCAST",
+            Self::NetAdmin => "NET_ADMIN",
+            Self::NetRaw => "NET_RAW",
+            Self::IpcLock => "IPC_LOCK",
+            Self::IpcOwner => "IPC_OWNER",
+            Self::SysModule => "SYS_MODULE",
+            Self::SysRawio => "SYS_RAWIO",
+            Self::SysChroot => "SYS_CHROOT",
+            Self::SysPtrace => "SYS_PTRACE",
+            Self::SysPacct => "SYS_PACCT",
+            Self::SysAdmin => "SYS_ADMIN",
+            Self::SysBoot => "SYS_BOOT",
+            Self::SysNice => "SYS_NICE",
+            Self::SysResource => "SYS_RESOURCE",
+            Self::SysTime => "SYS_TIME",
+            Self::SysTtyConfig => "SYS_TTY_CONFIG",
+            Self::Mknod => "MKNOD",
+            Self::Lease => "LEASE",
+            Self::AuditWrite => "AUDIT_WRITE",
+            Self::AuditControl => "AUDIT_CONTROL",
+            Self::Setfcap => "SETFCAP",
+            Self::MacOverride => "MAC_OVERRIDE",
+            Self::MacAdmin => "MAC_ADMIN",
+            Self::Syslog => "SYSLOG",
+            Self::WakeAlarm => "WAKE_ALARM",
+            Self::BlockSuspend => "BLOCK_SUSPEND",
+            Self::AuditRead => "AUDIT_READ",
+            Self::Perfmon => "PERFMON",
+            Self::Bpf => "BPF",
+            Self::CheckpointRestore => "CHECKPOINT_RESTORE",
+        };
+        write!(fmt, "CAP_{}", name)
     }
+}
 
-    pub fn from_kilobytes(kilobytes: libc::rlim_t) -> Self {
-        Self::from_bytes(kilobytes * 1_000)
-    }
+/// Where a standard stream of the command goes
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum Redirect {
+    /// The stream of the caller, as it is
+    Inherit,
+    /// `/dev/null` of the host. Without one, a pipe with the other end closed: reading gets
+    /// end of file right away and writing fails with `EPIPE` (and `SIGPIPE`).
+    Null,
+    /// A file of the host, the options say how outputs are opened
+    File(PathBuf, RedirectOptions),
+    /// Kept in memory and returned in the `RunInfo`, up to `limit` bytes (the rest is read and
+    /// dropped). Only for stdout and stderr.
+    Capture { limit: SpaceUsage },
+    /// Wherever stdout goes, once it is redirected (like `2>&1`). Only for stderr.
+    ToStdout,
+    /// A pipe to the caller, which gets its end from the `JailHandle`
+    Pipe,
+    /// These bytes, then end of file. They are put in a sealed memfd before the command
+    /// starts, so nothing has to write them while it runs. Only for stdin.
+    Bytes(Vec<u8>),
+    /// Written by the supervisor to `file` of the host (truncated first), up to `limit` bytes
+    /// when there is one, and also passed on to `Config::on_output` as it comes. Only for
+    /// stdout and stderr.
+    Tee {
+        file: PathBuf,
+        limit: Option<SpaceUsage>,
+    },
+}
 
-    pub fn from_megabytes(megabytes: libc::rlim_t) -> Self {
-        Self::from_kilobytes(megabytes * 1_000)
+impl Redirect {
+    pub fn file(&self) -> Option<&Path> {
+        match *self {
+            Self::File(ref path, _) | Self::Tee { file: ref path, .. } => Some(path),
+            Self::Inherit
+            | Self::Null
+            | Self::Capture { .. }
+            | Self::ToStdout
+            | Self::Pipe
+            | Self::Bytes(_) => None,
+        }
     }
 
-    pub fn from_gigabytes(gigabytes: libc::rlim_t) -> Self {
-        Self::from_megabytes(gigabytes * 1_000)
+    pub fn capture_limit(&self) -> Option<SpaceUsage> {
+        match *self {
+            Self::Capture { limit } => Some(limit),
+            Self::Inherit
+            | Self::Null
+            | Self::File(..)
+            | Self::ToStdout
+            | Self::Pipe
+            | Self::Bytes(_)
+            | Self::Tee { .. } => None,
+        }
     }
 
-    pub fn from_kibibytes(kibibytes: libc::rlim_t) -> Self {
-        Self::from_bytes(kibibytes * 1_024)
+    /// Whether this is a file opened inside the sandbox, see `RedirectOptions::in_sandbox`
+    pub fn in_sandbox(&self) -> bool {
+        match *self {
+            Self::File(_, options) => options.in_sandbox(),
+            Self::Inherit
+            | Self::Null
+            | Self::Capture { .. }
+            | Self::ToStdout
+            | Self::Pipe
+            | Self::Bytes(_)
+            | Self::Tee { .. } => false,
+        }
     }
+}
 
-    pub fn from_mebibytes(mebibytes: libc::rlim_t) -> Self {
-        Self::from_kibibytes(mebibytes * 1_024)
+impl Default for Redirect {
+    fn default() -> Self {
+        Self::Inherit
     }
+}
 
-    pub fn from_gibibytes(gibibytes: libc::rlim_t) -> Self {
-        Self::from_mebibytes(gibibytes * 1_024)
+impl From<PathBuf> for Redirect {
+    fn from(path: PathBuf) -> Self {
+        Self::File(path, RedirectOptions::default())
     }
+}
 
-    pub fn as_bytes(self) -> libc::rlim_t {
-        self.0
+impl From<Option<PathBuf>> for Redirect {
+    fn from(path: Option<PathBuf>) -> Self {
+        path.map_or(Self::Inherit, Self::from)
     }
+}
 
-    pub fn as_kilobytes(self) -> libc::rlim_t {
-        self.0 / 1_000
-    }
+/// How a file is opened for an output, stdin ignores it
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum OutputMode {
+    /// Made if missing, emptied otherwise
+    Truncate,
+    /// Made if missing, written at the end otherwise (several runs can share one log)
+    Append,
+    /// Made, failing if anything is at the path already, a symlink included, so nobody can
+    /// point the output somewhere else beforehand
+    CreateNew,
 }
 
-impl Display for SpaceUsage {
-    fn fmt(&self, fmt: &mut Formatter<'_>) -> fmt::Result {
-        if self.0 % (1 << 30) == 0 {
-            write!(fmt, "{} gibibytes", self.0 >> 30)
-        } else if self.0 % (1 << 20) == 0 {
-            write!(fmt, "{} mebibytes", self.0 >> 20)
-        } else if self.0 % (1 << 10) == 0 {
-            write!(fmt, "{} kibibytes", self.0 >> 10)
-        } else if self.0 % 1_000_000_000 == 0 {
-            write!(fmt, "{} gigabytes", self.0 / 1_000_000_000)
-        } else if self.0 % 1_000_000 == 0 {
-            write!(fmt, "{} megabytes", self.0 / 1_000_000)
-        } else if self.0 % 1_000 == 0 {
-            write!(fmt, "{} kilobytes", self.0 / 1_000)
-        } else {
-            write!(fmt, "{} bytes", self.0)
-        }
+impl Default for OutputMode {
+    fn default() -> Self {
+        Self::Truncate
     }
 }
 
-/// Limits for memory/time
-#[derive(Debug, Eq, PartialEq, Copy, Clone)]
-pub struct Limits {
-    wall_time: Option<Duration>,
-    user_time: Option<Duration>,
-    memory: Option<SpaceUsage>,
-    stack: Option<SpaceUsage>,
-    cache: Option<SpaceUsage>,
-    pids: Option<usize>,
+#[derive(Debug, Eq, PartialEq, Copy, Clone, Default)]
+pub struct RedirectOptions {
+    mode: OutputMode,
+    permissions: Option<u32>,
+    in_sandbox: bool,
 }
 
-impl Limits {
-    pub fn new(
-        wall_time: Option<Duration>,
-        user_time: Option<Duration>,
-        memory: Option<SpaceUsage>,
-        stack: Option<SpaceUsage>,
-        cache: Option<SpaceUsage>,
-        pids: Option<usize>,
-    ) -> Self {
+impl RedirectOptions {
+    pub fn new(mode: OutputMode, permissions: Option<u32>) -> Self {
         Self {
-            wall_time,
-            user_time,
-            memory,
-            stack,
-            cache,
-            pids,
+            mode,
+            permissions,
+            in_sandbox: false,
         }
     }
 
-    pub fn wall_time(&self) -> Option<Duration> {
-        self.wall_time
+    pub fn mode(self) -> OutputMode {
+        self.mode
     }
 
-    pub fn user_time(&self) -> Option<Duration> {
-        self.user_time
+    pub fn set_mode(&mut self, mode: OutputMode) {
+        self.mode = mode;
     }
 
-    pub fn memory(&self) -> Option<SpaceUsage> {
-        self.memory
+    /// The permissions of a file the output makes (less the umask), 0o666 if `None`
+    pub fn permissions(self) -> Option<u32> {
+        self.permissions
     }
 
-    pub fn stack(&self) -> Option<SpaceUsage> {
-        self.stack
+    pub fn set_permissions(&mut self, permissions: Option<u32>) {
+        self.permissions = permissions;
     }
 
-    pub fn cache(&self) -> Option<SpaceUsage> {
-        self.cache
+    /// Whether the path is resolved inside the sandbox, once its root and mounts are in place
+    /// (relative ones from the new root), instead of on the host. For stdin as well. Such a file
+    /// is opened after the ones of the host, in the same order among themselves, so it can
+    /// not be a FIFO the caller made on the host unless it is mounted in. Its directory is
+    /// not checked by `Config::validate`. `false` by default.
+    pub fn in_sandbox(self) -> bool {
+        self.in_sandbox
     }
 
-    pub fn pids(&self) -> Option<usize> {
-        self.pids
+    pub fn set_in_sandbox(&mut self, in_sandbox: bool) {
+        self.in_sandbox = in_sandbox;
     }
 }
 
-impl Default for Limits {
-    fn default() -> Self {
-        Self::new(None, None, None, None, None, None)
+// What the callbacks below wrap, shared with the threads of the supervisor calling them
+type OutputFn = dyn Fn(&[u8]) + Send + Sync;
+type UsageFn = dyn Fn(&RunUsage) + Send + Sync;
+
+/// Gets what the command writes to the streams redirected to `Redirect::Tee`, in the chunks the
+/// supervisor reads, on a thread of its own for each stream. The files are written first and
+/// do not wait for it: once a slow callback is 16 chunks behind, the next ones are dropped
+/// and counted in `RunUsage::dropped_output_bytes`. Like the `SeccompNotifyHandler` it runs in
+/// the supervisor, a forked copy of the caller.
+#[derive(Clone)]
+pub struct OutputCallback(Arc<OutputFn>);
+
+impl OutputCallback {
+    pub fn new<F>(callback: F) -> Self
+    where
+        F: Fn(&[u8]) + Send + Sync + 'static,
+    {
+        Self(Arc::new(callback))
     }
-}
 
-#[derive(Debug, Eq, PartialEq, Clone)]
-pub struct ControllerPath {
-    cpuacct: Option<PathBuf>,
-    memory: Option<PathBuf>,
-    pids: Option<PathBuf>,
+    pub(crate) fn call(&self, data: &[u8]) {
+        (self.0)(data)
+    }
 }
 
-impl ControllerPath {
-    pub fn new(cpuacct: Option<PathBuf>, memory: Option<PathBuf>, pids: Option<PathBuf>) -> Self {
-        Self {
-            cpuacct,
-            memory,
-            pids,
-        }
+impl Debug for OutputCallback {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "OutputCallback")
     }
+}
 
-    pub fn cpuacct(&self) -> Option<&Path> {
-        self.cpuacct.as_ref().map(PathBuf::as_path)
+// Only the very same callback compares equal
+impl PartialEq for OutputCallback {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
     }
+}
 
-    pub fn memory(&self) -> Option<&Path> {
-        self.memory.as_ref().map(PathBuf::as_path)
+impl Eq for OutputCallback {}
+
+/// Gets the usage of the run on every sample the supervisor takes while it waits for the
+/// command, at most once per tick of its alarm (5ms). It is called after the limits are
+/// checked, so a slow callback only delays the next sample, never a kill. Like the
+/// `OutputCallback` it runs in the supervisor, a forked copy of the caller.
+#[derive(Clone)]
+pub struct UsageCallback(Arc<UsageFn>);
+
+impl UsageCallback {
+    pub fn new<F>(callback: F) -> Self
+    where
+        F: Fn(&RunUsage) + Send + Sync + 'static,
+    {
+        Self(Arc::new(callback))
     }
 
-    pub fn pids(&self) -> Option<&Path> {
-        self.pids.as_ref().map(PathBuf::as_path)
+    pub(crate) fn call(&self, usage: &RunUsage) {
+        (self.0)(usage)
     }
 }
 
-impl Default for ControllerPath {
-    fn default() -> Self {
-        Self::new(None, None, None)
+impl Debug for UsageCallback {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "UsageCallback")
     }
 }
 
-#[derive(Debug, Eq, PartialEq, Clone, Copy)]
-pub struct MountOptions {
-    read_only: bool,
-    dev: bool,
-    exec: bool,
+// Only the very same callback compares equal
+impl PartialEq for UsageCallback {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
 }
 
-impl MountOptions {
-    pub fn read_only(self) -> bool {
-        self.read_only
-    }
+impl Eq for UsageCallback {}
 
-    pub fn dev(self) -> bool {
-        self.dev
-    }
+/// Where an extra file descriptor of the command comes from, see `Config::extra_fds`
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum FdSource {
+    /// A file of the host, opened with the given `open` flags (and 0o666 permissions if they
+    /// make it)
+    Path { path: PathBuf, flags: i32 },
+    /// A file descriptor of the caller, which stays open on its side
+    Fd(RawFd),
+}
 
-    pub fn exec(self) -> bool {
-        self.exec
-    }
+/// Whether the command starts without the file descriptors the caller had open (besides the
+/// standard streams and `Config::extra_fds`), they are marked close-on-exec in the jail
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum CloseFds {
+    Yes,
+    No,
+}
 
-    pub fn set_read_only(&mut self, value: bool) {
-        self.read_only = value;
+impl Default for CloseFds {
+    fn default() -> Self {
+        Self::Yes
     }
+}
 
-    pub fn set_dev(&mut self, value: bool) {
-        self.dev = value;
-    }
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum SwapRedirects {
+    Yes,
+    No,
+}
 
-    pub fn set_exec(&mut self, value: bool) {
-        self.exec = value;
+impl Default for SwapRedirects {
+    fn default() -> Self {
+        Self::No
     }
 }
 
-impl Default for MountOptions {
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum ClearUsage {
+    Yes,
+    No,
+}
+
+impl Default for ClearUsage {
     fn default() -> Self {
-        Self {
-            read_only: true,
-            dev: false,
-            exec: false,
-        }
+        Self::Yes
     }
 }
 
-#[derive(Debug, Eq, PartialEq, Clone)]
-pub struct Mount {
-    source: PathBuf,
-    destination: PathBuf,
-    mount_options: MountOptions,
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum Interactive {
+    Yes,
+    No,
 }
 
-impl Mount {
-    pub fn new(source: PathBuf, destination: PathBuf, mount_options: MountOptions) -> Self {
-        Self {
-            source,
-            destination,
-            mount_options,
-        }
+impl Default for Interactive {
+    fn default() -> Self {
+        Self::No
     }
+}
 
-    pub fn source(&self) -> &Path {
-        &self.source
-    }
+/// Whether to give the command a pseudo terminal of its own as standard input, output and
+/// error (and controlling terminal), whose master end the caller talks to it through, see
+/// `JailHandle::pty_master`
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum AllocatePty {
+    Yes,
+    No,
+}
 
-    pub fn destination(&self) -> &Path {
-        &self.destination
+impl Default for AllocatePty {
+    fn default() -> Self {
+        Self::No
     }
+}
 
-    pub fn mount_options(&self) -> MountOptions {
-        self.mount_options
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum CloneUser {
+    Yes,
+    No,
+}
+
+impl Default for CloneUser {
+    fn default() -> Self {
+        CloneUser::Yes
     }
 }
 
-#[derive(Debug, Eq, PartialEq, Clone)]
-pub enum Environment {
-    Forward,
-    EnvList(Vec<(String, String)>),
+/// How `CloneUser::Yes` keeps the command from the privileges of the caller
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum IsolationMode {
+    /// A user namespace if they can be used, otherwise the root mode when running as root
+    Auto,
+    /// A new user namespace, the caller is root inside it
+    UserNamespace,
+    /// No user namespace, for kernels with them disabled. Needs running as root, the command
+    /// runs as the sandbox user (nobody, 65534:65534, unless set) switched to after the mounts
+    Root,
 }
 
-impl Default for Environment {
+impl Default for IsolationMode {
     fn default() -> Self {
-        Self::EnvList(Vec::new())
+        Self::Auto
     }
 }
 
-#[derive(Debug, Eq, PartialEq)]
-pub struct Config {
-    command: PathBuf,
-    args: Vec<OsString>,
-    new_root: Option<PathBuf>,
-    share_net: ShareNet,
-    redirect_stdin: Option<PathBuf>,
-    redirect_stdout: Option<PathBuf>,
-    redirect_stderr: Option<PathBuf>,
-    limits: Limits,
-    instance_name: Option<OsString>,
-    controller_path: ControllerPath,
-    mounts: Vec<Mount>,
-    swap_redirects: SwapRedirects,
-    clear_usage: ClearUsage,
-    interactive: Interactive,
-    clone_user: CloneUser,
-    isolated_cgroup: IsolatedCgroup,
-    environment: Environment,
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum IsolatedCgroup {
+    Yes,
+    No,
 }
 
-impl Config {
-    #![allow(clippy::too_many_arguments)]
-    pub fn new(
-        command: PathBuf,
-        args: Vec<OsString>,
-        new_root: Option<PathBuf>,
-        share_net: ShareNet,
-        redirect_stdin: Option<PathBuf>,
-        redirect_stdout: Option<PathBuf>,
-        redirect_stderr: Option<PathBuf>,
-        limits: Limits,
-        instance_name: Option<OsString>,
-        controller_path: ControllerPath,
-        mounts: Vec<Mount>,
-        swap_redirects: SwapRedirects,
-        clear_usage: ClearUsage,
-        interactive: Interactive,
-        clone_user: CloneUser,
-        isolated_cgroup: IsolatedCgroup,
-        environment: Environment,
-    ) -> Self {
+impl Default for IsolatedCgroup {
+    fn default() -> Self {
+        Self::No
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum MountBinfmtInterpreter {
+    Yes,
+    No,
+}
+
+impl Default for MountBinfmtInterpreter {
+    fn default() -> Self {
+        Self::No
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum MountCgroups {
+    Yes,
+    No,
+}
+
+impl Default for MountCgroups {
+    fn default() -> Self {
+        Self::No
+    }
+}
+
+/// Whether to mount a `/dev` of its own in the new root: a tmpfs with the null, zero, full,
+/// random, urandom and tty devices of the host bind mounted in it, and the fd, stdin, stdout
+/// and stderr links to `/proc/self/fd`
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum MinimalDev {
+    Yes,
+    No,
+}
+
+impl Default for MinimalDev {
+    fn default() -> Self {
+        Self::Yes
+    }
+}
+
+/// Whether to mount a tmpfs of its own at `/tmp`, `/var/tmp` and `/dev/shm` in the new root,
+/// so runs can neither see the temporary files of each other nor fill the ones of the host
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum PrivateTmp {
+    Yes,
+    No,
+}
+
+impl Default for PrivateTmp {
+    fn default() -> Self {
+        Self::Yes
+    }
+}
+
+/// Whether to make the new root itself read-only, the mounts in it keep their own options
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum ReadOnlyRoot {
+    Yes,
+    No,
+}
+
+impl Default for ReadOnlyRoot {
+    fn default() -> Self {
+        Self::No
+    }
+}
+
+/// Which processes of its pid namespace the command can see in `/proc`, see the `hidepid`
+/// option in `proc(5)`
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum HidePid {
+    /// Every process can be seen
+    Off,
+    /// The directories of processes of other users are there, but can not be read
+    NoAccess,
+    /// Processes of other users are not there at all
+    Invisible,
+}
+
+impl Default for HidePid {
+    fn default() -> Self {
+        Self::Invisible
+    }
+}
+
+/// How the `/proc` of a sandbox in a new pid namespace is mounted. Sharing the pid namespace
+/// of the caller (`SharePid::Share`) bind mounts its `/proc` as it is instead.
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub struct ProcOptions {
+    hide_pid: HidePid,
+    subset_pid: bool,
+    mask_paths: bool,
+}
+
+impl ProcOptions {
+    pub fn new(hide_pid: HidePid, subset_pid: bool, mask_paths: bool) -> Self {
         Self {
-            command,
-            args,
-            new_root,
-            share_net,
-            redirect_stdin,
-            redirect_stdout,
-            redirect_stderr,
-            limits,
-            instance_name,
-            controller_path,
-            mounts,
-            swap_redirects,
-            clear_usage,
-            interactive,
-            clone_user,
-            isolated_cgroup,
-            environment,
+            hide_pid,
+            subset_pid,
+            mask_paths,
         }
     }
 
-    pub fn command(&self) -> &Path {
-        &self.command
+    pub fn hide_pid(self) -> HidePid {
+        self.hide_pid
     }
 
-    pub fn args<'a>(&'a self) -> Vec<&'a OsStr> {
-        self.args.iter().map(OsString::as_os_str).collect()
+    /// Whether to leave only the process directories (with `self` and `thread-self`) in
+    /// `/proc`, hiding `/proc/sys` and the other host wide files. It needs Linux 5.8 or later,
+    /// before that the whole `/proc` is mounted.
+    pub fn subset_pid(self) -> bool {
+        self.subset_pid
     }
 
-    pub fn new_root(&self) -> Option<&Path> {
-        self.new_root.as_ref().map(PathBuf::as_path)
+    /// Whether to hide the contents of the files of `/proc` revealing the host (`kcore`,
+    /// `keys`, `timer_list`, ...) under `/dev/null`, when they are there
+    pub fn mask_paths(self) -> bool {
+        self.mask_paths
     }
 
-    pub fn share_net(&self) -> ShareNet {
-        self.share_net
+    pub fn set_hide_pid(&mut self, value: HidePid) {
+        self.hide_pid = value;
+    }
+
+    pub fn set_subset_pid(&mut self, value: bool) {
+        self.subset_pid = value;
+    }
+
+    pub fn set_mask_paths(&mut self, value: bool) {
+        self.mask_paths = value;
     }
+}
 
-    pub fn redirect_stdin(&self) -> Option<&Path> {
-        self.redirect_stdin.as_ref().map(PathBuf::as_path)
+impl Default for ProcOptions {
+    fn default() -> Self {
+        Self::new(HidePid::default(), true, true)
     }
+}
+
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum DisableSwap {
+    Yes,
+    No,
+}
 
-    pub fn redirect_stdout(&self) -> Option<&Path> {
-        self.redirect_stdout.as_ref().map(PathBuf::as_path)
+impl Default for DisableSwap {
+    fn default() -> Self {
+        Self::Yes
     }
+}
+
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum AllowSameFileIo {
+    Yes,
+    No,
+}
 
-    pub fn redirect_stderr(&self) -> Option<&Path> {
-        self.redirect_stderr.as_ref().map(PathBuf::as_path)
+impl Default for AllowSameFileIo {
+    fn default() -> Self {
+        Self::No
     }
+}
+
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum ExcludeStoppedTime {
+    Yes,
+    No,
+}
 
-    pub fn limits(&self) -> Limits {
-        self.limits
+impl Default for ExcludeStoppedTime {
+    fn default() -> Self {
+        Self::No
     }
+}
 
-    pub fn instance_name(&self) -> Option<&OsStr> {
-        self.instance_name.as_ref().map(OsString::as_os_str)
+/// Whether the run can be paused with `JailHandle::pause`, which freezes the instance cgroup:
+/// on cgroups v1 that takes entering the freezer controller as well
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum Pausable {
+    Yes,
+    No,
+}
+
+impl Default for Pausable {
+    fn default() -> Self {
+        Self::No
     }
+}
 
-    pub fn controller_path(&self) -> &ControllerPath {
-        &self.controller_path
+/// Whether `INSIDE_SANDBOX_VAR` is added to the environment of the command, so a sandbox it
+/// spawns in turn fails with `Error::NestedSandboxUnsupported`. The command can unset it, it
+/// only guards against mistakes: the processes `spawn_jail` starts without an exec always know.
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum MarkInsideSandbox {
+    Yes,
+    No,
+}
+
+impl Default for MarkInsideSandbox {
+    fn default() -> Self {
+        Self::No
     }
+}
 
-    pub fn mounts(&self) -> &[Mount] {
-        self.mounts.as_ref()
+/// What counts toward the memory limit
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum MemoryAccounting {
+    /// The peak usage of the memory cgroup minus its page cache when sampled, page cache
+    /// reclaimed in between still counts
+    Full,
+    /// Only anonymous and mapped memory (`rss + mapped_file`, `anon` on cgroups v2), so reading
+    /// large files does not count toward the limit
+    RssOnly,
+}
+
+impl Default for MemoryAccounting {
+    fn default() -> Self {
+        Self::Full
     }
+}
 
-    pub fn swap_redirects(&self) -> SwapRedirects {
-        self.swap_redirects
+/// Whether the command may not create namespaces of its own, which would give it back a full
+/// set of capabilities in them: `unshare` and `clone` fail with EPERM when asked for any, and
+/// `clone3` (whose flags a seccomp filter can not see) with ENOSYS, so the C library falls
+/// back to `clone` for threads and processes
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum DenyNestedNamespaces {
+    Yes,
+    No,
+}
+
+impl Default for DenyNestedNamespaces {
+    fn default() -> Self {
+        Self::Yes
+    }
+}
+
+/// Whether the command may only exec once, as it is started: any later `execve` or
+/// `execveat` ends the run with `RunInfoResult::IllegalSyscall`
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum SingleExec {
+    Yes,
+    No,
+}
+
+impl Default for SingleExec {
+    fn default() -> Self {
+        Self::No
     }
+}
 
-    pub fn clear_usage(&self) -> ClearUsage {
-        self.clear_usage
+#[derive(Debug, Eq, PartialEq, Copy, Clone, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct SpaceUsage(libc::rlim_t);
+
+impl SpaceUsage {
+    pub fn from_bytes(bytes: libc::rlim_t) -> Self {
+        Self(bytes)
     }
 
-    pub fn interactive(&self) -> Interactive {
-        self.interactive
+    pub fn from_kilobytes(kilobytes: libc::rlim_t) -> Self {
+        Self::from_bytes(kilobytes.saturating_mul(1_000))
     }
 
-    pub fn clone_user(&self) -> CloneUser {
-        self.clone_user
+    pub fn from_megabytes(megabytes: libc::rlim_t) -> Self {
+        Self::from_kilobytes(megabytes.saturating_mul(1_000))
     }
 
-    pub fn isolated_cgroup(&self) -> IsolatedCgroup {
-        self.isolated_cgroup
+    pub fn from_gigabytes(gigabytes: libc::rlim_t) -> Self {
+        Self::from_megabytes(gigabytes.saturating_mul(1_000))
     }
 
-    pub fn environment(&self) -> &Environment {
-        &self.environment
+    pub fn from_kibibytes(kibibytes: libc::rlim_t) -> Self {
+        Self::from_bytes(kibibytes.saturating_mul(1_024))
+    }
+
+    pub fn from_mebibytes(mebibytes: libc::rlim_t) -> Self {
+        Self::from_kibibytes(mebibytes.saturating_mul(1_024))
+    }
+
+    pub fn from_gibibytes(gibibytes: libc::rlim_t) -> Self {
+        Self::from_mebibytes(gibibytes.saturating_mul(1_024))
+    }
+
+    pub fn as_bytes(self) -> libc::rlim_t {
+        self.0
+    }
+
+    pub fn as_kilobytes(self) -> libc::rlim_t {
+        self.0 / 1_000
+    }
+
+    pub fn saturating_sub(self, other: Self) -> Self {
+        Self(self.0.saturating_sub(other.0))
+    }
+}
+
+impl Display for SpaceUsage {
+    fn fmt(&self, fmt: &mut Formatter<'_>) -> fmt::Result {
+        if self.0 % (1 << 30) == 0 {
+            write!(fmt, "{} gibibytes", self.0 >> 30)
+        } else if self.0 % (1 << 20) == 0 {
+            write!(fmt, "{} mebibytes", self.0 >> 20)
+        } else if self.0 % (1 << 10) == 0 {
+            write!(fmt, "{} kibibytes", self.0 >> 10)
+        } else if self.0 % 1_000_000_000 == 0 {
+            write!(fmt, "{} gigabytes", self.0 / 1_000_000_000)
+        } else if self.0 % 1_000_000 == 0 {
+            write!(fmt, "{} megabytes", self.0 / 1_000_000)
+        } else if self.0 % 1_000 == 0 {
+            write!(fmt, "{} kilobytes", self.0 / 1_000)
+        } else {
+            write!(fmt, "{} bytes", self.0)
+        }
+    }
+}
+
+const CPU_QUOTA_UNIT: f64 = 1_000_000.0;
+
+/// The largest pids limit, `PID_MAX_LIMIT` on 64 bit (pids.max rejects anything larger)
+pub const PIDS_MAX_LIMIT: usize = 4 * 1_024 * 1_024;
+
+const WALL_TIME_FACTOR_UNIT: u128 = 1_000_000;
+const NANOS_PER_SEC: u128 = 1_000_000_000;
+
+/// How a wall time limit is derived from the user time limit, as `user_time * factor + slack`
+#[derive(Debug, Eq, PartialEq, Copy, Clone, Serialize, Deserialize)]
+pub struct WallTimeDerivation {
+    // In millionths, so that derivations stay comparable
+    factor: u64,
+    #[serde(with = "utils::duration_nanos")]
+    slack: Duration,
+}
+
+impl WallTimeDerivation {
+    /// A factor that is not positive (NaN included) ends up as 0, which `Limits::validate`
+    /// rejects
+    pub fn new(factor: f64, slack: Duration) -> Self {
+        Self {
+            factor: (factor * WALL_TIME_FACTOR_UNIT as f64).round() as u64,
+            slack,
+        }
+    }
+
+    pub fn factor(self) -> f64 {
+        self.factor as f64 / WALL_TIME_FACTOR_UNIT as f64
+    }
+
+    pub fn slack(self) -> Duration {
+        self.slack
+    }
+
+    /// The wall time limit for `user_time`, rounded to the nearest nanosecond. Saturates
+    /// instead of overflowing.
+    pub fn wall_time(self, user_time: Duration) -> Duration {
+        let max_duration = Duration::new(u64::MAX, (NANOS_PER_SEC - 1) as u32);
+        let nanos = match user_time.as_nanos().checked_mul(u128::from(self.factor)) {
+            Some(nanos) => (nanos + WALL_TIME_FACTOR_UNIT / 2) / WALL_TIME_FACTOR_UNIT,
+            None => return max_duration,
+        };
+        if nanos / NANOS_PER_SEC > u128::from(u64::MAX) {
+            return max_duration;
+        }
+
+        Duration::new(
+            (nanos / NANOS_PER_SEC) as u64,
+            (nanos % NANOS_PER_SEC) as u32,
+        )
+        .checked_add(self.slack)
+        .unwrap_or(max_duration)
+    }
+}
+
+/// Limits for memory/time
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct Limits {
+    wall_time: Option<Duration>,
+    user_time: Option<Duration>,
+    memory: Option<SpaceUsage>,
+    stack: Option<SpaceUsage>,
+    cache: Option<SpaceUsage>,
+    pids: Option<usize>,
+    // In millionths of a CPU, so that limits stay comparable
+    cpu_quota: Option<u64>,
+    cpus: Option<String>,
+    mems: Option<String>,
+    read_bps: Option<SpaceUsage>,
+    write_bps: Option<SpaceUsage>,
+    net_bytes: Option<SpaceUsage>,
+    output: Option<SpaceUsage>,
+    wall_time_derivation: Option<WallTimeDerivation>,
+}
+
+impl Limits {
+    pub fn new(
+        wall_time: Option<Duration>,
+        user_time: Option<Duration>,
+        memory: Option<SpaceUsage>,
+        stack: Option<SpaceUsage>,
+        cache: Option<SpaceUsage>,
+        pids: Option<usize>,
+    ) -> Self {
+        Self {
+            wall_time,
+            user_time,
+            memory,
+            stack,
+            cache,
+            pids,
+            cpu_quota: None,
+            cpus: None,
+            mems: None,
+            read_bps: None,
+            write_bps: None,
+            net_bytes: None,
+            output: None,
+            wall_time_derivation: None,
+        }
+    }
+
+    /// The wall time limit, derived from the user time limit if set up to
+    pub fn wall_time(&self) -> Option<Duration> {
+        match (self.wall_time_derivation, self.user_time) {
+            (Some(wall_time_derivation), Some(user_time)) => {
+                Some(wall_time_derivation.wall_time(user_time))
+            }
+            _ => self.wall_time,
+        }
+    }
+
+    pub fn user_time(&self) -> Option<Duration> {
+        self.user_time
+    }
+
+    pub fn memory(&self) -> Option<SpaceUsage> {
+        self.memory
+    }
+
+    pub fn stack(&self) -> Option<SpaceUsage> {
+        self.stack
+    }
+
+    pub fn cache(&self) -> Option<SpaceUsage> {
+        self.cache
+    }
+
+    pub fn pids(&self) -> Option<usize> {
+        self.pids
+    }
+
+    /// Fraction of one CPU the sandbox may use (0.5 is half of a core, 2.0 two cores)
+    pub fn cpu_quota(&self) -> Option<f64> {
+        self.cpu_quota
+            .map(|cpu_quota| cpu_quota as f64 / CPU_QUOTA_UNIT)
+    }
+
+    pub fn set_cpu_quota(&mut self, cpu_quota: Option<f64>) {
+        self.cpu_quota = cpu_quota.map(|cpu_quota| (cpu_quota * CPU_QUOTA_UNIT).round() as u64);
+    }
+
+    /// CPUs the sandbox may run on, in cpuset list format (for example `0-3,6`)
+    pub fn cpus(&self) -> Option<&str> {
+        self.cpus.as_ref().map(String::as_str)
+    }
+
+    pub fn set_cpus(&mut self, cpus: Option<String>) {
+        self.cpus = cpus;
+    }
+
+    /// Memory nodes the sandbox may allocate from, in cpuset list format
+    pub fn mems(&self) -> Option<&str> {
+        self.mems.as_ref().map(String::as_str)
+    }
+
+    pub fn set_mems(&mut self, mems: Option<String>) {
+        self.mems = mems;
+    }
+
+    /// Bytes per second the sandbox may read from the device backing its root
+    pub fn read_bps(&self) -> Option<SpaceUsage> {
+        self.read_bps
+    }
+
+    pub fn set_read_bps(&mut self, read_bps: Option<SpaceUsage>) {
+        self.read_bps = read_bps;
+    }
+
+    /// Bytes per second the sandbox may write to the device backing its root
+    pub fn write_bps(&self) -> Option<SpaceUsage> {
+        self.write_bps
+    }
+
+    pub fn set_write_bps(&mut self, write_bps: Option<SpaceUsage>) {
+        self.write_bps = write_bps;
+    }
+
+    /// Bytes the sandbox may receive and send over the network together. Enforced by sampling
+    /// the counters of its network namespace, so it is approximate: a run can go over it before
+    /// it is noticed. Only for a namespace of the sandbox's own (the network unshared and no
+    /// other namespace joined), the traffic of the host or of other runs counts otherwise.
+    pub fn net_bytes(&self) -> Option<SpaceUsage> {
+        self.net_bytes
+    }
+
+    pub fn set_net_bytes(&mut self, net_bytes: Option<SpaceUsage>) {
+        self.net_bytes = net_bytes;
+    }
+
+    /// Bytes the command may write to stdout and stderr together. Only enforced for the
+    /// streams the supervisor reads, `Redirect::Capture`, `Redirect::Tee` and `Redirect::Pipe`
+    /// (which goes through the supervisor with this limit), where a file size limit does not
+    /// apply.
+    pub fn output(&self) -> Option<SpaceUsage> {
+        self.output
+    }
+
+    pub fn set_output(&mut self, output: Option<SpaceUsage>) {
+        self.output = output;
+    }
+
+    /// Derives the wall time limit from the user time limit as `user_time * factor + slack`,
+    /// replacing any wall time limit given. Meant for oversubscribed CPUs, where the wall
+    /// time only guards against deadlocks.
+    pub fn derive_wall_from_user(&mut self, factor: f64, slack: Duration) {
+        self.wall_time_derivation = Some(WallTimeDerivation::new(factor, slack));
+    }
+
+    pub fn wall_time_derivation(&self) -> Option<WallTimeDerivation> {
+        self.wall_time_derivation
+    }
+
+    /// No limits at all, this is how a limit is disabled (zero is not a valid limit)
+    pub fn unlimited() -> Self {
+        Self::new(None, None, None, None, None, None)
+    }
+
+    /// Zero limits are rejected, a run could never fit in them. So is deriving the wall time
+    /// limit without a user time limit to derive it from, or with a factor that is not positive,
+    /// and a pids limit above `PIDS_MAX_LIMIT`.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if let Some(wall_time_derivation) = self.wall_time_derivation {
+            if self.user_time.is_none() {
+                return Err(ConfigError::DerivedWallTimeWithoutUserTime);
+            }
+            if wall_time_derivation.factor == 0 {
+                return Err(ConfigError::InvalidWallTimeFactor);
+            }
+        }
+
+        if let Some(pids) = self.pids.filter(|&pids| pids > PIDS_MAX_LIMIT) {
+            return Err(ConfigError::PidsLimitTooLarge(pids));
+        }
+
+        let zero_duration = Duration::from_secs(0);
+        let zero_space = SpaceUsage::from_bytes(0);
+        let zero_limit = [
+            ("wall time", self.wall_time == Some(zero_duration)),
+            ("user time", self.user_time == Some(zero_duration)),
+            ("memory", self.memory == Some(zero_space)),
+            ("stack", self.stack == Some(zero_space)),
+            ("cache", self.cache == Some(zero_space)),
+            ("pids", self.pids == Some(0)),
+            ("cpu quota", self.cpu_quota == Some(0)),
+            ("cpus", self.cpus() == Some("")),
+            ("mems", self.mems() == Some("")),
+            ("read bps", self.read_bps == Some(zero_space)),
+            ("write bps", self.write_bps == Some(zero_space)),
+            ("net bytes", self.net_bytes == Some(zero_space)),
+            ("output", self.output == Some(zero_space)),
+        ]
+        .iter()
+        .find(|&&(_, is_zero)| is_zero)
+        .map(|&(limit, _)| limit);
+
+        match zero_limit {
+            Some(limit) => Err(ConfigError::ZeroLimit(limit.to_owned())),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self::unlimited()
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct ControllerPath {
+    cpuacct: Option<PathBuf>,
+    memory: Option<PathBuf>,
+    pids: Option<PathBuf>,
+    cpu: Option<PathBuf>,
+    cpuset: Option<PathBuf>,
+    io: Option<PathBuf>,
+    freezer: Option<PathBuf>,
+}
+
+impl ControllerPath {
+    pub fn new(cpuacct: Option<PathBuf>, memory: Option<PathBuf>, pids: Option<PathBuf>) -> Self {
+        Self {
+            cpuacct,
+            memory,
+            pids,
+            cpu: None,
+            cpuset: None,
+            io: None,
+            freezer: None,
+        }
+    }
+
+    pub fn cpuacct(&self) -> Option<&Path> {
+        self.cpuacct.as_ref().map(PathBuf::as_path)
+    }
+
+    pub fn memory(&self) -> Option<&Path> {
+        self.memory.as_ref().map(PathBuf::as_path)
+    }
+
+    pub fn pids(&self) -> Option<&Path> {
+        self.pids.as_ref().map(PathBuf::as_path)
+    }
+
+    pub fn cpu(&self) -> Option<&Path> {
+        self.cpu.as_ref().map(PathBuf::as_path)
+    }
+
+    pub fn set_cpu(&mut self, cpu: Option<PathBuf>) {
+        self.cpu = cpu;
+    }
+
+    pub fn cpuset(&self) -> Option<&Path> {
+        self.cpuset.as_ref().map(PathBuf::as_path)
+    }
+
+    pub fn set_cpuset(&mut self, cpuset: Option<PathBuf>) {
+        self.cpuset = cpuset;
+    }
+
+    /// The blkio (cgroups v1) or io (cgroups v2) controller
+    pub fn io(&self) -> Option<&Path> {
+        self.io.as_ref().map(PathBuf::as_path)
+    }
+
+    pub fn set_io(&mut self, io: Option<PathBuf>) {
+        self.io = io;
+    }
+
+    /// The freezer controller (cgroups v1), on cgroups v2 every cgroup freezes on its own and
+    /// this is the hierarchy the instance is frozen in
+    pub fn freezer(&self) -> Option<&Path> {
+        self.freezer.as_ref().map(PathBuf::as_path)
+    }
+
+    pub fn set_freezer(&mut self, freezer: Option<PathBuf>) {
+        self.freezer = freezer;
+    }
+}
+
+impl Default for ControllerPath {
+    fn default() -> Self {
+        Self::new(None, None, None)
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub struct MountOptions {
+    read_only: bool,
+    dev: bool,
+    exec: bool,
+    force_exec: bool,
+    propagation: Option<Propagation>,
+    create_destination: bool,
+    recursive: bool,
+    suid: bool,
+    atime: bool,
+}
+
+impl MountOptions {
+    /// The given flags, the rest are the defaults
+    pub fn new(read_only: bool, dev: bool, exec: bool) -> Self {
+        Self {
+            read_only,
+            dev,
+            exec,
+            ..Self::default()
+        }
+    }
+
+    /// Starts from the defaults, `MountOptions::builder().writable().exec(true).build()`
+    pub fn builder() -> MountOptionsBuilder {
+        MountOptionsBuilder(Self::default())
+    }
+
+    pub fn read_only(self) -> bool {
+        self.read_only
+    }
+
+    pub fn dev(self) -> bool {
+        self.dev
+    }
+
+    pub fn exec(self) -> bool {
+        self.exec
+    }
+
+    /// Whether to skip the check for `noexec` inherited from the source filesystem, the bind
+    /// remount then clears it where the kernel allows (it does not for a mount locked inside a
+    /// user namespace, the mount fails)
+    pub fn force_exec(self) -> bool {
+        self.force_exec
+    }
+
+    /// The propagation of this mount, when unset it is the one of the whole mount namespace
+    /// (`Config::propagation`). Host events can only reach the mount if it is a slave or
+    /// shared mount on the host as well.
+    pub fn propagation(self) -> Option<Propagation> {
+        self.propagation
+    }
+
+    /// Whether to create a missing destination inside the new root, with its parent
+    /// directories (an empty file for a file bind mount). Its symlinks are resolved inside the
+    /// new root either way.
+    pub fn create_destination(self) -> bool {
+        self.create_destination
+    }
+
+    /// Whether a bind mount brings the mounts under its source along, they get the
+    /// restrictions of this one as well (read-only, noexec, ...)
+    pub fn recursive(self) -> bool {
+        self.recursive
+    }
+
+    /// Whether set-user-ID and set-group-ID bits are honoured, they are not by default
+    pub fn suid(self) -> bool {
+        self.suid
+    }
+
+    /// Whether access times are updated (`relatime`), without it the mount is `noatime`.
+    /// Inside a user namespace a bind mount keeps the one of its source.
+    pub fn atime(self) -> bool {
+        self.atime
+    }
+
+    pub fn set_read_only(&mut self, value: bool) {
+        self.read_only = value;
+    }
+
+    pub fn set_dev(&mut self, value: bool) {
+        self.dev = value;
+    }
+
+    pub fn set_exec(&mut self, value: bool) {
+        self.exec = value;
+    }
+
+    pub fn set_force_exec(&mut self, value: bool) {
+        self.force_exec = value;
+    }
+
+    pub fn set_propagation(&mut self, value: Option<Propagation>) {
+        self.propagation = value;
+    }
+
+    pub fn set_create_destination(&mut self, value: bool) {
+        self.create_destination = value;
+    }
+
+    pub fn set_recursive(&mut self, value: bool) {
+        self.recursive = value;
+    }
+
+    pub fn set_suid(&mut self, value: bool) {
+        self.suid = value;
+    }
+
+    pub fn set_atime(&mut self, value: bool) {
+        self.atime = value;
+    }
+}
+
+impl Default for MountOptions {
+    fn default() -> Self {
+        Self {
+            read_only: true,
+            dev: false,
+            exec: false,
+            force_exec: false,
+            propagation: None,
+            create_destination: true,
+            recursive: true,
+            suid: false,
+            atime: true,
+        }
+    }
+}
+
+/// Parses a comma separated list of options, like the ones of fstab: `ro`/`rw`, `dev`/`nodev`,
+/// `exec`/`noexec`, `force-exec` (which is `exec` as well), `suid`/`nosuid`,
+/// `atime`/`noatime`, `private`, `slave` or `shared`, `create`/`no-create`,
+/// `recursive`/`no-recursive` and `defaults`. The ones not given are the defaults, a later
+/// option wins over an earlier one.
+impl FromStr for MountOptions {
+    type Err = ConfigError;
+
+    fn from_str(string: &str) -> Result<Self, ConfigError> {
+        let mut mount_options = Self::default();
+        for option in string.split(',') {
+            match option {
+                "defaults" => {}
+                "ro" => mount_options.set_read_only(true),
+                "rw" => mount_options.set_read_only(false),
+                "dev" => mount_options.set_dev(true),
+                "nodev" => mount_options.set_dev(false),
+                "exec" => mount_options.set_exec(true),
+                "noexec" => {
+                    mount_options.set_exec(false);
+                    mount_options.set_force_exec(false);
+                }
+                "force-exec" => {
+                    mount_options.set_exec(true);
+                    mount_options.set_force_exec(true);
+                }
+                "suid" => mount_options.set_suid(true),
+                "nosuid" => mount_options.set_suid(false),
+                "atime" => mount_options.set_atime(true),
+                "noatime" => mount_options.set_atime(false),
+                "private" => mount_options.set_propagation(Some(Propagation::Private)),
+                "slave" => mount_options.set_propagation(Some(Propagation::Slave)),
+                "shared" => mount_options.set_propagation(Some(Propagation::Shared)),
+                "create" => mount_options.set_create_destination(true),
+                "no-create" => mount_options.set_create_destination(false),
+                "recursive" => mount_options.set_recursive(true),
+                "no-recursive" => mount_options.set_recursive(false),
+                option => return Err(ConfigError::InvalidMountOption(option.to_owned())),
+            }
+        }
+        Ok(mount_options)
+    }
+}
+
+/// Builds `MountOptions` in one expression, see `MountOptions::builder`
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub struct MountOptionsBuilder(MountOptions);
+
+impl MountOptionsBuilder {
+    pub fn read_only(mut self, value: bool) -> Self {
+        self.0.set_read_only(value);
+        self
+    }
+
+    /// Same as `read_only(false)`
+    pub fn writable(self) -> Self {
+        self.read_only(false)
+    }
+
+    pub fn dev(mut self, value: bool) -> Self {
+        self.0.set_dev(value);
+        self
+    }
+
+    pub fn exec(mut self, value: bool) -> Self {
+        self.0.set_exec(value);
+        self
+    }
+
+    /// Only has an effect along with `exec(true)`
+    pub fn force_exec(mut self, value: bool) -> Self {
+        self.0.set_force_exec(value);
+        self
+    }
+
+    pub fn propagation(mut self, value: Propagation) -> Self {
+        self.0.set_propagation(Some(value));
+        self
+    }
+
+    pub fn create_destination(mut self, value: bool) -> Self {
+        self.0.set_create_destination(value);
+        self
+    }
+
+    pub fn recursive(mut self, value: bool) -> Self {
+        self.0.set_recursive(value);
+        self
+    }
+
+    pub fn suid(mut self, value: bool) -> Self {
+        self.0.set_suid(value);
+        self
+    }
+
+    pub fn atime(mut self, value: bool) -> Self {
+        self.0.set_atime(value);
+        self
+    }
+
+    pub fn build(self) -> MountOptions {
+        self.0
+    }
+}
+
+/// What a `Mount` puts at its destination
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum MountKind {
+    /// A file or directory of the host, bind mounted
+    Bind { source: PathBuf },
+    /// An empty filesystem in memory of at most `size` (half of the memory by default), whose
+    /// pages count towards the memory of the command writing them
+    Tmpfs { size: Option<SpaceUsage> },
+    /// The `lower` directories of the host (the first one on top) merged under `upper`, which
+    /// gets all the changes, see `Overlay`
+    Overlay {
+        lower: Vec<PathBuf>,
+        upper: PathBuf,
+        work: PathBuf,
+    },
+}
+
+/// The directories of an overlay filesystem: the `lower` ones (the first one on top) are
+/// merged read-only under `upper`, which gets all the changes made. `work` is an empty
+/// directory on the same filesystem as `upper` for the kernel to prepare the changes in.
+/// Inside a user namespace it needs Linux 5.11 or later.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct Overlay {
+    lower: Vec<PathBuf>,
+    upper: PathBuf,
+    work: PathBuf,
+}
+
+impl Overlay {
+    pub fn new(lower: Vec<PathBuf>, upper: PathBuf, work: PathBuf) -> Self {
+        Self { lower, upper, work }
+    }
+
+    pub fn lower(&self) -> &[PathBuf] {
+        &self.lower
+    }
+
+    pub fn upper(&self) -> &Path {
+        &self.upper
+    }
+
+    pub fn work(&self) -> &Path {
+        &self.work
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        if self.lower.is_empty() {
+            Err(ConfigError::OverlayWithoutLower)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct Mount {
+    kind: MountKind,
+    destination: PathBuf,
+    mount_options: MountOptions,
+}
+
+impl Mount {
+    /// A bind mount of `source`, a file or a directory. A missing destination is created as
+    /// the same, along with its parent directories.
+    pub fn new(source: PathBuf, destination: PathBuf, mount_options: MountOptions) -> Self {
+        Self {
+            kind: MountKind::Bind { source },
+            destination,
+            mount_options,
+        }
+    }
+
+    /// A bind mount of `source` with the default options, read-only
+    pub fn read_only(source: PathBuf, destination: PathBuf) -> Self {
+        Self::new(source, destination, MountOptions::default())
+    }
+
+    /// A bind mount of `source` with the default options but writable
+    pub fn writable(source: PathBuf, destination: PathBuf) -> Self {
+        Self::new(
+            source,
+            destination,
+            MountOptions::builder().writable().build(),
+        )
+    }
+
+    /// A tmpfs at `destination`, only written to with `mount_options` that are not read-only
+    pub fn tmpfs(
+        destination: PathBuf,
+        size: Option<SpaceUsage>,
+        mount_options: MountOptions,
+    ) -> Self {
+        Self {
+            kind: MountKind::Tmpfs { size },
+            destination,
+            mount_options,
+        }
+    }
+
+    /// An overlay at `destination`, only written to with `mount_options` that are not
+    /// read-only. Overlays are mounted before the other mounts, which can go on top of them.
+    pub fn overlay(destination: PathBuf, overlay: Overlay, mount_options: MountOptions) -> Self {
+        Self {
+            kind: MountKind::Overlay {
+                lower: overlay.lower,
+                upper: overlay.upper,
+                work: overlay.work,
+            },
+            destination,
+            mount_options,
+        }
+    }
+
+    pub fn kind(&self) -> &MountKind {
+        &self.kind
+    }
+
+    /// The host path bind mounted, `None` for a tmpfs or an overlay
+    pub fn source(&self) -> Option<&Path> {
+        match self.kind {
+            MountKind::Bind { ref source } => Some(source),
+            MountKind::Tmpfs { .. } | MountKind::Overlay { .. } => None,
+        }
+    }
+
+    pub fn destination(&self) -> &Path {
+        &self.destination
+    }
+
+    pub fn mount_options(&self) -> MountOptions {
+        self.mount_options
+    }
+
+    /// Checks that the filesystem the source lives on doesn't forbid what the
+    /// mount options ask for, bind mounts inherit `noexec`/`nodev`/`nosuid` and
+    /// inside a user namespace they can not be cleared. `force_exec` skips the
+    /// check for `noexec`.
+    pub fn check_source_flags(&self, mount_info: &MountInfo) -> Result<(), ConfigError> {
+        let source = match self.kind {
+            MountKind::Bind { ref source } => source,
+            // Nothing to inherit them from
+            MountKind::Tmpfs { .. } | MountKind::Overlay { .. } => return Ok(()),
+        };
+        let canonical_source = match fs::canonicalize(source) {
+            Ok(canonical_source) => canonical_source,
+            // Missing sources are reported when mounting
+            Err(_) => return Ok(()),
+        };
+        let entry = match mount_info.find_mount(&canonical_source) {
+            Some(entry) => entry,
+            None => return Ok(()),
+        };
+
+        let mut missing = Vec::new();
+        if self.mount_options.exec()
+            && !self.mount_options.force_exec()
+            && entry.has_option("noexec")
+        {
+            missing.push("exec".to_owned());
+        }
+        if self.mount_options.dev() && entry.has_option("nodev") {
+            missing.push("dev".to_owned());
+        }
+        if self.mount_options.suid() && entry.has_option("nosuid") {
+            missing.push("suid".to_owned());
+        }
+
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError::SourceMountFlagsConflict {
+                source: source.clone(),
+                mount_point: entry.mount_point().to_path_buf(),
+                missing,
+            })
+        }
+    }
+}
+
+/// A veth pair connecting the sandbox to the host, `host_name` stays in the host network
+/// namespace and `peer_name` is moved into the one of the sandbox. Both ends are on the same
+/// `/prefix_len` subnet.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct VethConfig {
+    host_name: String,
+    host_address: Ipv4Addr,
+    peer_name: String,
+    peer_address: Ipv4Addr,
+    prefix_len: u8,
+}
+
+/// Interface names are at most IFNAMSIZ bytes, including the terminating NUL
+const MAX_INTERFACE_NAME_LEN: usize = 15;
+
+impl VethConfig {
+    pub fn new(
+        host_name: String,
+        host_address: Ipv4Addr,
+        peer_name: String,
+        peer_address: Ipv4Addr,
+        prefix_len: u8,
+    ) -> Self {
+        Self {
+            host_name,
+            host_address,
+            peer_name,
+            peer_address,
+            prefix_len,
+        }
+    }
+
+    pub fn host_name(&self) -> &str {
+        &self.host_name
+    }
+
+    pub fn host_address(&self) -> Ipv4Addr {
+        self.host_address
+    }
+
+    pub fn peer_name(&self) -> &str {
+        &self.peer_name
+    }
+
+    pub fn peer_address(&self) -> Ipv4Addr {
+        self.peer_address
+    }
+
+    pub fn prefix_len(&self) -> u8 {
+        self.prefix_len
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        for name in &[&self.host_name, &self.peer_name] {
+            if name.is_empty()
+                || name.len() > MAX_INTERFACE_NAME_LEN
+                || name.contains(|c: char| c == '/' || c == '\0' || c.is_whitespace())
+            {
+                return Err(ConfigError::InvalidInterfaceName(name.to_string()));
+            }
+        }
+        if self.host_name == self.peer_name {
+            return Err(ConfigError::InvalidInterfaceName(self.peer_name.clone()));
+        }
+        if self.prefix_len > 32 {
+            return Err(ConfigError::InvalidPrefixLen(self.prefix_len));
+        }
+        Ok(())
+    }
+}
+
+/// What a `SeccompPolicy` does with the syscalls outside of its allowlist
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum SeccompAction {
+    /// Kills the command, the run ends with `RunInfoResult::IllegalSyscall`
+    Kill,
+    /// Fails the syscall with this errno instead of running it
+    Errno(u16),
+    /// Runs the syscall anyway, but logs it to the kernel audit log
+    Log,
+}
+
+/// A syscall allowlist for the command, loaded as a seccomp filter right before the exec
+/// (which is always allowed, the command is started through `execve`). The names are the
+/// ones of the native architecture, such as `read` or `exit_group`.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct SeccompPolicy {
+    syscalls: Vec<String>,
+    notify: Vec<String>,
+    default_action: SeccompAction,
+}
+
+impl SeccompPolicy {
+    pub fn new(syscalls: Vec<String>, default_action: SeccompAction) -> Self {
+        Self {
+            syscalls,
+            notify: Vec::new(),
+            default_action,
+        }
+    }
+
+    pub fn syscalls(&self) -> &[String] {
+        &self.syscalls
+    }
+
+    /// The syscalls the notify handler of the config decides on (see
+    /// `Config::set_seccomp_notify_handler`), none by default. The allowlist comes first.
+    pub fn notify(&self) -> &[String] {
+        &self.notify
+    }
+
+    pub fn set_notify(&mut self, notify: Vec<String>) {
+        self.notify = notify;
+    }
+
+    /// Adds `syscall` to the allowlist, for composing policies such as the ones of
+    /// `seccomp_profiles`
+    pub fn allow(mut self, syscall: &str) -> Self {
+        if !self.syscalls.iter().any(|allowed| allowed == syscall) {
+            self.syscalls.push(syscall.to_owned());
+        }
+        self
+    }
+
+    /// Removes `syscall` from the allowlist (and the notified syscalls), it gets the default
+    /// action instead
+    pub fn deny(mut self, syscall: &str) -> Self {
+        self.syscalls.retain(|allowed| allowed != syscall);
+        self.notify.retain(|notified| notified != syscall);
+        self
+    }
+
+    pub fn default_action(&self) -> SeccompAction {
+        self.default_action
+    }
+
+    /// The numbers of the allowed syscalls, `None` if any of the names is unknown
+    pub(crate) fn syscall_numbers(&self) -> Option<Vec<u32>> {
+        self.syscalls
+            .iter()
+            .map(|syscall| syscalls::number(syscall))
+            .collect()
+    }
+
+    /// The numbers of the syscalls to notify on, `None` if any of the names is unknown
+    pub(crate) fn notify_numbers(&self) -> Option<Vec<u32>> {
+        self.notify
+            .iter()
+            .map(|syscall| syscalls::number(syscall))
+            .collect()
+    }
+
+    /// Whether the supervisor has to listen to the filter, for the syscalls it kills on or
+    /// notifies about
+    pub(crate) fn needs_listener(&self) -> bool {
+        self.default_action == SeccompAction::Kill || !self.notify.is_empty()
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        match self
+            .syscalls
+            .iter()
+            .chain(&self.notify)
+            .find(|syscall| syscalls::number(syscall).is_none())
+        {
+            Some(syscall) => Err(ConfigError::UnknownSyscall(syscall.clone())),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A kind of filesystem access a `LandlockPolicy` grants beneath a path
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum LandlockAccess {
+    ReadFile,
+    ReadDir,
+    /// Writing and truncating files, beneath a directory creating and removing them as well
+    WriteFile,
+    /// Running files, which reads them as well
+    Execute,
+}
+
+/// What `spawn_jail` does with a `LandlockPolicy` when the kernel has no Landlock (it needs
+/// 5.13 or later, with `landlock` among the enabled security modules)
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum LandlockUnsupported {
+    /// Fails with `Error::UnsupportedEnvironment`
+    Fail,
+    /// Runs the command without the restriction, with a warning in `RunInfo::warnings`
+    Warn,
+}
+
+/// Filesystem access restricted with Landlock, right before the exec: only the accesses given
+/// beneath the rule paths are allowed, every other one fails with `EACCES`. The paths are
+/// resolved inside the sandbox, and must include what the exec needs: `Execute` for the
+/// command and its dynamic loader, `ReadFile` for the libraries.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct LandlockPolicy {
+    rules: Vec<(PathBuf, Vec<LandlockAccess>)>,
+    unsupported: LandlockUnsupported,
+}
+
+impl LandlockPolicy {
+    pub fn new(
+        rules: Vec<(PathBuf, Vec<LandlockAccess>)>,
+        unsupported: LandlockUnsupported,
+    ) -> Self {
+        Self { rules, unsupported }
+    }
+
+    pub fn rules(&self) -> &[(PathBuf, Vec<LandlockAccess>)] {
+        &self.rules
+    }
+
+    pub fn unsupported(&self) -> LandlockUnsupported {
+        self.unsupported
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        match self.rules.iter().find(|(path, _)| !path.is_absolute()) {
+            Some((path, _)) => Err(ConfigError::LandlockPathNotAbsolute(path.clone())),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The environment of the command, with `INSIDE_SANDBOX_VAR` added only for
+/// `MarkInsideSandbox::Yes` (see `Config::set_mark_inside_sandbox`)
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum Environment {
+    Forward,
+    EnvList(Vec<(String, String)>),
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Self::EnvList(Vec::new())
+    }
+}
+
+/// A filesystem image mounted as the new root, instead of a directory tree of the host
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum RootImage {
+    /// Mounted read-only through a loop device, which only root can set up: it needs the root
+    /// isolation mode (see `IsolationMode`). The loop device is let go with the mount, once
+    /// the sandbox is gone.
+    Squashfs(PathBuf),
+    /// An uncompressed tar archive, unpacked into a tmpfs (counting towards the memory of the
+    /// sandbox) before every run. Devices and FIFOs in it are left out.
+    Tarball(PathBuf),
+}
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct Config {
+    command: PathBuf,
+    args: Vec<OsString>,
+    new_root: Option<PathBuf>,
+    new_root_overlay: Option<Overlay>,
+    root_image: Option<RootImage>,
+    working_directory: Option<PathBuf>,
+    copy_in: Vec<(PathBuf, PathBuf)>,
+    copy_out: Vec<(PathBuf, PathBuf)>,
+    copy_out_limit: Option<SpaceUsage>,
+    share_net: ShareNet,
+    redirect_stdin: Redirect,
+    redirect_stdout: Redirect,
+    redirect_stderr: Redirect,
+    limits: Limits,
+    instance_name: Option<OsString>,
+    controller_path: ControllerPath,
+    mounts: Vec<Mount>,
+    swap_redirects: SwapRedirects,
+    clear_usage: ClearUsage,
+    interactive: Interactive,
+    clone_user: CloneUser,
+    isolated_cgroup: IsolatedCgroup,
+    environment: Environment,
+    exclude_stopped_time: ExcludeStoppedTime,
+    pausable: Pausable,
+    mark_inside_sandbox: MarkInsideSandbox,
+    detached: Option<PathBuf>,
+    mount_binfmt_interpreter: MountBinfmtInterpreter,
+    mount_cgroups: MountCgroups,
+    minimal_dev: MinimalDev,
+    private_tmp: PrivateTmp,
+    private_tmp_size: SpaceUsage,
+    stderr_tail: SpaceUsage,
+    read_only_root: ReadOnlyRoot,
+    proc_options: ProcOptions,
+    disable_swap: DisableSwap,
+    allow_same_file_io: AllowSameFileIo,
+    extra_fds: Vec<(RawFd, FdSource)>,
+    socket_channel: Option<RawFd>,
+    fifo_open_timeout: Option<Duration>,
+    close_fds: CloseFds,
+    parent_limits: Option<Limits>,
+    kill_grace_period: Option<Duration>,
+    memory_accounting: MemoryAccounting,
+    share_ipc: ShareIpc,
+    sandbox_user: Option<(u32, u32)>,
+    share_pid: SharePid,
+    veth: Option<VethConfig>,
+    join_namespaces: Vec<(NamespaceKind, PathBuf)>,
+    propagation: Propagation,
+    isolation_mode: IsolationMode,
+    keep_capabilities: Vec<Capability>,
+    seccomp: Option<SeccompPolicy>,
+    seccomp_notify_handler: Option<SeccompNotifyHandler>,
+    on_output: Option<OutputCallback>,
+    on_usage: Option<UsageCallback>,
+    landlock: Option<LandlockPolicy>,
+    single_exec: SingleExec,
+    trace_syscalls: Option<PathBuf>,
+    deny_nested_namespaces: DenyNestedNamespaces,
+    allocate_pty: AllocatePty,
+}
+
+impl Config {
+    #![allow(clippy::too_many_arguments)]
+    #[deprecated(note = "use `Config::builder`, which sets any of the options")]
+    pub fn new(
+        command: PathBuf,
+        args: Vec<OsString>,
+        new_root: Option<PathBuf>,
+        share_net: ShareNet,
+        redirect_stdin: Redirect,
+        redirect_stdout: Redirect,
+        redirect_stderr: Redirect,
+        limits: Limits,
+        instance_name: Option<OsString>,
+        controller_path: ControllerPath,
+        mounts: Vec<Mount>,
+        swap_redirects: SwapRedirects,
+        clear_usage: ClearUsage,
+        interactive: Interactive,
+        clone_user: CloneUser,
+        isolated_cgroup: IsolatedCgroup,
+        environment: Environment,
+    ) -> Self {
+        Self {
+            command,
+            args,
+            new_root,
+            share_net,
+            redirect_stdin,
+            redirect_stdout,
+            redirect_stderr,
+            limits,
+            instance_name,
+            controller_path,
+            mounts,
+            swap_redirects,
+            clear_usage,
+            interactive,
+            clone_user,
+            isolated_cgroup,
+            environment,
+            ..Self::builder(PathBuf::new()).config
+        }
+    }
+
+    /// Starts a `Config` of `command` with the defaults: no arguments, the root of the host,
+    /// its network unshared, the standard streams inherited and no limits. The other options
+    /// are the defaults of their types, see `ConfigBuilder`.
+    ///
+    /// `Config::builder("/bin/echo").arg("hello").redirect_stdout(Redirect::Pipe).build()`
+    pub fn builder<T: AsRef<Path>>(command: T) -> ConfigBuilder {
+        ConfigBuilder {
+            config: Self {
+                command: command.as_ref().to_path_buf(),
+                args: Vec::new(),
+                new_root: None,
+                share_net: ShareNet::default(),
+                redirect_stdin: Redirect::default(),
+                redirect_stdout: Redirect::default(),
+                redirect_stderr: Redirect::default(),
+                limits: Limits::default(),
+                instance_name: None,
+                controller_path: ControllerPath::default(),
+                mounts: Vec::new(),
+                swap_redirects: SwapRedirects::default(),
+                clear_usage: ClearUsage::default(),
+                interactive: Interactive::default(),
+                clone_user: CloneUser::default(),
+                isolated_cgroup: IsolatedCgroup::default(),
+                environment: Environment::default(),
+                new_root_overlay: None,
+                root_image: None,
+                working_directory: None,
+                copy_in: Vec::new(),
+                copy_out: Vec::new(),
+                copy_out_limit: None,
+                exclude_stopped_time: ExcludeStoppedTime::default(),
+                pausable: Pausable::default(),
+                mark_inside_sandbox: MarkInsideSandbox::default(),
+                detached: None,
+                mount_binfmt_interpreter: MountBinfmtInterpreter::default(),
+                mount_cgroups: MountCgroups::default(),
+                minimal_dev: MinimalDev::default(),
+                private_tmp: PrivateTmp::default(),
+                private_tmp_size: SpaceUsage::from_megabytes(64),
+                stderr_tail: SpaceUsage::from_kibibytes(4),
+                read_only_root: ReadOnlyRoot::default(),
+                proc_options: ProcOptions::default(),
+                disable_swap: DisableSwap::default(),
+                allow_same_file_io: AllowSameFileIo::default(),
+                extra_fds: Vec::new(),
+                socket_channel: None,
+                fifo_open_timeout: Some(Duration::from_secs(5)),
+                close_fds: CloseFds::default(),
+                parent_limits: None,
+                kill_grace_period: None,
+                memory_accounting: MemoryAccounting::default(),
+                share_ipc: ShareIpc::default(),
+                sandbox_user: None,
+                share_pid: SharePid::default(),
+                veth: None,
+                join_namespaces: Vec::new(),
+                propagation: Propagation::default(),
+                isolation_mode: IsolationMode::default(),
+                keep_capabilities: Vec::new(),
+                seccomp: None,
+                seccomp_notify_handler: None,
+                on_output: None,
+                on_usage: None,
+                landlock: None,
+                single_exec: SingleExec::default(),
+                trace_syscalls: None,
+                deny_nested_namespaces: DenyNestedNamespaces::default(),
+                allocate_pty: AllocatePty::default(),
+            },
+        }
+    }
+
+    pub fn command(&self) -> &Path {
+        &self.command
+    }
+
+    pub fn args<'a>(&'a self) -> Vec<&'a OsStr> {
+        self.args.iter().map(OsString::as_os_str).collect()
+    }
+
+    pub fn new_root(&self) -> Option<&Path> {
+        self.new_root.as_ref().map(PathBuf::as_path)
+    }
+
+    /// An overlay mounted at the new root before anything else, so a pristine root can be
+    /// shared between runs with the changes of each kept in an upper directory of its own
+    pub fn new_root_overlay(&self) -> Option<&Overlay> {
+        self.new_root_overlay.as_ref()
+    }
+
+    pub fn set_new_root_overlay(&mut self, new_root_overlay: Option<Overlay>) {
+        self.new_root_overlay = new_root_overlay;
+    }
+
+    /// An image mounted at the new root before anything else, the directory itself is only
+    /// where it goes
+    pub fn root_image(&self) -> Option<&RootImage> {
+        self.root_image.as_ref()
+    }
+
+    pub fn set_root_image(&mut self, root_image: Option<RootImage>) {
+        self.root_image = root_image;
+    }
+
+    /// The directory the command runs in, inside the new root (`/` of it by default, the
+    /// current directory without one). Relative paths in the arguments start from it.
+    pub fn working_directory(&self) -> Option<&Path> {
+        self.working_directory.as_deref()
+    }
+
+    pub fn set_working_directory(&mut self, working_directory: Option<PathBuf>) {
+        self.working_directory = working_directory;
+    }
+
+    /// Files of the host (the first path) copied to the second path inside the sandbox before
+    /// the command starts, with their permissions. The destination is resolved inside the new
+    /// root like a mount destination and its missing directories are made. It has to be
+    /// writable in the sandbox, a copy to a read-only root fails.
+    pub fn copy_in(&self) -> &[(PathBuf, PathBuf)] {
+        &self.copy_in
+    }
+
+    pub fn set_copy_in(&mut self, copy_in: Vec<(PathBuf, PathBuf)>) {
+        self.copy_in = copy_in;
+    }
+
+    /// Files inside the sandbox (the first path) copied to the second path of the host once
+    /// the command exited, whatever the result of the run. The mounts of the sandbox (a tmpfs
+    /// for one) are still there at that point. Only regular files are copied.
+    pub fn copy_out(&self) -> &[(PathBuf, PathBuf)] {
+        &self.copy_out
+    }
+
+    pub fn set_copy_out(&mut self, copy_out: Vec<(PathBuf, PathBuf)>) {
+        self.copy_out = copy_out;
+    }
+
+    /// The largest file `copy_out` copies, a larger one fails the run with
+    /// `FFIError::CopyOutTooLarge`. No limit by default.
+    pub fn copy_out_limit(&self) -> Option<SpaceUsage> {
+        self.copy_out_limit
+    }
+
+    pub fn set_copy_out_limit(&mut self, copy_out_limit: Option<SpaceUsage>) {
+        self.copy_out_limit = copy_out_limit;
+    }
+
+    pub fn share_net(&self) -> ShareNet {
+        self.share_net
+    }
+
+    pub fn redirect_stdin(&self) -> &Redirect {
+        &self.redirect_stdin
+    }
+
+    pub fn redirect_stdout(&self) -> &Redirect {
+        &self.redirect_stdout
+    }
+
+    pub fn redirect_stderr(&self) -> &Redirect {
+        &self.redirect_stderr
+    }
+
+    pub fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    pub fn instance_name(&self) -> Option<&OsStr> {
+        self.instance_name.as_ref().map(OsString::as_os_str)
+    }
+
+    pub fn controller_path(&self) -> &ControllerPath {
+        &self.controller_path
+    }
+
+    pub fn mounts(&self) -> &[Mount] {
+        self.mounts.as_ref()
+    }
+
+    pub fn swap_redirects(&self) -> SwapRedirects {
+        self.swap_redirects
+    }
+
+    pub fn clear_usage(&self) -> ClearUsage {
+        self.clear_usage
+    }
+
+    /// Whether the command stays in the process group of the caller, to use its terminal.
+    /// With a new root a `/dev/pts` of its own is mounted in it as well, with `/dev/ptmx`.
+    pub fn interactive(&self) -> Interactive {
+        self.interactive
+    }
+
+    pub fn clone_user(&self) -> CloneUser {
+        self.clone_user
+    }
+
+    pub fn isolated_cgroup(&self) -> IsolatedCgroup {
+        self.isolated_cgroup
+    }
+
+    pub fn environment(&self) -> &Environment {
+        &self.environment
+    }
+
+    /// Whether time spent stopped (SIGSTOP/SIGTSTP) is left out of the wall time
+    pub fn exclude_stopped_time(&self) -> ExcludeStoppedTime {
+        self.exclude_stopped_time
+    }
+
+    pub fn set_exclude_stopped_time(&mut self, exclude_stopped_time: ExcludeStoppedTime) {
+        self.exclude_stopped_time = exclude_stopped_time;
+    }
+
+    /// Whether `JailHandle::pause` and `JailHandle::resume` can be used on the run
+    pub fn pausable(&self) -> Pausable {
+        self.pausable
+    }
+
+    pub fn set_pausable(&mut self, pausable: Pausable) {
+        self.pausable = pausable;
+    }
+
+    /// Whether the command is told it runs in a sandbox through its environment
+    pub fn mark_inside_sandbox(&self) -> MarkInsideSandbox {
+        self.mark_inside_sandbox
+    }
+
+    pub fn set_mark_inside_sandbox(&mut self, mark_inside_sandbox: MarkInsideSandbox) {
+        self.mark_inside_sandbox = mark_inside_sandbox;
+    }
+
+    /// Lets the run outlive the caller: the supervisor does not die along with it, and writes
+    /// the result (as JSON, see `AdoptedJail`) to this file of the host before it exits, as well
+    /// as sending it to the `JailHandle`. `JailHandle::into_recovery_token` then hands the run
+    /// over to another process. Pipes to the caller (`Redirect::Pipe`) break once it is gone.
+    /// Meanwhile the supervisor answers `AdoptedJail::status` on a unix socket next to the
+    /// file, with `.control` appended to its name.
+    pub fn detached(&self) -> Option<&Path> {
+        self.detached.as_ref().map(PathBuf::as_path)
+    }
+
+    pub fn set_detached(&mut self, detached: Option<PathBuf>) {
+        self.detached = detached;
+    }
+
+    /// Whether to bind mount (read-only) the `binfmt_misc` interpreter of a foreign
+    /// architecture command into the new root when it is missing from it
+    pub fn mount_binfmt_interpreter(&self) -> MountBinfmtInterpreter {
+        self.mount_binfmt_interpreter
+    }
+
+    pub fn set_mount_binfmt_interpreter(
+        &mut self,
+        mount_binfmt_interpreter: MountBinfmtInterpreter,
+    ) {
+        self.mount_binfmt_interpreter = mount_binfmt_interpreter;
+    }
+
+    /// Whether to bind mount (read-only) the instance cgroups under `/sys/fs/cgroup` in the
+    /// new root, so the command can read its own limits
+    pub fn mount_cgroups(&self) -> MountCgroups {
+        self.mount_cgroups
+    }
+
+    pub fn set_mount_cgroups(&mut self, mount_cgroups: MountCgroups) {
+        self.mount_cgroups = mount_cgroups;
+    }
+
+    /// Whether to mount a minimal `/dev` in the new root (without one there is nothing to
+    /// mount it in), `MinimalDev::Yes` by default. The mounts go on top of it.
+    pub fn minimal_dev(&self) -> MinimalDev {
+        self.minimal_dev
+    }
+
+    pub fn set_minimal_dev(&mut self, minimal_dev: MinimalDev) {
+        self.minimal_dev = minimal_dev;
+    }
+
+    /// Whether to mount a private tmpfs at `/tmp`, `/var/tmp` and `/dev/shm` in the new root,
+    /// `PrivateTmp::Yes` by default. A directory the mounts put something at or under is left
+    /// to them.
+    pub fn private_tmp(&self) -> PrivateTmp {
+        self.private_tmp
+    }
+
+    pub fn set_private_tmp(&mut self, private_tmp: PrivateTmp) {
+        self.private_tmp = private_tmp;
+    }
+
+    /// The size of each private tmpfs, 64mb by default. The pages written count towards the
+    /// memory of the command.
+    pub fn private_tmp_size(&self) -> SpaceUsage {
+        self.private_tmp_size
+    }
+
+    pub fn set_private_tmp_size(&mut self, private_tmp_size: SpaceUsage) {
+        self.private_tmp_size = private_tmp_size;
+    }
+
+    /// How much of the end of stderr a failed run keeps in `RunInfo::stderr_tail`, 4kb by
+    /// default, 0 for none. Only for stderr captured or redirected to a file of the host
+    /// (`Redirect::Capture`, `Redirect::Tee` and `Redirect::File` outside the sandbox).
+    pub fn stderr_tail(&self) -> SpaceUsage {
+        self.stderr_tail
+    }
+
+    pub fn set_stderr_tail(&mut self, stderr_tail: SpaceUsage) {
+        self.stderr_tail = stderr_tail;
+    }
+
+    /// Whether to remount the new root read-only before pivoting into it, so not even root in
+    /// the sandbox can write to it. Writable mounts (such as a tmpfs at `/tmp`) still are.
+    pub fn read_only_root(&self) -> ReadOnlyRoot {
+        self.read_only_root
+    }
+
+    pub fn set_read_only_root(&mut self, read_only_root: ReadOnlyRoot) {
+        self.read_only_root = read_only_root;
+    }
+
+    /// How `/proc` is mounted in a new pid namespace, by default with the processes of other
+    /// users invisible, only the process directories in it and the host wide files masked
+    pub fn proc_options(&self) -> ProcOptions {
+        self.proc_options
+    }
+
+    pub fn set_proc_options(&mut self, proc_options: ProcOptions) {
+        self.proc_options = proc_options;
+    }
+
+    /// Whether to set `memory.swappiness` to zero when there is a memory limit, so the
+    /// kernel fails allocations instead of swapping (which underreports the memory usage)
+    pub fn disable_swap(&self) -> DisableSwap {
+        self.disable_swap
+    }
+
+    pub fn set_disable_swap(&mut self, disable_swap: DisableSwap) {
+        self.disable_swap = disable_swap;
+    }
+
+    /// Whether stdin may be redirected from the same regular file as stdout or stderr.
+    /// Opening it for output truncates it, so the input may be read empty depending on the
+    /// order the redirects are opened in.
+    pub fn allow_same_file_io(&self) -> AllowSameFileIo {
+        self.allow_same_file_io
+    }
+
+    pub fn set_allow_same_file_io(&mut self, allow_same_file_io: AllowSameFileIo) {
+        self.allow_same_file_io = allow_same_file_io;
+    }
+
+    /// File descriptors the command starts with besides stdin, stdout and stderr, each at the
+    /// first one of its pair. They are put in place after the redirects, a source may be the
+    /// target of another pair.
+    pub fn extra_fds(&self) -> &[(RawFd, FdSource)] {
+        &self.extra_fds
+    }
+
+    pub fn set_extra_fds(&mut self, extra_fds: Vec<(RawFd, FdSource)>) {
+        self.extra_fds = extra_fds;
+    }
+
+    /// The file descriptor of the command that is one end of a Unix socket pair, whose other
+    /// end the caller gets from `JailHandle::channel`, none by default. Under a seccomp policy
+    /// sending and receiving on it are allowed whatever the policy says.
+    pub fn socket_channel(&self) -> Option<RawFd> {
+        self.socket_channel
+    }
+
+    pub fn set_socket_channel(&mut self, socket_channel: Option<RawFd>) {
+        self.socket_channel = socket_channel;
+    }
+
+    /// How long opening a FIFO to redirect a stream to waits for the other end to be opened,
+    /// failing with `FFIError::RedirectTimeout` past it (5 seconds by default). The wall time
+    /// limit only starts once the command does, so without it a peer that never comes would
+    /// keep the run waiting forever, as it does with `None`.
+    pub fn fifo_open_timeout(&self) -> Option<Duration> {
+        self.fifo_open_timeout
+    }
+
+    pub fn set_fifo_open_timeout(&mut self, fifo_open_timeout: Option<Duration>) {
+        self.fifo_open_timeout = fifo_open_timeout;
+    }
+
+    /// Whether the file descriptors the caller had open without close-on-exec (log files,
+    /// sockets) are kept from the command, `CloseFds::Yes` by default
+    pub fn close_fds(&self) -> CloseFds {
+        self.close_fds
+    }
+
+    pub fn set_close_fds(&mut self, close_fds: CloseFds) {
+        self.close_fds = close_fds;
+    }
+
+    /// Limits shared by all the instances under the parent of the instance name, e.g. the
+    /// `batch42` cgroups for an instance named `batch42/run3`. Only the memory (plus cache)
+    /// and pids limits apply.
+    ///
+    /// They are written only by the run that creates the parent cgroups, runs finding them
+    /// already there leave them as they are. The parent is set up under a temporary name and
+    /// renamed into place, so concurrent runs never enter a parent without its limits. Removing
+    /// the instance cgroups leaves the parent (and its limits) in place for the other instances.
+    pub fn parent_limits(&self) -> Option<&Limits> {
+        self.parent_limits.as_ref()
+    }
+
+    pub fn set_parent_limits(&mut self, parent_limits: Option<Limits>) {
+        self.parent_limits = parent_limits;
+    }
+
+    /// How long a command exceeding a limit (or cancelled with `JailHandle::kill`) gets between
+    /// SIGTERM and SIGKILL, to flush its output or clean up. The verdict is the exceeded limit
+    /// (or `RunInfoResult::Cancelled`) whatever it does in the meantime, and the usage is the one
+    /// at the time the limit was exceeded. Killed right away if `None`.
+    pub fn kill_grace_period(&self) -> Option<Duration> {
+        self.kill_grace_period
+    }
+
+    pub fn set_kill_grace_period(&mut self, kill_grace_period: Option<Duration>) {
+        self.kill_grace_period = kill_grace_period;
+    }
+
+    /// What counts toward the memory limit. Under `RssOnly` the page cache can also take as much
+    /// memory as the limit (unless there is a cache limit), so the kernel does not reclaim it
+    /// as soon as the program is close to the limit.
+    pub fn memory_accounting(&self) -> MemoryAccounting {
+        self.memory_accounting
+    }
+
+    pub fn set_memory_accounting(&mut self, memory_accounting: MemoryAccounting) {
+        self.memory_accounting = memory_accounting;
+    }
+
+    /// Whether to share the IPC namespace (System V shared memory, semaphores and message
+    /// queues, POSIX message queues) with the host. Unshared, every run starts with a fresh
+    /// one, so nothing leaks from one run into the next and the host limits (shmmax, ...)
+    /// can not be exhausted.
+    pub fn share_ipc(&self) -> ShareIpc {
+        self.share_ipc
+    }
+
+    pub fn set_share_ipc(&mut self, share_ipc: ShareIpc) {
+        self.share_ipc = share_ipc;
+    }
+
+    /// The (uid, gid) the command runs as inside the sandbox, root if `None`. The mounts and
+    /// the `pivot_root` are still done as root, the ids change right before the exec. With a
+    /// user namespace the calling user is mapped to them, otherwise they are switched to (which
+    /// needs the privileges to do so).
+    pub fn sandbox_user(&self) -> Option<(u32, u32)> {
+        self.sandbox_user
+    }
+
+    pub fn set_sandbox_user(&mut self, sandbox_user: Option<(u32, u32)>) {
+        self.sandbox_user = sandbox_user;
+    }
+
+    /// Whether the command runs in the pid namespace of the supervisor instead of being the
+    /// init of its own one, which makes it easier to find and trace from outside. It isolates
+    /// less: the command sees the supervisor in /proc and can signal it, and processes leaving
+    /// its process group outlive it until they are killed through the pids cgroup once the
+    /// run is over. After a `pivot_root` /proc is bound from the old root, a new one can only
+    /// be mounted for a pid namespace of its own.
+    pub fn share_pid(&self) -> SharePid {
+        self.share_pid
+    }
+
+    pub fn set_share_pid(&mut self, share_pid: SharePid) {
+        self.share_pid = share_pid;
+    }
+
+    /// A veth pair between the host and the network namespace of the sandbox, set up by the
+    /// sandbox itself before the exec. Needs the network namespace to be unshared, and
+    /// creating the host end needs `CAP_NET_ADMIN` in the host network namespace, which a new
+    /// user namespace does not give (so `CloneUser::No`, as root).
+    pub fn veth(&self) -> Option<&VethConfig> {
+        self.veth.as_ref()
+    }
+
+    pub fn set_veth(&mut self, veth: Option<VethConfig>) {
+        self.veth = veth;
+    }
+
+    /// Existing namespaces the sandbox joins instead of its own ones, given by their
+    /// `/proc/<pid>/ns/<kind>` files or bind mounts of them (such as the ones of
+    /// `ip netns`). Only net, ipc and uts namespaces can be joined. The files are opened
+    /// before anything is cloned, so `/proc/<pid>` paths are the ones of the caller. Joining
+    /// namespaces the user namespace of the sandbox does not own needs `CloneUser::No`.
+    pub fn join_namespaces(&self) -> &[(NamespaceKind, PathBuf)] {
+        &self.join_namespaces
+    }
+
+    pub fn set_join_namespaces(&mut self, join_namespaces: Vec<(NamespaceKind, PathBuf)>) {
+        self.join_namespaces = join_namespaces;
+    }
+
+    /// The propagation the whole mount namespace of the sandbox is remounted with before the
+    /// mounts are made, private by default. `pivot_root` does not work with a shared root, so
+    /// `Propagation::Shared` can not be used together with a new root.
+    pub fn propagation(&self) -> Propagation {
+        self.propagation
+    }
+
+    pub fn set_propagation(&mut self, propagation: Propagation) {
+        self.propagation = propagation;
+    }
+
+    /// How the sandbox is isolated with `CloneUser::Yes`, picked from whether user namespaces
+    /// can be used by default. Only ever set to test one of them.
+    pub fn isolation_mode(&self) -> IsolationMode {
+        self.isolation_mode
+    }
+
+    pub fn set_isolation_mode(&mut self, isolation_mode: IsolationMode) {
+        self.isolation_mode = isolation_mode;
+    }
+
+    /// The capabilities the command keeps, all the others are dropped from every capability
+    /// set (bounding included) before the exec, which also sets `no_new_privs`. None by
+    /// default. Inside a user namespace they only give power over its namespaces.
+    pub fn keep_capabilities(&self) -> &[Capability] {
+        &self.keep_capabilities
+    }
+
+    pub fn set_keep_capabilities(&mut self, keep_capabilities: Vec<Capability>) {
+        self.keep_capabilities = keep_capabilities;
+    }
+
+    /// The syscall allowlist the command runs under, none by default
+    pub fn seccomp(&self) -> Option<&SeccompPolicy> {
+        self.seccomp.as_ref()
+    }
+
+    pub fn set_seccomp(&mut self, seccomp: Option<SeccompPolicy>) {
+        self.seccomp = seccomp;
+    }
+
+    /// Answers the syscalls the seccomp policy notifies about, see `SeccompPolicy::set_notify`
+    pub fn seccomp_notify_handler(&self) -> Option<&SeccompNotifyHandler> {
+        self.seccomp_notify_handler.as_ref()
+    }
+
+    pub fn set_seccomp_notify_handler(
+        &mut self,
+        seccomp_notify_handler: Option<SeccompNotifyHandler>,
+    ) {
+        self.seccomp_notify_handler = seccomp_notify_handler;
+    }
+
+    /// Gets the output of the streams redirected to `Redirect::Tee`, none by default
+    pub fn on_output(&self) -> Option<&OutputCallback> {
+        self.on_output.as_ref()
+    }
+
+    pub fn set_on_output(&mut self, on_output: Option<OutputCallback>) {
+        self.on_output = on_output;
+    }
+
+    /// Gets the usage of the run as it goes, none by default
+    pub fn on_usage(&self) -> Option<&UsageCallback> {
+        self.on_usage.as_ref()
+    }
+
+    pub fn set_on_usage(&mut self, on_usage: Option<UsageCallback>) {
+        self.on_usage = on_usage;
+    }
+
+    /// The filesystem access the command is restricted to, unrestricted by default
+    pub fn landlock(&self) -> Option<&LandlockPolicy> {
+        self.landlock.as_ref()
+    }
+
+    pub fn set_landlock(&mut self, landlock: Option<LandlockPolicy>) {
+        self.landlock = landlock;
+    }
+
+    /// Whether the command may not exec anything else, `SingleExec::No` by default. Enforced
+    /// with a seccomp filter, together with the one of `seccomp` if any.
+    pub fn single_exec(&self) -> SingleExec {
+        self.single_exec
+    }
+
+    pub fn set_single_exec(&mut self, single_exec: SingleExec) {
+        self.single_exec = single_exec;
+    }
+
+    /// The file the syscalls of the command are logged to, with their return values and when
+    /// they were made, if any. The command is traced with ptrace for it: it runs a lot slower,
+    /// so the time limits are not to be relied on, and it can not be stopped meanwhile.
+    pub fn trace_syscalls(&self) -> Option<&Path> {
+        self.trace_syscalls.as_deref()
+    }
+
+    pub fn set_trace_syscalls(&mut self, trace_syscalls: Option<PathBuf>) {
+        self.trace_syscalls = trace_syscalls;
+    }
+
+    /// Whether the command may not create namespaces, `DenyNestedNamespaces::Yes` by default.
+    /// Enforced with a seccomp filter, ahead of the one of `seccomp` if any, on the
+    /// architectures seccomp policies are supported on.
+    pub fn deny_nested_namespaces(&self) -> DenyNestedNamespaces {
+        self.deny_nested_namespaces
+    }
+
+    pub fn set_deny_nested_namespaces(&mut self, deny_nested_namespaces: DenyNestedNamespaces) {
+        self.deny_nested_namespaces = deny_nested_namespaces;
+    }
+
+    /// Whether the command gets a pseudo terminal, `AllocatePty::No` by default. The
+    /// redirections still apply on top of it.
+    pub fn allocate_pty(&self) -> AllocatePty {
+        self.allocate_pty
+    }
+
+    pub fn set_allocate_pty(&mut self, allocate_pty: AllocatePty) {
+        self.allocate_pty = allocate_pty;
+    }
+
+    /// Validates the options and checks the paths they name against the filesystem, so a
+    /// mistake is reported with the path at fault instead of failing inside the sandbox: the
+    /// mount sources exist (and are directories when the destination is one), the new root
+    /// has what the sandbox needs, the directories of the redirects exist and the command is
+    /// an executable file where it can be told from outside. All the problems found are
+    /// returned, the first invalid option among them.
+    pub fn validate(&self) -> Result<(), Vec<ConfigError>> {
+        let mut errors: Vec<_> = self.validate_options().err().into_iter().collect();
+        errors.extend(self.check_paths());
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Validates the limits, the instance name, and that stdin is not redirected from the same
+    /// file as an output (through symlinks and hard links as well) unless allowed to
+    fn validate_options(&self) -> Result<(), ConfigError> {
+        self.limits.validate()?;
+
+        // Instance names are joined to the controller paths, so they must stay under them
+        if let Some(instance_name) = self.instance_name() {
+            let path = Path::new(instance_name);
+            if path.components().next().is_none()
+                || path
+                    .components()
+                    .any(|component| !matches!(component, Component::Normal(_)))
+            {
+                return Err(ConfigError::InvalidInstanceName(
+                    instance_name.to_os_string(),
+                ));
+            }
+        }
+
+        if let Some(parent_limits) = self.parent_limits() {
+            parent_limits.validate()?;
+            let has_parent = self.instance_name().map_or(false, |instance_name| {
+                Path::new(instance_name).components().count() > 1
+            });
+            if !has_parent {
+                return Err(ConfigError::ParentLimitsWithoutParent);
+            }
+        }
+
+        for (index, &(kind, _)) in self.join_namespaces.iter().enumerate() {
+            if let Some(conflict) = kind.join_conflict() {
+                return Err(ConfigError::CanNotJoinNamespace {
+                    kind: kind.to_string(),
+                    reason: conflict.to_owned(),
+                });
+            }
+            if self.join_namespaces[..index]
+                .iter()
+                .any(|&(other_kind, _)| other_kind == kind)
+            {
+                return Err(ConfigError::CanNotJoinNamespace {
+                    kind: kind.to_string(),
+                    reason: "it is given more than once".to_owned(),
+                });
+            }
+        }
+
+        if let Some(overlay) = self.new_root_overlay() {
+            overlay.validate()?;
+            if self.new_root.is_none() {
+                return Err(ConfigError::OverlayWithoutNewRoot);
+            }
+        }
+        if self.root_image.is_some() {
+            if self.new_root.is_none() {
+                return Err(ConfigError::RootImageWithoutNewRoot);
+            }
+            if self.new_root_overlay.is_some() {
+                return Err(ConfigError::RootImageWithOverlay);
+            }
+        }
+        for mount in &self.mounts {
+            if let MountKind::Overlay { ref lower, .. } = *mount.kind() {
+                if lower.is_empty() {
+                    return Err(ConfigError::OverlayWithoutLower);
+                }
+            }
+        }
+
+        if self.propagation == Propagation::Shared && self.new_root.is_some() {
+            return Err(ConfigError::SharedPropagationWithNewRoot);
+        }
+
+        if let Some(seccomp) = self.seccomp() {
+            seccomp.validate()?;
+            if !seccomp.notify().is_empty() && self.seccomp_notify_handler.is_none() {
+                return Err(ConfigError::SeccompNotifyWithoutHandler);
+            }
+        }
+
+        if let Some(landlock) = self.landlock() {
+            landlock.validate()?;
+        }
+
+        if self.limits.net_bytes().is_some()
+            && (self.share_net == ShareNet::Share
+                || self
+                    .join_namespaces
+                    .iter()
+                    .any(|&(kind, _)| kind == NamespaceKind::Net))
+        {
+            return Err(ConfigError::NetBytesWithSharedNet);
+        }
+
+        if let Some(veth) = self.veth() {
+            veth.validate()?;
+            if self.share_net == ShareNet::Share {
+                return Err(ConfigError::VethWithSharedNet);
+            }
+        }
+
+        match *self.redirect_stdin() {
+            Redirect::Capture { .. } | Redirect::Tee { .. } => {
+                return Err(ConfigError::CapturedStdin)
+            }
+            _ => {}
+        }
+        for (name, redirect) in &[
+            ("stdin", self.redirect_stdin()),
+            ("stdout", self.redirect_stdout()),
+        ] {
+            if **redirect == Redirect::ToStdout {
+                return Err(ConfigError::ToStdoutNotStderr((*name).to_owned()));
+            }
+        }
+        for (name, redirect) in &[
+            ("stdout", self.redirect_stdout()),
+            ("stderr", self.redirect_stderr()),
+        ] {
+            if let Redirect::Bytes(_) = **redirect {
+                return Err(ConfigError::BytesNotStdin((*name).to_owned()));
+            }
+        }
+
+        for (index, &(fd, ref source)) in self.extra_fds.iter().enumerate() {
+            let reason = if fd <= libc::STDERR_FILENO {
+                Some("the standard streams are redirected instead")
+            } else if self.extra_fds[..index]
+                .iter()
+                .any(|&(other_fd, _)| other_fd == fd)
+            {
+                Some("it is given more than once")
+            } else {
+                match *source {
+                    FdSource::Fd(source_fd)
+                        if unsafe { libc::fcntl(source_fd, libc::F_GETFD) } == -1 =>
+                    {
+                        Some("its source is not an open file descriptor")
+                    }
+                    FdSource::Fd(_) | FdSource::Path { .. } => None,
+                }
+            };
+            if let Some(reason) = reason {
+                return Err(ConfigError::InvalidExtraFd {
+                    fd,
+                    reason: reason.to_owned(),
+                });
+            }
+        }
+        if let Some(fd) = self.socket_channel {
+            let reason = if fd <= libc::STDERR_FILENO {
+                Some("the standard streams are redirected instead")
+            } else if self.extra_fds.iter().any(|&(other_fd, _)| other_fd == fd) {
+                Some("it is the socket channel as well")
+            } else {
+                None
+            };
+            if let Some(reason) = reason {
+                return Err(ConfigError::InvalidExtraFd {
+                    fd,
+                    reason: reason.to_owned(),
+                });
+            }
+        }
+
+        if self.allow_same_file_io == AllowSameFileIo::No {
+            if let Some(stdin) = host_file(self.redirect_stdin()) {
+                let output = host_file(self.redirect_stdout())
+                    .into_iter()
+                    .chain(host_file(self.redirect_stderr()))
+                    .find(|output| is_same_regular_file(stdin, output));
+                if let Some(output) = output {
+                    return Err(ConfigError::SameFileIo {
+                        input: stdin.to_path_buf(),
+                        output: output.to_path_buf(),
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn check_paths(&self) -> Vec<ConfigError> {
+        let mut errors = Vec::new();
+        let mut check_source = |source: &Path, destination: &Path| {
+            if !source.exists() {
+                errors.push(ConfigError::MountSourceNotFound {
+                    source: source.to_path_buf(),
+                    destination: destination.to_path_buf(),
+                });
+            }
+        };
+        match self.root_image {
+            Some(RootImage::Squashfs(ref image)) | Some(RootImage::Tarball(ref image)) => {
+                check_source(image, Path::new("/"))
+            }
+            None => {}
+        }
+        if let Some(overlay) = self.new_root_overlay() {
+            for dir in overlay
+                .lower()
+                .iter()
+                .chain(vec![&overlay.upper, &overlay.work])
+            {
+                check_source(dir, Path::new("/"));
+            }
+        }
+        for mount in &self.mounts {
+            match *mount.kind() {
+                MountKind::Bind { ref source } => check_source(source, mount.destination()),
+                MountKind::Overlay {
+                    ref lower,
+                    ref upper,
+                    ref work,
+                } => {
+                    for dir in lower.iter().chain(vec![upper, work]) {
+                        check_source(dir, mount.destination());
+                    }
+                }
+                MountKind::Tmpfs { .. } => {}
+            }
+        }
+
+        if let Some(new_root) = self.new_root() {
+            errors.extend(self.check_new_root(new_root));
+        } else {
+            let command = match self.working_directory() {
+                Some(working_directory) => working_directory.join(&self.command),
+                None => self.command.clone(),
+            };
+            errors.extend(self.check_command(command));
+        }
+
+        for redirect in host_file(self.redirect_stdin())
+            .into_iter()
+            .chain(host_file(self.redirect_stdout()))
+            .chain(host_file(self.redirect_stderr()))
+        {
+            let dir = redirect.parent().unwrap_or_else(|| Path::new(""));
+            if !dir.as_os_str().is_empty() && !dir.is_dir() {
+                errors.push(ConfigError::RedirectDirNotFound(redirect.to_path_buf()));
+            }
+        }
+        errors
+    }
+
+    fn check_new_root(&self, new_root: &Path) -> Vec<ConfigError> {
+        if !new_root.is_dir() {
+            return vec![ConfigError::NewRootNotFound(new_root.to_path_buf())];
+        }
+        // What is in the directory is covered up
+        if self.root_image.is_some() || self.new_root_overlay.is_some() {
+            return Vec::new();
+        }
+
+        let mut errors = Vec::new();
+        let mut missing_dirs = ["proc", ".old_root"]
+            .iter()
+            .map(Path::new)
+            .filter(|dir| !new_root.join(dir).exists())
+            .peekable();
+        if missing_dirs.peek().is_some() && is_on_read_only_filesystem(new_root) {
+            errors.extend(missing_dirs.map(|dir| ConfigError::NewRootMissingDir {
+                new_root: new_root.to_path_buf(),
+                dir: dir.to_path_buf(),
+            }));
+        }
+
+        // Mounted or copied over, or only known once the sandbox is set up
+        let command = match self.working_directory() {
+            Some(working_directory) => Path::new("/").join(working_directory).join(&self.command),
+            None => Path::new("/").join(&self.command),
+        };
+        let covered = self
+            .mounts
+            .iter()
+            .map(Mount::destination)
+            .chain(
+                self.copy_in
+                    .iter()
+                    .map(|(_, destination)| destination.as_path()),
+            )
+            .any(|destination| command.starts_with(Path::new("/").join(destination)));
+        if !covered {
+            if let Some(command) = path_in_new_root(new_root, &command) {
+                errors.extend(self.check_command(command));
+            }
+        }
+
+        for mount in &self.mounts {
+            let source = match *mount.kind() {
+                MountKind::Bind { ref source } => source,
+                MountKind::Tmpfs { .. } | MountKind::Overlay { .. } => continue,
+            };
+            let destination = match path_in_new_root(new_root, mount.destination()) {
+                Some(destination) => destination,
+                None => continue,
+            };
+            if let (Ok(source_metadata), Ok(destination_metadata)) =
+                (fs::metadata(source), fs::symlink_metadata(&destination))
+            {
+                if source_metadata.is_dir() != destination_metadata.is_dir() {
+                    errors.push(ConfigError::MountSourceTypeMismatch {
+                        source: source.clone(),
+                        destination: mount.destination().to_path_buf(),
+                    });
+                }
+            }
+        }
+        errors
+    }
+
+    // The path is reported as the command was given
+    fn check_command(&self, path: PathBuf) -> Option<ConfigError> {
+        match fs::metadata(&path) {
+            Ok(metadata) => {
+                if metadata.is_file() && metadata.mode() & 0o111 != 0 {
+                    None
+                } else {
+                    Some(ConfigError::CommandNotExecutable(self.command.clone()))
+                }
+            }
+            Err(_) => Some(ConfigError::CommandNotFound(self.command.clone())),
+        }
+    }
+}
+
+/// Builds a `Config` in one expression, see `Config::builder`. The setters are named after the
+/// getters of `Config` documenting the options, the ones of a list (`arg`, `mount`, ...) add to
+/// it.
+#[derive(Debug, Clone)]
+pub struct ConfigBuilder {
+    config: Config,
+}
+
+impl ConfigBuilder {
+    pub fn command<T: AsRef<Path>>(mut self, command: T) -> Self {
+        self.config.command = command.as_ref().to_path_buf();
+        self
+    }
+
+    pub fn arg<T: AsRef<OsStr>>(mut self, arg: T) -> Self {
+        self.config.args.push(arg.as_ref().to_os_string());
+        self
+    }
+
+    pub fn args<I, T>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: AsRef<OsStr>,
+    {
+        for arg in args {
+            self = self.arg(arg);
+        }
+        self
+    }
+
+    pub fn new_root<T: AsRef<Path>>(mut self, new_root: T) -> Self {
+        self.config.new_root = Some(new_root.as_ref().to_path_buf());
+        self
+    }
+
+    pub fn new_root_overlay(mut self, new_root_overlay: Overlay) -> Self {
+        self.config.new_root_overlay = Some(new_root_overlay);
+        self
+    }
+
+    pub fn root_image(mut self, root_image: RootImage) -> Self {
+        self.config.root_image = Some(root_image);
+        self
+    }
+
+    pub fn working_directory<T: AsRef<Path>>(mut self, working_directory: T) -> Self {
+        self.config.working_directory = Some(working_directory.as_ref().to_path_buf());
+        self
+    }
+
+    pub fn copy_in<T: AsRef<Path>, U: AsRef<Path>>(mut self, source: T, destination: U) -> Self {
+        self.config.copy_in.push((
+            source.as_ref().to_path_buf(),
+            destination.as_ref().to_path_buf(),
+        ));
+        self
+    }
+
+    pub fn copy_out<T: AsRef<Path>, U: AsRef<Path>>(mut self, source: T, destination: U) -> Self {
+        self.config.copy_out.push((
+            source.as_ref().to_path_buf(),
+            destination.as_ref().to_path_buf(),
+        ));
+        self
+    }
+
+    pub fn copy_out_limit(mut self, copy_out_limit: SpaceUsage) -> Self {
+        self.config.copy_out_limit = Some(copy_out_limit);
+        self
+    }
+
+    pub fn share_net(mut self, share_net: ShareNet) -> Self {
+        self.config.share_net = share_net;
+        self
+    }
+
+    pub fn redirect_stdin(mut self, redirect_stdin: Redirect) -> Self {
+        self.config.redirect_stdin = redirect_stdin;
+        self
+    }
+
+    pub fn redirect_stdout(mut self, redirect_stdout: Redirect) -> Self {
+        self.config.redirect_stdout = redirect_stdout;
+        self
+    }
+
+    pub fn redirect_stderr(mut self, redirect_stderr: Redirect) -> Self {
+        self.config.redirect_stderr = redirect_stderr;
+        self
+    }
+
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.config.limits = limits;
+        self
+    }
+
+    pub fn instance_name<T: AsRef<OsStr>>(mut self, instance_name: T) -> Self {
+        self.config.instance_name = Some(instance_name.as_ref().to_os_string());
+        self
+    }
+
+    pub fn controller_path(mut self, controller_path: ControllerPath) -> Self {
+        self.config.controller_path = controller_path;
+        self
+    }
+
+    pub fn mount(mut self, mount: Mount) -> Self {
+        self.config.mounts.push(mount);
+        self
+    }
+
+    pub fn swap_redirects(mut self, swap_redirects: SwapRedirects) -> Self {
+        self.config.swap_redirects = swap_redirects;
+        self
+    }
+
+    pub fn clear_usage(mut self, clear_usage: ClearUsage) -> Self {
+        self.config.clear_usage = clear_usage;
+        self
+    }
+
+    pub fn interactive(mut self, interactive: Interactive) -> Self {
+        self.config.interactive = interactive;
+        self
+    }
+
+    pub fn clone_user(mut self, clone_user: CloneUser) -> Self {
+        self.config.clone_user = clone_user;
+        self
+    }
+
+    pub fn isolated_cgroup(mut self, isolated_cgroup: IsolatedCgroup) -> Self {
+        self.config.isolated_cgroup = isolated_cgroup;
+        self
+    }
+
+    pub fn environment(mut self, environment: Environment) -> Self {
+        self.config.environment = environment;
+        self
+    }
+
+    pub fn exclude_stopped_time(mut self, exclude_stopped_time: ExcludeStoppedTime) -> Self {
+        self.config.exclude_stopped_time = exclude_stopped_time;
+        self
+    }
+
+    pub fn pausable(mut self, pausable: Pausable) -> Self {
+        self.config.pausable = pausable;
+        self
+    }
+
+    pub fn mark_inside_sandbox(mut self, mark_inside_sandbox: MarkInsideSandbox) -> Self {
+        self.config.mark_inside_sandbox = mark_inside_sandbox;
+        self
+    }
+
+    /// Detaches the run with its result written to `result_file`, see `Config::detached`
+    pub fn detached<T: AsRef<Path>>(mut self, result_file: T) -> Self {
+        self.config.detached = Some(result_file.as_ref().to_path_buf());
+        self
+    }
+
+    pub fn mount_binfmt_interpreter(
+        mut self,
+        mount_binfmt_interpreter: MountBinfmtInterpreter,
+    ) -> Self {
+        self.config.mount_binfmt_interpreter = mount_binfmt_interpreter;
+        self
+    }
+
+    pub fn mount_cgroups(mut self, mount_cgroups: MountCgroups) -> Self {
+        self.config.mount_cgroups = mount_cgroups;
+        self
+    }
+
+    pub fn minimal_dev(mut self, minimal_dev: MinimalDev) -> Self {
+        self.config.minimal_dev = minimal_dev;
+        self
+    }
+
+    pub fn private_tmp(mut self, private_tmp: PrivateTmp) -> Self {
+        self.config.private_tmp = private_tmp;
+        self
+    }
+
+    pub fn private_tmp_size(mut self, private_tmp_size: SpaceUsage) -> Self {
+        self.config.private_tmp_size = private_tmp_size;
+        self
+    }
+
+    pub fn stderr_tail(mut self, stderr_tail: SpaceUsage) -> Self {
+        self.config.stderr_tail = stderr_tail;
+        self
+    }
+
+    pub fn read_only_root(mut self, read_only_root: ReadOnlyRoot) -> Self {
+        self.config.read_only_root = read_only_root;
+        self
+    }
+
+    pub fn proc_options(mut self, proc_options: ProcOptions) -> Self {
+        self.config.proc_options = proc_options;
+        self
+    }
+
+    pub fn disable_swap(mut self, disable_swap: DisableSwap) -> Self {
+        self.config.disable_swap = disable_swap;
+        self
+    }
+
+    pub fn allow_same_file_io(mut self, allow_same_file_io: AllowSameFileIo) -> Self {
+        self.config.allow_same_file_io = allow_same_file_io;
+        self
+    }
+
+    pub fn extra_fd(mut self, fd: RawFd, source: FdSource) -> Self {
+        self.config.extra_fds.push((fd, source));
+        self
+    }
+
+    pub fn socket_channel(mut self, fd: RawFd) -> Self {
+        self.config.socket_channel = Some(fd);
+        self
+    }
+
+    /// `None` waits for the other end of a FIFO as long as it takes
+    pub fn fifo_open_timeout(mut self, fifo_open_timeout: Option<Duration>) -> Self {
+        self.config.fifo_open_timeout = fifo_open_timeout;
+        self
+    }
+
+    pub fn close_fds(mut self, close_fds: CloseFds) -> Self {
+        self.config.close_fds = close_fds;
+        self
+    }
+
+    pub fn parent_limits(mut self, parent_limits: Limits) -> Self {
+        self.config.parent_limits = Some(parent_limits);
+        self
+    }
+
+    pub fn kill_grace_period(mut self, kill_grace_period: Duration) -> Self {
+        self.config.kill_grace_period = Some(kill_grace_period);
+        self
+    }
+
+    pub fn memory_accounting(mut self, memory_accounting: MemoryAccounting) -> Self {
+        self.config.memory_accounting = memory_accounting;
+        self
+    }
+
+    pub fn share_ipc(mut self, share_ipc: ShareIpc) -> Self {
+        self.config.share_ipc = share_ipc;
+        self
+    }
+
+    pub fn sandbox_user(mut self, uid: u32, gid: u32) -> Self {
+        self.config.sandbox_user = Some((uid, gid));
+        self
+    }
+
+    pub fn share_pid(mut self, share_pid: SharePid) -> Self {
+        self.config.share_pid = share_pid;
+        self
+    }
+
+    pub fn veth(mut self, veth: VethConfig) -> Self {
+        self.config.veth = Some(veth);
+        self
+    }
+
+    pub fn join_namespace<T: AsRef<Path>>(mut self, kind: NamespaceKind, path: T) -> Self {
+        self.config
+            .join_namespaces
+            .push((kind, path.as_ref().to_path_buf()));
+        self
+    }
+
+    pub fn propagation(mut self, propagation: Propagation) -> Self {
+        self.config.propagation = propagation;
+        self
+    }
+
+    pub fn isolation_mode(mut self, isolation_mode: IsolationMode) -> Self {
+        self.config.isolation_mode = isolation_mode;
+        self
+    }
+
+    pub fn keep_capability(mut self, capability: Capability) -> Self {
+        self.config.keep_capabilities.push(capability);
+        self
+    }
+
+    pub fn seccomp(mut self, seccomp: SeccompPolicy) -> Self {
+        self.config.seccomp = Some(seccomp);
+        self
+    }
+
+    pub fn seccomp_notify_handler(mut self, seccomp_notify_handler: SeccompNotifyHandler) -> Self {
+        self.config.seccomp_notify_handler = Some(seccomp_notify_handler);
+        self
+    }
+
+    pub fn on_output(mut self, on_output: OutputCallback) -> Self {
+        self.config.on_output = Some(on_output);
+        self
+    }
+
+    pub fn on_usage(mut self, on_usage: UsageCallback) -> Self {
+        self.config.on_usage = Some(on_usage);
+        self
+    }
+
+    pub fn landlock(mut self, landlock: LandlockPolicy) -> Self {
+        self.config.landlock = Some(landlock);
+        self
+    }
+
+    pub fn single_exec(mut self, single_exec: SingleExec) -> Self {
+        self.config.single_exec = single_exec;
+        self
+    }
+
+    pub fn trace_syscalls<T: AsRef<Path>>(mut self, trace_syscalls: T) -> Self {
+        self.config.trace_syscalls = Some(trace_syscalls.as_ref().to_path_buf());
+        self
+    }
+
+    pub fn deny_nested_namespaces(mut self, deny_nested_namespaces: DenyNestedNamespaces) -> Self {
+        self.config.deny_nested_namespaces = deny_nested_namespaces;
+        self
+    }
+
+    pub fn allocate_pty(mut self, allocate_pty: AllocatePty) -> Self {
+        self.config.allocate_pty = allocate_pty;
+        self
+    }
+
+    /// The `Config` once `Config::validate` finds nothing wrong with it
+    pub fn build(self) -> Result<Config, ConfigErrors> {
+        self.config.validate().map_err(ConfigErrors::new)?;
+        Ok(self.config)
+    }
+}
+
+// Where `path` inside the new root is on the host, when no symlink is on the way: those
+// resolve differently once the new root is the root
+fn path_in_new_root(new_root: &Path, path: &Path) -> Option<PathBuf> {
+    let mut host_path = new_root.to_path_buf();
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => host_path.push(name),
+            Component::RootDir | Component::CurDir => continue,
+            Component::Prefix(_) | Component::ParentDir => return None,
+        }
+        match fs::symlink_metadata(&host_path) {
+            Ok(ref metadata) if metadata.file_type().is_symlink() => return None,
+            Ok(_) => {}
+            // Missing from here on
+            Err(_) => break,
+        }
+    }
+    Some(host_path)
+}
+
+fn is_on_read_only_filesystem(path: &Path) -> bool {
+    let (path, mount_info) = match (fs::canonicalize(path), MountInfo::read()) {
+        (Ok(path), Ok(mount_info)) => (path, mount_info),
+        _ => return false,
+    };
+    match mount_info.find_mount(&path) {
+        Some(entry) => entry.has_option("ro"),
+        None => false,
+    }
+}
+
+// The file of a redirect if it is one on the host, those inside the sandbox are not there yet
+fn host_file(redirect: &Redirect) -> Option<&Path> {
+    if redirect.in_sandbox() {
+        None
+    } else {
+        redirect.file()
+    }
+}
+
+// Devices such as /dev/null are fine to use for both, only regular files get truncated
+fn is_same_regular_file(first: &Path, second: &Path) -> bool {
+    match (fs::metadata(first), fs::metadata(second)) {
+        (Ok(first), Ok(second)) => {
+            first.is_file() && first.dev() == second.dev() && first.ino() == second.ino()
+        }
+        _ => false,
     }
 }
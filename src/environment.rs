@@ -0,0 +1,310 @@
+//! Checks for the kernel and system features the sandbox needs, which otherwise only show up as
+//! an errno from deep inside the clone
+
+use std::env;
+use std::fmt::{self, Display, Formatter};
+use std::fs;
+use std::process;
+use std::result::Result as StdResult;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use cgroups::{self, CgroupProbe};
+use config::{ControllerPath, NamespaceKind};
+use ffi;
+use version_info::{VersionInfo, VERSION_INFO};
+
+/// A feature `check_environment` probes for
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum Feature {
+    /// Making a new namespace of this kind (inside a new user namespace unless running as root)
+    Namespace(NamespaceKind),
+    /// Moving into a new root with `pivot_root`
+    PivotRoot,
+    /// A cgroup controller the sandbox always uses existing and being writable
+    CgroupController(String),
+    /// Restricting the filesystem access with Landlock, for `Config::set_landlock` only
+    Landlock,
+}
+
+impl Feature {
+    /// What to do about the feature missing
+    pub fn hint(&self) -> String {
+        match self {
+            Self::Namespace(NamespaceKind::User) => {
+                "Unprivileged user namespaces are disabled or restricted. Enable them with \
+                 `sysctl -w kernel.unprivileged_userns_clone=1` (Debian) and a nonzero \
+                 `user.max_user_namespaces`, lift `kernel.apparmor_restrict_unprivileged_userns` \
+                 (Ubuntu), or run as root, which falls back to the root isolation mode."
+                    .to_owned()
+            }
+            Self::Namespace(kind) => format!(
+                "The kernel needs {} namespaces (CONFIG_{}_NS), and seccomp profiles of \
+                 containers (such as the default one of Docker) block unshare.",
+                kind,
+                match kind {
+                    NamespaceKind::Mount => "MNT".to_owned(),
+                    kind => kind.to_string().to_uppercase(),
+                }
+            ),
+            Self::PivotRoot => "pivot_root does not work with the initramfs as the root (some \
+                                containers run from one), leave `Config::new_root` unset there."
+                .to_owned(),
+            Self::CgroupController(controller) => format!(
+                "The cgroups of every run are made under the {} controller path, the default \
+                 one or the one given in `ControllerPath`.",
+                controller
+            ),
+            Self::Landlock => "Landlock needs linux 5.13 or later built with \
+                               CONFIG_SECURITY_LANDLOCK, and `landlock` among the security \
+                               modules enabled with the `lsm=` boot parameter."
+                .to_owned(),
+        }
+    }
+}
+
+impl Display for Feature {
+    fn fmt(&self, fmt: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Namespace(kind) => write!(fmt, "{} namespaces", kind),
+            Self::PivotRoot => write!(fmt, "pivot_root"),
+            Self::CgroupController(controller) => write!(fmt, "{} cgroup controller", controller),
+            Self::Landlock => write!(fmt, "Landlock"),
+        }
+    }
+}
+
+/// Whether a feature works, and why not if it does not
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct FeatureCheck {
+    feature: Feature,
+    error: Option<String>,
+}
+
+impl FeatureCheck {
+    fn new(feature: Feature, result: StdResult<(), String>) -> Self {
+        Self {
+            feature,
+            error: result.err(),
+        }
+    }
+
+    pub fn feature(&self) -> &Feature {
+        &self.feature
+    }
+
+    pub fn passed(&self) -> bool {
+        self.error.is_none()
+    }
+
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    /// What to do about it, for failed checks
+    pub fn hint(&self) -> Option<String> {
+        self.error.as_ref().map(|_| self.feature.hint())
+    }
+}
+
+impl Display for FeatureCheck {
+    fn fmt(&self, fmt: &mut Formatter<'_>) -> fmt::Result {
+        match &self.error {
+            None => write!(fmt, "[ok] {}", self.feature),
+            Some(error) => write!(
+                fmt,
+                "[failed] {}: {}\n    {}",
+                self.feature,
+                error,
+                self.feature.hint()
+            ),
+        }
+    }
+}
+
+/// What `check_environment` found
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct EnvironmentReport(Vec<FeatureCheck>);
+
+impl EnvironmentReport {
+    pub fn checks(&self) -> &[FeatureCheck] {
+        &self.0
+    }
+
+    pub fn check(&self, feature: &Feature) -> Option<&FeatureCheck> {
+        self.0.iter().find(|check| check.feature() == feature)
+    }
+
+    /// Whether every feature works
+    pub fn passed(&self) -> bool {
+        self.0.iter().all(FeatureCheck::passed)
+    }
+
+    pub fn failures(&self) -> Vec<&FeatureCheck> {
+        self.0.iter().filter(|check| !check.passed()).collect()
+    }
+}
+
+impl Display for EnvironmentReport {
+    fn fmt(&self, fmt: &mut Formatter<'_>) -> fmt::Result {
+        for check in &self.0 {
+            writeln!(fmt, "{}", check)?;
+        }
+        Ok(())
+    }
+}
+
+// Scratch directories of concurrent checks in the same process must not collide
+static SCRATCH_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Probes everything the sandbox needs from the kernel and the system: user namespaces (made
+/// and mapped), the other namespaces it unshares, `pivot_root`, and the cgroup controllers it
+/// always uses at their default paths. Each probe runs in a forked process, which takes a few
+/// milliseconds in all.
+pub fn check_environment() -> EnvironmentReport {
+    let mut checks = vec![FeatureCheck::new(
+        Feature::Namespace(NamespaceKind::User),
+        ffi::probe_user_namespace(),
+    )];
+    for &kind in &[
+        NamespaceKind::Mount,
+        NamespaceKind::Pid,
+        NamespaceKind::Uts,
+        NamespaceKind::Ipc,
+        NamespaceKind::Net,
+        NamespaceKind::Cgroup,
+    ] {
+        checks.push(FeatureCheck::new(
+            Feature::Namespace(kind),
+            ffi::probe_namespace(kind),
+        ));
+    }
+
+    let scratch = env::temp_dir().join(format!(
+        "ia-sandbox-probe-{}-{}",
+        process::id(),
+        SCRATCH_COUNTER.fetch_add(1, Ordering::SeqCst)
+    ));
+    let pivot_root = fs::create_dir(&scratch)
+        .map_err(|err| format!("Could not create {:?}: {}", scratch, err))
+        .and_then(|()| {
+            let result = ffi::probe_pivot_root(&scratch);
+            let _ = fs::remove_dir(&scratch);
+            result
+        });
+    checks.push(FeatureCheck::new(Feature::PivotRoot, pivot_root));
+
+    let controllers = ["cpuacct", "memory", "pids"];
+    match cgroups::probe() {
+        Ok(probe) => {
+            let controller_path = probe.fill_controller_path(&ControllerPath::default());
+            let paths = [
+                controller_path.cpuacct(),
+                controller_path.memory(),
+                controller_path.pids(),
+            ];
+            for (&controller, path) in controllers.iter().zip(&paths) {
+                let result = match path {
+                    Some(path) => cgroups::check_controller_access(controller, path)
+                        .map_err(|err| err.to_string()),
+                    None => Err(format!("the {} controller is not mounted", controller)),
+                };
+                checks.push(FeatureCheck::new(
+                    Feature::CgroupController(controller.to_owned()),
+                    result,
+                ));
+            }
+        }
+        Err(err) => {
+            for &controller in &controllers {
+                checks.push(FeatureCheck::new(
+                    Feature::CgroupController(controller.to_owned()),
+                    Err(err.to_string()),
+                ));
+            }
+        }
+    }
+
+    EnvironmentReport(checks)
+}
+
+/// What `capabilities` found: the build that is running and what the host lets it do
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct Capabilities {
+    version_info: VersionInfo,
+    environment: EnvironmentReport,
+    cgroups: StdResult<CgroupProbe, String>,
+}
+
+impl Capabilities {
+    /// `VERSION_INFO`, with the `CLASSIFICATION_VERSION` the results are classified by
+    pub fn version_info(&self) -> &VersionInfo {
+        &self.version_info
+    }
+
+    /// What `check_environment` found
+    pub fn environment(&self) -> &EnvironmentReport {
+        &self.environment
+    }
+
+    /// What `cgroups::probe` found, or why it failed
+    pub fn cgroups(&self) -> StdResult<&CgroupProbe, &str> {
+        self.cgroups.as_ref().map_err(String::as_str)
+    }
+}
+
+impl Display for Capabilities {
+    fn fmt(&self, fmt: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(
+            fmt,
+            "{} (classification version {})",
+            self.version_info,
+            self.version_info.classification_version()
+        )?;
+        write!(fmt, "{}", self.environment)?;
+        match self.cgroups {
+            Ok(ref probe) => write!(fmt, "{}", probe),
+            Err(ref err) => writeln!(fmt, "Could not probe the cgroup controllers: {}", err),
+        }
+    }
+}
+
+/// Everything a worker reports about itself to tell it apart in a fleet: the build (see
+/// `VERSION_INFO`), `check_environment` and `cgroups::probe`
+pub fn capabilities() -> Capabilities {
+    Capabilities {
+        version_info: VERSION_INFO,
+        environment: check_environment(),
+        cgroups: cgroups::probe().map_err(|err| err.to_string()),
+    }
+}
+
+// Only the success is kept, a failure is reported with what went wrong every time
+static USER_NAMESPACES_USABLE: AtomicBool = AtomicBool::new(false);
+
+/// The user namespace probe of `check_environment`, which `spawn_jail` runs before every run
+/// that needs one
+pub(crate) fn check_user_namespaces() -> StdResult<(), FeatureCheck> {
+    if USER_NAMESPACES_USABLE.load(Ordering::SeqCst) {
+        return Ok(());
+    }
+    let check = FeatureCheck::new(
+        Feature::Namespace(NamespaceKind::User),
+        ffi::probe_user_namespace(),
+    );
+    if check.passed() {
+        USER_NAMESPACES_USABLE.store(true, Ordering::SeqCst);
+        Ok(())
+    } else {
+        Err(check)
+    }
+}
+
+/// Whether the kernel has Landlock, which `spawn_jail` checks for runs restricted with it
+pub(crate) fn check_landlock() -> StdResult<(), FeatureCheck> {
+    let check = FeatureCheck::new(Feature::Landlock, ffi::landlock_abi().map(|_| ()));
+    if check.passed() {
+        Ok(())
+    } else {
+        Err(check)
+    }
+}
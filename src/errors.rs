@@ -1,23 +1,48 @@
 use std::ffi::OsString;
+use std::fmt::{self, Display, Formatter};
 use std::path::PathBuf;
 use std::result::Result as StdResult;
 
 #[derive(Fail, Debug, Serialize, Deserialize)]
 pub enum FFIError {
+    #[fail(display = "Could not capture {}: {}", name, error)]
+    CaptureError { name: String, error: String },
     #[fail(display = "Could not chdir to {:?}: {}", path, error)]
     ChdirError { path: PathBuf, error: String },
     #[fail(display = "Could not chroot to {:?}: {}", path, error)]
     ChrootError { path: PathBuf, error: String },
     #[fail(display = "Could not clone process: {}", _0)]
     CloneError(String),
+    #[fail(display = "Could not close the inherited file descriptors: {}", _0)]
+    CloseFdsError(String),
+    #[fail(display = "Could not make the control channel: {}", _0)]
+    ControlChannelError(String),
+    #[fail(display = "Could not drop the capabilities: {}", _0)]
+    DropCapabilitiesError(String),
     #[fail(display = "Could not dup file descriptor {}({}): {}", name, fd, error)]
     DupFdError {
         fd: i32,
         name: String,
         error: String,
     },
+    #[fail(
+        display = "Could not copy {:?} to {:?}: {}",
+        source, destination, error
+    )]
+    CopyError {
+        source: PathBuf,
+        destination: PathBuf,
+        error: String,
+    },
+    #[fail(
+        display = "Could not copy {:?} out of the sandbox, it is larger than {} bytes",
+        source, limit
+    )]
+    CopyOutTooLarge { source: PathBuf, limit: u64 },
     #[fail(display = "Could not create directory {:?}: {}", path, error)]
     CreateDirError { path: PathBuf, error: String },
+    #[fail(display = "Could not use eventfd: {}", _0)]
+    EventFdError(String),
     #[fail(
         display = "Could not exec {:?} (arguments: {:?}): {}",
         command, arguments, error
@@ -27,8 +52,60 @@ pub enum FFIError {
         arguments: Vec<OsString>,
         error: String,
     },
+    #[fail(display = "Could not set up extra file descriptor {}: {}", fd, error)]
+    ExtraFdError { fd: i32, error: String },
+    #[fail(
+        display = "Could not exec {:?}, it is a {} binary and its binfmt_misc interpreter {:?} \
+                   does not exist inside the new root. Mount it (or enable binfmt interpreter \
+                   mounts) or register the interpreter with the F flag.",
+        command, arch, interpreter
+    )]
+    MissingBinfmtInterpreter {
+        command: PathBuf,
+        interpreter: PathBuf,
+        arch: String,
+    },
+    #[fail(display = "Could not use inotify: {}", _0)]
+    InotifyError(String),
+    #[fail(display = "Could not configure network interface {}: {}", name, error)]
+    InterfaceConfigError { name: String, error: String },
+    #[fail(
+        display = "Could not join the {} namespace {:?}: {}",
+        kind, path, error
+    )]
+    JoinNamespaceError {
+        kind: String,
+        path: PathBuf,
+        error: String,
+    },
+    #[fail(display = "Could not ask the supervisor to kill the run: {}", _0)]
+    KillError(String),
+    #[fail(
+        display = "Could not restrict the filesystem access with Landlock: {}",
+        _0
+    )]
+    LandlockError(String),
+    #[fail(display = "Could not set up a loop device for {:?}: {}", path, error)]
+    LoopDeviceError { path: PathBuf, error: String },
+    #[fail(display = "Could not make the memfd of stdin: {}", _0)]
+    MemfdError(String),
     #[fail(display = "Could not mount path: {:?}: {}", path, error)]
     MountError { path: PathBuf, error: String },
+    #[fail(
+        display = "Could not mount an overlay at {:?}: inside a user namespace it needs \
+                   Linux 5.11 or later, this is {}",
+        path, release
+    )]
+    OverlayUnsupported { path: PathBuf, release: String },
+    #[fail(display = "Could not talk to the kernel over rtnetlink: {}", _0)]
+    NetlinkError(String),
+    #[fail(display = "Could not set no_new_privs: {}", _0)]
+    NoNewPrivsError(String),
+    #[fail(
+        display = "{:?} contains a nul byte, which paths and arguments can not hold",
+        _0
+    )]
+    NulByteError(String),
     #[fail(display = "Could not open file descriptor {}({}): {}", name, fd, error)]
     OpenFdError {
         fd: i32,
@@ -46,8 +123,41 @@ pub enum FFIError {
         old_root: PathBuf,
         error: String,
     },
+    #[fail(display = "Could not pause or resume the run: {}", _0)]
+    PauseError(String),
+    #[fail(display = "Could not poll: {}", _0)]
+    PollError(String),
+    #[fail(display = "Could not read binfmt_misc registrations: {}", _0)]
+    ReadBinfmtError(String),
+    #[fail(display = "Could not read /proc/self/mountinfo: {}", _0)]
+    ReadMountInfoError(String),
+    #[fail(display = "Could not read network counters from {:?}: {}", path, error)]
+    ReadNetDevError { path: PathBuf, error: String },
+    #[fail(display = "Could not recover the run: {}", _0)]
+    RecoveryError(String),
+    #[fail(
+        display = "Could not make {} at {:?}, something is there already (it must be a new file)",
+        name, path
+    )]
+    RedirectFileExists { name: String, path: PathBuf },
+    #[fail(
+        display = "Could not open {:?}, nothing opened the other end of the FIFO in time",
+        path
+    )]
+    RedirectTimeout { path: PathBuf },
+    #[fail(
+        display = "Could not hand the root of the sandbox to the supervisor: {}",
+        _0
+    )]
+    RootChannelError(String),
     #[fail(display = "Could not set process to die when parent dies: {}", _0)]
     PrSetPDeathSigError(String),
+    #[fail(display = "Could not trace the syscalls: {}", _0)]
+    PtraceError(String),
+    #[fail(display = "Could not set up the pseudo terminal: {}", _0)]
+    PtyError(String),
+    #[fail(display = "Could not set up the seccomp filter: {}", _0)]
+    SeccompError(String),
     #[fail(display = "Could not set interval timer alarm: {}", _0)]
     SetITimerError(String),
     #[fail(
@@ -57,14 +167,45 @@ pub enum FFIError {
     SetpgidError { pid: i32, pgid: i32, error: String },
     #[fail(display = "Could not set resource limit: {}", _0)]
     SetRLimitError(String),
+    #[fail(display = "Could not send signal {} to the command: {}", signal, error)]
+    SignalError { signal: i32, error: String },
+    #[fail(display = "Could not query the status of the run: {}", _0)]
+    StatusError(String),
     #[fail(display = "Could not set a signal handler for {}: {}", signal, error)]
     SigActionError { signal: String, error: String },
+    #[fail(display = "Could not switch to uid {} and gid {}: {}", uid, gid, error)]
+    SetUserError { uid: u32, gid: u32, error: String },
+    #[fail(display = "Could not make the socket channel: {}", _0)]
+    SocketChannelError(String),
     #[fail(display = "Could not umount path: {:?}: {}", path, error)]
     UMountError { path: PathBuf, error: String },
+    #[fail(display = "Could not unpack {:?}: {}", path, error)]
+    UnpackError { path: PathBuf, error: String },
     #[fail(display = "Could not unshare cgroup namespace: {}", _0)]
     UnshareCGroupError(String),
+    #[fail(display = "Could not unshare a new {} namespace: {}", kind, error)]
+    UnshareError { kind: String, error: String },
     #[fail(display = "Could not usleep for {} microseconds: {}", time, error)]
     UsleepError { time: u32, error: String },
+    #[fail(
+        display = "Could not create veth pair {} - {}: {}",
+        host_name, peer_name, error
+    )]
+    VethCreateError {
+        host_name: String,
+        peer_name: String,
+        error: String,
+    },
+    #[fail(
+        display = "Could not create veth pair {} - {}: it needs CAP_NET_ADMIN in the host \
+                   network namespace, which a new user namespace does not give. Run as root \
+                   without cloning the user.",
+        host_name, peer_name
+    )]
+    VethPermissionError {
+        host_name: String,
+        peer_name: String,
+    },
     #[fail(display = "Could not write /proc/self/uid_map file: {}", _0)]
     WriteUidError(String),
     #[fail(display = "Could not write /proc/self/uid_map file: {}", _0)]
@@ -77,8 +218,16 @@ pub enum FFIError {
 
 #[derive(Fail, Debug, Serialize, Deserialize)]
 pub enum CGroupError {
-    #[fail(display = "Cgroup controller missing: {:?}", _0)]
-    ControllerMissing(PathBuf),
+    #[fail(
+        display = "Cgroup controller {} is missing at {:?}. Mount the {} hierarchy and create it \
+                   with `mkdir -p {:?} && chown -R {} {:?}`, or use a different controller path.",
+        controller, path, controller, path, uid, path
+    )]
+    ControllerMissing {
+        controller: String,
+        path: PathBuf,
+        uid: u32,
+    },
     #[fail(
         display = "Could not create instance controller under {:?} for {:?}: {}",
         controller_path, instance_name, error
@@ -88,6 +237,16 @@ pub enum CGroupError {
         instance_name: OsString,
         error: String,
     },
+    #[fail(
+        display = "Cgroup controller {} at {:?} is not writable by uid {}. Give it the \
+                   ownership with `chown -R {} {:?}` (or remount the hierarchy read-write).",
+        controller, path, uid, uid, path
+    )]
+    NotWritable {
+        controller: String,
+        path: PathBuf,
+        uid: u32,
+    },
     #[fail(
         display = "Could not open {:?} for controller {:?}: {}",
         file, controller_path, error
@@ -97,6 +256,30 @@ pub enum CGroupError {
         file: PathBuf,
         error: String,
     },
+    #[fail(
+        display = "Cgroup {:?} did not get {} in time, the processes may be stuck in the kernel",
+        instance_path, state
+    )]
+    FreezeTimeout {
+        instance_path: PathBuf,
+        state: String,
+    },
+    #[fail(
+        display = "Could not watch {:?} for memory pressure: {}",
+        instance_path, error
+    )]
+    MemoryPressureNotifierError {
+        instance_path: PathBuf,
+        error: String,
+    },
+    #[fail(
+        display = "Could not watch {:?} for out of memory events: {}",
+        instance_path, error
+    )]
+    OomNotifierError {
+        instance_path: PathBuf,
+        error: String,
+    },
     #[fail(
         display = "Could not parse `{}` from {:?} for controller {:?}: {}",
         buffer, file, controller_path, error
@@ -108,6 +291,8 @@ pub enum CGroupError {
         error: String,
     },
 
+    #[fail(display = "Could not read /proc/self/cgroup: {}", _0)]
+    ReadProcCgroupError(String),
     #[fail(
         display = "Could not read from {:?} for controller {:?}: {}",
         file, controller_path, error
@@ -128,12 +313,204 @@ pub enum CGroupError {
     },
 }
 
+#[derive(Fail, Debug, Serialize, Deserialize)]
+pub enum ConfigError {
+    #[fail(display = "Only stdin can be given bytes, not {}", _0)]
+    BytesNotStdin(String),
+    #[fail(display = "Can not join an existing {} namespace: {}", kind, reason)]
+    CanNotJoinNamespace { kind: String, reason: String },
+    #[fail(display = "Only stdout and stderr can be captured or teed, not stdin")]
+    CapturedStdin,
+    #[fail(display = "Command {:?} is not an executable file", _0)]
+    CommandNotExecutable(PathBuf),
+    #[fail(display = "Command {:?} does not exist", _0)]
+    CommandNotFound(PathBuf),
+    #[fail(display = "The wall time limit can not be derived without a user time limit")]
+    DerivedWallTimeWithoutUserTime,
+    #[fail(
+        display = "The {} of both runs of an interactive pair must be `Redirect::Pipe`",
+        _0
+    )]
+    InteractiveRedirect(String),
+    #[fail(
+        display = "Instance name {:?} must be a relative path without `.` or `..` components",
+        _0
+    )]
+    InvalidInstanceName(OsString),
+    #[fail(display = "Invalid extra file descriptor {}: {}", fd, reason)]
+    InvalidExtraFd { fd: i32, reason: String },
+    #[fail(
+        display = "Interface name {:?} must be 1 to 15 bytes without `/` or whitespace, and \
+                   the two ends of a veth pair must have different names",
+        _0
+    )]
+    InvalidInterfaceName(String),
+    #[fail(display = "Unrecognized mount option `{}`", _0)]
+    InvalidMountOption(String),
+    #[fail(
+        display = "The prefix length {} of an IPv4 address must be at most 32",
+        _0
+    )]
+    InvalidPrefixLen(u8),
+    #[fail(
+        display = "The wall time factor must be a positive number, the wall time limit is the \
+                   user time limit times it (plus the slack)"
+    )]
+    InvalidWallTimeFactor,
+    #[fail(
+        display = "Could not find the block device backing {:?}, io limits need one",
+        _0
+    )]
+    IoDeviceNotFound(PathBuf),
+    #[fail(
+        display = "Landlock rule path {:?} must be absolute, it is resolved inside the sandbox",
+        _0
+    )]
+    LandlockPathNotAbsolute(PathBuf),
+    #[fail(
+        display = "Source {:?} of the mount at {:?} does not exist",
+        source, destination
+    )]
+    MountSourceNotFound {
+        source: PathBuf,
+        destination: PathBuf,
+    },
+    #[fail(
+        display = "Source {:?} of the mount at {:?} is a directory and the destination is not, \
+                   or the other way around",
+        source, destination
+    )]
+    MountSourceTypeMismatch {
+        source: PathBuf,
+        destination: PathBuf,
+    },
+    #[fail(
+        display = "The network limit counts the traffic of the whole network namespace, which \
+                   has to be one of the sandbox's own: unshare the network and do not join \
+                   another namespace"
+    )]
+    NetBytesWithSharedNet,
+    #[fail(
+        display = "The new root {:?} has no {:?} directory and lives on a read-only \
+                   filesystem, where it can not be made. Make it in the new root.",
+        new_root, dir
+    )]
+    NewRootMissingDir { new_root: PathBuf, dir: PathBuf },
+    #[fail(display = "The new root {:?} is not a directory", _0)]
+    NewRootNotFound(PathBuf),
+    #[fail(display = "An overlay needs at least one lower directory")]
+    OverlayWithoutLower,
+    #[fail(display = "The new root can only be an overlay with a new root to mount it at")]
+    OverlayWithoutNewRoot,
+    #[fail(
+        display = "Parent limits need an instance name with a parent, such as `batch/run`, \
+                   to apply them to"
+    )]
+    ParentLimitsWithoutParent,
+    #[fail(
+        display = "The pids limit {} is above the largest one the kernel takes, PIDS_MAX_LIMIT",
+        _0
+    )]
+    PidsLimitTooLarge(usize),
+    #[fail(display = "The directory of redirect {:?} does not exist", _0)]
+    RedirectDirNotFound(PathBuf),
+    #[fail(display = "A root image is mounted at the new root, it needs one")]
+    RootImageWithoutNewRoot,
+    #[fail(display = "The new root can not be both a root image and an overlay")]
+    RootImageWithOverlay,
+    #[fail(display = "The root isolation mode needs running as root")]
+    RootModeWithoutRoot,
+    #[fail(
+        display = "Input {:?} and output {:?} are the same file, opening the output truncates \
+                   the input before it is read. Copy the input to a different file or allow \
+                   using the same file explicitly.",
+        input, output
+    )]
+    SameFileIo { input: PathBuf, output: PathBuf },
+    #[fail(
+        display = "The seccomp policy passes syscalls on to the supervisor, which needs a \
+                   notify handler to answer them"
+    )]
+    SeccompNotifyWithoutHandler,
+    #[fail(
+        display = "A shared mount propagation can not be used with a new root, pivot_root \
+                   needs the root to be a private or slave mount"
+    )]
+    SharedPropagationWithNewRoot,
+    #[fail(
+        display = "Mount source {:?} lives on {:?} which is mounted without {:?}. Remount {:?} \
+                   allowing them or copy the source to a filesystem that does.",
+        source, mount_point, missing, mount_point
+    )]
+    SourceMountFlagsConflict {
+        source: PathBuf,
+        mount_point: PathBuf,
+        missing: Vec<String>,
+    },
+    #[fail(
+        display = "A squashfs root image is mounted through a loop device, which can not be \
+                   set up inside a user namespace. Run as root in the root isolation mode."
+    )]
+    SquashfsInUserNamespace,
+    #[fail(display = "Only stderr can be redirected to stdout, not {}", _0)]
+    ToStdoutNotStderr(String),
+    #[fail(
+        display = "Unknown syscall {:?}, seccomp policies take the syscall names of the \
+                   architecture the sandbox is built for",
+        _0
+    )]
+    UnknownSyscall(String),
+    #[fail(
+        display = "A veth pair needs an unshared network namespace, there is nothing to \
+                   connect to the host otherwise"
+    )]
+    VethWithSharedNet,
+    #[fail(
+        display = "The {} limit can not be zero, leave it unset (or use Limits::unlimited()) \
+                   to disable it",
+        _0
+    )]
+    ZeroLimit(String),
+}
+
+/// All the problems `Config::validate` found, one per line
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ConfigErrors(Vec<ConfigError>);
+
+impl ConfigErrors {
+    pub fn new(errors: Vec<ConfigError>) -> Self {
+        Self(errors)
+    }
+
+    pub fn errors(&self) -> &[ConfigError] {
+        &self.0
+    }
+
+    pub fn into_errors(self) -> Vec<ConfigError> {
+        self.0
+    }
+}
+
+impl Display for ConfigErrors {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        for (index, error) in self.0.iter().enumerate() {
+            if index > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", error)?;
+        }
+        Ok(())
+    }
+}
+
 #[derive(Fail, Debug, Serialize, Deserialize)]
 pub enum ChildError {
     #[fail(display = "Cgroup error occurred.")]
     CGroupError(#[cause] CGroupError),
     #[fail(display = "FFI Error occurred.")]
     FFIError(#[cause] FFIError),
+    #[fail(display = "Panicked at {}: {}", location, message)]
+    InternalPanic { message: String, location: String },
 }
 
 impl From<CGroupError> for ChildError {
@@ -154,16 +531,33 @@ pub enum Error {
     CGroupError(#[cause] CGroupError),
     #[fail(display = "Child process error occurred.")]
     ChildError(#[cause] ChildError),
+    #[fail(display = "Invalid configuration.")]
+    ConfigError(#[cause] ConfigError),
     #[fail(display = "Child process successfully completed even though it used exec")]
     ContinuedPastExecError(String),
     #[fail(display = "Could not deserialize process result: {}", _0)]
     DeserializeError(String),
     #[fail(display = "FFI Error occurred.")]
     FFIError(#[cause] FFIError),
+    #[fail(display = "Internal sandbox error occurred.")]
+    Internal(#[cause] ChildError),
+    #[fail(display = "Invalid configuration:\n{}", _0)]
+    InvalidConfig(ConfigErrors),
+    #[fail(display = "Can not spawn a sandbox from inside another sandbox")]
+    NestedSandboxUnsupported,
     #[fail(display = "Child process stopped/continued unexpected")]
     StoppedContinuedError,
     #[fail(display = "Supervisor process died and could not collect execution information")]
     SupervisorProcessDiedError,
+    #[fail(
+        display = "The system does not support {}: {}. {}",
+        feature, error, hint
+    )]
+    UnsupportedEnvironment {
+        feature: String,
+        error: String,
+        hint: String,
+    },
 }
 
 impl From<CGroupError> for Error {
@@ -178,6 +572,12 @@ impl From<ChildError> for Error {
     }
 }
 
+impl From<ConfigError> for Error {
+    fn from(err: ConfigError) -> Self {
+        Self::ConfigError(err)
+    }
+}
+
 impl From<FFIError> for Error {
     fn from(err: FFIError) -> Self {
         Self::FFIError(err)
@@ -1,16 +1,27 @@
-use std::ffi::{CString, OsStr};
+use std::cmp;
+use std::collections::{HashMap, VecDeque};
+use std::ffi::{CStr, CString, OsStr, OsString};
 use std::fmt::Debug;
-use std::fs::{self, File, OpenOptions};
-use std::io::{Read, Write};
+use std::fs::{self, File, OpenOptions, Permissions};
+use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
 use std::iter;
 use std::marker::PhantomData;
-use std::mem::{self, MaybeUninit};
-use std::os::unix::ffi::OsStrExt;
-use std::os::unix::io::FromRawFd;
-use std::path::{Path, PathBuf};
+use std::mem::{self, size_of, MaybeUninit};
+use std::net::Ipv4Addr;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::os::unix::fs::{symlink, FileTypeExt, OpenOptionsExt, PermissionsExt};
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::os::unix::net::UnixStream;
+use std::panic;
+use std::path::{Component, Path, PathBuf};
+use std::process;
 use std::ptr;
 use std::result::Result as StdResult;
-use std::time::{Duration, Instant};
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicU32, AtomicU64, Ordering};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
 
 use bincode;
 use libc::{
@@ -20,20 +31,37 @@ use libc::{
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 
-use config::{CloneUser, Environment, Limits, Mount, ShareNet, SpaceUsage};
-use errors::{Error, FFIError};
-use run_info::{RunInfo, RunInfoResult, RunUsage};
+use binfmt::MissingInterpreter;
+use config::{
+    Capability, CloneUser, DenyNestedNamespaces, Environment, ExcludeStoppedTime, FdSource,
+    HidePid, LandlockAccess, LandlockPolicy, Limits, Mount, MountKind, NamespaceKind,
+    OutputCallback, OutputMode, Overlay, ProcOptions, Propagation, ReadOnlyRoot, Redirect,
+    SeccompAction, SeccompPolicy, ShareIpc, ShareNet, SharePid, SingleExec, SpaceUsage,
+    UsageCallback, VethConfig,
+};
+use errors::{ChildError, Error, FFIError};
+use mount_info::MountInfo;
+use run_info::{CapturedOutput, RunInfo, RunInfoResult, RunStatus, RunUsage};
+use seccomp::{SeccompNotification, SeccompNotifyHandler, SeccompResponse};
+use syscalls;
+use tarball::{Archive, Entry, EntryKind};
 
 type Result<T> = StdResult<T, FFIError>;
 
 const DEFAULT_STACK_SIZE: usize = 256 * 1024;
 const CLONE_NEWNET: libc::c_int = 0x40_000_000;
+const CLONE_NEWTIME: libc::c_int = 0x80;
 
 #[derive(Debug, Eq, PartialEq, Clone, Copy)]
 pub(crate) struct UserId(libc::uid_t);
 
 impl UserId {
     pub(crate) const ROOT: Self = Self(0);
+    pub(crate) const NOBODY: Self = Self(65534);
+
+    pub(crate) fn new(uid: libc::uid_t) -> Self {
+        Self(uid)
+    }
 }
 
 #[derive(Debug, Eq, PartialEq, Clone, Copy)]
@@ -41,17 +69,50 @@ pub(crate) struct GroupId(libc::gid_t);
 
 impl GroupId {
     pub(crate) const ROOT: Self = Self(0);
+    pub(crate) const NOBODY: Self = Self(65534);
+
+    pub(crate) fn new(gid: libc::gid_t) -> Self {
+        Self(gid)
+    }
 }
 
 pub(crate) fn get_user_group_id() -> (UserId, GroupId) {
     unsafe { (UserId(libc::getuid()), GroupId(libc::getgid())) }
 }
 
+// Recorded before cloning, processes in a new user namespace see their mapped uid instead
+static CALLER_UID: AtomicU32 = AtomicU32::new(u32::MAX);
+
+pub(crate) fn record_caller_uid() {
+    CALLER_UID.store(unsafe { libc::getuid() }, Ordering::SeqCst);
+}
+
+/// The uid of the user that spawned the sandbox
+pub(crate) fn caller_uid() -> libc::uid_t {
+    match CALLER_UID.load(Ordering::SeqCst) {
+        u32::MAX => unsafe { libc::getuid() },
+        uid => uid,
+    }
+}
+
+/// Whether the process may write to `path` (which fails on read-only mounts even for root)
+pub(crate) fn is_writable(path: &Path) -> bool {
+    match CString::new(path.as_os_str().as_bytes()) {
+        Ok(path) => unsafe { libc::access(path.as_ptr(), libc::W_OK) == 0 },
+        Err(_) => false,
+    }
+}
+
 pub(crate) fn getpid() -> libc::c_int {
     unsafe { libc::getpid() }
 }
 
-pub(crate) fn set_uid_gid_maps((uid, gid): (UserId, GroupId)) -> Result<()> {
+/// Maps the ids inside the user namespace to the ones outside of it (a single id each, that is
+/// all a process without privileges in the parent namespace can map)
+pub(crate) fn set_uid_gid_maps(
+    (uid, gid): (UserId, GroupId),
+    (inside_uid, inside_gid): (UserId, GroupId),
+) -> Result<()> {
     let uid_error = |_| FFIError::WriteUidError(last_error_string());
     let mut uid_map = OpenOptions::new()
         .write(true)
@@ -59,7 +120,7 @@ pub(crate) fn set_uid_gid_maps((uid, gid): (UserId, GroupId)) -> Result<()> {
         .map_err(&uid_error)?;
 
     uid_map
-        .write_all(format!("0 {} 1\n", uid.0).as_bytes())
+        .write_all(format!("{} {} 1\n", inside_uid.0, uid.0).as_bytes())
         .map_err(&uid_error)?;
 
     // We need to set /proc/self/setgroups to deny for writing the gid_map to succeed
@@ -77,31 +138,717 @@ pub(crate) fn set_uid_gid_maps((uid, gid): (UserId, GroupId)) -> Result<()> {
         .open("/proc/self/gid_map")
         .map_err(&gid_error)?;
     gid_map
-        .write_all(format!("0 {} 1\n", gid.0).as_bytes())
+        .write_all(format!("{} {} 1\n", inside_gid.0, gid.0).as_bytes())
         .map_err(&gid_error)?;
 
     Ok(())
 }
 
-#[allow(trivial_casts)]
+pub(crate) fn is_root() -> bool {
+    unsafe { libc::geteuid() == 0 }
+}
+
+/// Runs `probe` in a forked process, so whatever it changes (namespaces, mounts, the root)
+/// goes away with it. A multi-threaded process can not unshare its user namespace either.
+fn probe_in_child<F: FnOnce() -> Result<()>>(probe: F) -> StdResult<(), String> {
+    let (mut read_pipe, mut write_pipe) = make_pipe().map_err(|err| err.to_string())?;
+    let pid = unsafe { libc::fork() };
+    if pid == 0 {
+        drop(read_pipe);
+        let status = match panic::catch_unwind(panic::AssertUnwindSafe(probe)) {
+            Ok(Ok(())) => 0,
+            Ok(Err(err)) => {
+                let _ = write_pipe.write_all(err.to_string().as_bytes());
+                1
+            }
+            Err(_) => 1,
+        };
+        unsafe { libc::_exit(status) };
+    }
+    drop(write_pipe);
+    if pid == -1 {
+        return Err(last_error_string());
+    }
+
+    let mut status = 0;
+    while unsafe { libc::waitpid(pid, &mut status, 0) } == -1 {
+        if io::Error::last_os_error().kind() != io::ErrorKind::Interrupted {
+            return Err(last_error_string());
+        }
+    }
+    if libc::WIFEXITED(status) && libc::WEXITSTATUS(status) == 0 {
+        return Ok(());
+    }
+
+    // Sandboxes cloned meanwhile by other threads can hold the write end as well, so only
+    // what is already in the pipe is read
+    let mut message = Vec::new();
+    let _ = unsafe { libc::fcntl(read_pipe.as_raw_fd(), libc::F_SETFL, libc::O_NONBLOCK) };
+    let _ = read_pipe.read_to_end(&mut message);
+    if message.is_empty() {
+        Err(format!("the probe failed with wait status {}", status))
+    } else {
+        Err(String::from_utf8_lossy(&message).into_owned())
+    }
+}
+
+fn clone_flag(kind: NamespaceKind) -> libc::c_int {
+    match kind {
+        NamespaceKind::Cgroup => CLONE_NEWCGROUP,
+        NamespaceKind::Ipc => CLONE_NEWIPC,
+        NamespaceKind::Mount => CLONE_NEWNS,
+        NamespaceKind::Net => CLONE_NEWNET,
+        NamespaceKind::Pid => CLONE_NEWPID,
+        NamespaceKind::User => CLONE_NEWUSER,
+        NamespaceKind::Uts => CLONE_NEWUTS,
+    }
+}
+
+fn unshare_namespace(kind: NamespaceKind) -> Result<()> {
+    if unsafe { libc::unshare(clone_flag(kind)) } == -1 {
+        Err(FFIError::UnshareError {
+            kind: kind.to_string(),
+            error: last_error_string(),
+        })
+    } else {
+        Ok(())
+    }
+}
+
+/// Whether a new user namespace can be made and mapped, which kernels and distributions can
+/// forbid (`user.max_user_namespaces`, `kernel.unprivileged_userns_clone`, LSMs)
+pub(crate) fn probe_user_namespace() -> StdResult<(), String> {
+    let user_group_id = get_user_group_id();
+    probe_in_child(|| {
+        unshare_namespace(NamespaceKind::User)?;
+        set_uid_gid_maps(user_group_id, (UserId::ROOT, GroupId::ROOT))
+    })
+}
+
+/// Whether a new namespace of `kind` can be made, inside a new user namespace unless running
+/// as root like the sandbox does
+pub(crate) fn probe_namespace(kind: NamespaceKind) -> StdResult<(), String> {
+    probe_in_child(|| {
+        enter_probe_user_namespace()?;
+        unshare_namespace(kind)
+    })
+}
+
+// Unless running as root the sandbox makes its namespaces inside a mapped user namespace, files
+// can not even be created in one without the mapping
+fn enter_probe_user_namespace() -> Result<()> {
+    if !is_root() {
+        let user_group_id = get_user_group_id();
+        unshare_namespace(NamespaceKind::User)?;
+        set_uid_gid_maps(user_group_id, (UserId::ROOT, GroupId::ROOT))?;
+    }
+    Ok(())
+}
+
+/// Whether `pivot_root` works, into a tmpfs mounted on `scratch` in a new mount namespace
+/// (so `scratch` is left empty)
+pub(crate) fn probe_pivot_root(scratch: &Path) -> StdResult<(), String> {
+    probe_in_child(|| {
+        enter_probe_user_namespace()?;
+        unshare_namespace(NamespaceKind::Mount)?;
+        remount_root(Propagation::Private)?;
+
+        let tmpfs = CString::new("tmpfs").unwrap();
+        let scratch_c_string = os_str_to_c_string(scratch)?;
+        let res = unsafe {
+            libc::mount(
+                tmpfs.as_ptr(),
+                scratch_c_string.as_ptr(),
+                tmpfs.as_ptr(),
+                0,
+                ptr::null_mut(),
+            )
+        };
+        if res == -1 {
+            return Err(FFIError::MountError {
+                path: scratch.to_path_buf(),
+                error: last_error_string(),
+            });
+        }
+        pivot_root(scratch, ReadOnlyRoot::No, || Ok(()))
+    })
+}
+
+/// Drops the supplementary groups and switches the real, effective and saved ids
+pub(crate) fn set_user((uid, gid): (UserId, GroupId)) -> Result<()> {
+    let error = || FFIError::SetUserError {
+        uid: uid.0,
+        gid: gid.0,
+        error: last_error_string(),
+    };
+    // The group first, the privileges to change it are gone once the uid changed
+    unsafe {
+        // Switching away from root clears the permitted capabilities otherwise, the ones that
+        // are not kept are dropped right before the exec anyway
+        if libc::prctl(libc::PR_SET_KEEPCAPS, 1, 0, 0, 0) == -1 {
+            return Err(error());
+        }
+        if libc::setgroups(0, ptr::null()) == -1 {
+            return Err(error());
+        }
+        if libc::setresgid(gid.0, gid.0, gid.0) == -1 {
+            return Err(error());
+        }
+        if libc::setresuid(uid.0, uid.0, uid.0) == -1 {
+            return Err(error());
+        }
+    }
+    Ok(())
+}
+
+/// Drops every capability but `keep` from the bounding set, which needs `CAP_SETPCAP` so it must
+/// be done before switching to an unprivileged user. Capabilities the kernel has but the crate
+/// does not know of are dropped as well.
+pub(crate) fn drop_bounding_capabilities(keep: &[Capability]) -> Result<()> {
+    for number in 0.. {
+        // Reading a capability past the last one of the kernel fails with EINVAL
+        if unsafe { libc::prctl(libc::PR_CAPBSET_READ, number as libc::c_ulong) } == -1 {
+            break;
+        }
+        if keep.iter().any(|capability| capability.number() == number) {
+            continue;
+        }
+        if unsafe { libc::prctl(libc::PR_CAPBSET_DROP, number as libc::c_ulong) } == -1 {
+            return Err(FFIError::DropCapabilitiesError(last_error_string()));
+        }
+    }
+    Ok(())
+}
+
+#[repr(C)]
+struct CapUserHeader {
+    version: u32,
+    pid: libc::c_int,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct CapUserData {
+    effective: u32,
+    permitted: u32,
+    inheritable: u32,
+}
+
+const LINUX_CAPABILITY_VERSION_3: u32 = 0x2008_0522;
+
+/// Leaves only `keep` in the effective, permitted and inheritable sets and raises them in the
+/// ambient one, so that they survive the exec for other users than root as well
+pub(crate) fn restrict_capabilities(keep: &[Capability]) -> Result<()> {
+    let mut header = CapUserHeader {
+        version: LINUX_CAPABILITY_VERSION_3,
+        pid: 0,
+    };
+    let mut data = [CapUserData::default(); 2];
+    if unsafe { libc::syscall(libc::SYS_capget, &mut header, data.as_mut_ptr()) } == -1 {
+        return Err(FFIError::DropCapabilitiesError(last_error_string()));
+    }
+
+    // Switching to a user other than root already cleared them, so only what is left is kept
+    let mut keep_mask = [0_u32; 2];
+    for capability in keep {
+        keep_mask[capability.number() as usize / 32] |= 1 << (capability.number() % 32);
+    }
+    for (data, keep_mask) in data.iter_mut().zip(&keep_mask) {
+        let kept = data.permitted & keep_mask;
+        data.effective = kept;
+        data.permitted = kept;
+        data.inheritable = kept;
+    }
+    if unsafe { libc::syscall(libc::SYS_capset, &mut header, data.as_ptr()) } == -1 {
+        return Err(FFIError::DropCapabilitiesError(last_error_string()));
+    }
+
+    if unsafe {
+        libc::prctl(
+            libc::PR_CAP_AMBIENT,
+            libc::PR_CAP_AMBIENT_CLEAR_ALL,
+            0,
+            0,
+            0,
+        )
+    } == -1
+    {
+        return Err(FFIError::DropCapabilitiesError(last_error_string()));
+    }
+    for capability in keep {
+        let number = capability.number();
+        if data[number as usize / 32].permitted & (1 << (number % 32)) == 0 {
+            continue;
+        }
+        let raise = (
+            libc::PR_CAP_AMBIENT_RAISE as libc::c_ulong,
+            number as libc::c_ulong,
+        );
+        if unsafe { libc::prctl(libc::PR_CAP_AMBIENT, raise.0, raise.1, 0, 0) } == -1 {
+            return Err(FFIError::DropCapabilitiesError(last_error_string()));
+        }
+    }
+    Ok(())
+}
+
+/// Keeps the command (and everything it runs) from gaining privileges through setuid binaries
+/// or file capabilities
+pub(crate) fn set_no_new_privs() -> Result<()> {
+    if unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) } == -1 {
+        Err(FFIError::NoNewPrivsError(last_error_string()))
+    } else {
+        Ok(())
+    }
+}
+
+/// Makes the supervisor the tracer of the command, which then stops right after the exec for
+/// the `SyscallTracer` to take over. Before the seccomp filter, which may not allow ptrace.
+pub(crate) fn trace_me() -> Result<()> {
+    let result = unsafe {
+        libc::ptrace(
+            libc::PTRACE_TRACEME,
+            0,
+            ptr::null_mut::<libc::c_void>(),
+            ptr::null_mut::<libc::c_void>(),
+        )
+    };
+    if result == -1 {
+        Err(FFIError::PtraceError(last_error_string()))
+    } else {
+        Ok(())
+    }
+}
+
+fn bpf_statement(code: u32, k: u32) -> libc::sock_filter {
+    bpf_jump(code, k, 0, 0)
+}
+
+fn bpf_jump(code: u32, k: u32, jt: u8, jf: u8) -> libc::sock_filter {
+    libc::sock_filter {
+        code: code as u16,
+        jt,
+        jf,
+        k,
+    }
+}
+
+// Offsets of the fields of struct seccomp_data the filter looks at
+const SECCOMP_DATA_NR: u32 = 0;
+const SECCOMP_DATA_ARCH: u32 = 4;
+// The lower half of the first argument
+#[cfg(target_endian = "little")]
+const SECCOMP_DATA_ARG0: u32 = 16;
+#[cfg(target_endian = "big")]
+const SECCOMP_DATA_ARG0: u32 = 20;
+
+const NAMESPACE_FLAGS: libc::c_int = CLONE_NEWCGROUP
+    | CLONE_NEWIPC
+    | CLONE_NEWNET
+    | CLONE_NEWNS
+    | CLONE_NEWPID
+    | CLONE_NEWTIME
+    | CLONE_NEWUSER
+    | CLONE_NEWUTS;
+
+// Syscalls of another architecture are killed outright, their numbers mean something else.
+// First the `flag_rules` (syscall, flags, action) give the action of a syscall with any of the
+// flags in its first argument, then the first of `rules` matching the syscall number does,
+// `default_action` otherwise.
+fn seccomp_filter(
+    audit_arch: u32,
+    flag_rules: &[(u32, u32, u32)],
+    fd_rules: &[(u32, u32, u32)],
+    rules: &[(u32, u32)],
+    default_action: u32,
+) -> Vec<libc::sock_filter> {
+    let mut filter = vec![
+        bpf_statement(
+            libc::BPF_LD | libc::BPF_W | libc::BPF_ABS,
+            SECCOMP_DATA_ARCH,
+        ),
+        bpf_jump(
+            libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K,
+            audit_arch,
+            1,
+            0,
+        ),
+        bpf_statement(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_KILL_PROCESS),
+        bpf_statement(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, SECCOMP_DATA_NR),
+    ];
+    for &(syscall, flags, action) in flag_rules {
+        filter.push(bpf_jump(
+            libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K,
+            syscall,
+            0,
+            4,
+        ));
+        filter.push(bpf_statement(
+            libc::BPF_LD | libc::BPF_W | libc::BPF_ABS,
+            SECCOMP_DATA_ARG0,
+        ));
+        filter.push(bpf_jump(
+            libc::BPF_JMP | libc::BPF_JSET | libc::BPF_K,
+            flags,
+            0,
+            1,
+        ));
+        filter.push(bpf_statement(libc::BPF_RET | libc::BPF_K, action));
+        filter.push(bpf_statement(
+            libc::BPF_LD | libc::BPF_W | libc::BPF_ABS,
+            SECCOMP_DATA_NR,
+        ));
+    }
+    // Likewise, for the syscalls on one file descriptor
+    for &(syscall, fd, action) in fd_rules {
+        filter.push(bpf_jump(
+            libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K,
+            syscall,
+            0,
+            4,
+        ));
+        filter.push(bpf_statement(
+            libc::BPF_LD | libc::BPF_W | libc::BPF_ABS,
+            SECCOMP_DATA_ARG0,
+        ));
+        filter.push(bpf_jump(
+            libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K,
+            fd,
+            0,
+            1,
+        ));
+        filter.push(bpf_statement(libc::BPF_RET | libc::BPF_K, action));
+        filter.push(bpf_statement(
+            libc::BPF_LD | libc::BPF_W | libc::BPF_ABS,
+            SECCOMP_DATA_NR,
+        ));
+    }
+    for &(syscall, action) in rules {
+        filter.push(bpf_jump(
+            libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K,
+            syscall,
+            0,
+            1,
+        ));
+        filter.push(bpf_statement(libc::BPF_RET | libc::BPF_K, action));
+    }
+    filter.push(bpf_statement(libc::BPF_RET | libc::BPF_K, default_action));
+    filter
+}
+
+/// Loads the seccomp filter of the process, after `set_no_new_privs`: `policy` if any, with
+/// `SingleExec::Yes` every exec handed to the supervisor (which lets only the one starting the
+/// command through), and with `DenyNestedNamespaces::Yes` namespaces failed first. The
+/// syscalls a policy kills on or notifies about are handed to the supervisor as well, over
+/// `channel`, see `SeccompChannel`. A policy always allows sending and receiving on
+/// `socket_channel`.
+pub(crate) fn load_seccomp_filter(
+    policy: Option<&SeccompPolicy>,
+    single_exec: SingleExec,
+    deny_nested_namespaces: DenyNestedNamespaces,
+    channel: Option<&SeccompChannel>,
+    socket_channel: Option<RawFd>,
+) -> Result<()> {
+    let audit_arch = match syscalls::AUDIT_ARCH {
+        Some(audit_arch) => audit_arch,
+        // Denying namespaces is on by default, it is left out instead of failing every run
+        None if policy.is_none() && single_exec == SingleExec::No => return Ok(()),
+        None => {
+            return Err(FFIError::SeccompError(
+                "seccomp policies are not supported on this architecture".into(),
+            ))
+        }
+    };
+    let flag_rules = match deny_nested_namespaces {
+        DenyNestedNamespaces::Yes => vec![
+            (
+                libc::SYS_unshare as u32,
+                NAMESPACE_FLAGS as u32,
+                libc::SECCOMP_RET_ERRNO | libc::EPERM as u32,
+            ),
+            (
+                libc::SYS_clone as u32,
+                NAMESPACE_FLAGS as u32,
+                libc::SECCOMP_RET_ERRNO | libc::EPERM as u32,
+            ),
+        ],
+        DenyNestedNamespaces::No => Vec::new(),
+    };
+    let execs = [libc::SYS_execve as u32, libc::SYS_execveat as u32];
+    let mut rules = Vec::new();
+    match single_exec {
+        SingleExec::Yes => rules.extend(
+            execs
+                .iter()
+                .map(|&syscall| (syscall, libc::SECCOMP_RET_USER_NOTIF)),
+        ),
+        // The command is started through `execve`, which a policy always allows
+        SingleExec::No => rules.push((execs[0], libc::SECCOMP_RET_ALLOW)),
+    }
+    if deny_nested_namespaces == DenyNestedNamespaces::Yes {
+        rules.push((
+            libc::SYS_clone3 as u32,
+            libc::SECCOMP_RET_ERRNO | libc::ENOSYS as u32,
+        ));
+    }
+
+    let fd_rules: Vec<_> = match (policy, socket_channel) {
+        (Some(_), Some(fd)) => [
+            libc::SYS_sendto,
+            libc::SYS_recvfrom,
+            libc::SYS_sendmsg,
+            libc::SYS_recvmsg,
+        ]
+        .iter()
+        .map(|&syscall| (syscall as u32, fd as u32, libc::SECCOMP_RET_ALLOW))
+        .collect(),
+        _ => Vec::new(),
+    };
+
+    let default_action = match policy {
+        Some(policy) => {
+            let allowed = policy
+                .syscall_numbers()
+                .ok_or_else(|| FFIError::SeccompError("the policy has unknown syscalls".into()))?;
+            let notified = policy
+                .notify_numbers()
+                .ok_or_else(|| FFIError::SeccompError("the policy has unknown syscalls".into()))?;
+            rules.extend(
+                allowed
+                    .into_iter()
+                    .map(|syscall| (syscall, libc::SECCOMP_RET_ALLOW)),
+            );
+            rules.extend(
+                notified
+                    .into_iter()
+                    .map(|syscall| (syscall, libc::SECCOMP_RET_USER_NOTIF)),
+            );
+            match policy.default_action() {
+                SeccompAction::Kill => libc::SECCOMP_RET_USER_NOTIF,
+                SeccompAction::Errno(errno) => libc::SECCOMP_RET_ERRNO | u32::from(errno),
+                SeccompAction::Log => libc::SECCOMP_RET_LOG,
+            }
+        }
+        None => libc::SECCOMP_RET_ALLOW,
+    };
+    let mut filter = seccomp_filter(audit_arch, &flag_rules, &fd_rules, &rules, default_action);
+    let program = libc::sock_fprog {
+        len: filter.len() as u16,
+        filter: filter.as_mut_ptr(),
+    };
+
+    match channel {
+        Some(channel) => channel.load_filter(&program),
+        None => {
+            if unsafe { libc::prctl(libc::PR_SET_SECCOMP, libc::SECCOMP_MODE_FILTER, &program) }
+                == -1
+            {
+                Err(FFIError::SeccompError(last_error_string()))
+            } else {
+                Ok(())
+            }
+        }
+    }
+}
+
+// From linux/landlock.h, which libc does not have
+const LANDLOCK_CREATE_RULESET_VERSION: u32 = 1;
+const LANDLOCK_RULE_PATH_BENEATH: libc::c_int = 1;
+const LANDLOCK_ACCESS_FS_EXECUTE: u64 = 1;
+const LANDLOCK_ACCESS_FS_WRITE_FILE: u64 = 1 << 1;
+const LANDLOCK_ACCESS_FS_READ_FILE: u64 = 1 << 2;
+const LANDLOCK_ACCESS_FS_READ_DIR: u64 = 1 << 3;
+const LANDLOCK_ACCESS_FS_REMOVE_FILE: u64 = 1 << 5;
+const LANDLOCK_ACCESS_FS_MAKE_REG: u64 = 1 << 8;
+const LANDLOCK_ACCESS_FS_TRUNCATE: u64 = 1 << 14;
+
+#[repr(C)]
+struct LandlockRulesetAttr {
+    handled_access_fs: u64,
+}
+
+#[repr(C, packed)]
+struct LandlockPathBeneathAttr {
+    allowed_access: u64,
+    parent_fd: i32,
+}
+
+// Every filesystem access right of each Landlock ABI version, the ones a ruleset handles are
+// denied unless a rule allows them
+fn landlock_handled_access(abi: u32) -> u64 {
+    match abi {
+        1 => (1 << 13) - 1,
+        2 => (1 << 14) - 1,
+        3 | 4 => (1 << 15) - 1,
+        _ => (1 << 16) - 1,
+    }
+}
+
+/// The Landlock ABI version of the kernel
+pub(crate) fn landlock_abi() -> StdResult<u32, String> {
+    match unsafe {
+        libc::syscall(
+            libc::SYS_landlock_create_ruleset,
+            ptr::null::<LandlockRulesetAttr>(),
+            0,
+            LANDLOCK_CREATE_RULESET_VERSION,
+        )
+    } {
+        -1 => Err(last_error_string()),
+        abi => Ok(abi as u32),
+    }
+}
+
+fn landlock_path_error(path: &Path, error: String) -> FFIError {
+    FFIError::LandlockError(format!("{:?}: {}", path, error))
+}
+
+/// Restricts the filesystem access of the process to the one `policy` allows, after
+/// `set_no_new_privs`
+pub(crate) fn restrict_filesystem(policy: &LandlockPolicy) -> Result<()> {
+    let abi = landlock_abi().map_err(FFIError::LandlockError)?;
+    let handled_access = landlock_handled_access(abi);
+    let attr = LandlockRulesetAttr {
+        handled_access_fs: handled_access,
+    };
+    let ruleset = match unsafe {
+        libc::syscall(
+            libc::SYS_landlock_create_ruleset,
+            &attr,
+            size_of::<LandlockRulesetAttr>(),
+            0,
+        )
+    } {
+        -1 => return Err(FFIError::LandlockError(last_error_string())),
+        fd => unsafe { File::from_raw_fd(fd as RawFd) },
+    };
+
+    for (path, accesses) in policy.rules() {
+        let path_c_string = os_str_to_c_string(path)?;
+        let parent =
+            match unsafe { libc::open(path_c_string.as_ptr(), libc::O_PATH | libc::O_CLOEXEC) } {
+                -1 => return Err(landlock_path_error(path, last_error_string())),
+                fd => unsafe { File::from_raw_fd(fd) },
+            };
+        let is_dir = parent
+            .metadata()
+            .map_err(|err| landlock_path_error(path, err.to_string()))?
+            .is_dir();
+
+        let mut allowed_access = 0;
+        for access in accesses {
+            allowed_access |= match (access, is_dir) {
+                (LandlockAccess::ReadFile, _) => LANDLOCK_ACCESS_FS_READ_FILE,
+                (LandlockAccess::ReadDir, true) => LANDLOCK_ACCESS_FS_READ_DIR,
+                // Only a directory has entries to read
+                (LandlockAccess::ReadDir, false) => 0,
+                (LandlockAccess::WriteFile, true) => {
+                    LANDLOCK_ACCESS_FS_WRITE_FILE
+                        | LANDLOCK_ACCESS_FS_TRUNCATE
+                        | LANDLOCK_ACCESS_FS_MAKE_REG
+                        | LANDLOCK_ACCESS_FS_REMOVE_FILE
+                }
+                (LandlockAccess::WriteFile, false) => {
+                    LANDLOCK_ACCESS_FS_WRITE_FILE | LANDLOCK_ACCESS_FS_TRUNCATE
+                }
+                // The exec opens the command (and its dynamic loader) for reading as well
+                (LandlockAccess::Execute, _) => {
+                    LANDLOCK_ACCESS_FS_EXECUTE | LANDLOCK_ACCESS_FS_READ_FILE
+                }
+            };
+        }
+        let rule = LandlockPathBeneathAttr {
+            allowed_access: allowed_access & handled_access,
+            parent_fd: parent.as_raw_fd(),
+        };
+        if rule.allowed_access == 0 {
+            continue;
+        }
+        if unsafe {
+            libc::syscall(
+                libc::SYS_landlock_add_rule,
+                ruleset.as_raw_fd(),
+                LANDLOCK_RULE_PATH_BENEATH,
+                &rule,
+                0,
+            )
+        } == -1
+        {
+            return Err(landlock_path_error(path, last_error_string()));
+        }
+    }
+
+    if unsafe { libc::syscall(libc::SYS_landlock_restrict_self, ruleset.as_raw_fd(), 0) } == -1 {
+        return Err(FFIError::LandlockError(last_error_string()));
+    }
+    Ok(())
+}
+
 pub(crate) fn set_sig_alarm_handler() -> Result<()> {
+    set_wake_up_handler(libc::SIGALRM, "SIGALRM")
+}
+
+/// SIGCHLD is ignored by default, with a handler it interrupts the supervisor waiting on
+/// anything other than waitpid as soon as the child exits or stops
+pub(crate) fn set_sig_child_handler() -> Result<()> {
+    set_wake_up_handler(SIGCHLD, "SIGCHLD")
+}
+
+// Set once the supervisor gets SIGTERM, see `JailHandle::kill`
+static CANCELLED: AtomicBool = AtomicBool::new(false);
+
+/// SIGTERM asks the supervisor to end the run: the handler only takes note, the wait kills the
+/// command on its next tick
+pub(crate) fn set_sig_term_handler() -> Result<()> {
+    extern "C" fn handler(_: libc::c_int, _: *mut libc::siginfo_t, _: *mut libc::c_void) {
+        CANCELLED.store(true, Ordering::SeqCst);
+    }
+
+    set_handler(libc::SIGTERM, "SIGTERM", handler)
+}
+
+/// Blocks or unblocks `signal` for the calling thread, returns whether it was blocked
+pub(crate) fn set_signal_blocked(signal: libc::c_int, blocked: bool) -> bool {
+    let mut signals: libc::sigset_t = unsafe { mem::zeroed() };
+    let mut old_signals: libc::sigset_t = unsafe { mem::zeroed() };
+    let how = if blocked {
+        libc::SIG_BLOCK
+    } else {
+        libc::SIG_UNBLOCK
+    };
+    unsafe {
+        let _ = libc::sigemptyset(&mut signals);
+        let _ = libc::sigaddset(&mut signals, signal);
+        let _ = libc::pthread_sigmask(how, &signals, &mut old_signals);
+        libc::sigismember(&old_signals, signal) == 1
+    }
+}
+
+// A handler doing nothing, only there so blocking syscalls fail with EINTR on the signal
+fn set_wake_up_handler(signal: libc::c_int, name: &str) -> Result<()> {
     extern "C" fn handler(_: libc::c_int, _: *mut libc::siginfo_t, _: *mut libc::c_void) {}
 
+    set_handler(signal, name, handler)
+}
+
+#[allow(trivial_casts)]
+fn set_handler(
+    signal: libc::c_int,
+    name: &str,
+    handler: extern "C" fn(libc::c_int, *mut libc::siginfo_t, *mut libc::c_void),
+) -> Result<()> {
     let mut sigset = MaybeUninit::<libc::sigset_t>::uninit();
     let _ = unsafe { libc::sigemptyset(sigset.as_mut_ptr()) };
 
     let sigaction = libc::sigaction {
         sa_flags: libc::SA_SIGINFO,
-        sa_sigaction: unsafe {
-            mem::transmute::<_, libc::sighandler_t>(handler as extern "C" fn(_, _, _))
-        },
+        sa_sigaction: handler as libc::sighandler_t,
         sa_mask: unsafe { sigset.assume_init() },
         sa_restorer: None,
     };
 
-    if unsafe { libc::sigaction(libc::SIGALRM, &sigaction, ptr::null_mut()) } == -1 {
+    if unsafe { libc::sigaction(signal, &sigaction, ptr::null_mut()) } == -1 {
         Err(FFIError::SigActionError {
-            signal: "SIGALRM".into(),
+            signal: name.into(),
             error: last_error_string(),
         })
     } else {
@@ -138,8 +885,93 @@ pub(crate) fn set_alarm_interval(interval: libc::time_t) -> Result<()> {
 /// how often SIGALRM should trigger (in microseconds)
 const ALARM_TIMER_INTERVAL: libc::time_t = 5_000;
 
-pub(crate) fn clone<F, T: Debug>(share_net: ShareNet, vfork: bool, clone_user: CloneUser, f: F)
-                                 -> Result<CloneHandle<T>>
+// The result pipe of the cloned process, for its panic hook and `exec_command`
+static RESULT_PIPE_FD: AtomicI32 = AtomicI32::new(-1);
+
+// What the cloned process sends over the result pipe, any number of `ExecStarting` and
+// `Spawned` and then at most one `Result`. The `Err` of a result encodes the same whatever `T`
+// is.
+#[derive(Serialize, Deserialize)]
+enum ChildMessage<T> {
+    // The CLOCK_MONOTONIC time right before an exec, the last one is the exec that went through
+    ExecStarting(Duration),
+    // The pid of a child of the cloned process, in the pid namespace of the cloned process
+    Spawned(libc::pid_t),
+    Result(StdResult<T, ChildError>),
+}
+
+/// The time on `CLOCK_MONOTONIC`, which is the same for every process
+pub(crate) fn monotonic_now() -> Duration {
+    let mut time = unsafe { mem::zeroed::<libc::timespec>() };
+    // Only fails for clocks that do not exist
+    let _ = unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut time) };
+    Duration::new(time.tv_sec as u64, time.tv_nsec as u32)
+}
+
+// Tells the supervisor over the result pipe that the exec happens now, the wall time counts
+// from here
+fn send_exec_starting() {
+    let fd = RESULT_PIPE_FD.load(Ordering::SeqCst);
+    if fd == -1 {
+        return;
+    }
+    let mut result_pipe = mem::ManuallyDrop::new(unsafe { File::from_raw_fd(fd) });
+    send_message(
+        &mut result_pipe,
+        &ChildMessage::<()>::ExecStarting(monotonic_now()),
+    );
+}
+
+/// Tells the caller over the result pipe of the supervisor the pid of the child it cloned
+pub(crate) fn send_spawned(pid: libc::pid_t) {
+    let fd = RESULT_PIPE_FD.load(Ordering::SeqCst);
+    if fd == -1 {
+        return;
+    }
+    let mut result_pipe = mem::ManuallyDrop::new(unsafe { File::from_raw_fd(fd) });
+    send_message(&mut result_pipe, &ChildMessage::<()>::Spawned(pid));
+}
+
+// Writes `message` to the result pipe in one go, so the pipe turns readable (see
+// `JailHandle::poll_fd`) only once all of it is there
+fn send_message<T: Serialize>(result_pipe: &mut File, message: &ChildMessage<T>) {
+    if let Ok(data) = bincode::serialize(message) {
+        let _ = result_pipe.write_all(&data);
+    }
+}
+
+// Sends a panic of the crate itself over the result pipe and aborts, so it can not pass for
+// the sandboxed program aborting.
+fn set_panic_hook(result_pipe: &File) {
+    RESULT_PIPE_FD.store(result_pipe.as_raw_fd(), Ordering::SeqCst);
+    panic::set_hook(Box::new(|panic_info| {
+        let payload = panic_info.payload();
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|message| (*message).to_owned())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown panic payload".to_owned());
+        let location = panic_info
+            .location()
+            .map_or_else(String::new, ToString::to_string);
+
+        let mut result_pipe = unsafe { File::from_raw_fd(RESULT_PIPE_FD.load(Ordering::SeqCst)) };
+        send_message(
+            &mut result_pipe,
+            &ChildMessage::<()>::Result(Err(ChildError::InternalPanic { message, location })),
+        );
+        process::abort();
+    }));
+}
+
+pub(crate) fn clone<F, T: Debug>(
+    share_net: ShareNet,
+    share_ipc: ShareIpc,
+    share_pid: SharePid,
+    vfork: bool,
+    clone_user: CloneUser,
+    f: F,
+) -> Result<CloneHandle<T>>
 where
     F: FnOnce() -> T + Send,
     T: Serialize,
@@ -160,13 +992,17 @@ where
             mut write_error_pipe,
         } = obj;
 
+        set_panic_hook(&write_error_pipe);
         let result = inner();
-        let _ = bincode::serialize_into(&mut write_error_pipe, &result);
+        send_message(&mut write_error_pipe, &ChildMessage::Result(Ok(result)));
         0
     }
 
-    let mut clone_flags =
-        CLONE_NEWPID | CLONE_NEWIPC | CLONE_NEWUTS | CLONE_NEWNS | SIGCHLD;
+    let mut clone_flags = CLONE_NEWUTS | CLONE_NEWNS | SIGCHLD;
+
+    if share_pid == SharePid::Unshare {
+        clone_flags |= CLONE_NEWPID;
+    }
 
     if clone_user == CloneUser::Yes {
         clone_flags |= CLONE_NEWUSER;
@@ -176,6 +1012,10 @@ where
         clone_flags |= CLONE_NEWNET;
     }
 
+    if share_ipc == ShareIpc::Unshare {
+        clone_flags |= CLONE_NEWIPC;
+    }
+
     if vfork {
         clone_flags |= CLONE_VFORK;
     }
@@ -189,6 +1029,7 @@ where
         write_error_pipe,
     });
 
+    let cloned_at = monotonic_now();
     let pid = match unsafe {
         #[allow(trivial_casts)]
         libc::clone(
@@ -206,11 +1047,53 @@ where
 
     Ok(CloneHandle {
         pid,
+        share_pid,
+        cloned_at,
         read_error_pipe,
+        read: Vec::new(),
+        reaped: false,
+        spawned: None,
+        drop_grace_period: None,
+        detached: false,
         phantom: PhantomData,
     })
 }
 
+/// A namespace file opened for the jail to join, see `join_namespace`
+pub(crate) struct NamespaceFile {
+    kind: NamespaceKind,
+    path: PathBuf,
+    file: File,
+}
+
+impl NamespaceFile {
+    pub(crate) fn open(kind: NamespaceKind, path: &Path) -> Result<Self> {
+        let file = File::open(path).map_err(|err| FFIError::JoinNamespaceError {
+            kind: kind.to_string(),
+            path: path.to_path_buf(),
+            error: err.to_string(),
+        })?;
+        Ok(Self {
+            kind,
+            path: path.to_path_buf(),
+            file,
+        })
+    }
+}
+
+pub(crate) fn join_namespace(namespace: &NamespaceFile) -> Result<()> {
+    // The flag makes setns check the file is a namespace of that kind
+    if unsafe { libc::setns(namespace.file.as_raw_fd(), clone_flag(namespace.kind)) } == -1 {
+        Err(FFIError::JoinNamespaceError {
+            kind: namespace.kind.to_string(),
+            path: namespace.path.clone(),
+            error: last_error_string(),
+        })
+    } else {
+        Ok(())
+    }
+}
+
 pub(crate) fn unshare_cgroup() -> Result<()> {
     match unsafe { libc::unshare(CLONE_NEWCGROUP) } {
         -1 => Err(FFIError::UnshareCGroupError(last_error_string())),
@@ -218,21 +1101,29 @@ pub(crate) fn unshare_cgroup() -> Result<()> {
     }
 }
 
-pub(crate) fn remount_private() -> Result<()> {
-    let root = os_str_to_c_string("/");
-    let res = unsafe {
+fn set_propagation(path: &Path, propagation: Propagation, recursive: bool) -> Result<()> {
+    let path_c_string = os_str_to_c_string(path)?;
+    let mut flags = match propagation {
+        Propagation::Private => libc::MS_PRIVATE,
+        Propagation::Slave => libc::MS_SLAVE,
+        Propagation::Shared => libc::MS_SHARED,
+    };
+    if recursive {
+        flags |= libc::MS_REC;
+    }
+    let res = unsafe {
         libc::mount(
             ptr::null_mut(),
-            root.as_ptr(),
+            path_c_string.as_ptr(),
             ptr::null_mut(),
-            libc::MS_REC | libc::MS_PRIVATE,
+            flags,
             ptr::null_mut(),
         )
     };
 
     if res == -1 {
         Err(FFIError::MountError {
-            path: PathBuf::from("/"),
+            path: path.to_path_buf(),
             error: last_error_string(),
         })
     } else {
@@ -240,44 +1131,31 @@ pub(crate) fn remount_private() -> Result<()> {
     }
 }
 
-pub(crate) fn mount_inside(new_root: &Path, mount: &Mount) -> Result<()> {
-    // first create the folder or file (if it does not exist)
-    let inner_path = new_root.join(
-        mount
-            .destination()
-            .strip_prefix("/")
-            .unwrap_or_else(|_| mount.destination()),
-    );
-    let is_dir = mount.source().is_dir();
-    if is_dir {
-        fs::create_dir_all(&inner_path)
-            .map_err(|error| FFIError::CreateDirError {
-                path: inner_path.to_path_buf(),
-                error: error.to_string().into(),
-            })
-            .unwrap_or(());
-    } else {
-        inner_path.parent().map_or(Ok(()), |pardir| {
-            fs::create_dir_all(pardir).map_err(|error| FFIError::CreateDirError {
-                path: inner_path.to_path_buf(),
-                error: error.to_string().into(),
-            })
-        })?;
-        let _ = OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open(&inner_path)
-            .map_err(|error| FFIError::CreateDirError {
-                path: inner_path.to_path_buf(),
-                error: error.to_string().into(),
-            })?;
-    }
-
-    let source_c_string = os_str_to_c_string(mount.source());
-    let destination_c_string = os_str_to_c_string(&inner_path);
+pub(crate) fn remount_root(propagation: Propagation) -> Result<()> {
+    set_propagation(Path::new("/"), propagation, true)
+}
 
+pub(crate) fn mount_inside(new_root: &Path, mount: &Mount) -> Result<()> {
     let mount_options = mount.mount_options();
-    let mut mount_flags = libc::MS_BIND | libc::MS_NOSUID | libc::MS_REC;
+    // A tmpfs or an overlay is always a directory
+    let is_dir = match mount.source() {
+        Some(source) => source.is_dir(),
+        None => true,
+    };
+    let mut mount_point = open_mount_point(
+        new_root,
+        mount.destination(),
+        mount_options.create_destination(),
+        is_dir,
+    )?;
+
+    let mut mount_flags = 0;
+    if !mount_options.suid() {
+        mount_flags |= libc::MS_NOSUID;
+    }
+    if !mount_options.atime() {
+        mount_flags |= libc::MS_NOATIME;
+    }
     if mount_options.read_only() {
         mount_flags |= libc::MS_RDONLY;
     }
@@ -289,8 +1167,323 @@ pub(crate) fn mount_inside(new_root: &Path, mount: &Mount) -> Result<()> {
         mount_flags |= libc::MS_NOEXEC;
     }
 
-    let none = os_str_to_c_string("none");
-    let empty = os_str_to_c_string("");
+    match *mount.kind() {
+        MountKind::Bind { ref source } => bind_mount(
+            source,
+            &mut mount_point,
+            mount.destination(),
+            mount_flags,
+            mount_options.recursive(),
+        )?,
+        MountKind::Tmpfs { size } => {
+            mount_tmpfs(&mount_point.path(), mount.destination(), size, mount_flags)?
+        }
+        MountKind::Overlay {
+            ref lower,
+            ref upper,
+            ref work,
+        } => mount_overlay(
+            lower,
+            upper,
+            work,
+            &mount_point.path(),
+            mount.destination(),
+            mount_flags,
+        )?,
+    }
+
+    // Without one of its own the mount keeps the propagation of its source
+    mount_options.propagation().map_or(Ok(()), |propagation| {
+        mount_point.reopen(mount.destination())?;
+        set_propagation(&mount_point.path(), propagation, true)
+    })
+}
+
+// Where a mount goes inside the new root, resolved in it. The path to the descriptor stays put
+// whatever the symlinks.
+struct MountPoint {
+    root: File,
+    inner_path: PathBuf,
+    file: File,
+}
+
+impl MountPoint {
+    fn path(&self) -> PathBuf {
+        PathBuf::from(format!("/proc/self/fd/{}", self.file.as_raw_fd()))
+    }
+
+    // The descriptor is still the directory under a mount made on it, a remount (or anything
+    // made in the mount) needs the root of the mount on top
+    fn reopen(&mut self, destination: &Path) -> Result<()> {
+        self.file =
+            open_in_root(&self.root, &self.inner_path).map_err(|error| FFIError::MountError {
+                path: destination.to_path_buf(),
+                error: error.to_string(),
+            })?;
+        Ok(())
+    }
+}
+
+// Opens `path` with its symlinks (and `..`) resolved as if `root` was the root
+fn open_in_root(root: &File, path: &Path) -> io::Result<File> {
+    let mut how = unsafe { mem::zeroed::<libc::open_how>() };
+    how.flags = (libc::O_PATH | libc::O_CLOEXEC) as u64;
+    how.resolve = libc::RESOLVE_IN_ROOT | libc::RESOLVE_NO_MAGICLINKS;
+    let path_c_string = CString::new(path.as_os_str().as_bytes())?;
+    let fd = unsafe {
+        libc::syscall(
+            libc::SYS_openat2,
+            root.as_raw_fd(),
+            path_c_string.as_ptr(),
+            &how,
+            size_of::<libc::open_how>(),
+        )
+    };
+    if fd == -1 {
+        let error = io::Error::last_os_error();
+        if error.raw_os_error() == Some(libc::ENOSYS) {
+            return walk_in_root(root, path);
+        }
+        Err(error)
+    } else {
+        Ok(unsafe { File::from_raw_fd(fd as RawFd) })
+    }
+}
+
+// Without openat2 (before Linux 5.6) the components are opened one at a time. A symlink is read
+// and its target walked in its place, from `root` when absolute, and `..` never goes above `root`.
+fn walk_in_root(root: &File, path: &Path) -> io::Result<File> {
+    let mut dirs = vec![root.try_clone()?];
+    let mut components: VecDeque<OsString> = VecDeque::new();
+    let mut symlinks = 0;
+    push_components(&mut components, &mut dirs, path);
+    while let Some(name) = components.pop_front() {
+        if name == ".." {
+            if dirs.len() > 1 {
+                let _ = dirs.pop();
+            }
+            continue;
+        }
+        let name = CString::new(name.into_vec())?;
+        let parent = dirs.last().map_or(-1, |dir| dir.as_raw_fd());
+        let flags = libc::O_PATH | libc::O_NOFOLLOW | libc::O_CLOEXEC;
+        let fd = unsafe { libc::openat(parent, name.as_ptr(), flags) };
+        if fd == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        // With O_NOFOLLOW and O_PATH the symlink itself is opened
+        let file = unsafe { File::from_raw_fd(fd) };
+        if !file.metadata()?.file_type().is_symlink() {
+            dirs.push(file);
+            continue;
+        }
+        symlinks += 1;
+        if symlinks > MAX_SYMLINKS {
+            return Err(io::Error::from_raw_os_error(libc::ELOOP));
+        }
+        let mut target = vec![0u8; libc::PATH_MAX as usize];
+        // An empty path reads the symlink the descriptor itself is
+        let empty = CString::default();
+        let len = unsafe {
+            libc::readlinkat(
+                file.as_raw_fd(),
+                empty.as_ptr(),
+                target.as_mut_ptr() as *mut libc::c_char,
+                target.len(),
+            )
+        };
+        if len == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        target.truncate(len as usize);
+        push_components(
+            &mut components,
+            &mut dirs,
+            Path::new(OsStr::from_bytes(&target)),
+        );
+    }
+    Ok(dirs.pop().unwrap_or(root.try_clone()?))
+}
+
+// The most symlinks followed in one path, as the kernel does
+const MAX_SYMLINKS: usize = 40;
+
+// Puts the components of `path` in front of the ones left to walk, going back to the root first
+// when it is absolute
+fn push_components(components: &mut VecDeque<OsString>, dirs: &mut Vec<File>, path: &Path) {
+    if path.has_root() {
+        dirs.truncate(1);
+    }
+    for component in path.components().rev() {
+        match component {
+            Component::ParentDir => components.push_front(OsString::from("..")),
+            Component::Normal(name) => components.push_front(name.to_os_string()),
+            Component::Prefix(_) | Component::RootDir | Component::CurDir => {}
+        }
+    }
+}
+
+/// Resolves `destination` inside `new_root` one component at a time, so a symlink in the new
+/// root can not take the mount (or the directories made for it) out of it. With `create` the
+/// missing components are made on the way, the last one as an empty file unless `is_dir`.
+fn open_mount_point(
+    new_root: &Path,
+    destination: &Path,
+    create: bool,
+    is_dir: bool,
+) -> Result<MountPoint> {
+    let mount_error = |error: io::Error| FFIError::MountError {
+        path: destination.to_path_buf(),
+        error: error.to_string(),
+    };
+    let create_error = || FFIError::CreateDirError {
+        path: destination.to_path_buf(),
+        error: last_error_string(),
+    };
+    let root = File::open(new_root).map_err(mount_error)?;
+    let mut parent = open_in_root(&root, Path::new(".")).map_err(mount_error)?;
+
+    let components: Vec<_> = destination
+        .components()
+        .filter(|component| match *component {
+            Component::RootDir | Component::CurDir => false,
+            Component::Prefix(_) | Component::ParentDir | Component::Normal(_) => true,
+        })
+        .collect();
+    let mut inner_path = PathBuf::from(".");
+    for (index, component) in components.iter().enumerate() {
+        inner_path.push(component);
+        parent = match open_in_root(&root, &inner_path) {
+            Ok(file) => file,
+            Err(ref error) if create && error.raw_os_error() == Some(libc::ENOENT) => {
+                // Made in the directory resolved so far, never through a symlink
+                let name = os_str_to_c_string(component)?;
+                let res = if index + 1 == components.len() && !is_dir {
+                    let flags = libc::O_WRONLY | libc::O_CREAT | libc::O_NOFOLLOW | libc::O_CLOEXEC;
+                    let fd =
+                        unsafe { libc::openat(parent.as_raw_fd(), name.as_ptr(), flags, 0o666) };
+                    if fd != -1 {
+                        let _ = unsafe { libc::close(fd) };
+                    }
+                    fd
+                } else {
+                    unsafe { libc::mkdirat(parent.as_raw_fd(), name.as_ptr(), 0o777) }
+                };
+                if res == -1 {
+                    return Err(create_error());
+                }
+                open_in_root(&root, &inner_path).map_err(mount_error)?
+            }
+            Err(error) => return Err(mount_error(error)),
+        };
+    }
+    Ok(MountPoint {
+        root,
+        inner_path,
+        file: parent,
+    })
+}
+
+const MINIMAL_DEV_DEVICES: &[&str] = &["null", "zero", "full", "random", "urandom", "tty"];
+const MINIMAL_DEV_LINKS: &[(&str, &str)] = &[
+    ("fd", "/proc/self/fd"),
+    ("stdin", "/proc/self/fd/0"),
+    ("stdout", "/proc/self/fd/1"),
+    ("stderr", "/proc/self/fd/2"),
+];
+
+/// Mounts a tmpfs at `/dev` in `new_root` with the devices of the host that are safe to share
+/// bind mounted in it (device nodes can not be made inside a user namespace), see
+/// `MinimalDev`. Devices the host does not have are left out.
+pub(crate) fn mount_minimal_dev(new_root: &Path) -> Result<()> {
+    let dev = Path::new("/dev");
+    let mut mount_point = open_mount_point(new_root, dev, true, true)?;
+    mount_tmpfs(
+        &mount_point.path(),
+        dev,
+        None,
+        libc::MS_NOSUID | libc::MS_NODEV | libc::MS_NOEXEC,
+    )?;
+    mount_point.reopen(dev)?;
+
+    for device in MINIMAL_DEV_DEVICES {
+        let source = dev.join(device);
+        if !source.exists() {
+            continue;
+        }
+        // The devices are only usable without MS_NODEV
+        bind_mount(
+            &source,
+            &mut open_mount_point(new_root, &source, true, false)?,
+            &source,
+            libc::MS_NOSUID | libc::MS_NOEXEC,
+            false,
+        )?;
+    }
+
+    for &(link, target) in MINIMAL_DEV_LINKS {
+        symlink(target, mount_point.path().join(link)).map_err(|error| FFIError::MountError {
+            path: dev.join(link),
+            error: error.to_string(),
+        })?;
+    }
+    Ok(())
+}
+
+/// Mounts a devpts of its own at `/dev/pts` in `new_root` for the terminals of the command,
+/// with `/dev/ptmx` linked to the one in it (a ptmx already in the new root stays)
+pub(crate) fn mount_devpts(new_root: &Path) -> Result<()> {
+    let mount_point = open_mount_point(new_root, Path::new("/dev/pts"), true, true)?;
+    let devpts = os_str_to_c_string("devpts")?;
+    let destination_c_string = os_str_to_c_string(mount_point.path())?;
+    let data = os_str_to_c_string("newinstance,ptmxmode=0666,mode=0620")?;
+    let res = unsafe {
+        libc::mount(
+            devpts.as_ptr(),
+            destination_c_string.as_ptr(),
+            devpts.as_ptr(),
+            libc::MS_NOSUID | libc::MS_NOEXEC,
+            data.as_ptr() as *const _,
+        )
+    };
+    if res == -1 {
+        return Err(FFIError::MountError {
+            path: PathBuf::from("/dev/pts"),
+            error: last_error_string(),
+        });
+    }
+
+    // In /dev as resolved in the new root, where the devpts went
+    let dev = open_mount_point(new_root, Path::new("/dev"), false, true)?;
+    match symlink("pts/ptmx", dev.path().join("ptmx")) {
+        Err(ref error) if error.kind() != io::ErrorKind::AlreadyExists => {
+            Err(FFIError::MountError {
+                path: PathBuf::from("/dev/ptmx"),
+                error: error.to_string(),
+            })
+        }
+        _ => Ok(()),
+    }
+}
+
+fn bind_mount(
+    source: &Path,
+    mount_point: &mut MountPoint,
+    destination: &Path,
+    mount_flags: libc::c_ulong,
+    recursive: bool,
+) -> Result<()> {
+    let source_c_string = os_str_to_c_string(source)?;
+    let destination_c_string = os_str_to_c_string(mount_point.path())?;
+    let restrictions = mount_flags;
+    let mount_flags = if recursive {
+        libc::MS_BIND | libc::MS_REC | mount_flags
+    } else {
+        libc::MS_BIND | mount_flags
+    };
+
+    let none = os_str_to_c_string("none")?;
+    let empty = os_str_to_c_string("")?;
     // We need to mount twice for some mount flags to work (notably MS_RDONLY)
     let res = unsafe {
         libc::mount(
@@ -304,11 +1497,13 @@ pub(crate) fn mount_inside(new_root: &Path, mount: &Mount) -> Result<()> {
 
     if res == -1 {
         return Err(FFIError::MountError {
-            path: mount.destination().to_path_buf(),
+            path: destination.to_path_buf(),
             error: last_error_string(),
         });
     }
 
+    mount_point.reopen(destination)?;
+    let destination_c_string = os_str_to_c_string(mount_point.path())?;
     let res = unsafe {
         libc::mount(
             source_c_string.as_ptr(),
@@ -321,30 +1516,452 @@ pub(crate) fn mount_inside(new_root: &Path, mount: &Mount) -> Result<()> {
 
     if res == -1 {
         return Err(FFIError::MountError {
-            path: mount.destination().to_path_buf(),
+            path: destination.to_path_buf(),
+            error: last_error_string(),
+        });
+    }
+
+    // The remount only changed the mount on top
+    if recursive {
+        restrict_submounts(mount_point, destination, restrictions)?;
+    }
+    Ok(())
+}
+
+// From linux/mount.h, which libc does not have
+const MOUNT_ATTR_RDONLY: u64 = 0x1;
+const MOUNT_ATTR_NOSUID: u64 = 0x2;
+const MOUNT_ATTR_NODEV: u64 = 0x4;
+const MOUNT_ATTR_NOEXEC: u64 = 0x8;
+const MOUNT_ATTR_ATIME: u64 = 0x70;
+const MOUNT_ATTR_NOATIME: u64 = 0x10;
+
+#[repr(C)]
+struct MountAttr {
+    attr_set: u64,
+    attr_clr: u64,
+    propagation: u64,
+    userns_fd: u64,
+}
+
+/// Adds the restrictions in `mount_flags` to all the mounts under the one at `mount_point`,
+/// clearing none of theirs
+fn restrict_submounts(
+    mount_point: &MountPoint,
+    destination: &Path,
+    mount_flags: libc::c_ulong,
+) -> Result<()> {
+    let attr_set = [
+        (libc::MS_RDONLY, MOUNT_ATTR_RDONLY),
+        (libc::MS_NOSUID, MOUNT_ATTR_NOSUID),
+        (libc::MS_NODEV, MOUNT_ATTR_NODEV),
+        (libc::MS_NOEXEC, MOUNT_ATTR_NOEXEC),
+        (libc::MS_NOATIME, MOUNT_ATTR_NOATIME),
+    ]
+    .iter()
+    .filter(|&&(ms_flag, _)| mount_flags & ms_flag != 0)
+    .fold(0, |attrs, &(_, attr)| attrs | attr);
+    let attr = MountAttr {
+        attr_set,
+        // The access time is one setting out of several
+        attr_clr: if attr_set & MOUNT_ATTR_NOATIME != 0 {
+            MOUNT_ATTR_ATIME
+        } else {
+            0
+        },
+        propagation: 0,
+        userns_fd: 0,
+    };
+
+    let path = mount_point.path();
+    let path_c_string = os_str_to_c_string(&path)?;
+    let res = unsafe {
+        libc::syscall(
+            libc::SYS_mount_setattr,
+            libc::AT_FDCWD,
+            path_c_string.as_ptr(),
+            libc::AT_RECURSIVE,
+            &attr,
+            size_of::<MountAttr>(),
+        )
+    };
+    if res != -1 {
+        return Ok(());
+    }
+    if io::Error::last_os_error().raw_os_error() != Some(libc::ENOSYS) {
+        return Err(FFIError::MountError {
+            path: destination.to_path_buf(),
             error: last_error_string(),
         });
     }
 
+    // Before Linux 5.12 each of them is remounted on its own
+    let path = fs::canonicalize(&path).map_err(|error| FFIError::MountError {
+        path: destination.to_path_buf(),
+        error: error.to_string(),
+    })?;
+    let mount_info = MountInfo::read()?;
+    for entry in mount_info.entries() {
+        let submount = entry.mount_point();
+        if submount != path && submount.starts_with(&path) {
+            remount_adding_flags(submount, destination, mount_flags)?;
+        }
+    }
+    Ok(())
+}
+
+fn mount_tmpfs(
+    inner_path: &Path,
+    destination: &Path,
+    size: Option<SpaceUsage>,
+    mount_flags: libc::c_ulong,
+) -> Result<()> {
+    let tmpfs = os_str_to_c_string("tmpfs")?;
+    let destination_c_string = os_str_to_c_string(inner_path)?;
+    let data = os_str_to_c_string(
+        size.map(|size| format!("size={}", size.as_bytes()))
+            .unwrap_or_default(),
+    )?;
+    // A new mount takes all the flags at once, unlike a bind mount
+    let res = unsafe {
+        libc::mount(
+            tmpfs.as_ptr(),
+            destination_c_string.as_ptr(),
+            tmpfs.as_ptr(),
+            mount_flags,
+            data.as_ptr() as *const _,
+        )
+    };
+    if res == -1 {
+        Err(FFIError::MountError {
+            path: destination.to_path_buf(),
+            error: last_error_string(),
+        })
+    } else {
+        Ok(())
+    }
+}
+
+/// Mounts `overlay` at `new_root` itself, the rest of the mounts go on top of it
+pub(crate) fn mount_root_overlay(new_root: &Path, overlay: &Overlay) -> Result<()> {
+    mount_overlay(
+        overlay.lower(),
+        overlay.upper(),
+        overlay.work(),
+        new_root,
+        Path::new("/"),
+        libc::MS_NOSUID | libc::MS_NODEV,
+    )
+}
+
+const LOOP_CTL_GET_FREE: libc::c_ulong = 0x4C82;
+const LOOP_SET_FD: libc::c_ulong = 0x4C00;
+const LOOP_CLR_FD: libc::c_ulong = 0x4C01;
+const LOOP_SET_STATUS64: libc::c_ulong = 0x4C04;
+const LO_FLAGS_AUTOCLEAR: u32 = 4;
+
+#[repr(C)]
+struct LoopInfo64 {
+    lo_device: u64,
+    lo_inode: u64,
+    lo_rdevice: u64,
+    lo_offset: u64,
+    lo_sizelimit: u64,
+    lo_number: u32,
+    lo_encrypt_type: u32,
+    lo_encrypt_key_size: u32,
+    lo_flags: u32,
+    lo_file_name: [u8; 64],
+    lo_crypt_name: [u8; 64],
+    lo_encrypt_key: [u8; 32],
+    lo_init: [u64; 2],
+}
+
+/// Mounts the squashfs `image` read-only at `new_root` through a free loop device. The loop
+/// device is detached by the kernel once the mount is gone (with the mount namespace of the
+/// sandbox at the latest), or right away when the mount fails.
+pub(crate) fn mount_root_squashfs(new_root: &Path, image: &Path) -> Result<()> {
+    let loop_error = |error: io::Error| FFIError::LoopDeviceError {
+        path: image.to_path_buf(),
+        error: error.to_string(),
+    };
+    let image_file = File::open(image).map_err(loop_error)?;
+    let loop_control = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/loop-control")
+        .map_err(loop_error)?;
+    let (loop_path, loop_device) = loop {
+        let number = unsafe { libc::ioctl(loop_control.as_raw_fd(), LOOP_CTL_GET_FREE) };
+        if number == -1 {
+            return Err(loop_error(io::Error::last_os_error()));
+        }
+        let loop_path = PathBuf::from(format!("/dev/loop{}", number));
+        let loop_device = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&loop_path)
+            .map_err(loop_error)?;
+        if unsafe { libc::ioctl(loop_device.as_raw_fd(), LOOP_SET_FD, image_file.as_raw_fd()) }
+            != -1
+        {
+            break (loop_path, loop_device);
+        }
+        // Taken by someone else in the meantime
+        let error = io::Error::last_os_error();
+        if error.raw_os_error() != Some(libc::EBUSY) {
+            return Err(loop_error(error));
+        }
+    };
+
+    let mut loop_info = unsafe { mem::zeroed::<LoopInfo64>() };
+    loop_info.lo_flags = LO_FLAGS_AUTOCLEAR;
+    if unsafe { libc::ioctl(loop_device.as_raw_fd(), LOOP_SET_STATUS64, &loop_info) } == -1 {
+        let error = io::Error::last_os_error();
+        let _ = unsafe { libc::ioctl(loop_device.as_raw_fd(), LOOP_CLR_FD) };
+        return Err(loop_error(error));
+    }
+
+    let source = os_str_to_c_string(&loop_path)?;
+    let squashfs = os_str_to_c_string("squashfs")?;
+    let destination = os_str_to_c_string(new_root)?;
+    let res = unsafe {
+        libc::mount(
+            source.as_ptr(),
+            destination.as_ptr(),
+            squashfs.as_ptr(),
+            libc::MS_RDONLY | libc::MS_NOSUID | libc::MS_NODEV,
+            ptr::null(),
+        )
+    };
+    if res == -1 {
+        return Err(FFIError::MountError {
+            path: PathBuf::from("/"),
+            error: last_error_string(),
+        });
+    }
     Ok(())
 }
 
+/// Mounts a tmpfs at `new_root` and unpacks the tar archive `image` into it. The entries are
+/// made through directories resolved inside the new root, a symlink unpacked earlier can not
+/// take a later one out of it.
+pub(crate) fn mount_root_tarball(new_root: &Path, image: &Path) -> Result<()> {
+    mount_tmpfs(
+        new_root,
+        Path::new("/"),
+        None,
+        libc::MS_NOSUID | libc::MS_NODEV,
+    )?;
+    let file = File::open(image).map_err(|error| FFIError::UnpackError {
+        path: image.to_path_buf(),
+        error: error.to_string(),
+    })?;
+    let mut archive = Archive::new(io::BufReader::new(file));
+    loop {
+        let entry = archive
+            .next_entry()
+            .map_err(|error| FFIError::UnpackError {
+                path: image.to_path_buf(),
+                error: error.to_string(),
+            })?;
+        match entry {
+            Some(entry) => unpack_entry(new_root, &mut archive, &entry)?,
+            None => return Ok(()),
+        }
+    }
+}
+
+fn unpack_entry<R: Read>(new_root: &Path, archive: &mut Archive<R>, entry: &Entry) -> Result<()> {
+    let unpack_error = |error: String| FFIError::UnpackError {
+        path: entry.path().clone(),
+        error,
+    };
+    // Only the plain components, the archive can not climb out with `..` or `/`
+    let path: PathBuf = entry
+        .path()
+        .components()
+        .filter(|component| match *component {
+            Component::Normal(_) => true,
+            Component::Prefix(_)
+            | Component::RootDir
+            | Component::CurDir
+            | Component::ParentDir => false,
+        })
+        .collect();
+    let (parent, name) = match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) => (parent, name),
+        // The root itself
+        _ => return Ok(()),
+    };
+    let permissions = Permissions::from_mode(entry.mode());
+
+    match *entry.kind() {
+        EntryKind::Directory => {
+            let directory = open_mount_point(new_root, &path, true, true)
+                .map_err(|error| unpack_error(error.to_string()))?;
+            fs::set_permissions(directory.path(), permissions)
+                .map_err(|error| unpack_error(error.to_string()))
+        }
+        EntryKind::File => {
+            let parent = open_mount_point(new_root, parent, true, true)
+                .map_err(|error| unpack_error(error.to_string()))?;
+            let mut file = OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .custom_flags(libc::O_NOFOLLOW)
+                .open(parent.path().join(name))
+                .map_err(|error| unpack_error(error.to_string()))?;
+            archive
+                .copy_data(&mut file)
+                .and_then(|()| file.set_permissions(permissions))
+                .map_err(|error| unpack_error(error.to_string()))
+        }
+        EntryKind::Symlink(ref target) => {
+            let parent = open_mount_point(new_root, parent, true, true)
+                .map_err(|error| unpack_error(error.to_string()))?;
+            symlink(target, parent.path().join(name))
+                .map_err(|error| unpack_error(error.to_string()))
+        }
+        EntryKind::HardLink(ref target) => {
+            let parent = open_mount_point(new_root, parent, true, true)
+                .map_err(|error| unpack_error(error.to_string()))?;
+            let target = open_mount_point(new_root, target, false, false)
+                .map_err(|error| unpack_error(error.to_string()))?;
+            let target_c_string = os_str_to_c_string(target.path())?;
+            let link_c_string = os_str_to_c_string(parent.path().join(name))?;
+            // Through the descriptor of the target, the link is made to the file it is open on
+            let res = unsafe {
+                libc::linkat(
+                    libc::AT_FDCWD,
+                    target_c_string.as_ptr(),
+                    libc::AT_FDCWD,
+                    link_c_string.as_ptr(),
+                    libc::AT_SYMLINK_FOLLOW,
+                )
+            };
+            if res == -1 {
+                Err(unpack_error(last_error_string()))
+            } else {
+                Ok(())
+            }
+        }
+        EntryKind::Other => Ok(()),
+    }
+}
+
+fn mount_overlay(
+    lower: &[PathBuf],
+    upper: &Path,
+    work: &Path,
+    inner_path: &Path,
+    destination: &Path,
+    mount_flags: libc::c_ulong,
+) -> Result<()> {
+    // The option separators in the paths themselves are escaped with a backslash
+    let escape = |path: &Path| {
+        let mut escaped = Vec::new();
+        for &byte in path.as_os_str().as_bytes() {
+            if byte == b'\\' || byte == b':' || byte == b',' {
+                escaped.push(b'\\');
+            }
+            escaped.push(byte);
+        }
+        escaped
+    };
+    let mut data = b"lowerdir=".to_vec();
+    for (index, path) in lower.iter().enumerate() {
+        if index > 0 {
+            data.push(b':');
+        }
+        data.extend(escape(path));
+    }
+    data.extend_from_slice(b",upperdir=");
+    data.extend(escape(upper));
+    data.extend_from_slice(b",workdir=");
+    data.extend(escape(work));
+
+    let overlay = os_str_to_c_string("overlay")?;
+    let destination_c_string = os_str_to_c_string(inner_path)?;
+    let data = os_str_to_c_string(OsStr::from_bytes(&data))?;
+    let res = unsafe {
+        libc::mount(
+            overlay.as_ptr(),
+            destination_c_string.as_ptr(),
+            overlay.as_ptr(),
+            mount_flags,
+            data.as_ptr() as *const _,
+        )
+    };
+    if res != -1 {
+        return Ok(());
+    }
+
+    let error = last_error_string();
+    // Only root in the initial user namespace could mount one before
+    match kernel_release() {
+        Some(release)
+            if caller_uid() != 0
+                && matches!(kernel_version(&release), Some(version) if version < (5, 11)) =>
+        {
+            Err(FFIError::OverlayUnsupported {
+                path: destination.to_path_buf(),
+                release,
+            })
+        }
+        _ => Err(FFIError::MountError {
+            path: destination.to_path_buf(),
+            error,
+        }),
+    }
+}
+
+fn kernel_release() -> Option<String> {
+    let mut uts = unsafe { mem::zeroed::<libc::utsname>() };
+    if unsafe { libc::uname(&mut uts) } == -1 {
+        return None;
+    }
+    let release = unsafe { CStr::from_ptr(uts.release.as_ptr()) };
+    Some(release.to_string_lossy().into_owned())
+}
+
+/// The major and minor version in a release such as `5.10.0-21-amd64`
+fn kernel_version(release: &str) -> Option<(u32, u32)> {
+    let mut numbers = release.split(|c: char| !c.is_ascii_digit()).map(str::parse);
+    match (numbers.next(), numbers.next()) {
+        (Some(Ok(major)), Some(Ok(minor))) => Some((major, minor)),
+        _ => None,
+    }
+}
+
 const OLD_ROOT_NAME: &str = ".old_root";
-pub(crate) fn pivot_root<F>(new_root: &Path, before_umount: F) -> Result<()>
+pub(crate) fn pivot_root<F>(
+    new_root: &Path,
+    read_only_root: ReadOnlyRoot,
+    before_umount: F,
+) -> Result<()>
 where
     F: FnOnce() -> Result<()>,
 {
     let old_root = new_root.join(OLD_ROOT_NAME);
 
     // create an .old_root folder in case it doesn't exist
-    if !old_root.exists() {
-        fs::create_dir(&old_root).map_err(|error| FFIError::CreateDirError {
-            path: old_root.to_path_buf(),
-            error: error.to_string().into(),
-        })?;
+    let mut dirs = vec![old_root.clone()];
+    // and one for /proc to be mounted at, while the root can still be written to
+    if read_only_root == ReadOnlyRoot::Yes {
+        dirs.push(new_root.join("proc"));
+    }
+    for dir in &dirs {
+        if !dir.exists() {
+            fs::create_dir(dir).map_err(|error| FFIError::CreateDirError {
+                path: dir.to_path_buf(),
+                error: error.to_string().into(),
+            })?;
+        }
     }
 
-    let new_root_c_string = os_str_to_c_string(&new_root);
+    let new_root_c_string = os_str_to_c_string(&new_root)?;
     // bind mount it on top of itself (this is necessary for pivot_root to work)
     // it must also be a private mount (and everything under it as well)
     let res = unsafe {
@@ -364,6 +1981,10 @@ where
         });
     }
 
+    if read_only_root == ReadOnlyRoot::Yes {
+        remount_adding_flags(new_root, Path::new("/"), libc::MS_RDONLY)?;
+    }
+
     // Change directory first
     if unsafe { libc::chdir(new_root_c_string.as_ptr()) } == -1 {
         return Err(FFIError::ChdirError {
@@ -388,7 +2009,7 @@ where
 
     // And unmount .old_root
     let old_root = Path::new("/").join(OLD_ROOT_NAME);
-    let old_root_c_string = os_str_to_c_string(&old_root);
+    let old_root_c_string = os_str_to_c_string(&old_root)?;
     if unsafe { libc::umount2(old_root_c_string.as_ptr(), libc::MNT_DETACH) } == -1 {
         Err(FFIError::UMountError {
             path: old_root,
@@ -399,32 +2020,48 @@ where
     }
 }
 
-pub(crate) fn mount_proc() -> Result<()> {
-    let name = CString::new("proc").unwrap();
-    let path = PathBuf::from("/proc");
-
-    // create /proc in case it doesn't exist (likely first time we pivot_root)
-    if !path.exists() {
-        fs::create_dir(&path).map_err(|err| FFIError::CreateDirError {
-            path: path.clone(),
-            error: err.to_string().into(),
-        })?;
+// Adds `mount_flags` to the bind mount at `path`, only the one on top and not the ones under
+// it. Inside a user namespace the flags of the mount it was bound from can not be cleared, so
+// they are kept.
+fn remount_adding_flags(path: &Path, destination: &Path, mount_flags: libc::c_ulong) -> Result<()> {
+    let path_c_string = os_str_to_c_string(path)?;
+    let mut stat = unsafe { mem::zeroed::<libc::statvfs>() };
+    if unsafe { libc::statvfs(path_c_string.as_ptr(), &mut stat) } == -1 {
+        return Err(FFIError::MountError {
+            path: destination.to_path_buf(),
+            error: last_error_string(),
+        });
     }
-    let path_as_c_string = os_str_to_c_string(&path);
+    let locked_flags = [
+        (libc::ST_NOSUID, libc::MS_NOSUID),
+        (libc::ST_NODEV, libc::MS_NODEV),
+        (libc::ST_NOEXEC, libc::MS_NOEXEC),
+        (libc::ST_NOATIME, libc::MS_NOATIME),
+        (libc::ST_NODIRATIME, libc::MS_NODIRATIME),
+        (libc::ST_RELATIME, libc::MS_RELATIME),
+    ]
+    .iter()
+    .filter(|&&(st_flag, _)| stat.f_flag & st_flag != 0)
+    .fold(0, |flags, &(_, ms_flag)| flags | ms_flag);
+    // Only one of them is kept
+    let locked_flags = if mount_flags & libc::MS_NOATIME != 0 {
+        locked_flags & !libc::MS_RELATIME
+    } else {
+        locked_flags
+    };
 
     let res = unsafe {
         libc::mount(
-            name.as_ptr(),
-            path_as_c_string.as_ptr(),
-            name.as_ptr(),
-            0,
-            ptr::null_mut(),
+            ptr::null(),
+            path_c_string.as_ptr(),
+            ptr::null(),
+            libc::MS_REMOUNT | libc::MS_BIND | mount_flags | locked_flags,
+            ptr::null(),
         )
     };
-
     if res == -1 {
         Err(FFIError::MountError {
-            path,
+            path: destination.to_path_buf(),
             error: last_error_string(),
         })
     } else {
@@ -432,23 +2069,163 @@ pub(crate) fn mount_proc() -> Result<()> {
     }
 }
 
-const EXEC_RETRIES: usize = 10;
-const RETRY_DELAY: libc::c_uint = 50000;
-pub(crate) fn exec_command(
-    command: &Path,
-    arguments: &[&OsStr],
-    environment: &Environment,
-) -> Result<()> {
+// create /proc in case it doesn't exist (likely first time we pivot_root)
+fn create_proc_dir() -> Result<PathBuf> {
+    let path = PathBuf::from("/proc");
+    if !path.exists() {
+        fs::create_dir(&path).map_err(|err| FFIError::CreateDirError {
+            path: path.clone(),
+            error: err.to_string().into(),
+        })?;
+    }
+    Ok(path)
+}
+
+// Files of /proc with host wide information, hidden when the whole /proc is there
+const MASKED_PROC_PATHS: &[&str] = &[
+    "kcore",
+    "keys",
+    "latency_stats",
+    "sched_debug",
+    "sysrq-trigger",
+    "timer_list",
+    "timer_stats",
+];
+
+/// Mounts a proc filesystem at /proc, restricted by `options` when given (the supervisor
+/// needs to see all of it). The masked files are bind mounted over with the `/dev/null` of the
+/// old root while there is one, that of the new root may not exist.
+pub(crate) fn mount_proc(options: Option<ProcOptions>) -> Result<()> {
+    let name = CString::new("proc").unwrap();
+    let path = create_proc_dir()?;
+    let path_as_c_string = os_str_to_c_string(&path)?;
+
+    let mount = |data: &str| {
+        let data = CString::new(data).unwrap();
+        let res = unsafe {
+            libc::mount(
+                name.as_ptr(),
+                path_as_c_string.as_ptr(),
+                name.as_ptr(),
+                0,
+                data.as_ptr() as *const libc::c_void,
+            )
+        };
+        if res == -1 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    };
+    let mount_error = |error: io::Error| FFIError::MountError {
+        path: path.clone(),
+        error: error.to_string(),
+    };
+
+    let options = match options {
+        Some(options) => options,
+        None => return mount("").map_err(mount_error),
+    };
+    // Numbers rather than names, which only Linux 5.8 and later know
+    let hide_pid = match options.hide_pid() {
+        HidePid::Off => "hidepid=0",
+        HidePid::NoAccess => "hidepid=1",
+        HidePid::Invisible => "hidepid=2",
+    };
+    let res = if options.subset_pid() {
+        match mount(&format!("{},subset=pid", hide_pid)) {
+            // Before Linux 5.8 subset is an unknown option
+            Err(ref error) if error.raw_os_error() == Some(libc::EINVAL) => mount(hide_pid),
+            res => res,
+        }
+    } else {
+        mount(hide_pid)
+    };
+    res.map_err(mount_error)?;
+
+    if options.mask_paths() {
+        let old_root_null = Path::new("/").join(OLD_ROOT_NAME).join("dev/null");
+        let null_device = if old_root_null.exists() {
+            old_root_null
+        } else {
+            PathBuf::from("/dev/null")
+        };
+        let null_device_c_string = os_str_to_c_string(&null_device)?;
+        for masked in MASKED_PROC_PATHS {
+            let masked_path = path.join(masked);
+            if !masked_path.exists() {
+                continue;
+            }
+            let masked_path_c_string = os_str_to_c_string(&masked_path)?;
+            let res = unsafe {
+                libc::mount(
+                    null_device_c_string.as_ptr(),
+                    masked_path_c_string.as_ptr(),
+                    ptr::null(),
+                    libc::MS_BIND,
+                    ptr::null(),
+                )
+            };
+            if res == -1 {
+                return Err(FFIError::MountError {
+                    path: masked_path,
+                    error: last_error_string(),
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Bind mounts /proc of the old root over /proc, must be called before the old root is
+/// unmounted in `pivot_root`
+pub(crate) fn bind_old_root_proc() -> Result<()> {
+    let path = create_proc_dir()?;
+    let source = os_str_to_c_string(Path::new("/").join(OLD_ROOT_NAME).join("proc"))?;
+    let path_as_c_string = os_str_to_c_string(&path)?;
+
+    let res = unsafe {
+        libc::mount(
+            source.as_ptr(),
+            path_as_c_string.as_ptr(),
+            ptr::null_mut(),
+            libc::MS_BIND | libc::MS_REC,
+            ptr::null_mut(),
+        )
+    };
+
+    if res == -1 {
+        Err(FFIError::MountError {
+            path,
+            error: last_error_string(),
+        })
+    } else {
+        Ok(())
+    }
+}
+
+pub(crate) fn send_signal(pid: libc::pid_t, signal: libc::c_int) {
+    let _ = unsafe { libc::kill(pid, signal) };
+}
+
+const EXEC_RETRIES: usize = 10;
+const RETRY_DELAY: libc::c_uint = 50000;
+pub(crate) fn exec_command(
+    command: &Path,
+    arguments: &[&OsStr],
+    environment: &Environment,
+    missing_interpreter: Option<&MissingInterpreter>,
+) -> Result<()> {
     let arguments_c_string: Vec<_> = iter::once(os_str_to_c_string(command))
         .chain(arguments.iter().map(os_str_to_c_string)) // convert to C pointers
-        .collect();
+        .collect::<Result<_>>()?;
 
     let arguments_with_null_ending: Vec<_> = arguments_c_string
         .iter()
         .map(|c_string| c_string.as_ptr())
         .chain(iter::once(ptr::null())) // add an ending NULL
         .collect();
-    let command_as_c_string = os_str_to_c_string(command);
+    let command_as_c_string = os_str_to_c_string(command)?;
     let environment = match environment {
         Environment::Forward => None,
         Environment::EnvList(list) => {
@@ -456,12 +2233,13 @@ pub(crate) fn exec_command(
                 .iter()
                 .map(|(key, value)| key.to_owned() + "=" + value)
                 .map(os_str_to_c_string)
-                .collect();
+                .collect::<Result<_>>()?;
             Some(envs_c_string)
         }
     };
 
     for retry in 0..EXEC_RETRIES {
+        send_exec_starting();
         let res = unsafe {
             let command = command_as_c_string.as_ptr();
             let args = arguments_with_null_ending.as_slice().as_ptr();
@@ -480,6 +2258,16 @@ pub(crate) fn exec_command(
 
         if res == -1 {
             let error = errno::Errno::last_error();
+            if error.error_code() == libc::ENOENT {
+                // The command exists, but the interpreter the kernel runs it with does not
+                if let Some(missing_interpreter) = missing_interpreter {
+                    return Err(FFIError::MissingBinfmtInterpreter {
+                        command: command.to_path_buf(),
+                        interpreter: missing_interpreter.interpreter().to_path_buf(),
+                        arch: missing_interpreter.arch().to_owned(),
+                    });
+                }
+            }
             if error.error_code() != libc::ETXTBSY || retry == EXEC_RETRIES - 1 {
                 return Err(FFIError::ExecError {
                     command: command.to_path_buf(),
@@ -504,240 +2292,3037 @@ pub(crate) fn exec_command(
 pub(crate) struct Fd(libc::c_int, &'static str, libc::c_int, libc::c_int);
 
 pub(crate) const STDIN: &Fd = &Fd(0, "stdin", libc::O_RDONLY, 0);
-pub(crate) const STDOUT: &Fd = &Fd(
-    1,
-    "stdout",
-    libc::O_WRONLY | libc::O_CREAT | libc::O_TRUNC,
-    0o666,
-);
-pub(crate) const STDERR: &Fd = &Fd(
-    2,
-    "stderr",
-    libc::O_WRONLY | libc::O_CREAT | libc::O_TRUNC,
-    0o666,
-);
-
-pub(crate) fn redirect_fd(fd: &Fd, path: &Path) -> Result<()> {
-    let path_as_c_string = os_str_to_c_string(path);
-    match unsafe { libc::open(path_as_c_string.as_ptr(), fd.2, fd.3) } {
-        -1 => Err(FFIError::OpenFdError {
-            fd: fd.0,
-            name: fd.1.into(),
-            error: last_error_string(),
-        }),
-        x => {
-            if x != fd.0 && unsafe { libc::dup2(x, fd.0) } == -1 {
-                Err(FFIError::DupFdError {
-                    fd: fd.0,
-                    name: fd.1.into(),
-                    error: last_error_string(),
-                })
-            } else {
-                Ok(())
+pub(crate) const STDOUT: &Fd = &Fd(1, "stdout", libc::O_WRONLY, 0o666);
+pub(crate) const STDERR: &Fd = &Fd(2, "stderr", libc::O_WRONLY, 0o666);
+
+/// How often a writer of a FIFO checks whether a reader opened it, see `open_fifo`
+const FIFO_OPEN_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+// Opens a FIFO like open, -1 with errno set on failure, but waits at most `timeout` for the
+// other end. A writer opened with O_NONBLOCK fails with ENXIO until there is a reader, so it
+// is tried again until then. A reader opened with O_NONBLOCK does not wait for a writer at
+// all and its reads get EOF until one comes, so it is a blocking open interrupted by SIGALRM.
+fn open_fifo(
+    path: &Path,
+    flags: libc::c_int,
+    permissions: libc::c_int,
+    timeout: Duration,
+) -> Result<libc::c_int> {
+    let path_as_c_string = os_str_to_c_string(path)?;
+    let timeout_error = || FFIError::RedirectTimeout {
+        path: path.to_path_buf(),
+    };
+    let start = Instant::now();
+    if flags & libc::O_ACCMODE == libc::O_RDONLY {
+        let interval = timeout.as_secs() as libc::time_t * 1_000_000
+            + libc::time_t::from(timeout.subsec_micros());
+        // An interval of zero would disarm the timer instead
+        set_alarm_interval(cmp::max(interval, 1))?;
+        let opened = loop {
+            match unsafe { libc::open(path_as_c_string.as_ptr(), flags, permissions) } {
+                -1 if errno::Errno::last_error().error_code() == libc::EINTR => {
+                    if start.elapsed() >= timeout {
+                        break None;
+                    }
+                }
+                x => break Some(x),
+            }
+        };
+        // The timer would outlive the exec otherwise
+        set_alarm_interval(0)?;
+        return opened.ok_or_else(timeout_error);
+    }
+    loop {
+        match unsafe {
+            libc::open(
+                path_as_c_string.as_ptr(),
+                flags | libc::O_NONBLOCK,
+                permissions,
+            )
+        } {
+            -1 if errno::Errno::last_error().error_code() == libc::ENXIO => {
+                if start.elapsed() >= timeout {
+                    return Err(timeout_error());
+                }
+                thread::sleep(FIFO_OPEN_POLL_INTERVAL);
+            }
+            -1 => return Ok(-1),
+            x => {
+                // The command expects the blocking writes it would get otherwise
+                let status_flags = unsafe { libc::fcntl(x, libc::F_GETFL) };
+                if status_flags == -1
+                    || unsafe { libc::fcntl(x, libc::F_SETFL, status_flags & !libc::O_NONBLOCK) }
+                        == -1
+                {
+                    let _ = unsafe { libc::close(x) };
+                    return Ok(-1);
+                }
+                return Ok(x);
             }
         }
     }
 }
 
-pub(crate) fn move_to_different_process_group() -> Result<()> {
-    if unsafe { libc::setpgid(0, 0) } == -1 {
-        Err(FFIError::SetpgidError {
-            pid: 0,
-            pgid: 0,
+// The opened file is left only at `fd`, a second descriptor of a FIFO kept open across exec
+// means the peer never sees EOF or EPIPE when the command closes its end
+// `pipe` is the end of the command of the pipe made for a captured or piped stream, or the
+// memfd holding the bytes of stdin
+pub(crate) fn redirect_fd(
+    fd: &Fd,
+    redirect: &Redirect,
+    pipe: Option<&File>,
+    fifo_open_timeout: Option<Duration>,
+) -> Result<()> {
+    let open_error = || FFIError::OpenFdError {
+        fd: fd.0,
+        name: fd.1.into(),
+        error: last_error_string(),
+    };
+    let opened = match (redirect, pipe) {
+        (Redirect::Capture { .. }, Some(pipe))
+        | (Redirect::Tee { .. }, Some(pipe))
+        | (Redirect::Pipe, Some(pipe))
+        | (Redirect::Bytes(_), Some(pipe)) => {
+            match unsafe { libc::fcntl(pipe.as_raw_fd(), libc::F_DUPFD_CLOEXEC, 0) } {
+                -1 => return Err(open_error()),
+                x => x,
+            }
+        }
+        // Applied after stdout, it is already where it goes
+        (Redirect::ToStdout, _) => {
+            match unsafe { libc::fcntl(STDOUT.0, libc::F_DUPFD_CLOEXEC, 0) } {
+                -1 => return Err(open_error()),
+                x => x,
+            }
+        }
+        (Redirect::Inherit, _)
+        | (Redirect::Capture { .. }, None)
+        | (Redirect::Tee { .. }, None)
+        | (Redirect::Pipe, None)
+        | (Redirect::Bytes(_), None) => return Ok(()),
+        (Redirect::Null, _) => open_null(fd).ok_or_else(open_error)?,
+        (Redirect::File(path, options), _) => {
+            let flags = match (fd.2, options.mode()) {
+                (libc::O_RDONLY, _) => fd.2,
+                (_, OutputMode::Truncate) => fd.2 | libc::O_CREAT | libc::O_TRUNC,
+                (_, OutputMode::Append) => fd.2 | libc::O_CREAT | libc::O_APPEND,
+                // Fails on a symlink as well, even a dangling one
+                (_, OutputMode::CreateNew) => fd.2 | libc::O_CREAT | libc::O_EXCL,
+            };
+            let permissions = options
+                .permissions()
+                .map_or(fd.3, |permissions| permissions as libc::c_int);
+            let is_fifo = fs::metadata(path)
+                .ok()
+                .map_or(false, |metadata| metadata.file_type().is_fifo());
+            let opened = match fifo_open_timeout {
+                Some(timeout) if is_fifo => {
+                    open_fifo(path, flags | libc::O_CLOEXEC, permissions, timeout)?
+                }
+                _ => {
+                    let path_as_c_string = os_str_to_c_string(path)?;
+                    unsafe {
+                        libc::open(
+                            path_as_c_string.as_ptr(),
+                            flags | libc::O_CLOEXEC,
+                            permissions,
+                        )
+                    }
+                }
+            };
+            match opened {
+                -1 if options.mode() == OutputMode::CreateNew
+                    && errno::Errno::last_error().error_code() == libc::EEXIST =>
+                {
+                    return Err(FFIError::RedirectFileExists {
+                        name: fd.1.into(),
+                        path: path.clone(),
+                    })
+                }
+                -1 => return Err(open_error()),
+                x => x,
+            }
+        }
+    };
+    if opened == fd.0 {
+        // Landed on the fd itself (it was closed), just keep it open across exec
+        if unsafe { libc::fcntl(opened, libc::F_SETFD, 0) } == -1 {
+            return Err(FFIError::DupFdError {
+                fd: fd.0,
+                name: fd.1.into(),
+                error: last_error_string(),
+            });
+        }
+        return Ok(());
+    }
+    // dup2 clears close-on-exec on the duplicate
+    let result = if unsafe { libc::dup2(opened, fd.0) } == -1 {
+        Err(FFIError::DupFdError {
+            fd: fd.0,
+            name: fd.1.into(),
             error: last_error_string(),
         })
     } else {
         Ok(())
-    }
+    };
+    let _ = unsafe { libc::close(opened) };
+    result
 }
 
-fn sys_pivot_root(new_root: &Path, old_root: &Path) -> Result<()> {
-    let new_root_c_string = os_str_to_c_string(new_root);
-    let old_root_c_string = os_str_to_c_string(old_root);
-    match unsafe {
-        libc::syscall(
-            libc::SYS_pivot_root,
-            new_root_c_string.as_ptr(),
-            old_root_c_string.as_ptr(),
-        )
-    } {
-        -1 => {
-            let errno = errno::Errno::last_error();
-            let error = match errno.error_code() {
-                libc::EBUSY => "new_root or put_old are on the current root\
-                                filesystem, or a filesystem is already\
-                                mounted on put_old."
-                    .into(),
-                libc::EINVAL => "put_old is not underneath new_root".into(),
-                libc::ENOTDIR => "new_root or put_old is not a directory".into(),
-                libc::EPERM => "The calling process does not have the\
-                                CAP_SYS_ADMIN capability"
-                    .into(),
-                _ => errno.error_string(),
-            };
+// Never made when missing. Without it the end of a pipe `fd` would use, with the other one
+// closed.
+fn open_null(fd: &Fd) -> Option<RawFd> {
+    let access = fd.2 & libc::O_ACCMODE;
+    let null = CString::new("/dev/null").unwrap();
+    let opened = unsafe { libc::open(null.as_ptr(), access | libc::O_CLOEXEC) };
+    if opened != -1 {
+        return Some(opened);
+    }
 
-            Err(FFIError::PivotRootError {
-                new_root: new_root.to_path_buf(),
-                old_root: old_root.to_path_buf(),
-                error,
-            })
-        }
-        _ => Ok(()),
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } == -1 {
+        return None;
     }
+    let (kept, closed) = if access == libc::O_RDONLY {
+        (fds[0], fds[1])
+    } else {
+        (fds[1], fds[0])
+    };
+    let _ = unsafe { libc::close(closed) };
+    Some(kept)
 }
 
-pub(crate) fn kill_on_parent_death() -> Result<()> {
-    if unsafe { libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGKILL) } == -1 {
-        Err(FFIError::PrSetPDeathSigError(last_error_string()))
-    } else {
-        Ok(())
-    }
+/// Holds the free ones of the target fds of `Config::extra_fds` while the sandbox starts, so
+/// none of the files it opens along the way ends up at one, where the child would overwrite it
+pub(crate) struct FdReservation {
+    _reserved: Vec<File>,
 }
 
-pub(crate) fn set_stack_limit(stack: Option<SpaceUsage>) -> Result<()> {
-    let rlimit = if let Some(usage) = stack {
-        libc::rlimit {
-            rlim_cur: usage.as_bytes(),
-            rlim_max: usage.as_bytes(),
+impl FdReservation {
+    pub(crate) fn new(fds: &[RawFd]) -> Result<Self> {
+        let mut reserved = Vec::new();
+        if fds.is_empty() {
+            return Ok(Self {
+                _reserved: reserved,
+            });
         }
-    } else {
-        libc::rlimit {
-            rlim_cur: libc::RLIM_INFINITY,
-            rlim_max: libc::RLIM_INFINITY,
+        let root = os_str_to_c_string("/")?;
+        let placeholder = match unsafe { libc::open(root.as_ptr(), libc::O_PATH | libc::O_CLOEXEC) }
+        {
+            -1 => {
+                return Err(FFIError::ExtraFdError {
+                    fd: fds[0],
+                    error: last_error_string(),
+                })
+            }
+            x => unsafe { File::from_raw_fd(x) },
+        };
+        for &fd in fds {
+            // The lowest free one from `fd` on, which is `fd` itself only if it is free. Unlike
+            // dup2 it never closes what another thread of the caller just opened there.
+            match unsafe { libc::fcntl(placeholder.as_raw_fd(), libc::F_DUPFD_CLOEXEC, fd) } {
+                -1 => {
+                    return Err(FFIError::ExtraFdError {
+                        fd,
+                        error: last_error_string(),
+                    })
+                }
+                x if x == fd => reserved.push(unsafe { File::from_raw_fd(x) }),
+                x => {
+                    let _ = unsafe { libc::close(x) };
+                }
+            }
         }
-    };
-
-    if unsafe { libc::setrlimit(libc::RLIMIT_STACK, &rlimit) } == -1 {
-        Err(FFIError::SetRLimitError(last_error_string()))
-    } else {
-        Ok(())
+        Ok(Self {
+            _reserved: reserved,
+        })
     }
 }
 
-mod errno {
-    use libc;
-    use std::ffi::CStr;
-
-    #[derive(Debug)]
-    pub(crate) struct Errno(libc::c_int);
-
-    impl Errno {
-        pub(crate) fn last_error() -> Self {
-            unsafe { Self(*libc::__errno_location()) }
+/// Marks every file descriptor above the standard streams close-on-exec, but the ones in
+/// `keep`, so the command gets none of the ones the caller had open. The ones the jail still
+/// uses until the exec (the result pipe, the seccomp channel) stay open until then.
+pub(crate) fn close_inherited_fds(keep: &[RawFd]) -> Result<()> {
+    let mut keep: Vec<RawFd> = keep.iter().cloned().filter(|&fd| fd > STDERR.0).collect();
+    keep.sort();
+    // The ranges between the kept ones, the last one up to the largest there can be
+    let mut ranges = Vec::new();
+    let mut first = STDERR.0 + 1;
+    for &fd in &keep {
+        if fd > first {
+            ranges.push((first as libc::c_uint, (fd - 1) as libc::c_uint));
+        }
+        first = fd + 1;
+    }
+    ranges.push((first as libc::c_uint, libc::c_uint::MAX));
+    for &(first, last) in &ranges {
+        if unsafe {
+            libc::syscall(
+                libc::SYS_close_range,
+                first,
+                last,
+                libc::CLOSE_RANGE_CLOEXEC,
+            )
+        } == -1
+        {
+            return match errno::Errno::last_error().error_code() {
+                // Before Linux 5.11 (5.9 without the flag)
+                libc::ENOSYS | libc::EINVAL => close_inherited_fds_listed(&keep),
+                _ => Err(FFIError::CloseFdsError(last_error_string())),
+            };
         }
+    }
+    Ok(())
+}
 
-        pub(crate) fn error_code(&self) -> libc::c_int {
-            self.0
+// Without close_range the open file descriptors are listed instead
+fn close_inherited_fds_listed(keep: &[RawFd]) -> Result<()> {
+    // Collected first, the listing has a descriptor of its own while it lasts
+    let fds: Vec<RawFd> = fs::read_dir("/proc/self/fd")
+        .map_err(|err| FFIError::CloseFdsError(err.to_string()))?
+        .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok())
+        .collect();
+    for fd in fds
+        .into_iter()
+        .filter(|&fd| fd > STDERR.0 && !keep.contains(&fd))
+    {
+        // The one of the listing is closed by now and fails with EBADF
+        let flags = unsafe { libc::fcntl(fd, libc::F_GETFD) };
+        if flags != -1 {
+            let _ = unsafe { libc::fcntl(fd, libc::F_SETFD, flags | libc::FD_CLOEXEC) };
         }
+    }
+    Ok(())
+}
 
-        pub(crate) fn error_string(&self) -> String {
-            let buffer = &mut [0i8; 256];
-            if unsafe { libc::strerror_r(self.0, buffer.as_mut_ptr(), buffer.len()) } == -1 {
-                return "unexpected strerror_r error".into();
+/// Puts each source of `Config::extra_fds` at its target. They all go through temporary fds
+/// above the targets first, so a source that is the target of another pair is not overwritten
+/// before it is moved.
+pub(crate) fn set_up_extra_fds(extra_fds: &[(RawFd, FdSource)]) -> Result<()> {
+    let floor = match extra_fds.iter().map(|&(fd, _)| fd).max() {
+        Some(max_fd) => max_fd + 1,
+        None => return Ok(()),
+    };
+    let mut temporary_fds = Vec::new();
+    for &(fd, ref source) in extra_fds {
+        let extra_fd_error = |error| FFIError::ExtraFdError { fd, error };
+        let temporary_fd = match *source {
+            FdSource::Path { ref path, flags } => {
+                let path_c_string = os_str_to_c_string(path)?;
+                let opened = match unsafe {
+                    libc::open(path_c_string.as_ptr(), flags | libc::O_CLOEXEC, 0o666)
+                } {
+                    -1 => {
+                        return Err(extra_fd_error(format!(
+                            "could not open {:?}: {}",
+                            path,
+                            last_error_string()
+                        )))
+                    }
+                    x => x,
+                };
+                let moved = unsafe { libc::fcntl(opened, libc::F_DUPFD_CLOEXEC, floor) };
+                let error = last_error_string();
+                let _ = unsafe { libc::close(opened) };
+                match moved {
+                    -1 => return Err(extra_fd_error(error)),
+                    x => x,
+                }
             }
-            unsafe {
-                CStr::from_ptr(buffer.as_ptr())
-                    .to_string_lossy()
-                    .into_owned()
+            FdSource::Fd(source_fd) => {
+                match unsafe { libc::fcntl(source_fd, libc::F_DUPFD_CLOEXEC, floor) } {
+                    -1 => return Err(extra_fd_error(last_error_string())),
+                    x => x,
+                }
+            }
+        };
+        temporary_fds.push((fd, temporary_fd));
+    }
+    for (fd, temporary_fd) in temporary_fds {
+        // dup2 clears close-on-exec on the target
+        let result = unsafe { libc::dup2(temporary_fd, fd) };
+        let error = last_error_string();
+        let _ = unsafe { libc::close(temporary_fd) };
+        if result == -1 {
+            return Err(FFIError::ExtraFdError { fd, error });
+        }
+    }
+    // The donated fds are inherited at their own numbers as well, which the command is not
+    // meant to get
+    for (_, source) in extra_fds {
+        if let FdSource::Fd(source_fd) = *source {
+            if extra_fds.iter().all(|&(fd, _)| fd != source_fd) {
+                let _ = unsafe { libc::fcntl(source_fd, libc::F_SETFD, libc::FD_CLOEXEC) };
             }
         }
     }
+    Ok(())
 }
 
-fn last_error_string() -> String {
-    errno::Errno::last_error().error_string()
+/// The pipe of a captured stream, made by the supervisor before the clone so the child
+/// inherits the end it writes to
+pub(crate) struct CapturePipe {
+    name: &'static str,
+    reader: File,
+    writer: File,
+    limit: SpaceUsage,
+    tail: SpaceUsage,
 }
 
-fn make_pipe() -> Result<(File, File)> {
-    unsafe {
-        let fd = &mut [0; 2];
-        if libc::pipe2(fd.as_mut_ptr(), libc::O_CLOEXEC) == -1 {
-            Err(FFIError::Pipe2Error(last_error_string()))
-        } else {
-            Ok((File::from_raw_fd(fd[0]), File::from_raw_fd(fd[1])))
-        }
+impl CapturePipe {
+    /// Keeps the first `limit` bytes of the output, and the last `tail` ones
+    pub(crate) fn new(fd: &Fd, limit: SpaceUsage, tail: SpaceUsage) -> Result<Self> {
+        let (reader, writer) = make_pipe()?;
+        Ok(Self {
+            name: fd.1,
+            reader,
+            writer,
+            limit,
+            tail,
+        })
+    }
+
+    pub(crate) fn writer(&self) -> &File {
+        &self.writer
+    }
+
+    /// Reads the pipe on a thread of its own while the supervisor waits, the command would
+    /// block on a full pipe otherwise. Past the limit the output is still read, and dropped.
+    /// All of it is added to `written`.
+    pub(crate) fn drain(self, written: &Arc<AtomicU64>) -> Result<CaptureDrain> {
+        let Self {
+            name,
+            reader,
+            writer,
+            limit,
+            tail,
+        } = self;
+        // The end of the child is the only one left, the read ends once all the jail let go
+        // of it
+        drop(writer);
+        let capture_error = |err: io::Error| FFIError::CaptureError {
+            name: name.to_owned(),
+            error: err.to_string(),
+        };
+        let reader = CountingReader {
+            inner: reader,
+            counted: Arc::clone(written),
+        };
+        let handle = spawn_supervisor_thread(name, move || {
+            let mut data = Vec::new();
+            let mut reader = reader.take(limit.as_bytes());
+            let _ = reader.read_to_end(&mut data)?;
+            let mut reader = reader.into_inner();
+            // Past the limit only the end of what is dropped is kept
+            let tail = tail.as_bytes() as usize;
+            let mut last = data[data.len().saturating_sub(tail)..].to_vec();
+            let mut dropped = false;
+            let mut buf = [0; 8 * 1_024];
+            loop {
+                let read = match reader.read(&mut buf) {
+                    Ok(0) => break,
+                    Ok(read) => read,
+                    Err(ref err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(err) => return Err(err),
+                };
+                dropped = true;
+                last.extend_from_slice(&buf[..read]);
+                let excess = last.len().saturating_sub(tail);
+                let _ = last.drain(..excess);
+            }
+            Ok((CapturedOutput::new(data, dropped), last))
+        })
+        .map_err(capture_error)?;
+        Ok(CaptureDrain { name, handle })
     }
 }
 
-// This should not fail on linux, considering valid os strings do not contain null
-fn os_str_to_c_string<T: AsRef<OsStr>>(os_str: T) -> CString {
-    CString::new(os_str.as_ref().as_bytes()).unwrap()
+pub(crate) struct CaptureDrain {
+    name: &'static str,
+    handle: thread::JoinHandle<io::Result<(CapturedOutput, Vec<u8>)>>,
 }
 
-pub(crate) struct CloneHandle<T> {
-    pid: libc::pid_t,
-    read_error_pipe: File,
-    phantom: PhantomData<T>,
+impl CaptureDrain {
+    /// The output, and the tail kept of it (empty for the pipes to the caller)
+    pub(crate) fn join(self) -> Result<(CapturedOutput, Vec<u8>)> {
+        let name = self.name;
+        match self.handle.join() {
+            Ok(captured) => captured.map_err(|err| FFIError::CaptureError {
+                name: name.to_owned(),
+                error: err.to_string(),
+            }),
+            Err(_) => Err(FFIError::CaptureError {
+                name: name.to_owned(),
+                error: "the reading thread panicked".to_owned(),
+            }),
+        }
+    }
 }
 
-impl<T: DeserializeOwned> CloneHandle<T> {
-    pub(crate) fn wait<F: Fn(Duration) -> StdResult<RunUsage, Error>>(
-        mut self,
-        limits: Limits,
-        usage: F,
-    ) -> StdResult<RunInfo<Option<T>>, Error> {
-        let timer = Instant::now();
-        let mut data = Vec::new();
-        let _ = self
-            .read_error_pipe
-            .read_to_end(&mut data)
-            .map_err(|err| Error::DeserializeError(err.to_string().into()))?;
-        let result = if !data.is_empty() {
-            Some(
-                bincode::deserialize(&data)
-                    .map_err(|err| Error::DeserializeError(err.to_string().into()))?,
-            )
-        } else {
-            None
-        };
+/// The last `size` bytes of the file at `path`, or all of it when smaller
+pub(crate) fn read_file_tail(path: &Path, size: SpaceUsage) -> io::Result<Vec<u8>> {
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+    let _ = file.seek(SeekFrom::Start(len.saturating_sub(size.as_bytes())))?;
+    let mut tail = Vec::new();
+    let _ = file.take(size.as_bytes()).read_to_end(&mut tail)?;
+    Ok(tail)
+}
 
+// How many chunks `Config::on_output` may be behind before the next ones are dropped, and how
+// much is read at once (what a pipe holds by default)
+const TEE_BACKLOG: usize = 16;
+const TEE_CHUNK: usize = 64 * 1_024;
+
+/// The pipe of a teed stream (`Redirect::Tee`), made before the clone like a `CapturePipe`.
+/// The file is opened here as well, so the command does not start when it can not be.
+pub(crate) struct TeePipe {
+    name: &'static str,
+    reader: File,
+    writer: File,
+    file: File,
+    limit: Option<SpaceUsage>,
+}
+
+impl TeePipe {
+    pub(crate) fn new(fd: &Fd, path: &Path, limit: Option<SpaceUsage>) -> Result<Self> {
+        let file = File::create(path).map_err(|err| FFIError::CaptureError {
+            name: fd.1.to_owned(),
+            error: format!("{}: {}", path.display(), err),
+        })?;
+        let (reader, writer) = make_pipe()?;
+        Ok(Self {
+            name: fd.1,
+            reader,
+            writer,
+            file,
+            limit,
+        })
+    }
+
+    pub(crate) fn writer(&self) -> &File {
+        &self.writer
+    }
+
+    /// Writes what the command writes to the file on a thread of its own, and hands it over to
+    /// `on_output` on another one. The file comes first: the chunks that find `on_output`
+    /// too far behind are dropped and added to `dropped`. Past the limit of the file the
+    /// output is still read and passed on. All of it is added to `written`.
+    pub(crate) fn tee(
+        self,
+        written: &Arc<AtomicU64>,
+        dropped: &Arc<AtomicU64>,
+        on_output: Option<&OutputCallback>,
+    ) -> Result<TeeDrain> {
+        let Self {
+            name,
+            reader,
+            writer,
+            mut file,
+            limit,
+        } = self;
+        drop(writer);
+        let tee_error = |err: io::Error| FFIError::CaptureError {
+            name: name.to_owned(),
+            error: err.to_string(),
+        };
+        let (sender, consumer) = match on_output {
+            Some(on_output) => {
+                let (sender, receiver) = mpsc::sync_channel::<Vec<u8>>(TEE_BACKLOG);
+                let on_output = on_output.clone();
+                let consumer = spawn_supervisor_thread(name, move || {
+                    for data in receiver {
+                        on_output.call(&data);
+                    }
+                })
+                .map_err(tee_error)?;
+                (Some(sender), Some(consumer))
+            }
+            None => (None, None),
+        };
+        let mut reader = CountingReader {
+            inner: reader,
+            counted: Arc::clone(written),
+        };
+        let dropped = Arc::clone(dropped);
+        let handle = spawn_supervisor_thread(name, move || {
+            let mut buffer = vec![0; TEE_CHUNK];
+            let mut left = limit.map(SpaceUsage::as_bytes);
+            loop {
+                let read = match reader.read(&mut buffer) {
+                    Ok(0) => return Ok(()),
+                    Ok(read) => read,
+                    Err(ref err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(err) => return Err(err),
+                };
+                let kept = left.map_or(read, |left| cmp::min(left, read as u64) as usize);
+                file.write_all(&buffer[..kept])?;
+                if let Some(ref mut left) = left {
+                    *left -= kept as u64;
+                }
+                if let Some(ref sender) = sender {
+                    // Full, or the callback panicked and nothing takes it any more
+                    if sender.try_send(buffer[..read].to_vec()).is_err() {
+                        let _ = dropped.fetch_add(read as u64, Ordering::SeqCst);
+                    }
+                }
+            }
+        })
+        .map_err(tee_error)?;
+        Ok(TeeDrain {
+            name,
+            handle,
+            consumer,
+        })
+    }
+}
+
+pub(crate) struct TeeDrain {
+    name: &'static str,
+    handle: thread::JoinHandle<io::Result<()>>,
+    consumer: Option<thread::JoinHandle<()>>,
+}
+
+impl TeeDrain {
+    pub(crate) fn join(self) -> Result<()> {
+        let name = self.name;
+        let tee_error = |error: String| FFIError::CaptureError {
+            name: name.to_owned(),
+            error,
+        };
+        // The end of the channel goes with the first thread, the second one then runs out
+        let teed = match self.handle.join() {
+            Ok(teed) => teed.map_err(|err| tee_error(err.to_string())),
+            Err(_) => Err(tee_error("the teeing thread panicked".to_owned())),
+        };
+        if let Some(consumer) = self.consumer {
+            consumer
+                .join()
+                .map_err(|_| tee_error("the output callback panicked".to_owned()))?;
+        }
+        teed
+    }
+}
+
+/// The end of the caller of a pipe from an output of the command (`Redirect::Pipe`), read in
+/// place through the `JailHandle` without blocking. What was read is kept, so `drain` gives all
+/// of the output back in the end.
+pub(crate) struct OutputReader {
+    name: &'static str,
+    end: File,
+    read: Vec<u8>,
+    eof: bool,
+}
+
+impl OutputReader {
+    pub(crate) fn new(fd: &Fd, end: File) -> Result<Self> {
+        set_nonblocking(&end, true).map_err(|err| FFIError::CaptureError {
+            name: fd.1.to_owned(),
+            error: err.to_string(),
+        })?;
+        Ok(Self {
+            name: fd.1,
+            end,
+            read: Vec::new(),
+            eof: false,
+        })
+    }
+
+    /// What is in the pipe, 0 bytes when nothing is, `None` at EOF
+    pub(crate) fn read_available(&mut self, buf: &mut [u8]) -> Result<Option<usize>> {
+        while !self.eof {
+            match (&self.end).read(buf) {
+                Ok(0) if !buf.is_empty() => self.eof = true,
+                Ok(read) => {
+                    self.read.extend_from_slice(&buf[..read]);
+                    return Ok(Some(read));
+                }
+                Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(Some(0)),
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => {
+                    return Err(FFIError::CaptureError {
+                        name: self.name.to_owned(),
+                        error: err.to_string(),
+                    })
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Reads the rest on a thread of its own while the caller waits, the command would block
+    /// on a full pipe otherwise
+    pub(crate) fn drain(self) -> Result<CaptureDrain> {
+        let Self {
+            name,
+            end,
+            mut read,
+            eof,
+        } = self;
+        let capture_error = |err: io::Error| FFIError::CaptureError {
+            name: name.to_owned(),
+            error: err.to_string(),
+        };
+        set_nonblocking(&end, false).map_err(capture_error)?;
+        let handle = thread::Builder::new()
+            .name(name.to_owned())
+            .spawn(move || {
+                if !eof {
+                    let _ = (&end).read_to_end(&mut read)?;
+                }
+                Ok((CapturedOutput::new(read, false), Vec::new()))
+            })
+            .map_err(capture_error)?;
+        Ok(CaptureDrain { name, handle })
+    }
+}
+
+fn set_nonblocking(file: &File, nonblocking: bool) -> io::Result<()> {
+    let status_flags = unsafe { libc::fcntl(file.as_raw_fd(), libc::F_GETFL) };
+    if status_flags == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    let status_flags = if nonblocking {
+        status_flags | libc::O_NONBLOCK
+    } else {
+        status_flags & !libc::O_NONBLOCK
+    };
+    if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_SETFL, status_flags) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// A pipe in front of the end of the command of a `StdioPipe` for an output, so the supervisor
+/// sees (and counts) what goes through it when `Limits::output` is set
+pub(crate) struct RelayPipe {
+    name: &'static str,
+    reader: File,
+    writer: File,
+}
+
+impl RelayPipe {
+    pub(crate) fn new(fd: &Fd) -> Result<Self> {
+        let (reader, writer) = make_pipe()?;
+        Ok(Self {
+            name: fd.1,
+            reader,
+            writer,
+        })
+    }
+
+    pub(crate) fn writer(&self) -> &File {
+        &self.writer
+    }
+
+    /// Passes what the command writes on to `to` on a thread of its own, adding it to
+    /// `written`. Once writing to `to` fails (the caller dropped its end) the pipe is closed,
+    /// so the command gets `EPIPE` as it would without the relay.
+    pub(crate) fn relay(self, to: &File, written: &Arc<AtomicU64>) -> Result<RelayDrain> {
+        let Self {
+            name,
+            reader,
+            writer,
+        } = self;
+        drop(writer);
+        let relay_error = |err: io::Error| FFIError::CaptureError {
+            name: name.to_owned(),
+            error: err.to_string(),
+        };
+        let mut to = to.try_clone().map_err(relay_error)?;
+        let mut reader = CountingReader {
+            inner: reader,
+            counted: Arc::clone(written),
+        };
+        let handle = spawn_supervisor_thread(name, move || {
+            let _ = io::copy(&mut reader, &mut to);
+        })
+        .map_err(relay_error)?;
+        Ok(RelayDrain { name, handle })
+    }
+}
+
+pub(crate) struct RelayDrain {
+    name: &'static str,
+    handle: thread::JoinHandle<()>,
+}
+
+impl RelayDrain {
+    pub(crate) fn join(self) -> Result<()> {
+        let name = self.name;
+        self.handle.join().map_err(|_| FFIError::CaptureError {
+            name: name.to_owned(),
+            error: "the relaying thread panicked".to_owned(),
+        })
+    }
+}
+
+// Counts what is read through it towards `Limits::output`
+struct CountingReader<R> {
+    inner: R,
+    counted: Arc<AtomicU64>,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        let _ = self.counted.fetch_add(read as u64, Ordering::SeqCst);
+        Ok(read)
+    }
+}
+
+/// A memfd holding `bytes` for stdin (`Redirect::Bytes`), read from the start and sealed so
+/// the command can not change or grow it
+pub(crate) fn sealed_memfd(bytes: &[u8]) -> Result<File> {
+    let memfd_error = || FFIError::MemfdError(last_error_string());
+    let name = CString::new("stdin").unwrap();
+    let fd =
+        unsafe { libc::memfd_create(name.as_ptr(), libc::MFD_CLOEXEC | libc::MFD_ALLOW_SEALING) };
+    if fd == -1 {
+        return Err(memfd_error());
+    }
+    let mut file = unsafe { File::from_raw_fd(fd) };
+    let io_error = |err: io::Error| FFIError::MemfdError(err.to_string());
+    file.write_all(bytes).map_err(io_error)?;
+    let _ = file.seek(SeekFrom::Start(0)).map_err(io_error)?;
+    let seals = libc::F_SEAL_SEAL | libc::F_SEAL_SHRINK | libc::F_SEAL_GROW | libc::F_SEAL_WRITE;
+    if unsafe { libc::fcntl(fd, libc::F_ADD_SEALS, seals) } == -1 {
+        return Err(memfd_error());
+    }
+    Ok(file)
+}
+
+/// A pipe between the caller and a standard stream of the command (`Redirect::Pipe`), made
+/// before the supervisor so the caller can keep its end
+pub(crate) struct StdioPipe {
+    // None for a pipe to another sandbox, see `connected`
+    caller: Option<File>,
+    command: File,
+}
+
+impl StdioPipe {
+    pub(crate) fn new(fd: &Fd) -> Result<Self> {
+        let (reader, writer) = make_pipe()?;
+        Ok(if fd.2 == libc::O_RDONLY {
+            Self {
+                caller: Some(writer),
+                command: reader,
+            }
+        } else {
+            Self {
+                caller: Some(reader),
+                command: writer,
+            }
+        })
+    }
+
+    /// The end of a pipe the caller took from another sandbox, the caller keeps nothing
+    pub(crate) fn connected(command: File) -> Self {
+        Self {
+            caller: None,
+            command,
+        }
+    }
+
+    pub(crate) fn command_end(&self) -> &File {
+        &self.command
+    }
+
+    pub(crate) fn into_caller_end(self) -> Option<File> {
+        self.caller
+    }
+
+    /// For the supervisor, whose copy of the end of the caller would keep the command from
+    /// seeing EOF (or EPIPE). It never drops the pipe, it exits before `spawn_jail` returns.
+    pub(crate) fn close_caller_end(&self) {
+        if let Some(ref caller) = self.caller {
+            let _ = unsafe { libc::close(caller.as_raw_fd()) };
+        }
+    }
+
+    /// For the supervisor once the command has its own copy, see `close_caller_end`
+    pub(crate) fn close_command_end(&self) {
+        let _ = unsafe { libc::close(self.command.as_raw_fd()) };
+    }
+}
+
+/// A Unix socket pair between the caller and the command (`Config::socket_channel`), made
+/// before the supervisor like a `StdioPipe`
+pub(crate) struct SocketChannel {
+    caller: UnixStream,
+    command: UnixStream,
+}
+
+impl SocketChannel {
+    pub(crate) fn new() -> Result<Self> {
+        let (caller, command) =
+            UnixStream::pair().map_err(|err| FFIError::SocketChannelError(err.to_string()))?;
+        Ok(Self { caller, command })
+    }
+
+    pub(crate) fn command_fd(&self) -> RawFd {
+        self.command.as_raw_fd()
+    }
+
+    pub(crate) fn into_caller_end(self) -> UnixStream {
+        self.caller
+    }
+
+    /// For the supervisor, see `StdioPipe::close_caller_end`
+    pub(crate) fn close_caller_end(&self) {
+        let _ = unsafe { libc::close(self.caller.as_raw_fd()) };
+    }
+
+    /// For the supervisor once the command has its own copy
+    pub(crate) fn close_command_end(&self) {
+        let _ = unsafe { libc::close(self.command.as_raw_fd()) };
+    }
+}
+
+/// Freezes (`true`) or thaws the processes of the run, for `ControlRequest::Pause` and
+/// `ControlRequest::Resume`
+pub(crate) type Freeze<'a> = &'a dyn Fn(bool) -> StdResult<(), Error>;
+
+/// What `JailHandle` asks of the supervisor while the run goes on
+#[derive(Serialize, Deserialize)]
+pub(crate) enum ControlRequest {
+    Pause,
+    Resume,
+    // Sent to the direct child only
+    Signal(libc::c_int),
+    // Answered with a `RunStatus`, the others with `()`
+    Status,
+}
+
+// Requests and replies are a single packet each, a status with the usage of every cpu included
+const CONTROL_PACKET_SIZE: usize = 64 * 1024;
+
+/// Carries the requests of `JailHandle` from the caller to the supervisor, which looks for them
+/// on every tick of its wait and answers once it is done (a run paused, say). A packet socket,
+/// every request and reply is read whole or not at all. Made before the supervisor like a
+/// `SocketChannel`.
+pub(crate) struct ControlChannel {
+    caller: File,
+    supervisor: File,
+    // Takes the requests of the processes that adopted a detached run, see `connect`
+    listener: Option<File>,
+    // Its connections, each asking one request at a time
+    connections: Mutex<Vec<File>>,
+    // Where the last request came from
+    reply_to: AtomicI32,
+}
+
+impl ControlChannel {
+    /// With `listen_path`, processes other than the caller can send requests as well, see
+    /// `connect`
+    pub(crate) fn new(listen_path: Option<&Path>) -> Result<Self> {
+        let mut fds = [0; 2];
+        if unsafe {
+            libc::socketpair(
+                libc::AF_UNIX,
+                libc::SOCK_SEQPACKET | libc::SOCK_CLOEXEC,
+                0,
+                fds.as_mut_ptr(),
+            )
+        } == -1
+        {
+            return Err(FFIError::ControlChannelError(last_error_string()));
+        }
+        let (caller, supervisor) =
+            unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) };
+        let listener = match listen_path {
+            Some(path) => Some(
+                listen_unix(path)
+                    .map_err(|err| FFIError::ControlChannelError(format!("{:?}: {}", path, err)))?,
+            ),
+            None => None,
+        };
+        Ok(Self {
+            caller,
+            reply_to: AtomicI32::new(supervisor.as_raw_fd()),
+            supervisor,
+            listener,
+            connections: Mutex::new(Vec::new()),
+        })
+    }
+
+    pub(crate) fn into_caller_end(self) -> File {
+        self.caller
+    }
+
+    /// For the supervisor, see `StdioPipe::close_caller_end`
+    pub(crate) fn close_caller_end(&self) {
+        let _ = unsafe { libc::close(self.caller.as_raw_fd()) };
+    }
+
+    /// Connects to the channel of a supervisor listening at `path` (see `new`), for `request`
+    pub(crate) fn connect(path: &Path) -> StdResult<File, String> {
+        let (address, len) = unix_address(path)?;
+        let socket =
+            unsafe { libc::socket(libc::AF_UNIX, libc::SOCK_SEQPACKET | libc::SOCK_CLOEXEC, 0) };
+        if socket == -1 {
+            return Err(last_error_string());
+        }
+        let socket = unsafe { File::from_raw_fd(socket) };
+        if unsafe {
+            libc::connect(
+                socket.as_raw_fd(),
+                ptr::addr_of!(address).cast::<libc::sockaddr>(),
+                len,
+            )
+        } == -1
+        {
+            // Removed once the supervisor is done, or left by a supervisor that is gone
+            return Err(match errno::Errno::last_error().error_code() {
+                libc::ENOENT | libc::ECONNREFUSED => "the run is over".to_owned(),
+                _ => last_error_string(),
+            });
+        }
+        Ok(socket)
+    }
+
+    /// Sends `request` to the supervisor and waits for its answer, `Err` tells what went wrong
+    pub(crate) fn request<R: DeserializeOwned>(
+        caller: &File,
+        request: &ControlRequest,
+    ) -> StdResult<R, String> {
+        let over = || "the run is over".to_owned();
+        let packet = bincode::serialize(request).map_err(|err| err.to_string())?;
+        // A supervisor gone already must not SIGPIPE us
+        if unsafe {
+            libc::send(
+                caller.as_raw_fd(),
+                packet.as_ptr() as *const libc::c_void,
+                packet.len(),
+                libc::MSG_NOSIGNAL,
+            )
+        } == -1
+        {
+            return Err(match errno::Errno::last_error().error_code() {
+                libc::EPIPE | libc::ECONNRESET => over(),
+                _ => last_error_string(),
+            });
+        }
+        let mut reply = vec![0u8; CONTROL_PACKET_SIZE];
+        let len = loop {
+            match unsafe {
+                libc::recv(
+                    caller.as_raw_fd(),
+                    reply.as_mut_ptr() as *mut libc::c_void,
+                    reply.len(),
+                    0,
+                )
+            } {
+                -1 if errno::Errno::last_error().error_code() == libc::EINTR => {}
+                -1 => return Err(last_error_string()),
+                // The supervisor exited before it got to the request
+                0 => return Err(over()),
+                len => break len as usize,
+            }
+        };
+        bincode::deserialize::<StdResult<R, String>>(&reply[..len])
+            .map_err(|err| err.to_string())?
+    }
+
+    /// The next request of the caller or of a connection, if there is one
+    pub(crate) fn next_request(&self) -> Option<ControlRequest> {
+        let mut packet = vec![0u8; CONTROL_PACKET_SIZE];
+        let mut receive = |fd: RawFd| -> Option<StdResult<ControlRequest, ()>> {
+            match unsafe {
+                libc::recv(
+                    fd,
+                    packet.as_mut_ptr() as *mut libc::c_void,
+                    packet.len(),
+                    libc::MSG_DONTWAIT,
+                )
+            } {
+                // Closed by the other end
+                0 => Some(Err(())),
+                len if len > 0 => bincode::deserialize(&packet[..len as usize]).ok().map(Ok),
+                _ => None,
+            }
+        };
+        if let Some(Ok(request)) = receive(self.supervisor.as_raw_fd()) {
+            self.reply_to
+                .store(self.supervisor.as_raw_fd(), Ordering::SeqCst);
+            return Some(request);
+        }
+
+        let listener = self.listener.as_ref()?;
+        let mut connections = self
+            .connections
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        loop {
+            match unsafe {
+                libc::accept4(
+                    listener.as_raw_fd(),
+                    ptr::null_mut(),
+                    ptr::null_mut(),
+                    libc::SOCK_CLOEXEC,
+                )
+            } {
+                -1 => break,
+                fd => connections.push(unsafe { File::from_raw_fd(fd) }),
+            }
+        }
+        let mut request = None;
+        connections.retain(|connection| {
+            if request.is_some() {
+                return true;
+            }
+            match receive(connection.as_raw_fd()) {
+                Some(Ok(received)) => {
+                    self.reply_to
+                        .store(connection.as_raw_fd(), Ordering::SeqCst);
+                    request = Some(received);
+                    true
+                }
+                Some(Err(())) => false,
+                None => true,
+            }
+        });
+        request
+    }
+
+    /// Answers the last request, a caller gone meanwhile does not care
+    pub(crate) fn reply<R: Serialize>(&self, result: StdResult<R, String>) {
+        let mut packet = bincode::serialize(&result).unwrap_or_default();
+        packet.truncate(CONTROL_PACKET_SIZE);
+        let _ = unsafe {
+            libc::send(
+                self.reply_to.load(Ordering::SeqCst),
+                packet.as_ptr() as *const libc::c_void,
+                packet.len(),
+                libc::MSG_NOSIGNAL,
+            )
+        };
+    }
+}
+
+// The address of the unix socket at `path`
+fn unix_address(path: &Path) -> StdResult<(libc::sockaddr_un, libc::socklen_t), String> {
+    let mut address = unsafe { mem::zeroed::<libc::sockaddr_un>() };
+    address.sun_family = libc::AF_UNIX as libc::sa_family_t;
+    let bytes = path.as_os_str().as_bytes();
+    // With room for the terminating NUL
+    if bytes.len() >= address.sun_path.len() {
+        return Err(format!(
+            "the path is longer than the {} bytes of a unix socket address",
+            address.sun_path.len() - 1
+        ));
+    }
+    for (to, &from) in address.sun_path.iter_mut().zip(bytes) {
+        *to = from as libc::c_char;
+    }
+    let len = size_of::<libc::sa_family_t>() + bytes.len() + 1;
+    Ok((address, len as libc::socklen_t))
+}
+
+// A listening packet socket at `path`, which replaces whatever was there. Non-blocking, so
+// accepting finds the connections that are there without waiting for more.
+fn listen_unix(path: &Path) -> StdResult<File, String> {
+    let (address, len) = unix_address(path)?;
+    let socket = unsafe {
+        libc::socket(
+            libc::AF_UNIX,
+            libc::SOCK_SEQPACKET | libc::SOCK_CLOEXEC | libc::SOCK_NONBLOCK,
+            0,
+        )
+    };
+    if socket == -1 {
+        return Err(last_error_string());
+    }
+    let socket = unsafe { File::from_raw_fd(socket) };
+    let _ = fs::remove_file(path);
+    if unsafe {
+        libc::bind(
+            socket.as_raw_fd(),
+            ptr::addr_of!(address).cast::<libc::sockaddr>(),
+            len,
+        )
+    } == -1
+        || unsafe { libc::listen(socket.as_raw_fd(), libc::SOMAXCONN) } == -1
+    {
+        return Err(last_error_string());
+    }
+    Ok(socket)
+}
+
+/// Opens a pseudo terminal, as (master, slave)
+pub(crate) fn open_pty() -> Result<(File, File)> {
+    let (mut master, mut slave) = (0, 0);
+    let result = unsafe {
+        libc::openpty(
+            &mut master,
+            &mut slave,
+            ptr::null_mut(),
+            ptr::null(),
+            ptr::null(),
+        )
+    };
+    if result == -1 {
+        return Err(FFIError::PtyError(last_error_string()));
+    }
+    let (master, slave) = unsafe { (File::from_raw_fd(master), File::from_raw_fd(slave)) };
+    for file in &[&master, &slave] {
+        if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_SETFD, libc::FD_CLOEXEC) } == -1 {
+            return Err(FFIError::PtyError(last_error_string()));
+        }
+    }
+    Ok((master, slave))
+}
+
+/// Makes the slave of a pseudo terminal the standard input, output and error
+pub(crate) fn redirect_to_pty(slave: &File) -> Result<()> {
+    for fd in &[STDIN, STDOUT, STDERR] {
+        // dup2 clears close-on-exec on the duplicate
+        if unsafe { libc::dup2(slave.as_raw_fd(), fd.0) } == -1 {
+            return Err(FFIError::DupFdError {
+                fd: fd.0,
+                name: fd.1.into(),
+                error: last_error_string(),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Starts a session of its own, in a process group of its own as well, with the pseudo
+/// terminal as its controlling terminal (even if stdin was redirected away from it)
+pub(crate) fn take_controlling_terminal(slave: &File) -> Result<()> {
+    if unsafe { libc::setsid() } == -1 {
+        return Err(FFIError::PtyError(last_error_string()));
+    }
+    if unsafe { libc::ioctl(slave.as_raw_fd(), libc::TIOCSCTTY, 0) } == -1 {
+        return Err(FFIError::PtyError(last_error_string()));
+    }
+    Ok(())
+}
+
+pub(crate) fn chdir(path: &Path) -> Result<()> {
+    let path_c_string = os_str_to_c_string(path)?;
+    if unsafe { libc::chdir(path_c_string.as_ptr()) } == -1 {
+        Err(FFIError::ChdirError {
+            path: path.to_path_buf(),
+            error: last_error_string(),
+        })
+    } else {
+        Ok(())
+    }
+}
+
+/// Copies `source` (opened by the caller, the host is out of reach by now) to `destination`,
+/// resolved inside the root like a mount destination, with the permissions of the source
+pub(crate) fn copy_in(source: &File, source_path: &Path, destination: &Path) -> Result<()> {
+    let copy_error = |error: String| FFIError::CopyError {
+        source: source_path.to_path_buf(),
+        destination: destination.to_path_buf(),
+        error,
+    };
+    let mount_point = open_mount_point(Path::new("/"), destination, true, false)
+        .map_err(|error| copy_error(error.to_string()))?;
+    let copy = || -> io::Result<()> {
+        let mut file = OpenOptions::new()
+            .write(true)
+            .truncate(true)
+            .open(mount_point.path())?;
+        let mode = source.metadata()?.permissions().mode() & 0o777;
+        file.set_permissions(Permissions::from_mode(mode))?;
+        // Another run may have read it before
+        let mut source = source;
+        let _ = source.seek(SeekFrom::Start(0))?;
+        let _ = io::copy(&mut source, &mut file)?;
+        Ok(())
+    };
+    copy().map_err(|error| copy_error(error.to_string()))
+}
+
+/// Hands the root directory of the child over to the supervisor, to copy files out of it once
+/// the command exited (see `Config::copy_out`). The mount namespace of the child goes along,
+/// the mounts in it are taken down with the last process in it otherwise. The descriptors
+/// are sent before the exec and the supervisor takes them after the run, in the meantime the
+/// socket keeps them open.
+pub(crate) struct RootChannel {
+    supervisor: File,
+    child: File,
+}
+
+impl RootChannel {
+    pub(crate) fn new() -> Result<Self> {
+        let mut fds = [0; 2];
+        if unsafe {
+            libc::socketpair(
+                libc::AF_UNIX,
+                libc::SOCK_STREAM | libc::SOCK_CLOEXEC,
+                0,
+                fds.as_mut_ptr(),
+            )
+        } == -1
+        {
+            return Err(FFIError::RootChannelError(last_error_string()));
+        }
+        Ok(Self {
+            supervisor: unsafe { File::from_raw_fd(fds[0]) },
+            child: unsafe { File::from_raw_fd(fds[1]) },
+        })
+    }
+
+    /// Sends the current root, must be called after the pivot
+    pub(crate) fn send(&self) -> Result<()> {
+        let error = |error: io::Error| FFIError::RootChannelError(error.to_string());
+        let root = File::open("/").map_err(error)?;
+        let mount_namespace = File::open("/proc/self/ns/mnt").map_err(error)?;
+        let fds = [root.as_raw_fd(), mount_namespace.as_raw_fd()];
+
+        let mut byte = [0u8; 1];
+        let mut iov = libc::iovec {
+            iov_base: byte.as_mut_ptr() as *mut libc::c_void,
+            iov_len: byte.len(),
+        };
+        let mut control = [0u64; 4];
+        let mut msg = unsafe { mem::zeroed::<libc::msghdr>() };
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+        msg.msg_controllen = unsafe { libc::CMSG_SPACE(size_of::<[RawFd; 2]>() as u32) } as usize;
+        unsafe {
+            let cmsg = libc::CMSG_FIRSTHDR(&msg);
+            (*cmsg).cmsg_level = libc::SOL_SOCKET;
+            (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+            (*cmsg).cmsg_len = libc::CMSG_LEN(size_of::<[RawFd; 2]>() as u32) as usize;
+            ptr::copy_nonoverlapping(fds.as_ptr(), libc::CMSG_DATA(cmsg) as *mut RawFd, 2);
+        }
+        if unsafe { libc::sendmsg(self.child.as_raw_fd(), &msg, libc::MSG_NOSIGNAL) } == -1 {
+            Err(error(io::Error::last_os_error()))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Takes the root the child sent, `None` if it did not get that far
+    pub(crate) fn accept(self) -> Result<Option<SandboxRoot>> {
+        let Self { supervisor, child } = self;
+        // Otherwise a child that never sent it would not end the read
+        drop(child);
+
+        let mut byte = [0u8; 1];
+        let mut iov = libc::iovec {
+            iov_base: byte.as_mut_ptr() as *mut libc::c_void,
+            iov_len: byte.len(),
+        };
+        let mut control = [0u64; 4];
+        let mut msg = unsafe { mem::zeroed::<libc::msghdr>() };
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+        msg.msg_controllen = size_of::<[u64; 4]>();
+        let len = loop {
+            match unsafe { libc::recvmsg(supervisor.as_raw_fd(), &mut msg, libc::MSG_CMSG_CLOEXEC) }
+            {
+                -1 if errno::Errno::last_error().error_code() == libc::EINTR => {}
+                -1 => return Err(FFIError::RootChannelError(last_error_string())),
+                len => break len,
+            }
+        };
+        if len == 0 {
+            return Ok(None);
+        }
+
+        let mut fds: [RawFd; 2] = [-1; 2];
+        unsafe {
+            let cmsg = libc::CMSG_FIRSTHDR(&msg);
+            if cmsg.is_null()
+                || (*cmsg).cmsg_type != libc::SCM_RIGHTS
+                || (*cmsg).cmsg_len != libc::CMSG_LEN(size_of::<[RawFd; 2]>() as u32) as usize
+            {
+                return Err(FFIError::RootChannelError(
+                    "the descriptors are missing".into(),
+                ));
+            }
+            ptr::copy_nonoverlapping(libc::CMSG_DATA(cmsg) as *const RawFd, fds.as_mut_ptr(), 2);
+        }
+        Ok(Some(SandboxRoot {
+            root: unsafe { File::from_raw_fd(fds[0]) },
+            _mount_namespace: unsafe { File::from_raw_fd(fds[1]) },
+        }))
+    }
+}
+
+/// The root of the child as the supervisor sees it, see `RootChannel`
+pub(crate) struct SandboxRoot {
+    root: File,
+    _mount_namespace: File,
+}
+
+impl SandboxRoot {
+    /// Copies the regular file `source`, resolved inside the root, to `destination` on the host
+    /// unless it is larger than `limit`. A `source` the command did not make is left out.
+    pub(crate) fn copy_out(
+        &self,
+        source: &Path,
+        destination: &Path,
+        limit: Option<SpaceUsage>,
+    ) -> Result<()> {
+        let copy_error = |error: io::Error| FFIError::CopyError {
+            source: source.to_path_buf(),
+            destination: destination.to_path_buf(),
+            error: error.to_string(),
+        };
+        let too_large = |limit: u64| FFIError::CopyOutTooLarge {
+            source: source.to_path_buf(),
+            limit,
+        };
+
+        let file = match open_in_root(&self.root, source) {
+            Ok(file) => file,
+            Err(ref error) if error.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(error) => return Err(copy_error(error)),
+        };
+        // A FIFO would block the read and a device may never end it
+        if !file.metadata().map_err(copy_error)?.is_file() {
+            return Err(copy_error(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "not a regular file",
+            )));
+        }
+        let file = File::open(format!("/proc/self/fd/{}", file.as_raw_fd())).map_err(copy_error)?;
+        let limit = limit.map(SpaceUsage::as_bytes);
+        if let Some(limit) = limit {
+            if file.metadata().map_err(copy_error)?.len() > limit {
+                return Err(too_large(limit));
+            }
+        }
+
+        let mut output = File::create(destination).map_err(copy_error)?;
+        // The size is only checked up front, reading one byte past the limit tells
+        let copied = io::copy(
+            &mut file.take(limit.map_or(u64::MAX, |limit| limit + 1)),
+            &mut output,
+        )
+        .map_err(copy_error)?;
+        match limit {
+            Some(limit) if copied > limit => {
+                drop(output);
+                let _ = fs::remove_file(destination);
+                Err(too_large(limit))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+pub(crate) fn move_to_different_process_group() -> Result<()> {
+    if unsafe { libc::setpgid(0, 0) } == -1 {
+        Err(FFIError::SetpgidError {
+            pid: 0,
+            pgid: 0,
+            error: last_error_string(),
+        })
+    } else {
+        Ok(())
+    }
+}
+
+fn sys_pivot_root(new_root: &Path, old_root: &Path) -> Result<()> {
+    let new_root_c_string = os_str_to_c_string(new_root)?;
+    let old_root_c_string = os_str_to_c_string(old_root)?;
+    match unsafe {
+        libc::syscall(
+            libc::SYS_pivot_root,
+            new_root_c_string.as_ptr(),
+            old_root_c_string.as_ptr(),
+        )
+    } {
+        -1 => {
+            let errno = errno::Errno::last_error();
+            let error = match errno.error_code() {
+                libc::EBUSY => "new_root or put_old are on the current root\
+                                filesystem, or a filesystem is already\
+                                mounted on put_old."
+                    .into(),
+                libc::EINVAL => "put_old is not underneath new_root".into(),
+                libc::ENOTDIR => "new_root or put_old is not a directory".into(),
+                libc::EPERM => "The calling process does not have the\
+                                CAP_SYS_ADMIN capability"
+                    .into(),
+                _ => errno.error_string(),
+            };
+
+            Err(FFIError::PivotRootError {
+                new_root: new_root.to_path_buf(),
+                old_root: old_root.to_path_buf(),
+                error,
+            })
+        }
+        _ => Ok(()),
+    }
+}
+
+pub(crate) fn kill_on_parent_death() -> Result<()> {
+    if unsafe { libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGKILL) } == -1 {
+        Err(FFIError::PrSetPDeathSigError(last_error_string()))
+    } else {
+        Ok(())
+    }
+}
+
+pub(crate) fn set_stack_limit(stack: Option<SpaceUsage>) -> Result<()> {
+    let rlimit = if let Some(usage) = stack {
+        libc::rlimit {
+            rlim_cur: usage.as_bytes(),
+            rlim_max: usage.as_bytes(),
+        }
+    } else {
+        libc::rlimit {
+            rlim_cur: libc::RLIM_INFINITY,
+            rlim_max: libc::RLIM_INFINITY,
+        }
+    };
+
+    if unsafe { libc::setrlimit(libc::RLIMIT_STACK, &rlimit) } == -1 {
+        Err(FFIError::SetRLimitError(last_error_string()))
+    } else {
+        Ok(())
+    }
+}
+
+mod errno {
+    use libc;
+    use std::ffi::CStr;
+
+    #[derive(Debug)]
+    pub(crate) struct Errno(libc::c_int);
+
+    impl Errno {
+        pub(crate) fn last_error() -> Self {
+            unsafe { Self(*libc::__errno_location()) }
+        }
+
+        pub(crate) fn error_code(&self) -> libc::c_int {
+            self.0
+        }
+
+        pub(crate) fn error_string(&self) -> String {
+            let buffer = &mut [0i8; 256];
+            if unsafe { libc::strerror_r(self.0, buffer.as_mut_ptr(), buffer.len()) } == -1 {
+                return "unexpected strerror_r error".into();
+            }
+            unsafe {
+                CStr::from_ptr(buffer.as_ptr())
+                    .to_string_lossy()
+                    .into_owned()
+            }
+        }
+    }
+}
+
+fn last_error_string() -> String {
+    errno::Errno::last_error().error_string()
+}
+
+// The waits of the supervisor rely on being interrupted by SIGALRM and SIGCHLD, which must not
+// end up on the new thread instead. A write of the thread to a closed pipe fails with EPIPE
+// rather than killing the supervisor with SIGPIPE.
+fn spawn_supervisor_thread<F, T>(name: &str, f: F) -> io::Result<thread::JoinHandle<T>>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let mut signals: libc::sigset_t = unsafe { mem::zeroed() };
+    let mut old_signals: libc::sigset_t = unsafe { mem::zeroed() };
+    unsafe {
+        let _ = libc::sigemptyset(&mut signals);
+        let _ = libc::sigaddset(&mut signals, libc::SIGALRM);
+        let _ = libc::sigaddset(&mut signals, SIGCHLD);
+        let _ = libc::sigaddset(&mut signals, libc::SIGPIPE);
+        let _ = libc::pthread_sigmask(libc::SIG_BLOCK, &signals, &mut old_signals);
+    }
+    let spawned = thread::Builder::new().name(name.to_owned()).spawn(f);
+    let _ = unsafe { libc::pthread_sigmask(libc::SIG_SETMASK, &old_signals, ptr::null_mut()) };
+    spawned
+}
+
+fn make_pipe() -> Result<(File, File)> {
+    unsafe {
+        let fd = &mut [0; 2];
+        if libc::pipe2(fd.as_mut_ptr(), libc::O_CLOEXEC) == -1 {
+            Err(FFIError::Pipe2Error(last_error_string()))
+        } else {
+            Ok((File::from_raw_fd(fd[0]), File::from_raw_fd(fd[1])))
+        }
+    }
+}
+
+// Paths and arguments come from the caller, nothing stops them from holding a nul byte
+fn os_str_to_c_string<T: AsRef<OsStr>>(os_str: T) -> Result<CString> {
+    CString::new(os_str.as_ref().as_bytes())
+        .map_err(|_| FFIError::NulByteError(os_str.as_ref().to_string_lossy().into_owned()))
+}
+
+// The parts of rtnetlink needed to create a veth pair, libc does not have all of them
+const NETLINK_ROUTE: libc::c_int = 0;
+const RTM_NEWLINK: u16 = 16;
+const NLMSG_ERROR: u16 = 2;
+const NLM_F_REQUEST: u16 = 0x1;
+const NLM_F_ACK: u16 = 0x4;
+const NLM_F_EXCL: u16 = 0x200;
+const NLM_F_CREATE: u16 = 0x400;
+const NLMSG_HEADER_LEN: usize = 16;
+const IFINFOMSG_LEN: usize = 16;
+const IFLA_IFNAME: u16 = 3;
+const IFLA_LINKINFO: u16 = 18;
+const IFLA_NET_NS_PID: u16 = 19;
+const IFLA_INFO_KIND: u16 = 1;
+const IFLA_INFO_DATA: u16 = 2;
+const VETH_INFO_PEER: u16 = 1;
+
+fn socket(domain: libc::c_int, kind: libc::c_int, protocol: libc::c_int) -> io::Result<File> {
+    match unsafe { libc::socket(domain, kind | libc::SOCK_CLOEXEC, protocol) } {
+        -1 => Err(io::Error::last_os_error()),
+        fd => Ok(unsafe { File::from_raw_fd(fd) }),
+    }
+}
+
+/// Sockets opened in the host network namespace. They keep working on it after the process
+/// moved to a network namespace of its own, which is how the sandbox sets up the host end.
+pub(crate) struct HostNetSockets {
+    netlink: File,
+    inet: File,
+}
+
+impl HostNetSockets {
+    pub(crate) fn new() -> Result<Self> {
+        Ok(Self {
+            netlink: socket(libc::AF_NETLINK, libc::SOCK_RAW, NETLINK_ROUTE)
+                .map_err(|err| FFIError::NetlinkError(err.to_string()))?,
+            inet: socket(libc::AF_INET, libc::SOCK_DGRAM, 0)
+                .map_err(|err| FFIError::NetlinkError(err.to_string()))?,
+        })
+    }
+}
+
+// Appends a netlink attribute, `payload` writes its data (nested attributes included)
+fn push_attribute<F: FnOnce(&mut Vec<u8>)>(message: &mut Vec<u8>, kind: u16, payload: F) {
+    let start = message.len();
+    message.extend_from_slice(&[0; 4]);
+    payload(message);
+    let len = (message.len() - start) as u16;
+    message[start..start + 2].copy_from_slice(&len.to_ne_bytes());
+    message[start + 2..start + 4].copy_from_slice(&kind.to_ne_bytes());
+    // Attributes are 4 byte aligned
+    let aligned_len = (message.len() + 3) & !3;
+    message.resize(aligned_len, 0);
+}
+
+fn push_name_attribute(message: &mut Vec<u8>, kind: u16, name: &str) {
+    push_attribute(message, kind, |message| {
+        message.extend_from_slice(name.as_bytes());
+        message.push(0);
+    });
+}
+
+// Creates the pair in the namespace of the netlink socket, with the peer in the network
+// namespace of the calling process
+fn create_veth_pair(netlink: &File, host_name: &str, peer_name: &str) -> Result<()> {
+    let netlink_error = |err: io::Error| FFIError::NetlinkError(err.to_string());
+
+    // The header is filled in at the end, the ifinfomsg stays zeroed
+    let mut message = vec![0; NLMSG_HEADER_LEN + IFINFOMSG_LEN];
+    push_name_attribute(&mut message, IFLA_IFNAME, host_name);
+    push_attribute(&mut message, IFLA_LINKINFO, |message| {
+        push_name_attribute(message, IFLA_INFO_KIND, "veth");
+        push_attribute(message, IFLA_INFO_DATA, |message| {
+            push_attribute(message, VETH_INFO_PEER, |message| {
+                message.extend_from_slice(&[0; IFINFOMSG_LEN]);
+                push_name_attribute(message, IFLA_IFNAME, peer_name);
+                push_attribute(message, IFLA_NET_NS_PID, |message| {
+                    message.extend_from_slice(&(getpid() as u32).to_ne_bytes())
+                });
+            });
+        });
+    });
+    let len = message.len() as u32;
+    let flags = NLM_F_REQUEST | NLM_F_ACK | NLM_F_CREATE | NLM_F_EXCL;
+    message[0..4].copy_from_slice(&len.to_ne_bytes());
+    message[4..6].copy_from_slice(&RTM_NEWLINK.to_ne_bytes());
+    message[6..8].copy_from_slice(&flags.to_ne_bytes());
+    message[8..12].copy_from_slice(&1u32.to_ne_bytes());
+
+    // Without an address the message goes to the kernel, which answers with an error message
+    // (error 0 being the acknowledgement)
+    let mut netlink = netlink;
+    netlink.write_all(&message).map_err(&netlink_error)?;
+    let mut answer = [0u8; 4096];
+    let answer_len = netlink.read(&mut answer).map_err(&netlink_error)?;
+    if answer_len < NLMSG_HEADER_LEN + 4
+        || u16::from_ne_bytes([answer[4], answer[5]]) != NLMSG_ERROR
+    {
+        return Err(FFIError::NetlinkError(
+            "unexpected answer to RTM_NEWLINK".to_owned(),
+        ));
+    }
+    let mut error = [0u8; 4];
+    error.copy_from_slice(&answer[NLMSG_HEADER_LEN..NLMSG_HEADER_LEN + 4]);
+    match -i32::from_ne_bytes(error) {
+        0 => Ok(()),
+        libc::EPERM => Err(FFIError::VethPermissionError {
+            host_name: host_name.to_owned(),
+            peer_name: peer_name.to_owned(),
+        }),
+        errno => Err(FFIError::VethCreateError {
+            host_name: host_name.to_owned(),
+            peer_name: peer_name.to_owned(),
+            error: io::Error::from_raw_os_error(errno).to_string(),
+        }),
+    }
+}
+
+#[repr(C)]
+struct InterfaceRequest {
+    name: [u8; libc::IFNAMSIZ],
+    data: [u8; 24],
+}
+
+// Sets the address, netmask and brings up an interface of the namespace of `inet`
+fn configure_interface(inet: &File, name: &str, address: Ipv4Addr, prefix_len: u8) -> Result<()> {
+    let mut request = InterfaceRequest {
+        name: [0; libc::IFNAMSIZ],
+        data: [0; 24],
+    };
+    request.name[..name.len()].copy_from_slice(name.as_bytes());
+    let ioctl = |request_kind: libc::c_ulong, request: &mut InterfaceRequest| {
+        let request: *mut InterfaceRequest = request;
+        if unsafe { libc::ioctl(inet.as_raw_fd(), request_kind, request) } == -1 {
+            Err(FFIError::InterfaceConfigError {
+                name: name.to_owned(),
+                error: last_error_string(),
+            })
+        } else {
+            Ok(())
+        }
+    };
+
+    // Both as a sockaddr_in
+    let netmask = u32::MAX
+        .checked_shl(32 - u32::from(prefix_len))
+        .unwrap_or(0);
+    for &(request_kind, address) in &[
+        (libc::SIOCSIFADDR, address),
+        (libc::SIOCSIFNETMASK, Ipv4Addr::from(netmask)),
+    ] {
+        request.data = [0; 24];
+        request.data[0..2].copy_from_slice(&(libc::AF_INET as u16).to_ne_bytes());
+        request.data[4..8].copy_from_slice(&address.octets());
+        ioctl(request_kind, &mut request)?;
+    }
+
+    request.data = [0; 24];
+    ioctl(libc::SIOCGIFFLAGS, &mut request)?;
+    let flags = i16::from_ne_bytes([request.data[0], request.data[1]]) | libc::IFF_UP as i16;
+    request.data[0..2].copy_from_slice(&flags.to_ne_bytes());
+    ioctl(libc::SIOCSIFFLAGS, &mut request)
+}
+
+/// Creates the veth pair and configures both of its ends, from inside the network namespace
+/// the peer goes to (while still privileged in it)
+pub(crate) fn set_up_veth(veth: &VethConfig, host_net: &HostNetSockets) -> Result<()> {
+    create_veth_pair(&host_net.netlink, veth.host_name(), veth.peer_name())?;
+    configure_interface(
+        &host_net.inet,
+        veth.host_name(),
+        veth.host_address(),
+        veth.prefix_len(),
+    )?;
+    let inet = socket(libc::AF_INET, libc::SOCK_DGRAM, 0).map_err(|err| {
+        FFIError::InterfaceConfigError {
+            name: veth.peer_name().to_owned(),
+            error: err.to_string(),
+        }
+    })?;
+    configure_interface(
+        &inet,
+        veth.peer_name(),
+        veth.peer_address(),
+        veth.prefix_len(),
+    )
+}
+
+pub(crate) struct EventFd(File);
+
+impl EventFd {
+    pub(crate) fn new() -> Result<Self> {
+        match unsafe { libc::eventfd(0, libc::EFD_CLOEXEC | libc::EFD_NONBLOCK) } {
+            -1 => Err(FFIError::EventFdError(last_error_string())),
+            fd => Ok(Self(unsafe { File::from_raw_fd(fd) })),
+        }
+    }
+
+    /// Returns how many times the event was signaled since the last call, without blocking
+    pub(crate) fn read_events(&self) -> Result<u64> {
+        let mut counter = [0u8; 8];
+        match (&self.0).read(&mut counter) {
+            Ok(_) => Ok(u64::from_ne_bytes(counter)),
+            Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => Ok(0),
+            Err(err) => Err(FFIError::EventFdError(err.to_string())),
+        }
+    }
+}
+
+impl AsRawFd for EventFd {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
+pub(crate) struct Inotify(File);
+
+impl Inotify {
+    pub(crate) fn new() -> Result<Self> {
+        match unsafe { libc::inotify_init1(libc::IN_CLOEXEC | libc::IN_NONBLOCK) } {
+            -1 => Err(FFIError::InotifyError(last_error_string())),
+            fd => Ok(Self(unsafe { File::from_raw_fd(fd) })),
+        }
+    }
+
+    pub(crate) fn add_watch(&self, path: &Path, mask: u32) -> Result<()> {
+        let path = os_str_to_c_string(path)?;
+        if unsafe { libc::inotify_add_watch(self.0.as_raw_fd(), path.as_ptr(), mask) } == -1 {
+            Err(FFIError::InotifyError(last_error_string()))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl AsRawFd for Inotify {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
+// Blocks until `fd` is readable or a signal arrives (SIGALRM ticks, SIGCHLD), then drains
+// whatever was there. `fd` must be non blocking and yield at most a page per event, as eventfd
+// counters and inotify events do.
+fn wait_readable(fd: RawFd) -> StdResult<(), FFIError> {
+    let mut pollfd = libc::pollfd {
+        fd,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    match unsafe { libc::poll(&mut pollfd, 1, -1) } {
+        -1 if errno::Errno::last_error().error_code() == libc::EINTR => Ok(()),
+        -1 => Err(FFIError::PollError(last_error_string())),
+        _ => {
+            let mut buffer = [0u8; 4096];
+            let _ =
+                unsafe { libc::read(fd, buffer.as_mut_ptr() as *mut libc::c_void, buffer.len()) };
+            Ok(())
+        }
+    }
+}
+
+/// Hands the listener of a seccomp filter from the child over to the supervisor. The filter
+/// passes the syscalls it does not allow on to the listener, which tells the supervisor which
+/// syscall it was before it kills the child (the kernel killing it by itself does not), and
+/// the ones to notify about, which the supervisor answers through `handler`.
+///
+/// The child can not send the listener, the filter may not allow it, so it only tells which
+/// descriptor the listener is going to be and the supervisor takes it from there with
+/// `pidfd_getfd`. Until the exec the supervisor lets everything through, the syscalls are still
+/// the ones of the sandbox, so the child must not be a vfork.
+pub(crate) struct SeccompChannel {
+    supervisor: File,
+    child: File,
+    notify: Vec<u32>,
+    handler: Option<SeccompNotifyHandler>,
+}
+
+impl SeccompChannel {
+    pub(crate) fn new(
+        policy: Option<&SeccompPolicy>,
+        handler: Option<SeccompNotifyHandler>,
+    ) -> Result<Self> {
+        let notify = match policy {
+            Some(policy) => policy
+                .notify_numbers()
+                .ok_or_else(|| FFIError::SeccompError("the policy has unknown syscalls".into()))?,
+            None => Vec::new(),
+        };
+        let mut fds = [0; 2];
+        if unsafe {
+            libc::socketpair(
+                libc::AF_UNIX,
+                libc::SOCK_STREAM | libc::SOCK_CLOEXEC,
+                0,
+                fds.as_mut_ptr(),
+            )
+        } == -1
+        {
+            return Err(FFIError::SeccompError(last_error_string()));
+        }
+        Ok(Self {
+            supervisor: unsafe { File::from_raw_fd(fds[0]) },
+            child: unsafe { File::from_raw_fd(fds[1]) },
+            notify,
+            handler,
+        })
+    }
+
+    fn load_filter(&self, program: &libc::sock_fprog) -> Result<()> {
+        // The listener gets the lowest free descriptor, which is the one a dup gets as well
+        let reserved = match unsafe { libc::dup(self.child.as_raw_fd()) } {
+            -1 => return Err(FFIError::SeccompError(last_error_string())),
+            fd => fd,
+        };
+        let _ = unsafe { libc::close(reserved) };
+        (&self.child)
+            .write_all(&reserved.to_ne_bytes())
+            .map_err(|err| FFIError::SeccompError(err.to_string()))?;
+
+        let listener = unsafe {
+            libc::syscall(
+                libc::SYS_seccomp,
+                libc::SECCOMP_SET_MODE_FILTER,
+                libc::SECCOMP_FILTER_FLAG_NEW_LISTENER,
+                program,
+            )
+        };
+        if listener == -1 {
+            return Err(FFIError::SeccompError(last_error_string()));
+        }
+        if listener != libc::c_long::from(reserved) {
+            return Err(FFIError::SeccompError(format!(
+                "the listener is descriptor {} instead of {}",
+                listener, reserved
+            )));
+        }
+
+        // The exec closes the listener, the supervisor must have its own copy by then
+        let mut taken = [0u8; 1];
+        (&self.child)
+            .read_exact(&mut taken)
+            .map_err(|err| FFIError::SeccompError(err.to_string()))
+    }
+
+    /// Takes the listener of the filter the child `pid` loads, `None` if the child does not
+    /// get that far
+    pub(crate) fn accept(self, pid: libc::pid_t) -> Result<Option<SeccompListener>> {
+        let Self {
+            supervisor,
+            child,
+            notify,
+            handler,
+        } = self;
+        // Otherwise the child dying early would not end the read
+        drop(child);
+        let mut reserved = [0u8; 4];
+        match (&supervisor).read_exact(&mut reserved) {
+            Ok(()) => {}
+            Err(ref err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(err) => return Err(FFIError::SeccompError(err.to_string())),
+        }
+        let reserved = libc::c_int::from_ne_bytes(reserved);
+
+        let pidfd = match unsafe { libc::syscall(libc::SYS_pidfd_open, pid, 0) } {
+            -1 => return Err(FFIError::SeccompError(last_error_string())),
+            fd => unsafe { File::from_raw_fd(fd as RawFd) },
+        };
+        let listener = loop {
+            match unsafe { libc::syscall(libc::SYS_pidfd_getfd, pidfd.as_raw_fd(), reserved, 0) } {
+                -1 if errno::Errno::last_error().error_code() == libc::EBADF => {
+                    // Not loaded yet, unless the child exited (which makes the pidfd readable)
+                    let mut pollfd = libc::pollfd {
+                        fd: pidfd.as_raw_fd(),
+                        events: libc::POLLIN,
+                        revents: 0,
+                    };
+                    if unsafe { libc::poll(&mut pollfd, 1, 1) } == 1 {
+                        return Ok(None);
+                    }
+                }
+                -1 => return Err(FFIError::SeccompError(last_error_string())),
+                fd => break unsafe { File::from_raw_fd(fd as RawFd) },
+            }
+        };
+
+        // A child that died meanwhile does not need to know, and must not SIGPIPE us
+        let _ = unsafe {
+            libc::send(
+                supervisor.as_raw_fd(),
+                [1u8].as_ptr() as *const libc::c_void,
+                1,
+                libc::MSG_NOSIGNAL,
+            )
+        };
+        Ok(Some(SeccompListener {
+            file: listener,
+            notify,
+            handler,
+        }))
+    }
+}
+
+/// Whether the syscall `id` the listener passed on is still waiting for an answer
+pub(crate) fn seccomp_notification_valid(listener: RawFd, id: u64) -> bool {
+    let valid = unsafe { libc::ioctl(listener, libc::SECCOMP_IOCTL_NOTIF_ID_VALID, &id) };
+    valid == 0
+}
+
+/// The supervisor end of a seccomp filter, see `SeccompChannel`
+pub(crate) struct SeccompListener {
+    file: File,
+    notify: Vec<u32>,
+    handler: Option<SeccompNotifyHandler>,
+}
+
+impl SeccompListener {
+    /// The syscall the filter passed on, once the listener polled readable
+    fn receive(&self) -> Result<Option<libc::seccomp_notif>> {
+        let mut notification: libc::seccomp_notif = unsafe { mem::zeroed() };
+        if unsafe {
+            libc::ioctl(
+                self.file.as_raw_fd(),
+                libc::SECCOMP_IOCTL_NOTIF_RECV,
+                &mut notification,
+            )
+        } == -1
+        {
+            return match errno::Errno::last_error().error_code() {
+                // The syscall was interrupted (or the caller killed) before it got here
+                libc::ENOENT | libc::EINTR => Ok(None),
+                _ => Err(FFIError::SeccompError(last_error_string())),
+            };
+        }
+        Ok(Some(notification))
+    }
+
+    fn respond(&self, id: u64, response: SeccompResponse) -> Result<()> {
+        let mut response = match response {
+            SeccompResponse::Continue => libc::seccomp_notif_resp {
+                id,
+                val: 0,
+                error: 0,
+                flags: libc::SECCOMP_USER_NOTIF_FLAG_CONTINUE as u32,
+            },
+            SeccompResponse::Errno(errno) => libc::seccomp_notif_resp {
+                id,
+                val: 0,
+                error: -errno,
+                flags: 0,
+            },
+            SeccompResponse::Return(val) => libc::seccomp_notif_resp {
+                id,
+                val,
+                error: 0,
+                flags: 0,
+            },
+        };
+        if unsafe {
+            libc::ioctl(
+                self.file.as_raw_fd(),
+                libc::SECCOMP_IOCTL_NOTIF_SEND,
+                &mut response,
+            )
+        } == -1
+            && errno::Errno::last_error().error_code() != libc::ENOENT
+        {
+            return Err(FFIError::SeccompError(last_error_string()));
+        }
+        Ok(())
+    }
+
+    /// Answers the syscalls the filter passes on after the exec on a thread of its own, so
+    /// a slow handler does not hold up the wait for the child. Forbidden syscalls stay blocked
+    /// until drop kills the child, the first one is reported by `illegal_syscall`. `pending` is
+    /// one already received while reading the result.
+    fn supervise(self, pending: Option<libc::seccomp_notif>) -> Result<SeccompSupervisor> {
+        let illegal_syscall = Arc::new(Mutex::new(None));
+        let thread_illegal_syscall = Arc::clone(&illegal_syscall);
+
+        let _ = spawn_supervisor_thread("seccomp", move || {
+            self.serve(pending, &thread_illegal_syscall)
+        })
+        .map_err(|err| FFIError::SeccompError(err.to_string()))?;
+
+        Ok(SeccompSupervisor { illegal_syscall })
+    }
+
+    fn serve(self, pending: Option<libc::seccomp_notif>, illegal_syscall: &Mutex<Option<u32>>) {
+        if let Some(notification) = pending {
+            self.answer(&notification, illegal_syscall);
+        }
         loop {
-            let wall_time = timer.elapsed();
-            let usage = usage(wall_time)?;
+            let mut pollfd = libc::pollfd {
+                fd: self.file.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            if unsafe { libc::poll(&mut pollfd, 1, -1) } == -1 {
+                if errno::Errno::last_error().error_code() == libc::EINTR {
+                    continue;
+                }
+                return;
+            }
+            // Hung up once nothing runs under the filter anymore
+            if pollfd.revents & libc::POLLIN == 0 {
+                return;
+            }
+
+            match self.receive() {
+                Ok(Some(notification)) => self.answer(&notification, illegal_syscall),
+                Ok(None) => {}
+                Err(_) => return,
+            }
+        }
+    }
+
+    fn answer(&self, notification: &libc::seccomp_notif, illegal_syscall: &Mutex<Option<u32>>) {
+        let number = notification.data.nr as u32;
+        let handler = match self.handler {
+            Some(ref handler) if self.notify.contains(&number) => handler,
+            _ => {
+                if let Ok(mut illegal_syscall) = illegal_syscall.lock() {
+                    let _ = illegal_syscall.get_or_insert(number);
+                }
+                return;
+            }
+        };
+        let response = handler.handle(SeccompNotification::new(
+            notification.id,
+            notification.pid,
+            number,
+            notification.data.args,
+            self.file.as_raw_fd(),
+        ));
+        // Fails only when the syscall is gone already
+        let _ = self.respond(notification.id, response);
+    }
+}
+
+/// The thread answering the syscalls a seccomp filter passes on, see
+/// `SeccompListener::supervise`
+pub(crate) struct SeccompSupervisor {
+    illegal_syscall: Arc<Mutex<Option<u32>>>,
+}
+
+impl SeccompSupervisor {
+    fn illegal_syscall(&self) -> Option<u32> {
+        self.illegal_syscall
+            .lock()
+            .ok()
+            .and_then(|illegal_syscall| *illegal_syscall)
+    }
+}
+
+const PTRACE_SYSCALL_STOP: libc::c_int = libc::SIGTRAP | 0x80;
+
+/// Logs the syscalls of the command from the ptrace stops `CloneHandle::wait` passes on, one
+/// line per syscall: when it was made since the exec, the thread, the syscall and its return
+/// value (`?` if the thread was gone before it returned)
+pub(crate) struct SyscallTracer {
+    log: BufWriter<File>,
+    // From the first stop, the one right after the exec
+    started: Option<Instant>,
+    // The syscall each thread is in, with when it was made
+    entered: HashMap<libc::pid_t, (Duration, u64)>,
+}
+
+impl SyscallTracer {
+    pub(crate) fn new(path: &Path) -> Result<Self> {
+        let log = File::create(path).map_err(|err| {
+            FFIError::PtraceError(format!("could not create {}: {}", path.display(), err))
+        })?;
+        Ok(Self {
+            log: BufWriter::new(log),
+            started: None,
+            entered: HashMap::new(),
+        })
+    }
+
+    // Handles a stop of a traced thread and resumes it
+    fn stopped(&mut self, pid: libc::pid_t, status: libc::c_int) -> Result<()> {
+        let signal = libc::WSTOPSIG(status);
+        let mut deliver = 0;
+        match self.started {
+            None => {
+                // Follows the threads and processes the command starts as well, events instead
+                // of a SIGTRAP for their execs
+                let options = libc::PTRACE_O_TRACESYSGOOD
+                    | libc::PTRACE_O_TRACECLONE
+                    | libc::PTRACE_O_TRACEFORK
+                    | libc::PTRACE_O_TRACEVFORK
+                    | libc::PTRACE_O_TRACEEXEC
+                    | libc::PTRACE_O_EXITKILL;
+                ptrace(libc::PTRACE_SETOPTIONS, pid, options as usize)?;
+                self.started = Some(Instant::now());
+            }
+            Some(_) if signal == PTRACE_SYSCALL_STOP => self.syscall_stopped(pid)?,
+            // Events have nothing to deliver, new tracees start with a SIGSTOP of their own and
+            // the command would stay stopped for good on any other
+            Some(_) if status >> 16 != 0 || signal == libc::SIGSTOP => {}
+            Some(_) => deliver = signal,
+        }
+        ptrace(libc::PTRACE_SYSCALL, pid, deliver as usize)
+    }
+
+    fn syscall_stopped(&mut self, pid: libc::pid_t) -> Result<()> {
+        let mut info = MaybeUninit::<libc::ptrace_syscall_info>::zeroed();
+        let result = unsafe {
+            libc::ptrace(
+                libc::PTRACE_GET_SYSCALL_INFO,
+                pid,
+                size_of::<libc::ptrace_syscall_info>(),
+                info.as_mut_ptr(),
+            )
+        };
+        if result == -1 {
+            return Err(FFIError::PtraceError(last_error_string()));
+        }
+        let info = unsafe { info.assume_init() };
+        let since = self
+            .started
+            .map_or(Duration::from_secs(0), |started| started.elapsed());
+        match info.op {
+            libc::PTRACE_SYSCALL_INFO_ENTRY => {
+                let number = unsafe { info.u.entry.nr };
+                let _ = self.entered.insert(pid, (since, number));
+            }
+            libc::PTRACE_SYSCALL_INFO_EXIT => {
+                if let Some(entered) = self.entered.remove(&pid) {
+                    let value = unsafe { info.u.exit.sval };
+                    self.write(pid, entered, &value.to_string())?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    // A traced thread exited, in the middle of exit_group or killed
+    fn exited(&mut self, pid: libc::pid_t) -> Result<()> {
+        match self.entered.remove(&pid) {
+            Some(entered) => self.write(pid, entered, "?"),
+            None => Ok(()),
+        }
+    }
+
+    fn write(
+        &mut self,
+        pid: libc::pid_t,
+        (since, number): (Duration, u64),
+        value: &str,
+    ) -> Result<()> {
+        let name = syscalls::name(number as u32).unwrap_or("unknown");
+        writeln!(
+            self.log,
+            "{}.{:06} [{}] {}({}) = {}",
+            since.as_secs(),
+            since.subsec_micros(),
+            pid,
+            name,
+            number,
+            value
+        )
+        .map_err(|err| FFIError::PtraceError(format!("could not write the log: {}", err)))
+    }
+}
+
+impl Drop for SyscallTracer {
+    fn drop(&mut self) {
+        let pids: Vec<_> = self.entered.keys().cloned().collect();
+        for pid in pids {
+            let _ = self.exited(pid);
+        }
+    }
+}
+
+// The usage `wait4` reports for the reaped child, its threads included
+fn set_rusage(usage: &mut RunUsage, rusage: &libc::rusage) {
+    // ru_maxrss is in kibibytes
+    usage.set_max_rss(Some(SpaceUsage::from_kibibytes(
+        rusage.ru_maxrss as libc::rlim_t,
+    )));
+    usage.set_voluntary_ctxt_switches(Some(rusage.ru_nvcsw as u64));
+    usage.set_involuntary_ctxt_switches(Some(rusage.ru_nivcsw as u64));
+    usage.set_minor_faults(Some(rusage.ru_minflt as u64));
+    usage.set_major_faults(Some(rusage.ru_majflt as u64));
+}
+
+fn ptrace(request: libc::c_uint, pid: libc::pid_t, data: usize) -> Result<()> {
+    let result = unsafe {
+        libc::ptrace(
+            request,
+            pid,
+            ptr::null_mut::<libc::c_void>(),
+            data as *mut libc::c_void,
+        )
+    };
+    // The thread may have been killed meanwhile, its exit is reported next
+    if result == -1 && errno::Errno::last_error().error_code() != libc::ESRCH {
+        Err(FFIError::PtraceError(last_error_string()))
+    } else {
+        Ok(())
+    }
+}
+
+pub(crate) struct CloneHandle<T> {
+    pid: libc::pid_t,
+    share_pid: SharePid,
+    // On CLOCK_MONOTONIC, the setup time counts from here
+    cloned_at: Duration,
+    read_error_pipe: File,
+    // What `try_wait` read of the pipe so far
+    read: Vec<u8>,
+    // Once `try_wait` reaped the process, the pid is free to be reused
+    reaped: bool,
+    // The last `ChildMessage::Spawned` read
+    spawned: Option<libc::pid_t>,
+    // See `terminate_on_drop`
+    drop_grace_period: Option<Duration>,
+    // See `detach`
+    detached: bool,
+    phantom: PhantomData<T>,
+}
+
+impl<T: DeserializeOwned> CloneHandle<T> {
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn wait<F: Fn(Duration) -> StdResult<RunUsage, Error>>(
+        mut self,
+        limits: &Limits,
+        exclude_stopped_time: ExcludeStoppedTime,
+        kill_grace_period: Option<Duration>,
+        wake_up_fd: Option<RawFd>,
+        seccomp_channel: Option<SeccompChannel>,
+        mut tracer: Option<SyscallTracer>,
+        on_usage: Option<&UsageCallback>,
+        control: Option<&ControlChannel>,
+        freeze: Option<Freeze<'_>>,
+        usage: F,
+    ) -> StdResult<RunInfo<Option<T>>, Error> {
+        let listener = match seccomp_channel {
+            Some(seccomp_channel) => seccomp_channel.accept(self.pid)?,
+            None => None,
+        };
+        let (data, supervisor) = if let Some(listener) = listener {
+            let (data, pending) = self.read_result_allowing(&listener)?;
+            (data, Some(listener.supervise(pending)?))
+        } else {
+            let mut data = mem::take(&mut self.read);
+            let _ = self
+                .read_error_pipe
+                .read_to_end(&mut data)
+                .map_err(|err| Error::DeserializeError(err.to_string()))?;
+            (data, None)
+        };
+        let (exec_started, result) = read_messages::<T>(&data)?;
+        // The child reads the clock right before the exec, a child that does not exec ends
+        // with the setup
+        let started = exec_started.unwrap_or_else(monotonic_now);
+        let setup_time = started.checked_sub(self.cloned_at).unwrap_or_default();
+        // The wall clock at `started`
+        let now = SystemTime::now();
+        let started_at = now
+            .checked_sub(monotonic_now().checked_sub(started).unwrap_or_default())
+            .unwrap_or(now);
+
+        let mut stopped_time = Duration::from_secs(0);
+        let mut stopped_since: Option<Instant> = None;
+        // Frozen by the caller, see `ControlChannel`
+        let mut paused_time = Duration::from_secs(0);
+        let mut paused_since: Option<Instant> = None;
+        // Through `JailHandle::signal`, a SIGKILL from the caller is not one from the host
+        let mut sent_sigkill = false;
+        // Along with the status of the wait that reaped the command, if one did
+        let (mut run_info, wait_status) = loop {
+            // Answered once the usage is sampled below
+            let mut status_requested = false;
+            if let Some(control) = control {
+                if let Some(request) = control.next_request() {
+                    let result = match (request, freeze) {
+                        (ControlRequest::Status, _) => {
+                            status_requested = true;
+                            Ok(())
+                        }
+                        (ControlRequest::Signal(signal), _) => {
+                            if unsafe { libc::kill(self.pid, signal) } == -1 {
+                                Err(last_error_string())
+                            } else {
+                                sent_sigkill |= signal == libc::SIGKILL;
+                                Ok(())
+                            }
+                        }
+                        (_, None) => {
+                            Err("the run is not pausable, see `Config::set_pausable`".to_owned())
+                        }
+                        (request, Some(freeze)) => match (request, paused_since) {
+                            // Paused once it is frozen, up to when it starts to thaw
+                            (ControlRequest::Pause, None) => {
+                                freeze(true).map(|()| paused_since = Some(Instant::now()))
+                            }
+                            (ControlRequest::Resume, Some(since)) => {
+                                paused_time += since.elapsed();
+                                paused_since = None;
+                                freeze(false)
+                            }
+                            _ => Ok(()),
+                        }
+                        .map_err(|err| err.to_string()),
+                    };
+                    if !status_requested {
+                        control.reply(result);
+                    }
+                }
+            }
+            let total_paused_time =
+                paused_time + paused_since.map_or(Duration::from_secs(0), |since| since.elapsed());
+            let total_stopped_time = stopped_time
+                + stopped_since.map_or(Duration::from_secs(0), |since| since.elapsed());
+            // Never part of the wall time
+            let elapsed = monotonic_now()
+                .checked_sub(started)
+                .unwrap_or_default()
+                .checked_sub(total_paused_time)
+                .unwrap_or_default();
+            let wall_time = match exclude_stopped_time {
+                ExcludeStoppedTime::Yes => elapsed - total_stopped_time,
+                ExcludeStoppedTime::No => elapsed,
+            };
+            let mut usage = match usage(wall_time) {
+                Ok(usage) => usage,
+                // Only a child that failed before the exec sends a result, possibly before it
+                // got to its cgroups, and its error tells more
+                Err(_) if result.is_some() => RunUsage::default(),
+                Err(err) => return Err(err),
+            };
+            usage.set_stopped_time(total_stopped_time);
+            usage.set_paused_time(total_paused_time);
+            usage.set_setup_time(setup_time);
+
+            if let (true, Some(control)) = (status_requested, control) {
+                let remaining_wall_time = limits
+                    .wall_time()
+                    .map(|limit| limit.checked_sub(usage.wall_time()).unwrap_or_default());
+                control.reply(Ok(RunStatus::new(usage.clone(), remaining_wall_time)));
+            }
+
+            // Cancelled by the handle, with the same grace period as a limit
+            if CANCELLED.load(Ordering::SeqCst) {
+                let wait_status = kill_grace_period
+                    .and_then(|kill_grace_period| self.terminate(kill_grace_period));
+                break (RunInfo::new(RunInfoResult::Cancelled, usage), wait_status);
+            }
 
             if let Some(run_info) = usage.check_limits(limits) {
-                return Ok(run_info);
+                let wait_status = kill_grace_period
+                    .and_then(|kill_grace_period| self.terminate(kill_grace_period));
+                break (run_info, wait_status);
+            }
+
+            if let Some(ref supervisor) = supervisor {
+                if let Some(number) = supervisor.illegal_syscall() {
+                    let result = RunInfoResult::IllegalSyscall {
+                        number,
+                        name: syscalls::name(number).map(str::to_owned),
+                    };
+                    break (RunInfo::new(result, usage), None);
+                }
             }
 
-            // Check if something killed us
+            // Past the checks above, the callback can only hold up the next sample
+            if let Some(on_usage) = on_usage {
+                on_usage.call(&usage);
+            }
+
+            // Check if something killed us. With a wake up fd the wait happens in poll instead,
+            // which SIGCHLD interrupts as well when the child exits or stops (a SIGCHLD landing
+            // just before poll is caught up with on the next alarm tick)
             let mut status: libc::c_int = 0;
-            if unsafe { libc::waitpid(self.pid, &mut status, 0) } == -1 {
-                let error = errno::Errno::last_error();
-                if error.error_code() == libc::EINTR {
-                    continue; // interrupted by some signal
-                }
-                return Err(Error::FFIError(FFIError::WaitPidError(
-                    error.error_string(),
-                )));
-            } else {
-                if libc::WIFEXITED(status) {
-                    let exit_code = libc::WEXITSTATUS(status) as u32;
-                    if exit_code == 0 {
-                        return Ok(RunInfo::new(RunInfoResult::Success(result), usage));
-                    } else {
-                        return Ok(RunInfo::new(
-                            RunInfoResult::NonZeroExitStatus(exit_code),
-                            usage,
-                        ));
+            let options = match wake_up_fd {
+                Some(_) => libc::WUNTRACED | libc::WCONTINUED | libc::WNOHANG,
+                None => libc::WUNTRACED | libc::WCONTINUED,
+            };
+            // The stops of the traced threads are reported only with __WALL
+            let options = match tracer {
+                Some(_) => options | libc::__WALL,
+                None => options,
+            };
+            // Without a pid namespace of its own the orphans of the child are reparented to us,
+            // reap them along the way so they do not count towards the pids limit as zombies.
+            // Every thread and process of the command stops for the tracer as well.
+            let wait_pid = match (self.share_pid, &tracer) {
+                (SharePid::Unshare, None) => self.pid,
+                _ => -1,
+            };
+            let mut rusage = unsafe { mem::zeroed::<libc::rusage>() };
+            match unsafe { libc::wait4(wait_pid, &mut status, options, &mut rusage) } {
+                -1 => {
+                    let error = errno::Errno::last_error();
+                    if error.error_code() == libc::EINTR {
+                        continue; // interrupted by some signal
+                    }
+                    return Err(Error::FFIError(FFIError::WaitPidError(
+                        error.error_string(),
+                    )));
+                }
+                0 => {
+                    if let Some(wake_up_fd) = wake_up_fd {
+                        wait_readable(wake_up_fd)?;
+                    }
+                    continue;
+                }
+                pid => {
+                    if let Some(ref mut tracer) = tracer {
+                        if libc::WIFSTOPPED(status) {
+                            tracer.stopped(pid, status)?;
+                            continue;
+                        }
+                        if libc::WIFEXITED(status) || libc::WIFSIGNALED(status) {
+                            tracer.exited(pid)?;
+                        }
+                    }
+                    if pid != self.pid {
+                        continue; // an orphan, or a traced thread
+                    }
+                }
+            }
+
+            if libc::WIFEXITED(status) || libc::WIFSIGNALED(status) {
+                set_rusage(&mut usage, &rusage);
+            }
+
+            if libc::WIFEXITED(status) {
+                let exit_code = libc::WEXITSTATUS(status) as u32;
+                if exit_code == 0 {
+                    break (
+                        RunInfo::new(RunInfoResult::Success(result), usage),
+                        Some(status),
+                    );
+                } else {
+                    let result = RunInfoResult::NonZeroExitStatus(exit_code);
+                    break (RunInfo::new(result, usage), Some(status));
+                }
+            }
+
+            if libc::WIFSIGNALED(status) {
+                let signal = libc::WTERMSIG(status);
+                // Nothing in its pid namespace can SIGKILL the init of it, so short of the
+                // supervisor that SIGKILL came from outside the sandbox (or the OOM killer of
+                // the machine). In a shared pid namespace the command may have sent it itself.
+                let result = if signal == libc::SIGKILL
+                    && self.share_pid == SharePid::Unshare
+                    && !sent_sigkill
+                {
+                    RunInfoResult::KilledByHost
+                } else {
+                    RunInfoResult::killed_by_signal(signal as u32)
+                };
+                break (RunInfo::new(result, usage), Some(status));
+            }
+
+            // Stopped or continued, the command is still there
+            if libc::WIFSTOPPED(status) {
+                stopped_since = stopped_since.or_else(|| Some(Instant::now()));
+            }
+
+            if libc::WIFCONTINUED(status) {
+                if let Some(since) = stopped_since.take() {
+                    stopped_time += since.elapsed();
+                }
+            }
+        };
+
+        // Frozen processes (on cgroups v1) would not even die
+        if let (Some(freeze), Some(_)) = (freeze, paused_since) {
+            let _ = freeze(false);
+        }
+
+        run_info.set_wait_status(wait_status);
+        self.reaped = wait_status.is_some();
+        // The monotonic duration is the one to trust, the wall clock can jump meanwhile
+        run_info.set_started_at(Some(started_at));
+        run_info.set_finished_at(Some(SystemTime::now()));
+        run_info.set_duration(Some(
+            monotonic_now().checked_sub(started).unwrap_or_default(),
+        ));
+        Ok(run_info)
+    }
+
+    /// Like `wait` for a process that does not exec (the supervisor) with no limits, without
+    /// blocking: `None` while it runs. The pipe is read as it fills, the process would block
+    /// on writing a result larger than it holds otherwise.
+    pub(crate) fn try_wait(&mut self) -> StdResult<Option<RunInfo<Option<T>>>, Error> {
+        self.collect(libc::WNOHANG)
+    }
+
+    /// Like `try_wait`, except that once the pipe is closed it blocks until the process is gone
+    /// (it is exiting by then, and takes the processes of its pid namespace with it)
+    pub(crate) fn take_result(&mut self) -> StdResult<Option<RunInfo<Option<T>>>, Error> {
+        self.collect(0)
+    }
+
+    // Reads the pipe and reaps the process once it is closed, waitpid gets `options`
+    fn collect(&mut self, options: libc::c_int) -> StdResult<Option<RunInfo<Option<T>>>, Error> {
+        let mut data = mem::take(&mut self.read);
+        let closed = self.read_available(&mut data);
+        self.read = data;
+        // Closed once the process exits
+        if !closed? {
+            return Ok(None);
+        }
+
+        let mut status: libc::c_int = 0;
+        loop {
+            match unsafe { libc::waitpid(self.pid, &mut status, options) } {
+                -1 if errno::Errno::last_error().error_code() == libc::EINTR => {
+                    if options & libc::WNOHANG != 0 {
+                        return Ok(None);
+                    }
+                }
+                -1 => return Err(Error::FFIError(FFIError::WaitPidError(last_error_string()))),
+                0 => return Ok(None),
+                _ => break,
+            }
+        }
+        self.reaped = true;
+
+        let (_, result) = read_messages::<T>(&self.read)?;
+        let result = if libc::WIFEXITED(status) && libc::WEXITSTATUS(status) == 0 {
+            RunInfoResult::Success(result)
+        } else if libc::WIFEXITED(status) {
+            RunInfoResult::NonZeroExitStatus(libc::WEXITSTATUS(status) as u32)
+        } else {
+            RunInfoResult::killed_by_signal(libc::WTERMSIG(status) as u32)
+        };
+        let mut run_info = RunInfo::new(result, RunUsage::default());
+        run_info.set_wait_status(Some(status));
+        Ok(Some(run_info))
+    }
+
+    /// The pid of the cloned process, valid for as long as the handle is there
+    pub(crate) fn pid(&self) -> libc::pid_t {
+        self.pid
+    }
+
+    /// Whether the process has yet to exit, it is left to be reaped (and its pipe to be read) by
+    /// the wait for its result
+    pub(crate) fn is_running(&self) -> bool {
+        if self.reaped {
+            return false;
+        }
+        let mut info = unsafe { mem::zeroed::<libc::siginfo_t>() };
+        let options = libc::WEXITED | libc::WNOHANG | libc::WNOWAIT;
+        match unsafe { libc::waitid(libc::P_PID, self.pid as libc::id_t, &mut info, options) } {
+            // Not even a zombie, reaped by someone else
+            -1 => false,
+            _ => unsafe { info.si_pid() == 0 },
+        }
+    }
+
+    /// The read end of the pipe, readable when a message comes and for good once the process
+    /// exits
+    pub(crate) fn pipe_fd(&self) -> RawFd {
+        self.read_error_pipe.as_raw_fd()
+    }
+
+    /// The pid of the child the cloned process reported with `send_spawned`, in the pid
+    /// namespace of the cloned process. `None` until the message went through.
+    pub(crate) fn spawned(&mut self) -> StdResult<Option<libc::pid_t>, Error> {
+        if self.spawned.is_none() && !self.reaped {
+            let mut data = mem::take(&mut self.read);
+            let read = self.read_available(&mut data);
+            self.read = data;
+            let _ = read?;
+            let mut messages = self.read.as_slice();
+            // The last message may not be all there yet
+            while let Ok(message) = bincode::deserialize_from::<_, ChildMessage<T>>(&mut messages) {
+                if let ChildMessage::Spawned(pid) = message {
+                    self.spawned = Some(pid);
+                }
+            }
+        }
+        Ok(self.spawned)
+    }
+
+    /// Like `try_wait`, blocking up to `timeout` for the process to end. The wait is on a pidfd
+    /// of the process (and on the pipe, which must not fill up meanwhile), kernels without
+    /// pidfds are left with the pipe closing and a few short sleeps until the process is gone.
+    pub(crate) fn wait_timeout(
+        &mut self,
+        timeout: Duration,
+    ) -> StdResult<Option<RunInfo<Option<T>>>, Error> {
+        let deadline = Instant::now() + timeout;
+        let mut pidfd = None;
+        loop {
+            if let Some(run_info) = self.try_wait()? {
+                return Ok(Some(run_info));
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining == Duration::from_secs(0) {
+                return Ok(None);
+            }
+            if pidfd.is_none() {
+                pidfd = Some(open_pidfd(self.pid)?);
+            }
+            // Any wake up, spurious or not, is checked again by `try_wait`
+            wait_process_or_readable(
+                pidfd.as_ref().and_then(Option::as_ref),
+                &self.read_error_pipe,
+                remaining,
+            )?;
+        }
+    }
+}
+
+impl<T> CloneHandle<T> {
+    // Reads the result like read_to_end, letting the syscalls the filter passes on through
+    // meanwhile: until the exec closes the pipe they are made by the setup of the sandbox. The
+    // exec closes it before the command runs, so a syscall passed on once the pipe is closed
+    // is one of the command, which is returned instead.
+    fn read_result_allowing(
+        &mut self,
+        listener: &SeccompListener,
+    ) -> StdResult<(Vec<u8>, Option<libc::seccomp_notif>), Error> {
+        let mut data = mem::take(&mut self.read);
+        loop {
+            let mut pollfds = [
+                libc::pollfd {
+                    fd: self.read_error_pipe.as_raw_fd(),
+                    events: libc::POLLIN,
+                    revents: 0,
+                },
+                libc::pollfd {
+                    fd: listener.file.as_raw_fd(),
+                    events: libc::POLLIN,
+                    revents: 0,
+                },
+            ];
+            if unsafe { libc::poll(pollfds.as_mut_ptr(), 2, -1) } == -1 {
+                if errno::Errno::last_error().error_code() == libc::EINTR {
+                    continue;
+                }
+                return Err(Error::FFIError(FFIError::PollError(last_error_string())));
+            }
+
+            if pollfds[0].revents != 0 && self.read_available(&mut data)? {
+                return Ok((data, None));
+            }
+            if pollfds[1].revents & libc::POLLIN != 0 {
+                if let Some(notification) = listener.receive()? {
+                    if self.read_available(&mut data)? {
+                        return Ok((data, Some(notification)));
                     }
+                    listener.respond(notification.id, SeccompResponse::Continue)?;
+                }
+            }
+        }
+    }
+
+    // Reads what the pipe has without blocking, true once it is closed
+    fn read_available(&mut self, data: &mut Vec<u8>) -> StdResult<bool, Error> {
+        loop {
+            let mut pollfd = libc::pollfd {
+                fd: self.read_error_pipe.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            if unsafe { libc::poll(&mut pollfd, 1, 0) } != 1 {
+                return Ok(false);
+            }
+            let mut buffer = [0u8; 4096];
+            match self.read_error_pipe.read(&mut buffer) {
+                Ok(0) => return Ok(true),
+                Ok(len) => data.extend_from_slice(&buffer[..len]),
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(Error::DeserializeError(err.to_string())),
+            }
+        }
+    }
+
+    /// Once dropped before it was reaped, the process gets SIGTERM and `grace_period` to exit
+    /// before SIGKILL, and is reaped either way. Drop only sends SIGKILL otherwise, and leaves
+    /// the zombie behind.
+    pub(crate) fn terminate_on_drop(&mut self, grace_period: Duration) {
+        self.drop_grace_period = Some(grace_period);
+    }
+
+    /// Leaves the process alone on drop
+    pub(crate) fn detach(&mut self) {
+        self.detached = true;
+    }
+
+    /// Sends SIGTERM to the supervisor, which kills the command and ends the run as
+    /// `RunInfoResult::Cancelled`. The pid stays there until the handle reaps it, a supervisor
+    /// already done gets it as a zombie and nothing happens.
+    pub(crate) fn cancel(&self) -> Result<()> {
+        if self.reaped {
+            return Ok(());
+        }
+        if unsafe { libc::kill(self.pid, libc::SIGTERM) } == -1 {
+            Err(FFIError::KillError(last_error_string()))
+        } else {
+            Ok(())
+        }
+    }
+
+    // Sends SIGTERM to the process (and its process group) and waits for it to exit for at most
+    // `grace_period`, drop kills whatever is left. waitpid is interrupted by the alarm every
+    // tick, so the grace period ends on time even if the process ignores SIGTERM. The status of
+    // the process if it exited meanwhile.
+    fn terminate(&mut self, grace_period: Duration) -> Option<libc::c_int> {
+        let _ = unsafe { libc::kill(self.pid, libc::SIGTERM) };
+        let _ = unsafe { libc::kill(-self.pid, libc::SIGTERM) };
+
+        let timer = Instant::now();
+        while timer.elapsed() < grace_period {
+            let mut status: libc::c_int = 0;
+            match unsafe { libc::waitpid(self.pid, &mut status, 0) } {
+                -1 if errno::Errno::last_error().error_code() == libc::EINTR => {}
+                -1 => return None,
+                _ => {
+                    self.reaped = true;
+                    return Some(status);
+                }
+            }
+        }
+        None
+    }
+}
+
+/// The pid of the child `pid` of `parent` (as `parent` sees it from its pid namespace) in the
+/// pid namespace of the caller, looked up among the children of `parent` in /proc
+pub(crate) fn host_pid_of_child(parent: libc::pid_t, pid: libc::pid_t) -> Option<libc::pid_t> {
+    // The pids of a process in its namespace and the ones above it, from the one of /proc down
+    let ns_pids = |pid: &str| -> Option<Vec<libc::pid_t>> {
+        let status = fs::read_to_string(format!("/proc/{}/status", pid)).ok()?;
+        let line = status.lines().find(|line| line.starts_with("NSpid:"))?;
+        line["NSpid:".len()..]
+            .split_whitespace()
+            .map(|pid| pid.parse().ok())
+            .collect()
+    };
+
+    let depth = ns_pids(&parent.to_string())?.len();
+    let tasks = fs::read_dir(format!("/proc/{}/task", parent)).ok()?;
+    for task in tasks.filter_map(StdResult::ok) {
+        let children = fs::read_to_string(task.path().join("children")).unwrap_or_default();
+        for child in children.split_whitespace() {
+            match ns_pids(child) {
+                Some(ref child_pids) if child_pids.get(depth - 1) == Some(&pid) => {
+                    return child_pids.first().cloned()
                 }
+                _ => {}
+            }
+        }
+    }
+    None
+}
+
+// The state and the start time (in clock ticks after boot) of `pid`, from /proc
+fn process_stat(pid: libc::pid_t) -> Option<(char, u64)> {
+    let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // The command name in between the parentheses can hold anything
+    let mut fields = stat[stat.rfind(')')? + 1..].split_whitespace();
+    let state = fields.next()?.chars().next()?;
+    // Field 22, the state being field 3
+    let start_time = fields.nth(18)?.parse().ok()?;
+    Some((state, start_time))
+}
+
+/// The start time of `pid`, which tells it apart from a later process with the same pid. `None`
+/// once it exited.
+pub(crate) fn process_start_time(pid: libc::pid_t) -> Option<u64> {
+    match process_stat(pid)? {
+        ('Z', _) | ('X', _) => None,
+        (_, start_time) => Some(start_time),
+    }
+}
+
+/// A process some other process cloned, which can not be waited on but only watched until it
+/// exits. `start_time` (see `process_start_time`) guards against a later process with its pid.
+pub(crate) struct ForeignProcess {
+    pid: libc::pid_t,
+    start_time: u64,
+    pidfd: Option<File>,
+}
+
+impl ForeignProcess {
+    /// `None` if the process already exited
+    pub(crate) fn open(pid: libc::pid_t, start_time: u64) -> StdResult<Option<Self>, Error> {
+        // Opened before the check, the pidfd then refers to the process that passed it
+        let pidfd = match open_pidfd(pid) {
+            Err(_) if process_start_time(pid).is_none() => return Ok(None),
+            pidfd => pidfd?,
+        };
+        let process = Self {
+            pid,
+            start_time,
+            pidfd,
+        };
+        Ok(if process.has_exited() {
+            None
+        } else {
+            Some(process)
+        })
+    }
+
+    pub(crate) fn has_exited(&self) -> bool {
+        process_start_time(self.pid) != Some(self.start_time)
+    }
 
-                if libc::WIFSIGNALED(status) {
-                    let signal = libc::WTERMSIG(status) as u32;
-                    return Ok(RunInfo::new(RunInfoResult::KilledBySignal(signal), usage));
+    /// Blocks for at most `timeout` until the process exits, kernels without pidfds are left
+    /// with checking on it every `FOREIGN_PROCESS_POLL_INTERVAL`
+    pub(crate) fn wait_exit(&self, timeout: Duration) -> StdResult<(), Error> {
+        if let Some(ref pidfd) = self.pidfd {
+            let mut pollfd = libc::pollfd {
+                fd: pidfd.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            let timeout_ms = timeout
+                .as_nanos()
+                .div_ceil(1_000_000)
+                .min(libc::c_int::MAX as u128) as libc::c_int;
+            match unsafe { libc::poll(&mut pollfd, 1, timeout_ms) } {
+                -1 if errno::Errno::last_error().error_code() != libc::EINTR => {
+                    Err(Error::FFIError(FFIError::PollError(last_error_string())))
                 }
+                _ => Ok(()),
+            }
+        } else {
+            thread::sleep(FOREIGN_PROCESS_POLL_INTERVAL.min(timeout));
+            Ok(())
+        }
+    }
+
+    pub(crate) fn signal(&self, signal: libc::c_int) -> StdResult<(), Error> {
+        let result = match self.pidfd {
+            Some(ref pidfd) => unsafe {
+                libc::syscall(
+                    libc::SYS_pidfd_send_signal,
+                    pidfd.as_raw_fd(),
+                    signal,
+                    ptr::null::<libc::siginfo_t>(),
+                    0,
+                )
+            },
+            None if self.has_exited() => return Ok(()),
+            None => libc::c_long::from(unsafe { libc::kill(self.pid, signal) }),
+        };
+        match result {
+            // Exited meanwhile
+            -1 if errno::Errno::last_error().error_code() == libc::ESRCH => Ok(()),
+            -1 => Err(Error::FFIError(FFIError::KillError(last_error_string()))),
+            _ => Ok(()),
+        }
+    }
+}
+
+// How often `ForeignProcess::wait_exit` checks on the process without a pidfd
+const FOREIGN_PROCESS_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+// A pidfd, readable once the process exits, `None` on kernels before 5.3
+fn open_pidfd(pid: libc::pid_t) -> StdResult<Option<File>, Error> {
+    match unsafe { libc::syscall(libc::SYS_pidfd_open, pid, 0) } {
+        -1 if errno::Errno::last_error().error_code() == libc::ENOSYS => Ok(None),
+        -1 => Err(Error::FFIError(FFIError::PollError(last_error_string()))),
+        fd => Ok(Some(unsafe { File::from_raw_fd(fd as RawFd) })),
+    }
+}
 
-                if libc::WIFSTOPPED(status) || libc::WIFCONTINUED(status) {
-                    return Err(Error::StoppedContinuedError);
+// Blocks for at most `timeout` until the process of `pidfd` exits or `pipe` has something to
+// read (or is closed). Without a pidfd a closed pipe only means the process is about to exit.
+fn wait_process_or_readable(
+    pidfd: Option<&File>,
+    pipe: &File,
+    timeout: Duration,
+) -> StdResult<(), Error> {
+    const EXITING_SLEEP: Duration = Duration::from_millis(1);
+
+    let mut pollfds = [
+        libc::pollfd {
+            fd: pipe.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        },
+        libc::pollfd {
+            fd: pidfd.map_or(-1, AsRawFd::as_raw_fd),
+            events: libc::POLLIN,
+            revents: 0,
+        },
+    ];
+    // Rounded up, poll would spin through the last fraction of a millisecond otherwise
+    let timeout_ms = timeout
+        .as_nanos()
+        .div_ceil(1_000_000)
+        .min(libc::c_int::MAX as u128) as libc::c_int;
+    match unsafe { libc::poll(pollfds.as_mut_ptr(), 2, timeout_ms) } {
+        -1 if errno::Errno::last_error().error_code() == libc::EINTR => Ok(()),
+        -1 => Err(Error::FFIError(FFIError::PollError(last_error_string()))),
+        _ => {
+            // A closed pipe stays readable, with a pidfd there is that to wait on instead
+            if pollfds[0].revents & libc::POLLHUP != 0 {
+                if pidfd.is_some() {
+                    pollfds[0].fd = -1;
+                    let _ = unsafe { libc::poll(pollfds.as_mut_ptr(), 2, timeout_ms) };
+                } else {
+                    thread::sleep(EXITING_SLEEP.min(timeout));
                 }
             }
+            Ok(())
+        }
+    }
+}
+
+// The messages of the cloned process: when its last exec started, and its result
+fn read_messages<T: DeserializeOwned>(
+    data: &[u8],
+) -> StdResult<(Option<Duration>, Option<T>), Error> {
+    let mut exec_started = None;
+    let mut result = None;
+    let mut messages = data;
+    while !messages.is_empty() {
+        match bincode::deserialize_from::<_, ChildMessage<T>>(&mut messages)
+            .map_err(|err| Error::DeserializeError(err.to_string().into()))?
+        {
+            ChildMessage::ExecStarting(at) => exec_started = Some(at),
+            ChildMessage::Spawned(_) => {}
+            ChildMessage::Result(Ok(data)) => result = Some(data),
+            ChildMessage::Result(Err(err)) => return Err(Error::Internal(err)),
         }
     }
+    Ok((exec_started, result))
 }
 
 impl<T> Drop for CloneHandle<T> {
     fn drop(&mut self) {
-        let _ = unsafe { libc::kill(self.pid, libc::SIGKILL) };
+        if self.detached {
+            return;
+        }
+        let mut status: libc::c_int = 0;
+        if let (Some(grace_period), false) = (self.drop_grace_period, self.reaped) {
+            let _ = unsafe { libc::kill(self.pid, libc::SIGTERM) };
+            let timer = Instant::now();
+            while !self.reaped && timer.elapsed() < grace_period {
+                match unsafe { libc::waitpid(self.pid, &mut status, libc::WNOHANG) } {
+                    0 => thread::sleep(DROP_POLL_INTERVAL),
+                    -1 if errno::Errno::last_error().error_code() == libc::EINTR => {}
+                    _ => self.reaped = true,
+                }
+            }
+        }
+        if !self.reaped {
+            let _ = unsafe { libc::kill(self.pid, libc::SIGKILL) };
+        }
         let _ = unsafe { libc::kill(-self.pid, libc::SIGKILL) };
+        if let (Some(_), false) = (self.drop_grace_period, self.reaped) {
+            // Dies right away, unless stuck in the kernel
+            while unsafe { libc::waitpid(self.pid, &mut status, 0) } == -1
+                && errno::Errno::last_error().error_code() == libc::EINTR
+            {}
+        }
     }
 }
+
+// How often drop checks whether the process exited within the grace period
+const DROP_POLL_INTERVAL: Duration = Duration::from_millis(1);
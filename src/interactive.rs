@@ -0,0 +1,173 @@
+//! Two runs talking to each other: a solution and the interactor checking it, the output of each
+//! going to the input of the other
+
+use std::time::Duration;
+
+use config::{Config, Redirect};
+use errors::{ConfigError, Result};
+use libc;
+use run_info::{RunInfo, RunInfoResult};
+use {spawn_connected, spawn_jail, JailHandle};
+
+/// How `InteractivePair::wait` waits for the two runs
+#[derive(Debug, Eq, PartialEq, Copy, Clone, Default)]
+pub struct PairOptions {
+    solution_grace_period: Option<Duration>,
+}
+
+impl PairOptions {
+    pub fn new(solution_grace_period: Option<Duration>) -> Self {
+        Self {
+            solution_grace_period,
+        }
+    }
+
+    /// How long the solution may go on once the interactor is over, it is cancelled after. An
+    /// interactor that is done has nothing left to tell it, but a solution that does not read
+    /// its input would only end with its limits. Left to its limits if `None`.
+    pub fn solution_grace_period(self) -> Option<Duration> {
+        self.solution_grace_period
+    }
+
+    pub fn set_solution_grace_period(&mut self, solution_grace_period: Option<Duration>) {
+        self.solution_grace_period = solution_grace_period;
+    }
+}
+
+/// Which of the two runs the verdict of a pair comes from
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum InteractiveOutcome {
+    /// Both exited successfully
+    Success,
+    /// The solution failed (exceeded a limit, crashed, ...), whatever happened to the interactor
+    /// followed from it. Also when both exceeded a limit, an interactor mostly waits on the
+    /// solution.
+    SolutionFailed,
+    /// The interactor failed with the solution successful, or with the solution only killed for
+    /// writing to the interactor gone (`SIGPIPE`) or cancelled by `PairOptions`
+    InteractorFailed,
+}
+
+impl InteractiveOutcome {
+    fn of(solution: &RunInfo<()>, interactor: &RunInfo<()>) -> Self {
+        // Ended by the interactor going away, not of its own
+        let cut_short = match *solution.result() {
+            RunInfoResult::KilledBySignal(signal) => signal as libc::c_int == libc::SIGPIPE,
+            RunInfoResult::Cancelled => true,
+            _ => false,
+        };
+        match (solution.is_success(), interactor.is_success()) {
+            (true, true) => Self::Success,
+            (true, false) => Self::InteractorFailed,
+            (false, false) if cut_short => Self::InteractorFailed,
+            (false, _) => Self::SolutionFailed,
+        }
+    }
+}
+
+/// What `InteractivePair::wait` returns
+#[derive(Debug, PartialEq, Clone)]
+pub struct InteractiveRunInfo {
+    solution: RunInfo<()>,
+    interactor: RunInfo<()>,
+    outcome: InteractiveOutcome,
+}
+
+impl InteractiveRunInfo {
+    fn new(solution: RunInfo<()>, interactor: RunInfo<()>) -> Self {
+        let outcome = InteractiveOutcome::of(&solution, &interactor);
+        Self {
+            solution,
+            interactor,
+            outcome,
+        }
+    }
+
+    pub fn solution(&self) -> &RunInfo<()> {
+        &self.solution
+    }
+
+    pub fn interactor(&self) -> &RunInfo<()> {
+        &self.interactor
+    }
+
+    pub fn outcome(&self) -> InteractiveOutcome {
+        self.outcome
+    }
+
+    pub fn into_run_infos(self) -> (RunInfo<()>, RunInfo<()>) {
+        (self.solution, self.interactor)
+    }
+}
+
+/// The two runs of `spawn_interactive_pair`
+#[allow(missing_debug_implementations)]
+pub struct InteractivePair {
+    solution: JailHandle,
+    interactor: JailHandle,
+    options: PairOptions,
+}
+
+impl InteractivePair {
+    pub fn solution(&self) -> &JailHandle {
+        &self.solution
+    }
+
+    pub fn interactor(&self) -> &JailHandle {
+        &self.interactor
+    }
+
+    /// Cancels both runs, see `JailHandle::kill`
+    pub fn kill(&self) -> Result<()> {
+        self.interactor.kill()?;
+        self.solution.kill()
+    }
+
+    /// Waits for the interactor, then for the solution (see `PairOptions`). Either one sees the
+    /// other go away as EOF on its stdin, or as EPIPE (and `SIGPIPE`) writing to its stdout.
+    pub fn wait(self) -> Result<InteractiveRunInfo> {
+        let Self {
+            mut solution,
+            interactor,
+            options,
+        } = self;
+        let interactor = interactor.wait()?;
+        if let Some(grace_period) = options.solution_grace_period() {
+            if solution.wait_timeout(grace_period)?.is_none() {
+                solution.kill()?;
+            }
+        }
+        Ok(InteractiveRunInfo::new(solution.wait()?, interactor))
+    }
+}
+
+/// Spawns `solution` and `interactor` with the stdout of each connected to the stdin of the
+/// other, through pipes the caller keeps no end of. Both have to redirect stdin and stdout to
+/// `Redirect::Pipe`, the solution starts first and gets ahead as far as the pipe holds.
+pub fn spawn_interactive_pair(
+    solution: &Config,
+    interactor: &Config,
+    options: PairOptions,
+) -> Result<InteractivePair> {
+    for config in &[solution, interactor] {
+        for &(name, redirect) in &[
+            ("stdin", config.redirect_stdin()),
+            ("stdout", config.redirect_stdout()),
+        ] {
+            if *redirect != Redirect::Pipe {
+                return Err(ConfigError::InteractiveRedirect(name.to_owned()).into());
+            }
+        }
+    }
+
+    let mut solution = spawn_jail(solution)?;
+    // The interactor reads what the solution writes and writes what the solution reads. Dropped
+    // by `spawn_connected`, only the commands are left with them.
+    let ends = (solution.stdout.take(), solution.stdin.take());
+    let interactor = spawn_connected(interactor, ends)?;
+    Ok(InteractivePair {
+        solution,
+        interactor,
+        options,
+    })
+}
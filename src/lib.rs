@@ -45,146 +45,1310 @@ extern crate bincode;
 extern crate failure;
 extern crate libc;
 extern crate serde;
+extern crate serde_json;
 #[macro_use]
 extern crate serde_derive;
 
-mod cgroups;
+pub mod binfmt;
+pub mod cgroups;
 pub mod config;
+pub mod environment;
 pub mod errors;
 mod ffi;
+pub mod interactive;
+pub mod mount_info;
+pub mod net_dev;
+pub mod recovery;
 pub mod run_info;
+pub mod seccomp;
+pub mod seccomp_profiles;
+mod syscalls;
+mod tarball;
 pub mod utils;
+pub mod version_info;
 
-use config::{CloneUser, Config, Interactive, Limits, ShareNet, SwapRedirects};
+use std::cell::Cell;
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::result::Result as StdResult;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+use serde::de::DeserializeOwned;
+
+use config::{
+    AllocatePty, ClearUsage, CloneUser, CloseFds, Config, DenyNestedNamespaces, Environment,
+    ExcludeStoppedTime, FdSource, Interactive, IsolationMode, LandlockUnsupported, Limits,
+    MarkInsideSandbox, MinimalDev, Mount, MountBinfmtInterpreter, MountCgroups, MountKind,
+    MountOptions, NamespaceKind, Pausable, PrivateTmp, Redirect, RootImage, SeccompPolicy,
+    ShareIpc, ShareNet, SharePid, SingleExec, SpaceUsage, SwapRedirects,
+};
+pub use environment::{capabilities, check_environment};
 pub use errors::*;
 use ffi::CloneHandle;
-use run_info::{RunInfo, RunUsage};
+pub use interactive::{spawn_interactive_pair, InteractivePair, PairOptions};
+use mount_info::MountInfo;
+use net_dev::{NetDev, NetTraffic};
+pub use recovery::{AdoptedJail, RecoveryToken};
+use run_info::{DerivedWallTime, RunInfo, RunInfoResult, RunStatus, RunUsage};
+pub use version_info::VERSION_INFO;
+
+// Set in the processes spawned by spawn_jail, which start out as a copy of the caller. A
+// sandbox spawned from in there would look for the host cgroup paths and inherit the signal
+// handlers and result pipes of the outer one, so it is refused instead.
+static INSIDE_SANDBOX: AtomicBool = AtomicBool::new(false);
+
+/// Set in the environment of the commands run with `MarkInsideSandbox::Yes`, `spawn_jail`
+/// refuses to run from there
+pub const INSIDE_SANDBOX_VAR: &str = "IA_SANDBOX_INSIDE";
+
+// Read by the supervisor, which shares the network namespace of the caller
+const NET_DEV_PATH: &str = "/proc/self/net/dev";
 
 pub fn spawn_jail(config: &Config) -> Result<JailHandle> {
+    spawn_connected(config, (None, None))
+}
+
+// Like `spawn_jail`, the command reads from `stdin` and writes to `stdout` when given, ends of
+// pipes to another sandbox (see `spawn_interactive_pair`)
+fn spawn_connected(
+    config: &Config,
+    (stdin, stdout): (Option<File>, Option<File>),
+) -> Result<JailHandle> {
+    if INSIDE_SANDBOX.load(Ordering::SeqCst) || env::var_os(INSIDE_SANDBOX_VAR).is_some() {
+        return Err(Error::NestedSandboxUnsupported);
+    }
+    config
+        .validate()
+        .map_err(|errors| Error::InvalidConfig(ConfigErrors::new(errors)))?;
+
+    // Before the sandbox opens anything it keeps open, until the child takes their place
+    let extra_fds: Vec<_> = config.extra_fds().iter().map(|&(fd, _)| fd).collect();
+    let _fd_reservation = ffi::FdReservation::new(&extra_fds)?;
+
+    if !config.mounts().is_empty() {
+        let mount_info = MountInfo::read()?;
+        for mount in config.mounts() {
+            mount.check_source_flags(&mount_info)?;
+        }
+    }
+
+    // A foreign architecture command runs through its binfmt_misc interpreter, which has to
+    // exist inside the new root as well
+    let missing_interpreter = match config.new_root() {
+        Some(new_root) => binfmt::find_missing_interpreter(new_root, config.command())?,
+        None => None,
+    };
+    let (binfmt_mounts, missing_interpreter) = match missing_interpreter {
+        Some(ref missing_interpreter)
+            if config.mount_binfmt_interpreter() == MountBinfmtInterpreter::Yes =>
+        {
+            let mut mount_options = MountOptions::default();
+            mount_options.set_exec(true);
+            let interpreter = missing_interpreter.interpreter().to_path_buf();
+            (
+                vec![Mount::new(interpreter.clone(), interpreter, mount_options)],
+                None,
+            )
+        }
+        missing_interpreter => (Vec::new(), missing_interpreter),
+    };
+
+    // io limits apply per device, throttle the one backing the root of the sandbox
+    let limits = config.limits();
+    let io_device = if limits.read_bps().is_some() || limits.write_bps().is_some() {
+        let root = config.new_root().unwrap_or_else(|| Path::new("/"));
+        let root = fs::canonicalize(root).unwrap_or_else(|_| root.to_path_buf());
+        Some(
+            MountInfo::read()?
+                .block_device(&root)
+                .ok_or(ConfigError::IoDeviceNotFound(root))?,
+        )
+    } else {
+        None
+    };
+
+    // Reported along with the result
+    let mut warnings = Vec::new();
+
+    // Checked here, the restriction only happens right before the exec
+    let landlock = match config.landlock() {
+        Some(landlock) => match environment::check_landlock() {
+            Ok(()) => Some(landlock),
+            Err(check) => match landlock.unsupported() {
+                LandlockUnsupported::Fail => {
+                    return Err(Error::UnsupportedEnvironment {
+                        feature: check.feature().to_string(),
+                        error: check.error().unwrap_or_default().to_owned(),
+                        hint: check.feature().hint(),
+                    })
+                }
+                LandlockUnsupported::Warn => {
+                    warnings.push(format!(
+                        "running without the filesystem restriction, {}",
+                        check
+                    ));
+                    None
+                }
+            },
+        },
+        None => None,
+    };
+
+    // Controllers left to their default paths go where the caller may create cgroups, which
+    // inside a container is not always the root of the mounted hierarchy
+    let controller_path = cgroups::probe()?.fill_controller_path(config.controller_path());
+
+    // Opened before the supervisor mounts a /proc of its own pid namespace, so `/proc/<pid>`
+    // paths are the ones of the caller
+    let namespace_files = config
+        .join_namespaces()
+        .iter()
+        .map(|(kind, path)| ffi::NamespaceFile::open(*kind, path))
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .map_err(Error::FFIError)?;
+
+    // The child can not reach the files of the host once it pivoted
+    let copy_in_files = config
+        .copy_in()
+        .iter()
+        .map(|(source, destination)| {
+            File::open(source).map_err(|error| FFIError::CopyError {
+                source: source.clone(),
+                destination: destination.clone(),
+                error: error.to_string(),
+            })
+        })
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .map_err(Error::FFIError)?;
+
+    // Kernels can have user namespaces disabled, as root the sandbox can do without them by
+    // switching to an unprivileged user instead
+    let root_mode = config.clone_user() == CloneUser::Yes
+        && match config.isolation_mode() {
+            IsolationMode::Auto => ffi::is_root() && environment::check_user_namespaces().is_err(),
+            IsolationMode::UserNamespace => false,
+            IsolationMode::Root => true,
+        };
+    if root_mode && !ffi::is_root() {
+        return Err(ConfigError::RootModeWithoutRoot.into());
+    }
+    let clone_user = if root_mode {
+        CloneUser::No
+    } else {
+        config.clone_user()
+    };
+    if let (Some(RootImage::Squashfs(_)), CloneUser::Yes) = (config.root_image(), clone_user) {
+        return Err(ConfigError::SquashfsInUserNamespace.into());
+    }
+    // Failing here tells what is missing, the clone would only fail with EPERM or EINVAL
+    if clone_user == CloneUser::Yes {
+        environment::check_user_namespaces().map_err(|check| Error::UnsupportedEnvironment {
+            feature: check.feature().to_string(),
+            error: check.error().unwrap_or_default().to_owned(),
+            hint: check.feature().hint(),
+        })?;
+    }
+
+    // The caller keeps the master end in the `JailHandle`, the supervisor passes the slave on
+    let (pty_master, pty_slave) = match config.allocate_pty() {
+        AllocatePty::Yes => {
+            let (master, slave) = ffi::open_pty()?;
+            (Some(master), Some(slave))
+        }
+        AllocatePty::No => (None, None),
+    };
+    // Likewise for the pipes of the standard streams
+    let stdio_pipe = |redirect: &Redirect, fd, connected: Option<File>| match (redirect, connected)
+    {
+        (_, Some(connected)) => Ok(Some(ffi::StdioPipe::connected(connected))),
+        (Redirect::Pipe, None) => ffi::StdioPipe::new(fd).map(Some),
+        _ => Ok(None),
+    };
+    let stdin_pipe = stdio_pipe(config.redirect_stdin(), ffi::STDIN, stdin)?;
+    let stdout_pipe = stdio_pipe(config.redirect_stdout(), ffi::STDOUT, stdout)?;
+    let stderr_pipe = stdio_pipe(config.redirect_stderr(), ffi::STDERR, None)?;
+    let stdio_pipes = || stdin_pipe.iter().chain(&stdout_pipe).chain(&stderr_pipe);
+    // And for the socket channel
+    let socket_channel = match config.socket_channel() {
+        Some(_) => Some(ffi::SocketChannel::new()?),
+        None => None,
+    };
+
     let user_group_id = ffi::get_user_group_id();
+    ffi::record_caller_uid();
 
     ffi::set_sig_alarm_handler().map_err(Error::FFIError)?;
 
     // Start a supervisor process in a different pid namespace
     // If by any chance the supervisor process dies, by rules of pid namespaces
     // all its descendant processes will die as well
-    ffi::clone(ShareNet::Share, false, config.clone_user(), || {
-        ffi::kill_on_parent_death()?;
+    // The supervisor is always the init of its own pid namespace, the jail may share it
+    let (share_net, share_ipc, share_pid) = (ShareNet::Share, ShareIpc::Share, SharePid::Unshare);
+    // Read by whichever process adopts the run, from wherever it runs
+    let result_file = config
+        .detached()
+        .map(|path| env::current_dir().map_or_else(|_| path.to_path_buf(), |dir| dir.join(path)));
+    // And for `JailHandle::pause`, `JailHandle::signal` and `JailHandle::status`, which an
+    // adopted run answers next to its result file
+    let control_socket = result_file
+        .as_ref()
+        .map(|result_file| recovery::control_socket_path(result_file));
+    let control_channel = ffi::ControlChannel::new(control_socket.as_ref().map(PathBuf::as_path))?;
+    let supervise = || {
+        INSIDE_SANDBOX.store(true, Ordering::SeqCst);
+        ffi::set_sig_term_handler()?;
+        let _ = ffi::set_signal_blocked(libc::SIGTERM, false);
+        for stdio_pipe in stdio_pipes() {
+            stdio_pipe.close_caller_end();
+        }
+        if let Some(ref socket_channel) = socket_channel {
+            socket_channel.close_caller_end();
+        }
+        control_channel.close_caller_end();
+        if result_file.is_none() {
+            ffi::kill_on_parent_death()?;
+        }
+        ffi::set_sig_child_handler()?;
         // Mount proc just for security
-        ffi::mount_proc()?;
+        ffi::mount_proc(None)?;
         // Without setting uid/gid maps user is not seen so it can not do anything
-        if config.clone_user() == CloneUser::Yes {
-            ffi::set_uid_gid_maps(user_group_id)?;
+        if clone_user == CloneUser::Yes {
+            ffi::set_uid_gid_maps(user_group_id, (ffi::UserId::ROOT, ffi::GroupId::ROOT))?;
         }
 
-        ffi::clone(config.share_net(), true, config.clone_user(), || {
-            if config.swap_redirects() == SwapRedirects::Yes {
-                if let Some(stdout) = config.redirect_stdout() {
-                    ffi::redirect_fd(ffi::STDOUT, stdout)?;
-                }
+        if let Some(parent_limits) = config.parent_limits() {
+            cgroups::create_parent_cgroups(
+                &controller_path,
+                config.instance_name(),
+                parent_limits,
+            )?;
+        }
+
+        // Watch for OOM kills before the child even starts, the sampled usage can read back
+        // just under the limit even though the kernel already killed the process
+        let oom_notifier = cgroups::OomNotifier::new(&controller_path, config.instance_name())?;
+        // Without it a run allocating fast can get far past the limit before the next sample
+        let memory_pressure_notifier = match limits.memory() {
+            Some(_) => Some(cgroups::MemoryPressureNotifier::new(
+                &controller_path,
+                config.instance_name(),
+            )?),
+            None => None,
+        };
+        // cpu.stat and io.stat can not be reset, so remember where their counters start from
+        let (throttling_offset, (io_read_offset, io_written_offset)) = match config.clear_usage() {
+            ClearUsage::Yes => (
+                cgroups::get_throttling(&controller_path, config.instance_name())?,
+                cgroups::get_io_usage(&controller_path, config.instance_name())?,
+            ),
+            ClearUsage::No => (None, (SpaceUsage::from_bytes(0), SpaceUsage::from_bytes(0))),
+        };
+        let user_time_offset = cgroups::get_user_time_offset(
+            &controller_path,
+            config.instance_name(),
+            config.clear_usage(),
+        )?;
+        let (throttled_time_offset, nr_throttled_offset) =
+            throttling_offset.unwrap_or((Duration::from_secs(0), 0));
+        // Network counters belong to the whole namespace, a shared one has counted traffic long
+        // before the run and a new one starts from nothing
+        let net_traffic_offset = match config.share_net() {
+            ShareNet::Share => NetTraffic::read(NET_DEV_PATH)?,
+            ShareNet::Unshare => NetTraffic::default(),
+        };
+
+        let rss_peak = Cell::new(0);
+
+        // Opened here in the host network namespace, the jail sets up the veth pair through
+        // them from inside its own one (the supervisor is blocked until the exec meanwhile)
+        let host_net = match config.veth() {
+            Some(_) => Some(ffi::HostNetSockets::new()?),
+            None => None,
+        };
+
+        // Only a policy killing on the other syscalls or notifying about some (or a single exec)
+        // needs the supervisor, which then can not wait for the exec in a vfork
+        let seccomp_channel = if config.single_exec() == SingleExec::Yes
+            || config
+                .seccomp()
+                .into_iter()
+                .any(SeccompPolicy::needs_listener)
+        {
+            Some(ffi::SeccompChannel::new(
+                config.seccomp(),
+                config.seccomp_notify_handler().cloned(),
+            )?)
+        } else {
+            None
+        };
+
+        let root_channel = if config.copy_out().is_empty() {
+            None
+        } else {
+            Some(ffi::RootChannel::new()?)
+        };
+
+        let tracer = match config.trace_syscalls() {
+            Some(path) => Some(ffi::SyscallTracer::new(path)?),
+            None => None,
+        };
+
+        // Files can only be made on a tmpfs (or through an overlay) by users mapped in the user
+        // namespace it was mounted in, and the one of the child has no mapping until it is all
+        // set up. The mounts of the supervisor end up in the child as well, and do not propagate
+        // back to the host from a user namespace of its own.
+        if let (Some(new_root), CloneUser::Yes) = (config.new_root(), clone_user) {
+            mount_overlays(config, new_root)?;
+            if config.minimal_dev() == MinimalDev::Yes {
+                ffi::mount_minimal_dev(new_root)?;
             }
+            mount_private_tmp(config, new_root)?;
+        }
 
-            if let Some(stdin) = config.redirect_stdin() {
-                ffi::redirect_fd(ffi::STDIN, stdin)?;
+        // Made before the clone so the child inherits them, drained while waiting
+        let capture_stdout = match config.redirect_stdout().capture_limit() {
+            Some(limit) => Some(ffi::CapturePipe::new(
+                ffi::STDOUT,
+                limit,
+                SpaceUsage::from_bytes(0),
+            )?),
+            None => None,
+        };
+        let capture_stderr = match config.redirect_stderr().capture_limit() {
+            Some(limit) => Some(ffi::CapturePipe::new(
+                ffi::STDERR,
+                limit,
+                config.stderr_tail(),
+            )?),
+            None => None,
+        };
+        let tee_pipe = |redirect: &Redirect, fd| match *redirect {
+            Redirect::Tee { ref file, limit } => ffi::TeePipe::new(fd, file, limit).map(Some),
+            _ => Ok(None),
+        };
+        let tee_stdout = tee_pipe(config.redirect_stdout(), ffi::STDOUT)?;
+        let tee_stderr = tee_pipe(config.redirect_stderr(), ffi::STDERR)?;
+        let stdin_bytes = match *config.redirect_stdin() {
+            Redirect::Bytes(ref bytes) => Some(ffi::sealed_memfd(bytes)?),
+            _ => None,
+        };
+        // With an output limit the supervisor has to see the output going to the caller
+        let relay_pipe =
+            |stdio_pipe: &Option<ffi::StdioPipe>, fd| match (stdio_pipe, limits.output()) {
+                (Some(_), Some(_)) => ffi::RelayPipe::new(fd).map(Some),
+                _ => Ok(None),
+            };
+        let relay_stdout = relay_pipe(&stdout_pipe, ffi::STDOUT)?;
+        let relay_stderr = relay_pipe(&stderr_pipe, ffi::STDERR)?;
+        // Only one of them can be there for a stream
+        let stdin_end = stdin_pipe
+            .as_ref()
+            .map(ffi::StdioPipe::command_end)
+            .or(stdin_bytes.as_ref());
+        let stdout_end = capture_stdout
+            .as_ref()
+            .map(ffi::CapturePipe::writer)
+            .or_else(|| tee_stdout.as_ref().map(ffi::TeePipe::writer))
+            .or_else(|| relay_stdout.as_ref().map(ffi::RelayPipe::writer))
+            .or_else(|| stdout_pipe.as_ref().map(ffi::StdioPipe::command_end));
+        let stderr_end = capture_stderr
+            .as_ref()
+            .map(ffi::CapturePipe::writer)
+            .or_else(|| tee_stderr.as_ref().map(ffi::TeePipe::writer))
+            .or_else(|| relay_stderr.as_ref().map(ffi::RelayPipe::writer))
+            .or_else(|| stderr_pipe.as_ref().map(ffi::StdioPipe::command_end));
+
+        let (share_net, share_ipc, share_pid) =
+            (config.share_net(), config.share_ipc(), config.share_pid());
+        let vfork = seccomp_channel.is_none();
+        let child = ffi::clone(share_net, share_ipc, share_pid, vfork, clone_user, || {
+            // First, so the rest of the setup (the veth peer for one) happens in them
+            for namespace_file in &namespace_files {
+                ffi::join_namespace(namespace_file)?;
             }
 
-            if config.swap_redirects() == SwapRedirects::No {
-                if let Some(stdout) = config.redirect_stdout() {
-                    ffi::redirect_fd(ffi::STDOUT, stdout)?;
-                }
+            // Under the redirections, which still apply
+            if let Some(ref pty_slave) = pty_slave {
+                ffi::redirect_to_pty(pty_slave)?;
             }
 
-            if let Some(stderr) = config.redirect_stderr() {
-                ffi::redirect_fd(ffi::STDERR, stderr)?;
+            let stdio_ends = [stdin_end, stdout_end, stderr_end];
+            redirect_stdio(config, stdio_ends, false)?;
+            // The socket channel is one more of them
+            let mut extra_fds = config.extra_fds().to_vec();
+            if let (Some(fd), Some(socket_channel)) = (config.socket_channel(), &socket_channel) {
+                extra_fds.push((fd, FdSource::Fd(socket_channel.command_fd())));
+            }
+            ffi::set_up_extra_fds(&extra_fds)?;
+            if config.close_fds() == CloseFds::Yes {
+                let extra_fds: Vec<_> = extra_fds.iter().map(|&(fd, _)| fd).collect();
+                ffi::close_inherited_fds(&extra_fds)?;
             }
 
             ffi::set_stack_limit(config.limits().stack())?;
             // Enter cgroup before we pivot root, then it is too late
             cgroups::enter_all_cgroups(
-                config.controller_path(),
+                &controller_path,
                 config.instance_name(),
                 config.limits(),
+                io_device,
+                config.disable_swap(),
                 config.clear_usage(),
                 config.isolated_cgroup(),
+                config.memory_accounting(),
+                config.pausable(),
             )?;
 
+            // Unshare only once inside the instance cgroups, the namespace is rooted at the
+            // cgroups the process is in at that point so /proc/self/cgroup shows `/` and
+            // nothing of the host layout
             ffi::unshare_cgroup()?;
 
-            // Remount everything privately
-            ffi::remount_private()?;
+            if let (Some(veth), Some(host_net)) = (config.veth(), host_net.as_ref()) {
+                ffi::set_up_veth(veth, host_net)?;
+            }
+
+            let cgroup_mounts = match config.mount_cgroups() {
+                MountCgroups::Yes => cgroups::instance_mounts(
+                    &controller_path,
+                    config.instance_name(),
+                    config.limits(),
+                    io_device,
+                ),
+                MountCgroups::No => Vec::new(),
+            };
+
+            // Remount everything privately (or with the propagation asked for)
+            ffi::remount_root(config.propagation())?;
 
             if let Some(new_root) = config.new_root() {
-                for mount in config.mounts() {
+                if clone_user == CloneUser::No {
+                    mount_overlays(config, new_root)?;
+                    if config.minimal_dev() == MinimalDev::Yes {
+                        ffi::mount_minimal_dev(new_root)?;
+                    }
+                    mount_private_tmp(config, new_root)?;
+                }
+                for mount in config
+                    .mounts()
+                    .iter()
+                    .filter(|mount| !is_overlay(mount))
+                    .chain(&binfmt_mounts)
+                    .chain(&cgroup_mounts)
+                {
                     ffi::mount_inside(new_root, mount)?;
                 }
+                if config.interactive() == Interactive::Yes {
+                    ffi::mount_devpts(new_root)?;
+                }
 
-                ffi::pivot_root(new_root, || {
+                ffi::pivot_root(new_root, config.read_only_root(), || match share_pid {
                     // Mount proc (since we are in a new pid namespace)
                     // Must be done after pivot_root so we mount this in the right location
                     // but also before we unmount the old root because ... I don't know
-                    ffi::mount_proc()
+                    SharePid::Unshare => ffi::mount_proc(Some(config.proc_options())),
+                    // Only the user namespace owning the pid namespace can mount a proc for it
+                    SharePid::Share => ffi::bind_old_root_proc(),
                 })?;
-            } else {
-                ffi::mount_proc()?;
+            } else if share_pid == SharePid::Unshare {
+                ffi::mount_proc(Some(config.proc_options()))?;
             }
 
+            // The files inside the sandbox, now that it is all there
+            redirect_stdio(config, stdio_ends, true)?;
+
             // Make sure we are root (we don't really need to,
             // but this way the child process can do anything it likes
-            // inside its namespace and nothing outside)
+            // inside its namespace and nothing outside), or the sandbox user
             // Must be done after mount_proc so we can properly read and write
             // /proc/self/uid_map and /proc/self/gid_map
-            if config.clone_user() == CloneUser::Yes {
-                ffi::set_uid_gid_maps((ffi::UserId::ROOT, ffi::GroupId::ROOT))?;
+            // Without a user namespace root is the real one, so the root mode never stays root
+            let sandbox_user = config
+                .sandbox_user()
+                .map(|(uid, gid)| (ffi::UserId::new(uid), ffi::GroupId::new(gid)))
+                .or(if root_mode {
+                    Some((ffi::UserId::NOBODY, ffi::GroupId::NOBODY))
+                } else {
+                    None
+                });
+            // Needs CAP_SETPCAP, which a switch to another user loses
+            ffi::drop_bounding_capabilities(config.keep_capabilities())?;
+            if clone_user == CloneUser::Yes {
+                let inside_user_group_id =
+                    sandbox_user.unwrap_or((ffi::UserId::ROOT, ffi::GroupId::ROOT));
+                ffi::set_uid_gid_maps(
+                    (ffi::UserId::ROOT, ffi::GroupId::ROOT),
+                    inside_user_group_id,
+                )?;
+            }
+            // Once mapped (files can not be made before), and still as root without a user
+            // namespace
+            for (source, (source_path, destination)) in copy_in_files.iter().zip(config.copy_in()) {
+                ffi::copy_in(source, source_path, destination)?;
+            }
+            if let Some(ref root_channel) = root_channel {
+                root_channel.send()?;
+            }
+            if clone_user == CloneUser::No {
+                if let Some(sandbox_user) = sandbox_user {
+                    ffi::set_user(sandbox_user)?;
+                }
+            }
+
+            // As the user the command runs as, it has to be able to search the directory
+            if let Some(working_directory) = config.working_directory() {
+                ffi::chdir(working_directory)?;
             }
 
-            if config.interactive() == Interactive::No {
+            if let Some(ref pty_slave) = pty_slave {
+                // In a process group of its own all the same
+                ffi::take_controlling_terminal(pty_slave)?;
+            } else if config.interactive() == Interactive::No {
                 // Move the process to a different process group (so it can't kill it's own
                 // father by sending signals to the whole process group)
                 // But for interactive applications (mostly to test stuff), leave it there
                 ffi::move_to_different_process_group()?;
             }
 
-            ffi::exec_command(config.command(), &config.args(), config.environment())?;
+            // Last, the setup before needs them
+            ffi::restrict_capabilities(config.keep_capabilities())?;
+            ffi::set_no_new_privs()?;
+            if config.trace_syscalls().is_some() {
+                ffi::trace_me()?;
+            }
+            if let Some(landlock) = landlock {
+                ffi::restrict_filesystem(landlock)?;
+            }
+            if config.seccomp().is_some()
+                || config.single_exec() == SingleExec::Yes
+                || config.deny_nested_namespaces() == DenyNestedNamespaces::Yes
+            {
+                ffi::load_seccomp_filter(
+                    config.seccomp(),
+                    config.single_exec(),
+                    config.deny_nested_namespaces(),
+                    seccomp_channel.as_ref(),
+                    config.socket_channel(),
+                )?;
+            }
+
+            // What is left of `INSIDE_SANDBOX` past the exec, when asked for
+            let environment = match (config.environment().clone(), config.mark_inside_sandbox()) {
+                (environment, MarkInsideSandbox::No) => environment,
+                (Environment::Forward, MarkInsideSandbox::Yes) => {
+                    env::set_var(INSIDE_SANDBOX_VAR, "1");
+                    Environment::Forward
+                }
+                (Environment::EnvList(mut list), MarkInsideSandbox::Yes) => {
+                    list.push((INSIDE_SANDBOX_VAR.to_owned(), "1".to_owned()));
+                    Environment::EnvList(list)
+                }
+            };
+            ffi::exec_command(
+                config.command(),
+                &config.args(),
+                &environment,
+                missing_interpreter.as_ref(),
+            )?;
 
             Ok(())
-        })?
-        .wait(config.limits(), |wall_time| {
-            Ok(cgroups::get_usage(
-                config.controller_path(),
-                config.instance_name(),
-                wall_time,
-            )?)
-        })
-        .and_then(|run_info| {
-            run_info.and_then(|option| match option {
-                None => Ok(()),
-                Some(result) => result.map_err(Error::ChildError),
+        })?;
+        ffi::send_spawned(child.pid());
+
+        // The namespace of the command is read through its /proc entry, opened right away as the
+        // entry goes with the command (one gone already had no time for traffic worth counting).
+        // A joined namespace carries the traffic of others, it is not reported.
+        let net_dev = match config.share_net() {
+            ShareNet::Share => Some(NetDev::open(NET_DEV_PATH)?),
+            ShareNet::Unshare
+                if config
+                    .join_namespaces()
+                    .iter()
+                    .any(|&(kind, _)| kind == NamespaceKind::Net) =>
+            {
+                None
+            }
+            ShareNet::Unshare => NetDev::open(format!("/proc/{}/net/dev", child.pid())).ok(),
+        };
+
+        // Everything the supervisor reads of stdout and stderr, towards the output limit
+        let output_written = Arc::new(AtomicU64::new(0));
+        let drain_stdout = match capture_stdout {
+            Some(capture_stdout) => Some(capture_stdout.drain(&output_written)?),
+            None => None,
+        };
+        let drain_stderr = match capture_stderr {
+            Some(capture_stderr) => Some(capture_stderr.drain(&output_written)?),
+            None => None,
+        };
+        // What `Config::on_output` missed of the teed streams
+        let output_dropped = Arc::new(AtomicU64::new(0));
+        let mut tees = Vec::new();
+        for tee in tee_stdout.into_iter().chain(tee_stderr) {
+            tees.push(tee.tee(&output_written, &output_dropped, config.on_output())?);
+        }
+        // The relays keep a copy of the ends they pass the output on to
+        let mut relays = Vec::new();
+        for (relay, stdio_pipe) in relay_stdout
+            .into_iter()
+            .zip(&stdout_pipe)
+            .chain(relay_stderr.into_iter().zip(&stderr_pipe))
+        {
+            relays.push(relay.relay(stdio_pipe.command_end(), &output_written)?);
+        }
+        for stdio_pipe in stdio_pipes() {
+            stdio_pipe.close_command_end();
+        }
+        if let Some(ref socket_channel) = socket_channel {
+            socket_channel.close_command_end();
+        }
+        let freeze: ffi::Freeze<'_> = &|frozen| {
+            cgroups::freeze_instance(&controller_path, config.instance_name(), frozen)
+                .map_err(Error::from)
+        };
+
+        child
+            .wait(
+                config.limits(),
+                config.exclude_stopped_time(),
+                config.kill_grace_period(),
+                memory_pressure_notifier
+                    .as_ref()
+                    .map(|notifier| notifier.as_raw_fd()),
+                seccomp_channel,
+                tracer,
+                config.on_usage(),
+                Some(&control_channel),
+                match config.pausable() {
+                    Pausable::Yes => Some(freeze),
+                    Pausable::No => None,
+                },
+                |wall_time| {
+                    let mut usage = cgroups::get_usage(
+                        &controller_path,
+                        config.instance_name(),
+                        wall_time,
+                        user_time_offset,
+                        config.memory_accounting(),
+                        &rss_peak,
+                    )?;
+                    if let Some((throttled_time, nr_throttled)) =
+                        cgroups::get_throttling(&controller_path, config.instance_name())?
+                    {
+                        usage.set_throttled_time(
+                            throttled_time
+                                .checked_sub(throttled_time_offset)
+                                .unwrap_or_default(),
+                        );
+                        usage.set_nr_throttled(Some(
+                            nr_throttled.saturating_sub(nr_throttled_offset),
+                        ));
+                    }
+                    let (io_read, io_written) =
+                        cgroups::get_io_usage(&controller_path, config.instance_name())?;
+                    usage.set_io_read(io_read.saturating_sub(io_read_offset));
+                    usage.set_io_written(io_written.saturating_sub(io_written_offset));
+                    if let Some(ref net_dev) = net_dev {
+                        let net_traffic = net_dev.read()?.saturating_sub(net_traffic_offset);
+                        usage.set_net_rx_bytes(net_traffic.received());
+                        usage.set_net_tx_bytes(net_traffic.sent());
+                    }
+                    usage.set_output_bytes(SpaceUsage::from_bytes(
+                        output_written.load(Ordering::SeqCst),
+                    ));
+                    Ok(usage)
+                },
+            )
+            .and_then(|run_info| {
+                // The child does not take down the processes that left its process group with it
+                // unless it is the init of its own pid namespace
+                if share_pid == SharePid::Share {
+                    cgroups::kill_instance_processes(&controller_path, config.instance_name())?;
+                }
+                Ok(run_info)
             })
+            .and_then(|run_info| {
+                run_info.and_then(|option| match option {
+                    None => Ok(()),
+                    Some(result) => result.map_err(Error::ChildError),
+                })
+            })
+            .and_then(|run_info| {
+                // Whatever the result, the command may have written something on the way
+                if let Some(sandbox_root) =
+                    root_channel.map_or(Ok(None), ffi::RootChannel::accept)?
+                {
+                    for (source, destination) in config.copy_out() {
+                        sandbox_root.copy_out(source, destination, config.copy_out_limit())?;
+                    }
+                }
+                Ok(run_info)
+            })
+            .and_then(|mut run_info| {
+                // Past the kills above, nothing is left holding the pipes
+                let stdout = match drain_stdout {
+                    Some(drain_stdout) => Some(drain_stdout.join()?.0),
+                    None => None,
+                };
+                let (stderr, captured_stderr_tail) = match drain_stderr {
+                    Some(drain_stderr) => {
+                        let (stderr, tail) = drain_stderr.join()?;
+                        (Some(stderr), Some(tail))
+                    }
+                    None => (None, None),
+                };
+                for tee in tees {
+                    tee.join()?;
+                }
+                for relay in relays {
+                    relay.join()?;
+                }
+                // The sampled usage can miss output written just before the command ended
+                let written = SpaceUsage::from_bytes(output_written.load(Ordering::SeqCst));
+                let output_exceeded = limits.output().map_or(false, |output| output < written)
+                    && match *run_info.result() {
+                        RunInfoResult::Success(_)
+                        | RunInfoResult::NonZeroExitStatus(_)
+                        | RunInfoResult::KilledBySignal(_)
+                        | RunInfoResult::KilledByHost
+                        | RunInfoResult::RuntimeError { .. }
+                        | RunInfoResult::Aborted => true,
+                        _ => false,
+                    };
+                let dropped = SpaceUsage::from_bytes(output_dropped.load(Ordering::SeqCst));
+                if oom_notifier.triggered()? {
+                    // Without a memory limit on it or its parents the run ran the machine out
+                    // of memory
+                    let limited = limits.memory().is_some()
+                        || config.parent_limits().and_then(Limits::memory).is_some();
+                    run_info.set_result(if limited {
+                        RunInfoResult::MemoryLimitExceeded
+                    } else {
+                        RunInfoResult::KilledByOomKiller
+                    });
+                } else if output_exceeded {
+                    run_info.usage_mut().set_output_bytes(written);
+                    run_info.set_result(RunInfoResult::OutputLimitExceeded { written });
+                }
+                run_info.usage_mut().set_dropped_output_bytes(dropped);
+                run_info.set_traced(config.trace_syscalls().is_some());
+                run_info.set_stdout(stdout);
+                run_info.set_stderr(stderr);
+                // Read only for the runs someone will look into
+                if !run_info.is_success() && config.stderr_tail().as_bytes() > 0 {
+                    // The files are done with, a tail that can not be read is just left out
+                    let read_file_tail =
+                        |path: &Path| ffi::read_file_tail(path, config.stderr_tail()).ok();
+                    run_info.set_stderr_tail(match *config.redirect_stderr() {
+                        Redirect::Capture { .. } => captured_stderr_tail,
+                        Redirect::Tee { ref file, .. } => read_file_tail(file),
+                        Redirect::File(ref path, options) if !options.in_sandbox() => {
+                            read_file_tail(path)
+                        }
+                        _ => None,
+                    });
+                }
+                run_info.set_derived_wall_time(limits.wall_time_derivation().and_then(
+                    |wall_time_derivation| {
+                        limits
+                            .wall_time()
+                            .map(|wall_time| DerivedWallTime::new(wall_time_derivation, wall_time))
+                    },
+                ));
+                run_info.set_warnings(warnings.clone());
+                Ok(run_info)
+            })
+    };
+    // Until the supervisor has its handler, a `JailHandle::kill` right away would end it
+    let sig_term_blocked = ffi::set_signal_blocked(libc::SIGTERM, true);
+    let supervisor = ffi::clone(share_net, share_ipc, share_pid, false, clone_user, || {
+        let result = supervise();
+        if let Some(ref control_socket) = control_socket {
+            let _ = fs::remove_file(control_socket);
+        }
+        if let Some(ref result_file) = result_file {
+            // Nothing to tell it to, the adopting process finds the result missing
+            let _ = recovery::write_result(result_file, &result);
+        }
+        result
+    });
+    let _ = ffi::set_signal_blocked(libc::SIGTERM, sig_term_blocked);
+
+    supervisor
+        .map(|handle| {
+            JailHandle::new(
+                handle,
+                pty_master,
+                (
+                    stdin_pipe.and_then(ffi::StdioPipe::into_caller_end),
+                    stdout_pipe.and_then(ffi::StdioPipe::into_caller_end),
+                    stderr_pipe.and_then(ffi::StdioPipe::into_caller_end),
+                ),
+                socket_channel.map(ffi::SocketChannel::into_caller_end),
+                control_channel.into_caller_end(),
+                config.instance_name().map(OsStr::to_owned),
+                result_file,
+            )
         })
-    })
-    .map(JailHandle::new)
-    .map_err(Error::from)
+        .map_err(Error::from)
+}
+
+// Applies the redirects of the files inside the sandbox or of the rest, either way in the same
+// order: stdin and stdout in the one `SwapRedirects` asks for (it matters to FIFOs), then stderr,
+// which can go wherever stdout went
+fn redirect_stdio(
+    config: &Config,
+    [stdin_end, stdout_end, stderr_end]: [Option<&File>; 3],
+    in_sandbox: bool,
+) -> StdResult<(), FFIError> {
+    let stderr_in_sandbox = match *config.redirect_stderr() {
+        Redirect::ToStdout => config.redirect_stdout().in_sandbox(),
+        ref redirect => redirect.in_sandbox(),
+    };
+    let redirect = |fd, redirect: &Redirect, redirect_in_sandbox, end| {
+        if redirect_in_sandbox == in_sandbox {
+            ffi::redirect_fd(fd, redirect, end, config.fifo_open_timeout())
+        } else {
+            Ok(())
+        }
+    };
+    let stdout = config.redirect_stdout();
+    if config.swap_redirects() == SwapRedirects::Yes {
+        redirect(ffi::STDOUT, stdout, stdout.in_sandbox(), stdout_end)?;
+    }
+    let stdin = config.redirect_stdin();
+    redirect(ffi::STDIN, stdin, stdin.in_sandbox(), stdin_end)?;
+    if config.swap_redirects() == SwapRedirects::No {
+        redirect(ffi::STDOUT, stdout, stdout.in_sandbox(), stdout_end)?;
+    }
+    redirect(
+        ffi::STDERR,
+        config.redirect_stderr(),
+        stderr_in_sandbox,
+        stderr_end,
+    )
+}
+
+fn is_overlay(mount: &Mount) -> bool {
+    match *mount.kind() {
+        MountKind::Overlay { .. } => true,
+        MountKind::Bind { .. } | MountKind::Tmpfs { .. } => false,
+    }
+}
+
+// The root image or the overlay root first and then the overlays in it, before the rest of the
+// mounts
+fn mount_overlays(config: &Config, new_root: &Path) -> StdResult<(), FFIError> {
+    match config.root_image() {
+        Some(RootImage::Squashfs(image)) => ffi::mount_root_squashfs(new_root, image)?,
+        Some(RootImage::Tarball(image)) => ffi::mount_root_tarball(new_root, image)?,
+        None => {}
+    }
+    if let Some(overlay) = config.new_root_overlay() {
+        ffi::mount_root_overlay(new_root, overlay)?;
+    }
+    for mount in config.mounts().iter().filter(|mount| is_overlay(mount)) {
+        ffi::mount_inside(new_root, mount)?;
+    }
+    Ok(())
+}
+
+const PRIVATE_TMP_DIRS: &[&str] = &["/tmp", "/var/tmp", "/dev/shm"];
+
+// Like the minimal /dev, before the rest of the mounts. One of them going at or under a
+// directory takes the place of its tmpfs.
+fn mount_private_tmp(config: &Config, new_root: &Path) -> StdResult<(), FFIError> {
+    if config.private_tmp() == PrivateTmp::No {
+        return Ok(());
+    }
+    let mount_options = MountOptions::builder().writable().exec(true).build();
+    for dir in PRIVATE_TMP_DIRS {
+        if config
+            .mounts()
+            .iter()
+            .any(|mount| Path::new("/").join(mount.destination()).starts_with(dir))
+        {
+            continue;
+        }
+        let mount = Mount::tmpfs(
+            PathBuf::from(dir),
+            Some(config.private_tmp_size()),
+            mount_options,
+        );
+        ffi::mount_inside(new_root, &mount)?;
+    }
+    Ok(())
 }
 
+// How long a dropped `JailHandle` lets the supervisor clean up before SIGKILL
+const DROP_GRACE_PERIOD: Duration = Duration::from_millis(100);
+
+/// A run spawned with `spawn_jail`. Dropped before its result is collected (by `wait` or by
+/// `try_wait` returning it), it ends the run as `kill` does and reaps the supervisor, SIGKILL
+/// follows if the supervisor is not gone within a short grace period. The supervisor also dies
+/// along with the caller, this covers the caller going on without the run. `detach` opts out.
 #[allow(missing_debug_implementations)]
 pub struct JailHandle {
     handle: CloneHandle<Result<RunInfo<()>>>,
+    pty_master: Option<File>,
+    stdin: Option<File>,
+    stdout: Option<File>,
+    stderr: Option<File>,
+    stdout_reader: Option<ffi::OutputReader>,
+    stderr_reader: Option<ffi::OutputReader>,
+    channel: Option<UnixStream>,
+    run_info: Option<RunInfo<()>>,
+    child_pid: Option<libc::pid_t>,
+    // One request (and its reply) at a time
+    control_channel: Mutex<File>,
+    instance_name: Option<OsString>,
+    // See `Config::detached`
+    result_file: Option<PathBuf>,
 }
 
 impl JailHandle {
-    fn new(handle: CloneHandle<Result<RunInfo<()>>>) -> Self {
-        Self { handle }
+    fn new(
+        mut handle: CloneHandle<Result<RunInfo<()>>>,
+        pty_master: Option<File>,
+        (stdin, stdout, stderr): (Option<File>, Option<File>, Option<File>),
+        channel: Option<UnixStream>,
+        control_channel: File,
+        instance_name: Option<OsString>,
+        result_file: Option<PathBuf>,
+    ) -> Self {
+        handle.terminate_on_drop(DROP_GRACE_PERIOD);
+        Self {
+            handle,
+            pty_master,
+            stdin,
+            stdout,
+            stderr,
+            stdout_reader: None,
+            stderr_reader: None,
+            channel,
+            run_info: None,
+            child_pid: None,
+            control_channel: Mutex::new(control_channel),
+            instance_name,
+            result_file,
+        }
+    }
+
+    /// The pid of the supervisor, it only means that process while the handle is there (the
+    /// handle reaps it)
+    pub fn supervisor_pid(&self) -> libc::pid_t {
+        self.handle.pid()
+    }
+
+    /// The pid of the command (of the process that execs it, before that) as seen from the
+    /// caller, `None` until the supervisor cloned it. Like `supervisor_pid`, only valid while
+    /// the handle is there and the run is not over, it may be reused after.
+    pub fn child_pid(&mut self) -> Option<libc::pid_t> {
+        if self.child_pid.is_none() {
+            let pid = self.handle.spawned().ok()??;
+            self.child_pid = ffi::host_pid_of_child(self.handle.pid(), pid);
+        }
+        self.child_pid
+    }
+
+    /// Whether the run goes on, `false` once it is over even before its result is collected
+    /// (and after `try_wait` returned it). Only looks, the result is still there for `wait`.
+    pub fn is_running(&self) -> bool {
+        self.run_info.is_none() && self.handle.is_running()
+    }
+
+    /// The instance name of the run (see `Config::instance_name`), which names its cgroups
+    pub fn instance_name(&self) -> Option<&OsStr> {
+        self.instance_name.as_ref().map(OsString::as_os_str)
+    }
+
+    /// The master end of the pseudo terminal of the command (see `Config::allocate_pty`), what
+    /// is written to it is the input of the command and its output is read from it. Reading
+    /// fails with EIO once the command and the supervisor are gone.
+    pub fn pty_master(&self) -> Option<&File> {
+        self.pty_master.as_ref()
+    }
+
+    /// The end of the pipe to stdin of the command (see `Redirect::Pipe`), the first call takes
+    /// it. The command reads EOF once it is dropped.
+    pub fn stdin(&mut self) -> Option<impl Write> {
+        self.stdin.take()
+    }
+
+    /// The end of the pipe from stdout of the command (see `Redirect::Pipe`), the first call
+    /// takes it
+    pub fn stdout(&mut self) -> Option<impl Read> {
+        self.stdout.take()
+    }
+
+    /// The end of the pipe from stderr of the command (see `Redirect::Pipe`), the first call
+    /// takes it
+    pub fn stderr(&mut self) -> Option<impl Read> {
+        self.stderr.take()
+    }
+
+    /// The end of the socket channel of the caller (see `Config::socket_channel`), the first call
+    /// takes it
+    pub fn channel(&mut self) -> Option<UnixStream> {
+        self.channel.take()
+    }
+
+    /// Reads what the command wrote to the pipe from stdout (see `Redirect::Pipe`) so far,
+    /// without waiting for more: 0 bytes when nothing is there yet, `None` at EOF (or when the
+    /// pipe was taken by `stdout`, or there is none). What is read is also kept for `wait`.
+    pub fn read_stdout_available(&mut self, buf: &mut [u8]) -> Result<Option<usize>> {
+        read_available(&mut self.stdout, &mut self.stdout_reader, ffi::STDOUT, buf)
+    }
+
+    /// Like `read_stdout_available`, for the pipe from stderr
+    pub fn read_stderr_available(&mut self, buf: &mut [u8]) -> Result<Option<usize>> {
+        read_available(&mut self.stderr, &mut self.stderr_reader, ffi::STDERR, buf)
     }
 
+    /// Lets the run go on without the handle, which otherwise ends it when dropped before
+    /// `wait` (see `JailHandle`). The pipes and the socket channel that were not taken are
+    /// closed, and the run can not be waited on anymore.
+    pub fn detach(mut self) {
+        self.handle.detach();
+    }
+
+    /// Lets the run go on without the handle like `detach`, for another process to adopt it
+    /// (see `AdoptedJail`). Only for a detached run, see `Config::set_detached`.
+    pub fn into_recovery_token(mut self) -> Result<RecoveryToken> {
+        let result_file = self.result_file.take().ok_or_else(|| {
+            FFIError::RecoveryError(
+                "the run is not detached, see `Config::set_detached`".to_owned(),
+            )
+        })?;
+        let supervisor_pid = self.handle.pid();
+        // Over already, the result is in the file or on its way there
+        let start_time = ffi::process_start_time(supervisor_pid).unwrap_or_default();
+        let token = RecoveryToken::new(
+            supervisor_pid,
+            start_time,
+            result_file,
+            self.instance_name.take(),
+        );
+        self.detach();
+        Ok(token)
+    }
+
+    /// Freezes every process of the run (see `Config::set_pausable`) until `resume`, the time in
+    /// between is not part of the wall time and shows as `RunUsage::paused_time`. Returns once
+    /// the run is frozen, pausing a paused run does nothing. Fails with
+    /// `CGroupError::FreezeTimeout` when the run does not freeze in time, it goes on then.
+    pub fn pause(&self) -> Result<()> {
+        self.request_pause(true)
+    }
+
+    /// Thaws the run paused with `pause`, resuming a run that was not paused does nothing
+    pub fn resume(&self) -> Result<()> {
+        self.request_pause(false)
+    }
+
+    fn request_pause(&self, pause: bool) -> Result<()> {
+        let request = if pause {
+            ffi::ControlRequest::Pause
+        } else {
+            ffi::ControlRequest::Resume
+        };
+        Ok(self.request(&request).map_err(FFIError::PauseError)?)
+    }
+
+    /// Sends `signal` to the command, not to the processes it started. Past SIGKILL and SIGSTOP
+    /// it does not reach a command that is the init of its pid namespace (see
+    /// `Config::share_pid`) unless it handles that signal. Fails for a signal that does not exist
+    /// and for a run already over.
+    pub fn signal(&self, signal: i32) -> Result<()> {
+        let error = |error: String| FFIError::SignalError { signal, error };
+        if signal <= 0 || signal > libc::SIGRTMAX() {
+            return Err(error("no such signal".to_owned()).into());
+        }
+        Ok(self
+            .request(&ffi::ControlRequest::Signal(signal))
+            .map_err(error)?)
+    }
+
+    /// The usage of the run so far and what is left of its wall time limit, as the supervisor
+    /// sees it on its next sample. Fails for a run already over.
+    pub fn status(&self) -> Result<RunStatus> {
+        Ok(self
+            .request(&ffi::ControlRequest::Status)
+            .map_err(FFIError::StatusError)?)
+    }
+
+    fn request<R: DeserializeOwned>(&self, request: &ffi::ControlRequest) -> StdResult<R, String> {
+        let control_channel = self
+            .control_channel
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        ffi::ControlChannel::request(&control_channel, request)
+    }
+
+    /// Cancels the run: the supervisor kills the command with everything it started, right
+    /// away or after `Config::kill_grace_period`, and `wait` returns `RunInfoResult::Cancelled`.
+    /// Nothing happens for a run already over, and it can be called any number of times.
+    pub fn kill(&self) -> Result<()> {
+        self.handle.cancel().map_err(Error::from)
+    }
+
+    /// Checks whether the run is over without blocking: `None` while it goes on, the result
+    /// of `wait` after (and on every later call). The pipes and the socket channel stay open
+    /// meanwhile, what was read of them with `read_stdout_available` or `read_stderr_available`
+    /// is in `RunInfo::stdout` and `RunInfo::stderr` as for `wait`.
+    pub fn try_wait(&mut self) -> Result<Option<RunInfo<()>>> {
+        self.wait_timeout(Duration::from_secs(0))
+    }
+
+    /// Like `try_wait`, waiting up to `timeout` for the run to be over first
+    pub fn wait_timeout(&mut self, timeout: Duration) -> Result<Option<RunInfo<()>>> {
+        if let Some(ref run_info) = self.run_info {
+            return Ok(Some(run_info.clone()));
+        }
+        let run_info = self.handle.wait_timeout(timeout)?;
+        self.collect(run_info)
+    }
+
+    /// A descriptor to register with a reactor (epoll, mio, tokio's `AsyncFd`...), readable
+    /// when `take_result` has something to do: the supervisor sent a message, or the run is over.
+    /// Only `take_result` reads it, and it belongs to the handle.
+    pub fn poll_fd(&self) -> RawFd {
+        self.handle.pipe_fd()
+    }
+
+    /// Like `try_wait`, for when `poll_fd` is readable: it reads what is there, which may not be
+    /// the result yet (`None`, until `poll_fd` is readable again). With the result there, the
+    /// supervisor is already exiting and it only waits for it to be gone.
+    pub fn take_result(&mut self) -> Result<Option<RunInfo<()>>> {
+        if let Some(ref run_info) = self.run_info {
+            return Ok(Some(run_info.clone()));
+        }
+        let run_info = self.handle.take_result()?;
+        self.collect(run_info)
+    }
+
+    // Keeps the result of the supervisor (with what was read of the pipes), `None` while it runs
+    fn collect(
+        &mut self,
+        run_info: Option<RunInfo<Option<Result<RunInfo<()>>>>>,
+    ) -> Result<Option<RunInfo<()>>> {
+        let run_info = match run_info {
+            Some(run_info) => flatten_supervisor_run_info(run_info),
+            None => return Ok(None),
+        };
+        // The command is gone with the supervisor, the pipes are at their end
+        let drain_stdout = drain_output(self.stdout_reader.take())?;
+        let drain_stderr = drain_output(self.stderr_reader.take())?;
+        let run_info = join_output(run_info, drain_stdout, drain_stderr)?;
+        self.run_info = Some(run_info.clone());
+        Ok(Some(run_info))
+    }
+
+    /// The ends of the pipes (and of the socket channel) that were not taken are closed first,
+    /// the command would wait on them forever otherwise. Those read with `read_stdout_available` or
+    /// `read_stderr_available` are read to the end instead, and all of their output is in
+    /// `RunInfo::stdout` and `RunInfo::stderr`.
     pub fn wait(self) -> Result<RunInfo<()>> {
-        self.handle
-            .wait(Limits::default(), |_| Ok(RunUsage::default()))
-            .and_then(|run_info| {
-                run_info
-                    .success() // we only care if supervisor process successfully finished
-                    .and_then(|x| x) // its an option inside an option, so flatten it
-                    .ok_or(Error::SupervisorProcessDiedError)
-                    .and_then(|x| x) // result in result, flatten it
-            })
+        let Self {
+            handle,
+            pty_master: _pty_master,
+            stdin,
+            stdout,
+            stderr,
+            stdout_reader,
+            stderr_reader,
+            channel,
+            run_info,
+            child_pid: _child_pid,
+            control_channel: _control_channel,
+            instance_name: _instance_name,
+            result_file: _result_file,
+        } = self;
+        drop((stdin, stdout, stderr, channel));
+        if let Some(run_info) = run_info {
+            return Ok(run_info);
+        }
+        let drain_stdout = drain_output(stdout_reader)?;
+        let drain_stderr = drain_output(stderr_reader)?;
+        let run_info = handle
+            .wait(
+                &Limits::default(),
+                ExcludeStoppedTime::No,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                |_| Ok(RunUsage::default()),
+            )
+            .and_then(flatten_supervisor_run_info);
+        join_output(run_info, drain_stdout, drain_stderr)
+    }
+}
+
+fn flatten_supervisor_run_info(
+    run_info: RunInfo<Option<Result<RunInfo<()>>>>,
+) -> Result<RunInfo<()>> {
+    run_info
+        .success() // we only care if supervisor process successfully finished
+        .and_then(|x| x) // its an option inside an option, so flatten it
+        .ok_or(Error::SupervisorProcessDiedError)
+        .and_then(|x| x) // result in result, flatten it
+}
+
+fn drain_output(reader: Option<ffi::OutputReader>) -> Result<Option<ffi::CaptureDrain>> {
+    match reader {
+        Some(reader) => Ok(Some(reader.drain()?)),
+        None => Ok(None),
+    }
+}
+
+// Puts what was read of the pipes in the run info
+fn join_output(
+    run_info: Result<RunInfo<()>>,
+    drain_stdout: Option<ffi::CaptureDrain>,
+    drain_stderr: Option<ffi::CaptureDrain>,
+) -> Result<RunInfo<()>> {
+    // Ended along with the command either way
+    let stdout = match drain_stdout {
+        Some(drain_stdout) => Some(drain_stdout.join()?.0),
+        None => None,
+    };
+    let stderr = match drain_stderr {
+        Some(drain_stderr) => Some(drain_stderr.join()?.0),
+        None => None,
+    };
+    let mut run_info = run_info?;
+    if stdout.is_some() {
+        run_info.set_stdout(stdout);
+    }
+    if stderr.is_some() {
+        run_info.set_stderr(stderr);
+    }
+    Ok(run_info)
+}
+
+// Reads the pipe in place from then on, unless it was taken before
+fn read_available(
+    end: &mut Option<File>,
+    reader: &mut Option<ffi::OutputReader>,
+    fd: &ffi::Fd,
+    buf: &mut [u8],
+) -> Result<Option<usize>> {
+    if let Some(end) = end.take() {
+        *reader = Some(ffi::OutputReader::new(fd, end)?);
+    }
+    match *reader {
+        Some(ref mut reader) => Ok(reader.read_available(buf)?),
+        None => Ok(None),
     }
 }
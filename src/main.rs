@@ -48,20 +48,53 @@ extern crate serde_json;
 
 use failure::Fail;
 use std::process;
+use std::thread;
 
 use std::io;
 
+use ia_sandbox::config::Config;
+use ia_sandbox::run_info::RunInfo;
 use ia_sandbox::utils::DurationExt;
 
 mod app;
 mod args;
 use args::OutputType;
 
+fn run(config: &Config) -> Result<RunInfo<()>, failure::Error> {
+    let handle = ia_sandbox::spawn_jail(config)?;
+    let output = match handle.pty_master() {
+        Some(master) => {
+            // Pass our input and output through the pseudo terminal of the command. Nothing
+            // tells the input side it is done, it goes away with the process.
+            let mut input = master.try_clone()?;
+            let mut output = master.try_clone()?;
+            let _ = thread::spawn(move || io::copy(&mut io::stdin(), &mut input));
+            Some(thread::spawn(move || {
+                io::copy(&mut output, &mut io::stdout())
+            }))
+        }
+        None => None,
+    };
+    let run_info = handle.wait()?;
+    if let Some(output) = output {
+        // Ends with EIO once the last end of the slave is closed
+        let _ = output.join();
+    }
+    Ok(run_info)
+}
+
 fn main() {
-    match args::parse()
-        .and_then(|(args, output)| Ok((ia_sandbox::spawn_jail(&args)?.wait()?, output)))
-    {
+    match args::parse().and_then(|(args, output)| Ok((run(&args)?, output))) {
         Ok((run_info, output)) => {
+            match output {
+                // The `Display` of the run info, printed below, lists the warnings itself
+                OutputType::Human => {}
+                OutputType::Oneline | OutputType::Json => {
+                    for warning in run_info.warnings() {
+                        eprintln!("Warning: {}", warning);
+                    }
+                }
+            }
             match output {
                 OutputType::Human => println!("{}", run_info),
                 OutputType::Oneline => {
@@ -91,6 +124,7 @@ fn main() {
                 eprintln!("{}", cause);
                 fail = cause;
             }
+            eprintln!("{}", ia_sandbox::VERSION_INFO);
             process::exit(1);
         }
     }
@@ -0,0 +1,200 @@
+use std::ffi::OsString;
+use std::fmt::{self, Display, Formatter};
+use std::fs;
+use std::os::unix::ffi::OsStringExt;
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+use std::path::{Path, PathBuf};
+
+use errors::FFIError;
+
+/// One line of `/proc/self/mountinfo`
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct MountInfoEntry {
+    root: PathBuf,
+    mount_point: PathBuf,
+    mount_options: Vec<String>,
+    filesystem_type: String,
+    mount_source: PathBuf,
+    super_options: Vec<String>,
+}
+
+impl MountInfoEntry {
+    /// The path inside the mounted filesystem that forms the root of this mount
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn mount_point(&self) -> &Path {
+        &self.mount_point
+    }
+
+    /// Per mount options (such as `ro`, `nosuid`, `nodev`, `noexec`)
+    pub fn mount_options(&self) -> &[String] {
+        &self.mount_options
+    }
+
+    pub fn filesystem_type(&self) -> &str {
+        &self.filesystem_type
+    }
+
+    /// Filesystem specific source, such as the device for block device backed filesystems
+    pub fn mount_source(&self) -> &Path {
+        &self.mount_source
+    }
+
+    /// Per superblock options, for cgroups v1 these include the controllers of the hierarchy
+    pub fn super_options(&self) -> &[String] {
+        &self.super_options
+    }
+
+    pub fn has_option(&self, option: &str) -> bool {
+        self.mount_options.iter().any(|opt| opt == option)
+    }
+
+    // Format is described in proc(5):
+    // 36 35 98:0 /mnt1 /mnt2 rw,noatime master:1 - ext3 /dev/root rw,errors=continue
+    fn parse(line: &str) -> Option<Self> {
+        let mut fields = line.split_whitespace();
+        let root = unescape(fields.nth(3)?);
+        let mount_point = unescape(fields.next()?);
+        let mount_options = fields.next()?.split(',').map(str::to_owned).collect();
+        let filesystem_type = fields.find(|&field| field == "-").and(fields.next())?;
+        let mount_source = unescape(fields.next()?);
+        let super_options = fields.next().map_or_else(Vec::new, |options| {
+            options.split(',').map(str::to_owned).collect()
+        });
+
+        Some(Self {
+            root,
+            mount_point,
+            mount_options,
+            filesystem_type: filesystem_type.to_owned(),
+            mount_source,
+            super_options,
+        })
+    }
+}
+
+// Spaces, tabs, newlines and backslashes are escaped as \ooo octal sequences
+fn unescape(field: &str) -> PathBuf {
+    let bytes = field.as_bytes();
+    let mut result = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let octal = bytes.get(index + 1..index + 4).and_then(|digits| {
+            if bytes[index] == b'\\' && digits.iter().all(|digit| b'0' <= *digit && *digit <= b'7')
+            {
+                Some(
+                    digits
+                        .iter()
+                        .fold(0u8, |acc, digit| acc * 8 + (digit - b'0')),
+                )
+            } else {
+                None
+            }
+        });
+        match (bytes[index], octal) {
+            (b'\\', Some(byte)) => {
+                result.push(byte);
+                index += 4;
+            }
+            (byte, _) => {
+                result.push(byte);
+                index += 1;
+            }
+        }
+    }
+    PathBuf::from(OsString::from_vec(result))
+}
+
+/// A block device, as its major and minor numbers
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub struct BlockDevice {
+    major: u32,
+    minor: u32,
+}
+
+impl BlockDevice {
+    pub fn new(major: u32, minor: u32) -> Self {
+        Self { major, minor }
+    }
+
+    fn from_dev(dev: u64) -> Self {
+        Self::new(libc::major(dev), libc::minor(dev))
+    }
+
+    pub fn major(self) -> u32 {
+        self.major
+    }
+
+    pub fn minor(self) -> u32 {
+        self.minor
+    }
+}
+
+impl Display for BlockDevice {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.major, self.minor)
+    }
+}
+
+/// Parsed contents of `/proc/self/mountinfo`
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct MountInfo(Vec<MountInfoEntry>);
+
+impl MountInfo {
+    /// Reads the mount table of the calling process
+    pub fn read() -> Result<Self, FFIError> {
+        fs::read_to_string("/proc/self/mountinfo")
+            .map(|content| Self::parse(&content))
+            .map_err(|err| FFIError::ReadMountInfoError(err.to_string()))
+    }
+
+    /// Parses mountinfo formatted content, malformed lines are skipped
+    pub fn parse(content: &str) -> Self {
+        Self(content.lines().filter_map(MountInfoEntry::parse).collect())
+    }
+
+    pub fn entries(&self) -> &[MountInfoEntry] {
+        &self.0
+    }
+
+    /// Finds the mount that `path` (expected to be absolute and canonical) lives on.
+    ///
+    /// Mounts listed later are stacked on top of earlier ones, so the last
+    /// one with the longest matching mount point wins.
+    pub fn find_mount<T: AsRef<Path>>(&self, path: T) -> Option<&MountInfoEntry> {
+        let path = path.as_ref();
+        self.0
+            .iter()
+            .filter(|entry| path.starts_with(&entry.mount_point))
+            .fold(None, |best: Option<&MountInfoEntry>, entry| match best {
+                Some(best)
+                    if best.mount_point.components().count()
+                        > entry.mount_point.components().count() =>
+                {
+                    Some(best)
+                }
+                _ => Some(entry),
+            })
+    }
+
+    /// Finds the block device backing the filesystem `path` lives on.
+    ///
+    /// Filesystems such as btrfs or overlayfs report an anonymous device (major 0), so
+    /// for those the source of the mount is used when it is a block device.
+    pub fn block_device<T: AsRef<Path>>(&self, path: T) -> Option<BlockDevice> {
+        let path = path.as_ref();
+        let device = BlockDevice::from_dev(fs::metadata(path).ok()?.dev());
+        if device.major() != 0 {
+            return Some(device);
+        }
+
+        let source = fs::metadata(self.find_mount(path)?.mount_source()).ok()?;
+        if source.file_type().is_block_device() {
+            Some(BlockDevice::from_dev(source.rdev()))
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,106 @@
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use config::SpaceUsage;
+use errors::FFIError;
+
+/// Traffic of a network namespace, as read from `/proc/<pid>/net/dev` and summed over all
+/// its interfaces (loopback included)
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub struct NetTraffic {
+    received: SpaceUsage,
+    sent: SpaceUsage,
+}
+
+impl NetTraffic {
+    pub fn new(received: SpaceUsage, sent: SpaceUsage) -> Self {
+        Self { received, sent }
+    }
+
+    /// Reads a `net/dev` file, such as `/proc/self/net/dev`
+    pub fn read<T: AsRef<Path>>(path: T) -> Result<Self, FFIError> {
+        fs::read_to_string(path.as_ref())
+            .map(|content| Self::parse(&content))
+            .map_err(|err| FFIError::ReadNetDevError {
+                path: path.as_ref().to_path_buf(),
+                error: err.to_string(),
+            })
+    }
+
+    // Format is described in proc(5), two header lines and then one line per interface:
+    //     lo: 1234 12 0 0 0 0 0 0 1234 12 0 0 0 0 0 0
+    // with the received bytes first and the sent bytes ninth. Malformed lines are skipped.
+    pub fn parse(content: &str) -> Self {
+        content
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line[line.find(':')? + 1..].split_whitespace();
+                let received = fields.next()?.parse().ok()?;
+                let sent = fields.nth(7)?.parse().ok()?;
+                Some((received, sent))
+            })
+            .fold(Self::default(), |total, (received, sent)| {
+                Self::new(
+                    SpaceUsage::from_bytes(total.received.as_bytes().saturating_add(received)),
+                    SpaceUsage::from_bytes(total.sent.as_bytes().saturating_add(sent)),
+                )
+            })
+    }
+
+    pub fn received(self) -> SpaceUsage {
+        self.received
+    }
+
+    pub fn sent(self) -> SpaceUsage {
+        self.sent
+    }
+
+    pub fn saturating_sub(self, other: Self) -> Self {
+        Self::new(
+            self.received.saturating_sub(other.received),
+            self.sent.saturating_sub(other.sent),
+        )
+    }
+}
+
+/// A `net/dev` file kept open, it goes on reading the counters of the network namespace it was
+/// opened in once every process there is gone
+#[derive(Debug)]
+pub struct NetDev {
+    path: PathBuf,
+    file: File,
+}
+
+impl NetDev {
+    pub fn open<T: AsRef<Path>>(path: T) -> Result<Self, FFIError> {
+        File::open(path.as_ref())
+            .map(|file| Self {
+                path: path.as_ref().to_path_buf(),
+                file,
+            })
+            .map_err(|err| FFIError::ReadNetDevError {
+                path: path.as_ref().to_path_buf(),
+                error: err.to_string(),
+            })
+    }
+
+    /// Reads the file from the start again
+    pub fn read(&self) -> Result<NetTraffic, FFIError> {
+        let mut content = String::new();
+        (&self.file)
+            .seek(SeekFrom::Start(0))
+            .and_then(|_| (&self.file).read_to_string(&mut content))
+            .map(|_| NetTraffic::parse(&content))
+            .map_err(|err| FFIError::ReadNetDevError {
+                path: self.path.clone(),
+                error: err.to_string(),
+            })
+    }
+}
+
+impl Default for NetTraffic {
+    fn default() -> Self {
+        Self::new(SpaceUsage::from_bytes(0), SpaceUsage::from_bytes(0))
+    }
+}
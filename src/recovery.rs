@@ -0,0 +1,184 @@
+//! Runs adopted by another process than the one that spawned them, see `Config::set_detached`
+
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use errors::{Error, FFIError, Result};
+use ffi::{ControlChannel, ControlRequest, ForeignProcess};
+use libc;
+use run_info::{RunInfo, RunStatus};
+use serde_json;
+
+// How long `AdoptedJail::wait` waits on the supervisor at a time, in between it checks whether
+// the supervisor is still the process of the token
+const WAIT_INTERVAL: Duration = Duration::from_millis(100);
+
+/// What another process needs to adopt a detached run, see `JailHandle::into_recovery_token`.
+/// It serializes to be stored or passed on.
+#[derive(Debug, Eq, PartialEq, Clone, Serialize, Deserialize)]
+pub struct RecoveryToken {
+    supervisor_pid: libc::pid_t,
+    // Of the supervisor, tells it apart from a later process with the same pid
+    start_time: u64,
+    result_file: PathBuf,
+    instance_name: Option<OsString>,
+}
+
+impl RecoveryToken {
+    pub(crate) fn new(
+        supervisor_pid: libc::pid_t,
+        start_time: u64,
+        result_file: PathBuf,
+        instance_name: Option<OsString>,
+    ) -> Self {
+        Self {
+            supervisor_pid,
+            start_time,
+            result_file,
+            instance_name,
+        }
+    }
+
+    /// The pid of the supervisor, as seen from the pid namespace of the process that spawned
+    /// the run. The process adopting it has to see it under the same pid.
+    pub fn supervisor_pid(&self) -> libc::pid_t {
+        self.supervisor_pid
+    }
+
+    /// Where the supervisor writes the result, see `Config::detached`
+    pub fn result_file(&self) -> &Path {
+        &self.result_file
+    }
+
+    pub fn instance_name(&self) -> Option<&OsStr> {
+        self.instance_name.as_ref().map(OsString::as_os_str)
+    }
+}
+
+/// A detached run adopted from a `RecoveryToken`. The supervisor is not a child of the adopting
+/// process, the run is over once it exits, and its result is then read from the result file.
+#[allow(missing_debug_implementations)]
+pub struct AdoptedJail {
+    token: RecoveryToken,
+    // `None` once it exited
+    supervisor: Option<ForeignProcess>,
+    run_info: Option<RunInfo<()>>,
+}
+
+impl AdoptedJail {
+    /// Adopts the run of `token`. A run already over is adopted as well if its result is there
+    /// (`wait` returns it right away), not if the supervisor exited without writing one.
+    pub fn from_recovery_token(token: RecoveryToken) -> Result<Self> {
+        let supervisor = ForeignProcess::open(token.supervisor_pid, token.start_time)?;
+        if supervisor.is_none() && !token.result_file.exists() {
+            return Err(missing_result(&token));
+        }
+        Ok(Self {
+            token,
+            supervisor,
+            run_info: None,
+        })
+    }
+
+    pub fn supervisor_pid(&self) -> libc::pid_t {
+        self.token.supervisor_pid
+    }
+
+    pub fn instance_name(&self) -> Option<&OsStr> {
+        self.token.instance_name()
+    }
+
+    /// Whether the run goes on, see `JailHandle::is_running`
+    pub fn is_running(&self) -> bool {
+        match self.supervisor {
+            Some(ref supervisor) => self.run_info.is_none() && !supervisor.has_exited(),
+            None => false,
+        }
+    }
+
+    /// The status of the run like `JailHandle::status`, asked of the supervisor through the
+    /// socket it listens on next to the result file
+    pub fn status(&self) -> Result<RunStatus> {
+        let status = ControlChannel::connect(&control_socket_path(&self.token.result_file))
+            .and_then(|connection| ControlChannel::request(&connection, &ControlRequest::Status))
+            .map_err(FFIError::StatusError)?;
+        Ok(status)
+    }
+
+    /// Cancels the run like `JailHandle::kill`
+    pub fn kill(&self) -> Result<()> {
+        match self.supervisor {
+            Some(ref supervisor) => Ok(supervisor.signal(libc::SIGTERM)?),
+            None => Ok(()),
+        }
+    }
+
+    /// Checks whether the run is over without blocking: `None` while it goes on, the result of
+    /// `wait` after (and on every later call)
+    pub fn try_wait(&mut self) -> Result<Option<RunInfo<()>>> {
+        if self.run_info.is_none() {
+            if let Some(ref supervisor) = self.supervisor {
+                if !supervisor.has_exited() {
+                    return Ok(None);
+                }
+            }
+            self.supervisor = None;
+            self.run_info = Some(read_result(&self.token)?);
+        }
+        Ok(self.run_info.clone())
+    }
+
+    /// Waits for the run to end and returns its result, which the supervisor wrote before it
+    /// exited. The result file is left for the caller to remove.
+    pub fn wait(mut self) -> Result<RunInfo<()>> {
+        loop {
+            if let Some(run_info) = self.try_wait()? {
+                return Ok(run_info);
+            }
+            if let Some(ref supervisor) = self.supervisor {
+                supervisor.wait_exit(WAIT_INTERVAL)?;
+            }
+        }
+    }
+}
+
+/// Where the supervisor of a detached run with its result at `result_file` takes requests
+pub(crate) fn control_socket_path(result_file: &Path) -> PathBuf {
+    let mut path = result_file.as_os_str().to_owned();
+    path.push(".control");
+    PathBuf::from(path)
+}
+
+/// Writes `result` to `path` for `AdoptedJail`, which sees all of it or nothing
+pub(crate) fn write_result(path: &Path, result: &Result<RunInfo<()>>) -> io::Result<()> {
+    let mut partial_path = path.as_os_str().to_owned();
+    partial_path.push(".partial");
+    fs::write(&partial_path, serde_json::to_vec(result)?)?;
+    fs::rename(&partial_path, path)
+}
+
+fn read_result(token: &RecoveryToken) -> Result<RunInfo<()>> {
+    let error = |error: String| {
+        Error::from(FFIError::RecoveryError(format!(
+            "{:?}: {}",
+            token.result_file, error
+        )))
+    };
+    let data = match fs::read(&token.result_file) {
+        Ok(data) => data,
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Err(missing_result(token)),
+        Err(err) => return Err(error(err.to_string())),
+    };
+    serde_json::from_slice::<Result<RunInfo<()>>>(&data).map_err(|err| error(err.to_string()))?
+}
+
+fn missing_result(token: &RecoveryToken) -> Error {
+    FFIError::RecoveryError(format!(
+        "the supervisor (pid {}) exited without writing a result to {:?}",
+        token.supervisor_pid, token.result_file
+    ))
+    .into()
+}
@@ -1,17 +1,65 @@
 use std::fmt::{self, Display, Formatter};
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 
-use config::{Limits, SpaceUsage};
-use utils::DurationDisplay;
+use config::{Limits, SpaceUsage, WallTimeDerivation};
+use libc;
+use serde::Serialize;
+use serde_json;
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+use utils::{self, DurationDisplay};
+use version_info::{VersionInfo, VERSION_INFO};
+
+/// The version of the serialized `RunInfo`, raised whenever a field changes meaning or goes
+/// away (new fields alone do not raise it)
+pub const RUN_INFO_VERSION: u32 = 1;
+
+/// The version of how `RunInfoResult` classifies the end of a run (runtime errors, aborts, OOM
+/// kills...), raised whenever the same end may be classified differently. Recorded in
+/// `VersionInfo`, results of producers with different versions do not compare.
+pub const CLASSIFICATION_VERSION: u32 = 1;
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub enum RunInfoResult<T> {
     Success(T),
     NonZeroExitStatus(u32),
-    KilledBySignal(u32),
+    /// Killed by a signal that tells nothing more about what went wrong, like `SIGKILL` or
+    /// `SIGTERM`. `RunInfoResult::killed_by_signal` classifies the others.
+    KilledBySignal(#[serde(with = "utils::signal")] u32),
+    /// Killed by a signal for a fault of its own: `SIGSEGV`, `SIGBUS`, `SIGILL` or `SIGFPE`
+    RuntimeError {
+        #[serde(with = "utils::signal")]
+        signal: u32,
+    },
+    /// Killed by `SIGABRT`, mostly for a failed assertion
+    Aborted,
+    /// Killed by the OOM killer of a cgroup above the run, for a run without a memory limit on
+    /// it or its parents (hitting those is `MemoryLimitExceeded`)
+    KilledByOomKiller,
+    /// Killed by a `SIGKILL` the sandbox did not send, with its cgroups under their memory
+    /// limits: from outside or by the OOM killer of the machine. The run says nothing about
+    /// the command and can be run again. With `SharePid::Share` the command could have sent
+    /// it itself, it is `KilledBySignal` then.
+    KilledByHost,
+    /// Killed through `JailHandle::kill` before it ended
+    Cancelled,
+    /// Killed by the seccomp policy for calling syscall `number`, `name` is missing for the
+    /// syscalls the sandbox does not know the name of
+    IllegalSyscall {
+        number: u32,
+        name: Option<String>,
+    },
     MemoryLimitExceeded,
     TimeLimitExceeded,
-    WallTimeLimitExceeded,
+    NetworkLimitExceeded,
+    /// `written` is how much the command wrote to the streams the supervisor reads, at the
+    /// point it was noticed
+    OutputLimitExceeded {
+        written: SpaceUsage,
+    },
+    /// `derived` tells whether the wall time limit was derived from the user time limit
+    WallTimeLimitExceeded {
+        derived: bool,
+    },
 }
 
 #[allow(clippy::use_self)]
@@ -31,9 +79,19 @@ impl<T> RunInfoResult<T> {
             Self::Success(obj) => RunInfoResult::Success(cb(obj)?),
             Self::NonZeroExitStatus(exit_status) => RunInfoResult::NonZeroExitStatus(exit_status),
             Self::KilledBySignal(signal) => RunInfoResult::KilledBySignal(signal),
+            Self::RuntimeError { signal } => RunInfoResult::RuntimeError { signal },
+            Self::Aborted => RunInfoResult::Aborted,
+            Self::KilledByOomKiller => RunInfoResult::KilledByOomKiller,
+            Self::KilledByHost => RunInfoResult::KilledByHost,
+            Self::Cancelled => RunInfoResult::Cancelled,
+            Self::IllegalSyscall { number, name } => RunInfoResult::IllegalSyscall { number, name },
             Self::MemoryLimitExceeded => RunInfoResult::MemoryLimitExceeded,
             Self::TimeLimitExceeded => RunInfoResult::TimeLimitExceeded,
-            Self::WallTimeLimitExceeded => RunInfoResult::WallTimeLimitExceeded,
+            Self::NetworkLimitExceeded => RunInfoResult::NetworkLimitExceeded,
+            Self::OutputLimitExceeded { written } => RunInfoResult::OutputLimitExceeded { written },
+            Self::WallTimeLimitExceeded { derived } => {
+                RunInfoResult::WallTimeLimitExceeded { derived }
+            }
         })
     }
 
@@ -43,36 +101,285 @@ impl<T> RunInfoResult<T> {
             _ => None,
         }
     }
+
+    /// The result of the command killed by `signal`, by what the signal tells about it
+    pub fn killed_by_signal(signal: u32) -> Self {
+        match signal as libc::c_int {
+            libc::SIGSEGV | libc::SIGBUS | libc::SIGILL | libc::SIGFPE => {
+                Self::RuntimeError { signal }
+            }
+            libc::SIGABRT => Self::Aborted,
+            _ => Self::KilledBySignal(signal),
+        }
+    }
+
+    /// The signal that killed the command, for `KilledBySignal`, `RuntimeError`, `Aborted`,
+    /// `KilledByOomKiller` and `KilledByHost`
+    pub fn signal(&self) -> Option<Signal> {
+        match *self {
+            Self::KilledBySignal(signal) | Self::RuntimeError { signal } => {
+                Some(Signal::new(signal))
+            }
+            Self::Aborted => Some(Signal::new(libc::SIGABRT as u32)),
+            Self::KilledByOomKiller | Self::KilledByHost => Some(Signal::new(libc::SIGKILL as u32)),
+            _ => None,
+        }
+    }
+
+    /// The exit status of the command, for `NonZeroExitStatus`
+    pub fn exit_status(&self) -> Option<u32> {
+        match *self {
+            Self::NonZeroExitStatus(exit_status) => Some(exit_status),
+            _ => None,
+        }
+    }
 }
 
 impl<T> Display for RunInfoResult<T> {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         match *self {
             Self::Success(_) => write!(f, "Success"),
-            Self::NonZeroExitStatus(ref exit_code) => {
-                write!(f, "Non zero exit status: {}", exit_code)
+            Self::NonZeroExitStatus(ref exit_code) => write!(f, "Exited with status {}", exit_code),
+            Self::KilledBySignal(signal) => write!(f, "Killed by {}", Signal::new(signal)),
+            Self::RuntimeError { signal } if signal == libc::SIGFPE as u32 => {
+                write!(f, "Runtime error (SIGFPE, likely division by zero)")
+            }
+            Self::RuntimeError { signal } => {
+                let signal = Signal::new(signal);
+                match (signal.name(), signal.description()) {
+                    (Some(name), Some(description)) => {
+                        write!(f, "Runtime error ({}, {})", name, description)
+                    }
+                    _ => write!(f, "Runtime error ({})", signal),
+                }
             }
-            Self::KilledBySignal(ref signal) => write!(f, "Killed by Signal {}", signal),
+            Self::Aborted => write!(f, "Aborted (SIGABRT, likely a failed assertion)"),
+            Self::KilledByOomKiller => write!(f, "Killed by the OOM killer"),
+            Self::KilledByHost => {
+                write!(f, "Killed by the host (SIGKILL from outside the sandbox)")
+            }
+            Self::Cancelled => write!(f, "Cancelled"),
+            Self::IllegalSyscall {
+                number,
+                name: Some(ref name),
+            } => write!(f, "Illegal syscall {} ({})", name, number),
+            Self::IllegalSyscall { number, name: None } => write!(f, "Illegal syscall {}", number),
             Self::MemoryLimitExceeded => write!(f, "Memory limit exceeded"),
             Self::TimeLimitExceeded => write!(f, "Time limit exceeded"),
-            Self::WallTimeLimitExceeded => write!(f, "Wall time limit exceeded"),
+            Self::NetworkLimitExceeded => write!(f, "Network limit exceeded"),
+            Self::OutputLimitExceeded { written } => {
+                write!(f, "Output limit exceeded ({} written)", written)
+            }
+            Self::WallTimeLimitExceeded { derived: false } => write!(f, "Wall time limit exceeded"),
+            Self::WallTimeLimitExceeded { derived: true } => {
+                write!(
+                    f,
+                    "Wall time limit (derived from the user time limit) exceeded"
+                )
+            }
         }
     }
 }
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+/// What a judge makes of a run, see `RunInfo::verdict`. Displayed as the usual two letters.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub enum Verdict {
+    /// Exited with status 0, within the limits
+    Accepted,
+    /// Over the user or the wall time limit
+    TimeLimitExceeded,
+    /// Over the memory limit, or killed by the OOM killer of a cgroup above the run
+    MemoryLimitExceeded,
+    /// Over the output or the network limit
+    OutputLimitExceeded,
+    /// Exited with another status, killed by a signal or for an illegal syscall
+    RuntimeError,
+    /// Killed by the host or cancelled, the run says nothing about the command
+    Skipped,
+}
+
+/// The order `RunInfo::verdict` picks among the limits a run exceeded: memory, time, output
+pub const DEFAULT_LIMIT_PRECEDENCE: [Verdict; 3] = [
+    Verdict::MemoryLimitExceeded,
+    Verdict::TimeLimitExceeded,
+    Verdict::OutputLimitExceeded,
+];
+
+impl Display for Verdict {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let abbreviation = match *self {
+            Self::Accepted => "AC",
+            Self::TimeLimitExceeded => "TL",
+            Self::MemoryLimitExceeded => "ML",
+            Self::OutputLimitExceeded => "OL",
+            Self::RuntimeError => "RE",
+            Self::Skipped => "SK",
+        };
+        write!(f, "{}", abbreviation)
+    }
+}
+
+// Number, name and description (after strsignal) of the standard signals
+const SIGNALS: &[(libc::c_int, &str, &str)] = &[
+    (libc::SIGHUP, "SIGHUP", "hangup"),
+    (libc::SIGINT, "SIGINT", "interrupt"),
+    (libc::SIGQUIT, "SIGQUIT", "quit"),
+    (libc::SIGILL, "SIGILL", "illegal instruction"),
+    (libc::SIGTRAP, "SIGTRAP", "trace/breakpoint trap"),
+    (libc::SIGABRT, "SIGABRT", "aborted"),
+    (libc::SIGBUS, "SIGBUS", "bus error"),
+    (libc::SIGFPE, "SIGFPE", "arithmetic exception"),
+    (libc::SIGKILL, "SIGKILL", "killed"),
+    (libc::SIGUSR1, "SIGUSR1", "user defined signal 1"),
+    (libc::SIGSEGV, "SIGSEGV", "invalid memory reference"),
+    (libc::SIGUSR2, "SIGUSR2", "user defined signal 2"),
+    (libc::SIGPIPE, "SIGPIPE", "broken pipe"),
+    (libc::SIGALRM, "SIGALRM", "alarm clock"),
+    (libc::SIGTERM, "SIGTERM", "terminated"),
+    (libc::SIGSTKFLT, "SIGSTKFLT", "stack fault"),
+    (libc::SIGCHLD, "SIGCHLD", "child exited"),
+    (libc::SIGCONT, "SIGCONT", "continued"),
+    (libc::SIGSTOP, "SIGSTOP", "stopped (signal)"),
+    (libc::SIGTSTP, "SIGTSTP", "stopped"),
+    (libc::SIGTTIN, "SIGTTIN", "stopped (tty input)"),
+    (libc::SIGTTOU, "SIGTTOU", "stopped (tty output)"),
+    (libc::SIGURG, "SIGURG", "urgent I/O condition"),
+    (libc::SIGXCPU, "SIGXCPU", "CPU time limit exceeded"),
+    (libc::SIGXFSZ, "SIGXFSZ", "file size limit exceeded"),
+    (libc::SIGVTALRM, "SIGVTALRM", "virtual timer expired"),
+    (libc::SIGPROF, "SIGPROF", "profiling timer expired"),
+    (libc::SIGWINCH, "SIGWINCH", "window changed"),
+    (libc::SIGIO, "SIGIO", "I/O possible"),
+    (libc::SIGPWR, "SIGPWR", "power failure"),
+    (libc::SIGSYS, "SIGSYS", "bad system call"),
+];
+
+// The kernel numbers the real-time signals from 32, the C library keeps the first few of them
+// for itself and starts `SIGRTMIN` after
+const KERNEL_SIGRTMIN: libc::c_int = 32;
+
+/// A signal number, as in `RunInfoResult::KilledBySignal`, with its name and what it means
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Signal(u32);
+
+impl Signal {
+    pub fn new(number: u32) -> Self {
+        Self(number)
+    }
+
+    pub fn number(self) -> u32 {
+        self.0
+    }
+
+    fn standard(self) -> Option<(&'static str, &'static str)> {
+        SIGNALS
+            .iter()
+            .find(|&&(number, _, _)| number as u32 == self.0)
+            .map(|&(_, name, description)| (name, description))
+    }
+
+    fn is_real_time(self) -> bool {
+        self.0 >= KERNEL_SIGRTMIN as u32 && self.0 <= libc::SIGRTMAX() as u32
+    }
+
+    /// `SIGSEGV`, or `SIGRTMIN+n` for the real-time signals. `None` for the real-time signals
+    /// below `SIGRTMIN` (the C library uses them) and numbers that are not signals
+    pub fn name(self) -> Option<String> {
+        if let Some((name, _)) = self.standard() {
+            return Some(name.to_owned());
+        }
+        let sigrtmin = libc::SIGRTMIN() as u32;
+        match self.0 {
+            number if !self.is_real_time() || number < sigrtmin => None,
+            number if number == sigrtmin => Some("SIGRTMIN".to_owned()),
+            number => Some(format!("SIGRTMIN+{}", number - sigrtmin)),
+        }
+    }
+
+    /// What the signal means, like `invalid memory reference` for SIGSEGV
+    pub fn description(self) -> Option<&'static str> {
+        match self.standard() {
+            Some((_, description)) => Some(description),
+            None if self.is_real_time() => Some("real-time signal"),
+            None => None,
+        }
+    }
+}
+
+impl Display for Signal {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.name() {
+            Some(name) => write!(f, "{}", name)?,
+            None => write!(f, "signal {}", self.0)?,
+        }
+        if let Some(description) = self.description() {
+            write!(f, " ({})", description)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub struct RunUsage {
+    #[serde(with = "utils::duration_nanos")]
     user_time: Duration,
+    #[serde(with = "utils::duration_nanos")]
     wall_time: Duration,
     memory: SpaceUsage,
+    current_memory: SpaceUsage,
+    max_rss: Option<SpaceUsage>,
+    voluntary_ctxt_switches: Option<u64>,
+    involuntary_ctxt_switches: Option<u64>,
+    minor_faults: Option<u64>,
+    major_faults: Option<u64>,
+    #[serde(with = "utils::duration_nanos")]
+    stopped_time: Duration,
+    #[serde(default, with = "utils::duration_nanos")]
+    paused_time: Duration,
+    #[serde(with = "utils::duration_nanos")]
+    throttled_time: Duration,
+    #[serde(with = "utils::duration_nanos")]
+    setup_time: Duration,
+    nr_throttled: Option<u64>,
+    #[serde(with = "utils::optional_durations_nanos")]
+    cpu_usage_percpu: Option<Vec<Duration>>,
+    io_read: SpaceUsage,
+    io_written: SpaceUsage,
+    net_rx_bytes: SpaceUsage,
+    net_tx_bytes: SpaceUsage,
+    output_bytes: SpaceUsage,
+    dropped_output_bytes: SpaceUsage,
 }
 
 impl RunUsage {
-    pub fn new(user_time: Duration, wall_time: Duration, memory: SpaceUsage) -> Self {
+    pub fn new(
+        user_time: Duration,
+        wall_time: Duration,
+        memory: SpaceUsage,
+        current_memory: SpaceUsage,
+    ) -> Self {
         Self {
             user_time,
             wall_time,
             memory,
+            current_memory,
+            max_rss: None,
+            voluntary_ctxt_switches: None,
+            involuntary_ctxt_switches: None,
+            minor_faults: None,
+            major_faults: None,
+            stopped_time: Duration::from_secs(0),
+            paused_time: Duration::from_secs(0),
+            throttled_time: Duration::from_secs(0),
+            setup_time: Duration::from_secs(0),
+            nr_throttled: None,
+            cpu_usage_percpu: None,
+            io_read: SpaceUsage::from_bytes(0),
+            io_written: SpaceUsage::from_bytes(0),
+            net_rx_bytes: SpaceUsage::from_bytes(0),
+            net_tx_bytes: SpaceUsage::from_bytes(0),
+            output_bytes: SpaceUsage::from_bytes(0),
+            dropped_output_bytes: SpaceUsage::from_bytes(0),
         }
     }
 
@@ -84,34 +391,333 @@ impl RunUsage {
         self.wall_time
     }
 
+    /// Peak memory usage, short lived allocation spikes included
     pub fn memory(&self) -> SpaceUsage {
         self.memory
     }
 
-    pub fn check_limits<T>(self, limits: Limits) -> Option<RunInfo<T>> {
+    /// Memory usage at the time the usage was sampled
+    pub fn current_memory(&self) -> SpaceUsage {
+        self.current_memory
+    }
+
+    /// Peak resident set size from `wait4`, of the direct child only, not the whole tree. It
+    /// is there without a memory cgroup as well, `None` when the run ended (on a limit) before
+    /// the child was waited for
+    pub fn max_rss(&self) -> Option<SpaceUsage> {
+        self.max_rss
+    }
+
+    pub fn set_max_rss(&mut self, max_rss: Option<SpaceUsage>) {
+        self.max_rss = max_rss;
+    }
+
+    // The counters below come from `wait4` like `max_rss`: they include the threads of the
+    // direct child, but not the other processes of the run
+
+    /// Times the direct child gave up the CPU on its own, mostly to wait for I/O or a lock
+    pub fn voluntary_ctxt_switches(&self) -> Option<u64> {
+        self.voluntary_ctxt_switches
+    }
+
+    pub fn set_voluntary_ctxt_switches(&mut self, voluntary_ctxt_switches: Option<u64>) {
+        self.voluntary_ctxt_switches = voluntary_ctxt_switches;
+    }
+
+    /// Times the direct child was preempted, at the end of its time slice or when throttled
+    pub fn involuntary_ctxt_switches(&self) -> Option<u64> {
+        self.involuntary_ctxt_switches
+    }
+
+    pub fn set_involuntary_ctxt_switches(&mut self, involuntary_ctxt_switches: Option<u64>) {
+        self.involuntary_ctxt_switches = involuntary_ctxt_switches;
+    }
+
+    /// Page faults of the direct child served without I/O
+    pub fn minor_faults(&self) -> Option<u64> {
+        self.minor_faults
+    }
+
+    pub fn set_minor_faults(&mut self, minor_faults: Option<u64>) {
+        self.minor_faults = minor_faults;
+    }
+
+    /// Page faults of the direct child that had to read from disk
+    pub fn major_faults(&self) -> Option<u64> {
+        self.major_faults
+    }
+
+    pub fn set_major_faults(&mut self, major_faults: Option<u64>) {
+        self.major_faults = major_faults;
+    }
+
+    /// Wall time spent stopped by SIGSTOP/SIGTSTP (and the like) until continued
+    pub fn stopped_time(&self) -> Duration {
+        self.stopped_time
+    }
+
+    pub fn set_stopped_time(&mut self, stopped_time: Duration) {
+        self.stopped_time = stopped_time;
+    }
+
+    /// Wall time the run spent paused by `JailHandle::pause`, which is never part of the
+    /// wall time
+    pub fn paused_time(&self) -> Duration {
+        self.paused_time
+    }
+
+    pub fn set_paused_time(&mut self, paused_time: Duration) {
+        self.paused_time = paused_time;
+    }
+
+    /// Wall time the run was held back by its cpu quota
+    pub fn throttled_time(&self) -> Duration {
+        self.throttled_time
+    }
+
+    pub fn set_throttled_time(&mut self, throttled_time: Duration) {
+        self.throttled_time = throttled_time;
+    }
+
+    /// Wall time from the clone of the child to its exec (namespaces, cgroups, mounts and the
+    /// like), not part of `wall_time`
+    pub fn setup_time(&self) -> Duration {
+        self.setup_time
+    }
+
+    pub fn set_setup_time(&mut self, setup_time: Duration) {
+        self.setup_time = setup_time;
+    }
+
+    /// Number of cpu quota periods the run was throttled in, `None` if the instance never had
+    /// a cpu quota
+    pub fn nr_throttled(&self) -> Option<u64> {
+        self.nr_throttled
+    }
+
+    pub fn set_nr_throttled(&mut self, nr_throttled: Option<u64>) {
+        self.nr_throttled = nr_throttled;
+    }
+
+    /// User time spent on each CPU, `None` where the kernel does not account it (cgroups v2)
+    pub fn cpu_usage_percpu(&self) -> Option<&[Duration]> {
+        self.cpu_usage_percpu.as_ref().map(Vec::as_slice)
+    }
+
+    pub fn set_cpu_usage_percpu(&mut self, cpu_usage_percpu: Option<Vec<Duration>>) {
+        self.cpu_usage_percpu = cpu_usage_percpu;
+    }
+
+    /// Bytes read from block devices, only accounted for runs with io limits
+    pub fn io_read(&self) -> SpaceUsage {
+        self.io_read
+    }
+
+    pub fn set_io_read(&mut self, io_read: SpaceUsage) {
+        self.io_read = io_read;
+    }
+
+    /// Bytes written to block devices, only accounted for runs with io limits
+    pub fn io_written(&self) -> SpaceUsage {
+        self.io_written
+    }
+
+    pub fn set_io_written(&mut self, io_written: SpaceUsage) {
+        self.io_written = io_written;
+    }
+
+    /// Bytes received over the network, all traffic of the network namespace while the run
+    /// lasted (sampled). Not accounted for a joined namespace.
+    pub fn net_rx_bytes(&self) -> SpaceUsage {
+        self.net_rx_bytes
+    }
+
+    pub fn set_net_rx_bytes(&mut self, net_rx_bytes: SpaceUsage) {
+        self.net_rx_bytes = net_rx_bytes;
+    }
+
+    /// Bytes sent over the network, all traffic of the network namespace while the run lasted
+    /// (sampled). Not accounted for a joined namespace.
+    pub fn net_tx_bytes(&self) -> SpaceUsage {
+        self.net_tx_bytes
+    }
+
+    pub fn set_net_tx_bytes(&mut self, net_tx_bytes: SpaceUsage) {
+        self.net_tx_bytes = net_tx_bytes;
+    }
+
+    /// Bytes written to stdout and stderr, only accounted for the streams the supervisor
+    /// reads (see `Limits::output`)
+    pub fn output_bytes(&self) -> SpaceUsage {
+        self.output_bytes
+    }
+
+    pub fn set_output_bytes(&mut self, output_bytes: SpaceUsage) {
+        self.output_bytes = output_bytes;
+    }
+
+    /// Bytes of the streams redirected to `Redirect::Tee` that `Config::on_output` missed for
+    /// being too slow, the files got them all the same
+    pub fn dropped_output_bytes(&self) -> SpaceUsage {
+        self.dropped_output_bytes
+    }
+
+    pub fn set_dropped_output_bytes(&mut self, dropped_output_bytes: SpaceUsage) {
+        self.dropped_output_bytes = dropped_output_bytes;
+    }
+
+    /// The usage of a run on an instance used before with `ClearUsage::No`, from the usage it
+    /// reported (`self`) and the one the run before it reported (`earlier`). The counters the
+    /// cgroups keep across runs are subtracted: the user time, the time and periods
+    /// throttled, the time on each cpu (`None` unless both have it for as many cpus) and the
+    /// bytes of io. Everything else was for the run alone or is a peak, and is the one of
+    /// `self`: the wall time is never subtracted.
+    ///
+    /// `None` when a counter went back, the two are not from the same instance in order.
+    pub fn checked_sub(&self, earlier: &Self) -> Option<Self> {
+        let nr_throttled = match (self.nr_throttled, earlier.nr_throttled) {
+            (Some(nr_throttled), Some(earlier)) => Some(nr_throttled.checked_sub(earlier)?),
+            (nr_throttled, _) => nr_throttled,
+        };
+        let cpu_usage_percpu = match (self.cpu_usage_percpu(), earlier.cpu_usage_percpu()) {
+            (Some(percpu), Some(earlier)) if percpu.len() == earlier.len() => Some(
+                percpu
+                    .iter()
+                    .zip(earlier)
+                    .map(|(time, earlier)| time.checked_sub(*earlier))
+                    .collect::<Option<_>>()?,
+            ),
+            _ => None,
+        };
+        let io_read = self
+            .io_read
+            .as_bytes()
+            .checked_sub(earlier.io_read.as_bytes())?;
+        let io_written = self
+            .io_written
+            .as_bytes()
+            .checked_sub(earlier.io_written.as_bytes())?;
+        Some(Self {
+            user_time: self.user_time.checked_sub(earlier.user_time)?,
+            throttled_time: self.throttled_time.checked_sub(earlier.throttled_time)?,
+            nr_throttled,
+            cpu_usage_percpu,
+            io_read: SpaceUsage::from_bytes(io_read),
+            io_written: SpaceUsage::from_bytes(io_written),
+            ..self.clone()
+        })
+    }
+
+    /// The usage `checked_sub` took `self` from, the counters of `later` added to the ones of
+    /// `self` and everything else (the wall time as well) from `later`
+    pub fn saturating_add(&self, later: &Self) -> Self {
+        let nr_throttled = match (self.nr_throttled, later.nr_throttled) {
+            (Some(nr_throttled), Some(later)) => Some(nr_throttled.saturating_add(later)),
+            (nr_throttled, later) => later.or(nr_throttled),
+        };
+        let cpu_usage_percpu = match (self.cpu_usage_percpu(), later.cpu_usage_percpu()) {
+            (Some(percpu), Some(later)) if percpu.len() == later.len() => Some(
+                percpu
+                    .iter()
+                    .zip(later)
+                    .map(|(time, later)| saturating_add(*time, *later))
+                    .collect(),
+            ),
+            _ => None,
+        };
+        let io_read = self
+            .io_read
+            .as_bytes()
+            .saturating_add(later.io_read.as_bytes());
+        let io_written = self
+            .io_written
+            .as_bytes()
+            .saturating_add(later.io_written.as_bytes());
+        Self {
+            user_time: saturating_add(self.user_time, later.user_time),
+            throttled_time: saturating_add(self.throttled_time, later.throttled_time),
+            nr_throttled,
+            cpu_usage_percpu,
+            io_read: SpaceUsage::from_bytes(io_read),
+            io_written: SpaceUsage::from_bytes(io_written),
+            ..later.clone()
+        }
+    }
+
+    /// The first limit of `limits` the usage is over, in the order the supervisor checks them
+    pub fn exceeds(&self, limits: &Limits) -> Option<ExceededLimit> {
         if limits
             .user_time()
             .map_or(false, |time| time < self.user_time())
         {
-            return Some(RunInfo::new(RunInfoResult::TimeLimitExceeded, self));
+            return Some(ExceededLimit::UserTime);
         }
 
         if limits
             .wall_time()
             .map_or(false, |time| time < self.wall_time())
         {
-            return Some(RunInfo::new(RunInfoResult::WallTimeLimitExceeded, self));
+            return Some(ExceededLimit::WallTime);
         }
 
         if limits
             .memory()
             .map_or(false, |memory| memory < self.memory())
         {
-            return Some(RunInfo::new(RunInfoResult::MemoryLimitExceeded, self));
+            return Some(ExceededLimit::Memory);
+        }
+
+        if limits.net_bytes().map_or(false, |net_bytes| {
+            net_bytes.as_bytes()
+                < self
+                    .net_rx_bytes()
+                    .as_bytes()
+                    .saturating_add(self.net_tx_bytes().as_bytes())
+        }) {
+            return Some(ExceededLimit::Network);
+        }
+
+        if limits
+            .output()
+            .map_or(false, |output| output < self.output_bytes())
+        {
+            return Some(ExceededLimit::Output);
         }
 
         None
     }
+
+    pub fn check_limits<T>(&self, limits: &Limits) -> Option<RunInfo<T>> {
+        let result = match self.exceeds(limits)? {
+            ExceededLimit::UserTime => RunInfoResult::TimeLimitExceeded,
+            ExceededLimit::WallTime => RunInfoResult::WallTimeLimitExceeded {
+                derived: limits.wall_time_derivation().is_some(),
+            },
+            ExceededLimit::Memory => RunInfoResult::MemoryLimitExceeded,
+            ExceededLimit::Network => RunInfoResult::NetworkLimitExceeded,
+            ExceededLimit::Output => RunInfoResult::OutputLimitExceeded {
+                written: self.output_bytes(),
+            },
+        };
+        Some(RunInfo::new(result, self.clone()))
+    }
+}
+
+fn saturating_add(duration: Duration, other: Duration) -> Duration {
+    duration
+        .checked_add(other)
+        .unwrap_or_else(|| Duration::new(u64::MAX, 999_999_999))
+}
+
+/// A limit of `Limits` a usage is over, see `RunUsage::exceeds`
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum ExceededLimit {
+    UserTime,
+    WallTime,
+    Memory,
+    /// The bytes received and sent together
+    Network,
+    Output,
 }
 
 impl Default for RunUsage {
@@ -120,6 +726,7 @@ impl Default for RunUsage {
             Duration::from_secs(0),
             Duration::from_secs(0),
             SpaceUsage::from_bytes(0),
+            SpaceUsage::from_bytes(0),
         )
     }
 }
@@ -128,48 +735,500 @@ impl Display for RunUsage {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         writeln!(f, "Total user time: {}", DurationDisplay(self.user_time()))?;
         writeln!(f, "Wall time: {}", DurationDisplay(self.wall_time()))?;
+        if self.stopped_time() > Duration::from_secs(0) {
+            writeln!(f, "Stopped time: {}", DurationDisplay(self.stopped_time()))?;
+        }
+        if self.paused_time() > Duration::from_secs(0) {
+            writeln!(f, "Paused time: {}", DurationDisplay(self.paused_time()))?;
+        }
+        if self.throttled_time() > Duration::from_secs(0) {
+            writeln!(
+                f,
+                "Throttled time: {}",
+                DurationDisplay(self.throttled_time())
+            )?;
+        }
+        if self.setup_time() > Duration::from_secs(0) {
+            writeln!(f, "Setup time: {}", DurationDisplay(self.setup_time()))?;
+        }
+        if let Some(nr_throttled) = self.nr_throttled() {
+            writeln!(f, "Throttled periods: {}", nr_throttled)?;
+        }
+        if let Some(cpu_usage_percpu) = self.cpu_usage_percpu() {
+            let cpu_usage_percpu: Vec<String> = cpu_usage_percpu
+                .iter()
+                .map(|&usage| DurationDisplay(usage).to_string())
+                .collect();
+            writeln!(f, "Per CPU user time: {}", cpu_usage_percpu.join(" "))?;
+        }
+        if self.io_read() > SpaceUsage::from_bytes(0) {
+            writeln!(f, "I/O read: {}", self.io_read())?;
+        }
+        if self.io_written() > SpaceUsage::from_bytes(0) {
+            writeln!(f, "I/O written: {}", self.io_written())?;
+        }
+        if self.net_rx_bytes() > SpaceUsage::from_bytes(0) {
+            writeln!(f, "Network received: {}", self.net_rx_bytes())?;
+        }
+        if self.net_tx_bytes() > SpaceUsage::from_bytes(0) {
+            writeln!(f, "Network sent: {}", self.net_tx_bytes())?;
+        }
+        if self.output_bytes() > SpaceUsage::from_bytes(0) {
+            writeln!(f, "Output: {}", self.output_bytes())?;
+        }
+        if self.dropped_output_bytes() > SpaceUsage::from_bytes(0) {
+            writeln!(f, "Output dropped: {}", self.dropped_output_bytes())?;
+        }
+        if let Some(max_rss) = self.max_rss() {
+            writeln!(f, "Maximum RSS (direct child): {}", max_rss)?;
+        }
+        if let (Some(voluntary), Some(involuntary)) = (
+            self.voluntary_ctxt_switches(),
+            self.involuntary_ctxt_switches(),
+        ) {
+            writeln!(
+                f,
+                "Context switches (direct child): {} voluntary, {} involuntary",
+                voluntary, involuntary
+            )?;
+        }
+        if let (Some(minor), Some(major)) = (self.minor_faults(), self.major_faults()) {
+            writeln!(
+                f,
+                "Page faults (direct child): {} minor, {} major",
+                minor, major
+            )?;
+        }
         write!(f, "Maximum memory: {}", self.memory())
     }
 }
+
+/// A wall time limit derived from the user time limit, along with how it was derived
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct DerivedWallTime {
+    derivation: WallTimeDerivation,
+    #[serde(with = "utils::duration_nanos")]
+    wall_time: Duration,
+}
+
+impl DerivedWallTime {
+    pub fn new(derivation: WallTimeDerivation, wall_time: Duration) -> Self {
+        Self {
+            derivation,
+            wall_time,
+        }
+    }
+
+    pub fn derivation(&self) -> WallTimeDerivation {
+        self.derivation
+    }
+
+    pub fn wall_time(&self) -> Duration {
+        self.wall_time
+    }
+}
+
+/// How a run that goes on is doing, see `JailHandle::status`
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct RunStatus {
+    usage: RunUsage,
+    #[serde(with = "utils::optional_duration_nanos")]
+    remaining_wall_time: Option<Duration>,
+}
+
+impl RunStatus {
+    pub fn new(usage: RunUsage, remaining_wall_time: Option<Duration>) -> Self {
+        Self {
+            usage,
+            remaining_wall_time,
+        }
+    }
+
+    /// The usage at the last sample of the supervisor
+    pub fn usage(&self) -> &RunUsage {
+        &self.usage
+    }
+
+    /// What is left of the wall time limit, `None` without one
+    pub fn remaining_wall_time(&self) -> Option<Duration> {
+        self.remaining_wall_time
+    }
+}
+
+/// The output of a stream redirected to `Redirect::Capture`, as raw bytes
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct CapturedOutput {
+    data: Vec<u8>,
+    truncated: bool,
+}
+
+impl CapturedOutput {
+    pub fn new(data: Vec<u8>, truncated: bool) -> Self {
+        Self { data, truncated }
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn into_data(self) -> Vec<u8> {
+        self.data
+    }
+
+    /// Whether the command wrote more than the limit, which was left out
+    pub fn truncated(&self) -> bool {
+        self.truncated
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub struct RunInfo<T> {
+    // 0 for records serialized before it was versioned
+    #[serde(default)]
+    version: u32,
     result: RunInfoResult<T>,
+    #[serde(default)]
+    wait_status: Option<i32>,
     usage: RunUsage,
+    derived_wall_time: Option<DerivedWallTime>,
+    #[serde(default, with = "utils::optional_rfc3339")]
+    started_at: Option<SystemTime>,
+    #[serde(default, with = "utils::optional_rfc3339")]
+    finished_at: Option<SystemTime>,
+    #[serde(default, with = "utils::optional_duration_nanos")]
+    duration: Option<Duration>,
+    // Missing from records serialized before it was added
+    #[serde(default)]
+    producer: Option<VersionInfo>,
+    #[serde(default)]
+    traced: bool,
+    #[serde(default)]
+    stdout: Option<CapturedOutput>,
+    #[serde(default)]
+    stderr: Option<CapturedOutput>,
+    #[serde(default)]
+    stderr_tail: Option<Vec<u8>>,
+    #[serde(default)]
+    warnings: Vec<String>,
 }
 
 #[allow(clippy::use_self)]
 impl<T> RunInfo<T> {
     pub fn new(result: RunInfoResult<T>, usage: RunUsage) -> Self {
-        Self { result, usage }
+        Self {
+            version: RUN_INFO_VERSION,
+            result,
+            wait_status: None,
+            usage,
+            derived_wall_time: None,
+            started_at: None,
+            finished_at: None,
+            duration: None,
+            producer: Some(VERSION_INFO),
+            traced: false,
+            stdout: None,
+            stderr: None,
+            stderr_tail: None,
+            warnings: Vec::new(),
+        }
+    }
+
+    /// The `RUN_INFO_VERSION` this was serialized with, 0 for records from before it was
+    /// versioned
+    pub fn version(&self) -> u32 {
+        self.version
     }
 
     pub fn result(&self) -> &RunInfoResult<T> {
         &self.result
     }
 
+    pub(crate) fn set_result(&mut self, result: RunInfoResult<T>) {
+        self.result = result;
+    }
+
+    /// The status `wait` gave for the command once it ended, as it is. `None` when the
+    /// supervisor stopped waiting before that, for a limit exceeded or an illegal syscall,
+    /// unless the command exited within `Config::kill_grace_period`. The result can tell more:
+    /// a command the OOM killer got is still a `SIGKILL` here.
+    pub fn wait_status(&self) -> Option<i32> {
+        self.wait_status
+    }
+
+    pub fn set_wait_status(&mut self, wait_status: Option<i32>) {
+        self.wait_status = wait_status;
+    }
+
+    /// The status the command exited with (0 included) according to `wait_status`, `None` if
+    /// it was killed
+    pub fn exit_status(&self) -> Option<u32> {
+        self.wait_status
+            .filter(|&status| libc::WIFEXITED(status))
+            .map(|status| libc::WEXITSTATUS(status) as u32)
+    }
+
+    /// The signal that killed the command according to `wait_status`, `None` if it exited
+    pub fn signal(&self) -> Option<Signal> {
+        self.wait_status
+            .filter(|&status| libc::WIFSIGNALED(status))
+            .map(|status| Signal::new(libc::WTERMSIG(status) as u32))
+    }
+
+    /// Whether the signal that killed the command dumped its core
+    pub fn core_dumped(&self) -> bool {
+        self.wait_status.map_or(false, |status| {
+            libc::WIFSIGNALED(status) && libc::WCOREDUMP(status)
+        })
+    }
+
     pub fn usage(&self) -> &RunUsage {
         &self.usage
     }
 
+    pub(crate) fn usage_mut(&mut self) -> &mut RunUsage {
+        &mut self.usage
+    }
+
+    /// The wall time limit of the run, if it was derived from the user time limit
+    pub fn derived_wall_time(&self) -> Option<DerivedWallTime> {
+        self.derived_wall_time
+    }
+
+    pub fn set_derived_wall_time(&mut self, derived_wall_time: Option<DerivedWallTime>) {
+        self.derived_wall_time = derived_wall_time;
+    }
+
+    /// The time on the wall clock when the command was executed, as the supervisor saw it
+    pub fn started_at(&self) -> Option<SystemTime> {
+        self.started_at
+    }
+
+    pub fn set_started_at(&mut self, started_at: Option<SystemTime>) {
+        self.started_at = started_at;
+    }
+
+    /// The time on the wall clock when the supervisor was done with the command
+    pub fn finished_at(&self) -> Option<SystemTime> {
+        self.finished_at
+    }
+
+    pub fn set_finished_at(&mut self, finished_at: Option<SystemTime>) {
+        self.finished_at = finished_at;
+    }
+
+    /// How long the run took from `started_at` to `finished_at`, on the monotonic clock. It is
+    /// the one to trust when the wall clock jumped in between, and is never under the wall
+    /// time of the usage.
+    pub fn duration(&self) -> Option<Duration> {
+        self.duration
+    }
+
+    pub fn set_duration(&mut self, duration: Option<Duration>) {
+        self.duration = duration;
+    }
+
+    /// The build of ia-sandbox that produced this, `None` for records deserialized from builds
+    /// that did not include it
+    pub fn producer(&self) -> Option<&VersionInfo> {
+        self.producer.as_ref()
+    }
+
+    /// Whether the syscalls of the command were traced (see `Config::trace_syscalls`), which
+    /// slows it down enough for the times to be off
+    pub fn traced(&self) -> bool {
+        self.traced
+    }
+
+    pub fn set_traced(&mut self, traced: bool) {
+        self.traced = traced;
+    }
+
+    /// What the command wrote to stdout, `None` unless it was captured (or read from a pipe
+    /// through `JailHandle::read_stdout_available`)
+    pub fn stdout(&self) -> Option<&CapturedOutput> {
+        self.stdout.as_ref()
+    }
+
+    pub fn set_stdout(&mut self, stdout: Option<CapturedOutput>) {
+        self.stdout = stdout;
+    }
+
+    /// What the command wrote to stderr, `None` unless it was captured (or read from a pipe
+    /// through `JailHandle::read_stderr_available`)
+    pub fn stderr(&self) -> Option<&CapturedOutput> {
+        self.stderr.as_ref()
+    }
+
+    pub fn set_stderr(&mut self, stderr: Option<CapturedOutput>) {
+        self.stderr = stderr;
+    }
+
+    /// The last bytes the command wrote to stderr, up to `Config::stderr_tail`, for a run that
+    /// did not succeed with stderr captured or redirected to a file of the host. From a file
+    /// opened to append, it can end what was there before the run.
+    pub fn stderr_tail(&self) -> Option<&[u8]> {
+        self.stderr_tail.as_deref()
+    }
+
+    pub fn set_stderr_tail(&mut self, stderr_tail: Option<Vec<u8>>) {
+        self.stderr_tail = stderr_tail;
+    }
+
+    /// What the run went without because the host could not provide it, such as a Landlock
+    /// policy with `LandlockUnsupported::Warn` on a kernel without Landlock
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    pub fn set_warnings(&mut self, warnings: Vec<String>) {
+        self.warnings = warnings;
+    }
+
     pub fn is_success(&self) -> bool {
         self.result.is_success()
     }
 
+    /// The verdict of a judge on this run under `limits`, picking among the limits exceeded by
+    /// `DEFAULT_LIMIT_PRECEDENCE`
+    pub fn verdict(&self, limits: &Limits) -> Verdict {
+        self.verdict_with_precedence(limits, &DEFAULT_LIMIT_PRECEDENCE)
+    }
+
+    /// The verdict of a judge on this run under `limits`, picking among the limits exceeded by
+    /// `precedence` (the ones left out of it come after, by `DEFAULT_LIMIT_PRECEDENCE`).
+    ///
+    /// A limit counts as exceeded when the result says so or the usage is over it, even for a
+    /// command that exited with status 0 before the supervisor noticed (within the kill grace
+    /// period, or between two samples). Past the limits, a success that exited with status 0 is
+    /// `Accepted`, `KilledByHost` and `Cancelled` are `Skipped` and the rest is `RuntimeError`.
+    pub fn verdict_with_precedence(&self, limits: &Limits, precedence: &[Verdict]) -> Verdict {
+        // The limit the result is about, if any
+        let by_result = match self.result {
+            RunInfoResult::TimeLimitExceeded | RunInfoResult::WallTimeLimitExceeded { .. } => {
+                Some(Verdict::TimeLimitExceeded)
+            }
+            RunInfoResult::MemoryLimitExceeded | RunInfoResult::KilledByOomKiller => {
+                Some(Verdict::MemoryLimitExceeded)
+            }
+            RunInfoResult::OutputLimitExceeded { .. } | RunInfoResult::NetworkLimitExceeded => {
+                Some(Verdict::OutputLimitExceeded)
+            }
+            _ => None,
+        };
+        let usage = &self.usage;
+        let by_usage = |verdict: Verdict| match verdict {
+            Verdict::TimeLimitExceeded => {
+                limits
+                    .user_time()
+                    .map_or(false, |time| time < usage.user_time())
+                    || limits
+                        .wall_time()
+                        .map_or(false, |time| time < usage.wall_time())
+            }
+            Verdict::MemoryLimitExceeded => limits
+                .memory()
+                .map_or(false, |memory| memory < usage.memory()),
+            Verdict::OutputLimitExceeded => {
+                let net_bytes = usage
+                    .net_rx_bytes()
+                    .as_bytes()
+                    .saturating_add(usage.net_tx_bytes().as_bytes());
+                limits
+                    .output()
+                    .map_or(false, |output| output < usage.output_bytes())
+                    || limits
+                        .net_bytes()
+                        .map_or(false, |limit| limit.as_bytes() < net_bytes)
+            }
+            _ => false,
+        };
+        if let Some(&verdict) = precedence
+            .iter()
+            .chain(&DEFAULT_LIMIT_PRECEDENCE)
+            .find(|&&verdict| by_result == Some(verdict) || by_usage(verdict))
+        {
+            return verdict;
+        }
+
+        match self.result {
+            RunInfoResult::Success(_) if self.exit_status().map_or(true, |status| status == 0) => {
+                Verdict::Accepted
+            }
+            RunInfoResult::KilledByHost | RunInfoResult::Cancelled => Verdict::Skipped,
+            _ => Verdict::RuntimeError,
+        }
+    }
+
     pub fn and_then<A, B, F: FnOnce(T) -> Result<A, B>>(self, cb: F) -> Result<RunInfo<A>, B> {
-        let Self { result, usage } = self;
-        result
-            .and_then(cb)
-            .map(|result| RunInfo::new(result, usage))
+        let Self {
+            version,
+            result,
+            wait_status,
+            usage,
+            derived_wall_time,
+            started_at,
+            finished_at,
+            duration,
+            producer,
+            traced,
+            stdout,
+            stderr,
+            stderr_tail,
+            warnings,
+        } = self;
+        result.and_then(cb).map(|result| RunInfo {
+            version,
+            result,
+            wait_status,
+            usage,
+            derived_wall_time,
+            started_at,
+            finished_at,
+            duration,
+            producer,
+            traced,
+            stdout,
+            stderr,
+            stderr_tail,
+            warnings,
+        })
     }
 
     pub fn success(self) -> Option<T> {
         self.result.success()
     }
+
+    /// The JSON of this run: durations in nanoseconds, sizes in bytes and signals as
+    /// `{ "number": 9, "name": "SIGKILL" }`, under a top-level `"version"`
+    pub fn to_json(&self) -> String
+    where
+        T: Serialize,
+    {
+        serde_json::to_string(self).expect("RunInfo always serializes to JSON")
+    }
 }
 
 impl<T> Display for RunInfo<T> {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         writeln!(f, "{}", self.result)?;
+        if self.core_dumped() {
+            writeln!(f, "Core dumped")?;
+        }
+        if let Some(derived_wall_time) = self.derived_wall_time {
+            writeln!(
+                f,
+                "Wall time limit: {} (user time limit * {} + {})",
+                DurationDisplay(derived_wall_time.wall_time()),
+                derived_wall_time.derivation().factor(),
+                DurationDisplay(derived_wall_time.derivation().slack())
+            )?;
+        }
+        if self.traced {
+            writeln!(f, "Syscalls traced, the times are distorted")?;
+        }
+        for warning in &self.warnings {
+            writeln!(f, "Warning: {}", warning)?;
+        }
         write!(f, "{}", self.usage)
     }
 }
@@ -0,0 +1,132 @@
+use std::ffi::OsString;
+use std::fmt::{self, Debug, Formatter};
+use std::fs::File;
+use std::io;
+use std::os::unix::ffi::OsStringExt;
+use std::os::unix::fs::FileExt;
+use std::os::unix::io::RawFd;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use libc;
+
+use ffi;
+use syscalls;
+
+/// A syscall of the command passed on to the supervisor by a `SeccompPolicy`, the command is
+/// stopped in it until the `SeccompNotifyHandler` answers
+#[derive(Debug, Copy, Clone)]
+pub struct SeccompNotification {
+    id: u64,
+    pid: u32,
+    number: u32,
+    args: [u64; 6],
+    listener: RawFd,
+}
+
+impl SeccompNotification {
+    pub(crate) fn new(id: u64, pid: u32, number: u32, args: [u64; 6], listener: RawFd) -> Self {
+        Self {
+            id,
+            pid,
+            number,
+            args,
+            listener,
+        }
+    }
+
+    /// The thread making the syscall, in the pid namespace of the supervisor
+    pub fn pid(&self) -> u32 {
+        self.pid
+    }
+
+    pub fn number(&self) -> u32 {
+        self.number
+    }
+
+    /// The name of the syscall on the native architecture, such as `openat`
+    pub fn name(&self) -> Option<&'static str> {
+        syscalls::name(self.number)
+    }
+
+    pub fn args(&self) -> [u64; 6] {
+        self.args
+    }
+
+    /// Reads the path at `address` in the memory of the command, such as the second argument
+    /// of `openat`. Another thread of the command can still change it before the syscall runs
+    /// on `SeccompResponse::Continue`, so only a single threaded command can not get around it.
+    pub fn read_path(&self, address: u64) -> io::Result<PathBuf> {
+        let mem = File::open(format!("/proc/{}/mem", self.pid))?;
+        // The pid may have been reused by now, unless the syscall is still waiting for us
+        if !ffi::seccomp_notification_valid(self.listener, self.id) {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "the syscall is gone",
+            ));
+        }
+
+        let mut path = Vec::new();
+        let mut buffer = [0u8; 256];
+        while path.len() < libc::PATH_MAX as usize {
+            let len = mem.read_at(&mut buffer, address + path.len() as u64)?;
+            if len == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            if let Some(end) = buffer[..len].iter().position(|&byte| byte == 0) {
+                path.extend_from_slice(&buffer[..end]);
+                return Ok(PathBuf::from(OsString::from_vec(path)));
+            }
+            path.extend_from_slice(&buffer[..len]);
+        }
+        Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "the path is too long",
+        ))
+    }
+}
+
+/// The answer of a `SeccompNotifyHandler`
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum SeccompResponse {
+    /// Runs the syscall as if there was no filter
+    Continue,
+    /// Fails the syscall with this errno without running it
+    Errno(i32),
+    /// Returns this value without running the syscall
+    Return(i64),
+}
+
+/// Decides on the syscalls a `SeccompPolicy` passes on to the supervisor (see
+/// `SeccompPolicy::set_notify`), one at a time on a thread of its own. The supervisor is a
+/// forked copy of the caller, state shared with the caller does not get back to it.
+#[derive(Clone)]
+pub struct SeccompNotifyHandler(Arc<dyn Fn(SeccompNotification) -> SeccompResponse + Send + Sync>);
+
+impl SeccompNotifyHandler {
+    pub fn new<F>(handler: F) -> Self
+    where
+        F: Fn(SeccompNotification) -> SeccompResponse + Send + Sync + 'static,
+    {
+        Self(Arc::new(handler))
+    }
+
+    pub(crate) fn handle(&self, notification: SeccompNotification) -> SeccompResponse {
+        (self.0)(notification)
+    }
+}
+
+impl Debug for SeccompNotifyHandler {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "SeccompNotifyHandler")
+    }
+}
+
+// Only the very same handler compares equal, closures can not be compared otherwise
+impl PartialEq for SeccompNotifyHandler {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for SeccompNotifyHandler {}
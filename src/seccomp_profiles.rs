@@ -0,0 +1,130 @@
+//! Seccomp policies known to run the usual judge languages, killing on everything else. They
+//! are a starting point: `seccomp_profiles::cpp().allow("ioctl")` gives a solution more, and
+//! `SeccompPolicy::deny` less. Syscalls the native architecture does not have (such as `open`
+//! on aarch64) are left out.
+
+use config::{SeccompAction, SeccompPolicy};
+use syscalls;
+
+// What the C library needs to start, allocate, do I/O on the open descriptors, read the clock
+// and exit. Threads are allowed (the pids limit caps them, as it does processes).
+const BASE: &[&str] = &[
+    "access",
+    "arch_prctl",
+    "brk",
+    "clock_getres",
+    "clock_gettime",
+    "clock_nanosleep",
+    "clone",
+    "clone3",
+    "close",
+    "exit",
+    "exit_group",
+    "faccessat",
+    "faccessat2",
+    "fstat",
+    "futex",
+    "getegid",
+    "geteuid",
+    "getgid",
+    "getpid",
+    "getrandom",
+    "gettid",
+    "gettimeofday",
+    "getuid",
+    "lseek",
+    "lstat",
+    "madvise",
+    "membarrier",
+    "mmap",
+    "mprotect",
+    "mremap",
+    "munmap",
+    "nanosleep",
+    "newfstatat",
+    "open",
+    "openat",
+    "pread64",
+    "prlimit64",
+    "read",
+    "readlink",
+    "readlinkat",
+    "readv",
+    "rseq",
+    "rt_sigaction",
+    "rt_sigprocmask",
+    "rt_sigreturn",
+    "sched_getaffinity",
+    "sched_yield",
+    "set_robust_list",
+    "set_tid_address",
+    "sigaltstack",
+    "stat",
+    "statx",
+    "time",
+    "uname",
+    "write",
+    "writev",
+];
+
+// The interpreter looks around its library directories and the terminal on startup. Without
+// HOME its site module looks the user up, through an unix socket to nscd like the JVM below.
+const PYTHON3: &[&str] = &[
+    "connect",
+    "dup",
+    "dup2",
+    "dup3",
+    "fcntl",
+    "getcwd",
+    "getdents64",
+    "ioctl",
+    "socket",
+    "sysinfo",
+];
+
+// Besides its threads, the JVM keeps its performance counters in a locked file under
+// /tmp/hsperfdata_<user> (unless run with -XX:-UsePerfData) and looks the user up. The network
+// namespace of the sandbox keeps the sockets local.
+const JVM: &[&str] = &[
+    "connect",
+    "fchdir",
+    "fcntl",
+    "flock",
+    "ftruncate",
+    "getcwd",
+    "getdents64",
+    "getrusage",
+    "ioctl",
+    "mkdir",
+    "prctl",
+    "socket",
+    "sysinfo",
+    "tgkill",
+    "unlink",
+];
+
+fn profile(extra: &[&str]) -> SeccompPolicy {
+    SeccompPolicy::new(
+        BASE.iter()
+            .chain(extra)
+            .filter(|&&syscall| syscalls::number(syscall).is_some())
+            .map(|&syscall| syscall.to_owned())
+            .collect(),
+        SeccompAction::Kill,
+    )
+}
+
+/// Native programs linked against glibc, such as compiled C and C++ solutions
+pub fn cpp() -> SeccompPolicy {
+    profile(&[])
+}
+
+/// The reference Python 3 interpreter, given the script to run
+pub fn python3() -> SeccompPolicy {
+    profile(PYTHON3)
+}
+
+/// The Java virtual machine running a class, with its default garbage collector
+pub fn jvm() -> SeccompPolicy {
+    profile(JVM)
+}
@@ -0,0 +1,233 @@
+//! A reader for the uncompressed tar archives `RootImage::Tarball` unpacks, in the ustar, GNU
+//! and pax formats `tar` writes by default
+
+use std::ffi::OsStr;
+use std::io::{self, Read, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::path::PathBuf;
+use std::str;
+
+const BLOCK_SIZE: u64 = 512;
+
+/// What an entry of the archive makes
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub(crate) enum EntryKind {
+    File,
+    Directory,
+    Symlink(PathBuf),
+    /// A link to an earlier entry, at this path in the archive
+    HardLink(PathBuf),
+    /// Devices and FIFOs, which are left out
+    Other,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub(crate) struct Entry {
+    path: PathBuf,
+    kind: EntryKind,
+    mode: u32,
+}
+
+impl Entry {
+    /// As written in the archive, usually relative (`./bin/sh`)
+    pub(crate) fn path(&self) -> &PathBuf {
+        &self.path
+    }
+
+    pub(crate) fn kind(&self) -> &EntryKind {
+        &self.kind
+    }
+
+    /// The permission bits
+    pub(crate) fn mode(&self) -> u32 {
+        self.mode
+    }
+}
+
+pub(crate) struct Archive<R> {
+    reader: R,
+    // The data of the last entry not read yet, and the padding after it
+    remaining: u64,
+    padding: u64,
+}
+
+impl<R: Read> Archive<R> {
+    pub(crate) fn new(reader: R) -> Self {
+        Self {
+            reader,
+            remaining: 0,
+            padding: 0,
+        }
+    }
+
+    /// The next entry, skipping what is left of the data of the last one. `None` at the end of
+    /// the archive.
+    pub(crate) fn next_entry(&mut self) -> io::Result<Option<Entry>> {
+        let mut long_path = None;
+        let mut long_link = None;
+        loop {
+            self.skip_data()?;
+            let mut header = [0u8; BLOCK_SIZE as usize];
+            self.reader.read_exact(&mut header)?;
+            if header.iter().all(|&byte| byte == 0) {
+                return Ok(None);
+            }
+            if !checksum_matches(&header) {
+                return Err(invalid_data("bad header checksum"));
+            }
+
+            let size = parse_number(&header[124..136])?;
+            self.remaining = size;
+            self.padding = (BLOCK_SIZE - size % BLOCK_SIZE) % BLOCK_SIZE;
+            match header[156] {
+                // GNU long names, for the entry right after
+                b'L' => long_path = Some(self.read_name()?),
+                b'K' => long_link = Some(self.read_name()?),
+                // pax extended headers, only the paths matter here
+                b'x' => {
+                    let mut records = Vec::new();
+                    self.copy_data(&mut records)?;
+                    for (key, value) in parse_pax_records(&records)? {
+                        match key {
+                            b"path" => long_path = Some(path_from_bytes(value)),
+                            b"linkpath" => long_link = Some(path_from_bytes(value)),
+                            _ => {}
+                        }
+                    }
+                }
+                b'g' => {}
+                typeflag => {
+                    let path = long_path.take().unwrap_or_else(|| header_path(&header));
+                    let mut link = || {
+                        long_link
+                            .take()
+                            .unwrap_or_else(|| path_from_bytes(until_nul(&header[157..257])))
+                    };
+                    let kind = match typeflag {
+                        b'0' | b'\0' | b'7' => EntryKind::File,
+                        b'1' => EntryKind::HardLink(link()),
+                        b'2' => EntryKind::Symlink(link()),
+                        b'5' => EntryKind::Directory,
+                        _ => EntryKind::Other,
+                    };
+                    return Ok(Some(Entry {
+                        path,
+                        kind,
+                        mode: parse_number(&header[100..108])? as u32 & 0o7777,
+                    }));
+                }
+            }
+        }
+    }
+
+    /// Copies the data of the last entry
+    pub(crate) fn copy_data<W: Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        let remaining = self.remaining;
+        let copied = io::copy(&mut (&mut self.reader).take(remaining), writer)?;
+        self.remaining -= copied;
+        if self.remaining > 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        Ok(())
+    }
+
+    fn skip_data(&mut self) -> io::Result<()> {
+        self.copy_data(&mut io::sink())?;
+        let padding = self.padding;
+        self.padding = 0;
+        if io::copy(&mut (&mut self.reader).take(padding), &mut io::sink())? < padding {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        Ok(())
+    }
+
+    fn read_name(&mut self) -> io::Result<PathBuf> {
+        let mut name = Vec::new();
+        self.copy_data(&mut name)?;
+        Ok(path_from_bytes(until_nul(&name)))
+    }
+}
+
+fn invalid_data(error: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+fn until_nul(bytes: &[u8]) -> &[u8] {
+    match bytes.iter().position(|&byte| byte == 0) {
+        Some(end) => &bytes[..end],
+        None => bytes,
+    }
+}
+
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(OsStr::from_bytes(bytes))
+}
+
+// The ustar prefix field is something else in the GNU format
+fn header_path(header: &[u8]) -> PathBuf {
+    let name = until_nul(&header[0..100]);
+    if &header[257..263] == b"ustar\0" {
+        let prefix = until_nul(&header[345..500]);
+        if !prefix.is_empty() {
+            return path_from_bytes(prefix).join(path_from_bytes(name));
+        }
+    }
+    path_from_bytes(name)
+}
+
+// The checksum field counts as spaces
+fn checksum_matches(header: &[u8]) -> bool {
+    let sum: u64 = header
+        .iter()
+        .enumerate()
+        .map(|(index, &byte)| match index {
+            148..=155 => u64::from(b' '),
+            _ => u64::from(byte),
+        })
+        .sum();
+    parse_number(&header[148..156]).ok() == Some(sum)
+}
+
+// Octal, or big endian base-256 when the high bit is set (GNU, for large sizes)
+fn parse_number(field: &[u8]) -> io::Result<u64> {
+    if field[0] & 0x80 != 0 {
+        return Ok(field[1..]
+            .iter()
+            .fold(u64::from(field[0] & 0x7f), |number, &byte| {
+                number << 8 | u64::from(byte)
+            }));
+    }
+    let digits = until_nul(field);
+    let digits = str::from_utf8(digits)
+        .map_err(|_| invalid_data("bad number in header"))?
+        .trim_matches(' ');
+    if digits.is_empty() {
+        return Ok(0);
+    }
+    u64::from_str_radix(digits, 8).map_err(|_| invalid_data("bad number in header"))
+}
+
+// Records are `<length> <key>=<value>\n`, the length counting the whole record
+fn parse_pax_records(mut records: &[u8]) -> io::Result<Vec<(&[u8], &[u8])>> {
+    let bad_record = || invalid_data("bad pax record");
+    let mut parsed = Vec::new();
+    while !records.is_empty() {
+        let space = records
+            .iter()
+            .position(|&byte| byte == b' ')
+            .ok_or_else(bad_record)?;
+        let length: usize = str::from_utf8(&records[..space])
+            .ok()
+            .and_then(|length| length.parse().ok())
+            .filter(|&length| length > space + 1 && length <= records.len())
+            .ok_or_else(bad_record)?;
+        let record = &records[space + 1..length - 1];
+        let equals = record
+            .iter()
+            .position(|&byte| byte == b'=')
+            .ok_or_else(bad_record)?;
+        parsed.push((&record[..equals], &record[equals + 1..]));
+        records = &records[length..];
+    }
+    Ok(parsed)
+}
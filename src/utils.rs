@@ -6,7 +6,8 @@ pub struct DurationDisplay(pub Duration);
 
 impl Display for DurationDisplay {
     fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
-        let nanos = self.0.as_secs() * 1_000_000_000u64 + u64::from(self.0.subsec_nanos());
+        let nanos =
+            u128::from(self.0.as_secs()) * 1_000_000_000 + u128::from(self.0.subsec_nanos());
         if nanos < 1000 {
             write!(formatter, "{}ns", nanos)
         } else if nanos < 10_000_000 {
@@ -28,3 +29,321 @@ impl DurationExt for Duration {
         u128::from(self.as_secs()) * 1000 + u128::from(self.subsec_nanos()) / 1_000_000
     }
 }
+
+// The representations of `RunInfo` for people (JSON): durations in nanoseconds and signals
+// with their names. Records from before take the shapes they had then. Binary formats (the
+// supervisor sends the `RunInfo` back through bincode) keep the derived ones.
+pub(crate) mod duration_nanos {
+    use std::time::Duration;
+
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum HumanDuration {
+        Nanos(u64),
+        // `{ "secs": .., "nanos": .. }`
+        Derived(Duration),
+    }
+
+    // Saturates at about 584 years
+    pub(crate) fn to_nanos(duration: Duration) -> u64 {
+        let nanos = duration.as_nanos();
+        if nanos > u128::from(u64::MAX) {
+            u64::MAX
+        } else {
+            nanos as u64
+        }
+    }
+
+    pub(crate) fn serialize<S: Serializer>(
+        duration: &Duration,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.serialize_u64(to_nanos(*duration))
+        } else {
+            duration.serialize(serializer)
+        }
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Duration, D::Error> {
+        if deserializer.is_human_readable() {
+            Ok(match HumanDuration::deserialize(deserializer)? {
+                HumanDuration::Nanos(nanos) => Duration::from_nanos(nanos),
+                HumanDuration::Derived(duration) => duration,
+            })
+        } else {
+            Duration::deserialize(deserializer)
+        }
+    }
+}
+
+// `duration_nanos` for each of a list that may be missing
+pub(crate) mod optional_durations_nanos {
+    use std::time::Duration;
+
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    #[derive(Serialize, Deserialize)]
+    struct Nanos(#[serde(with = "super::duration_nanos")] Duration);
+
+    pub(crate) fn serialize<S: Serializer>(
+        durations: &Option<Vec<Duration>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        durations
+            .as_ref()
+            .map(|durations| {
+                durations
+                    .iter()
+                    .map(|&duration| Nanos(duration))
+                    .collect::<Vec<_>>()
+            })
+            .serialize(serializer)
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Vec<Duration>>, D::Error> {
+        Ok(
+            Option::<Vec<Nanos>>::deserialize(deserializer)?.map(|durations| {
+                durations
+                    .into_iter()
+                    .map(|Nanos(duration)| duration)
+                    .collect()
+            }),
+        )
+    }
+}
+
+// `duration_nanos` for a duration that may be missing
+pub(crate) mod optional_duration_nanos {
+    use std::time::Duration;
+
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    #[derive(Serialize, Deserialize)]
+    struct Nanos(#[serde(with = "super::duration_nanos")] Duration);
+
+    pub(crate) fn serialize<S: Serializer>(
+        duration: &Option<Duration>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        duration.map(Nanos).serialize(serializer)
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Duration>, D::Error> {
+        Ok(Option::<Nanos>::deserialize(deserializer)?.map(|Nanos(duration)| duration))
+    }
+}
+
+// A time that may be missing as RFC 3339, in UTC with nanoseconds:
+// `2023-11-14T22:13:20.123456789Z`. Any offset is read back.
+pub(crate) mod optional_rfc3339 {
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    use serde::de::Error as DeError;
+    use serde::ser::Error as SerError;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    const SECS_PER_DAY: u64 = 24 * 60 * 60;
+
+    // The date of a day since 1970-01-01, after
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    fn civil_from_days(days: u64) -> (u64, u64, u64) {
+        let days = days + 719_468;
+        let era = days / 146_097;
+        let day_of_era = days - era * 146_097;
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let month_index = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+        let month = if month_index < 10 {
+            month_index + 3
+        } else {
+            month_index - 9
+        };
+        let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+        (year, month, day)
+    }
+
+    // The other way around, `None` before 1970
+    fn days_from_civil(year: u64, month: u64, day: u64) -> Option<u64> {
+        let year = if month <= 2 {
+            year.checked_sub(1)?
+        } else {
+            year
+        };
+        let era = year / 400;
+        let year_of_era = year - era * 400;
+        let month_index = if month > 2 { month - 3 } else { month + 9 };
+        let day_of_year = (153 * month_index + 2) / 5 + day - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        (era * 146_097 + day_of_era).checked_sub(719_468)
+    }
+
+    pub(crate) fn format(time: SystemTime) -> Option<String> {
+        let since_epoch = time.duration_since(UNIX_EPOCH).ok()?;
+        let secs = since_epoch.as_secs();
+        let (year, month, day) = civil_from_days(secs / SECS_PER_DAY);
+        let secs_of_day = secs % SECS_PER_DAY;
+        Some(format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:09}Z",
+            year,
+            month,
+            day,
+            secs_of_day / 3600,
+            secs_of_day / 60 % 60,
+            secs_of_day % 60,
+            since_epoch.subsec_nanos()
+        ))
+    }
+
+    fn number(string: &str) -> Option<u64> {
+        if string.is_empty() || !string.bytes().all(|byte| byte.is_ascii_digit()) {
+            return None;
+        }
+        string.parse().ok()
+    }
+
+    pub(crate) fn parse(string: &str) -> Option<SystemTime> {
+        let string = string.to_ascii_uppercase();
+        let (date, time) = (string.get(..10)?, string.get(10..)?);
+        if date.as_bytes()[4] != b'-' || date.as_bytes()[7] != b'-' || !time.starts_with('T') {
+            return None;
+        }
+        let (year, month, day) = (
+            number(&date[..4])?,
+            number(&date[5..7])?,
+            number(&date[8..])?,
+        );
+        let time = &time[1..];
+        let (hms, rest) = (time.get(..8)?, time.get(8..)?);
+        if hms.as_bytes()[2] != b':' || hms.as_bytes()[5] != b':' {
+            return None;
+        }
+        let (hours, minutes, seconds) =
+            (number(&hms[..2])?, number(&hms[3..5])?, number(&hms[6..])?);
+        if !(1..=12).contains(&month) || !(1..=31).contains(&day) || hours > 23 || minutes > 59 {
+            return None;
+        }
+        // A leap second is folded into the next one
+        if seconds > 60 {
+            return None;
+        }
+
+        let (nanos, offset) = match rest.strip_prefix('.') {
+            Some(rest) => {
+                let digits = rest.find(|c: char| !c.is_ascii_digit())?;
+                // Past nanoseconds the digits are dropped
+                let fraction = &rest[..digits.min(9)];
+                let nanos = number(fraction)? * 10u64.pow(9 - fraction.len() as u32);
+                (nanos, &rest[digits..])
+            }
+            None => (0, rest),
+        };
+        // Seconds east of UTC
+        let offset = match offset {
+            "Z" => 0,
+            _ if offset.len() == 6 && offset.as_bytes()[3] == b':' => {
+                let offset_secs = number(&offset[1..3])? * 3600 + number(&offset[4..])? * 60;
+                match offset.as_bytes()[0] {
+                    b'+' => offset_secs as i64,
+                    b'-' => -(offset_secs as i64),
+                    _ => return None,
+                }
+            }
+            _ => return None,
+        };
+
+        let local_secs = days_from_civil(year, month, day)? * SECS_PER_DAY
+            + hours * 3600
+            + minutes * 60
+            + seconds;
+        let secs = (local_secs as i64).checked_sub(offset)?;
+        if secs < 0 {
+            return None;
+        }
+        Some(UNIX_EPOCH + Duration::new(secs as u64, nanos as u32))
+    }
+
+    pub(crate) fn serialize<S: Serializer>(
+        time: &Option<SystemTime>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            match *time {
+                Some(time) => format(time)
+                    .ok_or_else(|| S::Error::custom("time before 1970"))?
+                    .serialize(serializer),
+                None => serializer.serialize_none(),
+            }
+        } else {
+            time.serialize(serializer)
+        }
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<SystemTime>, D::Error> {
+        if deserializer.is_human_readable() {
+            match Option::<String>::deserialize(deserializer)? {
+                Some(time) => parse(&time)
+                    .map(Some)
+                    .ok_or_else(|| D::Error::custom(format!("invalid RFC 3339 time {}", time))),
+                None => Ok(None),
+            }
+        } else {
+            Option::<SystemTime>::deserialize(deserializer)
+        }
+    }
+}
+
+// A signal as `{ "number": 9, "name": "SIGKILL" }`, read back from just the number as well
+pub(crate) mod signal {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use run_info::Signal;
+
+    #[derive(Serialize, Deserialize)]
+    struct NamedSignal {
+        number: u32,
+        name: Option<String>,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum HumanSignal {
+        Named(NamedSignal),
+        Number(u32),
+    }
+
+    pub(crate) fn serialize<S: Serializer>(signal: &u32, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            NamedSignal {
+                number: *signal,
+                name: Signal::new(*signal).name(),
+            }
+            .serialize(serializer)
+        } else {
+            signal.serialize(serializer)
+        }
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+        if deserializer.is_human_readable() {
+            Ok(match HumanSignal::deserialize(deserializer)? {
+                HumanSignal::Named(signal) => signal.number,
+                HumanSignal::Number(number) => number,
+            })
+        } else {
+            u32::deserialize(deserializer)
+        }
+    }
+}
@@ -0,0 +1,64 @@
+use std::borrow::Cow;
+use std::fmt::{self, Display, Formatter};
+
+use run_info::CLASSIFICATION_VERSION;
+
+include!(concat!(env!("OUT_DIR"), "/version_info.rs"));
+
+/// The build of ia-sandbox that is running, embedded in every `RunInfo` it produces
+pub const VERSION_INFO: VersionInfo = VersionInfo {
+    version: Cow::Borrowed(env!("CARGO_PKG_VERSION")),
+    git_hash: GIT_HASH,
+    features: Cow::Borrowed(FEATURES),
+    classification_version: CLASSIFICATION_VERSION,
+};
+
+/// Identifies a build of ia-sandbox, either the running one (`VERSION_INFO`) or the one that
+/// produced a deserialized `RunInfo`
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct VersionInfo {
+    version: Cow<'static, str>,
+    git_hash: Option<Cow<'static, str>>,
+    features: Cow<'static, [Cow<'static, str>]>,
+    // 0 for producers from before it was recorded
+    #[serde(default)]
+    classification_version: u32,
+}
+
+impl VersionInfo {
+    /// The crate version
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+
+    /// The abbreviated git commit, if it was built from a git checkout (or the hash was given
+    /// through the `IA_SANDBOX_GIT_HASH` environment variable)
+    pub fn git_hash(&self) -> Option<&str> {
+        self.git_hash.as_ref().map(|hash| hash.as_ref())
+    }
+
+    /// The enabled cargo features, sorted
+    pub fn features(&self) -> impl Iterator<Item = &str> {
+        self.features.iter().map(|feature| feature.as_ref())
+    }
+
+    /// The `CLASSIFICATION_VERSION` of the build, 0 for builds from before it was recorded
+    pub fn classification_version(&self) -> u32 {
+        self.classification_version
+    }
+}
+
+// Formatted as `ia-sandbox 0.2.0 (0123456789ab) [integration-test, nightly]`, with the hash and
+// the features left out when missing
+impl Display for VersionInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "ia-sandbox {}", self.version)?;
+        if let Some(git_hash) = self.git_hash() {
+            write!(f, " ({})", git_hash)?;
+        }
+        if !self.features.is_empty() {
+            write!(f, " [{}]", self.features().collect::<Vec<_>>().join(", "))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,11 @@
+use std::thread;
+use std::time::Duration;
+
+fn main() {
+    // Touch 10MB more every millisecond until the kernel (or the sandbox) steps in
+    let mut chunks = Vec::new();
+    loop {
+        chunks.push(vec![1u8; 10 << 20]);
+        thread::sleep(Duration::from_millis(1));
+    }
+}
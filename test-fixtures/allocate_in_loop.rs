@@ -0,0 +1,7 @@
+fn main() {
+    // Keep touching fresh memory until the kernel steps in
+    let mut chunks = Vec::new();
+    loop {
+        chunks.push(vec![1u8; 1 << 20]);
+    }
+}
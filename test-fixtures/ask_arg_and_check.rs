@@ -0,0 +1,15 @@
+use std::env;
+use std::io::{self, stdin};
+use std::process;
+
+fn main() {
+    // Send the last argument, expect it back
+    let question = env::args().next_back().unwrap();
+    println!("{}", question);
+
+    let mut line = String::new();
+    stdin().read_line(&mut line).unwrap();
+    // Whatever else the solution writes after the answer must not hit a closed pipe
+    let _ = io::copy(&mut stdin(), &mut io::sink()).unwrap();
+    process::exit(if line.trim() == question { 0 } else { 1 });
+}
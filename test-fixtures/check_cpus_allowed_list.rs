@@ -0,0 +1,18 @@
+use std::env;
+use std::fs;
+use std::process;
+
+fn main() {
+    let expected = env::args().last().unwrap();
+    let status = fs::read_to_string("/proc/self/status").unwrap();
+    let cpus_allowed_list = status
+        .lines()
+        .find(|line| line.starts_with("Cpus_allowed_list:"))
+        .unwrap()
+        .trim_start_matches("Cpus_allowed_list:")
+        .trim();
+
+    if cpus_allowed_list != expected {
+        process::exit(1);
+    }
+}
@@ -0,0 +1,15 @@
+extern crate libc;
+
+use std::env;
+use std::mem;
+use std::process;
+
+// Fails if the file descriptor given last is open, like one the caller forgot to make
+// close-on-exec
+fn main() {
+    let fd = env::args().last().unwrap().parse().unwrap();
+    let mut stat: libc::stat = unsafe { mem::zeroed() };
+    if unsafe { libc::fstat(fd, &mut stat) } == 0 {
+        process::exit(1);
+    }
+}
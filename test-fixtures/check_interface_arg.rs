@@ -0,0 +1,18 @@
+use std::env;
+use std::fs;
+use std::process;
+
+fn main() {
+    let interface = env::args().last().unwrap();
+    let net_dev = fs::read_to_string("/proc/self/net/dev").unwrap();
+    // Past the two header lines, every line starts with `name:`
+    let found = net_dev
+        .lines()
+        .skip(2)
+        .filter_map(|line| line.split(':').next())
+        .any(|name| name.trim() == interface);
+
+    if !found {
+        process::exit(1);
+    }
+}
@@ -0,0 +1,10 @@
+extern crate libc;
+
+use std::thread;
+use std::time::Duration;
+
+fn main() {
+    // The writer on the other end should get EPIPE right away, not once this exits
+    unsafe { libc::close(libc::STDIN_FILENO) };
+    thread::sleep(Duration::from_secs(1));
+}
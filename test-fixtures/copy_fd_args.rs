@@ -0,0 +1,14 @@
+use std::env;
+use std::fs::File;
+use std::io;
+use std::os::unix::io::FromRawFd;
+
+// Copies everything from the file descriptor given before last to the one given last, like a
+// submission talking to an interactor over fds 3 and 4
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let (input, output) = (&args[args.len() - 2], &args[args.len() - 1]);
+    let mut input = unsafe { File::from_raw_fd(input.parse().unwrap()) };
+    let mut output = unsafe { File::from_raw_fd(output.parse().unwrap()) };
+    let _ = io::copy(&mut input, &mut output).unwrap();
+}
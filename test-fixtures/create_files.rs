@@ -0,0 +1,23 @@
+extern crate libc;
+
+use std::env;
+use std::ffi::CString;
+use std::os::unix::ffi::OsStrExt;
+
+// Prints the errno of creating each of the arguments, 0 if it was created
+fn main() {
+    let errnos: Vec<String> = env::args_os()
+        .skip(1)
+        .map(|path| {
+            let path = CString::new(path.as_bytes()).unwrap();
+            let flags = libc::O_WRONLY | libc::O_CREAT | libc::O_EXCL;
+            if unsafe { libc::open(path.as_ptr(), flags, 0o644) } == -1 {
+                std::io::Error::last_os_error().raw_os_error().unwrap()
+            } else {
+                0
+            }
+        })
+        .map(|errno| errno.to_string())
+        .collect();
+    println!("{}", errnos.join(" "));
+}
@@ -0,0 +1,11 @@
+extern crate libc;
+
+use std::process;
+
+// Fails if the segment was left behind by an earlier run in the same IPC namespace
+const KEY: libc::key_t = 0x1a5a_0293;
+
+fn main() {
+    let id = unsafe { libc::shmget(KEY, 4096, libc::IPC_CREAT | libc::IPC_EXCL | 0o600) };
+    process::exit(if id == -1 { 1 } else { 0 });
+}
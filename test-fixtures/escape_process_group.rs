@@ -0,0 +1,14 @@
+extern crate libc;
+
+use std::thread;
+use std::time::Duration;
+
+fn main() {
+    // The child leaves the process group, only the pid namespace or the cgroup can kill it
+    if unsafe { libc::fork() } == 0 {
+        let _ = unsafe { libc::setsid() };
+    }
+    loop {
+        thread::sleep(Duration::from_secs(1));
+    }
+}
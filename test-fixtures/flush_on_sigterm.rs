@@ -0,0 +1,22 @@
+extern crate libc;
+
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+static TERMINATED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn set_terminated(_: libc::c_int) {
+    TERMINATED.store(true, Ordering::SeqCst);
+}
+
+// Shuts down cleanly on SIGTERM, writing what it has yet to write and exiting with 0
+fn main() {
+    let handler = set_terminated as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    unsafe { libc::signal(libc::SIGTERM, handler) };
+    while !TERMINATED.load(Ordering::SeqCst) {
+        thread::sleep(Duration::from_millis(10));
+    }
+    io::stdout().write_all(b"flushed\n").unwrap();
+}
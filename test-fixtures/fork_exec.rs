@@ -0,0 +1,20 @@
+extern crate libc;
+
+use std::ffi::CString;
+use std::ptr;
+
+// Runs /bin/true in a child and exits with its status
+fn main() {
+    let path = CString::new("/bin/true").unwrap();
+    let argv = [path.as_ptr(), ptr::null()];
+    unsafe {
+        let pid = libc::fork();
+        if pid == 0 {
+            libc::execv(path.as_ptr(), argv.as_ptr());
+            libc::_exit(127);
+        }
+        let mut status = 0;
+        libc::waitpid(pid, &mut status, 0);
+        std::process::exit(libc::WEXITSTATUS(status));
+    }
+}
@@ -0,0 +1,12 @@
+extern crate libc;
+
+use std::process;
+
+// Succeeds only when the standard input is a terminal
+fn main() {
+    if unsafe { libc::isatty(0) } == 1 {
+        process::exit(0);
+    } else {
+        process::exit(1);
+    }
+}
@@ -0,0 +1,14 @@
+use std::io::{self, Write};
+use std::process;
+
+// Like a failed assertion: plenty of output, the message last. The init of a pid namespace
+// does not get the signals it raises, run it with the pid namespace shared
+fn main() {
+    let stderr = io::stderr();
+    let mut stderr = stderr.lock();
+    for line in 0..1_000 {
+        writeln!(stderr, "line {}", line).unwrap();
+    }
+    writeln!(stderr, "the last words before the abort").unwrap();
+    process::abort();
+}
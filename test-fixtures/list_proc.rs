@@ -0,0 +1,23 @@
+use std::env;
+use std::fs::{self, File};
+use std::io::Read;
+
+// Prints the entries of /proc, then for each of the arguments how many bytes a read of it gives
+// (or the errno opening it)
+fn main() {
+    let mut entries: Vec<String> = fs::read_dir("/proc")
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    entries.sort();
+    println!("{}", entries.join(" "));
+
+    let reads: Vec<String> = env::args_os()
+        .skip(1)
+        .map(|path| match File::open(path) {
+            Ok(mut file) => file.read(&mut [0u8; 4096]).unwrap().to_string(),
+            Err(error) => format!("errno {}", error.raw_os_error().unwrap()),
+        })
+        .collect();
+    println!("{}", reads.join(" "));
+}
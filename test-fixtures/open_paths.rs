@@ -0,0 +1,22 @@
+extern crate libc;
+
+use std::env;
+use std::ffi::CString;
+use std::os::unix::ffi::OsStrExt;
+
+// Prints the errno of opening each of the arguments, 0 if it opened
+fn main() {
+    let errnos: Vec<String> = env::args_os()
+        .skip(1)
+        .map(|path| {
+            let path = CString::new(path.as_bytes()).unwrap();
+            if unsafe { libc::open(path.as_ptr(), libc::O_RDONLY) } == -1 {
+                std::io::Error::last_os_error().raw_os_error().unwrap()
+            } else {
+                0
+            }
+        })
+        .map(|errno| errno.to_string())
+        .collect();
+    println!("{}", errnos.join(" "));
+}
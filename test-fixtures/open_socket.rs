@@ -0,0 +1,6 @@
+extern crate libc;
+
+fn main() {
+    let fd = unsafe { libc::socket(libc::AF_INET, libc::SOCK_STREAM, 0) };
+    println!("{}", fd);
+}
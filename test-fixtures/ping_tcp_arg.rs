@@ -0,0 +1,12 @@
+use std::env;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+
+fn main() {
+    let address = env::args().last().unwrap();
+    let mut stream = TcpStream::connect(address).unwrap();
+    stream.write_all(b"ping").unwrap();
+    let mut pong = [0; 4];
+    stream.read_exact(&mut pong).unwrap();
+    assert_eq!(&pong, b"pong");
+}
@@ -0,0 +1,12 @@
+use std::env;
+use std::io::{self, Write};
+
+// Writes the number of kilobytes given last to stdout, more than a pipe holds, and then a line
+// to stderr
+fn main() {
+    let kilobytes: usize = env::args().last().unwrap().parse().unwrap();
+    io::stdout()
+        .write_all(&vec![b'a'; kilobytes * 1_024])
+        .unwrap();
+    eprintln!("Done");
+}
@@ -0,0 +1,12 @@
+use std::thread;
+use std::time::Duration;
+
+// Prints 5 lines, 100ms apart, like a long submission showing its progress
+fn main() {
+    for line in 0..5 {
+        if line > 0 {
+            thread::sleep(Duration::from_millis(100));
+        }
+        println!("line {}", line);
+    }
+}
@@ -0,0 +1,13 @@
+use std::io::{self, Write};
+
+// Alternates between the two streams, flushing each line so they interleave in order
+fn main() {
+    let (stdout, stderr) = (io::stdout(), io::stderr());
+    let (mut stdout, mut stderr) = (stdout.lock(), stderr.lock());
+    writeln!(stdout, "stdout 1").unwrap();
+    stdout.flush().unwrap();
+    writeln!(stderr, "stderr 1").unwrap();
+    writeln!(stdout, "stdout 2").unwrap();
+    stdout.flush().unwrap();
+    writeln!(stderr, "stderr 2").unwrap();
+}
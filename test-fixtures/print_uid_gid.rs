@@ -0,0 +1,7 @@
+extern crate libc;
+
+fn main() {
+    unsafe {
+        println!("{} {}", libc::getuid(), libc::getgid());
+    }
+}
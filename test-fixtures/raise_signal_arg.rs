@@ -0,0 +1,15 @@
+extern crate libc;
+
+use std::env;
+use std::process;
+
+// The init of a pid namespace does not get the signals it raises, run it with the pid
+// namespace shared
+fn main() {
+    let signal = env::args().nth(1).unwrap().trim().parse().unwrap();
+    unsafe {
+        libc::signal(signal, libc::SIG_DFL);
+        libc::raise(signal);
+    }
+    process::exit(1);
+}
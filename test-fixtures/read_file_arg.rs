@@ -0,0 +1,12 @@
+use std::env;
+use std::fs::File;
+use std::io::Read;
+
+fn main() {
+    let path = env::args().last().unwrap();
+
+    // Read through a small buffer, only the page cache grows with the file
+    let mut file = File::open(path).unwrap();
+    let mut buffer = vec![0u8; 64 * 1024];
+    while file.read(&mut buffer).unwrap() > 0 {}
+}
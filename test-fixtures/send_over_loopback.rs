@@ -0,0 +1,37 @@
+use std::env;
+use std::io::{self, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::thread;
+use std::time::Duration;
+
+// Sends the given megabytes to a listener of its own on loopback, or to the address given before
+// them (`[address] megabytes`)
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let megabytes: usize = args.last().unwrap().parse().unwrap();
+
+    let (address, receiver) = match args.len() {
+        1 => {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let address = listener.local_addr().unwrap();
+            let receiver = thread::spawn(move || {
+                let (mut stream, _) = listener.accept().unwrap();
+                io::copy(&mut stream, &mut io::sink()).unwrap()
+            });
+            (address, Some(receiver))
+        }
+        _ => (args[0].parse().unwrap(), None),
+    };
+
+    let mut stream = TcpStream::connect(address).unwrap();
+    for _ in 0..megabytes {
+        stream.write_all(&vec![1; 1_000_000]).unwrap();
+    }
+    stream.shutdown(Shutdown::Write).unwrap();
+    if let Some(receiver) = receiver {
+        assert_eq!(receiver.join().unwrap(), megabytes as u64 * 1_000_000);
+    }
+
+    // Network usage is sampled, give the supervisor time to see all of it
+    thread::sleep(Duration::from_millis(100));
+}
@@ -0,0 +1,9 @@
+extern crate libc;
+
+use std::process;
+
+// Sends itself a SIGKILL, which does not reach the init of a pid namespace from in there
+fn main() {
+    unsafe { libc::kill(libc::getpid(), libc::SIGKILL) };
+    process::exit(3);
+}
@@ -0,0 +1,26 @@
+extern crate libc;
+
+use std::env;
+use std::io::{self, Write};
+use std::thread;
+use std::time::Duration;
+
+extern "C" fn exit_on_sigterm(_: libc::c_int) {
+    unsafe { libc::_exit(0) };
+}
+
+// As the init of its pid namespace, the process only gets the SIGTERM of the sandbox when it
+// handles it, a last argument of `ignore` ignores it explicitly and `exit` exits on it
+fn main() {
+    let handler = match env::args().last().as_ref().map(String::as_str) {
+        Some("exit") => exit_on_sigterm as extern "C" fn(libc::c_int) as libc::sighandler_t,
+        _ => libc::SIG_IGN,
+    };
+    unsafe { libc::signal(libc::SIGTERM, handler) };
+    // Written straight to the redirect, it must be there even though the process gets killed
+    io::stdout().write_all(b"partial\n").unwrap();
+    io::stdout().flush().unwrap();
+    loop {
+        thread::sleep(Duration::from_millis(10));
+    }
+}
@@ -0,0 +1,29 @@
+extern crate ia_sandbox;
+
+use std::env;
+use std::process;
+
+use ia_sandbox::config::Config;
+use ia_sandbox::errors::Error;
+
+// Succeeds if spawning a sandbox from in here is refused as nested, exits with 3 without trying
+// when not told it runs in one
+fn main() {
+    if env::var_os(ia_sandbox::INSIDE_SANDBOX_VAR).is_none() {
+        process::exit(3);
+    }
+    let config = Config::builder(env::current_exe().unwrap())
+        .build()
+        .unwrap();
+    match ia_sandbox::spawn_jail(&config) {
+        Err(Error::NestedSandboxUnsupported) => {}
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(1);
+        }
+        Ok(_) => {
+            eprintln!("Spawned a nested sandbox");
+            process::exit(2);
+        }
+    }
+}
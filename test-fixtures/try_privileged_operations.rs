@@ -0,0 +1,33 @@
+extern crate libc;
+
+use std::ffi::CString;
+use std::io;
+use std::ptr;
+
+// The errno of each attempt, 0 when it succeeded
+fn errno(res: libc::c_int) -> i32 {
+    if res == -1 {
+        io::Error::last_os_error().raw_os_error().unwrap()
+    } else {
+        0
+    }
+}
+
+fn main() {
+    let hostname = "ia-sandbox";
+    let sethostname = errno(unsafe { libc::sethostname(hostname.as_ptr() as *const _, 10) });
+
+    let tmpfs = CString::new("tmpfs").unwrap();
+    let root = CString::new("/").unwrap();
+    let mount = errno(unsafe {
+        libc::mount(
+            tmpfs.as_ptr(),
+            root.as_ptr(),
+            tmpfs.as_ptr(),
+            0,
+            ptr::null(),
+        )
+    });
+
+    println!("{} {}", sethostname, mount);
+}
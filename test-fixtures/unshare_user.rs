@@ -0,0 +1,15 @@
+extern crate libc;
+
+use std::thread;
+
+// Prints the errno of unsharing a user namespace, 0 if it did. A thread is started (and gone,
+// a process with threads can not unshare one) first to check that it still can be.
+fn main() {
+    thread::spawn(|| {}).join().unwrap();
+    let errno = if unsafe { libc::unshare(libc::CLONE_NEWUSER) } == -1 {
+        std::io::Error::last_os_error().raw_os_error().unwrap()
+    } else {
+        0
+    };
+    println!("{}", errno);
+}
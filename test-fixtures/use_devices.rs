@@ -0,0 +1,23 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::Path;
+
+// Writes to /dev/null, reads from /dev/urandom and checks the /dev/stdout link
+fn main() {
+    OpenOptions::new()
+        .write(true)
+        .open("/dev/null")
+        .unwrap()
+        .write_all(b"Hello null!\n")
+        .unwrap();
+    let mut random = [0u8; 16];
+    File::open("/dev/urandom")
+        .unwrap()
+        .read_exact(&mut random)
+        .unwrap();
+    assert_eq!(
+        fs::read_link("/dev/stdout").unwrap(),
+        Path::new("/proc/self/fd/1")
+    );
+    println!("Hello devices!");
+}
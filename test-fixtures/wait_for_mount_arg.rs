@@ -0,0 +1,22 @@
+use std::env;
+use std::fs::File;
+use std::path::Path;
+use std::process;
+use std::thread;
+use std::time::Duration;
+
+fn main() {
+    let directory = env::args().last().unwrap();
+    let directory = Path::new(&directory);
+    // Tell the host the sandbox is set up, it mounts under the directory once it sees this
+    File::create(directory.join("ready")).unwrap();
+
+    let mounted = directory.join("mounted").join("file");
+    for _ in 0..100 {
+        if mounted.exists() {
+            return;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    process::exit(1);
+}
@@ -0,0 +1,9 @@
+use std::fs::File;
+use std::io::Write;
+
+fn main() {
+    let mut file = File::create("/output").unwrap();
+    file.write_all(&vec![1; 2 * 1_024 * 1_024]).unwrap();
+    // Write it out to the disk now, instead of leaving it to writeback
+    file.sync_all().unwrap();
+}
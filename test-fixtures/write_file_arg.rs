@@ -0,0 +1,13 @@
+use std::env;
+use std::fs::{self, File};
+use std::io::Write;
+
+// Writes a line to the file given and prints it as read back
+fn main() {
+    let path = env::args().last().unwrap();
+    File::create(&path)
+        .unwrap()
+        .write_all(b"Hello file!\n")
+        .unwrap();
+    print!("{}", fs::read_to_string(&path).unwrap());
+}
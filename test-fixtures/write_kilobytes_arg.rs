@@ -0,0 +1,15 @@
+use std::env;
+use std::fs::File;
+use std::io::Write;
+
+// Writes the number of kilobytes given last to the file given before, failing when it does not
+// fit
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let (path, kilobytes) = (&args[args.len() - 2], &args[args.len() - 1]);
+    let kilobytes: usize = kilobytes.parse().unwrap();
+    File::create(path)
+        .unwrap()
+        .write_all(&vec![1; kilobytes * 1_024])
+        .unwrap();
+}
@@ -0,0 +1,17 @@
+use std::io::{self, ErrorKind, Write};
+use std::process;
+
+// Rust ignores SIGPIPE, so a closed reader shows up as EPIPE and the exit code tells it apart
+// from any other error
+fn main() {
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    let buffer = [b'A'; 4096];
+    loop {
+        match stdout.write_all(&buffer) {
+            Ok(()) => {}
+            Err(ref err) if err.kind() == ErrorKind::BrokenPipe => process::exit(32),
+            Err(_) => process::exit(1),
+        }
+    }
+}
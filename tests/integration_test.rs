@@ -1,24 +1,55 @@
 extern crate ia_sandbox;
 extern crate libc;
+extern crate serde_json;
 extern crate tempfile;
 
-use std::fs::File;
-use std::io::Write;
-use std::time::Duration;
+use std::cmp;
+use std::ffi::{CString, OsStr};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::net::{Ipv4Addr, Shutdown, TcpListener};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{symlink, PermissionsExt};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+use std::ptr;
+use std::thread;
+use std::time::{Duration, Instant, UNIX_EPOCH};
 
-use ia_sandbox::config::{ClearUsage, Environment, Mount, MountOptions, SpaceUsage, SwapRedirects};
-use ia_sandbox::errors::{ChildError, Error, FFIError};
+use ia_sandbox::binfmt::{self, Binfmt, BinfmtEntry};
+use ia_sandbox::cgroups::{self, CgroupProbe, CgroupVersion};
+use ia_sandbox::config::{
+    AllocatePty, AllowSameFileIo, Capability, ClearUsage, CloneUser, CloseFds, Config,
+    ControllerPath, DenyNestedNamespaces, DisableSwap, Environment, ExcludeStoppedTime, FdSource,
+    HidePid, Interactive, IsolationMode, LandlockAccess, LandlockPolicy, LandlockUnsupported,
+    Limits, MarkInsideSandbox, MemoryAccounting, MinimalDev, Mount, MountCgroups, MountOptions,
+    NamespaceKind, OutputCallback, OutputMode, Overlay, Pausable, PrivateTmp, ProcOptions,
+    Propagation, ReadOnlyRoot, Redirect, RedirectOptions, RootImage, SeccompAction, SeccompPolicy,
+    ShareIpc, ShareNet, SharePid, SingleExec, SpaceUsage, SwapRedirects, UsageCallback, VethConfig,
+    WallTimeDerivation, PIDS_MAX_LIMIT,
+};
+use ia_sandbox::environment::Feature;
+use ia_sandbox::errors::{CGroupError, ChildError, ConfigError, Error, FFIError};
+use ia_sandbox::interactive::{InteractiveOutcome, PairOptions};
+use ia_sandbox::mount_info::MountInfo;
+use ia_sandbox::run_info::{
+    DerivedWallTime, ExceededLimit, RunInfo, RunInfoResult, RunUsage, Signal, Verdict,
+    CLASSIFICATION_VERSION, RUN_INFO_VERSION,
+};
+use ia_sandbox::seccomp::{SeccompNotification, SeccompNotifyHandler, SeccompResponse};
+use ia_sandbox::seccomp_profiles;
+use ia_sandbox::{AdoptedJail, RecoveryToken, VERSION_INFO};
 
 use tempfile::Builder;
 
 mod utils;
-#[cfg(feature = "nightly")]
-use utils::matchers::KilledBySignal;
 use utils::matchers::{
-    AnnotateAssert, CompareLimits, IsSuccess, MemoryLimitExceeded, NonZeroExitStatus,
-    TimeLimitExceeded, WallTimeLimitExceeded,
+    Aborted, AnnotateAssert, Cancelled, CompareLimits, IllegalSyscall, IsSuccess, KilledByHost,
+    KilledBySignal, MemoryLimitExceeded, NetworkLimitExceeded, NonZeroExitStatus,
+    OutputLimitExceeded, RuntimeError, TimeLimitExceeded, WallTimeLimitExceeded,
 };
-use utils::{LimitsBuilder, PivotRoot, RunInfoExt, TestRunnerHelper};
+use utils::{ErrorExt, LimitsBuilder, PivotRoot, RunInfoExt, TestRunnerHelper};
 
 const HELLO_WORLD: &str = "./target/debug/hello_world";
 
@@ -37,6 +68,9 @@ const THREADS_LOOP_500_MS: &str = "./target/debug/threads_loop_500_ms";
 
 const ALLOCATE_20_MEGABYTES: &str = "./target/debug/allocate_20_megabytes";
 
+const ALLOCATE_IN_LOOP: &str = "./target/debug/allocate_in_loop";
+const ALLOCATE_10_MEGABYTES_PER_MS: &str = "./target/debug/allocate_10_megabytes_per_ms";
+
 const THREADS_ALLOCATE_20_MEGABYTES: &str = "./target/debug/threads_allocate_20_megabytes";
 
 const THREADS_SLEEP_1_SECOND: &str = "./target/debug/threads_sleep_1_second";
@@ -48,6 +82,121 @@ const EXIT_WITH_ENV: &str = "./target/debug/exit_with_env";
 const WRITE_THEN_READ: &str = "./target/debug/write_then_read";
 const READ_THEN_WRITE: &str = "./target/debug/read_then_write";
 
+const SLEEP_300_MS: &str = "./target/debug/sleep_300_ms";
+
+const CHECK_CPUS_ALLOWED_LIST: &str = "./target/debug/check_cpus_allowed_list";
+
+const WRITE_2_MEGABYTES: &str = "./target/debug/write_2_megabytes";
+
+const CHECK_CGROUP_ROOT: &str = "./target/debug/check_cgroup_root";
+
+const SEND_OVER_LOOPBACK: &str = "./target/debug/send_over_loopback";
+
+const RAISE_SIGNAL_ARG: &str = "./target/debug/raise_signal_arg";
+
+const LAST_WORDS_THEN_ABORT: &str = "./target/debug/last_words_then_abort";
+const LAST_WORDS: &[u8] = b"the last words before the abort\n";
+
+const SIGTERM_WITH_ARG: &str = "./target/debug/sigterm_with_arg";
+
+const CLOSE_STDIN_EARLY: &str = "./target/debug/close_stdin_early";
+
+const WRITE_UNTIL_EPIPE: &str = "./target/debug/write_until_epipe";
+
+const READ_FILE_ARG: &str = "./target/debug/read_file_arg";
+
+const CREATE_SHM_SEGMENT: &str = "./target/debug/create_shm_segment";
+
+const PRINT_UID_GID: &str = "./target/debug/print_uid_gid";
+
+const ESCAPE_PROCESS_GROUP: &str = "./target/debug/escape_process_group";
+
+const PING_TCP_ARG: &str = "./target/debug/ping_tcp_arg";
+
+const CHECK_INTERFACE_ARG: &str = "./target/debug/check_interface_arg";
+
+const WAIT_FOR_MOUNT_ARG: &str = "./target/debug/wait_for_mount_arg";
+
+const TRY_PRIVILEGED_OPERATIONS: &str = "./target/debug/try_privileged_operations";
+
+const OPEN_SOCKET: &str = "./target/debug/open_socket";
+const OPEN_PATHS: &str = "./target/debug/open_paths";
+
+const FORK_EXEC: &str = "./target/debug/fork_exec";
+
+const UNSHARE_USER: &str = "./target/debug/unshare_user";
+
+const WRITE_FILE_ARG: &str = "./target/debug/write_file_arg";
+
+const WRITE_KILOBYTES_ARG: &str = "./target/debug/write_kilobytes_arg";
+
+const PRINT_KILOBYTES_ARG: &str = "./target/debug/print_kilobytes_arg";
+
+const PRINT_STDOUT_STDERR: &str = "./target/debug/print_stdout_stderr";
+
+const COPY_FD_ARGS: &str = "./target/debug/copy_fd_args";
+
+const CHECK_FD_CLOSED_ARG: &str = "./target/debug/check_fd_closed_arg";
+
+const PRINT_LINE_EVERY_100_MS: &str = "./target/debug/print_line_every_100_ms";
+
+const ECHO_OVER_FD_ARG: &str = "./target/debug/echo_over_fd_arg";
+
+const USE_DEVICES: &str = "./target/debug/use_devices";
+
+const IS_TTY: &str = "./target/debug/is_tty";
+
+const CREATE_FILES: &str = "./target/debug/create_files";
+
+const LIST_PROC: &str = "./target/debug/list_proc";
+
+const FLUSH_ON_SIGTERM: &str = "./target/debug/flush_on_sigterm";
+
+const ASK_ARG_AND_CHECK: &str = "./target/debug/ask_arg_and_check";
+
+const SPAWN_NESTED_SANDBOX: &str = "./target/debug/spawn_nested_sandbox";
+
+const SIGKILL_SELF: &str = "./target/debug/sigkill_self";
+const PYTHON3: &str = "/usr/bin/python3";
+const CPP_HELLO_WORLD: &str =
+    "#include <iostream>\nint main() { std::cout << \"Hello World!\" << std::endl; }\n";
+
+// What the fixtures need to start up and print, without socket
+const SECCOMP_ALLOWED_SYSCALLS: &[&str] = &[
+    "access",
+    "arch_prctl",
+    "brk",
+    "close",
+    "exit",
+    "exit_group",
+    "fstat",
+    "futex",
+    "getrandom",
+    "gettid",
+    "ioctl",
+    "lseek",
+    "madvise",
+    "mmap",
+    "mprotect",
+    "munmap",
+    "newfstatat",
+    "open",
+    "openat",
+    "poll",
+    "pread64",
+    "prlimit64",
+    "read",
+    "rseq",
+    "rt_sigaction",
+    "rt_sigprocmask",
+    "sched_getaffinity",
+    "set_robust_list",
+    "set_tid_address",
+    "sigaltstack",
+    "statx",
+    "write",
+];
+
 #[test]
 fn test_basic_sandbox() {
     TestRunnerHelper::for_simple_exec("test_basic_sandbox", HELLO_WORLD, PivotRoot::DoNot)
@@ -64,6 +213,27 @@ fn test_exec_failed() {
         .command("missing")
         .build_and_run()
         .unwrap_err()
+        .into_config_error()
+    {
+        ConfigError::CommandNotFound(command) => assert_eq!(command, Path::new("missing")),
+        err => panic!("Expected command not found error, got {}", err),
+    }
+}
+
+#[test]
+fn test_exec_failed_in_new_root() {
+    let mut helper = TestRunnerHelper::for_simple_exec(
+        "test_exec_failed_in_new_root",
+        HELLO_WORLD,
+        PivotRoot::Pivot,
+    );
+    // Only resolved inside the new root, validating can not tell
+    symlink("/missing", helper.file_path("dangling")).unwrap();
+    match helper
+        .config_builder()
+        .command("/dangling")
+        .build_and_run()
+        .unwrap_err()
     {
         Error::ChildError(ChildError::FFIError(FFIError::ExecError { .. })) => (),
         err => panic!("Expected exec error, got {}", err),
@@ -111,6 +281,29 @@ fn test_redirect_stdin() {
         .assert(NonZeroExitStatus::new(23));
 }
 
+#[test]
+fn test_redirect_in_sandbox() {
+    let mut helper = TestRunnerHelper::for_simple_exec(
+        "test_redirect_in_sandbox",
+        EXIT_WITH_INPUT,
+        PivotRoot::Pivot,
+    );
+    // Only inside the new root, there is no /data on the host
+    fs::create_dir(helper.file_path("data")).unwrap();
+    helper.write_file("data/01.in", b"23");
+    let mut options = RedirectOptions::default();
+    options.set_in_sandbox(true);
+    helper
+        .config_builder()
+        .redirect_stdin(Redirect::File(PathBuf::from("/data/01.in"), options))
+        .redirect_stdout(Redirect::File(PathBuf::from("data/01.out"), options))
+        .redirect_stderr(Redirect::ToStdout)
+        .build_and_run()
+        .unwrap()
+        .assert(NonZeroExitStatus::new(23));
+    assert_eq!(fs::read(helper.file_path("data/01.out")).unwrap(), b"23\n");
+}
+
 #[test]
 fn test_redirect_stdout() {
     let mut helper =
@@ -144,264 +337,4991 @@ fn test_redirect_stderr() {
 }
 
 #[test]
-fn test_arguments() {
-    TestRunnerHelper::for_simple_exec("test_arguments", EXIT_WITH_LAST_ARGUMENT, PivotRoot::Pivot)
+fn test_redirect_null() {
+    let mut helper =
+        TestRunnerHelper::for_simple_exec("test_redirect_null", HELLO_WORLD, PivotRoot::Pivot);
+    let list_files = |dir: &Path| {
+        let mut files: Vec<_> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        files.sort();
+        files
+    };
+    // With the directories the sandbox makes in the new root
+    helper
         .config_builder()
-        .arg("0")
         .build_and_run()
         .unwrap()
         .assert(IsSuccess);
-
-    TestRunnerHelper::for_simple_exec("test_arguments", EXIT_WITH_LAST_ARGUMENT, PivotRoot::Pivot)
+    let files = list_files(&helper.file_path(""));
+    helper
         .config_builder()
-        .args(vec!["24", "0", "17"])
+        .inherit_stdin()
+        .redirect_stdout(Redirect::Null)
+        .redirect_stderr(Redirect::Null)
         .build_and_run()
         .unwrap()
-        .assert(NonZeroExitStatus::new(17))
+        .assert(IsSuccess);
+    assert_eq!(list_files(&helper.file_path("")), files);
 }
 
-#[cfg(feature = "nightly")]
 #[test]
-fn test_killed_by_signal() {
-    TestRunnerHelper::for_simple_exec(
-        "test_killed_by_signal",
-        KILL_WITH_SIGNAL_ARG,
-        PivotRoot::Pivot,
-    )
-    .config_builder()
-    .arg("8")
-    .build_and_run()
-    .unwrap()
-    .assert(KilledBySignal(8));
+fn test_redirect_capture() {
+    let capture = Redirect::Capture {
+        limit: SpaceUsage::from_kibibytes(4),
+    };
+    let run_info =
+        TestRunnerHelper::for_simple_exec("test_redirect_capture", HELLO_WORLD, PivotRoot::Pivot)
+            .config_builder()
+            .redirect_stdout(capture.clone())
+            .build_and_run()
+            .unwrap();
+    let stdout = run_info.stdout().cloned().unwrap();
+    run_info.assert(IsSuccess);
+    assert_eq!(stdout.data(), b"Hello World!\n");
+    assert!(!stdout.truncated());
 
-    TestRunnerHelper::for_simple_exec(
-        "test_killed_by_signal",
-        KILL_WITH_SIGNAL_ARG,
+    // Far more than the pipe holds, it has to be read as it comes
+    let run_info = TestRunnerHelper::for_simple_exec(
+        "test_redirect_capture",
+        PRINT_KILOBYTES_ARG,
         PivotRoot::Pivot,
     )
     .config_builder()
-    .arg("11")
+    .arg("1024")
+    .redirect_stdout(capture.clone())
+    .redirect_stderr(capture)
     .build_and_run()
-    .unwrap()
-    .assert(KilledBySignal(11));
+    .unwrap();
+    let (stdout, stderr) = (
+        run_info.stdout().cloned().unwrap(),
+        run_info.stderr().cloned().unwrap(),
+    );
+    run_info.assert(IsSuccess);
+    assert_eq!(stdout.data(), &[b'a'; 4 * 1_024][..]);
+    assert!(stdout.truncated());
+    assert_eq!(stderr.data(), b"Done\n");
+    assert!(!stderr.truncated());
 }
 
 #[test]
-fn test_wall_time_limit_exceeded() {
-    let mut limits = LimitsBuilder::new();
-    limits.wall_time(Duration::from_millis(1200));
-
-    TestRunnerHelper::for_simple_exec(
-        "test_wall_time_limit_exceeded",
-        SLEEP_1_SECOND,
-        PivotRoot::Pivot,
-    )
-    .config_builder()
-    .limits(limits)
-    .build_and_run()
-    .unwrap()
-    .assert(CompareLimits::new(IsSuccess, limits));
-
-    limits.wall_time(Duration::from_millis(800));
-    TestRunnerHelper::for_simple_exec(
-        "test_wall_time_limit_exceeded",
-        SLEEP_1_SECOND,
-        PivotRoot::Pivot,
-    )
-    .config_builder()
-    .limits(limits)
-    .build_and_run()
-    .unwrap()
-    .assert(CompareLimits::new(WallTimeLimitExceeded, limits));
+fn test_capture_stdin() {
+    match TestRunnerHelper::for_simple_exec("test_capture_stdin", HELLO_WORLD, PivotRoot::Pivot)
+        .config_builder()
+        .redirect_stdin(Redirect::Capture {
+            limit: SpaceUsage::from_kibibytes(4),
+        })
+        .build_and_run()
+        .unwrap_err()
+        .into_config_error()
+    {
+        ConfigError::CapturedStdin => {}
+        err => panic!("Unexpected error: {}", err),
+    }
 }
 
 #[test]
-fn test_time_limit_exceeded() {
-    let mut limits = LimitsBuilder::new();
-    limits.user_time(Duration::from_millis(600));
-
-    TestRunnerHelper::for_simple_exec("test_time_limit_exceeded", LOOP_500_MS, PivotRoot::Pivot)
+fn test_redirect_tee() {
+    let mut helper = TestRunnerHelper::for_simple_exec(
+        "test_redirect_tee",
+        PRINT_KILOBYTES_ARG,
+        PivotRoot::Pivot,
+    );
+    let (file_path, callback_path) = (helper.file_path("file"), helper.file_path("callback"));
+    // The callback runs in the supervisor, what it gets comes back through a file
+    let on_output = {
+        let callback_path = callback_path.clone();
+        OutputCallback::new(move |data| {
+            OpenOptions::new()
+                .append(true)
+                .create(true)
+                .open(&callback_path)
+                .unwrap()
+                .write_all(data)
+                .unwrap()
+        })
+    };
+    let run_info = helper
         .config_builder()
-        .limits(limits)
+        .arg("256")
+        .redirect_stdout(Redirect::Tee {
+            file: file_path.clone(),
+            limit: None,
+        })
+        .on_output(on_output)
         .build_and_run()
-        .unwrap()
-        .assert(CompareLimits::new(IsSuccess, limits));
+        .unwrap();
+    assert_eq!(
+        run_info.usage().dropped_output_bytes(),
+        SpaceUsage::from_bytes(0)
+    );
+    run_info.assert(IsSuccess);
+    assert_eq!(fs::read(&file_path).unwrap(), &[b'a'; 256 * 1_024][..]);
+    assert_eq!(
+        fs::read(&callback_path).unwrap(),
+        fs::read(&file_path).unwrap()
+    );
 
-    limits.user_time(Duration::from_millis(450));
-    TestRunnerHelper::for_simple_exec("test_time_limit_exceeded", LOOP_500_MS, PivotRoot::Pivot)
+    // The limit is only for the file
+    fs::remove_file(&callback_path).unwrap();
+    helper
         .config_builder()
-        .limits(limits)
+        .redirect_stdout(Redirect::Tee {
+            file: file_path.clone(),
+            limit: Some(SpaceUsage::from_kibibytes(4)),
+        })
         .build_and_run()
         .unwrap()
-        .assert(CompareLimits::new(TimeLimitExceeded, limits));
+        .assert(IsSuccess);
+    assert_eq!(fs::read(&file_path).unwrap(), &[b'a'; 4 * 1_024][..]);
+    assert_eq!(fs::read(&callback_path).unwrap(), &[b'a'; 256 * 1_024][..]);
 }
 
-#[test]
-fn test_threads_time_limit_exceeded() {
-    let mut limits = LimitsBuilder::new();
-    limits.user_time(Duration::from_millis(600));
+const STDOUT_STDERR_LINES: &[u8] = b"stdout 1\nstderr 1\nstdout 2\nstderr 2\n";
 
-    TestRunnerHelper::for_simple_exec(
-        "test_threads_time_limit_exceeded",
-        THREADS_LOOP_500_MS,
+#[test]
+fn test_stderr_to_stdout() {
+    let mut helper = TestRunnerHelper::for_simple_exec(
+        "test_stderr_to_stdout",
+        PRINT_STDOUT_STDERR,
         PivotRoot::Pivot,
-    )
-    .config_builder()
-    .limits(limits)
-    .build_and_run()
-    .unwrap()
-    .assert(CompareLimits::new(IsSuccess, limits));
+    );
+    let output_path = helper.file_path("output");
+    helper
+        .config_builder()
+        .stdout(&output_path)
+        .redirect_stderr(Redirect::ToStdout)
+        .build_and_run()
+        .unwrap()
+        .assert(IsSuccess);
+    assert_eq!(fs::read(&output_path).unwrap(), STDOUT_STDERR_LINES);
 
-    limits.user_time(Duration::from_millis(450));
-    TestRunnerHelper::for_simple_exec(
-        "test_threads_time_limit_exceeded",
-        THREADS_LOOP_500_MS,
+    let run_info = TestRunnerHelper::for_simple_exec(
+        "test_stderr_to_stdout",
+        PRINT_STDOUT_STDERR,
         PivotRoot::Pivot,
     )
     .config_builder()
-    .limits(limits)
+    .redirect_stdout(Redirect::Capture {
+        limit: SpaceUsage::from_kibibytes(4),
+    })
+    .redirect_stderr(Redirect::ToStdout)
     .build_and_run()
-    .unwrap()
-    .assert(CompareLimits::new(TimeLimitExceeded, limits));
+    .unwrap();
+    assert!(run_info.stderr().is_none());
+    let stdout = run_info.stdout().cloned().unwrap();
+    run_info.assert(IsSuccess);
+    assert_eq!(stdout.data(), STDOUT_STDERR_LINES);
+
+    for (stdin, stdout) in &[
+        (Redirect::ToStdout, Redirect::Inherit),
+        (Redirect::Inherit, Redirect::ToStdout),
+    ] {
+        match TestRunnerHelper::for_simple_exec(
+            "test_stderr_to_stdout",
+            PRINT_STDOUT_STDERR,
+            PivotRoot::Pivot,
+        )
+        .config_builder()
+        .redirect_stdin(stdin.clone())
+        .redirect_stdout(stdout.clone())
+        .build_and_run()
+        .unwrap_err()
+        .into_config_error()
+        {
+            ConfigError::ToStdoutNotStderr(_) => {}
+            err => panic!("Unexpected error: {}", err),
+        }
+    }
 }
 
+// Stdout is a FIFO, opened before or after stdin depending on the swap, and stderr has to end
+// up on it either way
 #[test]
-fn test_threads_wall_time_limit_exceeded() {
-    let mut limits = LimitsBuilder::new();
-    limits.wall_time(Duration::from_millis(1200));
+fn test_stderr_to_stdout_fifo() {
+    for &swap_redirects in &[SwapRedirects::No, SwapRedirects::Yes] {
+        let temp_dir = Builder::new()
+            .prefix("test_stderr_to_stdout_fifo")
+            .tempdir()
+            .unwrap();
+        let (input_path, output_path) = (
+            temp_dir.path().join("input"),
+            temp_dir.path().join("output"),
+        );
+        utils::make_fifo(&input_path);
+        utils::make_fifo(&output_path);
 
-    TestRunnerHelper::for_simple_exec(
-        "test_threads_wall_time_limit_exceeded",
-        THREADS_SLEEP_1_SECOND,
-        PivotRoot::Pivot,
-    )
-    .config_builder()
-    .limits(limits)
-    .build_and_run()
-    .unwrap()
-    .assert(CompareLimits::new(IsSuccess, limits));
+        let mut helper = TestRunnerHelper::for_simple_exec(
+            "test_stderr_to_stdout_fifo",
+            PRINT_STDOUT_STDERR,
+            PivotRoot::Pivot,
+        );
+        let handle = helper
+            .config_builder()
+            .stdin(&input_path)
+            .stdout(&output_path)
+            .redirect_stderr(Redirect::ToStdout)
+            .swap_redirects(swap_redirects)
+            .build_and_spawn()
+            .unwrap();
 
-    limits.wall_time(Duration::from_millis(800));
-    TestRunnerHelper::for_simple_exec(
-        "test_threads_wall_time_limit_exceeded",
-        THREADS_SLEEP_1_SECOND,
-        PivotRoot::Pivot,
-    )
-    .config_builder()
-    .limits(limits)
-    .build_and_run()
-    .unwrap()
-    .assert(CompareLimits::new(WallTimeLimitExceeded, limits));
+        // Each open blocks until the other end is opened, in the order the command opens them
+        let mut output = Vec::new();
+        match swap_redirects {
+            SwapRedirects::No => {
+                drop(File::create(&input_path).unwrap());
+                let _ = File::open(&output_path)
+                    .unwrap()
+                    .read_to_end(&mut output)
+                    .unwrap();
+            }
+            SwapRedirects::Yes => {
+                let mut output_file = File::open(&output_path).unwrap();
+                drop(File::create(&input_path).unwrap());
+                let _ = output_file.read_to_end(&mut output).unwrap();
+            }
+        }
+        handle.wait().unwrap().assert(IsSuccess);
+        assert_eq!(output, STDOUT_STDERR_LINES);
+    }
 }
 
 #[test]
-fn test_memory_limit_exceeded() {
-    let mut limits = LimitsBuilder::new();
-    limits.memory(SpaceUsage::from_megabytes(26));
-
-    TestRunnerHelper::for_simple_exec(
-        "test_memory_limit_exceeded",
-        ALLOCATE_20_MEGABYTES,
+fn test_redirect_output_mode() {
+    let mut helper = TestRunnerHelper::for_simple_exec(
+        "test_redirect_output_mode",
+        HELLO_WORLD,
         PivotRoot::Pivot,
-    )
-    .config_builder()
-    .limits(limits)
-    .build_and_run()
-    .unwrap()
-    .assert(CompareLimits::new(IsSuccess, limits));
+    );
+    let output_path = helper.file_path("output");
+    let mut run_with = |mode, permissions| {
+        helper
+            .config_builder()
+            .redirect_stdout(Redirect::File(
+                output_path.clone(),
+                RedirectOptions::new(mode, permissions),
+            ))
+            .build_and_run()
+    };
 
-    limits.memory(SpaceUsage::from_megabytes(19));
-    TestRunnerHelper::for_simple_exec(
-        "test_memory_limit_exceeded",
-        ALLOCATE_20_MEGABYTES,
+    run_with(OutputMode::Truncate, None)
+        .unwrap()
+        .assert(IsSuccess);
+    run_with(OutputMode::Truncate, None)
+        .unwrap()
+        .assert(IsSuccess);
+    assert_eq!(fs::read(&output_path).unwrap(), b"Hello World!\n");
+
+    run_with(OutputMode::Append, None)
+        .unwrap()
+        .assert(IsSuccess);
+    assert_eq!(
+        fs::read(&output_path).unwrap(),
+        b"Hello World!\nHello World!\n"
+    );
+
+    match run_with(OutputMode::CreateNew, None).unwrap_err() {
+        Error::ChildError(ChildError::FFIError(FFIError::RedirectFileExists { path, .. })) => {
+            assert_eq!(path, output_path)
+        }
+        err => panic!("Expected redirect file exists error, got {:?}", err),
+    }
+    assert_eq!(
+        fs::read(&output_path).unwrap(),
+        b"Hello World!\nHello World!\n"
+    );
+
+    fs::remove_file(&output_path).unwrap();
+    run_with(OutputMode::CreateNew, Some(0o600))
+        .unwrap()
+        .assert(IsSuccess);
+    assert_eq!(fs::read(&output_path).unwrap(), b"Hello World!\n");
+    assert_eq!(
+        fs::metadata(&output_path).unwrap().permissions().mode() & 0o777,
+        0o600
+    );
+}
+
+// A symlink planted at the output path, to a file or to nothing yet, is not followed
+#[test]
+fn test_redirect_create_new_symlink() {
+    let mut helper = TestRunnerHelper::for_simple_exec(
+        "test_redirect_create_new_symlink",
+        HELLO_WORLD,
         PivotRoot::Pivot,
-    )
-    .config_builder()
-    .limits(limits)
-    .build_and_run()
-    .unwrap()
-    .assert(CompareLimits::new(MemoryLimitExceeded, limits));
+    );
+    helper.write_file("victim", b"untouched");
+    let (victim_path, missing_path, output_path) = (
+        helper.file_path("victim"),
+        helper.file_path("missing"),
+        helper.file_path("output"),
+    );
+    for target in &[&victim_path, &missing_path] {
+        let _ = fs::remove_file(&output_path);
+        symlink(target, &output_path).unwrap();
+        match helper
+            .config_builder()
+            .redirect_stdout(Redirect::File(
+                output_path.clone(),
+                RedirectOptions::new(OutputMode::CreateNew, None),
+            ))
+            .build_and_run()
+            .unwrap_err()
+        {
+            Error::ChildError(ChildError::FFIError(FFIError::RedirectFileExists { .. })) => (),
+            err => panic!("Expected redirect file exists error, got {:?}", err),
+        }
+    }
+    assert_eq!(fs::read(&victim_path).unwrap(), b"untouched");
+    assert!(!missing_path.exists());
 }
 
 #[test]
-fn test_threads_memory_limit_exceeded() {
-    let mut limits = LimitsBuilder::new();
-    limits.memory(SpaceUsage::from_megabytes(40));
+fn test_redirect_pipe() {
+    let mut helper =
+        TestRunnerHelper::for_simple_exec("test_redirect_pipe", EXIT_WITH_INPUT, PivotRoot::Pivot);
+    let mut handle = helper
+        .config_builder()
+        .redirect_stdin(Redirect::Pipe)
+        .redirect_stdout(Redirect::Pipe)
+        .build_and_spawn()
+        .unwrap();
+    let (mut stdin, mut stdout) = (handle.stdin().unwrap(), handle.stdout().unwrap());
+    assert!(handle.stdin().is_none());
+    assert!(handle.stderr().is_none());
+    stdin.write_all(b"23\n").unwrap();
+    drop(stdin);
+    let mut output = String::new();
+    let _ = stdout.read_to_string(&mut output).unwrap();
+    handle.wait().unwrap().assert(NonZeroExitStatus::new(23));
+    assert_eq!(output, "23\n\n");
 
-    TestRunnerHelper::for_simple_exec(
-        "test_threads_memory_limit_exceeded",
-        THREADS_ALLOCATE_20_MEGABYTES,
-        PivotRoot::Pivot,
+    // The end of stdin that was not taken is closed, the command reads EOF (and fails to parse
+    // it) instead of waiting for more
+    let mut helper =
+        TestRunnerHelper::for_simple_exec("test_redirect_pipe", EXIT_WITH_INPUT, PivotRoot::Pivot);
+    helper
+        .config_builder()
+        .redirect_stdin(Redirect::Pipe)
+        .build_and_spawn()
+        .unwrap()
+        .wait()
+        .unwrap()
+        .assert(NonZeroExitStatus::new(101));
+}
+
+// Like test_interactive, with the two connected by the caller instead of FIFOs
+#[test]
+fn test_interactive_pipes() {
+    let mut limits = LimitsBuilder::new();
+    limits.wall_time(Duration::from_secs(1));
+
+    let spawn = |helper: &mut TestRunnerHelper| {
+        helper
+            .config_builder()
+            .limits(limits)
+            .redirect_stdin(Redirect::Pipe)
+            .redirect_stdout(Redirect::Pipe)
+            .build_and_spawn()
+            .unwrap()
+    };
+    let mut write_then_read_helper = TestRunnerHelper::for_simple_exec(
+        "test_interactive_pipes_write_then_read",
+        WRITE_THEN_READ,
+        PivotRoot::Pivot,
+    );
+    let mut write_then_read = spawn(&mut write_then_read_helper);
+    let mut read_then_write_helper = TestRunnerHelper::for_simple_exec(
+        "test_interactive_pipes_read_then_write",
+        READ_THEN_WRITE,
+        PivotRoot::Pivot,
+    );
+    let mut read_then_write = spawn(&mut read_then_write_helper);
+
+    let connect = |mut from: Box<dyn Read + Send>, mut to: Box<dyn Write + Send>| {
+        thread::spawn(move || io::copy(&mut from, &mut to).map(|_| ()))
+    };
+    let forward = connect(
+        Box::new(write_then_read.stdout().unwrap()),
+        Box::new(read_then_write.stdin().unwrap()),
+    );
+    let backward = connect(
+        Box::new(read_then_write.stdout().unwrap()),
+        Box::new(write_then_read.stdin().unwrap()),
+    );
+
+    write_then_read
+        .wait()
+        .unwrap()
+        .assert(AnnotateAssert::new(IsSuccess, "write_then_read"));
+    read_then_write
+        .wait()
+        .unwrap()
+        .assert(AnnotateAssert::new(IsSuccess, "read_then_write"));
+    forward.join().unwrap().unwrap();
+    backward.join().unwrap().unwrap();
+}
+
+#[test]
+fn test_read_output_available() {
+    let mut helper = TestRunnerHelper::for_simple_exec(
+        "test_read_output_available",
+        PRINT_LINE_EVERY_100_MS,
+        PivotRoot::Pivot,
+    );
+    let mut handle = helper
+        .config_builder()
+        .redirect_stdout(Redirect::Pipe)
+        .build_and_spawn()
+        .unwrap();
+    let mut buf = [0; 1_024];
+    let mut output = Vec::new();
+    let mut line_times = Vec::new();
+    while let Some(read) = handle.read_stdout_available(&mut buf).unwrap() {
+        output.extend_from_slice(&buf[..read]);
+        let lines = output.iter().filter(|&&byte| byte == b'\n').count();
+        while line_times.len() < lines {
+            line_times.push(Instant::now());
+        }
+        if read == 0 {
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+    let expected = b"line 0\nline 1\nline 2\nline 3\nline 4\n";
+    assert_eq!(output, &expected[..]);
+    // They came as they were printed, not all at once at the end
+    assert!(line_times[4] - line_times[0] >= Duration::from_millis(300));
+
+    let run_info = handle.wait().unwrap();
+    assert_eq!(run_info.stdout().unwrap().data(), &expected[..]);
+    run_info.assert(IsSuccess);
+
+    // What is left unread comes back all the same
+    let mut handle = helper
+        .config_builder()
+        .redirect_stdout(Redirect::Pipe)
+        .build_and_spawn()
+        .unwrap();
+    while handle.read_stdout_available(&mut buf).unwrap() == Some(0) {
+        thread::sleep(Duration::from_millis(10));
+    }
+    let run_info = handle.wait().unwrap();
+    assert_eq!(run_info.stdout().unwrap().data(), &expected[..]);
+    run_info.assert(IsSuccess);
+}
+
+#[test]
+fn test_redirect_bytes() {
+    TestRunnerHelper::for_simple_exec("test_redirect_bytes", EXIT_WITH_INPUT, PivotRoot::Pivot)
+        .config_builder()
+        .redirect_stdin(Redirect::Bytes(b"42".to_vec()))
+        .build_and_run()
+        .unwrap()
+        .assert(NonZeroExitStatus::new(42));
+
+    for (stdout, stderr) in &[
+        (Redirect::Bytes(b"42".to_vec()), Redirect::Inherit),
+        (Redirect::Inherit, Redirect::Bytes(b"42".to_vec())),
+    ] {
+        match TestRunnerHelper::for_simple_exec(
+            "test_redirect_bytes",
+            EXIT_WITH_INPUT,
+            PivotRoot::Pivot,
+        )
+        .config_builder()
+        .redirect_stdout(stdout.clone())
+        .redirect_stderr(stderr.clone())
+        .build_and_run()
+        .unwrap_err()
+        .into_config_error()
+        {
+            ConfigError::BytesNotStdin(_) => {}
+            err => panic!("Unexpected error: {}", err),
+        }
+    }
+}
+
+#[test]
+fn test_extra_fds() {
+    let mut helper =
+        TestRunnerHelper::for_simple_exec("test_extra_fds", COPY_FD_ARGS, PivotRoot::Pivot);
+    helper.write_file("input", b"1 2 3\n");
+    let (input_path, output_path) = (helper.file_path("input"), helper.file_path("output"));
+    helper
+        .config_builder()
+        .extra_fd(
+            3,
+            FdSource::Path {
+                path: input_path,
+                flags: libc::O_RDONLY,
+            },
+        )
+        .extra_fd(
+            4,
+            FdSource::Path {
+                path: output_path.clone(),
+                flags: libc::O_WRONLY | libc::O_CREAT | libc::O_TRUNC,
+            },
+        )
+        .args(vec!["3", "4"])
+        .build_and_run()
+        .unwrap()
+        .assert(IsSuccess);
+    assert_eq!(fs::read(&output_path).unwrap(), b"1 2 3\n");
+}
+
+// Each donated fd is the target of the other, neither may be overwritten before it is moved
+#[test]
+fn test_extra_fds_swapped() {
+    let mut helper =
+        TestRunnerHelper::for_simple_exec("test_extra_fds_swapped", COPY_FD_ARGS, PivotRoot::Pivot);
+    helper.write_file("input", b"1 2 3\n");
+    let input = File::open(helper.file_path("input")).unwrap();
+    let output = File::create(helper.file_path("output")).unwrap();
+    let (input_fd, output_fd) = (input.as_raw_fd(), output.as_raw_fd());
+    helper
+        .config_builder()
+        .extra_fd(input_fd, FdSource::Fd(output_fd))
+        .extra_fd(output_fd, FdSource::Fd(input_fd))
+        .args(vec![output_fd.to_string(), input_fd.to_string()])
+        .build_and_run()
+        .unwrap()
+        .assert(IsSuccess);
+    assert_eq!(fs::read(helper.file_path("output")).unwrap(), b"1 2 3\n");
+}
+
+#[test]
+fn test_socket_channel() {
+    let mut helper = TestRunnerHelper::for_simple_exec(
+        "test_socket_channel",
+        ECHO_OVER_FD_ARG,
+        PivotRoot::Pivot,
+    );
+    let _ = helper.config_builder().socket_channel(3).arg("3");
+    // Sending and receiving on the channel are allowed even when the policy does not
+    for seccomp in vec![
+        None,
+        Some(seccomp_policy(SeccompAction::Errno(libc::EACCES as u16))),
+    ] {
+        if let Some(seccomp) = seccomp {
+            let _ = helper.config_builder().seccomp(seccomp);
+        }
+        let mut handle = helper.config_builder().build_and_spawn().unwrap();
+        let mut channel = handle.channel().unwrap();
+        for message in &[&b"ping"[..], b"pong"] {
+            channel.write_all(message).unwrap();
+            let mut reply = vec![0; message.len()];
+            channel.read_exact(&mut reply).unwrap();
+            assert_eq!(&reply[..], *message);
+        }
+        channel.shutdown(Shutdown::Write).unwrap();
+        handle.wait().unwrap().assert(IsSuccess);
+    }
+}
+
+#[test]
+fn test_invalid_extra_fds() {
+    // Above any limit on the number of open files
+    let closed_fd = 1 << 20;
+    let cases = vec![
+        vec![(1, FdSource::Fd(0))],
+        vec![(3, FdSource::Fd(0)), (3, FdSource::Fd(0))],
+        vec![(3, FdSource::Fd(closed_fd))],
+    ];
+    for extra_fds in cases {
+        let mut helper = TestRunnerHelper::for_simple_exec(
+            "test_invalid_extra_fds",
+            HELLO_WORLD,
+            PivotRoot::Pivot,
+        );
+        for (fd, source) in extra_fds {
+            let _ = helper.config_builder().extra_fd(fd, source);
+        }
+        match helper
+            .config_builder()
+            .build_and_run()
+            .unwrap_err()
+            .into_config_error()
+        {
+            ConfigError::InvalidExtraFd { .. } => {}
+            err => panic!("Unexpected error: {}", err),
+        }
+    }
+}
+
+// A file descriptor of the caller without close-on-exec, like a log file opened through libc
+#[test]
+fn test_close_fds() {
+    let mut helper =
+        TestRunnerHelper::for_simple_exec("test_close_fds", CHECK_FD_CLOSED_ARG, PivotRoot::Pivot);
+    helper.write_file("sentinel", b"");
+    let sentinel = File::open(helper.file_path("sentinel")).unwrap();
+    let sentinel_fd = sentinel.as_raw_fd();
+    assert_eq!(unsafe { libc::fcntl(sentinel_fd, libc::F_SETFD, 0) }, 0);
+
+    helper
+        .config_builder()
+        .args(vec![sentinel_fd.to_string()])
+        .build_and_run()
+        .unwrap()
+        .assert(IsSuccess);
+
+    helper
+        .config_builder()
+        .close_fds(CloseFds::No)
+        .build_and_run()
+        .unwrap()
+        .assert(NonZeroExitStatus::new(1));
+}
+
+#[test]
+fn test_same_file_io() {
+    let mut helper =
+        TestRunnerHelper::for_simple_exec("test_same_file_io", EXIT_WITH_INPUT, PivotRoot::Pivot);
+
+    helper.write_file("input", b"23");
+    let input_path = helper.file_path("input");
+    let symlink_path = helper.file_path("symlink");
+    let hard_link_path = helper.file_path("hard_link");
+    symlink(&input_path, &symlink_path).unwrap();
+    fs::hard_link(&input_path, &hard_link_path).unwrap();
+
+    for output_path in &[&input_path, &symlink_path, &hard_link_path] {
+        match helper
+            .config_builder()
+            .stdin(&input_path)
+            .stdout(output_path)
+            .build_and_run()
+            .unwrap_err()
+            .into_config_error()
+        {
+            ConfigError::SameFileIo { input, output } => {
+                assert_eq!(input, input_path);
+                assert_eq!(&output, *output_path);
+            }
+            err => panic!("Expected same file io error, got {}", err),
+        }
+    }
+
+    match helper
+        .config_builder()
+        .stdout("/dev/null")
+        .stderr(&symlink_path)
+        .build_and_run()
+        .unwrap_err()
+        .into_config_error()
+    {
+        ConfigError::SameFileIo { output, .. } => {
+            assert_eq!(output, symlink_path)
+        }
+        err => panic!("Expected same file io error, got {}", err),
+    }
+    assert_eq!(helper.read_line(&input_path), "23");
+
+    // When allowed, stdin is opened first and then truncated by opening stdout
+    helper
+        .config_builder()
+        .stdout(&input_path)
+        .stderr("/dev/null")
+        .allow_same_file_io(AllowSameFileIo::Yes)
+        .build_and_run()
+        .unwrap()
+        .assert(NonZeroExitStatus::any());
+    assert_eq!(helper.read_line(&input_path), "\n");
+}
+
+#[test]
+fn test_arguments() {
+    TestRunnerHelper::for_simple_exec("test_arguments", EXIT_WITH_LAST_ARGUMENT, PivotRoot::Pivot)
+        .config_builder()
+        .arg("0")
+        .build_and_run()
+        .unwrap()
+        .assert(IsSuccess);
+
+    TestRunnerHelper::for_simple_exec("test_arguments", EXIT_WITH_LAST_ARGUMENT, PivotRoot::Pivot)
+        .config_builder()
+        .args(vec!["24", "0", "17"])
+        .build_and_run()
+        .unwrap()
+        .assert(NonZeroExitStatus::new(17))
+}
+
+#[cfg(feature = "nightly")]
+#[test]
+fn test_killed_by_signal() {
+    TestRunnerHelper::for_simple_exec(
+        "test_killed_by_signal",
+        KILL_WITH_SIGNAL_ARG,
+        PivotRoot::Pivot,
     )
     .config_builder()
-    .limits(limits)
+    .arg("8")
     .build_and_run()
     .unwrap()
-    .assert(CompareLimits::new(IsSuccess, limits));
+    .assert(RuntimeError(8));
 
-    limits.memory(SpaceUsage::from_megabytes(19));
     TestRunnerHelper::for_simple_exec(
-        "test_threads_memory_limit_exceeded",
-        THREADS_ALLOCATE_20_MEGABYTES,
+        "test_killed_by_signal",
+        KILL_WITH_SIGNAL_ARG,
         PivotRoot::Pivot,
     )
     .config_builder()
-    .limits(limits)
+    .arg("11")
+    .build_and_run()
+    .unwrap()
+    .assert(RuntimeError(11));
+}
+
+#[test]
+fn test_signal_classification() {
+    let raise = |signal: libc::c_int| {
+        TestRunnerHelper::for_simple_exec(
+            "test_signal_classification",
+            RAISE_SIGNAL_ARG,
+            PivotRoot::Pivot,
+        )
+        .config_builder()
+        .share_pid(SharePid::Share)
+        .arg(signal.to_string())
+        .build_and_run()
+        .unwrap()
+    };
+    for &signal in &[libc::SIGSEGV, libc::SIGBUS, libc::SIGILL, libc::SIGFPE] {
+        raise(signal).assert(RuntimeError(signal as u32));
+    }
+    raise(libc::SIGABRT).assert(Aborted);
+    raise(libc::SIGTERM).assert(KilledBySignal(libc::SIGTERM as u32));
+    raise(libc::SIGXFSZ).assert(KilledBySignal(libc::SIGXFSZ as u32));
+
+    assert_eq!(
+        RunInfoResult::<()>::killed_by_signal(libc::SIGFPE as u32).to_string(),
+        "Runtime error (SIGFPE, likely division by zero)"
+    );
+    assert_eq!(
+        RunInfoResult::<()>::killed_by_signal(libc::SIGSEGV as u32).to_string(),
+        "Runtime error (SIGSEGV, invalid memory reference)"
+    );
+    let aborted = RunInfoResult::<()>::killed_by_signal(libc::SIGABRT as u32);
+    assert_eq!(aborted, RunInfoResult::Aborted);
+    assert_eq!(aborted.signal(), Some(Signal::new(libc::SIGABRT as u32)));
+    assert_eq!(
+        RunInfoResult::<()>::NonZeroExitStatus(3).to_string(),
+        "Exited with status 3"
+    );
+    assert_eq!(
+        RunInfoResult::<()>::NonZeroExitStatus(3).exit_status(),
+        Some(3)
+    );
+}
+
+#[test]
+fn test_internal_panic() {
+    // The callback runs in the supervisor, under the same panic hook as the rest of the crate
+    let on_usage = UsageCallback::new(|_| panic!("Told to panic by test_internal_panic"));
+    match TestRunnerHelper::for_simple_exec("test_internal_panic", SLEEP_1_SECOND, PivotRoot::DoNot)
+        .config_builder()
+        .on_usage(on_usage)
+        .build_and_run()
+        .unwrap_err()
+    {
+        Error::Internal(ChildError::InternalPanic { message, location }) => {
+            assert!(message.contains("test_internal_panic"), "{}", message);
+            assert!(
+                location.starts_with("tests/integration_test.rs"),
+                "{}",
+                location
+            );
+        }
+        err => panic!("Expected internal panic error, got {}", err),
+    }
+}
+
+#[test]
+fn test_nul_byte() {
+    // Paths can not hold nul bytes, the child turns them down rather than panic
+    match TestRunnerHelper::for_simple_exec("test_nul_byte", HELLO_WORLD, PivotRoot::DoNot)
+        .config_builder()
+        .stdin("/dev/\0null")
+        .build_and_run()
+        .unwrap_err()
+    {
+        Error::ChildError(ChildError::FFIError(FFIError::NulByteError(path))) => {
+            assert_eq!(path, "/dev/\0null")
+        }
+        err => panic!("Expected nul byte error, got {}", err),
+    }
+}
+
+#[test]
+fn test_version_info() {
+    assert_eq!(VERSION_INFO.version(), env!("CARGO_PKG_VERSION"));
+    let features: Vec<_> = VERSION_INFO.features().collect();
+    let mut sorted = features.clone();
+    sorted.sort();
+    assert_eq!(features, sorted);
+    assert!(
+        VERSION_INFO
+            .to_string()
+            .starts_with(&format!("ia-sandbox {}", env!("CARGO_PKG_VERSION"))),
+        "{}",
+        VERSION_INFO
+    );
+
+    let run_info =
+        TestRunnerHelper::for_simple_exec("test_version_info", HELLO_WORLD, PivotRoot::DoNot)
+            .config_builder()
+            .build_and_run()
+            .unwrap();
+    assert_eq!(run_info.producer(), Some(&VERSION_INFO));
+
+    let mut json = serde_json::to_value(&run_info).unwrap();
+    assert_eq!(
+        json["producer"],
+        serde_json::json!({
+            "version": VERSION_INFO.version(),
+            "git_hash": VERSION_INFO.git_hash(),
+            "features": VERSION_INFO.features().collect::<Vec<_>>(),
+            "classification_version": CLASSIFICATION_VERSION,
+        })
+    );
+    let deserialized: RunInfo<()> = serde_json::from_value(json.clone()).unwrap();
+    assert_eq!(deserialized, run_info);
+
+    // Producers from before the classification version was recorded
+    let mut older = json.clone();
+    let _ = older["producer"]
+        .as_object_mut()
+        .unwrap()
+        .remove("classification_version");
+    let deserialized: RunInfo<()> = serde_json::from_value(older).unwrap();
+    assert_eq!(deserialized.producer().unwrap().classification_version(), 0);
+
+    // Records from before the producer was recorded
+    let _ = json.as_object_mut().unwrap().remove("producer");
+    let deserialized: RunInfo<()> = serde_json::from_value(json).unwrap();
+    assert_eq!(deserialized.producer(), None);
+    assert_eq!(deserialized.usage(), run_info.usage());
+}
+
+#[test]
+fn test_capabilities() {
+    let capabilities = ia_sandbox::capabilities();
+    assert_eq!(capabilities.version_info(), &VERSION_INFO);
+    assert_eq!(
+        capabilities.version_info().classification_version(),
+        CLASSIFICATION_VERSION
+    );
+    assert!(capabilities.environment().passed());
+    assert!(capabilities.cgroups().unwrap().controller("pids").is_some());
+    assert!(capabilities
+        .to_string()
+        .starts_with(&format!("{} (classification version ", VERSION_INFO)));
+}
+
+#[test]
+fn test_signal_names() {
+    for &(number, name, description) in &[
+        (libc::SIGSEGV, "SIGSEGV", "invalid memory reference"),
+        (libc::SIGFPE, "SIGFPE", "arithmetic exception"),
+        (libc::SIGKILL, "SIGKILL", "killed"),
+        (libc::SIGXFSZ, "SIGXFSZ", "file size limit exceeded"),
+    ] {
+        let signal = Signal::new(number as u32);
+        assert_eq!(signal.number(), number as u32);
+        assert_eq!(signal.name().as_deref(), Some(name));
+        assert_eq!(signal.description(), Some(description));
+        assert_eq!(signal.to_string(), format!("{} ({})", name, description));
+    }
+
+    let sigrtmin = libc::SIGRTMIN() as u32;
+    assert_eq!(Signal::new(sigrtmin).name(), Some("SIGRTMIN".to_owned()));
+    let signal = Signal::new(sigrtmin + 3);
+    assert_eq!(signal.name(), Some("SIGRTMIN+3".to_owned()));
+    assert_eq!(signal.to_string(), "SIGRTMIN+3 (real-time signal)");
+    // Kept by the C library
+    assert_eq!(Signal::new(32).name(), None);
+    assert_eq!(Signal::new(32).to_string(), "signal 32 (real-time signal)");
+    assert_eq!(Signal::new(0).to_string(), "signal 0");
+    assert_eq!(Signal::new(200).description(), None);
+
+    let result = RunInfoResult::<()>::KilledBySignal(libc::SIGSEGV as u32);
+    assert_eq!(result.signal(), Some(Signal::new(libc::SIGSEGV as u32)));
+    assert_eq!(
+        result.to_string(),
+        "Killed by SIGSEGV (invalid memory reference)"
+    );
+    assert_eq!(RunInfoResult::<()>::TimeLimitExceeded.signal(), None);
+}
+
+fn time_limit_exceeded_run_info() -> RunInfo<()> {
+    let mut usage = RunUsage::new(
+        Duration::from_millis(1001),
+        Duration::new(1, 250_000_000),
+        SpaceUsage::from_kibibytes(2048),
+        SpaceUsage::from_kibibytes(1024),
+    );
+    usage.set_cpu_usage_percpu(Some(vec![
+        Duration::from_millis(1000),
+        Duration::from_micros(1),
+    ]));
+    let mut run_info = RunInfo::new(RunInfoResult::TimeLimitExceeded, usage);
+    run_info.set_derived_wall_time(Some(DerivedWallTime::new(
+        WallTimeDerivation::new(1.5, Duration::from_millis(500)),
+        Duration::from_millis(2000),
+    )));
+    run_info
+}
+
+#[test]
+fn test_run_info_json_golden() {
+    let run_info = time_limit_exceeded_run_info();
+    let mut json: serde_json::Value = serde_json::from_str(&run_info.to_json()).unwrap();
+    // The producer changes with every build
+    assert!(json.as_object_mut().unwrap().remove("producer").is_some());
+
+    let golden: serde_json::Value =
+        serde_json::from_str(include_str!("golden/run_info_time_limit_exceeded.json")).unwrap();
+    assert_eq!(json, golden);
+    assert_eq!(json["version"], RUN_INFO_VERSION);
+
+    let deserialized: RunInfo<()> = serde_json::from_value(golden).unwrap();
+    assert_eq!(deserialized.version(), RUN_INFO_VERSION);
+    assert_eq!(deserialized.result(), run_info.result());
+    assert_eq!(deserialized.usage(), run_info.usage());
+    assert_eq!(
+        deserialized.derived_wall_time(),
+        run_info.derived_wall_time()
+    );
+}
+
+#[test]
+fn test_run_info_json_round_trip() {
+    let usage = time_limit_exceeded_run_info().usage().clone();
+    for result in vec![
+        RunInfoResult::Success(()),
+        RunInfoResult::NonZeroExitStatus(3),
+        RunInfoResult::KilledBySignal(libc::SIGSEGV as u32),
+        RunInfoResult::KilledBySignal(40),
+        RunInfoResult::RuntimeError {
+            signal: libc::SIGFPE as u32,
+        },
+        RunInfoResult::Aborted,
+        RunInfoResult::KilledByOomKiller,
+        RunInfoResult::KilledByHost,
+        RunInfoResult::Cancelled,
+        RunInfoResult::IllegalSyscall {
+            number: 59,
+            name: Some("execve".to_owned()),
+        },
+        RunInfoResult::MemoryLimitExceeded,
+        RunInfoResult::TimeLimitExceeded,
+        RunInfoResult::OutputLimitExceeded {
+            written: SpaceUsage::from_kibibytes(4),
+        },
+        RunInfoResult::WallTimeLimitExceeded { derived: true },
+    ] {
+        let mut run_info = RunInfo::new(result, usage.clone());
+        run_info.set_traced(true);
+        let deserialized: RunInfo<()> = serde_json::from_str(&run_info.to_json()).unwrap();
+        assert_eq!(deserialized, run_info);
+    }
+
+    let json = RunInfo::<()>::new(RunInfoResult::KilledBySignal(9), usage).to_json();
+    let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(
+        json["result"],
+        serde_json::json!({ "KilledBySignal": { "number": 9, "name": "SIGKILL" } })
+    );
+    assert_eq!(json["usage"]["user_time"], 1_001_000_000);
+    assert_eq!(json["usage"]["memory"], 2048 * 1024);
+}
+
+#[test]
+fn test_run_info_json_timestamps() {
+    let mut run_info = time_limit_exceeded_run_info();
+    let started_at = UNIX_EPOCH + Duration::new(1_700_000_000, 123_456_789);
+    run_info.set_started_at(Some(started_at));
+    run_info.set_finished_at(Some(started_at + Duration::from_millis(1500)));
+    run_info.set_duration(Some(Duration::from_millis(1499)));
+
+    let mut json: serde_json::Value = serde_json::from_str(&run_info.to_json()).unwrap();
+    assert_eq!(json["started_at"], "2023-11-14T22:13:20.123456789Z");
+    assert_eq!(json["finished_at"], "2023-11-14T22:13:21.623456789Z");
+    assert_eq!(json["duration"], 1_499_000_000);
+    let deserialized: RunInfo<()> = serde_json::from_value(json.clone()).unwrap();
+    assert_eq!(deserialized, run_info);
+
+    // Other offsets and precisions read back as well
+    for &(time, expected) in &[
+        ("2023-11-15T00:13:20.123456789+02:00", started_at),
+        ("2023-11-14t22:13:20.1234567891z", started_at),
+        (
+            "2023-11-14T20:13:20-02:00",
+            UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+        ),
+        (
+            "1970-01-01T00:00:00.5Z",
+            UNIX_EPOCH + Duration::from_millis(500),
+        ),
+        (
+            "2000-02-29T12:00:00Z",
+            UNIX_EPOCH + Duration::from_secs(951_825_600),
+        ),
+    ] {
+        json["started_at"] = time.into();
+        let deserialized: RunInfo<()> = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(deserialized.started_at(), Some(expected), "{}", time);
+    }
+    for time in &[
+        "2023-11-14 22:13:20Z",
+        "2023-13-14T22:13:20Z",
+        "2023-11-14T22:13:20",
+        "2023-11-14T22:13:20.Z",
+        "1969-12-31T23:59:59Z",
+    ] {
+        json["started_at"] = (*time).into();
+        assert!(
+            serde_json::from_value::<RunInfo<()>>(json.clone()).is_err(),
+            "{}",
+            time
+        );
+    }
+}
+
+#[test]
+fn test_run_info_json_before_version() {
+    // Durations as serde derives them and signals as bare numbers, without a version
+    let mut json: serde_json::Value =
+        serde_json::from_str(include_str!("golden/run_info_time_limit_exceeded.json")).unwrap();
+    let _ = json.as_object_mut().unwrap().remove("version");
+    json["result"] = serde_json::json!({ "KilledBySignal": 9 });
+    json["usage"]["user_time"] = serde_json::json!({ "secs": 1, "nanos": 1_000_000 });
+    let deserialized: RunInfo<()> = serde_json::from_value(json).unwrap();
+    assert_eq!(deserialized.version(), 0);
+    assert_eq!(deserialized.result(), &RunInfoResult::KilledBySignal(9));
+    assert_eq!(
+        deserialized.usage().user_time(),
+        Duration::from_millis(1001)
+    );
+}
+
+#[test]
+fn test_run_info_wait_status() {
+    let mut run_info: RunInfo<()> =
+        RunInfo::new(RunInfoResult::NonZeroExitStatus(3), RunUsage::default());
+    assert_eq!(run_info.exit_status(), None);
+    assert_eq!(run_info.signal(), None);
+
+    run_info.set_wait_status(Some(3 << 8));
+    assert_eq!(run_info.exit_status(), Some(3));
+    assert_eq!(run_info.signal(), None);
+    assert!(!run_info.core_dumped());
+
+    // Killed by SIGSEGV, with a core dump
+    let result = RunInfoResult::RuntimeError {
+        signal: libc::SIGSEGV as u32,
+    };
+    let mut run_info: RunInfo<()> = RunInfo::new(result, RunUsage::default());
+    run_info.set_wait_status(Some(0x80 | libc::SIGSEGV));
+    assert_eq!(run_info.exit_status(), None);
+    assert_eq!(run_info.signal(), Some(Signal::new(libc::SIGSEGV as u32)));
+    assert!(run_info.core_dumped());
+    assert!(run_info.to_string().contains("Core dumped"));
+
+    run_info.set_wait_status(Some(libc::SIGSEGV));
+    assert!(!run_info.core_dumped());
+    assert!(!run_info.to_string().contains("Core dumped"));
+}
+
+#[test]
+fn test_verdict() {
+    let mut limits = LimitsBuilder::new();
+    limits
+        .user_time(Duration::from_secs(1))
+        .wall_time(Duration::from_secs(2))
+        .memory(SpaceUsage::from_megabytes(64))
+        .output(SpaceUsage::from_kibibytes(4))
+        .net_bytes(SpaceUsage::from_kibibytes(4));
+    let limits = limits.into();
+    let usage = |user_time, wall_time, memory| {
+        RunUsage::new(
+            Duration::from_millis(user_time),
+            Duration::from_millis(wall_time),
+            SpaceUsage::from_megabytes(memory),
+            SpaceUsage::from_megabytes(8),
+        )
+    };
+    let within = || usage(500, 600, 16);
+    let verdict = |result, wait_status, usage| {
+        let mut run_info: RunInfo<()> = RunInfo::new(result, usage);
+        run_info.set_wait_status(wait_status);
+        run_info.verdict(&limits)
+    };
+
+    assert_eq!(
+        verdict(RunInfoResult::Success(()), Some(0), within()),
+        Verdict::Accepted
+    );
+    // From before the wait status was kept
+    assert_eq!(
+        verdict(RunInfoResult::Success(()), None, within()),
+        Verdict::Accepted
+    );
+    assert_eq!(
+        verdict(RunInfoResult::Success(()), Some(1 << 8), within()),
+        Verdict::RuntimeError
+    );
+    for result in vec![
+        RunInfoResult::NonZeroExitStatus(1),
+        RunInfoResult::KilledBySignal(libc::SIGTERM as u32),
+        RunInfoResult::RuntimeError {
+            signal: libc::SIGSEGV as u32,
+        },
+        RunInfoResult::Aborted,
+        RunInfoResult::IllegalSyscall {
+            number: 59,
+            name: Some("execve".to_owned()),
+        },
+    ] {
+        assert_eq!(verdict(result, None, within()), Verdict::RuntimeError);
+    }
+    assert_eq!(
+        verdict(RunInfoResult::KilledByHost, None, within()),
+        Verdict::Skipped
+    );
+    assert_eq!(
+        verdict(RunInfoResult::Cancelled, None, within()),
+        Verdict::Skipped
+    );
+    for &(ref result, expected) in &[
+        (RunInfoResult::TimeLimitExceeded, Verdict::TimeLimitExceeded),
+        (
+            RunInfoResult::WallTimeLimitExceeded { derived: false },
+            Verdict::TimeLimitExceeded,
+        ),
+        (
+            RunInfoResult::MemoryLimitExceeded,
+            Verdict::MemoryLimitExceeded,
+        ),
+        (
+            RunInfoResult::KilledByOomKiller,
+            Verdict::MemoryLimitExceeded,
+        ),
+        (
+            RunInfoResult::OutputLimitExceeded {
+                written: SpaceUsage::from_kibibytes(5),
+            },
+            Verdict::OutputLimitExceeded,
+        ),
+        (
+            RunInfoResult::NetworkLimitExceeded,
+            Verdict::OutputLimitExceeded,
+        ),
+    ] {
+        assert_eq!(verdict(result.clone(), None, within()), expected);
+    }
+
+    // Exited with status 0 within the kill grace period, or between two samples
+    assert_eq!(
+        verdict(RunInfoResult::Success(()), Some(0), usage(1100, 1200, 16)),
+        Verdict::TimeLimitExceeded
+    );
+    assert_eq!(
+        verdict(RunInfoResult::Success(()), Some(0), usage(500, 2100, 16)),
+        Verdict::TimeLimitExceeded
+    );
+    assert_eq!(
+        verdict(RunInfoResult::Success(()), Some(0), usage(500, 600, 65)),
+        Verdict::MemoryLimitExceeded
+    );
+    let mut over_output = within();
+    over_output.set_output_bytes(SpaceUsage::from_kibibytes(5));
+    assert_eq!(
+        verdict(RunInfoResult::Success(()), Some(0), over_output),
+        Verdict::OutputLimitExceeded
+    );
+    let mut over_network = within();
+    over_network.set_net_tx_bytes(SpaceUsage::from_kibibytes(5));
+    assert_eq!(
+        verdict(RunInfoResult::KilledByHost, None, over_network),
+        Verdict::OutputLimitExceeded
+    );
+
+    // Memory beats time beats output, unless told otherwise
+    let mut over_all = usage(500, 600, 65);
+    over_all.set_output_bytes(SpaceUsage::from_kibibytes(5));
+    let run_info: RunInfo<()> = RunInfo::new(RunInfoResult::TimeLimitExceeded, over_all);
+    assert_eq!(run_info.verdict(&limits), Verdict::MemoryLimitExceeded);
+    assert_eq!(
+        run_info.verdict_with_precedence(&limits, &[Verdict::OutputLimitExceeded]),
+        Verdict::OutputLimitExceeded
+    );
+    assert_eq!(
+        run_info.verdict_with_precedence(&limits, &[Verdict::TimeLimitExceeded]),
+        Verdict::TimeLimitExceeded
+    );
+    assert_eq!(
+        run_info.verdict_with_precedence(&limits, &[]),
+        Verdict::MemoryLimitExceeded
+    );
+
+    let verdicts = [
+        Verdict::Accepted,
+        Verdict::TimeLimitExceeded,
+        Verdict::MemoryLimitExceeded,
+        Verdict::OutputLimitExceeded,
+        Verdict::RuntimeError,
+        Verdict::Skipped,
+    ];
+    let abbreviations: Vec<_> = verdicts.iter().map(Verdict::to_string).collect();
+    assert_eq!(abbreviations, ["AC", "TL", "ML", "OL", "RE", "SK"]);
+}
+
+#[test]
+fn test_run_usage_difference() {
+    let usage = |user_time, wall_time, percpu: [u64; 2], io_read, throttled| {
+        let mut usage = RunUsage::new(
+            Duration::from_millis(user_time),
+            Duration::from_millis(wall_time),
+            SpaceUsage::from_megabytes(user_time / 100),
+            SpaceUsage::from_megabytes(1),
+        );
+        usage.set_cpu_usage_percpu(Some(
+            percpu
+                .iter()
+                .map(|&time| Duration::from_millis(time))
+                .collect(),
+        ));
+        usage.set_io_read(SpaceUsage::from_bytes(io_read));
+        usage.set_throttled_time(Duration::from_millis(throttled));
+        usage.set_nr_throttled(Some(throttled / 10));
+        usage.set_output_bytes(SpaceUsage::from_bytes(wall_time));
+        usage
+    };
+    // The instance is not cleared in between, the counters go on from the first run
+    let earlier = usage(1000, 1500, [600, 400], 100, 100);
+    let later = usage(1500, 800, [900, 600], 300, 300);
+
+    let difference = later.checked_sub(&earlier).unwrap();
+    assert_eq!(difference.user_time(), Duration::from_millis(500));
+    assert_eq!(
+        difference.cpu_usage_percpu(),
+        Some(&[Duration::from_millis(300), Duration::from_millis(200)][..])
+    );
+    assert_eq!(difference.io_read(), SpaceUsage::from_bytes(200));
+    assert_eq!(difference.throttled_time(), Duration::from_millis(200));
+    assert_eq!(difference.nr_throttled(), Some(20));
+    // The wall time, the peak memory and the output were for the later run alone
+    assert_eq!(difference.wall_time(), Duration::from_millis(800));
+    assert_eq!(difference.memory(), SpaceUsage::from_megabytes(15));
+    assert_eq!(difference.output_bytes(), SpaceUsage::from_bytes(800));
+    assert_eq!(earlier.saturating_add(&difference), later);
+
+    assert_eq!(earlier.checked_sub(&later), None);
+    let mut fewer_cpus = earlier.clone();
+    fewer_cpus.set_cpu_usage_percpu(Some(vec![Duration::from_millis(1000)]));
+    assert_eq!(
+        later.checked_sub(&fewer_cpus).unwrap().cpu_usage_percpu(),
+        None
+    );
+
+    let mut limits = LimitsBuilder::new();
+    limits.user_time(Duration::from_millis(600));
+    let user_time_limits = limits.into();
+    assert_eq!(difference.exceeds(&user_time_limits), None);
+    assert_eq!(
+        later.exceeds(&user_time_limits),
+        Some(ExceededLimit::UserTime)
+    );
+    limits.output(SpaceUsage::from_bytes(500));
+    assert_eq!(
+        difference.exceeds(&limits.into()),
+        Some(ExceededLimit::Output)
+    );
+}
+
+#[test]
+fn test_wall_time_limit_exceeded() {
+    let mut limits = LimitsBuilder::new();
+    limits.wall_time(Duration::from_millis(1200));
+
+    TestRunnerHelper::for_simple_exec(
+        "test_wall_time_limit_exceeded",
+        SLEEP_1_SECOND,
+        PivotRoot::Pivot,
+    )
+    .config_builder()
+    .limits(limits)
+    .build_and_run()
+    .unwrap()
+    .assert(CompareLimits::new(IsSuccess, limits));
+
+    limits.wall_time(Duration::from_millis(800));
+    TestRunnerHelper::for_simple_exec(
+        "test_wall_time_limit_exceeded",
+        SLEEP_1_SECOND,
+        PivotRoot::Pivot,
+    )
+    .config_builder()
+    .limits(limits)
+    .build_and_run()
+    .unwrap()
+    .assert(CompareLimits::new(WallTimeLimitExceeded, limits));
+}
+
+#[test]
+fn test_wall_time_excludes_setup() {
+    let temp_dir = Builder::new()
+        .prefix("test_wall_time_excludes_setup")
+        .tempdir()
+        .unwrap();
+    let mut builder = TestRunnerHelper::for_simple_exec(
+        "test_wall_time_excludes_setup",
+        SLEEP_1_SECOND,
+        PivotRoot::Pivot,
+    );
+    let config_builder = builder.config_builder();
+    // Enough mounts for the setup to take a while
+    for index in 0..32 {
+        config_builder
+            .mount(Mount::new(
+                temp_dir.path().into(),
+                format!("/bind/{}", index).into(),
+                MountOptions::default(),
+            ))
+            .mount(Mount::tmpfs(
+                format!("/tmpfs/{}", index).into(),
+                None,
+                MountOptions::default(),
+            ));
+    }
+    let run_info = config_builder.build_and_run().unwrap();
+    let usage = run_info.usage().clone();
+    run_info.assert(IsSuccess);
+    assert!(usage.wall_time() >= Duration::from_secs(1), "{}", usage);
+    assert!(usage.wall_time() < Duration::from_millis(1100), "{}", usage);
+    assert!(usage.setup_time() > Duration::from_secs(0), "{}", usage);
+}
+
+#[test]
+fn test_on_usage() {
+    let mut helper =
+        TestRunnerHelper::for_simple_exec("test_on_usage", SLEEP_1_SECOND, PivotRoot::Pivot);
+    // The callback runs in the supervisor, what it gets comes back through a file
+    let samples_path = helper.file_path("samples");
+    let on_usage = {
+        let samples_path = samples_path.clone();
+        UsageCallback::new(move |usage| {
+            let mut samples = OpenOptions::new()
+                .append(true)
+                .create(true)
+                .open(&samples_path)
+                .unwrap();
+            writeln!(samples, "{}", usage.wall_time().as_nanos()).unwrap();
+        })
+    };
+    helper
+        .config_builder()
+        .on_usage(on_usage)
+        .build_and_run()
+        .unwrap()
+        .assert(IsSuccess);
+
+    let samples: Vec<u128> = fs::read_to_string(&samples_path)
+        .unwrap()
+        .lines()
+        .map(|line| line.parse().unwrap())
+        .collect();
+    assert!(
+        samples.windows(2).all(|pair| pair[0] <= pair[1]),
+        "{:?}",
+        samples
+    );
+    let mut distinct = samples.clone();
+    distinct.dedup();
+    assert!(distinct.len() >= 3, "{:?}", samples);
+}
+
+#[test]
+fn test_wall_time_derivation() {
+    let derivation = WallTimeDerivation::new(1.5, Duration::from_millis(100));
+    assert_eq!(
+        derivation.wall_time(Duration::from_secs(1)),
+        Duration::from_millis(1_600)
+    );
+    assert_eq!(derivation.factor(), 1.5);
+
+    // Rounds to the nearest nanosecond
+    let derivation = WallTimeDerivation::new(0.5, Duration::from_secs(0));
+    assert_eq!(
+        derivation.wall_time(Duration::from_nanos(3)),
+        Duration::from_nanos(2)
+    );
+    let derivation = WallTimeDerivation::new(1.0 / 3.0, Duration::from_secs(0));
+    assert_eq!(
+        derivation.wall_time(Duration::from_nanos(1)),
+        Duration::from_nanos(0)
+    );
+
+    // Saturates instead of overflowing
+    let max_duration = Duration::new(u64::MAX, 999_999_999);
+    let derivation = WallTimeDerivation::new(2.0, Duration::from_secs(0));
+    assert_eq!(derivation.wall_time(max_duration), max_duration);
+    let derivation = WallTimeDerivation::new(1e12, Duration::from_secs(0));
+    assert_eq!(derivation.wall_time(max_duration), max_duration);
+    let derivation = WallTimeDerivation::new(1.0, Duration::from_secs(1));
+    assert_eq!(derivation.wall_time(max_duration), max_duration);
+}
+
+#[test]
+fn test_derived_wall_time_limit_exceeded() {
+    let mut limits = LimitsBuilder::new();
+    limits
+        .user_time(Duration::from_millis(200))
+        .derive_wall_from_user(2.0, Duration::from_millis(100));
+
+    let run_info = TestRunnerHelper::for_simple_exec(
+        "test_derived_wall_time_limit_exceeded",
+        SLEEP_1_SECOND,
+        PivotRoot::Pivot,
+    )
+    .config_builder()
+    .limits(limits)
+    .build_and_run()
+    .unwrap();
+    assert_eq!(
+        run_info.result(),
+        &RunInfoResult::WallTimeLimitExceeded { derived: true }
+    );
+    let derived_wall_time = run_info.derived_wall_time().unwrap();
+    assert_eq!(derived_wall_time.wall_time(), Duration::from_millis(500));
+    assert_eq!(derived_wall_time.derivation().factor(), 2.0);
+    assert_eq!(
+        derived_wall_time.derivation().slack(),
+        Duration::from_millis(100)
+    );
+
+    // Without a user time limit there is nothing to derive from
+    let mut limits = LimitsBuilder::new();
+    limits.derive_wall_from_user(2.0, Duration::from_millis(100));
+    match TestRunnerHelper::for_simple_exec(
+        "test_derived_wall_time_limit_exceeded",
+        SLEEP_1_SECOND,
+        PivotRoot::Pivot,
+    )
+    .config_builder()
+    .limits(limits)
+    .build_and_run()
+    .unwrap_err()
+    .into_config_error()
+    {
+        ConfigError::DerivedWallTimeWithoutUserTime => (),
+        err => panic!("Expected derived wall time error, got {}", err),
+    }
+
+    // Nor from a factor that is not positive
+    for &factor in &[0.0, -1.0, f64::NAN] {
+        let mut limits = LimitsBuilder::new();
+        limits
+            .user_time(Duration::from_millis(100))
+            .derive_wall_from_user(factor, Duration::from_millis(100));
+        match TestRunnerHelper::for_simple_exec(
+            "test_derived_wall_time_limit_exceeded",
+            SLEEP_1_SECOND,
+            PivotRoot::Pivot,
+        )
+        .config_builder()
+        .limits(limits)
+        .build_and_run()
+        .unwrap_err()
+        .into_config_error()
+        {
+            ConfigError::InvalidWallTimeFactor => (),
+            err => panic!(
+                "Expected wall time factor error for {}, got {}",
+                factor, err
+            ),
+        }
+    }
+}
+
+#[test]
+fn test_time_limit_exceeded() {
+    let mut limits = LimitsBuilder::new();
+    limits.user_time(Duration::from_millis(600));
+
+    TestRunnerHelper::for_simple_exec("test_time_limit_exceeded", LOOP_500_MS, PivotRoot::Pivot)
+        .config_builder()
+        .limits(limits)
+        .build_and_run()
+        .unwrap()
+        .assert(CompareLimits::new(IsSuccess, limits));
+
+    limits.user_time(Duration::from_millis(450));
+    TestRunnerHelper::for_simple_exec("test_time_limit_exceeded", LOOP_500_MS, PivotRoot::Pivot)
+        .config_builder()
+        .limits(limits)
+        .build_and_run()
+        .unwrap()
+        .assert(CompareLimits::new(TimeLimitExceeded, limits));
+}
+
+#[test]
+fn test_threads_time_limit_exceeded() {
+    let mut limits = LimitsBuilder::new();
+    limits.user_time(Duration::from_millis(600));
+
+    TestRunnerHelper::for_simple_exec(
+        "test_threads_time_limit_exceeded",
+        THREADS_LOOP_500_MS,
+        PivotRoot::Pivot,
+    )
+    .config_builder()
+    .limits(limits)
+    .build_and_run()
+    .unwrap()
+    .assert(CompareLimits::new(IsSuccess, limits));
+
+    limits.user_time(Duration::from_millis(450));
+    TestRunnerHelper::for_simple_exec(
+        "test_threads_time_limit_exceeded",
+        THREADS_LOOP_500_MS,
+        PivotRoot::Pivot,
+    )
+    .config_builder()
+    .limits(limits)
+    .build_and_run()
+    .unwrap()
+    .assert(CompareLimits::new(TimeLimitExceeded, limits));
+}
+
+#[test]
+fn test_threads_wall_time_limit_exceeded() {
+    let mut limits = LimitsBuilder::new();
+    limits.wall_time(Duration::from_millis(1200));
+
+    TestRunnerHelper::for_simple_exec(
+        "test_threads_wall_time_limit_exceeded",
+        THREADS_SLEEP_1_SECOND,
+        PivotRoot::Pivot,
+    )
+    .config_builder()
+    .limits(limits)
+    .build_and_run()
+    .unwrap()
+    .assert(CompareLimits::new(IsSuccess, limits));
+
+    limits.wall_time(Duration::from_millis(800));
+    TestRunnerHelper::for_simple_exec(
+        "test_threads_wall_time_limit_exceeded",
+        THREADS_SLEEP_1_SECOND,
+        PivotRoot::Pivot,
+    )
+    .config_builder()
+    .limits(limits)
+    .build_and_run()
+    .unwrap()
+    .assert(CompareLimits::new(WallTimeLimitExceeded, limits));
+}
+
+#[test]
+fn test_stderr_tail() {
+    let mut helper = TestRunnerHelper::for_simple_exec(
+        "test_stderr_tail",
+        LAST_WORDS_THEN_ABORT,
+        PivotRoot::Pivot,
+    );
+    let stderr_path = helper.file_path("stderr");
+    for redirect in vec![
+        Redirect::Capture {
+            limit: SpaceUsage::from_bytes(16),
+        },
+        Redirect::File(stderr_path.clone(), RedirectOptions::default()),
+        Redirect::Tee {
+            file: stderr_path,
+            limit: None,
+        },
+    ] {
+        let run_info = helper
+            .config_builder()
+            .share_pid(SharePid::Share)
+            .redirect_stderr(redirect)
+            .stderr_tail(SpaceUsage::from_bytes(LAST_WORDS.len() as u64))
+            .build_and_run()
+            .unwrap();
+        assert_eq!(run_info.stderr_tail(), Some(LAST_WORDS));
+        run_info.assert(Aborted);
+    }
+
+    // The default size, the builder of the helper keeps the one set above
+    let mut helper = TestRunnerHelper::for_simple_exec(
+        "test_stderr_tail",
+        LAST_WORDS_THEN_ABORT,
+        PivotRoot::Pivot,
+    );
+    let run_info = helper
+        .config_builder()
+        .share_pid(SharePid::Share)
+        .redirect_stderr(Redirect::Capture {
+            limit: SpaceUsage::from_bytes(16),
+        })
+        .build_and_run()
+        .unwrap();
+    let stderr_tail = run_info.stderr_tail().unwrap().to_vec();
+    run_info.assert(Aborted);
+    assert_eq!(stderr_tail.len(), 4 * 1_024);
+    assert!(stderr_tail.ends_with(LAST_WORDS));
+
+    let run_info =
+        TestRunnerHelper::for_simple_exec("test_stderr_tail", HELLO_WORLD, PivotRoot::Pivot)
+            .config_builder()
+            .redirect_stderr(Redirect::Capture {
+                limit: SpaceUsage::from_bytes(16),
+            })
+            .build_and_run()
+            .unwrap();
+    assert!(run_info.stderr_tail().is_none());
+    run_info.assert(IsSuccess);
+}
+
+#[test]
+fn test_kill() {
+    let mut helper =
+        TestRunnerHelper::for_simple_exec("test_kill", SLEEP_1_SECOND, PivotRoot::Pivot);
+    let handle = helper.config_builder().build_and_spawn().unwrap();
+    thread::sleep(Duration::from_millis(100));
+    let timer = Instant::now();
+    handle.kill().unwrap();
+    handle.kill().unwrap();
+    handle.wait().unwrap().assert(Cancelled);
+    assert!(timer.elapsed() < Duration::from_millis(500));
+
+    // Long done by the time of the kill
+    let mut helper = TestRunnerHelper::for_simple_exec("test_kill", HELLO_WORLD, PivotRoot::Pivot);
+    let handle = helper.config_builder().build_and_spawn().unwrap();
+    thread::sleep(Duration::from_millis(500));
+    handle.kill().unwrap();
+    handle.wait().unwrap().assert(IsSuccess);
+}
+
+#[test]
+fn test_try_wait() {
+    let mut helper =
+        TestRunnerHelper::for_simple_exec("test_try_wait", SLEEP_1_SECOND, PivotRoot::Pivot);
+    let mut handle = helper.config_builder().build_and_spawn().unwrap();
+    for _ in 0..3 {
+        assert!(handle.try_wait().unwrap().is_none());
+        thread::sleep(Duration::from_millis(100));
+    }
+
+    let timer = Instant::now();
+    let run_info = loop {
+        if let Some(run_info) = handle.try_wait().unwrap() {
+            break run_info;
+        }
+        assert!(timer.elapsed() < Duration::from_secs(5));
+        thread::sleep(Duration::from_millis(50));
+    };
+    run_info.clone().assert(IsSuccess);
+    // Done once, the same result every time after
+    assert_eq!(handle.try_wait().unwrap(), Some(run_info.clone()));
+    assert_eq!(handle.wait().unwrap(), run_info);
+}
+
+#[test]
+fn test_is_running() {
+    let mut helper =
+        TestRunnerHelper::for_simple_exec("test_is_running", SLEEP_300_MS, PivotRoot::Pivot);
+    let mut handle = helper.config_builder().build_and_spawn().unwrap();
+    assert_eq!(handle.instance_name(), Some(OsStr::new("test_is_running")));
+    assert!(handle.is_running());
+
+    thread::sleep(Duration::from_secs(1));
+    // Over, and still so once the result is taken
+    assert!(!handle.is_running());
+    let run_info = handle.try_wait().unwrap().unwrap();
+    run_info.clone().assert(IsSuccess);
+    assert!(!handle.is_running());
+    assert_eq!(handle.wait().unwrap(), run_info);
+}
+
+#[test]
+fn test_poll_fd() {
+    let mut helper =
+        TestRunnerHelper::for_simple_exec("test_poll_fd", SLEEP_1_SECOND, PivotRoot::Pivot);
+    let mut handle = helper.config_builder().build_and_spawn().unwrap();
+    let timer = Instant::now();
+    let mut wake_ups = 0;
+    let run_info = loop {
+        let mut pollfd = libc::pollfd {
+            fd: handle.poll_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        assert_eq!(unsafe { libc::poll(&mut pollfd, 1, 5_000) }, 1);
+        wake_ups += 1;
+        if let Some(run_info) = handle.take_result().unwrap() {
+            break run_info;
+        }
+    };
+    assert!(timer.elapsed() >= Duration::from_millis(900));
+    // Only for the messages of the supervisor, not busy
+    assert!(wake_ups < 10, "woke up {} times", wake_ups);
+    run_info.assert(IsSuccess);
+}
+
+#[test]
+fn test_child_pid() {
+    let mut helper =
+        TestRunnerHelper::for_simple_exec("test_child_pid", SLEEP_1_SECOND, PivotRoot::Pivot);
+    let mut handle = helper.config_builder().build_and_spawn().unwrap();
+    assert!(Path::new(&format!("/proc/{}", handle.supervisor_pid())).exists());
+
+    // The clone of the supervisor until the exec goes through
+    let timer = Instant::now();
+    let child_pid = loop {
+        if let Some(child_pid) = handle.child_pid() {
+            let cmdline = fs::read(format!("/proc/{}/cmdline", child_pid)).unwrap_or_default();
+            if cmdline.ends_with(b"sleep_1_second\0") {
+                break child_pid;
+            }
+        }
+        assert!(timer.elapsed() < Duration::from_millis(800));
+        thread::sleep(Duration::from_millis(1));
+    };
+    assert_ne!(child_pid, handle.supervisor_pid());
+    assert_eq!(
+        helper.wait_for_exec(&mut handle, "sleep_1_second"),
+        child_pid,
+        "the pid in the cgroup of the run"
+    );
+
+    handle.wait().unwrap().assert(IsSuccess);
+}
+
+#[test]
+fn test_wait_timeout() {
+    let mut helper =
+        TestRunnerHelper::for_simple_exec("test_wait_timeout", SLEEP_1_SECOND, PivotRoot::Pivot);
+    let mut handle = helper.config_builder().build_and_spawn().unwrap();
+    let timer = Instant::now();
+    assert!(handle
+        .wait_timeout(Duration::from_millis(200))
+        .unwrap()
+        .is_none());
+    assert!(timer.elapsed() >= Duration::from_millis(200));
+    assert!(timer.elapsed() < Duration::from_millis(800));
+
+    // Returns as soon as the run is over, well before the timeout
+    let run_info = handle
+        .wait_timeout(Duration::from_secs(10))
+        .unwrap()
+        .unwrap();
+    assert!(timer.elapsed() < Duration::from_secs(5));
+    run_info.assert(IsSuccess);
+
+    let mut helper =
+        TestRunnerHelper::for_simple_exec("test_wait_timeout", HELLO_WORLD, PivotRoot::Pivot);
+    let mut handle = helper.config_builder().build_and_spawn().unwrap();
+    handle
+        .wait_timeout(Duration::from_secs(5))
+        .unwrap()
+        .unwrap()
+        .assert(IsSuccess);
+}
+
+#[test]
+fn test_pause() {
+    let mut limits = LimitsBuilder::new();
+    limits.wall_time(Duration::from_millis(1500));
+    let mut helper =
+        TestRunnerHelper::for_simple_exec("test_pause", SLEEP_1_SECOND, PivotRoot::Pivot);
+    let mut handle = helper
+        .config_builder()
+        .limits(limits)
+        .pausable(Pausable::Yes)
+        .build_and_spawn()
+        .unwrap();
+    helper.wait_for_exec(&mut handle, "sleep_1_second");
+    thread::sleep(Duration::from_millis(500));
+
+    handle.pause().unwrap();
+    handle.pause().unwrap();
+    thread::sleep(Duration::from_secs(1));
+    handle.resume().unwrap();
+
+    // The sleep went on meanwhile, the wall time only counts the run outside of the pause
+    let run_info = handle.wait().unwrap();
+    let usage = run_info.usage().clone();
+    run_info.assert(IsSuccess);
+    assert!(usage.paused_time() >= Duration::from_secs(1));
+    assert!(usage.paused_time() < Duration::from_millis(1300));
+    assert!(usage.wall_time() < Duration::from_millis(1000));
+
+    // Not without asking for it
+    let mut helper = TestRunnerHelper::for_simple_exec("test_pause", HELLO_WORLD, PivotRoot::Pivot);
+    let handle = helper.config_builder().build_and_spawn().unwrap();
+    assert!(handle.pause().is_err());
+    handle.wait().unwrap().assert(IsSuccess);
+}
+
+#[test]
+fn test_signal() {
+    let capture = Redirect::Capture {
+        limit: SpaceUsage::from_kibibytes(4),
+    };
+    let mut helper =
+        TestRunnerHelper::for_simple_exec("test_signal", FLUSH_ON_SIGTERM, PivotRoot::Pivot);
+    let mut handle = helper
+        .config_builder()
+        .redirect_stdout(capture)
+        .build_and_spawn()
+        .unwrap();
+    helper.wait_for_exec(&mut handle, "flush_on_sigterm");
+    thread::sleep(Duration::from_millis(200));
+
+    assert!(handle.signal(0).is_err());
+    assert!(handle.signal(libc::SIGRTMAX() + 1).is_err());
+    handle.signal(libc::SIGTERM).unwrap();
+
+    let timer = Instant::now();
+    let run_info = loop {
+        if let Some(run_info) = handle.try_wait().unwrap() {
+            break run_info;
+        }
+        assert!(timer.elapsed() < Duration::from_secs(5));
+        thread::sleep(Duration::from_millis(50));
+    };
+    // Not to a run that is over
+    assert!(handle.signal(libc::SIGTERM).is_err());
+    let stdout = run_info.stdout().cloned().unwrap();
+    run_info.assert(IsSuccess);
+    assert_eq!(stdout.data(), b"flushed\n");
+}
+
+#[test]
+fn test_recovery_token() {
+    let mut helper =
+        TestRunnerHelper::for_simple_exec("test_recovery_token", SLEEP_300_MS, PivotRoot::Pivot);
+    let result_path = helper.file_path("result.json");
+    let handle = helper
+        .config_builder()
+        .detached(&result_path)
+        .build_and_spawn()
+        .unwrap();
+    let supervisor_pid = handle.supervisor_pid();
+    // Through a restart of the caller, say
+    let token = serde_json::to_string(&handle.into_recovery_token().unwrap()).unwrap();
+    let token: RecoveryToken = serde_json::from_str(&token).unwrap();
+    assert_eq!(token.supervisor_pid(), supervisor_pid);
+    assert_eq!(
+        token.instance_name(),
+        Some(OsStr::new("test_recovery_token"))
+    );
+
+    let adopted = AdoptedJail::from_recovery_token(token.clone()).unwrap();
+    assert!(adopted.is_running());
+    let run_info = adopted.wait().unwrap();
+    run_info.clone().assert(IsSuccess);
+
+    // Over already, the result is there
+    let mut adopted = AdoptedJail::from_recovery_token(token.clone()).unwrap();
+    assert!(!adopted.is_running());
+    assert_eq!(adopted.try_wait().unwrap(), Some(run_info));
+
+    // Stale, the supervisor is gone without one
+    fs::remove_file(&result_path).unwrap();
+    assert!(AdoptedJail::from_recovery_token(token).is_err());
+
+    // Not without asking for it
+    let mut helper =
+        TestRunnerHelper::for_simple_exec("test_recovery_token", HELLO_WORLD, PivotRoot::Pivot);
+    let handle = helper.config_builder().build_and_spawn().unwrap();
+    assert!(handle.into_recovery_token().is_err());
+}
+
+#[test]
+fn test_status() {
+    let mut limits = LimitsBuilder::new();
+    limits.wall_time(Duration::from_millis(500));
+    let mut helper =
+        TestRunnerHelper::for_simple_exec("test_status", SLEEP_1_SECOND, PivotRoot::Pivot);
+    let mut handle = helper
+        .config_builder()
+        .limits(limits)
+        .build_and_spawn()
+        .unwrap();
+    helper.wait_for_exec(&mut handle, "sleep_1_second");
+    let status = handle.status().unwrap();
+    assert!(
+        status.remaining_wall_time().unwrap() <= Duration::from_millis(500),
+        "{:?}",
+        status
+    );
+    assert!(
+        status.remaining_wall_time().unwrap() + status.usage().wall_time()
+            >= Duration::from_millis(500),
+        "{:?}",
+        status
+    );
+    handle.wait().unwrap().assert(WallTimeLimitExceeded);
+
+    // Asked by the process that adopted it, after a restart of the caller
+    let result_path = helper.file_path("result.json");
+    let mut handle = helper
+        .config_builder()
+        .detached(&result_path)
+        .build_and_spawn()
+        .unwrap();
+    helper.wait_for_exec(&mut handle, "sleep_1_second");
+    let token = serde_json::to_string(&handle.into_recovery_token().unwrap()).unwrap();
+    let adopted = AdoptedJail::from_recovery_token(serde_json::from_str(&token).unwrap()).unwrap();
+    let status = adopted.status().unwrap();
+    assert!(
+        status.remaining_wall_time().unwrap() <= Duration::from_millis(500),
+        "{:?}",
+        status
+    );
+    // The supervisor still enforces the limit
+    let adopted_again =
+        AdoptedJail::from_recovery_token(serde_json::from_str(&token).unwrap()).unwrap();
+    adopted.wait().unwrap().assert(WallTimeLimitExceeded);
+    assert!(!helper.file_path("result.json.control").exists());
+    match adopted_again.status() {
+        Err(Error::FFIError(FFIError::StatusError(_))) => {}
+        result => panic!("Expected status error, got {:?}", result),
+    }
+}
+
+#[test]
+fn test_drop_kills() {
+    let mut helper =
+        TestRunnerHelper::for_simple_exec("test_drop_kills", SLEEP_1_SECOND, PivotRoot::Pivot);
+    let mut handle = helper.config_builder().build_and_spawn().unwrap();
+    let supervisor_pid = handle.supervisor_pid();
+    let pid = helper.wait_for_exec(&mut handle, "sleep_1_second");
+    drop(handle);
+
+    thread::sleep(Duration::from_millis(100));
+    // Reaped, not left a zombie
+    assert!(!Path::new(&format!("/proc/{}", supervisor_pid)).exists());
+    let cmdline = fs::read(format!("/proc/{}/cmdline", pid)).unwrap_or_default();
+    assert!(!cmdline.ends_with(b"sleep_1_second\0"));
+}
+
+#[test]
+fn test_killed_by_host() {
+    let mut helper =
+        TestRunnerHelper::for_simple_exec("test_killed_by_host", SLEEP_1_SECOND, PivotRoot::Pivot);
+    let mut handle = helper.config_builder().build_and_spawn().unwrap();
+
+    let pid = helper.wait_for_exec(&mut handle, "sleep_1_second");
+    unsafe { libc::kill(pid, libc::SIGKILL) };
+
+    handle.wait().unwrap().assert(KilledByHost);
+}
+
+#[test]
+fn test_killed_by_itself() {
+    // Sharing the pid namespace its SIGKILL is no sign of the host
+    TestRunnerHelper::for_simple_exec("test_killed_by_itself", SIGKILL_SELF, PivotRoot::Pivot)
+        .config_builder()
+        .share_pid(SharePid::Share)
+        .build_and_run()
+        .unwrap()
+        .assert(KilledBySignal(9));
+
+    // As the init of its own pid namespace it can not SIGKILL itself
+    TestRunnerHelper::for_simple_exec("test_killed_by_itself", SIGKILL_SELF, PivotRoot::Pivot)
+        .config_builder()
+        .build_and_run()
+        .unwrap()
+        .assert(NonZeroExitStatus::new(3));
+
+    // Neither is one sent by the caller
+    let mut helper = TestRunnerHelper::for_simple_exec(
+        "test_killed_by_itself",
+        SLEEP_1_SECOND,
+        PivotRoot::Pivot,
+    );
+    let mut handle = helper.config_builder().build_and_spawn().unwrap();
+    let _ = helper.wait_for_exec(&mut handle, "sleep_1_second");
+    handle.signal(libc::SIGKILL).unwrap();
+    handle.wait().unwrap().assert(KilledBySignal(9));
+}
+
+fn run_stopped_for_1_second(
+    limits: LimitsBuilder,
+    exclude_stopped_time: ExcludeStoppedTime,
+) -> RunInfo<()> {
+    let mut helper =
+        TestRunnerHelper::for_simple_exec("test_stopped_time", SLEEP_300_MS, PivotRoot::Pivot);
+    let mut handle = helper
+        .config_builder()
+        .limits(limits)
+        .exclude_stopped_time(exclude_stopped_time)
+        .build_and_spawn()
+        .unwrap();
+
+    let pid = helper.wait_for_exec(&mut handle, "sleep_300_ms");
+    unsafe { libc::kill(pid, libc::SIGSTOP) };
+    thread::sleep(Duration::from_secs(1));
+    unsafe { libc::kill(pid, libc::SIGCONT) };
+
+    handle.wait().unwrap()
+}
+
+#[test]
+fn test_stopped_time() {
+    let run_info = run_stopped_for_1_second(LimitsBuilder::new(), ExcludeStoppedTime::No);
+    let usage = run_info.usage().clone();
+    run_info.assert(IsSuccess);
+    assert!(
+        usage.stopped_time() >= Duration::from_millis(900),
+        "{}",
+        usage
+    );
+    assert!(usage.wall_time() >= usage.stopped_time(), "{}", usage);
+
+    let mut limits = LimitsBuilder::new();
+    limits.wall_time(Duration::from_millis(800));
+    run_stopped_for_1_second(limits, ExcludeStoppedTime::No)
+        .assert(CompareLimits::new(WallTimeLimitExceeded, limits));
+
+    let run_info = run_stopped_for_1_second(limits, ExcludeStoppedTime::Yes);
+    let usage = run_info.usage().clone();
+    run_info.assert(CompareLimits::new(IsSuccess, limits));
+    assert!(
+        usage.stopped_time() >= Duration::from_millis(900),
+        "{}",
+        usage
+    );
+    assert!(usage.wall_time() < Duration::from_millis(800), "{}", usage);
+}
+
+#[test]
+fn test_cpu_quota() {
+    let mut limits = LimitsBuilder::new();
+    limits.cpu_quota(0.5);
+
+    let run_info =
+        TestRunnerHelper::for_simple_exec("test_cpu_quota", THREADS_LOOP_500_MS, PivotRoot::Pivot)
+            .config_builder()
+            .limits(limits)
+            .build_and_run()
+            .unwrap();
+    let usage = run_info.usage().clone();
+    run_info.assert(IsSuccess);
+    // The throttling is told by cpu.stat, the times only roughly: the threads together would
+    // take several times the wall time, half a core keeps them below it
+    assert!(usage.throttled_time() > Duration::from_secs(0), "{}", usage);
+    assert!(usage.nr_throttled().unwrap_or(0) > 0, "{}", usage);
+    assert!(usage.user_time() < usage.wall_time(), "{}", usage);
+}
+
+#[test]
+fn test_cpu_usage_percpu() {
+    let run_info = TestRunnerHelper::for_simple_exec(
+        "test_cpu_usage_percpu",
+        THREADS_LOOP_500_MS,
+        PivotRoot::Pivot,
+    )
+    .config_builder()
+    .build_and_run()
+    .unwrap();
+    let usage = run_info.usage().clone();
+    run_info.assert(IsSuccess);
+    assert_eq!(usage.nr_throttled(), None, "{}", usage);
+
+    // cgroups v2 does not account per CPU
+    if let Some(cpu_usage_percpu) = usage.cpu_usage_percpu() {
+        let total: Duration = cpu_usage_percpu.iter().sum();
+        // Both are read separately, the program can still run in between
+        let difference = cmp::max(total, usage.user_time()) - cmp::min(total, usage.user_time());
+        assert!(
+            difference <= usage.user_time() / 20 + Duration::from_millis(10),
+            "{}",
+            usage
+        );
+    }
+}
+
+#[test]
+fn test_cpuset() {
+    let mut limits = LimitsBuilder::new();
+    limits.cpus("0");
+
+    TestRunnerHelper::for_simple_exec("test_cpuset", CHECK_CPUS_ALLOWED_LIST, PivotRoot::Pivot)
+        .config_builder()
+        .arg("0")
+        .limits(limits)
+        .build_and_run()
+        .unwrap()
+        .assert(IsSuccess);
+    assert_eq!(
+        fs::read_to_string("/sys/fs/cgroup/cpuset/ia-sandbox/test_cpuset/cpuset.cpus")
+            .unwrap()
+            .trim(),
+        "0"
+    );
+}
+
+#[test]
+fn test_io_limits() {
+    let mut limits = LimitsBuilder::new();
+    limits.write_bps(SpaceUsage::from_mebibytes(1));
+
+    let mut helper =
+        TestRunnerHelper::for_simple_exec("test_io_limits", WRITE_2_MEGABYTES, PivotRoot::Pivot);
+    // The root isolation mode runs it as nobody
+    fs::set_permissions(helper.file_path(""), fs::Permissions::from_mode(0o777)).unwrap();
+    let run_info = helper
+        .config_builder()
+        .limits(limits)
+        .build_and_run()
+        .unwrap();
+    let usage = run_info.usage().clone();
+    run_info.assert(IsSuccess);
+    assert!(
+        usage.io_written() >= SpaceUsage::from_mebibytes(2),
+        "{}",
+        usage
+    );
+    // 2 mebibytes at 1 mebibyte per second, give or take the initial burst
+    assert!(usage.wall_time() >= Duration::from_millis(900), "{}", usage);
+}
+
+#[test]
+fn test_net_usage() {
+    let run_info =
+        TestRunnerHelper::for_simple_exec("test_net_usage", SEND_OVER_LOOPBACK, PivotRoot::Pivot)
+            .config_builder()
+            .arg("5")
+            .build_and_run()
+            .unwrap();
+    let usage = run_info.usage().clone();
+    run_info.assert(IsSuccess);
+    // Loopback counts everything both as received and sent
+    assert!(
+        usage.net_rx_bytes() >= SpaceUsage::from_megabytes(5),
+        "{}",
+        usage
+    );
+    assert!(
+        usage.net_tx_bytes() >= SpaceUsage::from_megabytes(5),
+        "{}",
+        usage
+    );
+}
+
+#[test]
+fn test_net_limit_exceeded() {
+    let mut limits = LimitsBuilder::new();
+    limits.net_bytes(SpaceUsage::from_megabytes(1));
+
+    // The counters of a shared namespace hold the traffic of the host as well
+    match TestRunnerHelper::for_simple_exec(
+        "test_net_limit_exceeded",
+        SEND_OVER_LOOPBACK,
+        PivotRoot::Pivot,
+    )
+    .config_builder()
+    .limits(limits)
+    .arg("20")
+    .build_and_run()
+    .unwrap_err()
+    .into_config_error()
+    {
+        ConfigError::NetBytesWithSharedNet => {}
+        err => panic!("Expected net bytes with shared net error, got {}", err),
+    }
+
+    // Sent to the host over a veth pair, out of a namespace of its own
+    let listener = TcpListener::bind("0.0.0.0:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let server = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        // Cut off by the kill
+        let _ = io::copy(&mut stream, &mut io::sink());
+    });
+
+    TestRunnerHelper::for_simple_exec(
+        "test_net_limit_exceeded",
+        SEND_OVER_LOOPBACK,
+        PivotRoot::Pivot,
+    )
+    .config_builder()
+    .clone_user(CloneUser::No)
+    .share_net(false)
+    .veth(veth_config("ia-test-host3", "ia-test-peer3", 3))
+    .limits(limits)
+    .args(vec![format!("10.231.3.1:{}", port), "20".to_owned()])
+    .build_and_run()
+    .unwrap()
+    .assert(CompareLimits::new(NetworkLimitExceeded, limits));
+
+    server.join().unwrap();
+}
+
+#[test]
+fn test_output_limit_exceeded() {
+    let mut limits = LimitsBuilder::new();
+    limits
+        .wall_time(Duration::from_secs(10))
+        .output(SpaceUsage::from_megabytes(1));
+
+    let run_info = TestRunnerHelper::for_simple_exec(
+        "test_output_limit_exceeded",
+        WRITE_UNTIL_EPIPE,
+        PivotRoot::Pivot,
+    )
+    .config_builder()
+    .limits(limits)
+    .redirect_stdout(Redirect::Capture {
+        limit: SpaceUsage::from_kibibytes(4),
+    })
+    .build_and_run()
+    .unwrap();
+    assert!(run_info.usage().wall_time() < Duration::from_secs(2));
+    if let RunInfoResult::OutputLimitExceeded { written } = *run_info.result() {
+        assert!(written > SpaceUsage::from_megabytes(1), "{}", written);
+    }
+    run_info.assert(OutputLimitExceeded);
+
+    // A pipe to the caller goes through the supervisor, which counts it all the same
+    let mut helper = TestRunnerHelper::for_simple_exec(
+        "test_output_limit_exceeded",
+        WRITE_UNTIL_EPIPE,
+        PivotRoot::Pivot,
+    );
+    let mut handle = helper
+        .config_builder()
+        .limits(limits)
+        .redirect_stdout(Redirect::Pipe)
+        .build_and_spawn()
+        .unwrap();
+    let mut stdout = handle.stdout().unwrap();
+    let reader = thread::spawn(move || io::copy(&mut stdout, &mut io::sink()));
+    let run_info = handle.wait().unwrap();
+    assert!(run_info.usage().wall_time() < Duration::from_secs(2));
+    run_info.assert(OutputLimitExceeded);
+    assert!(reader.join().unwrap().unwrap() > SpaceUsage::from_megabytes(1).as_bytes());
+}
+
+#[test]
+fn test_memory_limit_exceeded() {
+    let mut limits = LimitsBuilder::new();
+    limits.memory(SpaceUsage::from_megabytes(26));
+
+    let run_info = TestRunnerHelper::for_simple_exec(
+        "test_memory_limit_exceeded",
+        ALLOCATE_20_MEGABYTES,
+        PivotRoot::Pivot,
+    )
+    .config_builder()
+    .limits(limits)
+    .build_and_run()
+    .unwrap();
+    // The vector is freed before exiting, only the peak still sees it
+    assert!(
+        run_info.usage().memory() >= SpaceUsage::from_megabytes(20),
+        "Peak memory {} below 20MB",
+        run_info.usage().memory()
+    );
+    run_info.assert(CompareLimits::new(IsSuccess, limits));
+
+    limits.memory(SpaceUsage::from_megabytes(19));
+    TestRunnerHelper::for_simple_exec(
+        "test_memory_limit_exceeded",
+        ALLOCATE_20_MEGABYTES,
+        PivotRoot::Pivot,
+    )
+    .config_builder()
+    .limits(limits)
+    .build_and_run()
+    .unwrap()
+    .assert(CompareLimits::new(MemoryLimitExceeded, limits));
+}
+
+#[test]
+fn test_max_rss() {
+    let run_info =
+        TestRunnerHelper::for_simple_exec("test_max_rss", ALLOCATE_20_MEGABYTES, PivotRoot::Pivot)
+            .config_builder()
+            .controller_path(ControllerPath::default())
+            .build_and_run()
+            .unwrap();
+    let max_rss = run_info.usage().max_rss().unwrap();
+    assert!(
+        max_rss >= SpaceUsage::from_megabytes(20),
+        "Peak RSS {} below 20MB",
+        max_rss
+    );
+    // Every page of the vector is touched at least once
+    let minor_faults = run_info.usage().minor_faults().unwrap();
+    assert!(minor_faults >= 20_000_000 / 4096, "{}", minor_faults);
+    run_info.assert(IsSuccess);
+}
+
+#[test]
+fn test_threads_memory_limit_exceeded() {
+    let mut limits = LimitsBuilder::new();
+    limits.memory(SpaceUsage::from_megabytes(40));
+
+    TestRunnerHelper::for_simple_exec(
+        "test_threads_memory_limit_exceeded",
+        THREADS_ALLOCATE_20_MEGABYTES,
+        PivotRoot::Pivot,
+    )
+    .config_builder()
+    .limits(limits)
+    .build_and_run()
+    .unwrap()
+    .assert(CompareLimits::new(IsSuccess, limits));
+
+    limits.memory(SpaceUsage::from_megabytes(19));
+    TestRunnerHelper::for_simple_exec(
+        "test_threads_memory_limit_exceeded",
+        THREADS_ALLOCATE_20_MEGABYTES,
+        PivotRoot::Pivot,
+    )
+    .config_builder()
+    .limits(limits)
+    .build_and_run()
+    .unwrap()
+    .assert(CompareLimits::new(MemoryLimitExceeded, limits));
+}
+
+#[test]
+fn test_memory_limit_exceeded_oom_kill() {
+    let mut limits = LimitsBuilder::new();
+    limits.memory(SpaceUsage::from_megabytes(20));
+
+    // The OOM killer strikes while the sampled usage is still just under the limit,
+    // so repeat a few times to make sure the race is always reported correctly
+    for _ in 0..5 {
+        TestRunnerHelper::for_simple_exec(
+            "test_memory_limit_exceeded_oom_kill",
+            ALLOCATE_IN_LOOP,
+            PivotRoot::Pivot,
+        )
+        .config_builder()
+        .limits(limits)
+        .build_and_run()
+        .unwrap()
+        .assert(CompareLimits::new(MemoryLimitExceeded, limits));
+    }
+}
+
+#[test]
+fn test_memory_limit_exceeded_fast_allocation() {
+    let mut limits = LimitsBuilder::new();
+    limits.memory(SpaceUsage::from_megabytes(50));
+
+    // Memory pressure wakes the supervisor up right away, the run must not go on thrashing
+    // against the limit until a sample catches it
+    let timer = Instant::now();
+    let run_info = TestRunnerHelper::for_simple_exec(
+        "test_memory_limit_exceeded_fast_allocation",
+        ALLOCATE_10_MEGABYTES_PER_MS,
+        PivotRoot::Pivot,
+    )
+    .config_builder()
+    .limits(limits)
+    .build_and_run()
+    .unwrap();
+    let elapsed = timer.elapsed();
+    assert!(
+        run_info.usage().wall_time() < Duration::from_millis(300),
+        "{:?}",
+        run_info.usage().wall_time()
+    );
+    assert!(elapsed < Duration::from_millis(300), "{:?}", elapsed);
+    run_info.assert(CompareLimits::new(MemoryLimitExceeded, limits));
+}
+
+#[test]
+fn test_memory_accounting_rss_only() {
+    let mut limits = LimitsBuilder::new();
+    limits.memory(SpaceUsage::from_megabytes(64));
+
+    let mut helper = TestRunnerHelper::for_simple_exec(
+        "test_memory_accounting_rss_only",
+        READ_FILE_ARG,
+        PivotRoot::Pivot,
+    );
+    let input = File::create(helper.file_path("input")).unwrap();
+    let chunk = vec![1u8; 1 << 20];
+    for _ in 0..100 {
+        (&input).write_all(&chunk).unwrap();
+    }
+    // Drop the file from the page cache, so the run itself reads it in
+    input.sync_all().unwrap();
+    assert_eq!(
+        unsafe { libc::posix_fadvise(input.as_raw_fd(), 0, 0, libc::POSIX_FADV_DONTNEED) },
+        0
+    );
+
+    helper
+        .config_builder()
+        .limits(limits)
+        .memory_accounting(MemoryAccounting::RssOnly)
+        .arg("/input")
+        .build_and_run()
+        .unwrap()
+        .assert(CompareLimits::new(IsSuccess, limits));
+
+    // Memory the program allocates itself still counts
+    limits.memory(SpaceUsage::from_megabytes(10));
+    TestRunnerHelper::for_simple_exec(
+        "test_memory_accounting_rss_only",
+        ALLOCATE_20_MEGABYTES,
+        PivotRoot::Pivot,
+    )
+    .config_builder()
+    .limits(limits)
+    .memory_accounting(MemoryAccounting::RssOnly)
+    .build_and_run()
+    .unwrap()
+    .assert(CompareLimits::new(MemoryLimitExceeded, limits));
+}
+
+type SetLimit = fn(&mut LimitsBuilder) -> &mut LimitsBuilder;
+
+#[test]
+fn test_zero_limits_rejected() {
+    let zero_limits: Vec<(&str, SetLimit)> = vec![
+        ("wall time", |limits| {
+            limits.wall_time(Duration::from_secs(0))
+        }),
+        ("user time", |limits| {
+            limits.user_time(Duration::from_secs(0))
+        }),
+        ("memory", |limits| limits.memory(SpaceUsage::from_bytes(0))),
+        ("stack", |limits| limits.stack(SpaceUsage::from_bytes(0))),
+        ("cache", |limits| limits.cache(SpaceUsage::from_bytes(0))),
+        ("pids", |limits| limits.pids(0)),
+        ("cpu quota", |limits| limits.cpu_quota(0.0)),
+        ("cpus", |limits| limits.cpus("")),
+        ("mems", |limits| limits.mems("")),
+        ("read bps", |limits| {
+            limits.read_bps(SpaceUsage::from_bytes(0))
+        }),
+        ("write bps", |limits| {
+            limits.write_bps(SpaceUsage::from_bytes(0))
+        }),
+    ];
+
+    for (name, set_zero) in zero_limits {
+        let mut limits = LimitsBuilder::new();
+        let _ = set_zero(&mut limits);
+
+        match TestRunnerHelper::for_simple_exec(
+            "test_zero_limits_rejected",
+            HELLO_WORLD,
+            PivotRoot::Pivot,
+        )
+        .config_builder()
+        .limits(limits)
+        .build_and_run()
+        .unwrap_err()
+        .into_config_error()
+        {
+            ConfigError::ZeroLimit(limit) => assert_eq!(limit, name),
+            err => panic!("Expected zero {} limit error, got {}", name, err),
+        }
+    }
+}
+
+#[test]
+fn test_smallest_limits() {
+    let mut limits = LimitsBuilder::new();
+    limits.wall_time(Duration::from_nanos(1));
+    TestRunnerHelper::for_simple_exec("test_smallest_limits", HELLO_WORLD, PivotRoot::Pivot)
+        .config_builder()
+        .limits(limits)
+        .build_and_run()
+        .unwrap()
+        .assert(CompareLimits::new(WallTimeLimitExceeded, limits));
+
+    let mut limits = LimitsBuilder::new();
+    limits.user_time(Duration::from_nanos(1));
+    TestRunnerHelper::for_simple_exec("test_smallest_limits", LOOP_500_MS, PivotRoot::Pivot)
+        .config_builder()
+        .limits(limits)
+        .build_and_run()
+        .unwrap()
+        .assert(CompareLimits::new(TimeLimitExceeded, limits));
+
+    let mut limits = LimitsBuilder::new();
+    limits.memory(SpaceUsage::from_bytes(1));
+    TestRunnerHelper::for_simple_exec("test_smallest_limits", ALLOCATE_IN_LOOP, PivotRoot::Pivot)
+        .config_builder()
+        .limits(limits)
+        .build_and_run()
+        .unwrap()
+        .assert(CompareLimits::new(MemoryLimitExceeded, limits));
+}
+
+#[test]
+fn test_largest_limits() {
+    let mut limits = LimitsBuilder::new();
+    limits
+        .wall_time(Duration::new(u64::MAX, 999_999_999))
+        .user_time(Duration::new(u64::MAX, 999_999_999))
+        .memory(SpaceUsage::from_gibibytes(u64::MAX))
+        .cache(SpaceUsage::from_bytes(u64::MAX))
+        .pids(PIDS_MAX_LIMIT);
+
+    TestRunnerHelper::for_simple_exec("test_largest_limits", HELLO_WORLD, PivotRoot::Pivot)
+        .config_builder()
+        .limits(limits)
+        .build_and_run()
+        .unwrap()
+        .assert(CompareLimits::new(IsSuccess, limits));
+
+    // pids.max takes nothing larger
+    limits.pids(PIDS_MAX_LIMIT + 1);
+    match TestRunnerHelper::for_simple_exec("test_largest_limits", HELLO_WORLD, PivotRoot::Pivot)
+        .config_builder()
+        .limits(limits)
+        .build_and_run()
+        .unwrap_err()
+        .into_config_error()
+    {
+        ConfigError::PidsLimitTooLarge(pids) => assert_eq!(pids, PIDS_MAX_LIMIT + 1),
+        err => panic!("Expected pids limit error, got {}", err),
+    }
+}
+
+#[test]
+fn test_pids_limit_exceeded() {
+    let mut limits = LimitsBuilder::new();
+    limits.pids(5);
+
+    TestRunnerHelper::for_simple_exec(
+        "test_pids_limit_exceeded",
+        THREADS_ALLOCATE_20_MEGABYTES,
+        PivotRoot::Pivot,
+    )
+    .config_builder()
+    .limits(limits)
+    .build_and_run()
+    .unwrap()
+    .assert(CompareLimits::new(IsSuccess, limits));
+
+    limits.pids(4);
+    TestRunnerHelper::for_simple_exec(
+        "test_pids_limit_exceeded",
+        THREADS_ALLOCATE_20_MEGABYTES,
+        PivotRoot::Pivot,
+    )
+    .config_builder()
+    .limits(limits)
+    .build_and_run()
+    .unwrap()
+    .assert(CompareLimits::new(NonZeroExitStatus::any(), limits));
+}
+
+#[test]
+fn test_mount_directory() {
+    let temp_dir = Builder::new()
+        .prefix("test_mount_directory_special")
+        .tempdir()
+        .unwrap();
+    let input_path = temp_dir.path().join("input");
+    let mut file = File::create(&input_path).unwrap();
+    let _ = file.write(b"15\n").unwrap();
+
+    TestRunnerHelper::for_simple_exec("test_mount_directory", EXIT_WITH_ARG_FILE, PivotRoot::Pivot)
+        .config_builder()
+        .mount(Mount::new(
+            temp_dir.path().into(),
+            "/mount".into(),
+            MountOptions::default(),
+        ))
+        .arg("/mount/input")
+        .build_and_run()
+        .unwrap()
+        .assert(NonZeroExitStatus::new(15));
+}
+
+#[test]
+fn test_working_directory() {
+    let temp_dir = Builder::new()
+        .prefix("test_working_directory_special")
+        .tempdir()
+        .unwrap();
+    let input_path = temp_dir.path().join("input");
+    let mut file = File::create(&input_path).unwrap();
+    let _ = file.write(b"15\n").unwrap();
+
+    let mut helper = TestRunnerHelper::for_simple_exec(
+        "test_working_directory",
+        EXIT_WITH_ARG_FILE,
+        PivotRoot::Pivot,
+    );
+    helper
+        .config_builder()
+        .mount(Mount::new(
+            temp_dir.path().into(),
+            "/mount".into(),
+            MountOptions::default(),
+        ))
+        .working_directory("/mount")
+        .arg("input")
+        .build_and_run()
+        .unwrap()
+        .assert(NonZeroExitStatus::new(15));
+
+    match helper
+        .config_builder()
+        .working_directory("/missing")
+        .build_and_run()
+        .unwrap_err()
+    {
+        Error::ChildError(ChildError::FFIError(FFIError::ChdirError { .. })) => (),
+        err => panic!("Expected chdir error, got {}", err),
+    }
+}
+
+#[test]
+fn test_copy_in() {
+    let temp_dir = Builder::new()
+        .prefix("test_copy_in_host")
+        .tempdir()
+        .unwrap();
+    let input_path = temp_dir.path().join("input");
+    let mut file = File::create(&input_path).unwrap();
+    let _ = file.write(b"17\n").unwrap();
+
+    let mut helper =
+        TestRunnerHelper::for_simple_exec("test_copy_in", EXIT_WITH_ARG_FILE, PivotRoot::Pivot);
+    helper
+        .config_builder()
+        .copy_in(&input_path, "/data/input.txt")
+        .arg("/data/input.txt")
+        .build_and_run()
+        .unwrap()
+        .assert(NonZeroExitStatus::new(17));
+}
+
+#[test]
+fn test_copy_out() {
+    let temp_dir = Builder::new()
+        .prefix("test_copy_out_host")
+        .tempdir()
+        .unwrap();
+    let output_path = temp_dir.path().join("output");
+    let mut writable = MountOptions::default();
+    writable.set_read_only(false);
+
+    // Out of a tmpfs, which is gone with the mount namespace of the command otherwise
+    let mut helper =
+        TestRunnerHelper::for_simple_exec("test_copy_out", WRITE_FILE_ARG, PivotRoot::Pivot);
+    helper
+        .config_builder()
+        .mount(Mount::tmpfs("/tmp".into(), None, writable))
+        .copy_out("/tmp/output.txt", &output_path)
+        .copy_out("/tmp/missing.txt", temp_dir.path().join("missing"))
+        .arg("/tmp/output.txt")
+        .build_and_run()
+        .unwrap()
+        .assert(IsSuccess);
+    assert_eq!(fs::read_to_string(&output_path).unwrap(), "Hello file!\n");
+    assert!(!temp_dir.path().join("missing").exists());
+
+    fs::remove_file(&output_path).unwrap();
+    match helper
+        .config_builder()
+        .mount(Mount::tmpfs("/tmp".into(), None, writable))
+        .copy_out("/tmp/output.txt", &output_path)
+        .copy_out_limit(SpaceUsage::from_bytes(4))
+        .arg("/tmp/output.txt")
+        .build_and_run()
+    {
+        Err(Error::FFIError(FFIError::CopyOutTooLarge { limit: 4, .. })) => {}
+        result => panic!("Expected copy out too large error, got {:?}", result),
+    }
+    assert!(!output_path.exists());
+}
+
+#[test]
+fn test_mount_file() {
+    let temp_dir = Builder::new()
+        .prefix("test_mount_file_special")
+        .tempdir()
+        .unwrap();
+    let input_path = temp_dir.path().join("input");
+    let mut file = File::create(&input_path).unwrap();
+    let _ = file.write(b"15\n").unwrap();
+
+    let mut helper =
+        TestRunnerHelper::for_simple_exec("test_mount_file", EXIT_WITH_ARG_FILE, PivotRoot::Pivot);
+    helper
+        .config_builder()
+        .mount(Mount::new(
+            input_path.clone(),
+            "/input.txt".into(),
+            MountOptions::default(),
+        ))
+        .arg("/input.txt")
+        .build_and_run()
+        .unwrap()
+        .assert(NonZeroExitStatus::new(15));
+
+    // Read-only unless asked otherwise, under directories created for it
+    let mut helper =
+        TestRunnerHelper::for_simple_exec("test_mount_file", WRITE_FILE_ARG, PivotRoot::Pivot);
+    helper
+        .config_builder()
+        .mount(Mount::new(
+            input_path.clone(),
+            "/data/input.txt".into(),
+            MountOptions::default(),
+        ))
+        .arg("/data/input.txt")
+        .build_and_run()
+        .unwrap()
+        .assert(NonZeroExitStatus::any());
+    assert_eq!(helper.read_line(&input_path), "15\n");
+}
+
+#[test]
+fn test_mount_destination() {
+    let temp_dir = Builder::new()
+        .prefix("test_mount_destination_special")
+        .tempdir()
+        .unwrap();
+    let input_path = temp_dir.path().join("input");
+    let mut file = File::create(&input_path).unwrap();
+    let _ = file.write(b"15\n").unwrap();
+
+    let mut helper = TestRunnerHelper::for_simple_exec(
+        "test_mount_destination",
+        EXIT_WITH_ARG_FILE,
+        PivotRoot::Pivot,
+    );
+    helper
+        .config_builder()
+        .mount(Mount::new(
+            temp_dir.path().into(),
+            "/deeply/nested/dir".into(),
+            MountOptions::default(),
+        ))
+        .arg("/deeply/nested/dir/input")
+        .build_and_run()
+        .unwrap()
+        .assert(NonZeroExitStatus::new(15));
+
+    let mut mount_options = MountOptions::default();
+    mount_options.set_create_destination(false);
+    let mut helper = TestRunnerHelper::for_simple_exec(
+        "test_mount_destination",
+        EXIT_WITH_ARG_FILE,
+        PivotRoot::Pivot,
+    );
+    match helper
+        .config_builder()
+        .mount(Mount::new(
+            temp_dir.path().into(),
+            "/missing".into(),
+            mount_options,
+        ))
+        .build_and_run()
+        .unwrap_err()
+    {
+        Error::ChildError(ChildError::FFIError(FFIError::MountError { .. })) => (),
+        err => panic!("Expected mount error, got {}", err),
+    }
+    assert!(!helper.file_path("missing").exists());
+}
+
+#[test]
+fn test_mount_destination_symlink() {
+    let outside = Builder::new()
+        .prefix("test_mount_destination_symlink_outside")
+        .tempdir()
+        .unwrap();
+    let mut helper = TestRunnerHelper::for_simple_exec(
+        "test_mount_destination_symlink",
+        HELLO_WORLD,
+        PivotRoot::Pivot,
+    );
+    // Absolute, so it points out of the root when followed from the host
+    symlink(outside.path(), helper.file_path("escape")).unwrap();
+    let inside = helper.file_path(outside.path().strip_prefix("/").unwrap());
+    fs::create_dir_all(&inside).unwrap();
+    // The temporary directory of the host is likely under /tmp, which a private one would hide
+    helper
+        .config_builder()
+        .private_tmp(PrivateTmp::No)
+        .mount(Mount::tmpfs(
+            "/escape/dir".into(),
+            None,
+            MountOptions::default(),
+        ))
+        .build_and_run()
+        .unwrap()
+        .assert(IsSuccess);
+    assert!(!outside.path().join("dir").exists());
+    assert!(inside.join("dir").is_dir());
+}
+
+#[test]
+fn test_mount_destination_escape() {
+    let mut helper = TestRunnerHelper::for_simple_exec(
+        "test_mount_destination_escape",
+        HELLO_WORLD,
+        PivotRoot::Pivot,
+    );
+    // A rootfs made to point its mounts at the host
+    symlink("/", helper.file_path("tmp")).unwrap();
+    symlink("../../..", helper.file_path("up")).unwrap();
+    let names: Vec<_> = ["tmp", "up", "parent", "dev"]
+        .iter()
+        .map(|kind| format!("ia_sandbox_escape_{}_{}", kind, process::id()))
+        .collect();
+    // The minimal /dev and the devpts of an interactive run go there as well, to the directory
+    // of the rootfs rather than the one of the host
+    fs::create_dir(helper.file_path(&names[3])).unwrap();
+    symlink(Path::new("/").join(&names[3]), helper.file_path("dev")).unwrap();
+    helper
+        .config_builder()
+        .minimal_dev(MinimalDev::Yes)
+        .interactive(Interactive::Yes)
+        .mount(Mount::tmpfs(
+            Path::new("/tmp").join(&names[0]),
+            None,
+            MountOptions::default(),
+        ))
+        .mount(Mount::tmpfs(
+            Path::new("/up").join(&names[1]),
+            None,
+            MountOptions::default(),
+        ))
+        .mount(Mount::tmpfs(
+            Path::new("/../..").join(&names[2]),
+            None,
+            MountOptions::default(),
+        ))
+        .build_and_run()
+        .unwrap()
+        .assert(IsSuccess);
+    for name in &names {
+        assert!(!Path::new("/").join(name).exists());
+        assert!(helper.file_path(name).is_dir());
+    }
+}
+
+#[test]
+fn test_mount_recursive() {
+    let mut helper =
+        TestRunnerHelper::for_simple_exec("test_mount_recursive", CREATE_FILES, PivotRoot::Pivot);
+    let source = helper.file_path("src");
+    fs::create_dir_all(source.join("sub")).unwrap();
+    let mut writable = MountOptions::default();
+    writable.set_read_only(false);
+    let output_path = helper.file_path("output");
+    helper
+        .config_builder()
+        .mount(Mount::tmpfs("/src/sub".into(), None, writable))
+        // The tmpfs comes along, read-only as well
+        .mount(Mount::new(
+            source.clone(),
+            "/dst".into(),
+            MountOptions::default(),
+        ))
+        .args(["/src/sub/a", "/dst/sub/a", "/dst/sub/b"].iter())
+        .stdout(&output_path)
+        .build_and_run()
+        .unwrap()
+        .assert(IsSuccess);
+    assert_eq!(
+        helper.read_line(&output_path),
+        format!("0 {} {}\n", libc::EEXIST, libc::EROFS)
+    );
+
+    let mut mount_options = MountOptions::default();
+    mount_options.set_recursive(false);
+    let mut helper =
+        TestRunnerHelper::for_simple_exec("test_mount_recursive", CREATE_FILES, PivotRoot::Pivot);
+    let source = helper.file_path("src");
+    fs::create_dir_all(source.join("sub")).unwrap();
+    let output_path = helper.file_path("output");
+    helper
+        .config_builder()
+        .mount(Mount::tmpfs("/src/sub".into(), None, writable))
+        .mount(Mount::new(source, "/dst".into(), mount_options))
+        .args(["/src/sub/a", "/dst/sub/a"].iter())
+        .stdout(&output_path)
+        .build_and_run()
+        .unwrap()
+        .assert(IsSuccess);
+    assert_eq!(
+        helper.read_line(&output_path),
+        format!("0 {}\n", libc::EROFS)
+    );
+}
+
+#[test]
+fn test_mount_options_from_str() {
+    let parse = |string: &str| string.parse::<MountOptions>().unwrap();
+    assert_eq!(parse("defaults"), MountOptions::default());
+    assert_eq!(
+        parse("ro,nodev,noexec,nosuid,atime,create,recursive"),
+        MountOptions::default()
+    );
+
+    assert!(!parse("rw").read_only());
+    assert!(parse("rw,ro").read_only());
+    assert!(parse("dev").dev());
+    assert!(!parse("dev,nodev").dev());
+    assert!(parse("exec").exec());
+    assert!(!parse("exec").force_exec());
+    assert!(parse("force-exec").exec());
+    assert!(parse("force-exec").force_exec());
+    assert_eq!(parse("force-exec,noexec"), MountOptions::default());
+    assert!(parse("suid").suid());
+    assert!(!parse("suid,nosuid").suid());
+    assert!(!parse("noatime").atime());
+    assert!(parse("noatime,atime").atime());
+    assert_eq!(parse("private").propagation(), Some(Propagation::Private));
+    assert_eq!(parse("slave").propagation(), Some(Propagation::Slave));
+    assert_eq!(parse("shared").propagation(), Some(Propagation::Shared));
+    assert!(!parse("no-create").create_destination());
+    assert!(parse("no-create,create").create_destination());
+    assert!(!parse("no-recursive").recursive());
+    assert!(parse("no-recursive,recursive").recursive());
+
+    for (string, unrecognized) in &[
+        ("", ""),
+        ("ro,", ""),
+        ("RO", "RO"),
+        ("ro noexec", "ro noexec"),
+        ("rw,nosuchoption,exec", "nosuchoption"),
+    ] {
+        match string.parse::<MountOptions>() {
+            Err(ConfigError::InvalidMountOption(ref option)) if option == unrecognized => {}
+            result => panic!(
+                "Expected invalid mount option {:?} for {:?}, got {:?}",
+                unrecognized, string, result
+            ),
+        }
+    }
+}
+
+#[test]
+fn test_config_builder() {
+    let config = Config::builder("/bin/true").build().unwrap();
+    assert_eq!(config.command(), Path::new("/bin/true"));
+    assert!(config.args().is_empty());
+    assert_eq!(config.new_root(), None);
+    assert_eq!(config.share_net(), ShareNet::Unshare);
+    assert_eq!(*config.redirect_stdin(), Redirect::Inherit);
+    assert_eq!(*config.limits(), Limits::unlimited());
+    assert_eq!(config.instance_name(), None);
+    assert_eq!(config.private_tmp_size(), SpaceUsage::from_megabytes(64));
+    assert_eq!(config.fifo_open_timeout(), Some(Duration::from_secs(5)));
+
+    let config = Config::builder("/bin/true")
+        .arg("first")
+        .args(["second", "third"])
+        .share_net(ShareNet::Share)
+        .redirect_stdout(Redirect::Pipe)
+        .instance_name("builder")
+        .fifo_open_timeout(None)
+        .kill_grace_period(Duration::from_millis(100))
+        .build()
+        .unwrap();
+    assert_eq!(config.args(), vec!["first", "second", "third"]);
+    assert_eq!(config.share_net(), ShareNet::Share);
+    assert_eq!(*config.redirect_stdout(), Redirect::Pipe);
+    assert_eq!(config.instance_name(), Some(OsStr::new("builder")));
+    assert_eq!(config.fifo_open_timeout(), None);
+    assert_eq!(config.kill_grace_period(), Some(Duration::from_millis(100)));
+
+    // Validates, with all the problems found
+    let errors = Config::builder("/missing")
+        .instance_name("../escape")
+        .build()
+        .err()
+        .unwrap();
+    match *errors.errors() {
+        [ConfigError::InvalidInstanceName(ref name), ConfigError::CommandNotFound(ref command)] => {
+            assert_eq!(name, "../escape");
+            assert_eq!(command, Path::new("/missing"));
+        }
+        ref errors => panic!(
+            "Expected an invalid instance name and a missing command, got {:?}",
+            errors
+        ),
+    }
+}
+
+#[test]
+fn test_mount_options_builder() {
+    assert_eq!(
+        MountOptions::builder().writable().exec(true).build(),
+        MountOptions::new(false, false, true)
+    );
+    assert_eq!(MountOptions::builder().build(), MountOptions::default());
+    assert_eq!(
+        MountOptions::builder()
+            .read_only(false)
+            .dev(true)
+            .exec(true)
+            .force_exec(true)
+            .propagation(Propagation::Slave)
+            .create_destination(false)
+            .recursive(false)
+            .suid(true)
+            .atime(false)
+            .build(),
+        "rw,dev,force-exec,slave,no-create,no-recursive,suid,noatime"
+            .parse::<MountOptions>()
+            .unwrap()
+    );
+
+    let source = PathBuf::from("/source");
+    let destination = PathBuf::from("/destination");
+    assert_eq!(
+        Mount::read_only(source.clone(), destination.clone()),
+        Mount::new(source.clone(), destination.clone(), MountOptions::default())
+    );
+    assert_eq!(
+        Mount::writable(source.clone(), destination.clone()),
+        Mount::new(source, destination, MountOptions::new(false, false, false))
+    );
+}
+
+#[test]
+fn test_mount_tmpfs() {
+    let mut helper =
+        TestRunnerHelper::for_simple_exec("test_mount_tmpfs", WRITE_FILE_ARG, PivotRoot::Pivot);
+    let output_path = helper.file_path("output");
+    let mut mount_options = MountOptions::default();
+    mount_options.set_read_only(false);
+    helper
+        .config_builder()
+        .mount(Mount::tmpfs(
+            "/tmp".into(),
+            Some(SpaceUsage::from_megabytes(1)),
+            mount_options,
+        ))
+        .arg("/tmp/file")
+        .stdout(&output_path)
+        .build_and_run()
+        .unwrap()
+        .assert(IsSuccess);
+    assert_eq!(helper.read_line(&output_path), "Hello file!\n");
+    // Gone with the sandbox
+    assert!(!helper.file_path("tmp/file").exists());
+}
+
+#[test]
+fn test_private_tmp() {
+    let mut helper = TestRunnerHelper::for_simple_exec(
+        "test_private_tmp",
+        WRITE_KILOBYTES_ARG,
+        PivotRoot::Pivot,
+    );
+    helper
+        .config_builder()
+        .private_tmp_size(SpaceUsage::from_megabytes(1));
+    for dir in &["/tmp", "/var/tmp", "/dev/shm"] {
+        helper
+            .config_builder()
+            .args([&format!("{}/file", dir), "512"])
+            .build_and_run()
+            .unwrap()
+            .assert(IsSuccess);
+        helper
+            .config_builder()
+            .args([&format!("{}/file", dir), "2048"])
+            .build_and_run()
+            .unwrap()
+            .assert(NonZeroExitStatus::any());
+    }
+    assert!(!helper.file_path("tmp/file").exists());
+    assert!(!helper.file_path("var/tmp/file").exists());
+
+    // A mount of its own at /tmp takes its place
+    let mount_options = MountOptions::builder().writable().build();
+    helper
+        .config_builder()
+        .mount(Mount::tmpfs(
+            "/tmp".into(),
+            Some(SpaceUsage::from_megabytes(4)),
+            mount_options,
+        ))
+        .args(["/tmp/file", "2048"])
+        .build_and_run()
+        .unwrap()
+        .assert(IsSuccess);
+}
+
+#[test]
+fn test_new_root_overlay() {
+    let mut helper = TestRunnerHelper::for_simple_exec(
+        "test_new_root_overlay",
+        WRITE_FILE_ARG,
+        PivotRoot::Pivot,
+    );
+    let temp_dir = Builder::new()
+        .prefix("test_new_root_overlay")
+        .tempdir()
+        .unwrap();
+    for dir in &["upper", "work", "root", "scratch_upper", "scratch_work"] {
+        fs::create_dir(temp_dir.path().join(dir)).unwrap();
+    }
+    // The root isolation mode runs it as nobody, the upper directories are the merged ones
+    for dir in &["upper", "scratch_upper"] {
+        fs::set_permissions(temp_dir.path().join(dir), fs::Permissions::from_mode(0o777)).unwrap();
+    }
+    let lower = helper.file_path("");
+    let output_path = temp_dir.path().join("output");
+    helper
+        .config_builder()
+        .new_root(temp_dir.path().join("root"))
+        .new_root_overlay(Overlay::new(
+            vec![lower.clone()],
+            temp_dir.path().join("upper"),
+            temp_dir.path().join("work"),
+        ))
+        .arg("/scratch.txt")
+        .stdout(&output_path)
+        .build_and_run()
+        .unwrap()
+        .assert(IsSuccess);
+    assert_eq!(helper.read_line(&output_path), "Hello file!\n");
+    assert!(!lower.join("scratch.txt").exists());
+    assert_eq!(
+        fs::read_to_string(temp_dir.path().join("upper/scratch.txt")).unwrap(),
+        "Hello file!\n"
+    );
+
+    // And one inside the new root
+    let scratch = helper.file_path("scratch");
+    fs::create_dir(&scratch).unwrap();
+    let mut mount_options = MountOptions::default();
+    mount_options.set_read_only(false);
+    helper
+        .config_builder()
+        .mount(Mount::overlay(
+            "/scratch".into(),
+            Overlay::new(
+                vec![scratch.clone()],
+                temp_dir.path().join("scratch_upper"),
+                temp_dir.path().join("scratch_work"),
+            ),
+            mount_options,
+        ))
+        .arg("/scratch/file")
+        .build_and_run()
+        .unwrap()
+        .assert(IsSuccess);
+    assert!(!scratch.join("file").exists());
+    assert!(temp_dir.path().join("scratch_upper/file").exists());
+}
+
+#[test]
+fn test_root_image_tarball() {
+    let mut helper = TestRunnerHelper::for_simple_exec(
+        "test_root_image_tarball",
+        WRITE_FILE_ARG,
+        PivotRoot::Pivot,
+    );
+    let temp_dir = Builder::new()
+        .prefix("test_root_image_tarball")
+        .tempdir()
+        .unwrap();
+    // The binary, its libraries, and links of both kinds
+    symlink("write_file_arg", helper.file_path("symlinked")).unwrap();
+    fs::hard_link(
+        helper.file_path("write_file_arg"),
+        helper.file_path("hard_linked"),
+    )
+    .unwrap();
+    let image = temp_dir.path().join("image.tar");
+    let status = Command::new("tar")
+        .arg("-cf")
+        .arg(&image)
+        .arg("-C")
+        .arg(helper.file_path(""))
+        .arg(".")
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let new_root = temp_dir.path().join("root");
+    fs::create_dir(&new_root).unwrap();
+    let output_path = temp_dir.path().join("output");
+    helper
+        .config_builder()
+        .new_root(&new_root)
+        .root_image(RootImage::Tarball(image))
+        .arg("/scratch.txt")
+        .stdout(&output_path);
+    for command in &["/write_file_arg", "/symlinked", "/hard_linked"] {
+        helper
+            .config_builder()
+            .command(command)
+            .build_and_run()
+            .unwrap()
+            .assert(IsSuccess);
+        assert_eq!(helper.read_line(&output_path), "Hello file!\n");
+    }
+    // Unpacked into a tmpfs gone with the sandbox
+    assert_eq!(fs::read_dir(&new_root).unwrap().count(), 0);
+}
+
+#[test]
+fn test_read_only_root() {
+    let mut helper =
+        TestRunnerHelper::for_simple_exec("test_read_only_root", CREATE_FILES, PivotRoot::Pivot);
+    // The root isolation mode runs it as nobody
+    fs::set_permissions(helper.file_path(""), fs::Permissions::from_mode(0o777)).unwrap();
+    let output_path = helper.file_path("output");
+    let mut mount_options = MountOptions::default();
+    mount_options.set_read_only(false);
+    helper
+        .config_builder()
+        .read_only_root(ReadOnlyRoot::Yes)
+        .mount(Mount::tmpfs("/tmp".into(), None, mount_options))
+        .args(["/file", "/tmp/file"].iter())
+        .stdout(&output_path)
+        .build_and_run()
+        .unwrap()
+        .assert(IsSuccess);
+    assert_eq!(
+        helper.read_line(&output_path),
+        format!("{} 0\n", libc::EROFS)
+    );
+    assert!(!helper.file_path("file").exists());
+
+    helper
+        .config_builder()
+        .read_only_root(ReadOnlyRoot::No)
+        .args(["/file"].iter())
+        .build_and_run()
+        .unwrap()
+        .assert(IsSuccess);
+    assert!(helper.file_path("file").exists());
+}
+
+#[test]
+fn test_proc_options() {
+    let mut helper =
+        TestRunnerHelper::for_simple_exec("test_proc_options", LIST_PROC, PivotRoot::Pivot);
+    let output_path = helper.file_path("output");
+    helper
+        .config_builder()
+        .args(["/proc/keys", "/proc/timer_list"].iter())
+        .stdout(&output_path)
+        .build_and_run()
+        .unwrap()
+        .assert(IsSuccess);
+    assert_eq!(
+        helper.read_line(&output_path),
+        format!("1 self thread-self\nerrno {0} errno {0}\n", libc::ENOENT)
+    );
+
+    // The whole /proc, with the files revealing the host under /dev/null
+    let mut helper =
+        TestRunnerHelper::for_simple_exec("test_proc_options", LIST_PROC, PivotRoot::Pivot);
+    let output_path = helper.file_path("output");
+    helper
+        .config_builder()
+        .proc_options(ProcOptions::new(HidePid::Off, false, true))
+        .args(["/proc/keys", "/proc/timer_list"].iter())
+        .stdout(&output_path)
+        .build_and_run()
+        .unwrap()
+        .assert(IsSuccess);
+    let output = helper.read_line(&output_path);
+    let lines: Vec<_> = output.lines().collect();
+    let entries: Vec<_> = lines[0].split(' ').collect();
+    assert!(entries.contains(&"sys"));
+    assert!(entries.contains(&"timer_list"));
+    assert_eq!(lines[1], "0 0");
+}
+
+#[test]
+fn test_minimal_dev() {
+    let mut helper =
+        TestRunnerHelper::for_simple_exec("test_minimal_dev", USE_DEVICES, PivotRoot::Pivot);
+    let output_path = helper.file_path("output");
+    helper
+        .config_builder()
+        .stdout(&output_path)
+        .build_and_run()
+        .unwrap()
+        .assert(IsSuccess);
+    assert_eq!(helper.read_line(&output_path), "Hello devices!\n");
+
+    helper
+        .config_builder()
+        .minimal_dev(MinimalDev::No)
+        .build_and_run()
+        .unwrap()
+        .assert(NonZeroExitStatus::any());
+}
+
+#[test]
+fn test_allocate_pty() {
+    let mut helper =
+        TestRunnerHelper::for_simple_exec("test_allocate_pty", IS_TTY, PivotRoot::Pivot);
+    helper
+        .config_builder()
+        .inherit_stdin()
+        .allocate_pty(AllocatePty::Yes)
+        .build_and_run()
+        .unwrap()
+        .assert(IsSuccess);
+
+    // The redirections still apply
+    helper
+        .config_builder()
+        .stdin("/dev/null")
+        .build_and_run()
+        .unwrap()
+        .assert(NonZeroExitStatus::new(1));
+}
+
+#[test]
+fn test_interactive_devpts() {
+    let mut helper =
+        TestRunnerHelper::for_simple_exec("test_interactive_devpts", OPEN_PATHS, PivotRoot::Pivot);
+    let output_path = helper.file_path("output");
+    helper
+        .config_builder()
+        .interactive(Interactive::Yes)
+        .args(["/dev/ptmx", "/dev/pts/ptmx"].iter())
+        .stdout(&output_path)
+        .build_and_run()
+        .unwrap()
+        .assert(IsSuccess);
+    assert_eq!(helper.read_line(&output_path), "0 0\n");
+}
+
+#[test]
+fn test_mount_source_flags_conflict() {
+    let temp_dir = Builder::new()
+        .prefix("test_mount_source_flags_conflict")
+        .tempdir()
+        .unwrap();
+    let source = temp_dir.path().canonicalize().unwrap();
+    let mount_point = source.parent().unwrap();
+    let mount_point_escaped = mount_point.to_str().unwrap().replace(' ', "\\040");
+    let mount_info = MountInfo::parse(&format!(
+        "22 1 8:1 / / rw,relatime shared:1 - ext4 /dev/sda1 rw\n\
+         30 22 0:40 / {} rw,nosuid,nodev,noexec shared:2 - nfs server:/export rw\n",
+        mount_point_escaped
+    ));
+    assert_eq!(
+        mount_info.find_mount(&source).unwrap().mount_point(),
+        mount_point
+    );
+    assert_eq!(
+        mount_info.find_mount(&source).unwrap().mount_source(),
+        Path::new("server:/export")
+    );
+
+    let check = |exec, dev, suid, force_exec| {
+        let mut mount_options = MountOptions::default();
+        mount_options.set_exec(exec);
+        mount_options.set_dev(dev);
+        mount_options.set_suid(suid);
+        mount_options.set_force_exec(force_exec);
+        Mount::new(source.clone(), "/mount".into(), mount_options).check_source_flags(&mount_info)
+    };
+
+    check(false, false, false, false).unwrap();
+    check(true, false, false, true).unwrap();
+    match check(true, true, false, false).unwrap_err() {
+        err @ ConfigError::SourceMountFlagsConflict { .. } => {
+            let hint = err.to_string();
+            assert!(hint.contains(&format!("{:?}", mount_point)), "{}", hint);
+            assert!(hint.contains("[\"exec\", \"dev\"]"), "{}", hint);
+            assert!(hint.contains("Remount"), "{}", hint);
+        }
+        err => panic!("Expected mount flags conflict, got {}", err),
+    }
+    match check(false, true, false, true).unwrap_err() {
+        ConfigError::SourceMountFlagsConflict { missing, .. } => assert_eq!(missing, vec!["dev"]),
+        err => panic!("Expected mount flags conflict, got {}", err),
+    }
+    match check(true, false, true, true).unwrap_err() {
+        ConfigError::SourceMountFlagsConflict { missing, .. } => assert_eq!(missing, vec!["suid"]),
+        err => panic!("Expected mount flags conflict, got {}", err),
+    }
+}
+
+// The problems of a config with the paths at fault, as `Display` shows them
+fn assert_invalid_paths(result: Result<RunInfo<()>, Error>, paths: &[&Path]) {
+    match result.unwrap_err() {
+        err @ Error::InvalidConfig(_) => {
+            let message = err.to_string();
+            for path in paths {
+                assert!(message.contains(&format!("{:?}", path)), "{}", message);
+            }
+        }
+        err => panic!("Expected invalid config error, got {}", err),
+    }
+}
+
+#[test]
+fn test_validate_paths() {
+    // The config builder keeps what is set, one for each problem
+    let new_helper =
+        || TestRunnerHelper::for_simple_exec("test_validate_paths", HELLO_WORLD, PivotRoot::Pivot);
+
+    let mut helper = new_helper();
+    let missing = helper.file_path("missing");
+    let result = helper
+        .config_builder()
+        .mount(Mount::read_only(missing.clone(), "/mount".into()))
+        .build_and_run();
+    assert_invalid_paths(result, &[&missing]);
+
+    // A file over a directory of the new root
+    let mut helper = new_helper();
+    fs::create_dir(helper.file_path("dir")).unwrap();
+    let source = helper.file_path("hello_world");
+    let result = helper
+        .config_builder()
+        .mount(Mount::read_only(source.clone(), "/dir".into()))
+        .build_and_run();
+    assert_invalid_paths(result, &[&source, Path::new("/dir")]);
+
+    let mut helper = new_helper();
+    let missing = helper.file_path("missing");
+    let result = helper.config_builder().new_root(&missing).build_and_run();
+    assert_invalid_paths(result, &[&missing]);
+
+    let mut helper = new_helper();
+    let output_path = helper.file_path("missing/output");
+    let result = helper.config_builder().stdout(&output_path).build_and_run();
+    assert_invalid_paths(result, &[&output_path]);
+
+    let mut helper = new_helper();
+    helper.write_file("not_executable", b"data");
+    let result = helper
+        .config_builder()
+        .command("/not_executable")
+        .build_and_run();
+    assert_invalid_paths(result, &[Path::new("/not_executable")]);
+
+    // All of them at once
+    let mut helper = new_helper();
+    let missing = helper.file_path("missing");
+    let result = helper
+        .config_builder()
+        .command("/missing")
+        .stdout(missing.join("output"))
+        .mount(Mount::read_only(missing.clone(), "/mount".into()))
+        .build_and_run();
+    match result.unwrap_err() {
+        Error::InvalidConfig(errors) => assert_eq!(errors.errors().len(), 3, "{}", errors),
+        err => panic!("Expected invalid config error, got {}", err),
+    }
+}
+
+#[test]
+fn test_validate_read_only_new_root() {
+    let mut helper = TestRunnerHelper::for_simple_exec(
+        "test_validate_read_only_new_root",
+        HELLO_WORLD,
+        PivotRoot::Pivot,
+    );
+    let temp_dir = Builder::new()
+        .prefix("test_validate_read_only_new_root")
+        .tempdir()
+        .unwrap();
+    let new_root = temp_dir.path().join("root");
+    fs::create_dir(&new_root).unwrap();
+    let new_root_c_string = CString::new(new_root.as_os_str().as_bytes()).unwrap();
+    let tmpfs = CString::new("tmpfs").unwrap();
+    assert_eq!(
+        unsafe {
+            libc::mount(
+                tmpfs.as_ptr(),
+                new_root_c_string.as_ptr(),
+                tmpfs.as_ptr(),
+                libc::MS_RDONLY,
+                ptr::null(),
+            )
+        },
+        0
+    );
+    let result = helper.config_builder().new_root(&new_root).build_and_run();
+    let _ = unsafe { libc::umount2(new_root_c_string.as_ptr(), libc::MNT_DETACH) };
+    assert_invalid_paths(
+        result,
+        &[&new_root, Path::new("proc"), Path::new(".old_root")],
+    );
+}
+
+#[test]
+fn test_mount_propagation() {
+    fn mount_tmpfs(path: &Path) -> CString {
+        let path = CString::new(path.as_os_str().as_bytes()).unwrap();
+        let tmpfs = CString::new("tmpfs").unwrap();
+        assert_eq!(
+            unsafe {
+                libc::mount(
+                    tmpfs.as_ptr(),
+                    path.as_ptr(),
+                    tmpfs.as_ptr(),
+                    0,
+                    ptr::null(),
+                )
+            },
+            0
+        );
+        path
+    }
+
+    // Mounts only propagate out of shared mounts, so the source is a shared tmpfs
+    let temp_dir = Builder::new()
+        .prefix("test_mount_propagation_source")
+        .tempdir()
+        .unwrap();
+    let source = mount_tmpfs(temp_dir.path());
+    assert_eq!(
+        unsafe {
+            libc::mount(
+                ptr::null(),
+                source.as_ptr(),
+                ptr::null(),
+                libc::MS_SHARED,
+                ptr::null(),
+            )
+        },
+        0
+    );
+    let mounted_path = temp_dir.path().join("mounted");
+    fs::create_dir(&mounted_path).unwrap();
+
+    let run = |mount_propagation| {
+        let ready_path = temp_dir.path().join("ready");
+        let _ = fs::remove_file(&ready_path);
+        let mut mount_options = MountOptions::default();
+        mount_options.set_read_only(false);
+        mount_options.set_propagation(mount_propagation);
+
+        let mut helper = TestRunnerHelper::for_simple_exec(
+            "test_mount_propagation",
+            WAIT_FOR_MOUNT_ARG,
+            PivotRoot::Pivot,
+        );
+        let handle = helper
+            .config_builder()
+            .propagation(Propagation::Slave)
+            .mount(Mount::new(
+                temp_dir.path().into(),
+                "/mount".into(),
+                mount_options,
+            ))
+            .arg("/mount")
+            .build_and_spawn()
+            .unwrap();
+
+        // Mount only once the mounts of the sandbox are made, the ones made before are
+        // copied into it whatever the propagation
+        let start = Instant::now();
+        while !ready_path.exists() && start.elapsed() < Duration::from_secs(1) {
+            thread::sleep(Duration::from_millis(10));
+        }
+        let mounted = mount_tmpfs(&mounted_path);
+        File::create(mounted_path.join("file")).unwrap();
+        let result = handle.wait();
+        assert_eq!(unsafe { libc::umount(mounted.as_ptr()) }, 0);
+        result.unwrap()
+    };
+
+    let slave = run(None);
+    let private = run(Some(Propagation::Private));
+    assert_eq!(unsafe { libc::umount(source.as_ptr()) }, 0);
+    slave.assert(IsSuccess);
+    private.assert(NonZeroExitStatus::new(1));
+}
+
+#[test]
+fn test_check_environment() {
+    let report = ia_sandbox::check_environment();
+    assert!(report.passed(), "{}", report);
+    for feature in &[
+        Feature::Namespace(NamespaceKind::User),
+        Feature::Namespace(NamespaceKind::Net),
+        Feature::PivotRoot,
+        Feature::CgroupController("memory".to_owned()),
+    ] {
+        assert!(
+            report.check(feature).is_some(),
+            "{} is not checked",
+            feature
+        );
+    }
+    // The pivot_root probe cleans up after itself
+    let leftovers: Vec<_> = fs::read_dir(std::env::temp_dir())
+        .unwrap()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            entry
+                .file_name()
+                .to_string_lossy()
+                .starts_with(&format!("ia-sandbox-probe-{}-", std::process::id()))
+        })
+        .collect();
+    assert!(leftovers.is_empty(), "{:?}", leftovers);
+}
+
+#[test]
+fn test_cgroup_namespace() {
+    TestRunnerHelper::for_simple_exec("test_cgroup_namespace", CHECK_CGROUP_ROOT, PivotRoot::Pivot)
+        .config_builder()
+        .build_and_run()
+        .unwrap()
+        .assert(IsSuccess);
+}
+
+#[test]
+fn test_mount_cgroups() {
+    let mut limits = LimitsBuilder::new();
+    limits.pids(7);
+
+    TestRunnerHelper::for_simple_exec("test_mount_cgroups", EXIT_WITH_ARG_FILE, PivotRoot::Pivot)
+        .config_builder()
+        .limits(limits)
+        .mount_cgroups(MountCgroups::Yes)
+        .arg("/sys/fs/cgroup/pids/pids.max")
+        .build_and_run()
+        .unwrap()
+        .assert(NonZeroExitStatus::new(7));
+}
+
+#[test]
+fn test_swappiness() {
+    let swappiness_path =
+        Path::new("/sys/fs/cgroup/memory/ia-sandbox/test_swappiness").join("memory.swappiness");
+    let mut limits = LimitsBuilder::new();
+    limits.memory(SpaceUsage::from_megabytes(40));
+
+    let mut runner =
+        TestRunnerHelper::for_simple_exec("test_swappiness", HELLO_WORLD, PivotRoot::DoNot);
+    runner
+        .config_builder()
+        .limits(limits)
+        .build_and_run()
+        .unwrap()
+        .assert(IsSuccess);
+    // cgroups v2 has no per cgroup swappiness
+    if !swappiness_path.exists() {
+        return;
+    }
+    assert_eq!(fs::read_to_string(&swappiness_path).unwrap(), "0\n");
+
+    runner
+        .config_builder()
+        .disable_swap(DisableSwap::No)
+        .build_and_run()
+        .unwrap()
+        .assert(IsSuccess);
+    assert_eq!(
+        fs::read_to_string(&swappiness_path).unwrap(),
+        fs::read_to_string("/sys/fs/cgroup/memory/ia-sandbox/memory.swappiness").unwrap()
+    );
+}
+
+#[test]
+fn test_parent_limits() {
+    let mut parent_limits = LimitsBuilder::new();
+    parent_limits
+        .memory(SpaceUsage::from_megabytes(64))
+        .pids(20);
+
+    // All runs race to create the parent, exactly one of them writes its limits
+    let runs: Vec<_> = (0..8)
+        .map(|run| {
+            thread::spawn(move || {
+                let instance_name = format!("test_parent_limits/run{}", run);
+                let mut helper = TestRunnerHelper::for_simple_exec(
+                    &instance_name,
+                    HELLO_WORLD,
+                    PivotRoot::DoNot,
+                );
+                helper
+                    .config_builder()
+                    .parent_limits(parent_limits)
+                    .build_and_run()
+            })
+        })
+        .collect();
+    for run in runs {
+        run.join().unwrap().unwrap().assert(IsSuccess);
+    }
+
+    let memory_parent = Path::new("/sys/fs/cgroup/memory/ia-sandbox/test_parent_limits");
+    let pids_parent = Path::new("/sys/fs/cgroup/pids/ia-sandbox/test_parent_limits");
+    let memory_limit = fs::read_to_string(memory_parent.join("memory.limit_in_bytes"));
+    let pids_limit = fs::read_to_string(pids_parent.join("pids.max"));
+    // The helpers removed their instances, only the parent is left
+    let leftovers: Vec<_> = ["cpuacct", "memory", "pids"]
+        .iter()
+        .flat_map(|controller| {
+            let parent_path = Path::new("/sys/fs/cgroup")
+                .join(controller)
+                .join("ia-sandbox/test_parent_limits");
+            let leftovers: Vec<_> = fs::read_dir(&parent_path)
+                .unwrap()
+                .map(|entry| entry.unwrap().path())
+                .filter(|path| path.is_dir())
+                .collect();
+            fs::remove_dir(parent_path).unwrap();
+            leftovers
+        })
+        .collect();
+    assert_eq!(leftovers, Vec::<std::path::PathBuf>::new());
+    assert_eq!(memory_limit.unwrap(), "64000000\n");
+    assert_eq!(pids_limit.unwrap(), "20\n");
+}
+
+#[test]
+fn test_parent_limits_exceeded() {
+    let mut parent_limits = LimitsBuilder::new();
+    parent_limits.memory(SpaceUsage::from_megabytes(10));
+
+    TestRunnerHelper::for_simple_exec(
+        "test_parent_limits_exceeded/run",
+        ALLOCATE_20_MEGABYTES,
+        PivotRoot::DoNot,
+    )
+    .config_builder()
+    .parent_limits(parent_limits)
+    .build_and_run()
+    .unwrap()
+    .assert(MemoryLimitExceeded);
+    for controller in &["cpuacct", "memory", "pids"] {
+        fs::remove_dir(
+            Path::new("/sys/fs/cgroup")
+                .join(controller)
+                .join("ia-sandbox/test_parent_limits_exceeded"),
+        )
+        .unwrap();
+    }
+}
+
+// Makes `syscalls` fail with `errno` for the calling thread and everything it spawns
+fn deny_syscalls(syscalls: &[libc::c_long], errno: libc::c_int) {
+    let statement = |code: u32, k: u32| libc::sock_filter {
+        code: code as u16,
+        jt: 0,
+        jf: 0,
+        k,
+    };
+    let deny = |syscall: libc::c_long| {
+        vec![
+            libc::sock_filter {
+                code: (libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K) as u16,
+                jt: 0,
+                jf: 1,
+                k: syscall as u32,
+            },
+            statement(
+                libc::BPF_RET | libc::BPF_K,
+                libc::SECCOMP_RET_ERRNO | errno as u32,
+            ),
+        ]
+    };
+    let mut filter = vec![statement(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, 0)];
+    for &syscall in syscalls {
+        filter.extend(deny(syscall));
+    }
+    filter.push(statement(
+        libc::BPF_RET | libc::BPF_K,
+        libc::SECCOMP_RET_ALLOW,
+    ));
+    let program = libc::sock_fprog {
+        len: filter.len() as u16,
+        filter: filter.as_mut_ptr(),
+    };
+    assert_eq!(
+        unsafe {
+            libc::prctl(
+                libc::PR_SET_SECCOMP,
+                libc::SECCOMP_MODE_FILTER,
+                &program as *const libc::sock_fprog,
+            )
+        },
+        0,
+        "{}",
+        io::Error::last_os_error()
+    );
+}
+
+// Makes the rename syscalls fail with EPERM, as cgroups v2 does for renaming cgroups
+fn deny_renames() {
+    #[cfg(target_arch = "x86_64")]
+    deny_syscalls(&[libc::SYS_rename], libc::EPERM);
+    deny_syscalls(&[libc::SYS_renameat, libc::SYS_renameat2], libc::EPERM);
+}
+
+#[test]
+fn test_parent_limits_without_rename() {
+    // The filter stays with the thread, so the run gets its own
+    let run_info = thread::spawn(|| {
+        deny_renames();
+        let mut parent_limits = LimitsBuilder::new();
+        parent_limits.pids(20);
+        TestRunnerHelper::for_simple_exec(
+            "test_parent_limits_without_rename/run",
+            HELLO_WORLD,
+            PivotRoot::DoNot,
+        )
+        .config_builder()
+        .parent_limits(parent_limits)
+        .build_and_run()
+    })
+    .join()
+    .unwrap();
+
+    let pids_limit = fs::read_to_string(
+        "/sys/fs/cgroup/pids/ia-sandbox/test_parent_limits_without_rename/pids.max",
+    );
+    for controller in &["cpuacct", "memory", "pids"] {
+        fs::remove_dir(
+            Path::new("/sys/fs/cgroup")
+                .join(controller)
+                .join("ia-sandbox/test_parent_limits_without_rename"),
+        )
+        .unwrap();
+    }
+    run_info.unwrap().assert(IsSuccess);
+    assert_eq!(pids_limit.unwrap(), "20\n");
+}
+
+#[test]
+fn test_invalid_instance_name() {
+    for instance_name in &[
+        "",
+        "/test_invalid_instance_name",
+        "../test_invalid_instance_name",
+    ] {
+        match TestRunnerHelper::for_simple_exec(
+            "test_invalid_instance_name",
+            HELLO_WORLD,
+            PivotRoot::DoNot,
+        )
+        .config_builder()
+        .instance_name(instance_name)
+        .build_and_run()
+        .unwrap_err()
+        .into_config_error()
+        {
+            ConfigError::InvalidInstanceName(name) => {
+                assert_eq!(name, *instance_name)
+            }
+            err => panic!("Expected invalid instance name error, got {}", err),
+        }
+    }
+
+    match TestRunnerHelper::for_simple_exec(
+        "test_invalid_instance_name",
+        HELLO_WORLD,
+        PivotRoot::DoNot,
+    )
+    .config_builder()
+    .parent_limits(LimitsBuilder::new())
+    .build_and_run()
+    .unwrap_err()
+    .into_config_error()
+    {
+        ConfigError::ParentLimitsWithoutParent => {}
+        err => panic!("Expected parent limits without parent error, got {}", err),
+    }
+}
+
+fn remove_shm_segment(key: libc::key_t) {
+    let id = unsafe { libc::shmget(key, 0, 0) };
+    if id != -1 {
+        let _ = unsafe { libc::shmctl(id, libc::IPC_RMID, ptr::null_mut()) };
+    }
+}
+
+#[test]
+fn test_share_ipc() {
+    // The key create_shm_segment uses
+    let key = 0x1a5a_0293;
+    remove_shm_segment(key);
+
+    let mut helper =
+        TestRunnerHelper::for_simple_exec("test_share_ipc", CREATE_SHM_SEGMENT, PivotRoot::DoNot);
+    // Every run gets a fresh IPC namespace, the segment of the first one is gone
+    for _ in 0..2 {
+        helper
+            .config_builder()
+            .build_and_run()
+            .unwrap()
+            .assert(IsSuccess);
+    }
+
+    helper.config_builder().share_ipc(ShareIpc::Share);
+    helper
+        .config_builder()
+        .build_and_run()
+        .unwrap()
+        .assert(IsSuccess);
+    let run_info = helper.config_builder().build_and_run().unwrap();
+    remove_shm_segment(key);
+    run_info.assert(NonZeroExitStatus::new(1));
+}
+
+#[test]
+fn test_sandbox_user() {
+    let mut helper =
+        TestRunnerHelper::for_simple_exec("test_sandbox_user", PRINT_UID_GID, PivotRoot::Pivot);
+
+    let output_path = helper.file_path("output");
+    helper
+        .config_builder()
+        .stdout(&output_path)
+        .isolation_mode(IsolationMode::UserNamespace)
+        .build_and_run()
+        .unwrap()
+        .assert(IsSuccess);
+    assert_eq!(helper.read_line(&output_path), "0 0\n");
+
+    helper
+        .config_builder()
+        .sandbox_user(1000, 1001)
+        .build_and_run()
+        .unwrap()
+        .assert(IsSuccess);
+    assert_eq!(helper.read_line(&output_path), "1000 1001\n");
+}
+
+#[test]
+fn test_root_isolation_mode() {
+    let mut helper = TestRunnerHelper::for_simple_exec(
+        "test_root_isolation_mode",
+        PRINT_UID_GID,
+        PivotRoot::Pivot,
+    );
+
+    // Without a user namespace the command must not stay root
+    let output_path = helper.file_path("output");
+    helper
+        .config_builder()
+        .stdout(&output_path)
+        .isolation_mode(IsolationMode::Root)
+        .build_and_run()
+        .unwrap()
+        .assert(IsSuccess);
+    assert_eq!(helper.read_line(&output_path), "65534 65534\n");
+
+    helper
+        .config_builder()
+        .sandbox_user(1000, 1001)
+        .build_and_run()
+        .unwrap()
+        .assert(IsSuccess);
+    assert_eq!(helper.read_line(&output_path), "1000 1001\n");
+}
+
+#[test]
+fn test_drop_capabilities() {
+    let mut helper = TestRunnerHelper::for_simple_exec(
+        "test_drop_capabilities",
+        TRY_PRIVILEGED_OPERATIONS,
+        PivotRoot::Pivot,
+    );
+
+    // Both fail with EPERM without the capabilities, even as root of the namespaces
+    let output_path = helper.file_path("output");
+    helper
+        .config_builder()
+        .stdout(&output_path)
+        .build_and_run()
+        .unwrap()
+        .assert(IsSuccess);
+    assert_eq!(
+        helper.read_line(&output_path),
+        format!("{} {}\n", libc::EPERM, libc::EPERM)
+    );
+
+    // The sandbox owns its uts and mount namespaces, so the kept capability works on them
+    helper
+        .config_builder()
+        .keep_capability(Capability::SysAdmin)
+        .build_and_run()
+        .unwrap()
+        .assert(IsSuccess);
+    assert_eq!(helper.read_line(&output_path), "0 0\n");
+}
+
+fn seccomp_policy(default_action: SeccompAction) -> SeccompPolicy {
+    SeccompPolicy::new(
+        SECCOMP_ALLOWED_SYSCALLS
+            .iter()
+            .map(|&syscall| syscall.to_owned())
+            .collect(),
+        default_action,
+    )
+}
+
+#[test]
+fn test_seccomp() {
+    let mut helper =
+        TestRunnerHelper::for_simple_exec("test_seccomp", OPEN_SOCKET, PivotRoot::Pivot);
+
+    helper
+        .config_builder()
+        .seccomp(seccomp_policy(SeccompAction::Kill))
+        .build_and_run()
+        .unwrap()
+        .assert(IllegalSyscall(libc::SYS_socket as u32, "socket"));
+
+    // Failed instead, the command goes on
+    let output_path = helper.file_path("output");
+    helper
+        .config_builder()
+        .seccomp(seccomp_policy(SeccompAction::Errno(libc::EACCES as u16)))
+        .stdout(&output_path)
+        .build_and_run()
+        .unwrap()
+        .assert(IsSuccess);
+    assert_eq!(helper.read_line(&output_path), "-1\n");
+
+    let mut helper =
+        TestRunnerHelper::for_simple_exec("test_seccomp", HELLO_WORLD, PivotRoot::Pivot);
+    helper
+        .config_builder()
+        .seccomp(seccomp_policy(SeccompAction::Kill))
+        .build_and_run()
+        .unwrap()
+        .assert(IsSuccess);
+}
+
+// Runs in the supervisor, which sees the paths inside the new root as they are
+fn deny_etc_passwd(notification: SeccompNotification) -> SeccompResponse {
+    let address = match notification.name() {
+        Some("open") => notification.args()[0],
+        _ => notification.args()[1],
+    };
+    match notification.read_path(address) {
+        Ok(ref path) if path == Path::new("/etc/passwd") => SeccompResponse::Errno(libc::ENOENT),
+        Ok(_) => SeccompResponse::Continue,
+        Err(_) => SeccompResponse::Errno(libc::EFAULT),
+    }
+}
+
+#[test]
+fn test_seccomp_notify() {
+    let mut helper =
+        TestRunnerHelper::for_simple_exec("test_seccomp_notify", OPEN_PATHS, PivotRoot::Pivot);
+    fs::create_dir(helper.file_path("etc")).unwrap();
+    helper.write_file("etc/passwd", b"root:x:0:0::/root:/bin/sh\n");
+    helper.write_file("allowed", b"");
+
+    let mut policy = SeccompPolicy::new(
+        SECCOMP_ALLOWED_SYSCALLS
+            .iter()
+            .filter(|&&syscall| syscall != "open" && syscall != "openat")
+            .map(|&syscall| syscall.to_owned())
+            .collect(),
+        SeccompAction::Kill,
+    );
+    policy.set_notify(vec!["open".to_owned(), "openat".to_owned()]);
+
+    let output_path = helper.file_path("output");
+    helper
+        .config_builder()
+        .seccomp(policy)
+        .seccomp_notify_handler(SeccompNotifyHandler::new(deny_etc_passwd))
+        .args(["/etc/passwd", "/allowed"].iter())
+        .stdout(&output_path)
+        .build_and_run()
+        .unwrap()
+        .assert(IsSuccess);
+    assert_eq!(
+        helper.read_line(&output_path),
+        format!("{} 0\n", libc::ENOENT)
+    );
+}
+
+#[test]
+fn test_seccomp_profile_cpp() {
+    // Built here, where there is a C++ compiler
+    let build_dir = Builder::new()
+        .prefix("test_seccomp_profile_cpp")
+        .tempdir()
+        .unwrap();
+    let source = build_dir.path().join("hello_world.cpp");
+    fs::write(&source, CPP_HELLO_WORLD).unwrap();
+    let exec = build_dir.path().join("hello_world_cpp");
+    match Command::new("g++")
+        .arg(&source)
+        .arg("-o")
+        .arg(&exec)
+        .status()
+    {
+        Ok(status) => assert!(status.success()),
+        Err(_) => return,
+    }
+
+    let mut helper =
+        TestRunnerHelper::for_simple_exec("test_seccomp_profile_cpp", &exec, PivotRoot::Pivot);
+    let output_path = helper.file_path("output");
+    helper
+        .config_builder()
+        .seccomp(seccomp_profiles::cpp())
+        .stdout(&output_path)
+        .build_and_run()
+        .unwrap()
+        .assert(IsSuccess);
+    assert_eq!(helper.read_line(&output_path), "Hello World!\n");
+
+    // Composed, without the syscall the program starts with
+    let mut helper =
+        TestRunnerHelper::for_simple_exec("test_seccomp_profile_cpp", &exec, PivotRoot::Pivot);
+    helper
+        .config_builder()
+        .seccomp(seccomp_profiles::cpp().deny("brk"))
+        .build_and_run()
+        .unwrap()
+        .assert(IllegalSyscall(libc::SYS_brk as u32, "brk"));
+}
+
+#[test]
+fn test_seccomp_profile_python3() {
+    // The interpreter needs its standard library, so it runs in the host root
+    if !Path::new(PYTHON3).exists() {
+        return;
+    }
+
+    let mut helper = TestRunnerHelper::for_simple_exec(
+        "test_seccomp_profile_python3",
+        PYTHON3,
+        PivotRoot::DoNot,
+    );
+    let output_path = helper.file_path("output");
+    helper
+        .config_builder()
+        .seccomp(seccomp_profiles::python3())
+        .args(vec!["-c", "print('Hello World!')"])
+        .stdout(&output_path)
+        .build_and_run()
+        .unwrap()
+        .assert(IsSuccess);
+    assert_eq!(helper.read_line(&output_path), "Hello World!\n");
+}
+
+#[test]
+fn test_landlock() {
+    let mut helper =
+        TestRunnerHelper::for_simple_exec("test_landlock", OPEN_PATHS, PivotRoot::Pivot);
+    helper.write_file("allowed", b"");
+    helper.write_file("secret", b"");
+
+    let policy = LandlockPolicy::new(
+        vec![
+            (PathBuf::from("/open_paths"), vec![LandlockAccess::Execute]),
+            (PathBuf::from("/lib"), vec![LandlockAccess::Execute]),
+            (PathBuf::from("/lib64"), vec![LandlockAccess::Execute]),
+            (PathBuf::from("/allowed"), vec![LandlockAccess::ReadFile]),
+        ],
+        LandlockUnsupported::Fail,
+    );
+    let output_path = helper.file_path("output");
+    helper
+        .config_builder()
+        .landlock(policy)
+        .args(vec!["/secret", "/allowed"])
+        .stdout(&output_path)
+        .build_and_run()
+        .unwrap()
+        .assert(IsSuccess);
+    assert_eq!(
+        helper.read_line(&output_path),
+        format!("{} 0\n", libc::EACCES)
+    );
+
+    let mut helper =
+        TestRunnerHelper::for_simple_exec("test_landlock", OPEN_PATHS, PivotRoot::Pivot);
+    match helper
+        .config_builder()
+        .landlock(LandlockPolicy::new(
+            vec![(PathBuf::from("lib"), vec![LandlockAccess::ReadFile])],
+            LandlockUnsupported::Fail,
+        ))
+        .build_and_run()
+        .unwrap_err()
+        .into_config_error()
+    {
+        ConfigError::LandlockPathNotAbsolute(path) => {
+            assert_eq!(path, Path::new("lib"))
+        }
+        err => panic!("Expected landlock path error, got {}", err),
+    }
+}
+
+#[test]
+fn test_landlock_unsupported() {
+    // The filter stays with the thread, as a kernel without Landlock the check fails with ENOSYS
+    thread::spawn(|| {
+        deny_syscalls(&[libc::SYS_landlock_create_ruleset], libc::ENOSYS);
+        let policy = |unsupported| {
+            LandlockPolicy::new(
+                vec![(PathBuf::from("/"), vec![LandlockAccess::ReadFile])],
+                unsupported,
+            )
+        };
+
+        let run_info = TestRunnerHelper::for_simple_exec(
+            "test_landlock_unsupported",
+            HELLO_WORLD,
+            PivotRoot::DoNot,
+        )
+        .config_builder()
+        .landlock(policy(LandlockUnsupported::Warn))
+        .build_and_run()
+        .unwrap();
+        assert_eq!(run_info.warnings().len(), 1);
+        assert!(
+            run_info.warnings()[0].contains("without the filesystem restriction"),
+            "{:?}",
+            run_info.warnings()
+        );
+        assert!(run_info.to_string().contains("Warning: running without"));
+        run_info.assert(IsSuccess);
+
+        match TestRunnerHelper::for_simple_exec(
+            "test_landlock_unsupported",
+            HELLO_WORLD,
+            PivotRoot::DoNot,
+        )
+        .config_builder()
+        .landlock(policy(LandlockUnsupported::Fail))
+        .build_and_run()
+        .unwrap_err()
+        {
+            Error::UnsupportedEnvironment { .. } => {}
+            err => panic!("Expected unsupported environment error, got {}", err),
+        }
+    })
+    .join()
+    .unwrap();
+}
+
+#[test]
+fn test_single_exec() {
+    let mut helper =
+        TestRunnerHelper::for_simple_exec("test_single_exec", FORK_EXEC, PivotRoot::DoNot);
+    helper
+        .config_builder()
+        .single_exec(SingleExec::Yes)
+        .build_and_run()
+        .unwrap()
+        .assert(IllegalSyscall(libc::SYS_execve as u32, "execve"));
+
+    let mut helper =
+        TestRunnerHelper::for_simple_exec("test_single_exec", HELLO_WORLD, PivotRoot::Pivot);
+    let output_path = helper.file_path("output");
+    helper
+        .config_builder()
+        .single_exec(SingleExec::Yes)
+        .stdout(&output_path)
+        .build_and_run()
+        .unwrap()
+        .assert(IsSuccess);
+    assert_eq!(helper.read_line(&output_path), "Hello World!\n");
+}
+
+#[test]
+fn test_deny_nested_namespaces() {
+    let mut helper = TestRunnerHelper::for_simple_exec(
+        "test_deny_nested_namespaces",
+        UNSHARE_USER,
+        PivotRoot::Pivot,
+    );
+    let output_path = helper.file_path("output");
+    helper
+        .config_builder()
+        .stdout(&output_path)
+        .build_and_run()
+        .unwrap()
+        .assert(IsSuccess);
+    assert_eq!(helper.read_line(&output_path), format!("{}\n", libc::EPERM));
+
+    helper
+        .config_builder()
+        .deny_nested_namespaces(DenyNestedNamespaces::No)
+        .build_and_run()
+        .unwrap()
+        .assert(IsSuccess);
+    assert_eq!(helper.read_line(&output_path), "0\n");
+}
+
+#[test]
+fn test_nested_sandbox() {
+    for environment in [Environment::default(), Environment::Forward] {
+        TestRunnerHelper::for_simple_exec(
+            "test_nested_sandbox",
+            SPAWN_NESTED_SANDBOX,
+            PivotRoot::DoNot,
+        )
+        .config_builder()
+        .environment(environment.clone())
+        .mark_inside_sandbox(MarkInsideSandbox::Yes)
+        .build_and_run()
+        .unwrap()
+        .assert(IsSuccess);
+
+        // Unmarked, the environment is left as it is
+        TestRunnerHelper::for_simple_exec(
+            "test_nested_sandbox",
+            SPAWN_NESTED_SANDBOX,
+            PivotRoot::DoNot,
+        )
+        .config_builder()
+        .environment(environment)
+        .build_and_run()
+        .unwrap()
+        .assert(NonZeroExitStatus::new(3));
+    }
+}
+
+#[test]
+fn test_trace_syscalls() {
+    let mut helper =
+        TestRunnerHelper::for_simple_exec("test_trace_syscalls", HELLO_WORLD, PivotRoot::Pivot);
+    let output_path = helper.file_path("output");
+    let trace_path = helper.file_path("trace");
+    let run_info = helper
+        .config_builder()
+        .stdout(&output_path)
+        .trace_syscalls(&trace_path)
+        .build_and_run()
+        .unwrap();
+    assert!(run_info.traced());
+    run_info.assert(IsSuccess);
+    assert_eq!(helper.read_line(&output_path), "Hello World!\n");
+
+    // "Hello World!\n" is written in one go
+    let trace = helper.read_line(&trace_path);
+    assert!(
+        trace
+            .lines()
+            .any(|line| line.contains(&format!(" write({}) = 13", libc::SYS_write))),
+        "no write in the trace:\n{}",
+        trace
+    );
+}
+
+#[test]
+fn test_share_pid() {
+    let mut helper =
+        TestRunnerHelper::for_simple_exec("test_share_pid", HELLO_WORLD, PivotRoot::Pivot);
+
+    let output_path = helper.file_path("output");
+    helper
+        .config_builder()
+        .share_pid(SharePid::Share)
+        .stdout(&output_path)
+        .build_and_run()
+        .unwrap()
+        .assert(IsSuccess);
+    assert_eq!(helper.read_line(&output_path), "Hello World!\n");
+}
+
+#[test]
+fn test_share_pid_wall_time_kill() {
+    let mut limits = LimitsBuilder::new();
+    limits.wall_time(Duration::from_millis(200));
+
+    let mut helper = TestRunnerHelper::for_simple_exec(
+        "test_share_pid_wall_time_kill",
+        ESCAPE_PROCESS_GROUP,
+        PivotRoot::Pivot,
+    );
+    helper
+        .config_builder()
+        .share_pid(SharePid::Share)
+        .limits(limits)
+        .build_and_run()
+        .unwrap()
+        .assert(WallTimeLimitExceeded);
+
+    // Including the child that left the process group
+    let procs = fs::read_to_string(
+        "/sys/fs/cgroup/pids/ia-sandbox/test_share_pid_wall_time_kill/cgroup.procs",
+    )
+    .unwrap();
+    assert_eq!(procs, "");
+}
+
+fn veth_config(host_name: &str, peer_name: &str, subnet: u8) -> VethConfig {
+    VethConfig::new(
+        host_name.to_owned(),
+        Ipv4Addr::new(10, 231, subnet, 1),
+        peer_name.to_owned(),
+        Ipv4Addr::new(10, 231, subnet, 2),
+        24,
+    )
+}
+
+#[test]
+fn test_veth() {
+    // Bound before the sandbox exists, so it accepts on the host end of the pair as well
+    let listener = TcpListener::bind("0.0.0.0:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let server = thread::spawn(move || {
+        let (mut stream, address) = listener.accept().unwrap();
+        let mut ping = [0; 4];
+        stream.read_exact(&mut ping).unwrap();
+        stream.write_all(b"pong").unwrap();
+        (address.ip(), ping)
+    });
+
+    TestRunnerHelper::for_simple_exec("test_veth", PING_TCP_ARG, PivotRoot::Pivot)
+        .config_builder()
+        .clone_user(CloneUser::No)
+        .share_net(false)
+        .veth(veth_config("ia-test-host", "ia-test-peer", 1))
+        .args(vec![format!("10.231.1.1:{}", port)])
+        .build_and_run()
+        .unwrap()
+        .assert(IsSuccess);
+
+    let (address, ping) = server.join().unwrap();
+    assert_eq!(address, Ipv4Addr::new(10, 231, 1, 2));
+    assert_eq!(&ping, b"ping");
+    // Gone along with the network namespace of the sandbox
+    assert!(!Path::new("/sys/class/net/ia-test-host").exists());
+}
+
+#[test]
+fn test_veth_in_user_namespace() {
+    match TestRunnerHelper::for_simple_exec(
+        "test_veth_in_user_namespace",
+        HELLO_WORLD,
+        PivotRoot::DoNot,
+    )
+    .config_builder()
+    .share_net(false)
+    .isolation_mode(IsolationMode::UserNamespace)
+    .veth(veth_config("ia-test-host2", "ia-test-peer2", 2))
     .build_and_run()
-    .unwrap()
-    .assert(CompareLimits::new(MemoryLimitExceeded, limits));
+    .unwrap_err()
+    {
+        Error::ChildError(ChildError::FFIError(err @ FFIError::VethPermissionError { .. })) => {
+            assert!(err.to_string().contains("CAP_NET_ADMIN"))
+        }
+        err => panic!("Expected veth permission error, got {}", err),
+    }
 }
 
 #[test]
-fn test_pids_limit_exceeded() {
-    let mut limits = LimitsBuilder::new();
-    limits.pids(5);
-
-    TestRunnerHelper::for_simple_exec(
-        "test_pids_limit_exceeded",
-        THREADS_ALLOCATE_20_MEGABYTES,
+fn test_join_namespaces() {
+    let mut helper = TestRunnerHelper::for_simple_exec(
+        "test_join_namespaces",
+        CHECK_INTERFACE_ARG,
         PivotRoot::Pivot,
+    );
+
+    // A named network namespace the way `ip netns add` makes them: the namespace of a thread
+    // that unshared it bind mounted on a file. An interface of its own tells it apart.
+    let netns_path = helper.file_path("netns");
+    File::create(&netns_path).unwrap();
+    let netns = CString::new(netns_path.as_os_str().as_bytes()).unwrap();
+    let netns = thread::spawn(move || {
+        assert_eq!(unsafe { libc::unshare(libc::CLONE_NEWNET) }, 0);
+        let thread_netns = CString::new("/proc/thread-self/ns/net").unwrap();
+        assert_eq!(
+            unsafe {
+                libc::mount(
+                    thread_netns.as_ptr(),
+                    netns.as_ptr(),
+                    ptr::null(),
+                    libc::MS_BIND,
+                    ptr::null(),
+                )
+            },
+            0
+        );
+        assert!(Command::new("ip")
+            .args([
+                "link",
+                "add",
+                "ia-join-test",
+                "type",
+                "veth",
+                "peer",
+                "ia-join-peer"
+            ])
+            .status()
+            .unwrap()
+            .success());
+        netns
+    })
+    .join()
+    .unwrap();
+
+    helper
+        .config_builder()
+        .clone_user(CloneUser::No)
+        .args(vec!["ia-join-test"])
+        .build_and_run()
+        .unwrap()
+        .assert(NonZeroExitStatus::new(1));
+
+    let result = helper
+        .config_builder()
+        .join_namespace(NamespaceKind::Net, &netns_path)
+        .build_and_run();
+    assert_eq!(unsafe { libc::umount(netns.as_ptr()) }, 0);
+    result.unwrap().assert(IsSuccess);
+}
+
+#[test]
+fn test_join_namespaces_conflicting() {
+    match TestRunnerHelper::for_simple_exec(
+        "test_join_namespaces_conflicting",
+        HELLO_WORLD,
+        PivotRoot::DoNot,
     )
     .config_builder()
-    .limits(limits)
+    .join_namespace(NamespaceKind::Pid, "/proc/self/ns/pid")
     .build_and_run()
-    .unwrap()
-    .assert(CompareLimits::new(IsSuccess, limits));
+    .unwrap_err()
+    .into_config_error()
+    {
+        ConfigError::CanNotJoinNamespace { kind, .. } => {
+            assert_eq!(kind, "pid")
+        }
+        err => panic!("Expected can not join namespace error, got {}", err),
+    }
+}
 
-    limits.pids(4);
-    TestRunnerHelper::for_simple_exec(
-        "test_pids_limit_exceeded",
-        THREADS_ALLOCATE_20_MEGABYTES,
-        PivotRoot::Pivot,
+#[test]
+fn test_controller_missing() {
+    let missing_path = Path::new("/sys/fs/cgroup/pids/test_controller_missing");
+    match TestRunnerHelper::for_simple_exec(
+        "test_controller_missing",
+        HELLO_WORLD,
+        PivotRoot::DoNot,
     )
     .config_builder()
-    .limits(limits)
+    .controller_path(ControllerPath::new(None, None, Some(missing_path.into())))
     .build_and_run()
-    .unwrap()
-    .assert(CompareLimits::new(NonZeroExitStatus::any(), limits));
+    .unwrap_err()
+    {
+        Error::ChildError(ChildError::CGroupError(err)) => {
+            match err {
+                CGroupError::ControllerMissing {
+                    ref controller,
+                    ref path,
+                    ..
+                } => {
+                    assert_eq!(controller, "pids");
+                    assert_eq!(path, missing_path);
+                }
+                ref err => panic!("Expected controller missing error, got {}", err),
+            }
+            assert!(
+                err.to_string().contains(&format!(
+                    "mkdir -p {:?} && chown -R {} {:?}",
+                    missing_path,
+                    unsafe { libc::getuid() },
+                    missing_path
+                )),
+                "{}",
+                err
+            );
+        }
+        err => panic!("Expected cgroup error, got {}", err),
+    }
+}
+
+fn write_cgroup_files(cgroup_path: &Path, files: &[(&str, &str)]) {
+    fs::create_dir_all(cgroup_path).unwrap();
+    for &(file, content) in files {
+        fs::write(cgroup_path.join(file), content).unwrap();
+    }
 }
 
 #[test]
-fn test_mount_directory() {
+fn test_hybrid_cgroups() {
+    // Memory on a cgroups v1 hierarchy, pids and the cpu time on the unified v2 one, mimicked
+    // with the files the sandbox uses
     let temp_dir = Builder::new()
-        .prefix("test_mount_directory_special")
+        .prefix("test_hybrid_cgroups")
         .tempdir()
         .unwrap();
-    let input_path = temp_dir.path().join("input");
-    let mut file = File::create(&input_path).unwrap();
-    let _ = file.write(b"15\n").unwrap();
+    let memory_path = temp_dir.path().join("memory");
+    let pids_path = temp_dir.path().join("pids");
+    let cpu_path = temp_dir.path().join("cpu");
+    let memory_instance_path = memory_path.join("test_hybrid_cgroups");
+    let pids_instance_path = pids_path.join("test_hybrid_cgroups");
+    let cpu_instance_path = cpu_path.join("test_hybrid_cgroups");
+    write_cgroup_files(
+        &memory_instance_path,
+        &[
+            ("tasks", ""),
+            ("cgroup.event_control", ""),
+            ("memory.limit_in_bytes", ""),
+            ("memory.max_usage_in_bytes", "0\n"),
+            ("memory.usage_in_bytes", "1048576\n"),
+            ("memory.stat", "cache 0\ntotal_rss 0\ntotal_mapped_file 0\n"),
+            ("memory.oom_control", ""),
+            ("memory.pressure_level", ""),
+        ],
+    );
+    for path in &[&pids_path, &cpu_path] {
+        write_cgroup_files(
+            path,
+            &[
+                ("cgroup.controllers", "cpu memory pids\n"),
+                ("cgroup.subtree_control", ""),
+            ],
+        );
+    }
+    write_cgroup_files(
+        &pids_instance_path,
+        &[
+            ("cgroup.controllers", "pids\n"),
+            ("cgroup.procs", ""),
+            ("pids.max", ""),
+        ],
+    );
+    write_cgroup_files(
+        &cpu_instance_path,
+        &[
+            ("cgroup.controllers", ""),
+            ("cgroup.procs", ""),
+            ("cpu.stat", "usage_usec 1500\n"),
+        ],
+    );
+    assert_eq!(CgroupVersion::detect(&memory_path), CgroupVersion::V1);
+    assert_eq!(CgroupVersion::detect(&pids_path), CgroupVersion::V2);
 
-    TestRunnerHelper::for_simple_exec("test_mount_directory", EXIT_WITH_ARG_FILE, PivotRoot::Pivot)
+    let mut limits = LimitsBuilder::new();
+    limits.memory(SpaceUsage::from_megabytes(64)).pids(5);
+    let mut helper =
+        TestRunnerHelper::for_simple_exec("test_hybrid_cgroups", HELLO_WORLD, PivotRoot::DoNot);
+    let run_info = helper
         .config_builder()
-        .mount(Mount::new(
-            temp_dir.path().into(),
-            "/mount".into(),
-            MountOptions::default(),
+        .limits(limits)
+        .controller_path(ControllerPath::new(
+            Some(cpu_path.clone()),
+            Some(memory_path.clone()),
+            Some(pids_path.clone()),
         ))
-        .arg("/mount/input")
         .build_and_run()
+        .unwrap();
+
+    let read = |path: &Path| fs::read_to_string(path).unwrap();
+    let memory_limit: u64 = read(&memory_instance_path.join("memory.limit_in_bytes"))
+        .trim()
+        .parse()
+        .unwrap();
+    assert!(memory_limit >= SpaceUsage::from_megabytes(64).as_bytes());
+    assert!(!read(&memory_instance_path.join("tasks")).is_empty());
+    assert_eq!(read(&pids_path.join("cgroup.subtree_control")), "+pids\n");
+    assert_eq!(read(&pids_instance_path.join("pids.max")), "5\n");
+    assert!(!read(&pids_instance_path.join("cgroup.procs")).is_empty());
+    assert!(!read(&cpu_instance_path.join("cgroup.procs")).is_empty());
+
+    // usage_usec can not be reset, it counts from where it was before the run
+    assert_eq!(run_info.usage().user_time(), Duration::from_secs(0));
+    assert_eq!(
+        run_info.usage().current_memory(),
+        SpaceUsage::from_mebibytes(1)
+    );
+    run_info.assert(IsSuccess);
+
+    fs::write(cpu_instance_path.join("cpu.stat"), "usage_usec 2500\n").unwrap();
+    let run_info = helper
+        .config_builder()
+        .clear_usage(ClearUsage::No)
+        .build_and_run()
+        .unwrap();
+    assert_eq!(run_info.usage().user_time(), Duration::from_micros(2500));
+    run_info.assert(IsSuccess);
+}
+
+#[test]
+fn test_controller_not_writable() {
+    let read_only = Builder::new()
+        .prefix("test_controller_not_writable")
+        .tempdir()
+        .unwrap();
+    let path = CString::new(read_only.path().as_os_str().as_bytes()).unwrap();
+    let tmpfs = CString::new("tmpfs").unwrap();
+    assert_eq!(
+        unsafe {
+            libc::mount(
+                tmpfs.as_ptr(),
+                path.as_ptr(),
+                tmpfs.as_ptr(),
+                libc::MS_RDONLY,
+                ptr::null(),
+            )
+        },
+        0
+    );
+
+    let result = TestRunnerHelper::for_simple_exec(
+        "test_controller_not_writable",
+        HELLO_WORLD,
+        PivotRoot::DoNot,
+    )
+    .config_builder()
+    .controller_path(ControllerPath::new(
+        None,
+        None,
+        Some(read_only.path().into()),
+    ))
+    .build_and_run();
+    assert_eq!(unsafe { libc::umount(path.as_ptr()) }, 0);
+
+    match result.unwrap_err() {
+        Error::ChildError(ChildError::CGroupError(err)) => {
+            match err {
+                CGroupError::NotWritable {
+                    ref controller,
+                    ref path,
+                    ..
+                } => {
+                    assert_eq!(controller, "pids");
+                    assert_eq!(path, read_only.path());
+                }
+                ref err => panic!("Expected not writable error, got {}", err),
+            }
+            assert!(err.to_string().contains("chown -R"), "{}", err);
+        }
+        err => panic!("Expected cgroup error, got {}", err),
+    }
+}
+
+#[test]
+fn test_cgroup_probe() {
+    let probe = cgroups::probe().unwrap();
+    let memory = probe.controller("memory").unwrap();
+    assert!(memory.mount_point().is_some(), "{}", probe);
+    assert!(memory.subtree().is_some(), "{}", probe);
+    assert_eq!(
+        memory.controller_path(),
+        Path::new("/sys/fs/cgroup/memory/ia-sandbox"),
+        "{}",
+        probe
+    );
+
+    // Paths that are given are kept
+    let controller_path =
+        probe.fill_controller_path(&ControllerPath::new(Some("/cpuacct".into()), None, None));
+    assert_eq!(controller_path.cpuacct(), Some(Path::new("/cpuacct")));
+    assert_eq!(
+        controller_path.memory(),
+        Some(Path::new("/sys/fs/cgroup/memory/ia-sandbox"))
+    );
+}
+
+#[test]
+fn test_cgroup_probe_remapped_root() {
+    // A container whose memory hierarchy is mounted at its own cgroup, with the judge in a
+    // cgroup of its own below that
+    let hierarchy = Builder::new()
+        .prefix("test_cgroup_probe_remapped_root")
+        .tempdir()
+        .unwrap();
+    fs::create_dir(hierarchy.path().join("judge")).unwrap();
+    let mount_info = MountInfo::parse(&format!(
+        "22 1 8:1 / / rw,relatime shared:1 - ext4 /dev/sda1 rw\n\
+         36 22 0:32 /docker/f00 {} rw,nosuid,nodev,noexec - cgroup cgroup rw,memory\n\
+         37 22 0:33 /docker/f00 /sys/fs/cgroup/cpu,cpuacct rw - cgroup cgroup rw,cpu,cpuacct\n\
+         38 22 0:34 /docker/f00 /sys/fs/cgroup/freezer rw - cgroup cgroup rw,freezer\n\
+         42 22 0:38 / /sys/fs/cgroup/unified rw - cgroup2 cgroup2 rw\n",
+        hierarchy.path().display()
+    ));
+    let probe = CgroupProbe::new(
+        &mount_info,
+        "5:freezer:/docker/f00\n\
+         4:memory:/docker/f00/judge\n\
+         2:cpu,cpuacct:/docker/f00\n\
+         1:pids:/docker/f00\n\
+         0::/docker/f00\n",
+    );
+
+    let memory = probe.controller("memory").unwrap();
+    assert_eq!(memory.mount_root(), Some(Path::new("/docker/f00")));
+    assert_eq!(memory.subtree(), Some(hierarchy.path().join("judge")));
+    assert!(memory.subtree().unwrap().is_dir());
+    assert_eq!(
+        probe.controller("cpuacct").unwrap().subtree(),
+        Some(Path::new("/sys/fs/cgroup/cpu,cpuacct").to_path_buf())
+    );
+    assert_eq!(
+        probe.controller("freezer").unwrap().subtree(),
+        Some(Path::new("/sys/fs/cgroup/freezer").to_path_buf())
+    );
+    // pids is not a v1 hierarchy here, so it is looked for on the v2 one
+    assert_eq!(
+        probe.controller("pids").unwrap().mount_point(),
+        Some(Path::new("/sys/fs/cgroup/unified"))
+    );
+    assert_eq!(
+        probe.controller("pids").unwrap().subtree(),
+        Some(Path::new("/sys/fs/cgroup/unified/docker/f00").to_path_buf())
+    );
+}
+
+#[test]
+fn test_cgroup_probe_namespace() {
+    // Inside a cgroup namespace the own cgroup shows up as `/`, and so does the root of a
+    // hierarchy mounted from within it. One mounted from outside it is out of reach.
+    let mount_info = MountInfo::parse(
+        "36 22 0:32 / /sys/fs/cgroup/memory rw - cgroup cgroup rw,memory\n\
+         40 22 0:36 /.. /sys/fs/cgroup/pids rw - cgroup cgroup rw,pids\n",
+    );
+    let probe = CgroupProbe::new(&mount_info, "4:memory:/\n1:pids:/\n");
+
+    assert_eq!(
+        probe.controller("memory").unwrap().subtree(),
+        Some(Path::new("/sys/fs/cgroup/memory").to_path_buf())
+    );
+    assert_eq!(probe.controller("pids").unwrap().subtree(), None);
+    assert_eq!(probe.controller("blkio").unwrap().mount_point(), None);
+    assert_eq!(probe.controller("blkio").unwrap().subtree(), None);
+}
+
+fn fake_elf_header(machine: u16) -> Vec<u8> {
+    let mut header = b"\x7fELF\x02\x01\x01".to_vec();
+    header.resize(64, 0);
+    header[16] = 2; // ET_EXEC
+    header[18..20].copy_from_slice(&machine.to_le_bytes());
+    header
+}
+
+const QEMU_AARCH64_BINFMT: &str = "enabled
+interpreter /usr/bin/qemu-aarch64-static
+flags: OC
+offset 0
+magic 7f454c460201010000000000000000000200b700
+mask ffffffffffffff00fffffffffffffffffeffffff
+";
+
+#[test]
+fn test_binfmt_elf_machine() {
+    assert_eq!(binfmt::elf_machine(&fake_elf_header(183)), Some(183));
+    let mut big_endian = fake_elf_header(0);
+    big_endian[5] = 2;
+    big_endian[18..20].copy_from_slice(&22u16.to_be_bytes());
+    assert_eq!(binfmt::elf_machine(&big_endian), Some(22));
+    assert_eq!(binfmt::elf_machine(b"#!/bin/sh\n"), None);
+    assert_eq!(binfmt::elf_machine(b"\x7fELF"), None);
+
+    assert!(binfmt::host_machine().is_some());
+    assert_eq!(binfmt::machine_name(183), "aarch64");
+    assert_eq!(binfmt::machine_name(0xbeef), "machine 48879");
+}
+
+#[test]
+fn test_binfmt_entry() {
+    let entry = BinfmtEntry::parse("qemu-aarch64", QEMU_AARCH64_BINFMT).unwrap();
+    assert_eq!(
+        entry.interpreter(),
+        Path::new("/usr/bin/qemu-aarch64-static")
+    );
+    assert!(entry.enabled());
+    assert!(!entry.fix_binary());
+    assert!(entry.matches("solution", &fake_elf_header(183)));
+    assert!(!entry.matches("solution", &fake_elf_header(62)));
+    assert!(!entry.matches("solution", b"\x7fELF"));
+
+    let entry = BinfmtEntry::parse(
+        "wine",
+        "disabled\ninterpreter /usr/bin/wine\nflags: F\nextension .exe\n",
+    )
+    .unwrap();
+    assert!(!entry.enabled());
+    assert!(entry.fix_binary());
+    assert!(entry.matches("solution.exe", b""));
+    assert!(!entry.matches("solution", b""));
+    assert_eq!(Binfmt::new(vec![entry]).find("solution.exe", b""), None);
+
+    assert_eq!(BinfmtEntry::parse("broken", "enabled\nflags: \n"), None);
+}
+
+#[test]
+fn test_binfmt_missing_interpreter() {
+    let new_root = Builder::new()
+        .prefix("test_binfmt_missing_interpreter")
+        .tempdir()
+        .unwrap();
+    let host_machine = binfmt::host_machine().unwrap();
+    let foreign_machine: u16 = if host_machine == 183 { 62 } else { 183 };
+    // e_type and e_machine, both little endian
+    let content = QEMU_AARCH64_BINFMT.replace(
+        "0200b700",
+        &format!(
+            "0200{:02x}{:02x}",
+            foreign_machine & 0xff,
+            foreign_machine >> 8
+        ),
+    );
+
+    File::create(new_root.path().join("foreign"))
         .unwrap()
-        .assert(NonZeroExitStatus::new(15));
+        .write_all(&fake_elf_header(foreign_machine))
+        .unwrap();
+    File::create(new_root.path().join("native"))
+        .unwrap()
+        .write_all(&fake_elf_header(host_machine))
+        .unwrap();
+
+    let binfmt = Binfmt::new(vec![BinfmtEntry::parse("qemu", &content).unwrap()]);
+    let missing_interpreter = binfmt
+        .missing_interpreter(new_root.path(), Path::new("/foreign"))
+        .unwrap();
+    assert_eq!(
+        missing_interpreter.interpreter(),
+        Path::new("/usr/bin/qemu-aarch64-static")
+    );
+    assert_eq!(
+        missing_interpreter.arch(),
+        binfmt::machine_name(foreign_machine)
+    );
+    assert_eq!(
+        binfmt.missing_interpreter(new_root.path(), Path::new("/native")),
+        None
+    );
+    assert_eq!(
+        binfmt.missing_interpreter(new_root.path(), Path::new("/missing")),
+        None
+    );
+
+    // Registered with the F flag, the kernel already holds the interpreter open
+    let fixed = Binfmt::new(vec![BinfmtEntry::parse(
+        "qemu",
+        &content.replace("flags: OC", "flags: OCF"),
+    )
+    .unwrap()]);
+    assert_eq!(
+        fixed.missing_interpreter(new_root.path(), Path::new("/foreign")),
+        None
+    );
+
+    fs::create_dir_all(new_root.path().join("usr/bin")).unwrap();
+    File::create(new_root.path().join("usr/bin/qemu-aarch64-static")).unwrap();
+    assert_eq!(
+        binfmt.missing_interpreter(new_root.path(), Path::new("/foreign")),
+        None
+    );
 }
 
 #[test]
@@ -409,7 +5329,13 @@ fn test_clear_usage() {
     let mut limits = LimitsBuilder::new();
     limits.user_time(Duration::from_millis(600));
 
-    TestRunnerHelper::for_simple_exec("test_clear_usage", THREADS_LOOP_500_MS, PivotRoot::Pivot)
+    // A single helper for all runs, dropping it removes the instance cgroups
+    let mut helper = TestRunnerHelper::for_simple_exec(
+        "test_clear_usage",
+        THREADS_LOOP_500_MS,
+        PivotRoot::Pivot,
+    );
+    helper
         .config_builder()
         .limits(limits)
         .clear_usage(ClearUsage::Yes)
@@ -417,23 +5343,65 @@ fn test_clear_usage() {
         .unwrap()
         .assert(CompareLimits::new(IsSuccess, limits));
 
-    TestRunnerHelper::for_simple_exec("test_clear_usage", THREADS_LOOP_500_MS, PivotRoot::Pivot)
+    helper
         .config_builder()
-        .limits(limits)
         .clear_usage(ClearUsage::No)
         .build_and_run()
         .unwrap()
         .assert(CompareLimits::new(TimeLimitExceeded, limits));
 
-    TestRunnerHelper::for_simple_exec("test_clear_usage", THREADS_LOOP_500_MS, PivotRoot::Pivot)
+    helper
         .config_builder()
-        .limits(limits)
         .clear_usage(ClearUsage::Yes)
         .build_and_run()
         .unwrap()
         .assert(CompareLimits::new(IsSuccess, limits));
 }
 
+#[test]
+fn test_clear_usage_memory() {
+    let failcnt_path =
+        Path::new("/sys/fs/cgroup/memory/ia-sandbox/test_clear_usage_memory/memory.failcnt");
+    let mut limits = LimitsBuilder::new();
+    limits.memory(SpaceUsage::from_megabytes(10));
+
+    let mut helper = TestRunnerHelper::for_simple_exec(
+        "test_clear_usage_memory",
+        ALLOCATE_20_MEGABYTES,
+        PivotRoot::DoNot,
+    );
+    helper
+        .config_builder()
+        .limits(limits)
+        .build_and_run()
+        .unwrap()
+        .assert(CompareLimits::new(MemoryLimitExceeded, limits));
+
+    let run_info = helper
+        .config_builder()
+        .limits(LimitsBuilder::new())
+        .build_and_run()
+        .unwrap();
+    let usage = run_info.usage().clone();
+    run_info.assert(IsSuccess);
+    assert!(
+        usage.memory() >= SpaceUsage::from_megabytes(20),
+        "{}",
+        usage
+    );
+    assert_eq!(helper.read_line(failcnt_path), "0\n");
+
+    // The peak of the previous runs is not inherited
+    let run_info = helper
+        .config_builder()
+        .command(HELLO_WORLD)
+        .build_and_run()
+        .unwrap();
+    let usage = run_info.usage().clone();
+    run_info.assert(IsSuccess);
+    assert!(usage.memory() < SpaceUsage::from_megabytes(10), "{}", usage);
+}
+
 #[test]
 fn test_environment() {
     TestRunnerHelper::for_simple_exec("exit_with_env", EXIT_WITH_ENV, PivotRoot::Pivot)
@@ -448,51 +5416,314 @@ fn test_environment() {
 }
 
 #[test]
-fn test_interactive() {
-    let temp_dir = Builder::new().prefix("test_interactive").tempdir().unwrap();
+fn test_kill_grace_period() {
+    let mut limits = LimitsBuilder::new();
+    limits.wall_time(Duration::from_millis(300));
+    // The timings are measured against the run killed right away, which takes as long to set
+    // up and to tear down under load
+    let grace_period = Duration::from_secs(1);
+
+    let mut helper = TestRunnerHelper::for_simple_exec(
+        "test_kill_grace_period",
+        SIGTERM_WITH_ARG,
+        PivotRoot::DoNot,
+    );
+    let output_path = helper.file_path("output");
+    let timer = Instant::now();
+    let run_info = helper
+        .config_builder()
+        .limits(limits)
+        .args(vec!["ignore"])
+        .stdout(&output_path)
+        .build_and_run()
+        .unwrap();
+    let killed_right_away = timer.elapsed();
+    assert_eq!(run_info.wait_status(), None);
+    run_info.assert(WallTimeLimitExceeded);
+
+    // SIGTERM is ignored, so it is killed only once the grace period is over
+    let timer = Instant::now();
+    helper
+        .config_builder()
+        .kill_grace_period(grace_period)
+        .build_and_run()
+        .unwrap()
+        .assert(WallTimeLimitExceeded);
+    let elapsed = timer.elapsed();
+    assert!(
+        elapsed >= Duration::from_millis(300) + grace_period,
+        "{:?}",
+        elapsed
+    );
+    assert!(
+        elapsed < killed_right_away + grace_period * 2,
+        "{:?} vs {:?}",
+        elapsed,
+        killed_right_away
+    );
+    assert_eq!(fs::read_to_string(&output_path).unwrap(), "partial\n");
+
+    // Exiting on SIGTERM cuts the grace period short, the verdict stays the same and the exit
+    // is reaped within the grace period
+    let timer = Instant::now();
+    let run_info = helper
+        .config_builder()
+        .args(vec!["exit"])
+        .kill_grace_period(grace_period * 10)
+        .build_and_run()
+        .unwrap();
+    let elapsed = timer.elapsed();
+    assert!(
+        elapsed < killed_right_away + grace_period * 5,
+        "{:?} vs {:?}",
+        elapsed,
+        killed_right_away
+    );
+    assert_eq!(run_info.exit_status(), Some(0));
+    run_info.assert(WallTimeLimitExceeded);
+
+    // What it writes on SIGTERM makes it, be it for a limit or a cancel
+    let mut helper = TestRunnerHelper::for_simple_exec(
+        "test_kill_grace_period",
+        FLUSH_ON_SIGTERM,
+        PivotRoot::DoNot,
+    );
+    let output_path = helper.file_path("output");
+    helper
+        .config_builder()
+        .limits(limits)
+        .stdout(&output_path)
+        .kill_grace_period(Duration::from_secs(1))
+        .build_and_run()
+        .unwrap()
+        .assert(WallTimeLimitExceeded);
+    assert_eq!(fs::read_to_string(&output_path).unwrap(), "flushed\n");
 
-    let a_path = temp_dir.path().join("a_file");
-    let b_path = temp_dir.path().join("b_file");
+    fs::remove_file(&output_path).unwrap();
+    let mut handle = helper
+        .config_builder()
+        .limits(LimitsBuilder::new())
+        .build_and_spawn()
+        .unwrap();
+    let pid = helper.wait_for_exec(&mut handle, "flush_on_sigterm");
+    utils::wait_for_signal_handler(pid, libc::SIGTERM);
+    handle.kill().unwrap();
+    handle.wait().unwrap().assert(Cancelled);
+    assert_eq!(fs::read_to_string(&output_path).unwrap(), "flushed\n");
+}
 
-    utils::make_fifo(&a_path);
-    utils::make_fifo(&b_path);
+#[test]
+fn test_fifo_closed_early() {
+    let temp_dir = Builder::new()
+        .prefix("test_fifo_closed_early")
+        .tempdir()
+        .unwrap();
+    let fifo_path = temp_dir.path().join("fifo");
+    utils::make_fifo(&fifo_path);
 
     let mut limits = LimitsBuilder::new();
-    limits.wall_time(Duration::from_secs(1));
+    limits.wall_time(Duration::from_secs(3));
 
-    let mut write_then_read_helper = TestRunnerHelper::for_simple_exec(
-        "test_interactive_write_then_read",
-        WRITE_THEN_READ,
-        PivotRoot::Pivot,
+    let mut reader_helper = TestRunnerHelper::for_simple_exec(
+        "test_fifo_closed_early_reader",
+        CLOSE_STDIN_EARLY,
+        PivotRoot::DoNot,
     );
-    let write_then_read = write_then_read_helper
+    let reader = reader_helper
         .config_builder()
         .limits(limits)
-        .stdout(&a_path)
-        .stdin(&b_path)
-        .swap_redirects(SwapRedirects::Yes)
+        .stdin(&fifo_path)
         .build_and_spawn()
         .unwrap();
 
-    let mut read_then_write_helper = TestRunnerHelper::for_simple_exec(
-        "test_interactive_read_then_write",
-        READ_THEN_WRITE,
-        PivotRoot::Pivot,
+    let mut writer_helper = TestRunnerHelper::for_simple_exec(
+        "test_fifo_closed_early_writer",
+        WRITE_UNTIL_EPIPE,
+        PivotRoot::DoNot,
     );
-    let read_then_write = read_then_write_helper
+    let timer = Instant::now();
+    let writer = writer_helper
         .config_builder()
         .limits(limits)
-        .stdin(&a_path)
-        .stdout(&b_path)
+        .stdout(&fifo_path)
         .build_and_spawn()
         .unwrap();
 
-    write_then_read
+    // EPIPE comes as soon as the reader closes its stdin, long before it exits after a second
+    writer
         .wait()
         .unwrap()
-        .assert(AnnotateAssert::new(IsSuccess, "write_then_read"));
-    read_then_write
+        .assert(AnnotateAssert::new(NonZeroExitStatus::new(32), "writer"));
+    assert!(
+        timer.elapsed() < Duration::from_millis(700),
+        "{:?}",
+        timer.elapsed()
+    );
+    reader
         .wait()
         .unwrap()
-        .assert(AnnotateAssert::new(IsSuccess, "read_then_write"));
+        .assert(AnnotateAssert::new(IsSuccess, "reader"));
+}
+
+#[test]
+fn test_interactive() {
+    let run_pair = |name: &str,
+                    (solution, solution_args): (&str, &[&str]),
+                    (interactor, interactor_args): (&str, &[&str]),
+                    options: PairOptions| {
+        // The interactor gets longer, so it sees the end of a solution killed at its limit
+        // before reaching its own
+        let build = |helper: &mut TestRunnerHelper, args: &[&str], wall_time: u64| {
+            let mut limits = LimitsBuilder::new();
+            limits.wall_time(Duration::from_millis(wall_time));
+            helper
+                .config_builder()
+                .limits(limits)
+                .args(args)
+                .redirect_stdin(Redirect::Pipe)
+                .redirect_stdout(Redirect::Pipe)
+                .build()
+                .unwrap()
+        };
+        let (solution_name, interactor_name) =
+            (format!("{}_solution", name), format!("{}_interactor", name));
+        let mut solution_helper =
+            TestRunnerHelper::for_simple_exec(&solution_name, solution, PivotRoot::Pivot);
+        let solution = build(&mut solution_helper, solution_args, 300);
+        let mut interactor_helper =
+            TestRunnerHelper::for_simple_exec(&interactor_name, interactor, PivotRoot::Pivot);
+        let interactor = build(&mut interactor_helper, interactor_args, 600);
+        let timer = Instant::now();
+        let run_info = ia_sandbox::spawn_interactive_pair(&solution, &interactor, options)
+            .unwrap()
+            .wait()
+            .unwrap();
+        assert!(
+            timer.elapsed() < Duration::from_secs(1),
+            "{:?}",
+            timer.elapsed()
+        );
+        run_info
+    };
+
+    let run_info = run_pair(
+        "test_interactive",
+        (WRITE_THEN_READ, &[]),
+        (READ_THEN_WRITE, &[]),
+        PairOptions::default(),
+    );
+    assert_eq!(run_info.outcome(), InteractiveOutcome::Success);
+    let (solution, interactor) = run_info.into_run_infos();
+    solution.assert(AnnotateAssert::new(IsSuccess, "solution"));
+    interactor.assert(AnnotateAssert::new(IsSuccess, "interactor"));
+
+    // The solution exits with what the interactor sent it, the interactor checks that it got
+    // it back
+    let run_info = run_pair(
+        "test_interactive",
+        (EXIT_WITH_INPUT, &[]),
+        (ASK_ARG_AND_CHECK, &["7"]),
+        PairOptions::default(),
+    );
+    assert_eq!(run_info.outcome(), InteractiveOutcome::SolutionFailed);
+    let (solution, interactor) = run_info.into_run_infos();
+    solution.assert(AnnotateAssert::new(NonZeroExitStatus::new(7), "solution"));
+    interactor.assert(AnnotateAssert::new(IsSuccess, "interactor"));
+
+    // The interactor exits first without a word, the solution reads EOF instead of hanging
+    let run_info = run_pair(
+        "test_interactive",
+        (EXIT_WITH_INPUT, &[]),
+        (EXIT_WITH_LAST_ARGUMENT, &["0"]),
+        PairOptions::default(),
+    );
+    assert_eq!(run_info.outcome(), InteractiveOutcome::SolutionFailed);
+    let (solution, interactor) = run_info.into_run_infos();
+    solution.assert(NonZeroExitStatus::any());
+    interactor.assert(IsSuccess);
+
+    // The solution exceeds a limit, the interactor writing to it gets EPIPE
+    let run_info = run_pair(
+        "test_interactive",
+        (SLEEP_1_SECOND, &[]),
+        (WRITE_UNTIL_EPIPE, &[]),
+        PairOptions::default(),
+    );
+    assert_eq!(run_info.outcome(), InteractiveOutcome::SolutionFailed);
+    let (solution, interactor) = run_info.into_run_infos();
+    solution.assert(WallTimeLimitExceeded);
+    interactor.assert(NonZeroExitStatus::new(32));
+
+    // Both exceed a limit, the verdict is the one of the solution
+    let run_info = run_pair(
+        "test_interactive",
+        (SLEEP_1_SECOND, &[]),
+        (SLEEP_1_SECOND, &[]),
+        PairOptions::default(),
+    );
+    assert_eq!(run_info.outcome(), InteractiveOutcome::SolutionFailed);
+    let (solution, interactor) = run_info.into_run_infos();
+    solution.assert(WallTimeLimitExceeded);
+    interactor.assert(WallTimeLimitExceeded);
+
+    // Done with a solution that goes on
+    let run_info = run_pair(
+        "test_interactive",
+        (SLEEP_1_SECOND, &[]),
+        (EXIT_WITH_LAST_ARGUMENT, &["1"]),
+        PairOptions::new(Some(Duration::from_millis(100))),
+    );
+    assert_eq!(run_info.outcome(), InteractiveOutcome::InteractorFailed);
+    let (solution, interactor) = run_info.into_run_infos();
+    solution.assert(Cancelled);
+    interactor.assert(NonZeroExitStatus::new(1));
+
+    // Only over pipes
+    let mut helper =
+        TestRunnerHelper::for_simple_exec("test_interactive", HELLO_WORLD, PivotRoot::Pivot);
+    let config = helper.config_builder().build().unwrap();
+    match ia_sandbox::spawn_interactive_pair(&config, &config, PairOptions::default())
+        .err()
+        .unwrap()
+        .into_config_error()
+    {
+        ConfigError::InteractiveRedirect(stream) => assert_eq!(stream, "stdin"),
+        err => panic!("Unexpected error: {}", err),
+    }
+}
+
+#[test]
+fn test_fifo_open_timeout() {
+    let temp_dir = Builder::new()
+        .prefix("test_fifo_open_timeout")
+        .tempdir()
+        .unwrap();
+    let fifo_path = temp_dir.path().join("fifo");
+    utils::make_fifo(&fifo_path);
+
+    // Only one side of an interactive pair, nothing ever opens the other end of the FIFO
+    for &(command, stdin) in &[(EXIT_WITH_INPUT, true), (HELLO_WORLD, false)] {
+        let mut helper =
+            TestRunnerHelper::for_simple_exec("test_fifo_open_timeout", command, PivotRoot::Pivot);
+        let config_builder = helper.config_builder();
+        if stdin {
+            let _ = config_builder.stdin(&fifo_path);
+        } else {
+            let _ = config_builder.stdout(&fifo_path);
+        }
+        let start = Instant::now();
+        match config_builder
+            .fifo_open_timeout(Some(Duration::from_millis(300)))
+            .build_and_run()
+            .unwrap_err()
+        {
+            Error::ChildError(ChildError::FFIError(FFIError::RedirectTimeout { path })) => {
+                assert_eq!(path, fifo_path)
+            }
+            err => panic!("Expected redirect timeout error, got {:?}", err),
+        }
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(300), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_secs(3), "{:?}", elapsed);
+    }
 }
@@ -1,57 +1,55 @@
-use std::ffi::{OsStr, OsString};
+use std::env;
+use std::ffi::OsStr;
+use std::mem;
+use std::os::unix::io::RawFd;
 use std::path::{Path, PathBuf};
 use std::time::Duration;
 
 use ia_sandbox::config::{
-    ClearUsage, Config, ControllerPath, Environment, Interactive, Limits, Mount, ShareNet,
-    SpaceUsage, SwapRedirects,
+    self, AllocatePty, AllowSameFileIo, Capability, ClearUsage, CloneUser, CloseFds, Config,
+    ControllerPath, DenyNestedNamespaces, DisableSwap, Environment, ExcludeStoppedTime, FdSource,
+    Interactive, IsolationMode, LandlockPolicy, Limits, MarkInsideSandbox, MemoryAccounting,
+    MinimalDev, Mount, MountCgroups, NamespaceKind, OutputCallback, Overlay, Pausable, PrivateTmp,
+    ProcOptions, Propagation, ReadOnlyRoot, Redirect, RootImage, SeccompPolicy, ShareIpc, ShareNet,
+    SharePid, SingleExec, SpaceUsage, SwapRedirects, UsageCallback, VethConfig,
 };
 use ia_sandbox::run_info::RunInfo;
-use ia_sandbox::{self, JailHandle, Result};
-
-pub struct ConfigBuilder {
-    command: PathBuf,
-    args: Vec<OsString>,
-    new_root: Option<PathBuf>,
-    share_net: bool,
-    redirect_stdin: Option<PathBuf>,
-    redirect_stdout: Option<PathBuf>,
-    redirect_stderr: Option<PathBuf>,
-    limits: Option<Limits>,
-    instance_name: Option<OsString>,
-    mounts: Vec<Mount>,
-    swap_redirects: SwapRedirects,
-    clear_usage: ClearUsage,
-    environment: Environment,
-}
+use ia_sandbox::seccomp::SeccompNotifyHandler;
+use ia_sandbox::{self, Error, JailHandle, Result};
+
+// `config::ConfigBuilder` with the defaults of the tests, borrowed by the test runner helper
+pub struct ConfigBuilder(config::ConfigBuilder);
 
 impl ConfigBuilder {
-    pub fn new<T: AsRef<OsStr>>(command: T) -> ConfigBuilder {
-        ConfigBuilder {
-            command: command.as_ref().into(),
-            args: Vec::new(),
-            new_root: None,
-            share_net: true,
-            redirect_stdin: Some("/dev/null".into()),
-            redirect_stdout: Some("/dev/null".into()),
-            redirect_stderr: Some("/dev/null".into()),
-            limits: None,
-            instance_name: Some("test".into()),
-            mounts: Vec::new(),
-            swap_redirects: SwapRedirects::default(),
-            clear_usage: ClearUsage::default(),
-            environment: Environment::default(),
-        }
+    pub fn new<T: AsRef<Path>>(command: T) -> ConfigBuilder {
+        ConfigBuilder(
+            Config::builder(command)
+                .share_net(ShareNet::Share)
+                .redirect_stdin(PathBuf::from("/dev/null").into())
+                .redirect_stdout(PathBuf::from("/dev/null").into())
+                .redirect_stderr(PathBuf::from("/dev/null").into())
+                .instance_name("test")
+                .isolation_mode(isolation_mode_from_env())
+                .single_exec(SingleExec::No)
+                .deny_nested_namespaces(DenyNestedNamespaces::Yes),
+        )
     }
 
-    pub fn command<T: AsRef<Path>>(&mut self, command: T) -> &mut ConfigBuilder {
-        self.command = command.as_ref().into();
+    fn update<F>(&mut self, update: F) -> &mut ConfigBuilder
+    where
+        F: FnOnce(config::ConfigBuilder) -> config::ConfigBuilder,
+    {
+        let builder = mem::replace(&mut self.0, Config::builder(""));
+        self.0 = update(builder);
         self
     }
 
+    pub fn command<T: AsRef<Path>>(&mut self, command: T) -> &mut ConfigBuilder {
+        self.update(|builder| builder.command(command))
+    }
+
     pub fn arg<T: AsRef<OsStr>>(&mut self, arg: T) -> &mut ConfigBuilder {
-        self.args.push(arg.as_ref().into());
-        self
+        self.update(|builder| builder.arg(arg))
     }
 
     pub fn args<I, T>(&mut self, args: I) -> &mut ConfigBuilder
@@ -59,92 +57,298 @@ impl ConfigBuilder {
         I: IntoIterator<Item = T>,
         T: AsRef<OsStr>,
     {
-        for arg in args {
-            self.arg(arg);
-        }
-
-        self
+        self.update(|builder| builder.args(args))
     }
 
     pub fn new_root<T: AsRef<Path>>(&mut self, new_root: T) -> &mut ConfigBuilder {
-        self.new_root = Some(new_root.as_ref().into());
-        self
+        self.update(|builder| builder.new_root(new_root))
+    }
+
+    pub fn working_directory<T: AsRef<Path>>(
+        &mut self,
+        working_directory: T,
+    ) -> &mut ConfigBuilder {
+        self.update(|builder| builder.working_directory(working_directory))
+    }
+
+    pub fn copy_in<T: AsRef<Path>, U: AsRef<Path>>(
+        &mut self,
+        source: T,
+        destination: U,
+    ) -> &mut ConfigBuilder {
+        self.update(|builder| builder.copy_in(source, destination))
+    }
+
+    pub fn extra_fd(&mut self, fd: RawFd, source: FdSource) -> &mut ConfigBuilder {
+        self.update(|builder| builder.extra_fd(fd, source))
+    }
+
+    pub fn fifo_open_timeout(&mut self, fifo_open_timeout: Option<Duration>) -> &mut ConfigBuilder {
+        self.update(|builder| builder.fifo_open_timeout(fifo_open_timeout))
+    }
+
+    pub fn socket_channel(&mut self, fd: RawFd) -> &mut ConfigBuilder {
+        self.update(|builder| builder.socket_channel(fd))
+    }
+
+    pub fn close_fds(&mut self, close_fds: CloseFds) -> &mut ConfigBuilder {
+        self.update(|builder| builder.close_fds(close_fds))
+    }
+
+    pub fn copy_out<T: AsRef<Path>, U: AsRef<Path>>(
+        &mut self,
+        source: T,
+        destination: U,
+    ) -> &mut ConfigBuilder {
+        self.update(|builder| builder.copy_out(source, destination))
+    }
+
+    pub fn copy_out_limit(&mut self, copy_out_limit: SpaceUsage) -> &mut ConfigBuilder {
+        self.update(|builder| builder.copy_out_limit(copy_out_limit))
+    }
+
+    pub fn new_root_overlay(&mut self, new_root_overlay: Overlay) -> &mut ConfigBuilder {
+        self.update(|builder| builder.new_root_overlay(new_root_overlay))
+    }
+
+    pub fn root_image(&mut self, root_image: RootImage) -> &mut ConfigBuilder {
+        self.update(|builder| builder.root_image(root_image))
     }
 
     pub fn share_net(&mut self, share_net: bool) -> &mut ConfigBuilder {
-        self.share_net = share_net;
-        self
+        let share_net = if share_net {
+            ShareNet::Share
+        } else {
+            ShareNet::Unshare
+        };
+        self.update(|builder| builder.share_net(share_net))
+    }
+
+    pub fn share_ipc(&mut self, share_ipc: ShareIpc) -> &mut ConfigBuilder {
+        self.update(|builder| builder.share_ipc(share_ipc))
+    }
+
+    pub fn sandbox_user(&mut self, uid: u32, gid: u32) -> &mut ConfigBuilder {
+        self.update(|builder| builder.sandbox_user(uid, gid))
+    }
+
+    pub fn share_pid(&mut self, share_pid: SharePid) -> &mut ConfigBuilder {
+        self.update(|builder| builder.share_pid(share_pid))
+    }
+
+    pub fn clone_user(&mut self, clone_user: CloneUser) -> &mut ConfigBuilder {
+        self.update(|builder| builder.clone_user(clone_user))
+    }
+
+    pub fn veth(&mut self, veth: VethConfig) -> &mut ConfigBuilder {
+        self.update(|builder| builder.veth(veth))
+    }
+
+    pub fn join_namespace<T: AsRef<Path>>(
+        &mut self,
+        kind: NamespaceKind,
+        path: T,
+    ) -> &mut ConfigBuilder {
+        self.update(|builder| builder.join_namespace(kind, path))
+    }
+
+    pub fn propagation(&mut self, propagation: Propagation) -> &mut ConfigBuilder {
+        self.update(|builder| builder.propagation(propagation))
+    }
+
+    pub fn isolation_mode(&mut self, isolation_mode: IsolationMode) -> &mut ConfigBuilder {
+        self.update(|builder| builder.isolation_mode(isolation_mode))
+    }
+
+    pub fn keep_capability(&mut self, capability: Capability) -> &mut ConfigBuilder {
+        self.update(|builder| builder.keep_capability(capability))
+    }
+
+    pub fn seccomp(&mut self, seccomp: SeccompPolicy) -> &mut ConfigBuilder {
+        self.update(|builder| builder.seccomp(seccomp))
+    }
+
+    pub fn seccomp_notify_handler(
+        &mut self,
+        seccomp_notify_handler: SeccompNotifyHandler,
+    ) -> &mut ConfigBuilder {
+        self.update(|builder| builder.seccomp_notify_handler(seccomp_notify_handler))
+    }
+
+    pub fn on_output(&mut self, on_output: OutputCallback) -> &mut ConfigBuilder {
+        self.update(|builder| builder.on_output(on_output))
+    }
+
+    pub fn on_usage(&mut self, on_usage: UsageCallback) -> &mut ConfigBuilder {
+        self.update(|builder| builder.on_usage(on_usage))
+    }
+
+    pub fn landlock(&mut self, landlock: LandlockPolicy) -> &mut ConfigBuilder {
+        self.update(|builder| builder.landlock(landlock))
+    }
+
+    pub fn single_exec(&mut self, single_exec: SingleExec) -> &mut ConfigBuilder {
+        self.update(|builder| builder.single_exec(single_exec))
+    }
+
+    pub fn trace_syscalls<T: AsRef<Path>>(&mut self, trace_syscalls: T) -> &mut ConfigBuilder {
+        self.update(|builder| builder.trace_syscalls(trace_syscalls))
+    }
+
+    pub fn deny_nested_namespaces(
+        &mut self,
+        deny_nested_namespaces: DenyNestedNamespaces,
+    ) -> &mut ConfigBuilder {
+        self.update(|builder| builder.deny_nested_namespaces(deny_nested_namespaces))
     }
 
     pub fn stdin<T: AsRef<Path>>(&mut self, redirect_stdin: T) -> &mut ConfigBuilder {
-        self.redirect_stdin = Some(redirect_stdin.as_ref().into());
-        self
+        self.update(|builder| builder.redirect_stdin(redirect_stdin.as_ref().to_path_buf().into()))
     }
 
-    pub fn stdout<T: AsRef<Path>>(&mut self, redirect_stdin: T) -> &mut ConfigBuilder {
-        self.redirect_stdout = Some(redirect_stdin.as_ref().into());
-        self
+    pub fn inherit_stdin(&mut self) -> &mut ConfigBuilder {
+        self.update(|builder| builder.redirect_stdin(Redirect::Inherit))
     }
 
-    pub fn stderr<T: AsRef<Path>>(&mut self, redirect_stdin: T) -> &mut ConfigBuilder {
-        self.redirect_stderr = Some(redirect_stdin.as_ref().into());
-        self
+    pub fn redirect_stdin(&mut self, redirect_stdin: Redirect) -> &mut ConfigBuilder {
+        self.update(|builder| builder.redirect_stdin(redirect_stdin))
+    }
+
+    pub fn stdout<T: AsRef<Path>>(&mut self, redirect_stdout: T) -> &mut ConfigBuilder {
+        self.update(|builder| {
+            builder.redirect_stdout(redirect_stdout.as_ref().to_path_buf().into())
+        })
+    }
+
+    pub fn redirect_stdout(&mut self, redirect_stdout: Redirect) -> &mut ConfigBuilder {
+        self.update(|builder| builder.redirect_stdout(redirect_stdout))
+    }
+
+    pub fn stderr<T: AsRef<Path>>(&mut self, redirect_stderr: T) -> &mut ConfigBuilder {
+        self.update(|builder| {
+            builder.redirect_stderr(redirect_stderr.as_ref().to_path_buf().into())
+        })
+    }
+
+    pub fn redirect_stderr(&mut self, redirect_stderr: Redirect) -> &mut ConfigBuilder {
+        self.update(|builder| builder.redirect_stderr(redirect_stderr))
+    }
+
+    pub fn controller_path(&mut self, controller_path: ControllerPath) -> &mut ConfigBuilder {
+        self.update(|builder| builder.controller_path(controller_path))
     }
 
     pub fn limits<T: Into<Limits>>(&mut self, limits: T) -> &mut ConfigBuilder {
-        self.limits = Some(limits.into());
-        self
+        self.update(|builder| builder.limits(limits.into()))
     }
 
     pub fn instance_name<T: AsRef<OsStr>>(&mut self, instance_name: T) -> &mut ConfigBuilder {
-        self.instance_name = Some(instance_name.as_ref().into());
-        self
+        self.update(|builder| builder.instance_name(instance_name))
     }
 
     pub fn mount(&mut self, mount: Mount) -> &mut ConfigBuilder {
-        self.mounts.push(mount);
-        self
+        self.update(|builder| builder.mount(mount))
     }
 
     pub fn swap_redirects(&mut self, swap_redirects: SwapRedirects) -> &mut ConfigBuilder {
-        self.swap_redirects = swap_redirects;
-        self
+        self.update(|builder| builder.swap_redirects(swap_redirects))
     }
 
     pub fn clear_usage(&mut self, clear_usage: ClearUsage) -> &mut ConfigBuilder {
-        self.clear_usage = clear_usage;
-        self
+        self.update(|builder| builder.clear_usage(clear_usage))
     }
 
     pub fn environment(&mut self, environment: Environment) -> &mut ConfigBuilder {
-        self.environment = environment;
-        self
+        self.update(|builder| builder.environment(environment))
     }
 
-    pub fn build_and_spawn(&mut self) -> Result<JailHandle> {
-        let config = Config::new(
-            self.command.clone(),
-            self.args.clone(),
-            self.new_root.clone(),
-            if self.share_net {
-                ShareNet::Share
-            } else {
-                ShareNet::Unshare
-            },
-            self.redirect_stdin.clone(),
-            self.redirect_stdout.clone(),
-            self.redirect_stderr.clone(),
-            self.limits.unwrap_or_default(),
-            self.instance_name.clone(),
-            ControllerPath::default(),
-            self.mounts.clone(),
-            self.swap_redirects,
-            self.clear_usage,
-            Interactive::default(),
-            self.environment.clone(),
-        );
+    pub fn exclude_stopped_time(
+        &mut self,
+        exclude_stopped_time: ExcludeStoppedTime,
+    ) -> &mut ConfigBuilder {
+        self.update(|builder| builder.exclude_stopped_time(exclude_stopped_time))
+    }
 
-        ia_sandbox::spawn_jail(&config)
+    pub fn pausable(&mut self, pausable: Pausable) -> &mut ConfigBuilder {
+        self.update(|builder| builder.pausable(pausable))
+    }
+
+    pub fn mark_inside_sandbox(
+        &mut self,
+        mark_inside_sandbox: MarkInsideSandbox,
+    ) -> &mut ConfigBuilder {
+        self.update(|builder| builder.mark_inside_sandbox(mark_inside_sandbox))
+    }
+
+    pub fn detached<T: AsRef<Path>>(&mut self, result_file: T) -> &mut ConfigBuilder {
+        self.update(|builder| builder.detached(result_file))
+    }
+
+    pub fn mount_cgroups(&mut self, mount_cgroups: MountCgroups) -> &mut ConfigBuilder {
+        self.update(|builder| builder.mount_cgroups(mount_cgroups))
+    }
+
+    pub fn interactive(&mut self, interactive: Interactive) -> &mut ConfigBuilder {
+        self.update(|builder| builder.interactive(interactive))
+    }
+
+    pub fn allocate_pty(&mut self, allocate_pty: AllocatePty) -> &mut ConfigBuilder {
+        self.update(|builder| builder.allocate_pty(allocate_pty))
+    }
+
+    pub fn minimal_dev(&mut self, minimal_dev: MinimalDev) -> &mut ConfigBuilder {
+        self.update(|builder| builder.minimal_dev(minimal_dev))
+    }
+
+    pub fn private_tmp(&mut self, private_tmp: PrivateTmp) -> &mut ConfigBuilder {
+        self.update(|builder| builder.private_tmp(private_tmp))
+    }
+
+    pub fn private_tmp_size(&mut self, private_tmp_size: SpaceUsage) -> &mut ConfigBuilder {
+        self.update(|builder| builder.private_tmp_size(private_tmp_size))
+    }
+
+    pub fn stderr_tail(&mut self, stderr_tail: SpaceUsage) -> &mut ConfigBuilder {
+        self.update(|builder| builder.stderr_tail(stderr_tail))
+    }
+
+    pub fn read_only_root(&mut self, read_only_root: ReadOnlyRoot) -> &mut ConfigBuilder {
+        self.update(|builder| builder.read_only_root(read_only_root))
+    }
+
+    pub fn proc_options(&mut self, proc_options: ProcOptions) -> &mut ConfigBuilder {
+        self.update(|builder| builder.proc_options(proc_options))
+    }
+
+    pub fn disable_swap(&mut self, disable_swap: DisableSwap) -> &mut ConfigBuilder {
+        self.update(|builder| builder.disable_swap(disable_swap))
+    }
+
+    pub fn allow_same_file_io(
+        &mut self,
+        allow_same_file_io: AllowSameFileIo,
+    ) -> &mut ConfigBuilder {
+        self.update(|builder| builder.allow_same_file_io(allow_same_file_io))
+    }
+
+    pub fn parent_limits<T: Into<Limits>>(&mut self, parent_limits: T) -> &mut ConfigBuilder {
+        self.update(|builder| builder.parent_limits(parent_limits.into()))
+    }
+
+    pub fn kill_grace_period(&mut self, kill_grace_period: Duration) -> &mut ConfigBuilder {
+        self.update(|builder| builder.kill_grace_period(kill_grace_period))
+    }
+
+    pub fn memory_accounting(&mut self, memory_accounting: MemoryAccounting) -> &mut ConfigBuilder {
+        self.update(|builder| builder.memory_accounting(memory_accounting))
+    }
+
+    pub fn build(&mut self) -> Result<Config> {
+        self.0.clone().build().map_err(Error::InvalidConfig)
+    }
+
+    pub fn build_and_spawn(&mut self) -> Result<JailHandle> {
+        ia_sandbox::spawn_jail(&self.build()?)
     }
 
     pub fn build_and_run(&mut self) -> Result<RunInfo<()>> {
@@ -152,13 +356,34 @@ impl ConfigBuilder {
     }
 }
 
+// Lets the whole suite run in either mode, `IA_SANDBOX_TEST_ISOLATION_MODE=root cargo test`
+fn isolation_mode_from_env() -> IsolationMode {
+    match env::var("IA_SANDBOX_TEST_ISOLATION_MODE")
+        .as_ref()
+        .map(String::as_str)
+    {
+        Ok("root") => IsolationMode::Root,
+        Ok("user-namespace") => IsolationMode::UserNamespace,
+        _ => IsolationMode::Auto,
+    }
+}
+
 #[derive(Clone, Copy, Default)]
 pub struct LimitsBuilder {
     wall_time: Option<Duration>,
     user_time: Option<Duration>,
     memory: Option<SpaceUsage>,
     stack: Option<SpaceUsage>,
+    cache: Option<SpaceUsage>,
     pids: Option<usize>,
+    cpu_quota: Option<f64>,
+    cpus: Option<&'static str>,
+    mems: Option<&'static str>,
+    read_bps: Option<SpaceUsage>,
+    write_bps: Option<SpaceUsage>,
+    net_bytes: Option<SpaceUsage>,
+    output: Option<SpaceUsage>,
+    wall_time_derivation: Option<(f64, Duration)>,
 }
 
 impl LimitsBuilder {
@@ -168,7 +393,16 @@ impl LimitsBuilder {
             user_time: None,
             memory: None,
             stack: None,
+            cache: None,
             pids: None,
+            cpu_quota: None,
+            cpus: None,
+            mems: None,
+            read_bps: None,
+            write_bps: None,
+            net_bytes: None,
+            output: None,
+            wall_time_derivation: None,
         }
     }
 
@@ -192,20 +426,77 @@ impl LimitsBuilder {
         self
     }
 
+    pub fn cache(&mut self, cache: SpaceUsage) -> &mut LimitsBuilder {
+        self.cache = Some(cache);
+        self
+    }
+
     pub fn pids(&mut self, pids: usize) -> &mut LimitsBuilder {
         self.pids = Some(pids);
         self
     }
+
+    pub fn cpu_quota(&mut self, cpu_quota: f64) -> &mut LimitsBuilder {
+        self.cpu_quota = Some(cpu_quota);
+        self
+    }
+
+    pub fn cpus(&mut self, cpus: &'static str) -> &mut LimitsBuilder {
+        self.cpus = Some(cpus);
+        self
+    }
+
+    pub fn mems(&mut self, mems: &'static str) -> &mut LimitsBuilder {
+        self.mems = Some(mems);
+        self
+    }
+
+    pub fn read_bps(&mut self, read_bps: SpaceUsage) -> &mut LimitsBuilder {
+        self.read_bps = Some(read_bps);
+        self
+    }
+
+    pub fn write_bps(&mut self, write_bps: SpaceUsage) -> &mut LimitsBuilder {
+        self.write_bps = Some(write_bps);
+        self
+    }
+
+    pub fn net_bytes(&mut self, net_bytes: SpaceUsage) -> &mut LimitsBuilder {
+        self.net_bytes = Some(net_bytes);
+        self
+    }
+
+    pub fn output(&mut self, output: SpaceUsage) -> &mut LimitsBuilder {
+        self.output = Some(output);
+        self
+    }
+
+    pub fn derive_wall_from_user(&mut self, factor: f64, slack: Duration) -> &mut LimitsBuilder {
+        self.wall_time_derivation = Some((factor, slack));
+        self
+    }
 }
 
 impl From<LimitsBuilder> for Limits {
     fn from(limits_builder: LimitsBuilder) -> Limits {
-        Limits::new(
+        let mut limits = Limits::new(
             limits_builder.wall_time,
             limits_builder.user_time,
             limits_builder.memory,
             limits_builder.stack,
+            limits_builder.cache,
             limits_builder.pids,
-        )
+        );
+        limits.set_cpu_quota(limits_builder.cpu_quota);
+        limits.set_cpus(limits_builder.cpus.map(str::to_owned));
+        limits.set_mems(limits_builder.mems.map(str::to_owned));
+        limits.set_read_bps(limits_builder.read_bps);
+        limits.set_write_bps(limits_builder.write_bps);
+        limits.set_net_bytes(limits_builder.net_bytes);
+        limits.set_output(limits_builder.output);
+        if let Some((factor, slack)) = limits_builder.wall_time_derivation {
+            limits.derive_wall_from_user(factor, slack);
+        }
+        limits
     }
 }
@@ -57,8 +57,9 @@ impl Matcher for NonZeroExitStatus {
     }
 
     fn try_match(&self, run_info: RunInfo<()>) -> Result<(), Self::Output> {
-        match *run_info.result() {
-            RunInfoResult::NonZeroExitStatus(x) if self.0.map(|y| x == y).unwrap_or(true) => Ok(()),
+        // The status of the result has to be the one the command exited with
+        match (run_info.result().exit_status(), run_info.exit_status()) {
+            (Some(x), Some(y)) if x == y && self.0.map(|z| x == z).unwrap_or(true) => Ok(()),
             _ => Err(run_info),
         }
     }
@@ -75,9 +76,106 @@ impl Matcher for KilledBySignal {
         format!("result is KilledBySignal({})", self.0)
     }
 
+    fn try_match(&self, run_info: RunInfo<()>) -> Result<(), Self::Output> {
+        match (run_info.result(), run_info.signal()) {
+            (&RunInfoResult::KilledBySignal(_), Some(signal)) if signal.number() == self.0 => {
+                Ok(())
+            }
+            _ => Err(run_info),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct RuntimeError(pub u32);
+
+impl Matcher for RuntimeError {
+    type AssertionString = String;
+    type Output = RunInfo<()>;
+
+    fn assertion_string(&self) -> Self::AssertionString {
+        format!("result is RuntimeError({})", self.0)
+    }
+
+    fn try_match(&self, run_info: RunInfo<()>) -> Result<(), Self::Output> {
+        match *run_info.result() {
+            RunInfoResult::RuntimeError { signal } if signal == self.0 => Ok(()),
+            _ => Err(run_info),
+        }
+    }
+}
+
+pub struct Aborted;
+
+impl Matcher for Aborted {
+    type AssertionString = &'static str;
+    type Output = RunInfo<()>;
+
+    fn assertion_string(&self) -> Self::AssertionString {
+        "result is Aborted"
+    }
+
+    fn try_match(&self, run_info: RunInfo<()>) -> Result<(), Self::Output> {
+        match *run_info.result() {
+            RunInfoResult::Aborted => Ok(()),
+            _ => Err(run_info),
+        }
+    }
+}
+
+pub struct KilledByHost;
+
+impl Matcher for KilledByHost {
+    type AssertionString = &'static str;
+    type Output = RunInfo<()>;
+
+    fn assertion_string(&self) -> Self::AssertionString {
+        "result is KilledByHost"
+    }
+
+    fn try_match(&self, run_info: RunInfo<()>) -> Result<(), Self::Output> {
+        match *run_info.result() {
+            RunInfoResult::KilledByHost => Ok(()),
+            _ => Err(run_info),
+        }
+    }
+}
+
+pub struct Cancelled;
+
+impl Matcher for Cancelled {
+    type AssertionString = &'static str;
+    type Output = RunInfo<()>;
+
+    fn assertion_string(&self) -> Self::AssertionString {
+        "result is Cancelled"
+    }
+
     fn try_match(&self, run_info: RunInfo<()>) -> Result<(), Self::Output> {
         match *run_info.result() {
-            RunInfoResult::KilledBySignal(x) if x == self.0 => Ok(()),
+            RunInfoResult::Cancelled => Ok(()),
+            _ => Err(run_info),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct IllegalSyscall(pub u32, pub &'static str);
+
+impl Matcher for IllegalSyscall {
+    type AssertionString = String;
+    type Output = RunInfo<()>;
+
+    fn assertion_string(&self) -> Self::AssertionString {
+        format!("result is IllegalSyscall({}, {})", self.0, self.1)
+    }
+
+    fn try_match(&self, run_info: RunInfo<()>) -> Result<(), Self::Output> {
+        match *run_info.result() {
+            RunInfoResult::IllegalSyscall {
+                number,
+                name: Some(ref name),
+            } if number == self.0 && name == self.1 => Ok(()),
             _ => Err(run_info),
         }
     }
@@ -95,7 +193,7 @@ impl Matcher for WallTimeLimitExceeded {
 
     fn try_match(&self, run_info: RunInfo<()>) -> Result<(), Self::Output> {
         match *run_info.result() {
-            RunInfoResult::WallTimeLimitExceeded => Ok(()),
+            RunInfoResult::WallTimeLimitExceeded { .. } => Ok(()),
             _ => Err(run_info),
         }
     }
@@ -137,6 +235,42 @@ impl Matcher for MemoryLimitExceeded {
     }
 }
 
+pub struct NetworkLimitExceeded;
+
+impl Matcher for NetworkLimitExceeded {
+    type AssertionString = &'static str;
+    type Output = RunInfo<()>;
+
+    fn assertion_string(&self) -> Self::AssertionString {
+        "result is NetworkLimitExceeded"
+    }
+
+    fn try_match(&self, run_info: RunInfo<()>) -> Result<(), Self::Output> {
+        match *run_info.result() {
+            RunInfoResult::NetworkLimitExceeded => Ok(()),
+            _ => Err(run_info),
+        }
+    }
+}
+
+pub struct OutputLimitExceeded;
+
+impl Matcher for OutputLimitExceeded {
+    type AssertionString = &'static str;
+    type Output = RunInfo<()>;
+
+    fn assertion_string(&self) -> Self::AssertionString {
+        "result is OutputLimitExceeded"
+    }
+
+    fn try_match(&self, run_info: RunInfo<()>) -> Result<(), Self::Output> {
+        match *run_info.result() {
+            RunInfoResult::OutputLimitExceeded { .. } => Ok(()),
+            _ => Err(run_info),
+        }
+    }
+}
+
 pub struct AnnotateAssert<T: Matcher> {
     matcher: T,
     annotate: Cow<'static, str>,
@@ -188,8 +322,8 @@ impl<T: Matcher> Matcher for CompareLimits<T> {
 
     fn try_match(&self, run_info: RunInfo<()>) -> Result<(), Self::Output> {
         self.matcher
-            .try_match(run_info)
-            .map_err(|_| CompareLimitsRunUsage(self.limits, run_info))
+            .try_match(run_info.clone())
+            .map_err(|_| CompareLimitsRunUsage(self.limits.clone(), run_info))
     }
 }
 
@@ -4,8 +4,12 @@ use std::io::{BufRead, BufReader, Read, Write};
 use std::os::unix::ffi::OsStrExt;
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
 
+use ia_sandbox::errors::{ConfigError, Error};
 use ia_sandbox::run_info::RunInfo;
+use ia_sandbox::JailHandle;
 
 use libc;
 use tempfile::{Builder, TempDir};
@@ -16,6 +20,10 @@ pub use self::builder::{ConfigBuilder, LimitsBuilder};
 pub mod matchers;
 use self::matchers::Matcher;
 
+// How long `TestRunnerHelper::wait_for_exec` waits, a supervisor stuck in the setup fails the
+// test instead of hanging it
+const WAIT_FOR_EXEC_TIMEOUT: Duration = Duration::from_secs(10);
+
 fn get_exec_libs<T>(file: T) -> Vec<PathBuf>
 where
     T: AsRef<Path>,
@@ -90,7 +98,10 @@ impl<'a> TestRunnerHelper<'a> {
         exec_path: T,
         pivot_root: PivotRoot,
     ) -> TestRunnerHelper {
-        let temp_dir = Builder::new().prefix(test_name).tempdir().unwrap();
+        let temp_dir = Builder::new()
+            .prefix(&test_name.replace('/', "_"))
+            .tempdir()
+            .unwrap();
         let exec_path = exec_path.as_ref();
 
         copy_by_command(
@@ -132,6 +143,41 @@ impl<'a> TestRunnerHelper<'a> {
         file.write_all(data).unwrap();
     }
 
+    /// Waits until `command` was exec'd inside the sandbox and returns its pid. Panics with
+    /// the result of the run if it ends first, or if it takes longer than `WAIT_FOR_EXEC_TIMEOUT`.
+    pub fn wait_for_exec(&self, handle: &mut JailHandle, command: &str) -> libc::pid_t {
+        let procs_path = Path::new("/sys/fs/cgroup/pids/ia-sandbox")
+            .join(self.test_name)
+            .join("cgroup.procs");
+        let timer = Instant::now();
+        loop {
+            let procs = fs::read_to_string(&procs_path).unwrap_or_default();
+            for pid in procs.lines() {
+                let cmdline = fs::read(format!("/proc/{}/cmdline", pid)).unwrap_or_default();
+                if cmdline.ends_with(format!("{}\0", command).as_bytes()) {
+                    return pid.parse().unwrap();
+                }
+            }
+            match handle.try_wait() {
+                Ok(None) => {}
+                result => panic!(
+                    "{} was never exec'd, the run ended with {:?}",
+                    command, result
+                ),
+            }
+            if timer.elapsed() > WAIT_FOR_EXEC_TIMEOUT {
+                handle.kill().unwrap();
+                panic!(
+                    "{} was not exec'd after {:?}, the run ended with {:?}",
+                    command,
+                    WAIT_FOR_EXEC_TIMEOUT,
+                    handle.wait_timeout(WAIT_FOR_EXEC_TIMEOUT)
+                );
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
     pub fn read_line<T: AsRef<Path>>(&mut self, path: T) -> String {
         let mut file = File::open(path.as_ref()).unwrap();
         let mut line = String::new();
@@ -150,6 +196,32 @@ impl<'a> Drop for TestRunnerHelper<'a> {
             .unwrap_or(());
         fs::remove_dir(Path::new("/sys/fs/cgroup/pids/ia-sandbox").join(self.test_name))
             .unwrap_or(());
+        fs::remove_dir(Path::new("/sys/fs/cgroup/freezer/ia-sandbox").join(self.test_name))
+            .unwrap_or(());
+    }
+}
+
+/// Waits until the process `pid` handles `signal`, an exec'd fixture only gets to install its
+/// handlers in a while. Panics after `WAIT_FOR_EXEC_TIMEOUT`.
+pub fn wait_for_signal_handler(pid: libc::pid_t, signal: libc::c_int) {
+    let timer = Instant::now();
+    loop {
+        let status = fs::read_to_string(format!("/proc/{}/status", pid)).unwrap_or_default();
+        let caught = status
+            .lines()
+            .find(|line| line.starts_with("SigCgt:"))
+            .and_then(|line| u64::from_str_radix(line["SigCgt:".len()..].trim(), 16).ok())
+            .unwrap_or(0);
+        if caught & (1 << (signal - 1)) != 0 {
+            return;
+        }
+        assert!(
+            timer.elapsed() <= WAIT_FOR_EXEC_TIMEOUT,
+            "{} never handled signal {}",
+            pid,
+            signal
+        );
+        thread::sleep(Duration::from_millis(1));
     }
 }
 
@@ -167,9 +239,37 @@ pub trait RunInfoExt {
 
 impl RunInfoExt for RunInfo<()> {
     fn assert<F: Matcher>(self, matcher: F) {
+        // Every run the supervisor waited for is timed
+        let duration = self.duration().expect("the run was not timed");
+        assert!(
+            self.usage().wall_time() <= duration,
+            "wall time {:?} over the duration {:?} of the run",
+            self.usage().wall_time(),
+            duration
+        );
+        assert!(self.started_at() <= self.finished_at());
         match matcher.try_match(self) {
             Ok(()) => (),
             Err(err) => panic!("assertion failed: {}\n{}", matcher.assertion_string(), err),
         }
     }
 }
+
+pub trait ErrorExt {
+    /// The one problem `Config::validate` found, panics on any other error
+    fn into_config_error(self) -> ConfigError;
+}
+
+impl ErrorExt for Error {
+    fn into_config_error(self) -> ConfigError {
+        match self {
+            Error::InvalidConfig(errors) => {
+                let mut errors = errors.into_errors();
+                assert_eq!(errors.len(), 1, "expected one problem: {:?}", errors);
+                errors.remove(0)
+            }
+            Error::ConfigError(error) => error,
+            err => panic!("Expected an invalid config error, got {}", err),
+        }
+    }
+}